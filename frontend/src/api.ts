@@ -1,5 +1,5 @@
 import axios from 'axios';
-import type { PingAggregatedResponse, PingAggregatedQuery, Target, TargetRequest, StorageStatsResponse, SubnetSuggestion } from './types';
+import type { PingAggregatedResponse, PingAggregatedQuery, Target, TargetListQuery, TargetRequest, StorageStatsResponse, SubnetSuggestion, Preferences, PingTestRequest, PingTestResponse, TracerouteRequest, TracerouteResponse, TracerouteHistoryResponse, OutagesResponse, TunnelOverheadResponse, FlowReportResponse, AuthStatus, LoginResponse, ApiTokenInfo, CreatedApiToken, Role, SystemInfo, ApiErrorBody } from './types';
 import { getBasePath } from './lib/basePath';
 
 // Use dynamic base path for Home Assistant ingress support
@@ -12,6 +12,63 @@ const apiClient = axios.create({
   },
 });
 
+// With [auth] enabled, the login sets an HttpOnly session cookie that the
+// browser sends with every request, including EventSource streams.
+// Listeners are told when a request needs a (new) login.
+const unauthorizedListeners = new Set<() => void>();
+
+export function onUnauthorized(listener: () => void): () => void {
+  unauthorizedListeners.add(listener);
+  return () => {
+    unauthorizedListeners.delete(listener);
+  };
+}
+
+/** A failed API request, carrying the `{code, message, details}` error body */
+export class ApiError extends Error {
+  readonly status: number;
+  readonly code: string;
+  readonly details?: unknown;
+
+  constructor(status: number, body: ApiErrorBody) {
+    super(body.message);
+    this.name = 'ApiError';
+    this.status = status;
+    this.code = body.code;
+    this.details = body.details;
+  }
+}
+
+function isApiErrorBody(data: unknown): data is ApiErrorBody {
+  if (typeof data !== 'object' || data === null) {
+    return false;
+  }
+  const body = data as Partial<ApiErrorBody>;
+  return typeof body.code === 'string' && typeof body.message === 'string';
+}
+
+apiClient.interceptors.response.use(undefined, (error) => {
+  if (axios.isAxiosError(error) && error.response?.status === 401 && !error.config?.url?.includes('/auth/login')) {
+    unauthorizedListeners.forEach((listener) => listener());
+  }
+  // Errors from the server itself become ApiErrors with its message;
+  // network errors and responses from proxies stay AxiosErrors
+  if (axios.isAxiosError(error) && error.response && isApiErrorBody(error.response.data)) {
+    return Promise.reject(new ApiError(error.response.status, error.response.data));
+  }
+  return Promise.reject(error);
+});
+
+// Endpoints are written unversioned; request the current API version
+const API_VERSION_PREFIX = '/api/v1/';
+
+apiClient.interceptors.request.use((config) => {
+  if (config.url?.startsWith('/api/')) {
+    config.url = API_VERSION_PREFIX + config.url.slice('/api/'.length);
+  }
+  return config;
+});
+
 export async function fetchPingAggregated(query: PingAggregatedQuery = {}): Promise<PingAggregatedResponse> {
   const params = new URLSearchParams();
   
@@ -33,6 +90,12 @@ export async function fetchPingAggregated(query: PingAggregatedQuery = {}): Prom
   if (query.include_percentiles !== undefined) {
     params.append('include_percentiles', query.include_percentiles.toString());
   }
+  if (query.group_by) {
+    params.append('group_by', query.group_by);
+  }
+  if (query.tz) {
+    params.append('tz', query.tz);
+  }
 
   const response = await apiClient.get<PingAggregatedResponse>(`/api/ping/aggregated?${params.toString()}`);
   return response.data;
@@ -40,8 +103,8 @@ export async function fetchPingAggregated(query: PingAggregatedQuery = {}): Prom
 
 // Target management API functions
 
-export async function fetchTargets(): Promise<Target[]> {
-  const response = await apiClient.get<Target[]>('/api/targets');
+export async function fetchTargets(query?: TargetListQuery): Promise<Target[]> {
+  const response = await apiClient.get<Target[]>('/api/targets', { params: query });
   return response.data;
 }
 
@@ -59,14 +122,113 @@ export async function deleteTarget(id: string): Promise<void> {
   await apiClient.delete(`/api/targets/${id}`);
 }
 
+export async function pauseTarget(id: string): Promise<Target> {
+  const response = await apiClient.post<Target>(`/api/targets/${id}/pause`);
+  return response.data;
+}
+
+export async function resumeTarget(id: string): Promise<Target> {
+  const response = await apiClient.post<Target>(`/api/targets/${id}/resume`);
+  return response.data;
+}
+
+export async function fetchTunnelOverhead(
+  id: string,
+  from?: string | number,
+  bucket?: string
+): Promise<TunnelOverheadResponse> {
+  const response = await apiClient.get<TunnelOverheadResponse>(`/api/targets/${id}/tunnel`, {
+    params: { from, bucket },
+  });
+  return response.data;
+}
+
+export async function fetchTargetFlows(id: string, from?: string | number): Promise<FlowReportResponse> {
+  const response = await apiClient.get<FlowReportResponse>(`/api/targets/${id}/flows`, {
+    params: { from },
+  });
+  return response.data;
+}
+
+export async function testPing(request: PingTestRequest): Promise<PingTestResponse> {
+  const response = await apiClient.post<PingTestResponse>('/api/ping/test', request);
+  return response.data;
+}
+
+export async function runTraceroute(request: TracerouteRequest): Promise<TracerouteResponse> {
+  const response = await apiClient.post<TracerouteResponse>('/api/traceroute', request);
+  return response.data;
+}
+
+export async function fetchTracerouteHistory(targetId: string, from?: string | number): Promise<TracerouteHistoryResponse> {
+  const response = await apiClient.get<TracerouteHistoryResponse>('/api/traceroute/history', {
+    params: { target_id: targetId, from },
+  });
+  return response.data;
+}
+
+export async function fetchOutages(target?: string, from?: string | number): Promise<OutagesResponse> {
+  const response = await apiClient.get<OutagesResponse>('/api/outages', {
+    params: { target, from },
+  });
+  return response.data;
+}
+
 export async function fetchStorageStats(): Promise<StorageStatsResponse> {
   const response = await apiClient.get<StorageStatsResponse>('/api/storage/stats');
   return response.data;
 }
 
+// Preferences API functions
+
+export async function fetchPreferences(): Promise<Preferences> {
+  const response = await apiClient.get<Preferences>('/api/preferences');
+  return response.data;
+}
+
+/** Merge keys into the stored preferences; a `null` value removes a key */
+export async function updatePreferences(update: Preferences): Promise<Preferences> {
+  const response = await apiClient.put<Preferences>('/api/preferences', update);
+  return response.data;
+}
+
 // Discovery API functions
 
 export async function fetchSubnets(): Promise<SubnetSuggestion[]> {
   const response = await apiClient.get<SubnetSuggestion[]>('/api/discovery/subnets');
   return response.data;
 }
+
+export async function fetchSystemInfo(): Promise<SystemInfo> {
+  const response = await apiClient.get<SystemInfo>('/api/system/info');
+  return response.data;
+}
+
+// Authentication API functions
+export async function fetchAuthStatus(): Promise<AuthStatus> {
+  const response = await apiClient.get<AuthStatus>('/api/auth/status');
+  return response.data;
+}
+
+export async function login(username: string, password: string): Promise<LoginResponse> {
+  const response = await apiClient.post<LoginResponse>('/api/auth/login', { username, password });
+  return response.data;
+}
+
+export async function logout(): Promise<void> {
+  await apiClient.post('/api/auth/logout');
+}
+
+export async function fetchApiTokens(): Promise<ApiTokenInfo[]> {
+  const response = await apiClient.get<ApiTokenInfo[]>('/api/auth/tokens');
+  return response.data;
+}
+
+export async function createApiToken(name: string, role: Role = 'viewer'): Promise<CreatedApiToken> {
+  const response = await apiClient.post<CreatedApiToken>('/api/auth/tokens', { name, role });
+  return response.data;
+}
+
+export async function deleteApiToken(id: string): Promise<void> {
+  await apiClient.delete(`/api/auth/tokens/${id}`);
+}
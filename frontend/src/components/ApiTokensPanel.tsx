@@ -0,0 +1,131 @@
+import { useState } from 'react';
+import { useMutation, useQuery, useQueryClient } from '@tanstack/react-query';
+import { createApiToken, deleteApiToken, fetchApiTokens } from '@/api';
+import type { CreatedApiToken, Role } from '@/types';
+import { Button } from '@/components/ui/button';
+import { Input } from '@/components/ui/input';
+import { Label } from '@/components/ui/label';
+import {
+  Select,
+  SelectContent,
+  SelectItem,
+  SelectTrigger,
+  SelectValue,
+} from '@/components/ui/select';
+import { Card, CardContent, CardHeader, CardTitle } from '@/components/ui/card';
+import { KeyRound, Plus, Trash2 } from 'lucide-react';
+
+/**
+ * API tokens of `[[auth.tokens]]`, for Grafana, scripts and the like.
+ * A new token is shown once; only its hash is stored.
+ */
+export function ApiTokensPanel() {
+  const queryClient = useQueryClient();
+  const [name, setName] = useState('');
+  const [role, setRole] = useState<Role>('viewer');
+  const [created, setCreated] = useState<CreatedApiToken | null>(null);
+
+  const { data: tokens = [], isLoading } = useQuery({
+    queryKey: ['apiTokens'],
+    queryFn: fetchApiTokens,
+  });
+
+  const createMutation = useMutation({
+    mutationFn: () => createApiToken(name.trim(), role),
+    onSuccess: (token) => {
+      setCreated(token);
+      setName('');
+      queryClient.invalidateQueries({ queryKey: ['apiTokens'] });
+    },
+  });
+
+  const deleteMutation = useMutation({
+    mutationFn: deleteApiToken,
+    onSuccess: () => {
+      queryClient.invalidateQueries({ queryKey: ['apiTokens'] });
+    },
+  });
+
+  const handleSubmit = (e: React.FormEvent) => {
+    e.preventDefault();
+    createMutation.mutate();
+  };
+
+  return (
+    <Card className="mb-6">
+      <CardHeader>
+        <CardTitle className="text-xl">API Tokens</CardTitle>
+      </CardHeader>
+      <CardContent className="space-y-4">
+        {created && (
+          <div className="bg-muted px-4 py-3 rounded text-sm space-y-1">
+            <div>Token for "{created.name}", shown only once:</div>
+            <code className="block break-all font-mono">{created.token}</code>
+          </div>
+        )}
+
+        {isLoading ? (
+          <div className="text-muted-foreground">Loading tokens...</div>
+        ) : tokens.length === 0 ? (
+          <div className="text-muted-foreground text-sm">No API tokens.</div>
+        ) : (
+          <ul className="divide-y divide-border">
+            {tokens.map((token) => (
+              <li key={token.id} className="flex items-center justify-between py-2">
+                <div className="flex items-center gap-2">
+                  <KeyRound className="size-4 text-muted-foreground" />
+                  <span className="font-medium">{token.name}</span>
+                  <span className="text-sm text-muted-foreground">
+                    {token.role}, created {new Date(token.created_at * 1000).toLocaleDateString()}
+                  </span>
+                </div>
+                <Button
+                  variant="ghost"
+                  size="icon"
+                  onClick={() => {
+                    if (confirm(`Delete API token "${token.name}"?`)) {
+                      deleteMutation.mutate(token.id);
+                    }
+                  }}
+                  title="Delete token"
+                >
+                  <Trash2 className="size-4" />
+                </Button>
+              </li>
+            ))}
+          </ul>
+        )}
+
+        <form onSubmit={handleSubmit} className="flex flex-wrap items-end gap-2">
+          <div className="space-y-2 flex-1 min-w-40">
+            <Label htmlFor="token-name">Name</Label>
+            <Input
+              id="token-name"
+              placeholder="grafana"
+              value={name}
+              onChange={(e) => setName(e.target.value)}
+              required
+            />
+          </div>
+          <div className="space-y-2">
+            <Label>Role</Label>
+            <Select value={role} onValueChange={(value) => setRole(value as Role)}>
+              <SelectTrigger className="w-32">
+                <SelectValue />
+              </SelectTrigger>
+              <SelectContent>
+                <SelectItem value="viewer">Viewer</SelectItem>
+                <SelectItem value="editor">Editor</SelectItem>
+                <SelectItem value="admin">Admin</SelectItem>
+              </SelectContent>
+            </Select>
+          </div>
+          <Button type="submit" disabled={createMutation.isPending || !name.trim()}>
+            <Plus className="size-4" />
+            Create Token
+          </Button>
+        </form>
+      </CardContent>
+    </Card>
+  );
+}
@@ -0,0 +1,82 @@
+import { useState } from 'react';
+import { Button } from '@/components/ui/button';
+import { Input } from '@/components/ui/input';
+import { Label } from '@/components/ui/label';
+import { Card, CardContent, CardHeader, CardTitle } from '@/components/ui/card';
+import { ApiError } from '@/api';
+
+interface LoginFormProps {
+  onLogin: (username: string, password: string) => void;
+  /** Shown while reads work without a login */
+  onCancel?: () => void;
+  isPending: boolean;
+  error: unknown;
+}
+
+function loginErrorMessage(error: unknown): string {
+  if (error instanceof ApiError && error.status === 401) {
+    return 'Invalid username or password';
+  }
+  return error instanceof Error ? error.message : 'Login failed';
+}
+
+export function LoginForm({ onLogin, onCancel, isPending, error }: LoginFormProps) {
+  const [username, setUsername] = useState('');
+  const [password, setPassword] = useState('');
+
+  const handleSubmit = (e: React.FormEvent) => {
+    e.preventDefault();
+    onLogin(username, password);
+  };
+
+  return (
+    <div className="container mx-auto px-4 py-16 flex justify-center">
+      <Card className="w-full max-w-sm">
+        <CardHeader>
+          <CardTitle className="text-xl">Sign in</CardTitle>
+        </CardHeader>
+        <CardContent>
+          <form onSubmit={handleSubmit} className="space-y-4">
+            <div className="space-y-2">
+              <Label htmlFor="username">Username</Label>
+              <Input
+                id="username"
+                autoComplete="username"
+                value={username}
+                onChange={(e) => setUsername(e.target.value)}
+                required
+                autoFocus
+              />
+            </div>
+            <div className="space-y-2">
+              <Label htmlFor="password">Password</Label>
+              <Input
+                id="password"
+                type="password"
+                autoComplete="current-password"
+                value={password}
+                onChange={(e) => setPassword(e.target.value)}
+                required
+              />
+            </div>
+            {!!error && (
+              <div className="bg-destructive/10 border border-destructive/30 text-destructive px-3 py-2 rounded text-sm">
+                {loginErrorMessage(error)}
+              </div>
+            )}
+            <div className="flex gap-2">
+              <Button type="submit" className="flex-1" disabled={isPending}>
+                {isPending ? 'Signing in...' : 'Sign in'}
+              </Button>
+              {onCancel && (
+                <Button type="button" variant="outline" onClick={onCancel}>
+                  Cancel
+                </Button>
+              )}
+            </div>
+          </form>
+        </CardContent>
+      </Card>
+    </div>
+  );
+}
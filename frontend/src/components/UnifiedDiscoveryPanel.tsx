@@ -2,7 +2,7 @@ import { useMutation, useQuery, useQueryClient } from '@tanstack/react-query';
 import { useState, useMemo, createElement, useEffect } from 'react';
 import { useUnifiedDiscovery, type UnifiedDiscoveryConfig } from '@/hooks/useUnifiedDiscovery';
 import { createTarget, fetchSubnets } from '@/api';
-import type { TargetRequest, IdentifiedDevice, SubnetSuggestion, DeviceInfo } from '@/types';
+import type { TargetRequest, IdentifiedDevice, SubnetSuggestion, DeviceInfo, DiscoverySource } from '@/types';
 import { Button } from '@/components/ui/button';
 import { Checkbox } from '@/components/ui/checkbox';
 import { Label } from '@/components/ui/label';
@@ -32,6 +32,8 @@ import {
   Router,
   LayoutList,
   Group,
+  Cable,
+  Printer,
 } from 'lucide-react';
 import { JsonView } from '@/components/JsonView';
 import { SearchInput } from '@/components/SearchInput';
@@ -88,6 +90,20 @@ function DeviceIcon({
   return <Wifi className="size-4" />;
 }
 
+/** Badge label for a discovery source */
+function sourceLabel(source: DiscoverySource): string {
+  switch (source.type) {
+    case 'mdns':
+      return 'mDNS';
+    case 'arp':
+      return 'ARP';
+    case 'ws_discovery':
+      return 'WS-Discovery';
+    default:
+      return source.type;
+  }
+}
+
 /** Get the primary address for a device (for use as unique key) */
 function getDeviceAddress(device: IdentifiedDevice): string {
   return device.device_info.primary_address;
@@ -98,6 +114,15 @@ function getDeviceName(device: IdentifiedDevice): string {
   return device.device_info.friendly_name ?? device.device_info.name;
 }
 
+/** Get the target name for a device, using the gateway name for gateways */
+function getTargetName(device: IdentifiedDevice): string | undefined {
+  if (device.gateway) {
+    return device.gateway.target_name;
+  }
+  const name = getDeviceName(device);
+  return name !== getDeviceAddress(device) ? name : undefined;
+}
+
 function getServiceTypeName(serviceType: string): string {
   const typeMap: Record<string, string> = {
     '_http._tcp.local.': 'HTTP Server',
@@ -165,12 +190,22 @@ function SubnetCard({
 
 type IpInputMode = 'suggested' | 'cidr' | 'range';
 
+/** Remaining scan time, e.g. "45s" or "3m 20s" */
+function formatEta(seconds: number): string {
+  if (seconds < 60) return `${seconds}s`;
+  const minutes = Math.floor(seconds / 60);
+  return minutes < 60
+    ? `${minutes}m ${seconds % 60}s`
+    : `${Math.floor(minutes / 60)}h ${minutes % 60}m`;
+}
+
 export function UnifiedDiscoveryPanel({ existingAddresses }: UnifiedDiscoveryPanelProps) {
   const queryClient = useQueryClient();
   const {
     devices,
     status,
     message,
+    progress,
     startDiscovery,
     stopDiscovery,
     clearDevices,
@@ -187,15 +222,20 @@ export function UnifiedDiscoveryPanel({ existingAddresses }: UnifiedDiscoveryPan
   // Discovery configuration state
   const [mdnsEnabled, setMdnsEnabled] = useState(true);
   const [ipScanEnabled, setIpScanEnabled] = useState(false);
+  const [arpEnabled, setArpEnabled] = useState(false);
+  const [wsDiscoveryEnabled, setWsDiscoveryEnabled] = useState(false);
+  const [icmpSweep, setIcmpSweep] = useState(false);
   const [ipInputMode, setIpInputMode] = useState<IpInputMode>('suggested');
   const [selectedSubnet, setSelectedSubnet] = useState<SubnetSuggestion | null>(null);
   const [cidrInput, setCidrInput] = useState('');
   const [startIpInput, setStartIpInput] = useState('');
   const [endIpInput, setEndIpInput] = useState('');
+  const [autoAddGateway, setAutoAddGateway] = useState(false);
 
   // Device selection state
   const [selectedDevices, setSelectedDevices] = useState<Set<string>>(new Set());
   const [addedDevices, setAddedDevices] = useState<Set<string>>(new Set());
+  const [autoAddAttempted, setAutoAddAttempted] = useState<Set<string>>(new Set());
   const [expandedDevices, setExpandedDevices] = useState<Set<string>>(new Set());
   const [searchQuery, setSearchQuery] = useState('');
   const [groupByManufacturer, setGroupByManufacturer] = useState(false);
@@ -327,9 +367,12 @@ export function UnifiedDiscoveryPanel({ existingAddresses }: UnifiedDiscoveryPan
     const config: UnifiedDiscoveryConfig = {
       mdnsEnabled,
       ipScanEnabled,
+      arpEnabled,
+      wsDiscoveryEnabled,
     };
 
     if (ipScanEnabled) {
+      config.icmpSweep = icmpSweep;
       if (ipInputMode === 'suggested' && selectedSubnet) {
         config.selectedSubnet = selectedSubnet;
       } else if (ipInputMode === 'cidr' && cidrInput) {
@@ -345,7 +388,7 @@ export function UnifiedDiscoveryPanel({ existingAddresses }: UnifiedDiscoveryPan
 
   const canStartDiscovery = useMemo(() => {
     if (isRunning) return false;
-    if (!mdnsEnabled && !ipScanEnabled) return false;
+    if (!mdnsEnabled && !ipScanEnabled && !arpEnabled && !wsDiscoveryEnabled) return false;
     
     if (ipScanEnabled) {
       if (ipInputMode === 'suggested') return selectedSubnet !== null;
@@ -354,7 +397,7 @@ export function UnifiedDiscoveryPanel({ existingAddresses }: UnifiedDiscoveryPan
     }
     
     return true;
-  }, [mdnsEnabled, ipScanEnabled, ipInputMode, selectedSubnet, cidrInput, startIpInput, endIpInput, isRunning]);
+  }, [mdnsEnabled, ipScanEnabled, arpEnabled, wsDiscoveryEnabled, ipInputMode, selectedSubnet, cidrInput, startIpInput, endIpInput, isRunning]);
 
   const handleToggleDevice = (address: string) => {
     setSelectedDevices((prev) => {
@@ -389,28 +432,51 @@ export function UnifiedDiscoveryPanel({ existingAddresses }: UnifiedDiscoveryPan
     );
 
     for (const device of devicesToAdd) {
-      const addr = getDeviceAddress(device);
-      const name = getDeviceName(device);
-      const target: TargetRequest = {
-        address: addr,
-        name: name !== addr ? name : undefined,
-      };
-
-      try {
-        await createMutation.mutateAsync(target);
-        setAddedDevices((prev) => new Set(prev).add(addr));
-      } catch (error) {
-        console.error(`Failed to add device ${addr}:`, error);
-      }
+      await handleAddDevice(device);
     }
 
     setSelectedDevices(new Set());
   };
 
+  const handleAddDevice = async (device: IdentifiedDevice) => {
+    const addr = getDeviceAddress(device);
+    const target: TargetRequest = {
+      address: addr,
+      name: getTargetName(device),
+    };
+
+    try {
+      await createMutation.mutateAsync(target);
+      setAddedDevices((prev) => new Set(prev).add(addr));
+    } catch (error) {
+      console.error(`Failed to add device ${addr}:`, error);
+    }
+  };
+
+  // Automatically add gateways found by the IP scan as targets
+  useEffect(() => {
+    if (!autoAddGateway) return;
+    for (const device of devices) {
+      const addr = getDeviceAddress(device);
+      if (
+        device.gateway &&
+        !existingAddresses.has(addr) &&
+        !addedDevices.has(addr) &&
+        !autoAddAttempted.has(addr)
+      ) {
+        setAutoAddAttempted((prev) => new Set(prev).add(addr));
+        void handleAddDevice(device);
+      }
+    }
+    // handleAddDevice only depends on the mutation
+    // eslint-disable-next-line react-hooks/exhaustive-deps
+  }, [autoAddGateway, devices, existingAddresses, addedDevices, autoAddAttempted]);
+
   const handleClear = () => {
     clearDevices();
     setSelectedDevices(new Set());
     setAddedDevices(new Set());
+    setAutoAddAttempted(new Set());
     setExpandedDevices(new Set());
     setSearchQuery('');
   };
@@ -508,6 +574,34 @@ export function UnifiedDiscoveryPanel({ existingAddresses }: UnifiedDiscoveryPan
                 IP Range Scan
               </Label>
             </div>
+
+            {/* ARP Toggle */}
+            <div className="flex items-center gap-2">
+              <Checkbox
+                id="arp-enabled"
+                checked={arpEnabled}
+                onCheckedChange={(checked) => setArpEnabled(checked === true)}
+                disabled={isRunning}
+              />
+              <Label htmlFor="arp-enabled" className="flex items-center gap-2 cursor-pointer">
+                <Cable className="size-4 text-teal-500" />
+                Neighbor Table (ARP)
+              </Label>
+            </div>
+
+            {/* WS-Discovery Toggle */}
+            <div className="flex items-center gap-2">
+              <Checkbox
+                id="ws-discovery-enabled"
+                checked={wsDiscoveryEnabled}
+                onCheckedChange={(checked) => setWsDiscoveryEnabled(checked === true)}
+                disabled={isRunning}
+              />
+              <Label htmlFor="ws-discovery-enabled" className="flex items-center gap-2 cursor-pointer">
+                <Printer className="size-4 text-orange-500" />
+                WS-Discovery
+              </Label>
+            </div>
           </div>
 
           {/* IP Scan Configuration */}
@@ -612,6 +706,30 @@ export function UnifiedDiscoveryPanel({ existingAddresses }: UnifiedDiscoveryPan
                       </div>
                     </div>
                   )}
+
+                  <div className="flex items-center gap-2 pt-1">
+                    <Checkbox
+                      id="icmp-sweep"
+                      checked={icmpSweep}
+                      onCheckedChange={(checked) => setIcmpSweep(checked === true)}
+                      disabled={isRunning}
+                    />
+                    <Label htmlFor="icmp-sweep" className="cursor-pointer text-sm">
+                      Also ping every address (finds devices without open ports)
+                    </Label>
+                  </div>
+
+                  <div className="flex items-center gap-2 pt-1">
+                    <Checkbox
+                      id="auto-add-gateway"
+                      checked={autoAddGateway}
+                      onCheckedChange={(checked) => setAutoAddGateway(checked === true)}
+                    />
+                    <Label htmlFor="auto-add-gateway" className="flex items-center gap-2 cursor-pointer text-sm">
+                      <Router className="size-4 text-emerald-500" />
+                      Automatically add the subnet gateway as a target
+                    </Label>
+                  </div>
               </div>
             </div>
           )}
@@ -636,6 +754,25 @@ export function UnifiedDiscoveryPanel({ existingAddresses }: UnifiedDiscoveryPan
           </div>
         )}
 
+        {/* IP scan progress */}
+        {isRunning && progress && progress.total > 0 && (
+          <div className="mb-4 space-y-1">
+            <div className="h-2 w-full rounded bg-muted overflow-hidden">
+              <div
+                className="h-full bg-blue-500 transition-all"
+                style={{ width: `${Math.min(100, (progress.scanned / progress.total) * 100)}%` }}
+              />
+            </div>
+            <div className="flex justify-between text-xs text-muted-foreground">
+              <span>
+                {progress.scanned.toLocaleString()} of {progress.total.toLocaleString()} addresses
+                scanned, {progress.found} found
+              </span>
+              {progress.etaSeconds !== null && <span>{formatEta(progress.etaSeconds)} left</span>}
+            </div>
+          </div>
+        )}
+
         {/* Search and grouping controls */}
         {devices.length > 0 && (
           <div className="mb-4 space-y-3">
@@ -886,7 +1023,7 @@ export function UnifiedDiscoveryPanel({ existingAddresses }: UnifiedDiscoveryPan
                                     : 'bg-purple-500/10 text-purple-600 dark:text-purple-400'
                                 }`}
                               >
-                                {source.type === 'mdns' ? 'mDNS' : source.type}
+                                {sourceLabel(source)}
                               </span>
                             ))}
                             {info.device_type && (
@@ -894,6 +1031,11 @@ export function UnifiedDiscoveryPanel({ existingAddresses }: UnifiedDiscoveryPan
                                 {info.device_type}
                               </span>
                             )}
+                            {device.gateway && (
+                              <span className="text-xs px-1.5 py-0.5 rounded bg-emerald-500/10 text-emerald-600 dark:text-emerald-400">
+                                Gateway of {device.gateway.subnet}
+                              </span>
+                            )}
                             {services.length > 1 && (
                               <span className="text-xs px-1.5 py-0.5 rounded bg-slate-500/10 text-slate-600 dark:text-slate-400">
                                 {services.length} services
@@ -920,6 +1062,18 @@ export function UnifiedDiscoveryPanel({ existingAddresses }: UnifiedDiscoveryPan
                             )}
                           </div>
                         </Label>
+                        {device.gateway && !isDisabled && (
+                          <Button
+                            variant="outline"
+                            size="sm"
+                            onClick={() => handleAddDevice(device)}
+                            disabled={createMutation.isPending}
+                            title={`Add "${device.gateway.target_name}" as a ping target`}
+                          >
+                            <Plus className="size-4" />
+                            Add gateway target
+                          </Button>
+                        )}
                         <button
                           type="button"
                           onClick={() => handleToggleDetails(addr)}
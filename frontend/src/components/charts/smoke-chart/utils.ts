@@ -30,9 +30,7 @@ export function getTimeFormat(
 export function prepareChartData(data: BucketDataPoint[]): ChartDataPoint[] {
   return data
     .map((bucket) => {
-      const total = bucket.count;
-      const packetLossPercent =
-        total > 0 ? (bucket.failed_count / total) * 100 : 0;
+      const packetLossPercent = bucket.loss_percent ?? 0;
       return {
         timestamp: bucket.timestamp_unix * 1000,
         timestampEnd: bucket.timestamp_end_unix * 1000,
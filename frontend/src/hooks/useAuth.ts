@@ -0,0 +1,64 @@
+import { useMutation, useQuery, useQueryClient } from '@tanstack/react-query';
+import { useEffect, useState } from 'react';
+import { fetchAuthStatus, login, logout, onUnauthorized } from '../api';
+
+const AUTH_STATUS_KEY = ['authStatus'];
+
+/** GET /api/auth/status, shared by every component asking */
+export function useAuthStatus() {
+  return useQuery({
+    queryKey: AUTH_STATUS_KEY,
+    queryFn: fetchAuthStatus,
+  });
+}
+
+/**
+ * Login state of the web UI. The session itself lives in an HttpOnly cookie
+ * set by the server, so only its status is known here.
+ */
+export function useAuth() {
+  const queryClient = useQueryClient();
+  // Set when a request was refused for lack of a (valid) session
+  const [loginRequested, setLoginRequested] = useState(false);
+
+  const statusQuery = useAuthStatus();
+
+  useEffect(
+    () =>
+      onUnauthorized(() => {
+        setLoginRequested(true);
+        queryClient.invalidateQueries({ queryKey: AUTH_STATUS_KEY });
+      }),
+    [queryClient]
+  );
+
+  const loginMutation = useMutation({
+    mutationFn: ({ username, password }: { username: string; password: string }) =>
+      login(username, password),
+    onSuccess: () => {
+      setLoginRequested(false);
+      // Everything loaded so far may have been limited to public reads
+      queryClient.invalidateQueries();
+    },
+  });
+
+  const logoutMutation = useMutation({
+    mutationFn: logout,
+    onSettled: () => {
+      queryClient.invalidateQueries();
+    },
+  });
+
+  const status = statusQuery.data;
+  const loginRequired =
+    !!status && status.enabled && !status.authenticated && (!status.public_read || loginRequested);
+
+  return {
+    status,
+    loginRequired,
+    requestLogin: () => setLoginRequested(true),
+    cancelLogin: () => setLoginRequested(false),
+    login: loginMutation,
+    logout: logoutMutation,
+  };
+}
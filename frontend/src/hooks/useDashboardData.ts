@@ -29,7 +29,7 @@ export function useDashboardData({
   // Fetch all targets
   const targetsQuery = useQuery({
     queryKey: ['targets'],
-    queryFn: fetchTargets,
+    queryFn: () => fetchTargets(),
     enabled,
     refetchInterval,
   });
@@ -0,0 +1,58 @@
+import { useEffect, useState } from 'react';
+import type { PingDataPoint } from '@/types';
+import { getBasePath } from '@/lib/basePath';
+
+/** Results kept in memory for the live graph */
+const MAX_POINTS = 600;
+
+interface UseLivePingsResult {
+  /** Most recent ping results, oldest first */
+  points: PingDataPoint[];
+  /** Whether the SSE connection is open */
+  connected: boolean;
+}
+
+/**
+ * Hook for streaming new ping results from /api/ping/live via SSE.
+ * Pass a target address or ID to only receive that target's results.
+ */
+export function useLivePings(target?: string, enabled = true): UseLivePingsResult {
+  const [points, setPoints] = useState<PingDataPoint[]>([]);
+  const [connected, setConnected] = useState(false);
+
+  useEffect(() => {
+    if (!enabled) {
+      return;
+    }
+
+    const params = new URLSearchParams();
+    if (target) {
+      params.set('target', target);
+    }
+    const eventSource = new EventSource(`${getBasePath()}api/v1/ping/live?${params.toString()}`);
+
+    eventSource.onopen = () => setConnected(true);
+    eventSource.onerror = () => setConnected(false);
+
+    eventSource.addEventListener('ping', (event) => {
+      try {
+        const point: PingDataPoint = JSON.parse((event as MessageEvent).data);
+        setPoints((prev) => [...prev.slice(-(MAX_POINTS - 1)), point]);
+      } catch (e) {
+        console.error('Failed to parse live ping result:', e);
+      }
+    });
+
+    eventSource.addEventListener('lagged', (event) => {
+      console.warn('Live ping stream skipped results:', (event as MessageEvent).data);
+    });
+
+    return () => {
+      eventSource.close();
+      setConnected(false);
+      setPoints([]);
+    };
+  }, [target, enabled]);
+
+  return { points, connected };
+}
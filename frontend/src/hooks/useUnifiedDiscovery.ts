@@ -4,11 +4,23 @@ import { getBasePath } from '@/lib/basePath';
 
 export type DiscoveryStatus = 'idle' | 'running' | 'completed' | 'error';
 
+/** Latest progress of a running IP scan */
+export interface IpScanProgress {
+  scanned: number;
+  total: number;
+  found: number;
+  etaSeconds: number | null;
+}
+
 export interface UnifiedDiscoveryConfig {
   /** Enable mDNS discovery */
   mdnsEnabled: boolean;
   /** Enable IP scan discovery */
   ipScanEnabled: boolean;
+  /** Enable neighbor table (ARP) discovery; sweeps the IP scan range if set */
+  arpEnabled: boolean;
+  /** Enable WS-Discovery (printers, scanners, ONVIF cameras) */
+  wsDiscoveryEnabled: boolean;
   /** Selected subnet for IP scan */
   selectedSubnet?: SubnetSuggestion;
   /** Custom CIDR for IP scan */
@@ -17,6 +29,10 @@ export interface UnifiedDiscoveryConfig {
   startIp?: string;
   /** Custom end IP for IP scan */
   endIp?: string;
+  /** Resolve IP scan results via reverse DNS (server default: true) */
+  reverseDns?: boolean;
+  /** Also find hosts answering ICMP echo requests (server default: false) */
+  icmpSweep?: boolean;
 }
 
 interface UseUnifiedDiscoveryResult {
@@ -26,6 +42,8 @@ interface UseUnifiedDiscoveryResult {
   status: DiscoveryStatus;
   /** Status message from the server */
   message: string | null;
+  /** Progress of the IP scan, while one is running */
+  progress: IpScanProgress | null;
   /** Start unified discovery with the given configuration */
   startDiscovery: (config: UnifiedDiscoveryConfig) => void;
   /** Stop discovery */
@@ -44,6 +62,7 @@ export function useUnifiedDiscovery(): UseUnifiedDiscoveryResult {
   const [devices, setDevices] = useState<IdentifiedDevice[]>([]);
   const [status, setStatus] = useState<DiscoveryStatus>('idle');
   const [message, setMessage] = useState<string | null>(null);
+  const [progress, setProgress] = useState<IpScanProgress | null>(null);
   const eventSourceRef = useRef<EventSource | null>(null);
 
   // Cleanup on unmount
@@ -61,6 +80,7 @@ export function useUnifiedDiscovery(): UseUnifiedDiscoveryResult {
       eventSourceRef.current.close();
       eventSourceRef.current = null;
     }
+    setProgress(null);
     if (status === 'running') {
       setStatus('idle');
       setMessage('Discovery stopped');
@@ -76,6 +96,7 @@ export function useUnifiedDiscovery(): UseUnifiedDiscoveryResult {
     // Clear previous results
     setDevices([]);
     setMessage(null);
+    setProgress(null);
     setStatus('running');
 
     // Build the SSE URL with query parameters
@@ -88,6 +109,12 @@ export function useUnifiedDiscovery(): UseUnifiedDiscoveryResult {
     // Add IP scan configuration
     params.set('ip_scan', config.ipScanEnabled.toString());
 
+    // Add ARP flag
+    params.set('arp', config.arpEnabled.toString());
+
+    // Add WS-Discovery flag
+    params.set('ws_discovery', config.wsDiscoveryEnabled.toString());
+
     if (config.ipScanEnabled) {
       if (config.selectedSubnet) {
         params.set('cidr', config.selectedSubnet.cidr);
@@ -97,6 +124,12 @@ export function useUnifiedDiscovery(): UseUnifiedDiscoveryResult {
         params.set('start_ip', config.startIp);
         params.set('end_ip', config.endIp);
       }
+      if (config.reverseDns !== undefined) {
+        params.set('reverse_dns', config.reverseDns.toString());
+      }
+      if (config.icmpSweep !== undefined) {
+        params.set('icmp', config.icmpSweep.toString());
+      }
     }
 
     const url = `${basePath}api/discovery/unified?${params.toString()}`;
@@ -137,8 +170,18 @@ export function useUnifiedDiscovery(): UseUnifiedDiscoveryResult {
             setStatus('running');
             break;
 
+          case 'progress':
+            setProgress({
+              scanned: data.scanned,
+              total: data.total,
+              found: data.found,
+              etaSeconds: data.eta_seconds,
+            });
+            break;
+
           case 'completed':
             setMessage(data.message);
+            setProgress(null);
             setStatus('completed');
             eventSource.close();
             eventSourceRef.current = null;
@@ -146,10 +189,20 @@ export function useUnifiedDiscovery(): UseUnifiedDiscoveryResult {
 
           case 'error':
             setMessage(data.message);
+            setProgress(null);
             setStatus('error');
             eventSource.close();
             eventSourceRef.current = null;
             break;
+
+          case 'heartbeat':
+            if (data.dropped_events > 0) {
+              console.warn(
+                `Discovery stream is falling behind: ${data.dropped_events} updates dropped, ` +
+                  `${data.queue_depth}/${data.queue_capacity} queued`
+              );
+            }
+            break;
         }
       } catch (e) {
         console.error('Failed to parse discovery event:', e);
@@ -179,6 +232,7 @@ export function useUnifiedDiscovery(): UseUnifiedDiscoveryResult {
     devices,
     status,
     message,
+    progress,
     startDiscovery,
     stopDiscovery,
     clearDevices,
@@ -3,7 +3,9 @@ import { TanStackRouterDevtools } from '@tanstack/router-devtools'
 import { NavigationMenu, NavigationMenuItem, NavigationMenuList } from '@/components/ui/navigation-menu'
 import { cn } from '@/lib/utils'
 import { useTheme } from '@/hooks/useTheme'
-import { Sun, Moon, Monitor } from 'lucide-react'
+import { useAuth } from '@/hooks/useAuth'
+import { LoginForm } from '@/components/LoginForm'
+import { Sun, Moon, Monitor, LogIn, LogOut } from 'lucide-react'
 import { Button } from '@/components/ui/button'
 import LogoLight from '@/assets/logo/sparkping_logo.svg'
 import LogoDark from '@/assets/logo/sparkping_logo_dark.svg'
@@ -20,18 +22,29 @@ export const Route = createRootRoute({
 })
 
 function RootComponent() {
+  const auth = useAuth()
+
   return (
     <div className="min-h-screen bg-background text-foreground">
-      <Navigation />
+      <Navigation auth={auth} />
       <main>
-        <Outlet />
+        {auth.loginRequired ? (
+          <LoginForm
+            onLogin={(username, password) => auth.login.mutate({ username, password })}
+            onCancel={auth.status?.public_read ? auth.cancelLogin : undefined}
+            isPending={auth.login.isPending}
+            error={auth.login.error}
+          />
+        ) : (
+          <Outlet />
+        )}
       </main>
       {import.meta.env.DEV && <TanStackRouterDevtools />}
     </div>
   )
 }
 
-function Navigation() {
+function Navigation({ auth }: { auth: ReturnType<typeof useAuth> }) {
   const router = useRouterState()
   const currentPath = router.location.pathname
   const { theme, setTheme, isDark } = useTheme()
@@ -101,15 +114,45 @@ function Navigation() {
           </NavigationMenu>
         </div>
 
-        <Button
-          variant="ghost"
-          size="icon"
-          onClick={cycleTheme}
-          className="text-muted-foreground hover:text-foreground"
-          title={`Theme: ${theme}`}
-        >
-          <ThemeIcon className="size-5" />
-        </Button>
+        <div className="flex items-center gap-2">
+          {auth.status?.enabled && (auth.status.authenticated ? (
+            <>
+              <span className="text-sm text-muted-foreground">
+                {auth.status.user ?? auth.status.token_name}
+              </span>
+              {auth.status.user && (
+                <Button
+                  variant="ghost"
+                  size="icon"
+                  onClick={() => auth.logout.mutate()}
+                  className="text-muted-foreground hover:text-foreground"
+                  title="Sign out"
+                >
+                  <LogOut className="size-5" />
+                </Button>
+              )}
+            </>
+          ) : (
+            <Button
+              variant="ghost"
+              size="sm"
+              onClick={auth.requestLogin}
+              className="text-muted-foreground hover:text-foreground"
+            >
+              <LogIn className="size-4" />
+              Sign in
+            </Button>
+          ))}
+          <Button
+            variant="ghost"
+            size="icon"
+            onClick={cycleTheme}
+            className="text-muted-foreground hover:text-foreground"
+            title={`Theme: ${theme}`}
+          >
+            <ThemeIcon className="size-5" />
+          </Button>
+        </div>
       </div>
     </nav>
   )
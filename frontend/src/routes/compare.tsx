@@ -77,7 +77,7 @@ function CompareView() {
 
   const { data: allTargets, isLoading: targetsLoading } = useQuery({
     queryKey: ['targets'],
-    queryFn: fetchTargets,
+    queryFn: () => fetchTargets(),
   });
 
   const [pickerOpen, setPickerOpen] = useState(true);
@@ -163,9 +163,7 @@ function Dashboard() {
               {/* Target rows */}
               {filteredAndSortedTargetStats.map((stat) => {
               const latencyData = stat.recentData.map((d) => d.avg ?? 0)
-              const packetLossData = stat.recentData.map((d) => 
-                d.count > 0 ? (d.failed_count / d.count) * 100 : 0
-              )
+              const packetLossData = stat.recentData.map((d) => d.loss_percent ?? 0)
               
               // Get the latest data point
               const latestData = stat.recentData.length > 0 
@@ -2,13 +2,22 @@ import { createFileRoute } from '@tanstack/react-router'
 import { useQuery, useMutation, useQueryClient } from '@tanstack/react-query'
 import { useState, useMemo } from 'react'
 import { fetchTargets, createTarget, updateTarget, deleteTarget, fetchStorageStats } from '@/api'
-import type { Target, TargetRequest, TargetStorageStats } from '@/types'
+import type { ProbeType, Target, TargetRequest, TargetStorageStats } from '@/types'
 import { Button } from '@/components/ui/button'
 import { Input } from '@/components/ui/input'
 import { Label } from '@/components/ui/label'
+import {
+  Select,
+  SelectContent,
+  SelectItem,
+  SelectTrigger,
+  SelectValue,
+} from '@/components/ui/select'
 import { Card, CardContent, CardHeader, CardTitle, CardAction } from '@/components/ui/card'
 import { Trash2, Edit2, Plus, X, Save, HardDrive, Calendar, ArrowUpDown } from 'lucide-react'
 import { UnifiedDiscoveryPanel } from '@/components/UnifiedDiscoveryPanel'
+import { ApiTokensPanel } from '@/components/ApiTokensPanel'
+import { useAuthStatus } from '@/hooks/useAuth'
 import { PageLayout } from '@/components/PageLayout'
 import { compareIpAddresses, type SortField } from '@/lib/sorting'
 import { SearchInput } from '@/components/SearchInput'
@@ -44,6 +53,7 @@ function formatTimestamp(timestamp: number): string {
 
 function Settings() {
   const queryClient = useQueryClient()
+  const { data: authStatus } = useAuthStatus()
   const [editingId, setEditingId] = useState<string | null>(null)
   const [showAddForm, setShowAddForm] = useState(false)
   const [sortField, setSortField] = useState<SortField>('name')
@@ -57,7 +67,7 @@ function Settings() {
 
   const { data: targets, isLoading, error } = useQuery({
     queryKey: ['targets'],
-    queryFn: fetchTargets,
+    queryFn: () => fetchTargets(),
   })
 
   const { data: storageStats } = useQuery({
@@ -142,6 +152,8 @@ function Settings() {
       name: target.name || '',
       ping_count: target.ping_count,
       ping_interval: target.ping_interval,
+      probe_type: target.probe_type,
+      port: target.port,
     })
     setShowAddForm(false)
   }
@@ -289,6 +301,9 @@ function Settings() {
                         </div>
                       </div>
                       <div className="flex flex-wrap gap-x-3 gap-y-1 text-xs text-muted-foreground">
+                        {target.probe_type === 'tcp' && (
+                          <span>TCP :{target.port ?? 80}</span>
+                        )}
                         <span>{target.ping_count} pings</span>
                         <span>{target.ping_interval}s interval</span>
                         {storageByTarget.get(target.id) && (
@@ -332,6 +347,8 @@ function Settings() {
 
       {/* Device Discovery Section */}
       <UnifiedDiscoveryPanel existingAddresses={existingAddresses} />
+
+      {authStatus?.enabled && authStatus.role === 'admin' && <ApiTokensPanel />}
     </PageLayout>
   )
 }
@@ -357,7 +374,7 @@ function TargetForm({ formData, setFormData, onSubmit, onCancel, isSubmitting }:
             type="text"
             value={formData.address}
             onChange={(e) => setFormData({ ...formData, address: e.target.value })}
-            placeholder="192.168.1.1"
+            placeholder="192.168.1.1 or router.example.com"
             required
           />
         </div>
@@ -413,6 +430,44 @@ function TargetForm({ formData, setFormData, onSubmit, onCancel, isSubmitting }:
           />
           <p className="text-xs text-muted-foreground">Seconds between ping cycles (default: 1)</p>
         </div>
+
+        <div className="space-y-2">
+          <Label htmlFor="probe_type">Probe Type</Label>
+          <Select
+            value={formData.probe_type ?? 'icmp'}
+            onValueChange={(value) => setFormData({ ...formData, probe_type: value as ProbeType })}
+          >
+            <SelectTrigger id="probe_type" className="w-full">
+              <SelectValue />
+            </SelectTrigger>
+            <SelectContent>
+              <SelectItem value="icmp">ICMP ping</SelectItem>
+              <SelectItem value="tcp">TCP connect</SelectItem>
+            </SelectContent>
+          </Select>
+          <p className="text-xs text-muted-foreground">TCP measures the time to open a connection</p>
+        </div>
+
+        {formData.probe_type === 'tcp' && (
+          <div className="space-y-2">
+            <Label htmlFor="port">Port</Label>
+            <Input
+              id="port"
+              type="number"
+              min="1"
+              max="65535"
+              value={formData.port || ''}
+              onChange={(e) =>
+                setFormData({
+                  ...formData,
+                  port: e.target.value ? parseInt(e.target.value, 10) : undefined,
+                })
+              }
+              placeholder="80"
+            />
+            <p className="text-xs text-muted-foreground">TCP port to connect to (default: 80)</p>
+          </div>
+        )}
       </div>
 
       <div className="flex gap-2 pt-2">
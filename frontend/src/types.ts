@@ -6,9 +6,12 @@ export interface PingDataPoint {
   target: string;
   target_name: string | null;
   sequence: number;
+  probe_type: ProbeType;
   success: boolean;
   latency_ms: number | null;
   metric_type: string;
+  /** Site of the agent that measured the result; absent for this server's own pings */
+  site?: string;
 }
 
 export interface PingStatistics {
@@ -18,6 +21,11 @@ export interface PingStatistics {
   min_latency_ms: number | null;
   max_latency_ms: number | null;
   success_rate: number;
+  /** Average RTT delta between consecutive pings of a batch */
+  avg_jitter_ms: number | null;
+  max_jitter_ms: number | null;
+  /** Average loss percentage of completed batches */
+  avg_batch_loss_percent: number | null;
 }
 
 export interface TimeRange {
@@ -39,6 +47,8 @@ export interface PingDataResponse {
   data: PingDataPoint[];
   statistics: PingStatistics;
   total_count: number;
+  /** Pass as `cursor` to fetch the next page; null on the last page */
+  next_cursor: string | null;
 }
 
 export interface PingDataQuery {
@@ -47,6 +57,10 @@ export interface PingDataQuery {
   to?: number;
   metric?: 'latency' | 'failed' | 'all';
   limit?: number;
+  order?: 'asc' | 'desc';
+  cursor?: string;
+  /** Agent site to read ("local" by default, "*" for all sites) */
+  site?: string;
 }
 
 export interface Percentiles {
@@ -70,15 +84,27 @@ export interface BucketDataPoint {
   count: number;
   successful_count: number;
   failed_count: number;
+  /** Data the bucket was computed from; "mixed" for rollups plus raw data */
+  resolution?: 'raw' | '1m' | '1h' | 'mixed';
+  /** False for buckets only partly inside the queried range */
+  complete: boolean;
+  /** Packet loss in percent; absent for buckets without pings */
+  loss_percent?: number;
 }
 
 export interface PingAggregatedQuery {
   target?: string;
   from?: number | string; // Can be absolute timestamp (number) or relative time range (string like "24h", "7d")
   to?: number;
-  metric?: 'latency' | 'failed' | 'all';
+  metric?: 'latency' | 'failed' | 'all' | 'loss' | 'storage_size' | 'jitter' | 'batch_loss';
   bucket?: string;
   include_percentiles?: boolean;
+  /** Merge targets sharing a tag value, e.g. "tag:site" for "site:<value>" tags, or "site" per agent site */
+  group_by?: string;
+  /** IANA time zone that day/week buckets start at local midnight in, e.g. "Europe/Berlin" */
+  tz?: string;
+  /** Agent site to aggregate ("local" by default) */
+  site?: string;
 }
 
 export interface PingAggregatedResponse {
@@ -86,14 +112,41 @@ export interface PingAggregatedResponse {
   data: BucketDataPoint[];
   total_count: number;
   bucket_duration_seconds: number;
+  /** Data the buckets were computed from: raw points or 1m/1h rollups */
+  resolution: 'raw' | '1m' | '1h';
+  /** Grouping applied; `target` then holds the group value */
+  group_by?: string;
+  /** Time zone the day buckets are aligned to */
+  tz?: string;
 }
 
+/** How a target is probed: ICMP echo or TCP connect latency */
+export type ProbeType = 'icmp' | 'tcp';
+
 export interface Target {
   id: string;
   address: string;
   name?: string | null;
   ping_count: number;
   ping_interval: number;
+  probe_type: ProbeType;
+  /** Port for TCP probes (default: 80) */
+  port?: number | null;
+  /** Days this target's data is kept (default: global retention) */
+  retention_days?: number | null;
+  /** Paused targets are not pinged but keep their history */
+  paused: boolean;
+  /** Listed first with sort=order */
+  favorite?: boolean;
+  /** Position in the user-chosen order, lower first (0 = unordered) */
+  sort_order?: number;
+  tags: string[];
+  /** Key/value labels stored with every result and exported to Prometheus */
+  labels?: Record<string, string>;
+  /** ID of the outside reference target of a VPN tunnel target */
+  tunnel_reference?: string | null;
+  /** Number of ECMP flows each batch is spread over */
+  ecmp_flows?: number | null;
 }
 
 export interface TargetRequest {
@@ -102,6 +155,35 @@ export interface TargetRequest {
   name?: string | null;
   ping_count?: number;
   ping_interval?: number;
+  probe_type?: ProbeType;
+  port?: number | null;
+  retention_days?: number | null;
+  /** Omitted on update keeps the current tags */
+  tags?: string[];
+  /** Omitted on update keeps the current labels, {} removes them */
+  labels?: Record<string, string>;
+  /** Omitted on update keeps the current reference, "" removes it */
+  tunnel_reference?: string;
+  /** At most ping_count; omitted on update keeps the current value, 0 turns flows off */
+  ecmp_flows?: number;
+}
+
+/** PATCH /api/targets/{id}; omitted fields are kept */
+export interface TargetPatchRequest {
+  favorite?: boolean;
+  sort_order?: number;
+}
+
+export type TargetState = 'up' | 'down' | 'unknown' | 'paused';
+
+export interface TargetListQuery {
+  /** Substring of ID, name, or address */
+  q?: string;
+  tag?: string;
+  state?: TargetState;
+  favorite?: boolean;
+  /** order: favorites first, then sort_order */
+  sort?: 'name' | 'address' | 'latency' | 'order';
 }
 
 export interface TargetStorageStats {
@@ -112,6 +194,46 @@ export interface TargetStorageStats {
   latest_timestamp: number | null;
 }
 
+export interface TargetStatus {
+  state: TargetState;
+  /** Whether the target's ping task is running */
+  task_running: boolean;
+  last_sample_unix: number | null;
+  last_success: boolean | null;
+  /** Null if the last ping failed */
+  last_latency_ms: number | null;
+  /** Failed pings since the last successful one */
+  consecutive_failed_count: number;
+  avg_latency_24h_ms: number | null;
+  loss_percent_24h: number | null;
+}
+
+/** GET /api/targets/{id} */
+export interface TargetDetailResponse {
+  target: Target;
+  status: TargetStatus;
+  /** Null before anything was stored */
+  storage: TargetStorageStats | null;
+}
+
+/** GET /api/targets/{id}/task, POST /api/targets/{id}/restart */
+export interface TaskHealth {
+  target_id: string;
+  /** False for paused targets and tasks that ended (e.g. by a panic) */
+  running: boolean;
+  paused: boolean;
+  /** Running without a completed batch for 3 cycles */
+  stalled: boolean;
+  started_at_unix: number | null;
+  last_iteration_unix: number | null;
+  /** End of the current wait for the schedule */
+  idle_until_unix: number | null;
+  iterations: number;
+  /** Expected seconds per batch */
+  cycle_seconds: number | null;
+  restarts: number;
+}
+
 export interface StorageStatsResponse {
   total_size_bytes: number;
   targets: TargetStorageStats[];
@@ -156,8 +278,84 @@ export interface SonosVendorInfo {
   icon_url: string | null;
 }
 
+/** Philips Hue bridge information */
+export interface HueVendorInfo {
+  vendor: 'hue';
+  /** The bridge name configured in the Hue app */
+  name: string;
+  /** Model ID (e.g., "BSB002") */
+  model_id: string | null;
+  /** Bridge ID (e.g., "001788FFFE23BFC2") */
+  bridge_id: string | null;
+  /** Software version */
+  software_version: string | null;
+  /** API version */
+  api_version: string | null;
+  /** MAC address */
+  mac_address: string | null;
+  /** Whether the bridge has not been set up yet */
+  factory_new: boolean | null;
+}
+
+/** Shelly device information */
+export interface ShellyVendorInfo {
+  vendor: 'shelly';
+  /** Device generation (1 for the original devices, 2+ for Plus/Pro/Gen3) */
+  generation: number;
+  /** Model identifier (e.g., "SHSW-1", "SNSW-001P16EU") */
+  model: string;
+  /** Application code of Gen2+ devices (e.g., "Plus1PM") */
+  app: string | null;
+  /** Device ID (e.g., "shellyplus1pm-441793d69718") */
+  device_id: string | null;
+  /** Name configured on the device */
+  name: string | null;
+  /** MAC address */
+  mac_address: string | null;
+  /** Firmware version */
+  firmware_version: string | null;
+  /** Full firmware build ID */
+  firmware_id: string | null;
+  /** Whether the local API requires authentication */
+  auth_enabled: boolean;
+  /** Whether the Shelly cloud connection is enabled (Gen1 only) */
+  cloud_enabled: boolean | null;
+  /** Whether the device is connected to the Shelly cloud */
+  cloud_connected: boolean | null;
+}
+
+/** ESPHome node information (from the native API) */
+export interface EsphomeVendorInfo {
+  vendor: 'esphome';
+  /** Node name (e.g., "living-room-sensor") */
+  name: string;
+  /** Friendly name configured for the node */
+  friendly_name: string | null;
+  /** MAC address */
+  mac_address: string | null;
+  /** ESPHome version the firmware was built with */
+  esphome_version: string | null;
+  /** Firmware build time */
+  compilation_time: string | null;
+  /** Board the firmware was built for (e.g., "esp32dev") */
+  board: string | null;
+  /** Manufacturer of the chip (e.g., "Espressif") */
+  manufacturer: string | null;
+  /** Project name for ESPHome-based products */
+  project_name: string | null;
+  project_version: string | null;
+  /** Area suggested by the node configuration */
+  suggested_area: string | null;
+  /** Native API version of the node (e.g., "1.10") */
+  api_version: string | null;
+}
+
 /** Vendor-specific information (tagged union) */
-export type VendorInfo = SonosVendorInfo;
+export type VendorInfo =
+  | SonosVendorInfo
+  | HueVendorInfo
+  | ShellyVendorInfo
+  | EsphomeVendorInfo;
 
 export interface DiscoveredService {
   /** Service type (e.g., "_http._tcp.local.") */
@@ -201,7 +399,9 @@ export interface DeviceInfo {
 /** Source of device discovery */
 export type DiscoverySource =
   | { type: 'mdns'; service_types: string[] }
-  | { type: 'ip_scan'; ports: number[] };
+  | { type: 'ip_scan'; ports: number[] }
+  | { type: 'arp'; mac: string }
+  | { type: 'ws_discovery'; types: string[] };
 
 /** Raw discovery data preserved for detailed inspection */
 export interface RawDiscoveryData {
@@ -215,6 +415,16 @@ export interface RawDiscoveryData {
   ttl?: number;
 }
 
+/** Gateway of a scanned subnet */
+export interface GatewayInfo {
+  /** Subnet in CIDR notation */
+  subnet: string;
+  /** How the gateway was determined */
+  source: 'routing_table' | 'first_host';
+  /** Suggested target name (e.g., "Gateway – 192.168.1.0/24") */
+  target_name: string;
+}
+
 /** A fully identified device with parsed information from the backend */
 export interface IdentifiedDevice {
   /** High-level device information (parsed by backend) */
@@ -223,6 +433,17 @@ export interface IdentifiedDevice {
   discovery_sources: DiscoverySource[];
   /** Raw discovery data for detailed inspection */
   raw_discovery: RawDiscoveryData;
+  /** Set when the device is the gateway of a scanned subnet */
+  gateway?: GatewayInfo;
+  /** Configured target the device already is */
+  tracked_target?: TargetMatch;
+}
+
+export interface TargetMatch {
+  target_id: string;
+  /** "address": target address is one of the device's addresses or its hostname;
+   * "mac": the neighbor table maps the target address to the device's MAC */
+  matched_by: 'address' | 'mac';
 }
 
 export type DiscoveryEvent =
@@ -230,7 +451,24 @@ export type DiscoveryEvent =
   | { event_type: 'device_updated'; device: IdentifiedDevice }
   | { event_type: 'started'; message: string }
   | { event_type: 'completed'; message: string; device_count: number }
-  | { event_type: 'error'; message: string };
+  | { event_type: 'error'; message: string }
+  | {
+      event_type: 'progress';
+      /** Addresses the IP scan has checked so far */
+      scanned: number;
+      /** Addresses in the scanned range */
+      total: number;
+      /** Devices the IP scan found so far */
+      found: number;
+      /** Estimated seconds until the IP scan completes, once known */
+      eta_seconds: number | null;
+    }
+  | {
+      event_type: 'heartbeat';
+      queue_depth: number;
+      queue_capacity: number;
+      dropped_events: number;
+    };
 
 // IP Scan Discovery types
 
@@ -250,3 +488,282 @@ export interface SubnetSuggestion {
   /** Source of this suggestion (e.g., "local", "traceroute") */
   source: string;
 }
+
+// Preferences types
+
+/** UI preferences shared by every browser viewing this instance */
+export type Preferences = Record<string, unknown>;
+
+// Trend types
+
+export interface ForecastPoint {
+  timestamp_unix: number;
+  value: number;
+  /** Lower bound of the 95% prediction band */
+  lower: number;
+  /** Upper bound of the 95% prediction band */
+  upper: number;
+}
+
+export interface MetricTrend {
+  samples: number;
+  /** Change per day (ms/day for latency, percentage points/day for loss) */
+  slope_per_day: number;
+  current: number;
+  change_over_window: number;
+  seasonal: boolean;
+  residual_stddev: number;
+  forecast: ForecastPoint[];
+}
+
+export interface TrendResponse {
+  target_id: string;
+  from: number;
+  to: number;
+  latency: MetricTrend | null;
+  loss: MetricTrend | null;
+}
+
+export type SocketType = 'dgram_native' | 'dgram' | 'raw';
+
+export interface PingTestRequest {
+  address: string;
+  /** Number of probes (default: 3, max: 10) */
+  count?: number;
+  /** Timeout per probe in milliseconds (default: 5000, max: 10000) */
+  timeout_ms?: number;
+  socket_type?: SocketType;
+  probe_type?: ProbeType;
+  port?: number | null;
+}
+
+export interface PingTestPacket {
+  sequence: number;
+  success: boolean;
+  latency_ms: number | null;
+  error: string | null;
+}
+
+export interface PingTestResponse {
+  address: string;
+  resolved_ip: string | null;
+  probe_type: ProbeType;
+  port: number | null;
+  socket_type: SocketType | null;
+  packets: PingTestPacket[];
+  sent: number;
+  received: number;
+  loss_percent: number;
+  min_latency_ms: number | null;
+  avg_latency_ms: number | null;
+  max_latency_ms: number | null;
+  /** Resolution error; no probes were sent */
+  error: string | null;
+}
+
+export interface TracerouteHop {
+  ttl: number;
+  /** Answering router, null when every probe timed out */
+  address: string | null;
+  /** Per-probe latencies (null = timeout); omitted for stored runs */
+  latencies_ms?: (number | null)[];
+  avg_latency_ms: number | null;
+  loss_percent: number;
+}
+
+export interface TracerouteRequest {
+  address: string;
+  /** Highest TTL probed (default: 30, max: 64) */
+  max_hops?: number;
+  /** Probes per TTL (default: 3, max: 5) */
+  probes_per_hop?: number;
+  /** Timeout per probe in milliseconds (default: 1000, max: 5000) */
+  timeout_ms?: number;
+  socket_type?: SocketType;
+}
+
+export interface TracerouteResponse {
+  address: string;
+  resolved_ip: string;
+  reached: boolean;
+  hops: TracerouteHop[];
+}
+
+export interface StoredTraceroute {
+  timestamp: string;
+  timestamp_unix: number;
+  hops: TracerouteHop[];
+  /** Path differs from the previous run */
+  path_changed: boolean;
+}
+
+export interface TracerouteHistoryResponse {
+  target_id: string;
+  runs: StoredTraceroute[];
+}
+
+/** Run of consecutive failed pings from GET /api/outages */
+export interface TargetOutage {
+  target_id: string;
+  target: string;
+  target_name: string | null;
+  start: string;
+  start_unix: number;
+  end: string | null;
+  end_unix: number | null;
+  duration_seconds: number | null;
+  ongoing: boolean;
+}
+
+export interface OutagesResponse {
+  outages: TargetOutage[];
+}
+
+/** Per-target results of one `summary` frame of /api/ping/live?max_rate=... */
+export interface LiveTargetSummary {
+  target_id: string;
+  count: number;
+  successful_count: number;
+  failed_count: number;
+  min_latency_ms: number | null;
+  avg_latency_ms: number | null;
+  max_latency_ms: number | null;
+  /** Most recent result */
+  last: PingDataPoint;
+}
+
+export interface LiveSummary {
+  from_unix: number;
+  to_unix: number;
+  targets: LiveTargetSummary[];
+}
+
+/** Bucket of GET /api/targets/:id/tunnel; overheads are tunnel minus reference */
+export interface TunnelOverheadPoint {
+  timestamp: string;
+  timestamp_unix: number;
+  tunnel_avg_ms: number | null;
+  reference_avg_ms: number | null;
+  delta_latency_ms: number | null;
+  tunnel_loss_percent: number | null;
+  reference_loss_percent: number | null;
+  differential_loss_percent: number | null;
+}
+
+export interface TunnelOverheadResponse {
+  target_id: string;
+  reference_id: string;
+  from_timestamp: number;
+  to_timestamp: number;
+  bucket_duration_seconds: number;
+  data: TunnelOverheadPoint[];
+  avg_delta_latency_ms: number | null;
+  differential_loss_percent: number | null;
+}
+
+/** Results of one ECMP flow of GET /api/targets/:id/flows */
+export interface FlowStats {
+  flow: number;
+  successful_count: number;
+  failed_count: number;
+  loss_percent: number | null;
+  avg_latency_ms: number | null;
+}
+
+export interface FlowReportResponse {
+  target_id: string;
+  ecmp_flows: number;
+  from_timestamp: number;
+  to_timestamp: number;
+  flows: FlowStats[];
+  /** Highest minus lowest loss across flows, in percentage points */
+  loss_divergence_percent: number | null;
+  latency_divergence_ms: number | null;
+  /** Flow with the most loss when loss is concentrated on some paths */
+  suspect_flow: number | null;
+}
+
+/** Each role includes the ones before it */
+export type Role = 'viewer' | 'editor' | 'admin';
+
+/** GET /api/auth/status */
+export interface AuthStatus {
+  enabled: boolean;
+  /** Read-only requests work without a token */
+  public_read: boolean;
+  authenticated: boolean;
+  user: string | null;
+  token_name: string | null;
+  role: Role | null;
+}
+
+export interface LoginResponse {
+  token: string;
+  expires_at: number;
+  user: string;
+  role: Role;
+}
+
+export interface ApiTokenInfo {
+  id: string;
+  name: string;
+  created_at: number;
+  role: Role;
+}
+
+/** The token is only returned when it is created */
+export interface CreatedApiToken extends ApiTokenInfo {
+  token: string;
+}
+
+/** Outcome of the release check (`[updates] check`) */
+export interface UpdateStatus {
+  enabled: boolean;
+  latest_version: string | null;
+  update_available: boolean;
+  release_url: string | null;
+  published_at: string | null;
+  last_checked: number | null;
+  last_error: string | null;
+}
+
+/** GET /api/system/info */
+export interface SystemInfo {
+  version: string;
+  os: string;
+  arch: string;
+  update: UpdateStatus;
+}
+
+export type DiagnosisStepKind = 'dns' | 'icmp_dgram' | 'icmp_raw' | 'tcp' | 'traceroute' | 'mtu';
+
+export type DiagnosisStepStatus = 'passed' | 'warning' | 'failed' | 'skipped';
+
+/** `step` event of POST /api/targets/:id/diagnose */
+export interface DiagnosisStep {
+  step: DiagnosisStepKind;
+  status: DiagnosisStepStatus;
+  summary: string;
+  details?: Record<string, unknown>;
+}
+
+/** Final `verdict` event of POST /api/targets/:id/diagnose */
+export interface DiagnosisReport {
+  target_id: string;
+  address: string;
+  resolved_ip: string | null;
+  steps: DiagnosisStep[];
+  verdict: string;
+  /** Step the verdict is based on, null if nothing is wrong */
+  failed_step: DiagnosisStepKind | null;
+}
+
+/** Body of every API error response */
+export interface ApiErrorBody {
+  /** Stable, machine-readable code, e.g. `invalid_request` or `target_not_found` */
+  code: string;
+  /** Message in the language requested via Accept-Language */
+  message: string;
+  /** Structured details, depending on the error */
+  details?: unknown;
+}
@@ -0,0 +1,350 @@
+//! Agent mode: push ping results to a central SparkPing (`[agent]`).
+//!
+//! An agent is a SparkPing on a remote site that pings its own targets as
+//! usual and also pushes every result, taken from the `LiveFeed`, to the
+//! central server's POST /api/ingest/batch with the `[ingest] token` set
+//! there. Each result carries the agent's site as `site` label, so the
+//! central server can tell the vantage points apart. Batches are pushed
+//! once `batch_size` results are buffered, or every `push_interval` seconds
+//! if fewer arrive. While the central server is unreachable the batch is
+//! retried with exponential backoff and new results keep being buffered, up
+//! to `MAX_BUFFERED_BATCHES` batches; a batch the server rejects as invalid
+//! is dropped, since sending it again would not change the answer.
+
+use crate::api::ingest::dto::IngestResult;
+use crate::backoff::backoff;
+use crate::config::{AgentConfig, AppConfig};
+use crate::live::{LiveFeed, LivePing};
+use crate::storage::SITE_LABEL;
+use crate::tasks::wait_until_enabled;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Batches buffered while the central server is unreachable; older results
+/// are dropped beyond that
+const MAX_BUFFERED_BATCHES: usize = 20;
+
+/// Ingest endpoint of the central server at `server`
+fn ingest_url(server: &str) -> String {
+    format!("{}/api/ingest/batch", server.trim_end_matches('/'))
+}
+
+/// Configured site name, or the hostname
+fn site_name(settings: &AgentConfig) -> String {
+    settings
+        .site
+        .clone()
+        .filter(|site| !site.is_empty())
+        .unwrap_or_else(|| {
+            hostname::get()
+                .ok()
+                .and_then(|h| h.to_str().map(|s| s.to_string()))
+                .unwrap_or_else(|| "unknown".to_string())
+        })
+}
+
+/// Ingest form of a result, labeled with the agent's site
+fn to_result(ping: &LivePing, site: &str) -> IngestResult {
+    let point = &ping.point;
+    let latency_ms = point.latency_ms.filter(|_| point.success);
+    IngestResult {
+        target_id: ping.target_id.clone(),
+        timestamp: point.timestamp_unix,
+        latency_ms,
+        failed: latency_ms.is_none(),
+        sequence: Some(point.sequence),
+        labels: BTreeMap::from([(SITE_LABEL.to_string(), site.to_string())]),
+    }
+}
+
+/// Why a push failed
+#[derive(Debug)]
+enum PushError {
+    /// The server refused the batch itself (4xx other than auth and rate
+    /// limits); retrying does not help
+    Rejected(String),
+    /// Network errors, auth errors and server errors, worth retrying
+    Failed(String),
+}
+
+/// Buffered results and the retry state of the batch at their front
+#[derive(Debug, Default)]
+struct PushBuffer {
+    results: VecDeque<IngestResult>,
+    /// Failed attempts of the current batch
+    attempts: u32,
+    retry_at: Option<Instant>,
+    dropped: u64,
+}
+
+impl PushBuffer {
+    fn push(&mut self, result: IngestResult, batch_size: usize) {
+        self.results.push_back(result);
+        let limit = batch_size.max(1) * MAX_BUFFERED_BATCHES;
+        while self.results.len() > limit {
+            self.results.pop_front();
+            self.dropped += 1;
+        }
+    }
+
+    /// Whether a push is due: a full batch, or any results once the push
+    /// interval has passed, but never during backoff
+    fn due(&self, batch_size: usize, interval_elapsed: bool, now: Instant) -> bool {
+        if self.retry_at.is_some_and(|at| now < at) {
+            return false;
+        }
+        self.results.len() >= batch_size.max(1) || (interval_elapsed && !self.results.is_empty())
+    }
+
+    fn batch(&self, batch_size: usize) -> Vec<IngestResult> {
+        self.results
+            .iter()
+            .take(batch_size.max(1))
+            .cloned()
+            .collect()
+    }
+
+    /// Remove the batch at the front, pushed or rejected
+    fn done(&mut self, batch_size: usize) {
+        let count = self.results.len().min(batch_size.max(1));
+        self.results.drain(..count);
+        self.attempts = 0;
+        self.retry_at = None;
+    }
+
+    /// Record a failed push; the batch is retried after a backoff
+    fn failed(&mut self, now: Instant) {
+        self.attempts += 1;
+        self.retry_at = Some(now + backoff(self.attempts));
+    }
+}
+
+async fn push_batch(
+    client: &reqwest::Client,
+    url: &str,
+    token: &str,
+    batch: &[IngestResult],
+) -> Result<(), PushError> {
+    let body = serde_json::to_vec(batch).map_err(|e| PushError::Rejected(e.to_string()))?;
+    let response = client
+        .post(url)
+        .bearer_auth(token)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| PushError::Failed(e.to_string()))?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    let body = response.text().await.unwrap_or_default();
+    let message = format!("HTTP {}: {}", status.as_u16(), body.trim());
+    let retry = matches!(status.as_u16(), 401 | 403 | 408 | 429) || status.is_server_error();
+    Err(if retry {
+        PushError::Failed(message)
+    } else {
+        PushError::Rejected(message)
+    })
+}
+
+fn read_settings(config: &RwLock<AppConfig>) -> Option<AgentConfig> {
+    match config.read() {
+        Ok(c) => Some(c.agent.clone()),
+        Err(e) => {
+            error!("Failed to read config for the agent: {}", e);
+            None
+        }
+    }
+}
+
+/// Push until the settings change or the feed closes
+async fn run_agent(live: &LiveFeed, config: &RwLock<AppConfig>, settings: &AgentConfig) {
+    let (Some(server), Some(token)) = (settings.server.as_deref(), settings.token.as_deref())
+    else {
+        return;
+    };
+    let client = match reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to create HTTP client for the agent: {}", e);
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            return;
+        }
+    };
+
+    let url = ingest_url(server);
+    let site = site_name(settings);
+    info!("Pushing ping results of site '{}' to {}", site, url);
+    let mut results = live.subscribe();
+    let mut buffer = PushBuffer::default();
+    let mut push = tokio::time::interval(Duration::from_secs(settings.push_interval.max(1)));
+    // The first tick completes immediately
+    push.tick().await;
+
+    loop {
+        let mut interval_elapsed = false;
+        tokio::select! {
+            result = results.recv() => match result {
+                // Results pushed to this server by its own agents are
+                // theirs to report
+                Ok(ping) if ping.point.site.is_some() => {}
+                Ok(ping) => buffer.push(to_result(&ping, &site), settings.batch_size),
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Agent fell behind and skipped {} results", skipped);
+                    buffer.dropped += skipped;
+                }
+                Err(RecvError::Closed) => return,
+            },
+            _ = push.tick() => {
+                if read_settings(config).is_some_and(|current| current != *settings) {
+                    info!("Agent settings changed");
+                    return;
+                }
+                interval_elapsed = true;
+            }
+        }
+
+        if interval_elapsed && buffer.dropped > 0 {
+            warn!("{} ping results were not pushed", buffer.dropped);
+            buffer.dropped = 0;
+        }
+        if !buffer.due(settings.batch_size, interval_elapsed, Instant::now()) {
+            continue;
+        }
+        let batch = buffer.batch(settings.batch_size);
+        match push_batch(&client, &url, token, &batch).await {
+            Ok(()) => {
+                debug!("Pushed {} ping results", batch.len());
+                buffer.done(settings.batch_size);
+            }
+            Err(PushError::Rejected(e)) => {
+                error!(
+                    "Central server rejected {} ping results, dropping them: {}",
+                    batch.len(),
+                    e
+                );
+                buffer.done(settings.batch_size);
+            }
+            Err(PushError::Failed(e)) => {
+                buffer.failed(Instant::now());
+                warn!(
+                    "Pushing ping results failed (attempt {}), retrying in {:?}: {}",
+                    buffer.attempts,
+                    backoff(buffer.attempts),
+                    e
+                );
+            }
+        }
+    }
+}
+
+/// Start the agent. Settings are re-read every push interval; without
+/// `[agent] server` the live feed is not subscribed to.
+pub fn start_agent_task(live: Arc<LiveFeed>, config: Arc<RwLock<AppConfig>>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            wait_until_enabled(&config, |c| {
+                let settings = &c.agent;
+                settings.server.as_deref().is_some_and(|s| !s.is_empty())
+                    && settings.token.as_deref().is_some_and(|t| !t.is_empty())
+            })
+            .await;
+
+            let Some(settings) = read_settings(&config) else {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                continue;
+            };
+
+            run_agent(&live, &config, &settings).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::ping::dto::PingDataPoint;
+    use crate::config::ProbeType;
+
+    fn ping(success: bool, latency: Option<f64>) -> LivePing {
+        LivePing {
+            target_id: "router".to_string(),
+            point: PingDataPoint {
+                timestamp: "2024-01-01T00:00:00+00:00".to_string(),
+                timestamp_unix: 1_704_067_200,
+                target: "192.168.1.1".to_string(),
+                target_name: None,
+                sequence: 2,
+                probe_type: ProbeType::Icmp,
+                success,
+                latency_ms: latency,
+                metric_type: "ping_latency".to_string(),
+                site: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_to_result() {
+        let result = to_result(&ping(true, Some(12.5)), "berlin");
+        assert_eq!(result.target_id, "router");
+        assert_eq!(result.timestamp, 1_704_067_200);
+        assert_eq!((result.latency_ms, result.failed), (Some(12.5), false));
+        assert_eq!(result.sequence, Some(2));
+        assert_eq!(
+            result.labels.get(SITE_LABEL).map(String::as_str),
+            Some("berlin")
+        );
+
+        // The ingest API refuses failed results with a latency
+        let result = to_result(&ping(false, Some(12.5)), "berlin");
+        assert_eq!((result.latency_ms, result.failed), (None, true));
+        let result = to_result(&ping(true, None), "berlin");
+        assert!(result.failed);
+    }
+
+    #[test]
+    fn test_ingest_url() {
+        assert_eq!(
+            ingest_url("https://sparkping.example.com/"),
+            "https://sparkping.example.com/api/ingest/batch"
+        );
+        assert_eq!(
+            ingest_url("http://10.0.0.5:8080"),
+            "http://10.0.0.5:8080/api/ingest/batch"
+        );
+    }
+
+    #[test]
+    fn test_buffer_retries_and_drops_oldest() {
+        let now = Instant::now();
+        let mut buffer = PushBuffer::default();
+        for _ in 0..3 {
+            buffer.push(to_result(&ping(true, Some(1.0)), "berlin"), 2);
+        }
+        assert!(buffer.due(2, false, now));
+        assert_eq!(buffer.batch(2).len(), 2);
+
+        // A failed push backs off and keeps the batch
+        buffer.failed(now);
+        assert!(!buffer.due(2, true, now));
+        assert!(buffer.due(2, true, now + backoff(1)));
+        buffer.done(2);
+        assert_eq!(buffer.results.len(), 1);
+        assert!(!buffer.due(2, false, now));
+        assert!(buffer.due(2, true, now));
+
+        for _ in 0..(2 * MAX_BUFFERED_BATCHES) {
+            buffer.push(to_result(&ping(true, Some(1.0)), "berlin"), 2);
+        }
+        assert_eq!(buffer.results.len(), 2 * MAX_BUFFERED_BATCHES);
+        assert_eq!(buffer.dropped, 1);
+    }
+}
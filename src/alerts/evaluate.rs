@@ -0,0 +1,195 @@
+use crate::config::AlertCondition;
+use crate::rollups::{TargetRollups, WindowStats};
+
+/// A single ping result: `latency_ms` is None for failed pings
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub latency_ms: Option<f64>,
+}
+
+/// Outcome of evaluating a rule for one target
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Evaluation {
+    /// Not enough data to evaluate the rule
+    NoData,
+    /// Rule evaluated; `value` is the observed latency/loss (None for "down")
+    Evaluated { firing: bool, value: Option<f64> },
+}
+
+/// Statistics of the samples of an evaluation window, for windows longer
+/// than the rollups keep
+pub fn sample_stats(samples: &[Sample], window_seconds: i64) -> WindowStats {
+    let latencies: Vec<f64> = samples.iter().filter_map(|s| s.latency_ms).collect();
+    let count = samples.len();
+    let failed_count = count - latencies.len();
+    WindowStats {
+        window_seconds,
+        count,
+        successful_count: latencies.len(),
+        failed_count,
+        loss_percent: (count > 0).then(|| failed_count as f64 / count as f64 * 100.0),
+        avg_latency_ms: (!latencies.is_empty())
+            .then(|| latencies.iter().sum::<f64>() / latencies.len() as f64),
+        min_latency_ms: latencies.iter().copied().reduce(f64::min),
+        max_latency_ms: latencies.iter().copied().reduce(f64::max),
+    }
+}
+
+/// Evaluate a latency or loss rule against the statistics of its evaluation
+/// window. Down rules are evaluated with [`evaluate_down`].
+pub fn evaluate_window(
+    condition: AlertCondition,
+    threshold: f64,
+    stats: Option<&WindowStats>,
+) -> Evaluation {
+    let Some(stats) = stats.filter(|s| s.count > 0) else {
+        return Evaluation::NoData;
+    };
+
+    let value = match condition {
+        AlertCondition::LatencyMs => stats.avg_latency_ms,
+        AlertCondition::LossPercent => stats.loss_percent,
+        AlertCondition::Down => return Evaluation::NoData,
+    };
+    // Only failures: latency is undefined, loss/down rules cover this
+    Evaluation::Evaluated {
+        firing: value.is_some_and(|v| v > threshold),
+        value,
+    }
+}
+
+/// Evaluate a down rule against the rollups of a target: it fires once the
+/// last `required_failures` results all failed. Targets without results
+/// since `since` have no data.
+pub fn evaluate_down(
+    rollups: Option<&TargetRollups>,
+    required_failures: usize,
+    since: i64,
+) -> Evaluation {
+    let Some(rollups) = rollups.filter(|r| r.last_sample_unix.is_some_and(|t| t >= since)) else {
+        return Evaluation::NoData;
+    };
+
+    let required = required_failures.max(1) as u64;
+    if rollups.consecutive_failed_count >= required {
+        return Evaluation::Evaluated {
+            firing: true,
+            value: None,
+        };
+    }
+    if rollups.total_successful_count == 0 {
+        // Not enough results yet
+        return Evaluation::NoData;
+    }
+    Evaluation::Evaluated {
+        firing: false,
+        value: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rollups::RollingAggregator;
+
+    fn ok(latency_ms: f64) -> Sample {
+        Sample {
+            latency_ms: Some(latency_ms),
+        }
+    }
+
+    fn failed() -> Sample {
+        Sample { latency_ms: None }
+    }
+
+    fn stats(samples: &[Sample]) -> WindowStats {
+        sample_stats(samples, 60)
+    }
+
+    #[test]
+    fn test_latency_condition() {
+        let samples = stats(&[ok(150.0), ok(300.0), failed()]);
+        assert_eq!(
+            evaluate_window(AlertCondition::LatencyMs, 200.0, Some(&samples)),
+            Evaluation::Evaluated {
+                firing: true,
+                value: Some(225.0)
+            }
+        );
+        assert_eq!(
+            evaluate_window(AlertCondition::LatencyMs, 250.0, Some(&samples)),
+            Evaluation::Evaluated {
+                firing: false,
+                value: Some(225.0)
+            }
+        );
+
+        let samples = stats(&[failed()]);
+        assert_eq!(
+            evaluate_window(AlertCondition::LatencyMs, 250.0, Some(&samples)),
+            Evaluation::Evaluated {
+                firing: false,
+                value: None
+            }
+        );
+    }
+
+    #[test]
+    fn test_loss_condition() {
+        let samples = stats(&[ok(10.0), failed(), failed(), ok(10.0)]);
+        assert_eq!(
+            evaluate_window(AlertCondition::LossPercent, 20.0, Some(&samples)),
+            Evaluation::Evaluated {
+                firing: true,
+                value: Some(50.0)
+            }
+        );
+    }
+
+    #[test]
+    fn test_down_condition() {
+        let rollups = RollingAggregator::new();
+        rollups.record("t1", 1, Some(10.0));
+        for timestamp in 2..5 {
+            rollups.record("t1", timestamp, None);
+        }
+        // Last three results failed
+        let current = rollups.rollups("t1", 4);
+        assert_eq!(
+            evaluate_down(current.as_ref(), 3, 0),
+            Evaluation::Evaluated {
+                firing: true,
+                value: None
+            }
+        );
+        // Not for long enough
+        assert_eq!(
+            evaluate_down(current.as_ref(), 4, 0),
+            Evaluation::Evaluated {
+                firing: false,
+                value: None
+            }
+        );
+        // No results within the window
+        assert_eq!(evaluate_down(current.as_ref(), 3, 5), Evaluation::NoData);
+
+        // Not enough results yet
+        rollups.record("t2", 1, None);
+        assert_eq!(
+            evaluate_down(rollups.rollups("t2", 1).as_ref(), 3, 0),
+            Evaluation::NoData
+        );
+    }
+
+    #[test]
+    fn test_no_data() {
+        assert_eq!(
+            evaluate_window(AlertCondition::LossPercent, 20.0, None),
+            Evaluation::NoData
+        );
+        assert_eq!(
+            evaluate_window(AlertCondition::LossPercent, 20.0, Some(&stats(&[]))),
+            Evaluation::NoData
+        );
+    }
+}
@@ -0,0 +1,471 @@
+//! Alerting: threshold rules evaluated periodically against the rolling
+//! rollups of live ping results.
+//!
+//! Latency and loss rules read their window from the `RollingAggregator`;
+//! only windows longer than the hour it keeps are read from storage. Down
+//! rules count consecutive failures in the rollups. Rules (`[[alerts.rules]]`) are read from the shared config on every
+//! evaluation, so hot reloads apply without restarting the task. Each
+//! (rule, target) pair has its own state; transitions between `ok` and
+//! `firing` are sent to the `[notifications]` channels.
+
+pub mod evaluate;
+
+use crate::api::ping::query::{parse_bucket_duration, select_target_data};
+use crate::config::{AlertCondition, AlertRule, AppConfig, NotificationKind, Target};
+use crate::notifications::{dispatch, target_label, Notification};
+use crate::rollups::{RollingAggregator, MAX_WINDOW_SECONDS};
+use crate::storage::{series_site, StorageBackend};
+use evaluate::{evaluate_down, evaluate_window, sample_stats, Evaluation, Sample};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+/// Upper bound for a single ping including its timeout, used to size the
+/// lookback window of "down" rules
+const PING_TIMEOUT_SECS: u64 = 5;
+
+/// Alert state transition sent to notification channels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertEvent {
+    Firing,
+    Resolved,
+}
+
+/// Current state of a (rule, target) pair
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertState {
+    Ok,
+    Firing,
+    /// No results in the evaluation window yet
+    NoData,
+}
+
+/// Alert status of one rule for one target
+#[derive(Debug, Clone, Serialize)]
+pub struct AlertStatus {
+    pub rule: String,
+    pub target_id: String,
+    pub target: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_name: Option<String>,
+    pub condition: AlertCondition,
+    pub threshold: f64,
+    pub state: AlertState,
+    /// Observed latency (ms) or loss (%) at the last evaluation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<f64>,
+    /// Unix timestamp of the last state change
+    pub since: i64,
+    /// Unix timestamp of the last evaluation
+    pub last_evaluated: i64,
+}
+
+/// Alert states of all (rule, target) pairs, shared through `AppState`
+#[derive(Debug, Default)]
+pub struct AlertEngine {
+    states: RwLock<HashMap<(String, String), AlertStatus>>,
+}
+
+impl AlertEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All alert states, sorted by rule and target
+    pub fn statuses(&self) -> Vec<AlertStatus> {
+        let states = self.states.read().unwrap_or_else(|e| e.into_inner());
+        let mut statuses: Vec<AlertStatus> = states.values().cloned().collect();
+        statuses.sort_by(|a, b| (&a.rule, &a.target_id).cmp(&(&b.rule, &b.target_id)));
+        statuses
+    }
+
+    /// Evaluate all rules at Unix time `now`.
+    ///
+    /// Returns the `ok` <-> `firing` transitions to notify about.
+    pub fn evaluate(
+        &self,
+        config: &AppConfig,
+        rollups: &RollingAggregator,
+        storage: &dyn StorageBackend,
+        now: i64,
+    ) -> Vec<(AlertEvent, AlertStatus)> {
+        let mut transitions = Vec::new();
+        let mut active: HashSet<(String, String)> = HashSet::new();
+
+        for rule in &config.alerts.rules {
+            let window = match rule_window(rule, config.alerts.evaluation_interval) {
+                Ok(window) => window,
+                Err(e) => {
+                    warn!("Skipping alert rule '{}': {}", rule.name, e);
+                    continue;
+                }
+            };
+
+            for target in config
+                .targets
+                .iter()
+                .filter(|t| rule.target == "*" || rule.target == t.id)
+            {
+                let key = (rule.name.clone(), target.id.clone());
+                active.insert(key.clone());
+
+                let evaluation = match window {
+                    RuleWindow::Intervals(intervals) => {
+                        let (lookback, required) =
+                            down_window(target, intervals, config.alerts.evaluation_interval);
+                        let current = rollups.rollups(&target.id, now);
+                        evaluate_down(current.as_ref(), required, now - lookback as i64)
+                    }
+                    RuleWindow::Duration(seconds) if seconds as i64 <= MAX_WINDOW_SECONDS => {
+                        let stats = rollups.window(&target.id, now, seconds as i64);
+                        evaluate_window(rule.condition, rule.threshold, stats.as_ref())
+                    }
+                    RuleWindow::Duration(seconds) => {
+                        let from = now - seconds as i64;
+                        match collect_samples(storage, target, from, now) {
+                            Ok(samples) => {
+                                let stats = sample_stats(&samples, seconds as i64);
+                                evaluate_window(rule.condition, rule.threshold, Some(&stats))
+                            }
+                            Err(e) => {
+                                error!(
+                                    "Failed to query samples for alert rule '{}' on target {}: {}",
+                                    rule.name, target.id, e
+                                );
+                                continue;
+                            }
+                        }
+                    }
+                };
+
+                if let Some(transition) = self.apply(key, rule, target, evaluation, now) {
+                    transitions.push(transition);
+                }
+            }
+        }
+
+        // Forget states of removed rules and targets
+        let mut states = self.states.write().unwrap_or_else(|e| e.into_inner());
+        states.retain(|key, _| active.contains(key));
+
+        transitions
+    }
+
+    /// Record an evaluation and return the transition to notify about, if any
+    fn apply(
+        &self,
+        key: (String, String),
+        rule: &AlertRule,
+        target: &Target,
+        evaluation: Evaluation,
+        now: i64,
+    ) -> Option<(AlertEvent, AlertStatus)> {
+        let mut states = self.states.write().unwrap_or_else(|e| e.into_inner());
+        let previous = states.get(&key).map(|s| (s.state, s.since));
+
+        let (state, value) = match evaluation {
+            // Missing data does not resolve a firing alert
+            Evaluation::NoData if previous.map(|(s, _)| s) == Some(AlertState::Firing) => {
+                (AlertState::Firing, None)
+            }
+            Evaluation::NoData => (AlertState::NoData, None),
+            Evaluation::Evaluated {
+                firing: true,
+                value,
+            } => (AlertState::Firing, value),
+            Evaluation::Evaluated {
+                firing: false,
+                value,
+            } => (AlertState::Ok, value),
+        };
+
+        let since = match previous {
+            Some((previous_state, since)) if previous_state == state => since,
+            _ => now,
+        };
+
+        let status = AlertStatus {
+            rule: rule.name.clone(),
+            target_id: target.id.clone(),
+            target: target.address.clone(),
+            target_name: target.name.clone(),
+            condition: rule.condition,
+            threshold: rule.threshold,
+            state,
+            value,
+            since,
+            last_evaluated: now,
+        };
+        states.insert(key, status.clone());
+
+        let was_firing = previous.map(|(s, _)| s) == Some(AlertState::Firing);
+        match (was_firing, state) {
+            (false, AlertState::Firing) => Some((AlertEvent::Firing, status)),
+            (true, AlertState::Ok) => Some((AlertEvent::Resolved, status)),
+            _ => None,
+        }
+    }
+}
+
+/// Evaluation window of a rule
+#[derive(Debug, Clone, Copy)]
+enum RuleWindow {
+    /// Latency/loss rules: window length in seconds
+    Duration(u64),
+    /// Down rules: number of consecutive failed ping cycles
+    Intervals(u32),
+}
+
+fn rule_window(rule: &AlertRule, evaluation_interval: u64) -> Result<RuleWindow, String> {
+    match rule.condition {
+        AlertCondition::Down => Ok(RuleWindow::Intervals(rule.intervals.max(1))),
+        AlertCondition::LatencyMs | AlertCondition::LossPercent => {
+            let seconds = parse_bucket_duration(&rule.for_duration)?;
+            // The window must cover at least one evaluation interval
+            Ok(RuleWindow::Duration(
+                (seconds as u64).max(evaluation_interval),
+            ))
+        }
+    }
+}
+
+/// Lookback (seconds) and required number of consecutive failed results for
+/// a down rule
+fn down_window(target: &Target, intervals: u32, evaluation_interval: u64) -> (u64, usize) {
+    let cycle = target.ping_interval + target.ping_count as u64 * PING_TIMEOUT_SECS;
+    let lookback = intervals as u64 * cycle + evaluation_interval;
+    let required = intervals as usize * target.ping_count as usize;
+    (lookback, required)
+}
+
+/// Collect successful and failed results of a target in `[from, to]`, for
+/// windows longer than the rollups keep
+fn collect_samples(
+    storage: &dyn StorageBackend,
+    target: &Target,
+    from: i64,
+    to: i64,
+) -> Result<Vec<Sample>, Box<dyn std::error::Error + Send + Sync>> {
+    let to_sample = |metric: &str, value: f64| Sample {
+        latency_ms: (metric == "ping_latency").then_some(value),
+    };
+
+    let mut samples = Vec::new();
+    for metric in ["ping_latency", "ping_failed"] {
+        let points = select_target_data(storage, metric, target, from, to)?;
+        samples.extend(points.iter().map(|p| to_sample(metric, p.value)));
+    }
+
+    // Fallback: label set changed (e.g. renamed target), scan by target_id
+    if samples.is_empty() {
+        for metric in ["ping_latency", "ping_failed"] {
+            for (labels, points) in storage.select_all(metric, from, to)? {
+                if series_site(&labels).is_none()
+                    && labels
+                        .iter()
+                        .any(|l| l.name == "target_id" && l.value == target.id)
+                {
+                    samples.extend(points.iter().map(|p| to_sample(metric, p.value)));
+                }
+            }
+        }
+    }
+
+    Ok(samples)
+}
+
+/// Notification for an alert transition
+fn alert_notification(event: AlertEvent, status: &AlertStatus, target: &Target) -> Notification {
+    let label = target_label(target);
+    let observed = match (status.condition, status.value) {
+        (AlertCondition::LatencyMs, Some(value)) => format!(
+            "average latency {:.1} ms (threshold {} ms)",
+            value, status.threshold
+        ),
+        (AlertCondition::LossPercent, Some(value)) => format!(
+            "packet loss {:.1}% (threshold {}%)",
+            value, status.threshold
+        ),
+        (AlertCondition::Down, _) => "all recent pings failed".to_string(),
+        (_, None) => "no latency data".to_string(),
+    };
+    let (kind, title, message) = match event {
+        AlertEvent::Firing => (
+            NotificationKind::AlertFiring,
+            format!("Alert '{}' firing for {}", status.rule, label),
+            format!(
+                "Alert '{}' is firing for {}: {}.",
+                status.rule, label, observed
+            ),
+        ),
+        AlertEvent::Resolved => (
+            NotificationKind::AlertResolved,
+            format!("Alert '{}' resolved for {}", status.rule, label),
+            format!(
+                "Alert '{}' resolved for {}: {}.",
+                status.rule, label, observed
+            ),
+        ),
+    };
+
+    Notification::new(kind, title, message, status.last_evaluated)
+        .with_target(target)
+        .with_details(serde_json::to_value(status).unwrap_or_default())
+}
+
+/// Start the background task that evaluates alert rules
+pub fn start_alert_task(
+    engine: Arc<AlertEngine>,
+    config: Arc<RwLock<AppConfig>>,
+    rollups: Arc<RollingAggregator>,
+    storage: Arc<dyn StorageBackend>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            let snapshot = config.read().map(|c| c.clone()).map_err(|e| e.to_string());
+            let snapshot = match snapshot {
+                Ok(snapshot) => snapshot,
+                Err(e) => {
+                    error!("Failed to read config for alert evaluation: {}", e);
+                    tokio::time::sleep(Duration::from_secs(30)).await;
+                    continue;
+                }
+            };
+            let interval = snapshot.alerts.evaluation_interval.max(1);
+
+            if !snapshot.alerts.rules.is_empty() {
+                let engine_for_eval = Arc::clone(&engine);
+                let rollups_for_eval = Arc::clone(&rollups);
+                let storage_for_eval = Arc::clone(&storage);
+                let channels = snapshot.notifications.channels.clone();
+                let targets = snapshot.targets.clone();
+                let result = tokio::task::spawn_blocking(move || {
+                    let now = chrono::Utc::now().timestamp();
+                    engine_for_eval.evaluate(
+                        &snapshot,
+                        &rollups_for_eval,
+                        storage_for_eval.as_ref(),
+                        now,
+                    )
+                })
+                .await;
+
+                match result {
+                    Ok(transitions) => {
+                        for (event, status) in transitions {
+                            info!(
+                                "Alert '{}' for target {} is {:?}",
+                                status.rule, status.target_id, event
+                            );
+                            if let Some(target) = targets.iter().find(|t| t.id == status.target_id)
+                            {
+                                dispatch(&channels, alert_notification(event, &status, target));
+                            }
+                        }
+                    }
+                    Err(e) => error!("Alert evaluation task failed: {}", e),
+                }
+            }
+
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(id: &str) -> Target {
+        Target {
+            id: id.to_string(),
+            address: "192.168.1.1".to_string(),
+            ping_interval: 10,
+            ..Default::default()
+        }
+    }
+
+    fn rule() -> AlertRule {
+        AlertRule {
+            name: "high-latency".to_string(),
+            target: "*".to_string(),
+            condition: AlertCondition::LatencyMs,
+            threshold: 100.0,
+            for_duration: "1m".to_string(),
+            intervals: 3,
+        }
+    }
+
+    fn key() -> (String, String) {
+        ("high-latency".to_string(), "t1".to_string())
+    }
+
+    #[test]
+    fn test_transitions() {
+        let engine = AlertEngine::new();
+        let (rule, target) = (rule(), target("t1"));
+        let firing = Evaluation::Evaluated {
+            firing: true,
+            value: Some(150.0),
+        };
+        let ok = Evaluation::Evaluated {
+            firing: false,
+            value: Some(20.0),
+        };
+
+        let (event, status) = engine.apply(key(), &rule, &target, firing, 100).unwrap();
+        assert_eq!(event, AlertEvent::Firing);
+        assert_eq!(status.since, 100);
+
+        // Still firing: no new notification, `since` is kept
+        assert!(engine.apply(key(), &rule, &target, firing, 130).is_none());
+        assert_eq!(engine.statuses()[0].since, 100);
+
+        // Missing data does not resolve the alert
+        assert!(engine
+            .apply(key(), &rule, &target, Evaluation::NoData, 160)
+            .is_none());
+        assert_eq!(engine.statuses()[0].state, AlertState::Firing);
+
+        let (event, status) = engine.apply(key(), &rule, &target, ok, 190).unwrap();
+        assert_eq!(event, AlertEvent::Resolved);
+        assert_eq!(status.since, 190);
+    }
+
+    #[test]
+    fn test_evaluate_from_rollups() {
+        let rollups = RollingAggregator::new();
+        let storage = crate::storage::memory_storage();
+        let mut config: AppConfig = serde_json::from_value(serde_json::json!({
+            "server": { "host": "0.0.0.0", "port": 8080 },
+            "logging": { "level": "info", "file": "sparkping.log" },
+            "database": { "path": "./tsink-data" },
+        }))
+        .unwrap();
+        config.targets = vec![target("t1")];
+        config.alerts.rules = vec![rule()];
+        config.alerts.evaluation_interval = 30;
+
+        let engine = AlertEngine::new();
+        engine.evaluate(&config, &rollups, &*storage, 1000);
+        assert_eq!(engine.statuses()[0].state, AlertState::NoData);
+
+        // Only in the rollups, not in storage
+        rollups.record("t1", 970, Some(150.0));
+        rollups.record("t1", 990, Some(250.0));
+        let transitions = engine.evaluate(&config, &rollups, &*storage, 1000);
+        assert_eq!(transitions.len(), 1);
+        assert_eq!(transitions[0].0, AlertEvent::Firing);
+        assert_eq!(transitions[0].1.value, Some(200.0));
+    }
+
+    #[test]
+    fn test_down_window() {
+        // 3 cycles of (10s interval + 3 pings * 5s timeout) + 30s slack
+        assert_eq!(down_window(&target("t1"), 3, 30), (105, 9));
+    }
+}
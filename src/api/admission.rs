@@ -0,0 +1,298 @@
+//! Global admission control for historical query endpoints.
+//!
+//! Per-requester quotas (see `quota`) don't stop many requesters, or one
+//! dashboard with many panels, from running dozens of storage scans at once
+//! and starving the ping tasks of CPU and I/O. At most
+//! `[server.query_admission] max_concurrent` queries run at a time; excess
+//! queries wait in a queue that is served round-robin across requesters, so
+//! one client's burst cannot starve the others. Queries that wait longer than
+//! `queue_timeout_ms`, or arrive while `max_queued` are already waiting, are
+//! rejected with 429 and a `Retry-After` header. A query's slot is held until
+//! its response body has been sent.
+
+use crate::api::error::{ApiError, ErrorCode};
+use crate::api::middleware::hold_until_body_ends;
+use crate::api::quota::requester_key;
+use crate::api::AppState;
+use crate::config::QueryAdmissionConfig;
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{header, HeaderValue, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tracing::warn;
+
+/// Reason a query was not admitted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdmissionRejected {
+    /// `max_queued` queries were already waiting
+    QueueFull,
+    /// No slot became free within `queue_timeout_ms`
+    Timeout,
+}
+
+/// A query waiting for a slot
+#[derive(Debug)]
+struct Waiter {
+    ticket: u64,
+    admit: oneshot::Sender<()>,
+}
+
+#[derive(Debug, Default)]
+struct AdmissionState {
+    running: u32,
+    queued: usize,
+    next_ticket: u64,
+    /// Waiting queries per requester; the front requester is served next
+    /// and then moves to the back
+    waiting: VecDeque<(String, VecDeque<Waiter>)>,
+}
+
+impl AdmissionState {
+    /// Hand a finished query's slot to the next waiter, or free it
+    fn release(&mut self) {
+        while let Some((requester, mut waiters)) = self.waiting.pop_front() {
+            let next = waiters.pop_front();
+            if !waiters.is_empty() {
+                self.waiting.push_back((requester, waiters));
+            }
+            if let Some(waiter) = next {
+                self.queued -= 1;
+                // The slot stays taken; a failed send means the waiter is gone
+                if waiter.admit.send(()).is_ok() {
+                    return;
+                }
+            }
+        }
+        self.running = self.running.saturating_sub(1);
+    }
+
+    /// Remove a waiter that gave up. False if it was already admitted.
+    fn remove(&mut self, ticket: u64) -> bool {
+        for (_, waiters) in self.waiting.iter_mut() {
+            if let Some(index) = waiters.iter().position(|w| w.ticket == ticket) {
+                waiters.remove(index);
+                self.queued -= 1;
+                self.waiting.retain(|(_, waiters)| !waiters.is_empty());
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// Shared admission state, held in `AppState`
+#[derive(Debug, Default)]
+pub struct QueryAdmission {
+    state: Mutex<AdmissionState>,
+}
+
+/// A running query's slot, handed to the next waiter when dropped
+#[derive(Debug)]
+pub struct AdmissionPermit {
+    admission: Arc<QueryAdmission>,
+}
+
+impl Drop for AdmissionPermit {
+    fn drop(&mut self) {
+        self.admission.lock().release();
+    }
+}
+
+/// Removes a queued query that is cancelled or times out, and frees the
+/// slot if it was admitted in the meantime. Owns the receiving end so that
+/// an admission cannot be sent to a waiter that is already gone.
+struct QueuedTicket {
+    admission: Arc<QueryAdmission>,
+    ticket: u64,
+    admit: oneshot::Receiver<()>,
+    admitted: bool,
+}
+
+impl Drop for QueuedTicket {
+    fn drop(&mut self) {
+        if !self.admitted {
+            let mut state = self.admission.lock();
+            if !state.remove(self.ticket) {
+                state.release();
+            }
+        }
+    }
+}
+
+impl QueryAdmission {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, AdmissionState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Wait for a slot to run a query by `requester`
+    pub async fn acquire(
+        self: &Arc<Self>,
+        requester: &str,
+        limits: QueryAdmissionConfig,
+    ) -> Result<AdmissionPermit, AdmissionRejected> {
+        let (admit, admitted) = oneshot::channel();
+        let ticket = {
+            let mut state = self.lock();
+            if state.running < limits.max_concurrent && state.queued == 0 {
+                state.running += 1;
+                return Ok(AdmissionPermit {
+                    admission: Arc::clone(self),
+                });
+            }
+            if state.queued >= limits.max_queued as usize {
+                return Err(AdmissionRejected::QueueFull);
+            }
+
+            let ticket = state.next_ticket;
+            state.next_ticket += 1;
+            state.queued += 1;
+            let waiter = Waiter { ticket, admit };
+            match state.waiting.iter_mut().find(|(r, _)| r == requester) {
+                Some((_, waiters)) => waiters.push_back(waiter),
+                None => state
+                    .waiting
+                    .push_back((requester.to_string(), VecDeque::from([waiter]))),
+            }
+            ticket
+        };
+
+        let mut queued = QueuedTicket {
+            admission: Arc::clone(self),
+            ticket,
+            admit: admitted,
+            admitted: false,
+        };
+        let timeout = Duration::from_millis(limits.queue_timeout_ms);
+        match tokio::time::timeout(timeout, &mut queued.admit).await {
+            Ok(Ok(())) => {
+                queued.admitted = true;
+                Ok(AdmissionPermit {
+                    admission: Arc::clone(self),
+                })
+            }
+            _ => Err(AdmissionRejected::Timeout),
+        }
+    }
+}
+
+/// Middleware enforcing `[server.query_admission]` on historical query routes
+pub(crate) async fn query_admission_middleware(
+    State(state): State<AppState>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    // Read limits per request so config hot reloads apply immediately
+    let limits = state
+        .config
+        .read()
+        .map(|c| c.server.query_admission)
+        .unwrap_or_default();
+    if limits.max_concurrent == 0 {
+        return next.run(req).await;
+    }
+
+    let requester = requester_key(&req);
+    match state.admission.acquire(&requester, limits).await {
+        // The streamed export keeps reading storage after the response head
+        Ok(permit) => hold_until_body_ends(next.run(req).await, permit),
+        Err(rejected) => {
+            warn!("Query from {} not admitted: {:?}", requester, rejected);
+            let message = match rejected {
+                AdmissionRejected::QueueFull => "Too many queries waiting, try again later",
+                AdmissionRejected::Timeout => "Timed out waiting for a query slot",
+            };
+            let retry_after = limits.queue_timeout_ms.div_ceil(1000).max(1);
+            let mut response = ApiError::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                ErrorCode::Overloaded,
+                message,
+            )
+            .with_details(serde_json::json!({ "retry_after_seconds": retry_after }))
+            .into_response();
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+            response
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(max_concurrent: u32, max_queued: u32) -> QueryAdmissionConfig {
+        QueryAdmissionConfig {
+            max_concurrent,
+            max_queued,
+            queue_timeout_ms: 1000,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_queue_full_and_timeout() {
+        let admission = Arc::new(QueryAdmission::new());
+        let limits = limits(1, 0);
+
+        let permit = admission.acquire("a", limits).await.unwrap();
+        assert_eq!(
+            admission.acquire("b", limits).await.unwrap_err(),
+            AdmissionRejected::QueueFull
+        );
+
+        let limits = QueryAdmissionConfig {
+            max_queued: 1,
+            queue_timeout_ms: 10,
+            ..limits
+        };
+        assert_eq!(
+            admission.acquire("b", limits).await.unwrap_err(),
+            AdmissionRejected::Timeout
+        );
+        assert_eq!(admission.lock().queued, 0);
+
+        // The slot is free again once the running query finishes
+        drop(permit);
+        assert!(admission.acquire("b", limits).await.is_ok());
+        assert_eq!(admission.lock().running, 0);
+    }
+
+    #[tokio::test]
+    async fn test_waiters_served_round_robin() {
+        let admission = Arc::new(QueryAdmission::new());
+        let limits = limits(1, 10);
+        let permit = admission.acquire("busy", limits).await.unwrap();
+
+        // "busy" queues three queries before "quiet" queues one
+        let (done_tx, mut done_rx) = tokio::sync::mpsc::unbounded_channel();
+        for (queued, requester) in ["busy", "busy", "busy", "quiet"].into_iter().enumerate() {
+            let task_admission = Arc::clone(&admission);
+            let done_tx = done_tx.clone();
+            tokio::spawn(async move {
+                let _permit = task_admission.acquire(requester, limits).await.unwrap();
+                done_tx.send(requester).unwrap();
+            });
+            // Let the task enqueue before the next one
+            while admission.lock().queued <= queued {
+                tokio::task::yield_now().await;
+            }
+        }
+
+        drop(permit);
+        let mut order = Vec::new();
+        for _ in 0..4 {
+            order.push(done_rx.recv().await.unwrap());
+        }
+        assert_eq!(order, vec!["busy", "quiet", "busy", "busy"]);
+        assert_eq!(admission.lock().running, 0);
+    }
+}
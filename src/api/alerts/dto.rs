@@ -0,0 +1,10 @@
+use crate::alerts::AlertStatus;
+use serde::Serialize;
+
+/// Response for GET /api/alerts
+#[derive(Debug, Serialize)]
+pub struct AlertsResponse {
+    /// Number of (rule, target) pairs currently firing
+    pub firing: usize,
+    pub alerts: Vec<AlertStatus>,
+}
@@ -0,0 +1,16 @@
+use super::dto::AlertsResponse;
+use crate::alerts::AlertState;
+use crate::api::AppState;
+use axum::{extract::State, response::Json};
+
+/// HTTP handler for GET /api/alerts
+///
+/// Returns the current state of every alert rule for every matching target.
+pub(crate) async fn get_alerts(State(state): State<AppState>) -> Json<AlertsResponse> {
+    let alerts = state.alerts.statuses();
+    let firing = alerts
+        .iter()
+        .filter(|a| a.state == AlertState::Firing)
+        .count();
+    Json(AlertsResponse { firing, alerts })
+}
@@ -0,0 +1,20 @@
+use crate::audit_log::AuditEntry;
+use serde::{Deserialize, Serialize};
+
+/// Query parameters for the audit log API
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    /// Only entries at or after this Unix timestamp in seconds (optional)
+    #[serde(default)]
+    pub since: Option<i64>,
+    /// Maximum number of entries (default: 100, at most 1000)
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// API response containing audit log entries
+#[derive(Debug, Serialize)]
+pub struct AuditResponse {
+    /// Audited requests, newest first
+    pub entries: Vec<AuditEntry>,
+}
@@ -0,0 +1,156 @@
+use super::dto::{AuditQuery, AuditResponse};
+use crate::api::error::{ApiError, ErrorCode};
+use crate::api::extract::Query;
+use crate::api::middleware::is_mutating;
+use crate::api::quota::client_ip;
+use crate::api::AppState;
+use crate::audit_log::{config_diff, AuditEntry};
+use crate::auth::{Authenticated, Principal};
+use axum::body::Body;
+use axum::extract::{ConnectInfo, State};
+use axum::http::{Method, Request, Uri};
+use axum::middleware::Next;
+use axum::response::{Json, Response};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tracing::error;
+
+/// Entries returned without `limit`
+const DEFAULT_LIMIT: usize = 100;
+/// Upper bound for `limit`
+const MAX_LIMIT: usize = 1000;
+
+/// HTTP handler for GET /api/audit
+///
+/// Lists audited requests (target and token management, preference updates,
+/// storage operations) with who sent them and the config lines they
+/// changed, newest first.
+pub(crate) async fn get_audit(
+    State(state): State<AppState>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<AuditResponse>, ApiError> {
+    let since = query.since.unwrap_or(0);
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+
+    let audit = Arc::clone(&state.audit);
+    let entries = tokio::task::spawn_blocking(move || audit.entries(since, limit))
+        .await
+        .map_err(|e| {
+            error!("Task join error: {}", e);
+            ApiError::internal(ErrorCode::Internal, e.to_string())
+        })?
+        .map_err(|e| {
+            error!("Failed to read audit log: {}", e);
+            ApiError::internal(
+                ErrorCode::Internal,
+                format!("Failed to read audit log: {}", e),
+            )
+        })?;
+
+    Ok(Json(AuditResponse { entries }))
+}
+
+/// Whether a request is recorded in the audit log: everything
+/// `is_mutating()` except ingesting results and running probes, scans and
+/// test notifications, which change no settings or stored history.
+pub(crate) fn is_audited(method: &Method, uri: &Uri) -> bool {
+    let path = uri.path();
+    is_mutating(method, path)
+        && !(path.starts_with("/api/ingest/")
+            || path == "/api/ping/test"
+            || path == "/api/traceroute"
+            || path.starts_with("/api/discovery/")
+            || path == "/api/notifications/test"
+            || path.ends_with("/diagnose"))
+}
+
+/// Record audited requests with their outcome, sender and config file
+/// changes. Runs after `auth_middleware`, so the sender is known, and
+/// outside `role_middleware`, so refused attempts are recorded too.
+pub(crate) async fn audit_middleware(
+    State(state): State<AppState>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    if !is_audited(req.method(), req.uri()) {
+        return next.run(req).await;
+    }
+
+    let method = req.method().to_string();
+    let path = req
+        .uri()
+        .path_and_query()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| req.uri().path().to_string());
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ci| ci.0);
+    let client = client_ip(peer, req.headers());
+    let (user, token_name) = match req.extensions().get::<Authenticated>() {
+        Some(Authenticated {
+            principal: Principal::User(name),
+            ..
+        }) => (Some(name.clone()), None),
+        Some(Authenticated {
+            principal: Principal::Token(name),
+            ..
+        }) => (None, Some(name.clone())),
+        None => (None, None),
+    };
+
+    // Targets and tokens are stored in the config file, so its changes are
+    // what the request did
+    let before = tokio::fs::read_to_string(&state.config_path)
+        .await
+        .unwrap_or_default();
+    let response = next.run(req).await;
+    let after = tokio::fs::read_to_string(&state.config_path)
+        .await
+        .unwrap_or_default();
+
+    let entry = AuditEntry {
+        timestamp: chrono::Utc::now().timestamp(),
+        method,
+        path,
+        status: response.status().as_u16(),
+        user,
+        token_name,
+        client,
+        config_diff: config_diff(&before, &after),
+    };
+    let audit = Arc::clone(&state.audit);
+    match tokio::task::spawn_blocking(move || audit.record(&entry)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => error!("Failed to write audit log: {}", e),
+        Err(e) => error!("Task join error: {}", e),
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_audited() {
+        let uri = |s: &str| s.parse::<Uri>().unwrap();
+        assert!(is_audited(&Method::POST, &uri("/api/targets")));
+        assert!(is_audited(&Method::DELETE, &uri("/api/targets/router")));
+        assert!(is_audited(&Method::POST, &uri("/api/targets/router/pause")));
+        assert!(is_audited(&Method::POST, &uri("/api/storage/prune")));
+        assert!(is_audited(&Method::POST, &uri("/api/auth/tokens")));
+        assert!(is_audited(&Method::POST, &uri("/api/storage/quarantine")));
+
+        assert!(!is_audited(&Method::GET, &uri("/api/targets")));
+        assert!(!is_audited(&Method::GET, &uri("/api/storage/verify")));
+        assert!(!is_audited(&Method::POST, &uri("/api/ingest/batch")));
+        assert!(!is_audited(&Method::POST, &uri("/api/ping/test")));
+        assert!(!is_audited(
+            &Method::POST,
+            &uri("/api/targets/router/diagnose")
+        ));
+        assert!(!is_audited(&Method::POST, &uri("/api/auth/login")));
+    }
+}
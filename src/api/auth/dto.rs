@@ -0,0 +1,67 @@
+use crate::config::Role;
+use serde::{Deserialize, Serialize};
+
+/// Request body for POST /api/auth/login
+#[derive(Debug, Deserialize)]
+pub struct LoginRequest {
+    pub username: String,
+    pub password: String,
+}
+
+/// Session token returned by POST /api/auth/login
+#[derive(Debug, Serialize)]
+pub struct LoginResponse {
+    /// Bearer token for the `Authorization` header
+    pub token: String,
+    /// Unix timestamp in seconds when the session ends
+    pub expires_at: i64,
+    pub user: String,
+    pub role: Role,
+}
+
+/// API response for GET /api/auth/status
+#[derive(Debug, Serialize)]
+pub struct AuthStatusResponse {
+    /// Whether authentication is required
+    pub enabled: bool,
+    /// Whether read-only requests work without a token
+    pub public_read: bool,
+    /// Whether the request carried a valid token
+    pub authenticated: bool,
+    /// Logged-in user (session tokens)
+    pub user: Option<String>,
+    /// Name of the API token used
+    pub token_name: Option<String>,
+    /// Role of the user or API token
+    pub role: Option<Role>,
+}
+
+/// Request body for POST /api/auth/tokens
+#[derive(Debug, Deserialize)]
+pub struct CreateTokenRequest {
+    /// What the token is used for (e.g. "grafana")
+    pub name: String,
+    /// Role of the token (default: viewer)
+    pub role: Option<Role>,
+}
+
+/// An API token, without its secret
+#[derive(Debug, Serialize)]
+pub struct ApiTokenInfo {
+    pub id: String,
+    pub name: String,
+    /// Unix timestamp in seconds of creation
+    pub created_at: i64,
+    pub role: Role,
+}
+
+/// Newly created API token. The token itself is only returned here; the
+/// config stores its hash.
+#[derive(Debug, Serialize)]
+pub struct CreatedTokenResponse {
+    pub id: String,
+    pub name: String,
+    pub created_at: i64,
+    pub role: Role,
+    pub token: String,
+}
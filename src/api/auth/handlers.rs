@@ -0,0 +1,386 @@
+use super::dto::{
+    ApiTokenInfo, AuthStatusResponse, CreateTokenRequest, CreatedTokenResponse, LoginRequest,
+    LoginResponse,
+};
+use crate::api::error::{ApiError, ErrorCode};
+use crate::api::extract::Json;
+use crate::api::quota::client_ip;
+use crate::api::AppState;
+use crate::auth::{
+    authenticate, generate_token, hash_token, login as check_login, Authenticated, Principal,
+};
+use crate::config::{ApiToken, AuthConfig, Role};
+use crate::config_file;
+use axum::body::Body;
+use axum::extract::{ConnectInfo, Extension, Path, State};
+use axum::http::{header, HeaderMap, HeaderValue, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use std::net::SocketAddr;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+/// Bearer token of the `Authorization` header
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| {
+            let (scheme, token) = h.split_once(' ')?;
+            scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+        })
+}
+
+/// Cookie holding the session token of the web UI
+const SESSION_COOKIE: &str = "sparkping_session";
+
+/// Session token of the session cookie
+fn session_cookie(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(';'))
+        .find_map(|cookie| {
+            let (name, value) = cookie.trim().split_once('=')?;
+            (name == SESSION_COOKIE && !value.is_empty()).then_some(value)
+        })
+}
+
+/// `Set-Cookie` value for a session token; an empty token clears the cookie.
+/// HttpOnly keeps the token away from scripts, and SameSite=Strict keeps
+/// other sites from making requests with it. Secure is set when a TLS
+/// proxy reports HTTPS.
+fn set_session_cookie(token: &str, max_age: u64, headers: &HeaderMap) -> HeaderValue {
+    let https = headers
+        .get("x-forwarded-proto")
+        .and_then(|h| h.to_str().ok())
+        .is_some_and(|proto| proto.eq_ignore_ascii_case("https"));
+    let cookie = format!(
+        "{}={}; Path=/; Max-Age={}; HttpOnly; SameSite=Strict{}",
+        SESSION_COOKIE,
+        token,
+        max_age,
+        if https { "; Secure" } else { "" }
+    );
+    HeaderValue::from_str(&cookie).unwrap_or_else(|_| HeaderValue::from_static(""))
+}
+
+fn auth_config(state: &AppState) -> Result<AuthConfig, ApiError> {
+    state.config.read().map(|c| c.auth.clone()).map_err(|e| {
+        error!("Failed to read config: {}", e);
+        ApiError::internal(ErrorCode::ConfigUnavailable, "Failed to read configuration")
+    })
+}
+
+fn unauthorized(message: &str) -> ApiError {
+    ApiError::new(StatusCode::UNAUTHORIZED, ErrorCode::Unauthorized, message)
+}
+
+/// Authenticate the request's bearer token or session cookie, if any, and
+/// store the result as
+/// an [`Authenticated`] request extension for `role_middleware`. Invalid
+/// tokens are ignored here, so that requests with an expired session still
+/// work where no role is required.
+pub(crate) async fn auth_middleware(
+    State(state): State<AppState>,
+    mut req: Request<Body>,
+    next: Next,
+) -> Result<Response, ApiError> {
+    // Read settings per request so config hot reloads apply immediately
+    let auth = auth_config(&state)?;
+    if auth.enabled {
+        let now = chrono::Utc::now().timestamp();
+        let headers = req.headers();
+        let authenticated = bearer_token(headers)
+            .or_else(|| session_cookie(headers))
+            .and_then(|token| authenticate(&auth, &state.auth_sessions, token, now));
+        if let Some(authenticated) = authenticated {
+            req.extensions_mut().insert(authenticated);
+        }
+    }
+    Ok(next.run(req).await)
+}
+
+/// HTTP handler for POST /api/auth/login
+///
+/// Checks a `[[auth.users]]` password and starts a session of
+/// `[auth] session_ttl` seconds. The session token is returned and set as
+/// session cookie. Clients with too many failed logins get 429 until their
+/// lockout ends (`LoginThrottle`).
+pub(crate) async fn login(
+    State(state): State<AppState>,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    Json(request): Json<LoginRequest>,
+) -> Result<Response, ApiError> {
+    let auth = auth_config(&state)?;
+    let ttl = auth.session_ttl;
+
+    let client = client_ip(peer.map(|ConnectInfo(addr)| addr), &headers);
+    let now = chrono::Utc::now().timestamp();
+    if let Some(retry_after) = state.login_throttle.retry_after(&client, now) {
+        warn!("Refused login from {}: too many failed logins", client);
+        let mut response = ApiError::new(
+            StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::TooManyLoginAttempts,
+            format!(
+                "Too many failed logins, try again in {} seconds",
+                retry_after
+            ),
+        )
+        .with_details(serde_json::json!({ "retry_after_seconds": retry_after }))
+        .into_response();
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+        return Ok(response);
+    }
+
+    // Argon2 is deliberately slow; keep it off the async workers
+    let username = request.username.clone();
+    let user = tokio::task::spawn_blocking(move || {
+        check_login(&auth, &request.username, &request.password).map(|u| (u.name.clone(), u.role))
+    })
+    .await
+    .map_err(|e| {
+        error!("Task join error: {}", e);
+        ApiError::internal(ErrorCode::Internal, e.to_string())
+    })?;
+
+    let Some((user, role)) = user else {
+        warn!("Failed login for user '{}' from {}", username, client);
+        state.login_throttle.failed(&client, now);
+        return Err(unauthorized("Invalid username or password"));
+    };
+
+    state.login_throttle.succeeded(&client);
+    let (token, expires_at) = state.auth_sessions.create(&user, now, ttl);
+    info!("User '{}' logged in", user);
+
+    let cookie = set_session_cookie(&token, ttl, &headers);
+    Ok((
+        [(header::SET_COOKIE, cookie)],
+        Json(LoginResponse {
+            token,
+            expires_at,
+            user,
+            role,
+        }),
+    )
+        .into_response())
+}
+
+/// HTTP handler for POST /api/auth/logout
+///
+/// Ends the session of the bearer token and of the session cookie, and
+/// clears the cookie.
+pub(crate) async fn logout(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    for token in [bearer_token(&headers), session_cookie(&headers)]
+        .into_iter()
+        .flatten()
+    {
+        state.auth_sessions.remove(token);
+    }
+    Ok((
+        StatusCode::NO_CONTENT,
+        [(header::SET_COOKIE, set_session_cookie("", 0, &headers))],
+    )
+        .into_response())
+}
+
+/// HTTP handler for GET /api/auth/status
+pub(crate) async fn get_auth_status(
+    State(state): State<AppState>,
+    authenticated: Option<Extension<Authenticated>>,
+) -> Result<Json<AuthStatusResponse>, ApiError> {
+    let auth = auth_config(&state)?;
+    let authenticated = authenticated.map(|Extension(a)| a);
+
+    let (user, token_name) = match authenticated.as_ref().map(|a| &a.principal) {
+        Some(Principal::User(user)) => (Some(user.clone()), None),
+        Some(Principal::Token(name)) => (None, Some(name.clone())),
+        None => (None, None),
+    };
+
+    Ok(Json(AuthStatusResponse {
+        enabled: auth.enabled,
+        public_read: auth.public_read,
+        authenticated: authenticated.is_some(),
+        user,
+        token_name,
+        role: authenticated.map(|a| a.role),
+    }))
+}
+
+/// HTTP handler for GET /api/auth/tokens
+pub(crate) async fn get_tokens(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ApiTokenInfo>>, ApiError> {
+    let auth = auth_config(&state)?;
+    Ok(Json(
+        auth.tokens
+            .into_iter()
+            .map(|t| ApiTokenInfo {
+                id: t.id,
+                name: t.name,
+                created_at: t.created_at,
+                role: t.role,
+            })
+            .collect(),
+    ))
+}
+
+/// HTTP handler for POST /api/auth/tokens
+///
+/// Creates an API token and stores its hash in config.toml. The token is
+/// only returned in this response.
+pub(crate) async fn create_token(
+    State(state): State<AppState>,
+    Json(request): Json<CreateTokenRequest>,
+) -> Result<Json<CreatedTokenResponse>, ApiError> {
+    let name = request.name.trim().to_string();
+    if name.is_empty() {
+        return Err(
+            ApiError::bad_request(ErrorCode::InvalidRequest, "Token name is required")
+                .with_details(serde_json::json!({ "field": "name" })),
+        );
+    }
+
+    let mut config = state.config.write().map_err(|e| {
+        error!("Failed to write config: {}", e);
+        ApiError::internal(
+            ErrorCode::ConfigUnavailable,
+            "Failed to access configuration",
+        )
+    })?;
+
+    let token = generate_token();
+    let api_token = ApiToken {
+        id: Uuid::new_v4().to_string(),
+        name,
+        hash: hash_token(&token),
+        created_at: chrono::Utc::now().timestamp(),
+        // Least privilege unless asked for more
+        role: request.role.unwrap_or(Role::Viewer),
+    };
+
+    let mut doc = config_file::read_config_file(&state.config_path).map_err(|e| {
+        error!("Failed to read config file: {}", e);
+        ApiError::internal(
+            ErrorCode::ConfigFileError,
+            format!("Failed to read config file: {}", e),
+        )
+    })?;
+    config_file::add_api_token(&mut doc, &api_token).map_err(|e| {
+        error!("Failed to add API token: {}", e);
+        ApiError::internal(
+            ErrorCode::ConfigFileError,
+            format!("Failed to add API token: {}", e),
+        )
+    })?;
+    config_file::write_config_file(&state.config_path, &doc, &state.write_flag).map_err(|e| {
+        error!("Failed to write config file: {}", e);
+        ApiError::internal(
+            ErrorCode::ConfigFileError,
+            format!("Failed to write config file: {}", e),
+        )
+    })?;
+
+    config.auth.tokens.push(api_token.clone());
+    info!("Created API token '{}'", api_token.name);
+
+    Ok(Json(CreatedTokenResponse {
+        id: api_token.id,
+        name: api_token.name,
+        created_at: api_token.created_at,
+        role: api_token.role,
+        token,
+    }))
+}
+
+/// HTTP handler for DELETE /api/auth/tokens/{id}
+pub(crate) async fn delete_token(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let mut config = state.config.write().map_err(|e| {
+        error!("Failed to write config: {}", e);
+        ApiError::internal(
+            ErrorCode::ConfigUnavailable,
+            "Failed to access configuration",
+        )
+    })?;
+
+    if !config.auth.tokens.iter().any(|t| t.id == id) {
+        return Err(ApiError::not_found(
+            ErrorCode::TokenNotFound,
+            format!("API token with id '{}' not found", id),
+        ));
+    }
+
+    let mut doc = config_file::read_config_file(&state.config_path).map_err(|e| {
+        error!("Failed to read config file: {}", e);
+        ApiError::internal(
+            ErrorCode::ConfigFileError,
+            format!("Failed to read config file: {}", e),
+        )
+    })?;
+    config_file::remove_api_token(&mut doc, &id).map_err(|e| {
+        error!("Failed to remove API token: {}", e);
+        ApiError::internal(
+            ErrorCode::ConfigFileError,
+            format!("Failed to remove API token: {}", e),
+        )
+    })?;
+    config_file::write_config_file(&state.config_path, &doc, &state.write_flag).map_err(|e| {
+        error!("Failed to write config file: {}", e);
+        ApiError::internal(
+            ErrorCode::ConfigFileError,
+            format!("Failed to write config file: {}", e),
+        )
+    })?;
+
+    config.auth.tokens.retain(|t| t.id != id);
+    info!("Deleted API token {}", id);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_cookie() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(session_cookie(&headers), None);
+        headers.insert(
+            header::COOKIE,
+            HeaderValue::from_static("theme=dark; sparkping_session=spk_abc"),
+        );
+        assert_eq!(session_cookie(&headers), Some("spk_abc"));
+        headers.insert(
+            header::COOKIE,
+            HeaderValue::from_static("sparkping_session="),
+        );
+        assert_eq!(session_cookie(&headers), None);
+    }
+
+    #[test]
+    fn test_set_session_cookie() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            set_session_cookie("spk_abc", 60, &headers),
+            "sparkping_session=spk_abc; Path=/; Max-Age=60; HttpOnly; SameSite=Strict"
+        );
+        headers.insert("x-forwarded-proto", HeaderValue::from_static("https"));
+        assert_eq!(
+            set_session_cookie("", 0, &headers),
+            "sparkping_session=; Path=/; Max-Age=0; HttpOnly; SameSite=Strict; Secure"
+        );
+    }
+}
@@ -0,0 +1,98 @@
+//! Conditional responses for polled data queries.
+//!
+//! Dashboards refresh `/api/ping/data`, `/api/ping/aggregated` and
+//! `/api/ping/summary` every few seconds, mostly getting the same JSON back.
+//! Their responses carry a weak `ETag` (a hash of the body) and
+//! `Cache-Control: no-cache`, so browsers revalidate with `If-None-Match`
+//! and an unchanged response is answered with an empty 304. The router
+//! compresses these routes (gzip or brotli, by `Accept-Encoding`) outside of
+//! this middleware, so the tag is the same for every encoding.
+
+use axum::body::{to_bytes, Body};
+use axum::http::{header, HeaderValue, Method, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use sha2::{Digest, Sha256};
+use tracing::error;
+
+/// Weak entity tag of a response body
+fn etag(body: &[u8]) -> String {
+    let digest = Sha256::digest(body);
+    format!("W/\"{}\"", hex::encode(&digest[..16]))
+}
+
+/// Whether an `If-None-Match` header value matches `etag`. Tags compare
+/// weakly (a `W/` prefix is ignored), and `*` matches any.
+fn if_none_match(value: &str, etag: &str) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let etag = opaque(etag);
+    value
+        .split(',')
+        .any(|tag| tag.trim() == "*" || opaque(tag) == etag)
+}
+
+/// Tag successful GET responses and answer revalidations of unchanged ones
+/// with 304 Not Modified
+pub(crate) async fn etag_middleware(req: Request<Body>, next: Next) -> Response {
+    if req.method() != Method::GET {
+        return next.run(req).await;
+    }
+    let if_none_match_header = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+
+    let response = next.run(req).await;
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+
+    // The data responses are JSON built in memory, so buffering them costs
+    // no more than the handler already spent
+    let (mut parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            error!("Failed to read response body: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let etag = etag(&bytes);
+    if let Ok(value) = HeaderValue::from_str(&etag) {
+        parts.headers.insert(header::ETAG, value);
+    }
+    parts
+        .headers
+        .entry(header::CACHE_CONTROL)
+        .or_insert(HeaderValue::from_static("no-cache"));
+
+    if if_none_match_header.is_some_and(|value| if_none_match(&value, &etag)) {
+        parts.status = StatusCode::NOT_MODIFIED;
+        parts.headers.remove(header::CONTENT_TYPE);
+        parts.headers.remove(header::CONTENT_LENGTH);
+        return Response::from_parts(parts, Body::empty());
+    }
+    Response::from_parts(parts, Body::from(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_if_none_match() {
+        let tag = etag(b"{\"data\":[]}");
+        assert!(tag.starts_with("W/\"") && tag.ends_with('"'));
+        assert_eq!(tag, etag(b"{\"data\":[]}"));
+        assert_ne!(tag, etag(b"{\"data\":[1]}"));
+
+        assert!(if_none_match(&tag, &tag));
+        // Strong form of the same tag, and lists
+        assert!(if_none_match(tag.trim_start_matches("W/"), &tag));
+        assert!(if_none_match(&format!("\"other\", {}", tag), &tag));
+        assert!(if_none_match("*", &tag));
+        assert!(!if_none_match("\"other\"", &tag));
+    }
+}
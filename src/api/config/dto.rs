@@ -0,0 +1,33 @@
+use crate::config_validation::ConfigIssue;
+use crate::health::ConfigReload;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Response for POST /api/config/validate
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ConfigValidationResponse {
+    /// Whether the config would be accepted
+    pub valid: bool,
+    pub issues: Vec<ConfigIssue>,
+}
+
+impl ConfigValidationResponse {
+    pub fn new(issues: Vec<ConfigIssue>) -> Self {
+        Self {
+            valid: issues.is_empty(),
+            issues,
+        }
+    }
+}
+
+/// Response for GET /api/config/status
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ConfigStatusResponse {
+    /// Config file that is watched for changes
+    pub path: String,
+    /// Validation of the file as it is on disk now
+    pub file: ConfigValidationResponse,
+    /// Outcome of the last reload after the file changed (None if it did not
+    /// change since startup)
+    pub last_reload: Option<ConfigReload>,
+}
@@ -0,0 +1,53 @@
+use super::dto::{ConfigStatusResponse, ConfigValidationResponse};
+use crate::api::AppState;
+use crate::config_validation::{validate_config, ConfigIssue};
+use crate::health::health;
+use axum::{extract::State, response::Json};
+
+/// HTTP handler for POST /api/config/validate
+///
+/// Validates a complete `config.toml` sent as the request body without
+/// writing or applying it: TOML syntax, unknown keys, invalid values (e.g.
+/// socket types), duplicate target IDs and zero ping counts or intervals.
+#[utoipa::path(
+    post,
+    path = "/api/config/validate",
+    tag = "config",
+    summary = "Validate a config file",
+    request_body(content = String, content_type = "application/toml", description = "Contents of config.toml"),
+    responses(
+        (status = 200, description = "Validation result; `valid` is false if issues were found", body = ConfigValidationResponse),
+    )
+)]
+pub(crate) async fn validate(body: String) -> Json<ConfigValidationResponse> {
+    let issues = validate_config(&body).err().unwrap_or_default();
+    Json(ConfigValidationResponse::new(issues))
+}
+
+/// HTTP handler for GET /api/config/status
+///
+/// Validation of the config file on disk and the outcome of the last reload.
+/// A changed file that fails validation is not applied; its issues are
+/// reported here.
+#[utoipa::path(
+    get,
+    path = "/api/config/status",
+    tag = "config",
+    summary = "Config file validation and last reload",
+    responses((status = 200, description = "Config status", body = ConfigStatusResponse))
+)]
+pub(crate) async fn get_config_status(State(state): State<AppState>) -> Json<ConfigStatusResponse> {
+    let issues = match tokio::fs::read_to_string(&state.config_path).await {
+        Ok(text) => validate_config(&text).err().unwrap_or_default(),
+        Err(e) => vec![ConfigIssue::load_error(format!(
+            "Failed to read config file: {}",
+            e
+        ))],
+    };
+
+    Json(ConfigStatusResponse {
+        path: state.config_path.display().to_string(),
+        file: ConfigValidationResponse::new(issues),
+        last_reload: health().last_config_reload(),
+    })
+}
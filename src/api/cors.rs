@@ -0,0 +1,90 @@
+//! CORS policy (`[server.cors]`).
+//!
+//! Lets browsers on other origins, such as a separately hosted frontend or
+//! Grafana's JSON datasource, call the API. Preflight requests are answered
+//! before authentication, since browsers send them without credentials.
+
+use crate::config::CorsConfig;
+use axum::http::{HeaderName, HeaderValue, Method};
+use std::time::Duration;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+use tracing::warn;
+
+/// Response headers scripts on other origins may read
+const EXPOSED_HEADERS: [&str; 5] = ["api-version", "deprecation", "etag", "link", "retry-after"];
+
+/// Parse configured values, skipping (and logging) invalid ones
+fn parse_all<T>(kind: &str, values: &[String], parse: impl Fn(&str) -> Option<T>) -> Vec<T> {
+    values
+        .iter()
+        .filter_map(|value| {
+            let parsed = parse(value.trim());
+            if parsed.is_none() {
+                warn!("Ignoring invalid CORS {} '{}'", kind, value);
+            }
+            parsed
+        })
+        .collect()
+}
+
+/// CORS layer for the configured policy; None while no origin is allowed
+pub(crate) fn cors_layer(config: &CorsConfig) -> Option<CorsLayer> {
+    if config.allowed_origins.is_empty() {
+        return None;
+    }
+
+    let origin = if config.allowed_origins.iter().any(|o| o.trim() == "*") {
+        AllowOrigin::from(Any)
+    } else {
+        // Origins are compared byte for byte, and browsers send them without
+        // a trailing slash
+        let origins = parse_all("origin", &config.allowed_origins, |o| {
+            HeaderValue::from_str(o.trim_end_matches('/')).ok()
+        });
+        if origins.is_empty() {
+            return None;
+        }
+        AllowOrigin::list(origins)
+    };
+
+    let methods = parse_all("method", &config.allowed_methods, |m| {
+        Method::from_bytes(m.to_ascii_uppercase().as_bytes()).ok()
+    });
+    let headers = parse_all("header", &config.allowed_headers, |h| {
+        HeaderName::from_bytes(h.as_bytes()).ok()
+    });
+
+    Some(
+        CorsLayer::new()
+            .allow_origin(origin)
+            .allow_methods(methods)
+            .allow_headers(headers)
+            .expose_headers(EXPOSED_HEADERS.map(HeaderName::from_static))
+            .max_age(Duration::from_secs(config.max_age)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disabled_without_origins() {
+        assert!(cors_layer(&CorsConfig::default()).is_none());
+
+        let invalid_only = CorsConfig {
+            allowed_origins: vec!["https://bad\norigin".to_string()],
+            ..CorsConfig::default()
+        };
+        assert!(cors_layer(&invalid_only).is_none());
+    }
+
+    #[test]
+    fn test_parse_all_skips_invalid() {
+        let values = vec!["get".to_string(), "BAD METHOD".to_string()];
+        let methods = parse_all("method", &values, |m| {
+            Method::from_bytes(m.to_ascii_uppercase().as_bytes()).ok()
+        });
+        assert_eq!(methods, vec![Method::GET]);
+    }
+}
@@ -0,0 +1,207 @@
+use crate::api::ping::dto::BucketDataPoint;
+use chrono::{DateTime, Local};
+use plotters::coord::Shift;
+use plotters::prelude::*;
+
+/// Output format for rendered snapshot charts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotFormat {
+    Svg,
+    Png,
+}
+
+impl SnapshotFormat {
+    /// Content-Type header value for this format
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            SnapshotFormat::Svg => "image/svg+xml",
+            SnapshotFormat::Png => "image/png",
+        }
+    }
+}
+
+/// Latency line color (avg per bucket)
+const LINE_COLOR: RGBColor = RGBColor(37, 99, 235);
+/// Packet loss marker color
+const LOSS_COLOR: RGBColor = RGBColor(220, 38, 38);
+
+/// Render a small latency chart for a single target.
+///
+/// The chart shows the min–max latency band and the average latency per bucket,
+/// with buckets containing packet loss shaded red (darker = more loss).
+/// PNG output is rendered without text since no fonts are bundled; SVG output
+/// includes a caption and axis labels.
+pub fn render_latency_chart(
+    buckets: &[BucketDataPoint],
+    title: &str,
+    from: i64,
+    to: i64,
+    size: (u32, u32),
+    format: SnapshotFormat,
+) -> Result<Vec<u8>, String> {
+    match format {
+        SnapshotFormat::Svg => {
+            let mut svg = String::new();
+            {
+                let root = SVGBackend::with_string(&mut svg, size).into_drawing_area();
+                draw_chart(&root, buckets, Some(title), from, to)?;
+                root.present().map_err(|e| e.to_string())?;
+            }
+            Ok(svg.into_bytes())
+        }
+        SnapshotFormat::Png => {
+            let (width, height) = size;
+            let mut pixels = vec![0u8; (width * height * 3) as usize];
+            {
+                let root = BitMapBackend::with_buffer(&mut pixels, size).into_drawing_area();
+                draw_chart(&root, buckets, None, from, to)?;
+                root.present().map_err(|e| e.to_string())?;
+            }
+
+            let image = image::RgbImage::from_raw(width, height, pixels)
+                .ok_or_else(|| "Invalid image buffer size".to_string())?;
+            let mut png = std::io::Cursor::new(Vec::new());
+            image
+                .write_to(&mut png, image::ImageOutputFormat::Png)
+                .map_err(|e| e.to_string())?;
+            Ok(png.into_inner())
+        }
+    }
+}
+
+fn draw_chart<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    buckets: &[BucketDataPoint],
+    title: Option<&str>,
+    from: i64,
+    to: i64,
+) -> Result<(), String> {
+    root.fill(&WHITE).map_err(|e| e.to_string())?;
+
+    let max_latency = buckets.iter().filter_map(|b| b.max).fold(0.0f64, f64::max);
+    let y_max = (max_latency * 1.1).max(1.0);
+    let to = to.max(from + 1);
+
+    let mut builder = ChartBuilder::on(root);
+    builder.margin(8);
+    if let Some(title) = title {
+        builder
+            .caption(title, ("sans-serif", 14))
+            .x_label_area_size(20)
+            .y_label_area_size(40);
+    }
+    let mut chart = builder
+        .build_cartesian_2d(from..to, 0f64..y_max)
+        .map_err(|e| e.to_string())?;
+
+    if title.is_some() {
+        let span = to - from;
+        chart
+            .configure_mesh()
+            .x_labels(5)
+            .y_labels(4)
+            .light_line_style(WHITE)
+            .x_label_formatter(&|ts| format_time_label(*ts, span))
+            .y_label_formatter(&|ms| format!("{:.0} ms", ms))
+            .draw()
+            .map_err(|e| e.to_string())?;
+    }
+
+    // Packet loss: shade the whole bucket height, more opaque for more loss
+    chart
+        .draw_series(buckets.iter().filter(|b| b.failed_count > 0).map(|b| {
+            let loss_ratio = b.failed_count as f64 / b.count.max(1) as f64;
+            Rectangle::new(
+                [(b.timestamp_unix, 0.0), (b.timestamp_end_unix, y_max)],
+                LOSS_COLOR.mix(0.15 + 0.6 * loss_ratio).filled(),
+            )
+        }))
+        .map_err(|e| e.to_string())?;
+
+    // Min–max latency band
+    chart
+        .draw_series(buckets.iter().filter_map(|b| {
+            let (min, max) = (b.min?, b.max?);
+            Some(Rectangle::new(
+                [(b.timestamp_unix, min), (b.timestamp_end_unix, max)],
+                LINE_COLOR.mix(0.2).filled(),
+            ))
+        }))
+        .map_err(|e| e.to_string())?;
+
+    // Average latency line through bucket midpoints
+    chart
+        .draw_series(LineSeries::new(
+            buckets.iter().filter_map(|b| {
+                let mid = b.timestamp_unix + (b.timestamp_end_unix - b.timestamp_unix) / 2;
+                b.avg.map(|avg| (mid, avg))
+            }),
+            LINE_COLOR.stroke_width(2),
+        ))
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// Format an axis timestamp: time of day for short ranges, date for long ones
+fn format_time_label(timestamp: i64, span_seconds: i64) -> String {
+    let Some(utc) = DateTime::from_timestamp(timestamp, 0) else {
+        return String::new();
+    };
+    let local = utc.with_timezone(&Local);
+    if span_seconds > 2 * 86400 {
+        local.format("%m-%d").to_string()
+    } else {
+        local.format("%H:%M").to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bucket(start: i64, avg: f64, failed_count: usize) -> BucketDataPoint {
+        BucketDataPoint {
+            timestamp: String::new(),
+            timestamp_unix: start,
+            timestamp_end_unix: start + 60,
+            target: "192.168.1.1".to_string(),
+            target_name: None,
+            min: Some(avg - 1.0),
+            max: Some(avg + 1.0),
+            avg: Some(avg),
+            percentiles: None,
+            count: 3,
+            successful_count: 3 - failed_count,
+            failed_count,
+            resolution: None,
+            complete: true,
+            loss_percent: None,
+        }
+    }
+
+    #[test]
+    fn test_render_svg() {
+        let buckets = vec![bucket(0, 10.0, 0), bucket(60, 12.0, 1)];
+        let svg = render_latency_chart(&buckets, "Router", 0, 120, (300, 100), SnapshotFormat::Svg)
+            .unwrap();
+        let svg = String::from_utf8(svg).unwrap();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("Router"));
+    }
+
+    #[test]
+    fn test_render_png() {
+        let buckets = vec![bucket(0, 10.0, 0)];
+        let png = render_latency_chart(&buckets, "Router", 0, 60, (120, 40), SnapshotFormat::Png)
+            .unwrap();
+        // PNG signature
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    }
+
+    #[test]
+    fn test_render_empty() {
+        let svg = render_latency_chart(&[], "Empty", 0, 0, (200, 80), SnapshotFormat::Svg).unwrap();
+        assert!(!svg.is_empty());
+    }
+}
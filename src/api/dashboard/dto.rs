@@ -0,0 +1,25 @@
+use crate::api::ping::dto::{deserialize_time_range, TimeRangeValue};
+use serde::Deserialize;
+
+/// Query parameters for the dashboard snapshot API
+#[derive(Debug, Deserialize)]
+pub struct SnapshotQuery {
+    /// Target ID or address to render
+    pub target: String,
+    /// Start timestamp (Unix timestamp in seconds) or relative time range (e.g., "24h", "7d").
+    /// Default: "24h"
+    #[serde(default, deserialize_with = "deserialize_time_range")]
+    pub from: Option<TimeRangeValue>,
+    /// End timestamp (Unix timestamp in seconds, optional)
+    #[serde(default)]
+    pub to: Option<i64>,
+    /// Time bucket duration (e.g., "5m", "1h"). Default: chosen from the time range
+    #[serde(default)]
+    pub bucket: Option<String>,
+    /// Image width in pixels (default: 600)
+    #[serde(default)]
+    pub width: Option<u32>,
+    /// Image height in pixels (default: 200)
+    #[serde(default)]
+    pub height: Option<u32>,
+}
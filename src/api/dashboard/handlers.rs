@@ -0,0 +1,134 @@
+use super::chart::{render_latency_chart, SnapshotFormat};
+use super::dto::SnapshotQuery;
+use crate::api::error::{ApiError, ErrorCode};
+use crate::api::extract::Query;
+use crate::api::ping::dto::TimeRangeValue;
+use crate::api::ping::handlers::find_target_config;
+use crate::api::ping::query::{
+    parse_bucket_duration, query_ping_aggregated_chunked, resolve_time_range_value,
+};
+use crate::api::AppState;
+use axum::{
+    extract::State,
+    http::header,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+use tracing::{error, info};
+
+/// Default snapshot size in pixels
+const DEFAULT_WIDTH: u32 = 600;
+const DEFAULT_HEIGHT: u32 = 200;
+
+/// Target number of buckets when no bucket size is requested
+const AUTO_BUCKET_COUNT: i64 = 150;
+
+/// HTTP handler for GET /api/dashboard/snapshot.svg
+pub(crate) async fn get_snapshot_svg(
+    State(state): State<AppState>,
+    Query(query): Query<SnapshotQuery>,
+) -> Result<Response, ApiError> {
+    render_snapshot(state, query, SnapshotFormat::Svg).await
+}
+
+/// HTTP handler for GET /api/dashboard/snapshot.png
+pub(crate) async fn get_snapshot_png(
+    State(state): State<AppState>,
+    Query(query): Query<SnapshotQuery>,
+) -> Result<Response, ApiError> {
+    render_snapshot(state, query, SnapshotFormat::Png).await
+}
+
+/// Pick a bucket size that yields roughly AUTO_BUCKET_COUNT buckets, rounded to whole minutes
+fn auto_bucket_seconds(span_seconds: i64) -> i64 {
+    let raw = span_seconds / AUTO_BUCKET_COUNT;
+    ((raw / 60).max(1)) * 60
+}
+
+async fn render_snapshot(
+    state: AppState,
+    query: SnapshotQuery,
+    format: SnapshotFormat,
+) -> Result<Response, ApiError> {
+    info!("Rendering {:?} snapshot: {:?}", format, query);
+
+    let target_config = find_target_config(&state, &query.target).ok_or_else(|| {
+        ApiError::not_found(
+            ErrorCode::TargetNotFound,
+            format!("Target '{}' not found", query.target),
+        )
+    })?;
+
+    let from_value = query
+        .from
+        .clone()
+        .unwrap_or_else(|| TimeRangeValue::Relative("24h".to_string()));
+    let from = resolve_time_range_value(&from_value).map_err(|e| {
+        error!("Invalid time range: {}", e);
+        ApiError::bad_request(ErrorCode::InvalidTimeRange, e)
+    })?;
+    let to = query.to.unwrap_or_else(|| chrono::Utc::now().timestamp());
+
+    let bucket_duration_seconds = match query.bucket {
+        Some(ref bucket) => parse_bucket_duration(bucket).map_err(|e| {
+            error!("Invalid bucket duration: {}", e);
+            ApiError::bad_request(ErrorCode::InvalidDuration, e)
+        })?,
+        None => auto_bucket_seconds(to - from),
+    };
+
+    let width = query.width.unwrap_or(DEFAULT_WIDTH).clamp(100, 2000);
+    let height = query.height.unwrap_or(DEFAULT_HEIGHT).clamp(50, 1000);
+    let title = target_config
+        .name
+        .clone()
+        .unwrap_or_else(|| target_config.address.clone());
+
+    // Aggregation and rendering are both CPU/IO bound, keep them off the async runtime
+    let storage = Arc::clone(&state.storage);
+    let image = tokio::task::spawn_blocking(move || {
+        let (buckets, _) = query_ping_aggregated_chunked(
+            &*storage,
+            Some(&target_config.address),
+            Some(&target_config),
+            from,
+            to,
+            bucket_duration_seconds,
+            false,
+        )
+        .map_err(|e| e.to_string())?;
+        render_latency_chart(&buckets, &title, from, to, (width, height), format)
+    })
+    .await
+    .map_err(|e| {
+        error!("Task join error: {}", e);
+        ApiError::internal(ErrorCode::Internal, e.to_string())
+    })?
+    .map_err(|e| {
+        error!("Error rendering snapshot: {}", e);
+        ApiError::internal(ErrorCode::Internal, e)
+    })?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type()),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        image,
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auto_bucket_seconds() {
+        // 24h over ~150 buckets rounds down to 9 minutes
+        assert_eq!(auto_bucket_seconds(86400), 540);
+        // Short ranges never go below one minute
+        assert_eq!(auto_bucket_seconds(600), 60);
+        assert_eq!(auto_bucket_seconds(0), 60);
+    }
+}
@@ -0,0 +1,3 @@
+pub mod chart;
+pub mod dto;
+pub mod handlers;
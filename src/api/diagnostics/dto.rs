@@ -0,0 +1,27 @@
+use crate::calibration::LatencyCalibration;
+use crate::crash_report::CrashReport;
+use crate::startup_audit::StartupAudit;
+use crate::update_check::UpdateStatus;
+use serde::Serialize;
+
+/// Response for GET /api/diagnostics (also /api/system/diagnostics)
+#[derive(Debug, Serialize)]
+pub struct DiagnosticsResponse {
+    pub version: &'static str,
+    /// Leftovers of crashed runs cleaned up at startup
+    pub startup_audit: StartupAudit,
+    /// DGRAM latency correction measured at startup (`[ping] calibrate`)
+    pub latency_calibration: Option<LatencyCalibration>,
+    /// Newest crash report found at startup
+    pub last_crash: Option<CrashReport>,
+}
+
+/// Response for GET /api/system/info
+#[derive(Debug, Serialize)]
+pub struct SystemInfoResponse {
+    pub version: &'static str,
+    pub os: &'static str,
+    pub arch: &'static str,
+    /// Latest release found by the update check
+    pub update: UpdateStatus,
+}
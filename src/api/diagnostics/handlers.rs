@@ -0,0 +1,29 @@
+use super::dto::{DiagnosticsResponse, SystemInfoResponse};
+use crate::api::AppState;
+use crate::calibration;
+use crate::crash_report;
+use crate::update_check::CURRENT_VERSION;
+use axum::{extract::State, response::Json};
+
+/// HTTP handler for GET /api/diagnostics and GET /api/system/diagnostics
+pub(crate) async fn get_diagnostics(State(state): State<AppState>) -> Json<DiagnosticsResponse> {
+    Json(DiagnosticsResponse {
+        version: env!("CARGO_PKG_VERSION"),
+        startup_audit: (*state.startup_audit).clone(),
+        latency_calibration: calibration::current().cloned(),
+        last_crash: crash_report::last_crash().cloned(),
+    })
+}
+
+/// HTTP handler for GET /api/system/info
+///
+/// Version and platform of this build, and the outcome of the update check
+/// (`[updates] check`).
+pub(crate) async fn get_system_info(State(state): State<AppState>) -> Json<SystemInfoResponse> {
+    Json(SystemInfoResponse {
+        version: CURRENT_VERSION,
+        os: std::env::consts::OS,
+        arch: std::env::consts::ARCH,
+        update: state.updates.status(),
+    })
+}
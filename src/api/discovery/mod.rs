@@ -1,21 +1,47 @@
+use crate::api::error::{ApiError, ErrorCode, ErrorResponse};
+use crate::api::extract::{Json, Query};
+use crate::api::ping::dto::{deserialize_time_range, TimeRangeValue};
+use crate::api::ping::query::resolve_time_range_value;
+use crate::api::AppState;
+use crate::config::SocketType;
 use crate::device_identification::IdentifiedDiscoveryEvent;
-use crate::ip_scan::{get_suggested_subnets, SubnetSuggestion};
-use crate::unified_discovery::{run_unified_discovery, UnifiedDiscoveryConfig};
+use crate::discovery_session::{DiscoverySessionStatus, MAX_RUNNING_JOBS};
+use crate::ip_scan::{get_suggested_subnets, IpRangeSpec, SubnetSuggestion};
+use crate::port_history::{query_port_history, DevicePortHistory};
+use crate::presence::{query_presence_events, PresenceDevice, PresenceEvent};
+use crate::unified_discovery::{UnifiedDiscoveryConfig, CLIENT_CHANNEL_CAPACITY};
 use async_stream::stream;
-use axum::extract::Query;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
 use axum::response::sse::{Event, KeepAlive, Sse};
-use axum::response::Json;
 use futures::Stream;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::convert::Infallible;
-use tokio::sync::mpsc;
+use std::sync::Arc;
+use std::time::Duration;
 use tracing::{error, info};
+use tracking::TargetIndex;
+use utoipa::{IntoParams, ToSchema};
+
+mod tracking;
+
+/// Interval between heartbeat events on the discovery stream
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
 
 /// HTTP handler for GET /api/discovery/subnets
 ///
 /// Returns suggested subnets for IP scanning based on:
 /// - Local network interfaces
 /// - Traceroute to discover private network hops
+#[utoipa::path(
+    get,
+    path = "/api/discovery/subnets",
+    tag = "discovery",
+    summary = "Subnets suggested for IP scans",
+    responses(
+        (status = 200, description = "Suggested subnets", body = Vec<SubnetSuggestion>),
+    )
+)]
 pub async fn get_subnets() -> Json<Vec<SubnetSuggestion>> {
     info!("Getting subnet suggestions");
 
@@ -27,8 +53,10 @@ pub async fn get_subnets() -> Json<Vec<SubnetSuggestion>> {
     Json(subnets)
 }
 
-/// Query parameters for unified discovery
-#[derive(Debug, Deserialize)]
+/// Query parameters for unified discovery, also the body of
+/// POST /api/discovery/jobs
+#[derive(Debug, Deserialize, IntoParams, ToSchema)]
+#[into_params(parameter_in = Query)]
 pub struct UnifiedDiscoveryQuery {
     /// Enable mDNS discovery (default: true)
     #[serde(default = "default_true")]
@@ -54,61 +82,188 @@ pub struct UnifiedDiscoveryQuery {
     /// Number of concurrent connections
     #[serde(default)]
     pub concurrency: Option<usize>,
+    /// Resolve hostnames of IP scan results via reverse DNS (default: true)
+    #[serde(default = "default_true")]
+    pub reverse_dns: bool,
+    /// DNS server for reverse lookups ("ip" or "ip:port"; default: the
+    /// system nameserver)
+    #[serde(default)]
+    pub dns_server: Option<String>,
+    /// Ask hosts without a PTR record for their NetBIOS/LLMNR name
+    /// (default: true)
+    #[serde(default = "default_true")]
+    pub netbios: bool,
+    /// Timeout per name lookup in milliseconds
+    #[serde(default)]
+    pub dns_timeout_ms: Option<u64>,
+    /// Check `ports` with TCP connects during the IP scan (default: true)
+    #[serde(default = "default_true")]
+    pub tcp: bool,
+    /// Also send an ICMP echo request to every address of the IP scan,
+    /// finding devices without open ports; uses `[ping] socket_type`
+    /// (default: false)
+    #[serde(default)]
+    pub icmp: bool,
+    /// Enable neighbor table (ARP) discovery (default: false). The IP scan
+    /// range, if given, is swept first.
+    #[serde(default)]
+    pub arp: bool,
+    /// Enable WS-Discovery (default: false)
+    #[serde(default)]
+    pub ws_discovery: bool,
 }
 
 fn default_true() -> bool {
     true
 }
 
+impl UnifiedDiscoveryQuery {
+    /// Discovery configuration for these parameters; the ICMP echo sweep
+    /// uses `socket_type`
+    fn to_config(&self, socket_type: SocketType) -> UnifiedDiscoveryConfig {
+        // Build IP scan config if enabled
+        let ip_scan_config = if self.ip_scan {
+            // Parse ports
+            let ports = self
+                .ports
+                .as_ref()
+                .map(|p| p.split(',').filter_map(|s| s.trim().parse().ok()).collect())
+                .unwrap_or_else(|| vec![80, 443, 22]);
+
+            Some(crate::unified_discovery::IpScanConfig {
+                cidr: self.cidr.clone(),
+                start_ip: self.start_ip.clone(),
+                end_ip: self.end_ip.clone(),
+                ports,
+                timeout_ms: self.timeout_ms.unwrap_or(500),
+                concurrency: self.concurrency.unwrap_or(50),
+                reverse_dns: self.reverse_dns,
+                dns_server: self.dns_server.clone(),
+                netbios: self.netbios,
+                dns_timeout_ms: self
+                    .dns_timeout_ms
+                    .unwrap_or(crate::reverse_dns::DEFAULT_TIMEOUT_MS),
+                tcp: self.tcp,
+                icmp: self.icmp,
+                socket_type,
+            })
+        } else {
+            None
+        };
+
+        let arp_range = if let Some(cidr) = self.cidr.clone() {
+            Some(IpRangeSpec::Cidr { cidr })
+        } else if let (Some(start_ip), Some(end_ip)) = (self.start_ip.clone(), self.end_ip.clone())
+        {
+            Some(IpRangeSpec::Range { start_ip, end_ip })
+        } else {
+            None
+        };
+
+        UnifiedDiscoveryConfig {
+            mdns_enabled: self.mdns,
+            ip_scan_enabled: self.ip_scan,
+            ip_scan: ip_scan_config,
+            arp_enabled: self.arp,
+            arp_range,
+            ws_discovery_enabled: self.ws_discovery,
+        }
+    }
+}
+
+/// Configured targets to annotate discovered devices with
+fn target_index(state: &AppState) -> TargetIndex {
+    let targets = state
+        .config
+        .read()
+        .map(|config| config.targets.clone())
+        .unwrap_or_default();
+    TargetIndex::new(&targets, &state.presence.devices())
+}
+
+/// Socket type of ICMP echo sweeps: the one pings use
+fn ping_socket_type(state: &AppState) -> SocketType {
+    state
+        .config
+        .read()
+        .map(|config| config.ping.socket_type)
+        .unwrap_or_default()
+}
+
 /// HTTP handler for GET /api/discovery/unified (SSE endpoint)
 ///
 /// Starts unified device discovery with multiple methods and streams merged results.
 /// Devices discovered by multiple methods are deduplicated by IP address.
+/// Streams with the same parameters share one running scan: a stream joining
+/// late first gets the devices found so far. A heartbeat event with the
+/// client queue depth and dropped update count is sent every few seconds so
+/// slow consumers are visible.
+#[utoipa::path(
+    get,
+    path = "/api/discovery/unified",
+    tag = "discovery",
+    summary = "Discover devices with mDNS, IP scan, ARP and WS-Discovery (Server-Sent Events)",
+    params(UnifiedDiscoveryQuery),
+    responses(
+        (status = 200, description = "Device, progress and heartbeat events", content_type = "text/event-stream", body = String),
+    )
+)]
 pub async fn start_unified_discovery(
+    State(state): State<AppState>,
     Query(query): Query<UnifiedDiscoveryQuery>,
 ) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
     info!(
-        "Starting unified discovery (mDNS: {}, IP scan: {})",
-        query.mdns, query.ip_scan
+        "Starting unified discovery (mDNS: {}, IP scan: {}, ARP: {}, WS-Discovery: {})",
+        query.mdns, query.ip_scan, query.arp, query.ws_discovery
     );
 
-    // Build IP scan config if enabled
-    let ip_scan_config = if query.ip_scan {
-        // Parse ports
-        let ports = query
-            .ports
-            .as_ref()
-            .map(|p| p.split(',').filter_map(|s| s.trim().parse().ok()).collect())
-            .unwrap_or_else(|| vec![80, 443, 22]);
-
-        Some(crate::unified_discovery::IpScanConfig {
-            cidr: query.cidr.clone(),
-            start_ip: query.start_ip.clone(),
-            end_ip: query.end_ip.clone(),
-            ports,
-            timeout_ms: query.timeout_ms.unwrap_or(500),
-            concurrency: query.concurrency.unwrap_or(50),
-        })
-    } else {
-        None
-    };
+    let config = query.to_config(ping_socket_type(&state));
 
-    let config = UnifiedDiscoveryConfig {
-        mdns_enabled: query.mdns,
-        ip_scan_enabled: query.ip_scan,
-        ip_scan: ip_scan_config,
-    };
+    // Dropping the subscription with the stream stops the scan once no one
+    // else is subscribed
+    let (mut subscription, backpressure) = state.discovery_sessions.subscribe(
+        config,
+        Arc::clone(&state.storage),
+        &state.discovery_stats,
+    );
+
+    let mut targets = target_index(&state);
 
     let stream = stream! {
-        let (tx, mut rx) = mpsc::channel::<IdentifiedDiscoveryEvent>(100);
+        // Catch up on the session's devices so far
+        for mut event in std::mem::take(&mut subscription.replay) {
+            targets.annotate_event(&mut event);
+            match serde_json::to_string(&event) {
+                Ok(json) => yield Ok(Event::default().data(json)),
+                Err(e) => error!("Failed to serialize discovery event: {}", e),
+            }
+        }
 
-        // Spawn the unified discovery task
-        tokio::spawn(async move {
-            run_unified_discovery(tx, config).await;
-        });
+        let rx = &mut subscription.rx;
+        let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+        // The first tick completes immediately
+        heartbeat.tick().await;
+
+        // Stream events as they arrive, interleaved with heartbeats
+        loop {
+            let mut event = tokio::select! {
+                event = rx.recv() => match event {
+                    Some(event) => event,
+                    None => break,
+                },
+                _ = heartbeat.tick() => {
+                    // Pick up targets added while discovering
+                    targets = target_index(&state);
+                    IdentifiedDiscoveryEvent::Heartbeat {
+                        queue_depth: rx.len(),
+                        queue_capacity: CLIENT_CHANNEL_CAPACITY,
+                        dropped_events: backpressure.dropped_events(),
+                    }
+                }
+            };
+            backpressure.record_queue_depth(rx.len());
+            targets.annotate_event(&mut event);
 
-        // Stream events as they arrive
-        while let Some(event) = rx.recv().await {
             match serde_json::to_string(&event) {
                 Ok(json) => {
                     yield Ok(Event::default().data(json));
@@ -127,3 +282,362 @@ pub async fn start_unified_discovery(
 
     Sse::new(stream).keep_alive(KeepAlive::default())
 }
+
+/// Response for GET /api/discovery/status
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DiscoveryStatusResponse {
+    /// Running discovery sessions, oldest first
+    pub sessions: Vec<DiscoverySessionStatus>,
+}
+
+/// HTTP handler for GET /api/discovery/status
+///
+/// Lists the running discovery sessions with their methods and number of
+/// subscribed streams.
+#[utoipa::path(
+    get,
+    path = "/api/discovery/status",
+    tag = "discovery",
+    summary = "Running discovery sessions",
+    responses(
+        (status = 200, description = "Running discovery sessions", body = DiscoveryStatusResponse),
+    )
+)]
+pub async fn get_discovery_status(State(state): State<AppState>) -> Json<DiscoveryStatusResponse> {
+    Json(DiscoveryStatusResponse {
+        sessions: state.discovery_sessions.status(),
+    })
+}
+
+/// Response for GET /api/discovery/jobs
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DiscoveryJobsResponse {
+    /// Running discovery jobs, oldest first
+    pub jobs: Vec<DiscoverySessionStatus>,
+}
+
+/// HTTP handler for POST /api/discovery/jobs
+///
+/// Starts a discovery job with the parameters of GET /api/discovery/unified.
+/// Unlike a stream, a job keeps scanning without a connected client until
+/// it completes or is cancelled; found ports still go into the port history,
+/// and a stream with the same parameters follows the job's results.
+#[utoipa::path(
+    post,
+    path = "/api/discovery/jobs",
+    tag = "discovery",
+    summary = "Start a discovery job",
+    request_body = UnifiedDiscoveryQuery,
+    responses(
+        (status = 202, description = "The started (or already running) job", body = DiscoverySessionStatus),
+        (status = 429, description = "Too many discovery jobs are running", body = ErrorResponse),
+    )
+)]
+pub async fn start_discovery_job(
+    State(state): State<AppState>,
+    Json(query): Json<UnifiedDiscoveryQuery>,
+) -> Result<(StatusCode, Json<DiscoverySessionStatus>), ApiError> {
+    info!(
+        "Starting discovery job (mDNS: {}, IP scan: {}, ARP: {}, WS-Discovery: {})",
+        query.mdns, query.ip_scan, query.arp, query.ws_discovery
+    );
+    let job = state
+        .discovery_sessions
+        .start_job(
+            query.to_config(ping_socket_type(&state)),
+            Arc::clone(&state.storage),
+            &state.discovery_stats,
+        )
+        .ok_or_else(|| {
+            ApiError::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                ErrorCode::Overloaded,
+                format!("{} discovery jobs are already running", MAX_RUNNING_JOBS),
+            )
+            .with_details(serde_json::json!({ "limit": MAX_RUNNING_JOBS }))
+        })?;
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+/// HTTP handler for GET /api/discovery/jobs
+///
+/// Lists running discovery jobs with their progress.
+#[utoipa::path(
+    get,
+    path = "/api/discovery/jobs",
+    tag = "discovery",
+    summary = "Running discovery jobs",
+    responses(
+        (status = 200, description = "Running discovery jobs", body = DiscoveryJobsResponse),
+    )
+)]
+pub async fn list_discovery_jobs(State(state): State<AppState>) -> Json<DiscoveryJobsResponse> {
+    let jobs = state
+        .discovery_sessions
+        .status()
+        .into_iter()
+        .filter(|session| session.job)
+        .collect();
+    Json(DiscoveryJobsResponse { jobs })
+}
+
+/// HTTP handler for DELETE /api/discovery/jobs/:id
+///
+/// Cancels a discovery job, or any running session listed by
+/// GET /api/discovery/status. Subscribed streams receive an error event.
+#[utoipa::path(
+    delete,
+    path = "/api/discovery/jobs/{id}",
+    tag = "discovery",
+    summary = "Cancel a discovery job",
+    params(("id" = String, Path, description = "Job or session id")),
+    responses(
+        (status = 204, description = "Job cancelled"),
+        (status = 404, description = "No running job with this id", body = ErrorResponse),
+    )
+)]
+pub async fn cancel_discovery_job(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    if !state.discovery_sessions.cancel(&id) {
+        return Err(ApiError::not_found(
+            ErrorCode::DiscoveryJobNotFound,
+            format!("No running discovery job '{}'", id),
+        ));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// A device on the network that no target pings yet
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UntrackedDevice {
+    pub address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mac: Option<String>,
+    /// Best available name from discovery
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// Where the device was seen: "discovery" and/or "neighbor_table"
+    pub sources: Vec<String>,
+    /// Unix timestamp in seconds the device was last seen
+    pub last_seen: i64,
+}
+
+/// Response for GET /api/discovery/untracked
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UntrackedResponse {
+    /// Untracked devices, most recently seen first
+    pub devices: Vec<UntrackedDevice>,
+}
+
+/// HTTP handler for GET /api/discovery/untracked
+///
+/// Lists devices seen by discovery since startup or present in the
+/// neighbor table that match no configured target by address, hostname or
+/// MAC address.
+#[utoipa::path(
+    get,
+    path = "/api/discovery/untracked",
+    tag = "discovery",
+    summary = "Devices on the network that are not targets yet",
+    responses(
+        (status = 200, description = "Untracked devices", body = UntrackedResponse),
+    )
+)]
+pub async fn get_untracked_devices(State(state): State<AppState>) -> Json<UntrackedResponse> {
+    let targets = target_index(&state);
+    let mut devices: Vec<UntrackedDevice> = Vec::new();
+
+    for (device, seen_at) in state.discovery_sessions.seen_devices() {
+        let info = &device.device_info;
+        if targets
+            .lookup(
+                &info.addresses,
+                info.hostname.as_deref(),
+                info.mac_address.as_deref(),
+            )
+            .is_some()
+        {
+            continue;
+        }
+        devices.push(UntrackedDevice {
+            address: info.primary_address.clone(),
+            mac: info.mac_address.clone(),
+            name: Some(info.name.clone()).filter(|n| *n != info.primary_address),
+            hostname: info.hostname.clone(),
+            sources: vec!["discovery".to_string()],
+            last_seen: seen_at,
+        });
+    }
+
+    for neighbor in state.presence.devices().into_iter().filter(|n| n.present) {
+        let addresses = [neighbor.address.clone()];
+        if targets
+            .lookup(&addresses, None, Some(&neighbor.mac))
+            .is_some()
+        {
+            continue;
+        }
+        match devices.iter_mut().find(|d| d.address == neighbor.address) {
+            Some(device) => {
+                device.sources.push("neighbor_table".to_string());
+                device.mac.get_or_insert(neighbor.mac);
+                device.last_seen = device.last_seen.max(neighbor.last_seen_unix);
+            }
+            None => devices.push(UntrackedDevice {
+                address: neighbor.address,
+                mac: Some(neighbor.mac),
+                name: None,
+                hostname: None,
+                sources: vec!["neighbor_table".to_string()],
+                last_seen: neighbor.last_seen_unix,
+            }),
+        }
+    }
+
+    devices.sort_by(|a, b| b.last_seen.cmp(&a.last_seen));
+    Json(UntrackedResponse { devices })
+}
+
+/// Query parameters for the port history API
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PortHistoryQuery {
+    /// Filter by device IP address (optional)
+    #[serde(default)]
+    pub address: Option<String>,
+    /// Only report changes at or after this time: Unix timestamp in seconds
+    /// or relative time range (e.g., "24h", "7d"). Default: all history
+    #[serde(default, deserialize_with = "deserialize_time_range")]
+    #[param(value_type = Option<String>)]
+    pub from: Option<TimeRangeValue>,
+    /// End timestamp (Unix timestamp in seconds, optional)
+    #[serde(default)]
+    pub to: Option<i64>,
+}
+
+/// HTTP handler for GET /api/discovery/ports
+///
+/// Returns the open ports of every device seen by an IP scan, together with
+/// the ports that opened or closed between consecutive scans.
+#[utoipa::path(
+    get,
+    path = "/api/discovery/ports",
+    tag = "discovery",
+    summary = "Open ports and port changes per scanned device",
+    params(PortHistoryQuery),
+    responses(
+        (status = 200, description = "Port history per device", body = Vec<DevicePortHistory>),
+        (status = 400, description = "Invalid time range", body = ErrorResponse),
+        (status = 500, description = "Storage query failed", body = ErrorResponse),
+    )
+)]
+pub async fn get_port_history(
+    State(state): State<AppState>,
+    Query(query): Query<PortHistoryQuery>,
+) -> Result<Json<Vec<DevicePortHistory>>, ApiError> {
+    let from = match query.from {
+        Some(ref value) => resolve_time_range_value(value).map_err(|e| {
+            error!("Invalid time range: {}", e);
+            ApiError::bad_request(ErrorCode::InvalidTimeRange, e)
+        })?,
+        None => 0,
+    };
+    let to = query.to.unwrap_or_else(|| chrono::Utc::now().timestamp());
+
+    let storage = Arc::clone(&state.storage);
+    let address = query.address.clone();
+    let history = tokio::task::spawn_blocking(move || {
+        query_port_history(&*storage, address.as_deref(), from, to)
+    })
+    .await
+    .map_err(|e| {
+        error!("Task join error: {}", e);
+        ApiError::internal(ErrorCode::Internal, e.to_string())
+    })?
+    .map_err(|e| {
+        error!("Error querying port history: {}", e);
+        ApiError::internal(ErrorCode::StorageError, e.to_string())
+    })?;
+
+    Ok(Json(history))
+}
+
+/// Query parameters for the presence API
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PresenceQuery {
+    /// Filter events by MAC or IP address (optional)
+    #[serde(default)]
+    pub device: Option<String>,
+    /// Only report events at or after this time: Unix timestamp in seconds
+    /// or relative time range (e.g., "24h", "7d"). Default: "24h"
+    #[serde(default, deserialize_with = "deserialize_time_range")]
+    #[param(value_type = Option<String>)]
+    pub from: Option<TimeRangeValue>,
+    /// End timestamp (Unix timestamp in seconds, optional)
+    #[serde(default)]
+    pub to: Option<i64>,
+}
+
+/// Response for GET /api/discovery/presence
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PresenceResponse {
+    /// Devices seen in the neighbor table since startup, present ones first
+    pub devices: Vec<PresenceDevice>,
+    /// Stored arrivals and departures in the requested range, oldest first
+    pub events: Vec<PresenceEvent>,
+}
+
+/// HTTP handler for GET /api/discovery/presence
+///
+/// Returns devices tracked from the ARP/NDP neighbor table and their
+/// arrival/departure history. Requires `[presence] enabled = true`.
+#[utoipa::path(
+    get,
+    path = "/api/discovery/presence",
+    tag = "discovery",
+    summary = "Devices in the neighbor table and their arrivals and departures",
+    params(PresenceQuery),
+    responses(
+        (status = 200, description = "Devices and presence events", body = PresenceResponse),
+        (status = 400, description = "Invalid time range", body = ErrorResponse),
+        (status = 500, description = "Storage query failed", body = ErrorResponse),
+    )
+)]
+pub async fn get_presence(
+    State(state): State<AppState>,
+    Query(query): Query<PresenceQuery>,
+) -> Result<Json<PresenceResponse>, ApiError> {
+    let to = query.to.unwrap_or_else(|| chrono::Utc::now().timestamp());
+    let from = match query.from {
+        Some(ref value) => resolve_time_range_value(value).map_err(|e| {
+            error!("Invalid time range: {}", e);
+            ApiError::bad_request(ErrorCode::InvalidTimeRange, e)
+        })?,
+        None => to - 24 * 3600,
+    };
+
+    let storage = Arc::clone(&state.storage);
+    let device = query.device.clone();
+    let events = tokio::task::spawn_blocking(move || {
+        query_presence_events(&*storage, device.as_deref(), from, to)
+    })
+    .await
+    .map_err(|e| {
+        error!("Task join error: {}", e);
+        ApiError::internal(ErrorCode::Internal, e.to_string())
+    })?
+    .map_err(|e| {
+        error!("Error querying presence events: {}", e);
+        ApiError::internal(ErrorCode::StorageError, e.to_string())
+    })?;
+
+    Ok(Json(PresenceResponse {
+        devices: state.presence.devices(),
+        events,
+    }))
+}
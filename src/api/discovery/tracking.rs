@@ -0,0 +1,145 @@
+//! Matching discovered devices against configured targets.
+//!
+//! A device is tracked when a target's address is one of its IP addresses
+//! or its hostname, or when the neighbor table maps a target's address to
+//! the device's MAC address, so devices that moved to another IP address
+//! are still recognized.
+
+use crate::config::Target;
+use crate::device_identification::{
+    IdentifiedDevice, IdentifiedDiscoveryEvent, TargetMatch, TargetMatchKind,
+};
+use crate::presence::PresenceDevice;
+use std::collections::HashMap;
+
+/// Target ids by address and by MAC address
+#[derive(Debug, Default)]
+pub(crate) struct TargetIndex {
+    by_address: HashMap<String, String>,
+    by_mac: HashMap<String, String>,
+}
+
+impl TargetIndex {
+    /// Index `targets`, with MAC addresses of their addresses from
+    /// `neighbors`
+    pub(crate) fn new(targets: &[Target], neighbors: &[PresenceDevice]) -> Self {
+        let mut index = Self::default();
+        for target in targets {
+            let address = target.address.to_lowercase();
+            if let Some(neighbor) = neighbors.iter().find(|n| n.address == address) {
+                index
+                    .by_mac
+                    .entry(normalize_mac(&neighbor.mac))
+                    .or_insert_with(|| target.id.clone());
+            }
+            index
+                .by_address
+                .entry(address)
+                .or_insert_with(|| target.id.clone());
+        }
+        index
+    }
+
+    /// The target matching a device with these addresses, hostname and MAC
+    pub(crate) fn lookup(
+        &self,
+        addresses: &[String],
+        hostname: Option<&str>,
+        mac: Option<&str>,
+    ) -> Option<TargetMatch> {
+        let by_address = addresses
+            .iter()
+            .map(|a| a.to_lowercase())
+            .chain(hostname.map(|h| h.trim_end_matches('.').to_lowercase()))
+            .find_map(|a| self.by_address.get(&a));
+        if let Some(target_id) = by_address {
+            return Some(TargetMatch {
+                target_id: target_id.clone(),
+                matched_by: TargetMatchKind::Address,
+            });
+        }
+        mac.and_then(|mac| self.by_mac.get(&normalize_mac(mac)))
+            .map(|target_id| TargetMatch {
+                target_id: target_id.clone(),
+                matched_by: TargetMatchKind::Mac,
+            })
+    }
+
+    /// Set `tracked_target` of a device
+    pub(crate) fn annotate(&self, device: &mut IdentifiedDevice) {
+        let info = &device.device_info;
+        device.tracked_target = self.lookup(
+            &info.addresses,
+            info.hostname.as_deref(),
+            info.mac_address.as_deref(),
+        );
+    }
+
+    /// Set `tracked_target` of the device in a device event
+    pub(crate) fn annotate_event(&self, event: &mut IdentifiedDiscoveryEvent) {
+        if let IdentifiedDiscoveryEvent::DeviceFound { device }
+        | IdentifiedDiscoveryEvent::DeviceUpdated { device } = event
+        {
+            self.annotate(device);
+        }
+    }
+}
+
+/// Lowercase MAC address with ":" separators
+fn normalize_mac(mac: &str) -> String {
+    mac.to_lowercase().replace('-', ":")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(id: &str, address: &str) -> Target {
+        Target {
+            id: id.to_string(),
+            address: address.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_lookup() {
+        let neighbors = vec![PresenceDevice {
+            mac: "AA:BB:CC:DD:EE:FF".to_string(),
+            address: "192.168.1.20".to_string(),
+            interface: "eth0".to_string(),
+            present: true,
+            last_seen_unix: 0,
+            since_unix: 0,
+        }];
+        let index = TargetIndex::new(
+            &[
+                target("router", "192.168.1.1"),
+                target("nas", "192.168.1.20"),
+                target("printer", "Printer.local"),
+            ],
+            &neighbors,
+        );
+
+        let matched = |addresses: &[&str], hostname, mac| {
+            let addresses: Vec<String> = addresses.iter().map(|a| a.to_string()).collect();
+            index
+                .lookup(&addresses, hostname, mac)
+                .map(|m| (m.target_id, m.matched_by))
+        };
+        assert_eq!(
+            matched(&["192.168.1.1"], None, None),
+            Some(("router".to_string(), TargetMatchKind::Address))
+        );
+        assert_eq!(
+            matched(&["192.168.1.30"], Some("printer.local."), None),
+            Some(("printer".to_string(), TargetMatchKind::Address))
+        );
+        // The NAS moved to another address
+        assert_eq!(
+            matched(&["192.168.1.21"], None, Some("aa-bb-cc-dd-ee-ff")),
+            Some(("nas".to_string(), TargetMatchKind::Mac))
+        );
+        assert_eq!(matched(&["192.168.1.99"], None, None), None);
+    }
+}
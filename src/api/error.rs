@@ -0,0 +1,394 @@
+//! Structured API errors.
+//!
+//! Handlers return `ApiError`, which is serialized as
+//! `{"code": "...", "message": "...", "details": ...}` so that frontends can
+//! branch on the stable `code` instead of parsing messages. Messages are
+//! English by default; `localize_errors_middleware` replaces them with a
+//! translation when the client prefers another supported language via
+//! `Accept-Language`.
+
+use axum::body::Body;
+use axum::http::{header, HeaderValue, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Json, Response};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Stable, machine-readable error codes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// Request body or parameters failed validation
+    InvalidRequest,
+    /// `from`/`to` could not be parsed
+    InvalidTimeRange,
+    /// A duration parameter (e.g. `bucket`, `min_gap`) could not be parsed
+    InvalidDuration,
+    /// No target with the given id/address is configured
+    TargetNotFound,
+    /// A target with the given id already exists
+    TargetAlreadyExists,
+    /// No API token with the given id exists
+    TokenNotFound,
+    /// No running discovery job has the given id
+    DiscoveryJobNotFound,
+    /// In-memory configuration could not be accessed
+    ConfigUnavailable,
+    /// Configuration file could not be read or written
+    ConfigFileError,
+    /// Time-series storage query failed
+    StorageError,
+    /// Request was rejected by access control
+    Forbidden,
+    /// Credentials are missing or invalid
+    Unauthorized,
+    /// Requester exceeded its query budget or concurrency limit
+    QuotaExceeded,
+    /// Too many failed logins; the client is locked out for a while
+    TooManyLoginAttempts,
+    /// Too many queries are running or waiting server-wide
+    Overloaded,
+    /// Changes are disabled by `[server] read_only`
+    ReadOnly,
+    /// The requested API version is not served
+    UnsupportedApiVersion,
+    /// An external integration (e.g. Home Assistant) is not configured
+    IntegrationNotConfigured,
+    /// An external integration returned an error or could not be reached
+    IntegrationError,
+    /// Any other server-side failure
+    Internal,
+}
+
+impl ErrorCode {
+    /// Generic message for this code in the given language
+    pub fn message(&self, language: Language) -> &'static str {
+        use ErrorCode::*;
+        match language {
+            Language::En => match self {
+                InvalidRequest => "Invalid request",
+                InvalidTimeRange => "Invalid time range",
+                InvalidDuration => "Invalid duration",
+                TargetNotFound => "Target not found",
+                TargetAlreadyExists => "Target already exists",
+                TokenNotFound => "API token not found",
+                DiscoveryJobNotFound => "Discovery job not found",
+                ConfigUnavailable => "Configuration is unavailable",
+                ConfigFileError => "Failed to access the configuration file",
+                StorageError => "Failed to query storage",
+                Forbidden => "Access denied",
+                Unauthorized => "Authentication required",
+                QuotaExceeded => "Query quota exceeded",
+                TooManyLoginAttempts => "Too many failed logins, try again later",
+                Overloaded => "Server is busy, try again later",
+                ReadOnly => "Server is in read-only mode",
+                UnsupportedApiVersion => "Unsupported API version",
+                IntegrationNotConfigured => "Integration is not configured",
+                IntegrationError => "Integration request failed",
+                Internal => "Internal server error",
+            },
+            Language::De => match self {
+                InvalidRequest => "Ungültige Anfrage",
+                InvalidTimeRange => "Ungültiger Zeitraum",
+                InvalidDuration => "Ungültige Dauer",
+                TargetNotFound => "Ziel nicht gefunden",
+                TargetAlreadyExists => "Ziel existiert bereits",
+                TokenNotFound => "API-Token nicht gefunden",
+                DiscoveryJobNotFound => "Suchauftrag nicht gefunden",
+                ConfigUnavailable => "Konfiguration ist nicht verfügbar",
+                ConfigFileError => "Zugriff auf die Konfigurationsdatei fehlgeschlagen",
+                StorageError => "Abfrage des Datenspeichers fehlgeschlagen",
+                Forbidden => "Zugriff verweigert",
+                Unauthorized => "Authentifizierung erforderlich",
+                QuotaExceeded => "Abfragekontingent überschritten",
+                TooManyLoginAttempts => {
+                    "Zu viele fehlgeschlagene Anmeldungen, bitte später erneut versuchen"
+                }
+                Overloaded => "Server ist ausgelastet, bitte später erneut versuchen",
+                ReadOnly => "Server ist im Nur-Lese-Modus",
+                UnsupportedApiVersion => "Nicht unterstützte API-Version",
+                IntegrationNotConfigured => "Integration ist nicht konfiguriert",
+                IntegrationError => "Anfrage an die Integration fehlgeschlagen",
+                Internal => "Interner Serverfehler",
+            },
+        }
+    }
+}
+
+/// Languages supported for error messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Language {
+    #[default]
+    En,
+    De,
+}
+
+impl Language {
+    fn from_tag(tag: &str) -> Option<Self> {
+        let primary = tag.split(['-', '_']).next()?.trim().to_ascii_lowercase();
+        match primary.as_str() {
+            "en" => Some(Language::En),
+            "de" => Some(Language::De),
+            _ => None,
+        }
+    }
+
+    /// Pick the preferred supported language from an Accept-Language header
+    /// (e.g. "de-DE,de;q=0.9,en;q=0.8"). Falls back to English.
+    pub fn from_accept_language(header: &str) -> Self {
+        let mut best: Option<(Self, f32)> = None;
+
+        for entry in header.split(',') {
+            let mut parts = entry.split(';');
+            let Some(language) = parts.next().and_then(Self::from_tag) else {
+                continue;
+            };
+            let quality = parts
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .and_then(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if quality > 0.0 && best.is_none_or(|(_, q)| quality > q) {
+                best = Some((language, quality));
+            }
+        }
+
+        best.map(|(language, _)| language).unwrap_or_default()
+    }
+
+    /// Language tag for the Content-Language header
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Language::En => "en",
+            Language::De => "de",
+        }
+    }
+}
+
+/// JSON error body
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = ErrorResponse)]
+pub(crate) struct ErrorBody<'a> {
+    code: ErrorCode,
+    /// Error message, translated according to `Accept-Language`
+    #[schema(value_type = String)]
+    message: &'a str,
+    /// Structured details, depending on the error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    details: Option<&'a serde_json::Value>,
+}
+
+/// Error body as documented in the OpenAPI spec
+pub(crate) type ErrorResponse = ErrorBody<'static>;
+
+/// Error returned by API handlers
+#[derive(Debug, Clone)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub code: ErrorCode,
+    /// Specific English message (e.g. "Target with id 'x' not found")
+    pub message: String,
+    /// Optional structured details
+    pub details: Option<serde_json::Value>,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    pub fn bad_request(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, code, message)
+    }
+
+    pub fn not_found(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, code, message)
+    }
+
+    pub fn conflict(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::CONFLICT, code, message)
+    }
+
+    pub fn internal(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, code, message)
+    }
+
+    /// Attach structured details to the error
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    /// Render the error response in the given language.
+    ///
+    /// For non-English responses the message is replaced by the translation
+    /// for the error code, and the specific English message moves to
+    /// `details` unless details were already provided.
+    fn to_response(&self, language: Language) -> Response {
+        let (message, details) = match language {
+            Language::En => (self.message.as_str(), self.details.clone()),
+            _ => (
+                self.code.message(language),
+                self.details
+                    .clone()
+                    .or_else(|| Some(serde_json::Value::String(self.message.clone()))),
+            ),
+        };
+
+        let body = ErrorBody {
+            code: self.code,
+            message,
+            details: details.as_ref(),
+        };
+
+        let mut response = (self.status, Json(body)).into_response();
+        response.headers_mut().insert(
+            header::CONTENT_LANGUAGE,
+            HeaderValue::from_static(language.tag()),
+        );
+        // Keep the error available for localize_errors_middleware
+        response.extensions_mut().insert(self.clone());
+        response
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        self.to_response(Language::En)
+    }
+}
+
+/// Middleware that re-renders `ApiError` responses in the language requested
+/// via Accept-Language
+pub(crate) async fn localize_errors_middleware(req: Request<Body>, next: Next) -> Response {
+    let language = req
+        .headers()
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|h| h.to_str().ok())
+        .map(Language::from_accept_language)
+        .unwrap_or_default();
+
+    let response = next.run(req).await;
+    if language == Language::En {
+        return response;
+    }
+    localize(response, language)
+}
+
+/// Re-render an `ApiError` response in `language`, keeping the headers set
+/// after it was rendered (e.g. `Retry-After` on 429s)
+fn localize(response: Response, language: Language) -> Response {
+    let Some(error) = response.extensions().get::<ApiError>() else {
+        return response;
+    };
+    let mut localized = error.to_response(language);
+    for (name, value) in response.headers() {
+        if name != header::CONTENT_TYPE
+            && name != header::CONTENT_LENGTH
+            && name != header::CONTENT_LANGUAGE
+        {
+            localized.headers_mut().append(name, value.clone());
+        }
+    }
+    localized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accept_language_parsing() {
+        assert_eq!(Language::from_accept_language("de-DE"), Language::De);
+        assert_eq!(
+            Language::from_accept_language("de-DE,de;q=0.9,en;q=0.8"),
+            Language::De
+        );
+        assert_eq!(
+            Language::from_accept_language("en;q=0.5, de;q=0.8"),
+            Language::De
+        );
+        assert_eq!(
+            Language::from_accept_language("de;q=0.5, en-US"),
+            Language::En
+        );
+        // Unsupported languages fall back to English
+        assert_eq!(Language::from_accept_language("fr-FR, *"), Language::En);
+        assert_eq!(Language::from_accept_language(""), Language::En);
+        assert_eq!(Language::from_accept_language("de;q=0"), Language::En);
+    }
+
+    #[test]
+    fn test_error_body_serialization() {
+        let error = ApiError::not_found(ErrorCode::TargetNotFound, "Target with id 'x' not found");
+        let body = ErrorBody {
+            code: error.code,
+            message: &error.message,
+            details: error.details.as_ref(),
+        };
+        let json = serde_json::to_string(&body).unwrap();
+        assert_eq!(
+            json,
+            r#"{"code":"target_not_found","message":"Target with id 'x' not found"}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_localized_response() {
+        let error = ApiError::not_found(ErrorCode::TargetNotFound, "Target with id 'x' not found");
+        let response = error.to_response(Language::De);
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            response.headers().get(header::CONTENT_LANGUAGE).unwrap(),
+            "de"
+        );
+
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(json["code"], "target_not_found");
+        assert_eq!(json["message"], "Ziel nicht gefunden");
+        assert_eq!(json["details"], "Target with id 'x' not found");
+    }
+
+    #[test]
+    fn test_localized_response_keeps_headers() {
+        let mut response = ApiError::new(
+            StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::QuotaExceeded,
+            "Rate limit of 30 expensive requests per minute exceeded",
+        )
+        .into_response();
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(2));
+
+        let response = localize(response, Language::De);
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers().get(header::RETRY_AFTER).unwrap(), "2");
+        assert_eq!(
+            response.headers().get(header::CONTENT_LANGUAGE).unwrap(),
+            "de"
+        );
+        assert_eq!(
+            response
+                .headers()
+                .get_all(header::CONTENT_TYPE)
+                .iter()
+                .count(),
+            1
+        );
+    }
+}
@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+use std::hash::{BuildHasher, RandomState};
+
+/// Replaces target addresses with opaque, stable-per-export IDs.
+///
+/// IDs are keyed hashes with a random key per export, so the same address
+/// maps to the same ID within one export, but IDs cannot be reversed by
+/// hashing candidate addresses (the IPv4 space is small enough to brute
+/// force an unkeyed hash) or correlated across exports.
+#[derive(Default)]
+pub struct Anonymizer {
+    key: RandomState,
+    ids: HashMap<String, String>,
+}
+
+impl Anonymizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Anonymized ID for a target address (e.g. "target-3f2a9c1d")
+    pub fn target_id(&mut self, address: &str) -> String {
+        if let Some(id) = self.ids.get(address) {
+            return id.clone();
+        }
+
+        let mut hash = self.key.hash_one(address);
+        // Avoid collisions between distinct addresses within one export
+        let mut id = format!("target-{:08x}", hash as u32);
+        while self.ids.values().any(|existing| *existing == id) {
+            hash = self.key.hash_one(hash);
+            id = format!("target-{:08x}", hash as u32);
+        }
+
+        self.ids.insert(address.to_string(), id.clone());
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_id_stable_within_export() {
+        let mut anonymizer = Anonymizer::new();
+        let a = anonymizer.target_id("192.168.1.1");
+        let b = anonymizer.target_id("8.8.8.8");
+
+        assert_eq!(anonymizer.target_id("192.168.1.1"), a);
+        assert_ne!(a, b);
+        assert!(a.starts_with("target-"));
+        assert!(!a.contains("192"));
+    }
+}
@@ -0,0 +1,85 @@
+use crate::api::ping::dto::{deserialize_time_range, TimeRangeValue};
+use serde::{Deserialize, Serialize};
+
+/// Query parameters for the data export API
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    /// Filter by target address or ID (optional, all targets if not specified)
+    #[serde(default)]
+    pub target: Option<String>,
+    /// Start timestamp (Unix timestamp in seconds) or relative time range (e.g., "24h", "7d").
+    /// Default: "24h"
+    #[serde(default, deserialize_with = "deserialize_time_range")]
+    pub from: Option<TimeRangeValue>,
+    /// End timestamp (Unix timestamp in seconds, optional)
+    #[serde(default)]
+    pub to: Option<i64>,
+    /// Filter by metric type: "latency", "failed", or "all" (default: "all")
+    #[serde(default)]
+    pub metric: Option<String>,
+    /// Maximum number of results to export (optional, no limit if not specified)
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// Output format: "csv" (default), "json" (an array) or "ndjson" (one
+    /// JSON object per line)
+    #[serde(default)]
+    pub format: Option<String>,
+    /// Replace target addresses with hashed IDs and drop target names (default: false)
+    #[serde(default)]
+    pub anonymize: bool,
+    /// Resample onto a grid of this cell width (e.g., "1m", "1h"), aligned to
+    /// multiples of it since the epoch. Default: raw results
+    #[serde(default)]
+    pub step: Option<String>,
+    /// How grid cells without results are filled: "none" (default), "previous",
+    /// "linear", or "zero". Requires `step`
+    #[serde(default)]
+    pub fill: Option<String>,
+    /// One row per timestamp and one column per target (default: false).
+    /// Requires `step`
+    #[serde(default)]
+    pub pivot: bool,
+    /// Value in the target columns of a pivoted export: "latency" (default) or "loss"
+    #[serde(default)]
+    pub pivot_value: Option<String>,
+}
+
+/// A single exported ping result
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportRow {
+    /// ISO 8601 formatted timestamp
+    pub timestamp: String,
+    /// Unix timestamp in seconds
+    pub timestamp_unix: i64,
+    /// Target IP address, or a hashed ID when anonymized
+    pub target: String,
+    /// Target name (omitted when anonymized)
+    pub target_name: Option<String>,
+    /// Whether the ping was successful
+    pub success: bool,
+    /// Latency in milliseconds (None if ping failed)
+    pub latency_ms: Option<f64>,
+}
+
+/// A grid cell of a resampled export
+#[derive(Debug, Clone, Serialize)]
+pub struct ResampledExportRow {
+    /// ISO 8601 formatted timestamp (start of the cell)
+    pub timestamp: String,
+    /// Unix timestamp in seconds (start of the cell)
+    pub timestamp_unix: i64,
+    /// Target IP address, or a hashed ID when anonymized
+    pub target: String,
+    /// Target name (omitted when anonymized)
+    pub target_name: Option<String>,
+    /// Number of successful pings in the cell
+    pub successful_count: usize,
+    /// Number of failed pings in the cell
+    pub failed_count: usize,
+    /// Percentage of failed pings (None for empty cells unless filled)
+    pub loss_percent: Option<f64>,
+    /// Average latency in milliseconds (None if no ping succeeded, unless filled)
+    pub latency_ms: Option<f64>,
+    /// True if the cell had no results and its values come from the fill policy
+    pub filled: bool,
+}
@@ -0,0 +1,590 @@
+use super::anonymize::Anonymizer;
+use super::dto::{ExportQuery, ExportRow, ResampledExportRow};
+use super::resample::{
+    format_timestamp, grid_len, FillPolicy, PivotValue, ResampleOptions, ResampledGrid, Resampler,
+    MAX_GRID_TIMESTAMPS,
+};
+use crate::api::error::{ApiError, ErrorCode};
+use crate::api::extract::Query;
+use crate::api::ping::dto::{PingDataPoint, TimeRangeValue};
+use crate::api::ping::handlers::{clamp_query_start, find_target_config};
+use crate::api::ping::query::{
+    parse_bucket_duration, resolve_time_range_value, PingDataStream, ResolvedPingDataQuery,
+};
+use crate::api::AppState;
+use crate::storage::SiteSelector;
+use async_stream::stream;
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::header,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tracing::{debug, error, info};
+
+/// Rows encoded per chunk of a streamed export
+const STREAM_BATCH_ROWS: usize = 1000;
+
+/// Encoded chunks buffered ahead of a slow client
+const STREAM_BUFFERED_CHUNKS: usize = 4;
+
+/// Export formats of GET /api/export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExportFormat {
+    Csv,
+    /// A single JSON array
+    Json,
+    /// One JSON object per line
+    Ndjson,
+}
+
+/// HTTP handler for GET /api/export
+///
+/// Exports raw ping results as CSV, a JSON array or newline-delimited JSON.
+/// Results are read and encoded a time chunk at a time while the response is
+/// sent, so exports of millions of points never hold the whole result in
+/// memory. A storage error mid-stream aborts the response, leaving a
+/// truncated body.
+///
+/// With `anonymize=true`, target addresses are replaced with hashed IDs and
+/// names are dropped so the dataset can be shared publicly without revealing
+/// the network layout. With `step`, results are resampled onto a fixed grid
+/// first (see [`super::resample`]): gaps are filled per `fill`, and
+/// `pivot=true` turns the targets into columns. The grid is built before the
+/// first byte is sent.
+pub(crate) async fn get_export(
+    State(state): State<AppState>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, ApiError> {
+    info!("Exporting ping data: {:?}", query);
+
+    let format = match query.format.as_deref() {
+        None | Some("csv") => ExportFormat::Csv,
+        Some("json") => ExportFormat::Json,
+        Some("ndjson") | Some("jsonl") => ExportFormat::Ndjson,
+        Some("parquet") => {
+            return Err(ApiError::bad_request(
+                ErrorCode::InvalidRequest,
+                "Parquet export is not supported; use format=csv, json or ndjson",
+            )
+            .with_details(serde_json::json!({ "field": "format" })))
+        }
+        Some(other) => {
+            return Err(ApiError::bad_request(
+                ErrorCode::InvalidRequest,
+                format!("Unsupported export format '{}'", other),
+            )
+            .with_details(serde_json::json!({ "field": "format" })))
+        }
+    };
+
+    let from_value = query
+        .from
+        .clone()
+        .unwrap_or_else(|| TimeRangeValue::Relative("24h".to_string()));
+    let from = resolve_time_range_value(&from_value).map_err(|e| {
+        error!("Invalid time range: {}", e);
+        ApiError::bad_request(ErrorCode::InvalidTimeRange, e)
+    })?;
+    let to = query.to.unwrap_or_else(|| chrono::Utc::now().timestamp());
+
+    let target_config = query
+        .target
+        .as_ref()
+        .and_then(|t| find_target_config(&state, t));
+
+    let resolved_query = ResolvedPingDataQuery {
+        // Data is labeled by address; accept an ID as well
+        target: target_config
+            .as_ref()
+            .map(|t| t.address.clone())
+            .or(query.target.clone()),
+        target_config,
+        from: clamp_query_start(&state, from),
+        to,
+        metric: query.metric.clone(),
+        limit: query.limit,
+        addresses: None,
+        descending: false,
+        after: None,
+        site: SiteSelector::Local,
+    };
+
+    let resample = resample_options(&query)?;
+    if let Some(options) = resample {
+        let timestamps = grid_len(resolved_query.from, resolved_query.to, options.step);
+        if timestamps > MAX_GRID_TIMESTAMPS {
+            return Err(ApiError::bad_request(
+                ErrorCode::InvalidRequest,
+                format!(
+                    "Resampling would produce {} timestamps (limit {}); use a larger step or a shorter time range",
+                    timestamps, MAX_GRID_TIMESTAMPS
+                ),
+            )
+            .with_details(serde_json::json!({ "field": "step" })));
+        }
+    }
+
+    let (tx, mut rx) = mpsc::channel::<Result<Bytes, std::io::Error>>(STREAM_BUFFERED_CHUNKS);
+    let storage = Arc::clone(&state.storage);
+    let anonymize = query.anonymize;
+    tokio::task::spawn_blocking(move || {
+        let mut anonymizer = anonymize.then(Anonymizer::new);
+        let limit = resolved_query.limit.unwrap_or(usize::MAX);
+        let points = PingDataStream::new(&*storage, &resolved_query).take(limit);
+        let rows = points.map(|point| point.map(|p| export_row(p, anonymizer.as_mut())));
+
+        match resample {
+            Some(options) => {
+                let mut resampler =
+                    Resampler::new(resolved_query.from, resolved_query.to, options.step);
+                for row in rows {
+                    // Nothing is sent before the grid is complete; stop
+                    // scanning once the client is gone
+                    if tx.is_closed() {
+                        debug!("Export client disconnected while resampling");
+                        return;
+                    }
+                    match row {
+                        Ok(row) => resampler.add(row),
+                        Err(e) => {
+                            error!("Error querying ping data for export: {}", e);
+                            let _ = tx.blocking_send(Err(std::io::Error::other(e.to_string())));
+                            return;
+                        }
+                    }
+                }
+                send_grid(&tx, &resampler.finish(options.fill), format, options.pivot);
+            }
+            None => send_rows(&tx, rows, format),
+        }
+    });
+
+    let body = Body::from_stream(stream! {
+        while let Some(chunk) = rx.recv().await {
+            yield chunk;
+        }
+    });
+    let (content_type, extension) = match format {
+        ExportFormat::Csv => ("text/csv; charset=utf-8", "csv"),
+        ExportFormat::Json => ("application/json", "json"),
+        ExportFormat::Ndjson => ("application/x-ndjson", "ndjson"),
+    };
+    let filename = if anonymize {
+        format!("sparkping-export-anonymized.{}", extension)
+    } else {
+        format!("sparkping-export.{}", extension)
+    };
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// Parse the resampling parameters of an export; None for raw exports
+fn resample_options(query: &ExportQuery) -> Result<Option<ResampleOptions>, ApiError> {
+    let Some(step) = query.step.as_deref() else {
+        let field = if query.fill.is_some() {
+            Some("fill")
+        } else if query.pivot || query.pivot_value.is_some() {
+            Some("pivot")
+        } else {
+            None
+        };
+        return match field {
+            Some(field) => Err(ApiError::bad_request(
+                ErrorCode::InvalidRequest,
+                format!("'{}' requires a resampling 'step'", field),
+            )
+            .with_details(serde_json::json!({ "field": field }))),
+            None => Ok(None),
+        };
+    };
+
+    let step = parse_bucket_duration(step).map_err(|e| {
+        ApiError::bad_request(ErrorCode::InvalidDuration, e)
+            .with_details(serde_json::json!({ "field": "step" }))
+    })?;
+    let fill = query
+        .fill
+        .as_deref()
+        .map(FillPolicy::parse)
+        .transpose()
+        .map_err(|e| {
+            ApiError::bad_request(ErrorCode::InvalidRequest, e)
+                .with_details(serde_json::json!({ "field": "fill" }))
+        })?
+        .unwrap_or(FillPolicy::None);
+    let pivot_value = query
+        .pivot_value
+        .as_deref()
+        .map(PivotValue::parse)
+        .transpose()
+        .map_err(|e| {
+            ApiError::bad_request(ErrorCode::InvalidRequest, e)
+                .with_details(serde_json::json!({ "field": "pivot_value" }))
+        })?
+        .unwrap_or(PivotValue::Latency);
+
+    Ok(Some(ResampleOptions {
+        step,
+        fill,
+        pivot: query.pivot.then_some(pivot_value),
+    }))
+}
+
+/// Frames encoded rows in the export format and sends them a batch at a
+/// time: CSV after its header, JSON as an array, NDJSON a line per row
+struct ChunkWriter<'a> {
+    tx: &'a mpsc::Sender<Result<Bytes, std::io::Error>>,
+    format: ExportFormat,
+    buffer: String,
+    rows: usize,
+}
+
+impl<'a> ChunkWriter<'a> {
+    fn new(
+        tx: &'a mpsc::Sender<Result<Bytes, std::io::Error>>,
+        format: ExportFormat,
+        csv_header: &str,
+    ) -> Self {
+        let buffer = match format {
+            ExportFormat::Csv => csv_header.to_string(),
+            ExportFormat::Json => String::from("["),
+            ExportFormat::Ndjson => String::new(),
+        };
+        Self {
+            tx,
+            format,
+            buffer,
+            rows: 0,
+        }
+    }
+
+    /// Add an encoded row. Returns false once the client is gone.
+    fn push(&mut self, row: &str) -> bool {
+        if self.format == ExportFormat::Json && self.rows > 0 {
+            self.buffer.push(',');
+        }
+        self.buffer.push_str(row);
+        if self.format != ExportFormat::Json {
+            self.buffer.push('\n');
+        }
+        self.rows += 1;
+        if self.rows.is_multiple_of(STREAM_BATCH_ROWS) && !self.send() {
+            debug!("Export client disconnected after {} rows", self.rows);
+            return false;
+        }
+        true
+    }
+
+    /// Send the rest of the output, closing a JSON array
+    fn finish(mut self) {
+        if self.format == ExportFormat::Json {
+            self.buffer.push_str("]\n");
+        }
+        if !self.buffer.is_empty() {
+            self.send();
+        }
+    }
+
+    /// Send the buffered output as the next chunk
+    fn send(&mut self) -> bool {
+        let chunk = Bytes::from(std::mem::take(&mut self.buffer));
+        self.tx.blocking_send(Ok(chunk)).is_ok()
+    }
+}
+
+/// Encode and send raw export rows a batch at a time
+fn send_rows(
+    tx: &mpsc::Sender<Result<Bytes, std::io::Error>>,
+    rows: impl Iterator<Item = Result<ExportRow, Box<dyn std::error::Error + Send + Sync>>>,
+    format: ExportFormat,
+) {
+    let mut writer = ChunkWriter::new(tx, format, CSV_HEADER);
+    for row in rows {
+        let row = match row {
+            Ok(row) => row,
+            Err(e) => {
+                error!("Error querying ping data for export: {}", e);
+                let _ = tx.blocking_send(Err(std::io::Error::other(e.to_string())));
+                return;
+            }
+        };
+        let encoded = match format {
+            ExportFormat::Csv => csv_row(&row),
+            ExportFormat::Json | ExportFormat::Ndjson => json_row(&row),
+        };
+        if !writer.push(&encoded) {
+            return;
+        }
+    }
+    writer.finish();
+}
+
+/// Encode and send a resampled grid a batch of rows at a time
+fn send_grid(
+    tx: &mpsc::Sender<Result<Bytes, std::io::Error>>,
+    grid: &ResampledGrid,
+    format: ExportFormat,
+    pivot: Option<PivotValue>,
+) {
+    let csv = format == ExportFormat::Csv;
+    let header = match pivot {
+        Some(_) => pivot_csv_header(grid),
+        None => String::from(RESAMPLED_CSV_HEADER),
+    };
+    let mut writer = ChunkWriter::new(tx, format, &header);
+    let rows: Box<dyn Iterator<Item = String> + '_> = match (csv, pivot) {
+        (true, Some(value)) => Box::new(
+            grid.pivot_rows(value)
+                .map(|(ts, values)| pivot_csv_row(ts, &values)),
+        ),
+        (false, Some(value)) => Box::new(
+            grid.pivot_rows(value)
+                .map(|(ts, values)| pivot_json_row(grid, ts, values)),
+        ),
+        (true, None) => Box::new(grid.rows().map(|row| resampled_csv_row(&row))),
+        (false, None) => Box::new(
+            grid.rows()
+                .map(|row| serde_json::to_string(&row).unwrap_or_default()),
+        ),
+    };
+
+    for row in rows {
+        if !writer.push(&row) {
+            return;
+        }
+    }
+    writer.finish();
+}
+
+/// Convert a query result to an export row, anonymizing its target if an
+/// anonymizer is given
+fn export_row(point: PingDataPoint, anonymizer: Option<&mut Anonymizer>) -> ExportRow {
+    let (target, target_name) = match anonymizer {
+        Some(anonymizer) => (anonymizer.target_id(&point.target), None),
+        None => (point.target, point.target_name),
+    };
+    ExportRow {
+        timestamp: point.timestamp,
+        timestamp_unix: point.timestamp_unix,
+        target,
+        target_name,
+        success: point.success,
+        latency_ms: point.latency_ms,
+    }
+}
+
+/// Quote a CSV field if it contains separators, quotes, or line breaks
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+const CSV_HEADER: &str = "timestamp,timestamp_unix,target,target_name,success,latency_ms\n";
+
+fn csv_row(row: &ExportRow) -> String {
+    format!(
+        "{},{},{},{},{},{}",
+        row.timestamp,
+        row.timestamp_unix,
+        csv_field(&row.target),
+        csv_field(row.target_name.as_deref().unwrap_or("")),
+        row.success,
+        optional_number(row.latency_ms),
+    )
+}
+
+fn optional_number(value: Option<f64>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+const RESAMPLED_CSV_HEADER: &str = "timestamp,timestamp_unix,target,target_name,successful_count,failed_count,loss_percent,latency_ms,filled\n";
+
+fn resampled_csv_row(row: &ResampledExportRow) -> String {
+    format!(
+        "{},{},{},{},{},{},{},{},{}",
+        row.timestamp,
+        row.timestamp_unix,
+        csv_field(&row.target),
+        csv_field(row.target_name.as_deref().unwrap_or("")),
+        row.successful_count,
+        row.failed_count,
+        optional_number(row.loss_percent),
+        optional_number(row.latency_ms),
+        row.filled,
+    )
+}
+
+/// Header of a pivoted export: one column per target, named by its address
+/// (names need not be unique)
+fn pivot_csv_header(grid: &ResampledGrid) -> String {
+    let mut header = String::from("timestamp,timestamp_unix");
+    for series in &grid.targets {
+        header.push(',');
+        header.push_str(&csv_field(&series.target));
+    }
+    header.push('\n');
+    header
+}
+
+fn pivot_csv_row(timestamp: i64, values: &[Option<f64>]) -> String {
+    let mut line = format!("{},{}", format_timestamp(timestamp), timestamp);
+    for value in values {
+        line.push(',');
+        line.push_str(&optional_number(*value));
+    }
+    line
+}
+
+/// A pivoted row as a flat JSON object keyed by target
+fn pivot_json_row(grid: &ResampledGrid, timestamp: i64, values: Vec<Option<f64>>) -> String {
+    let mut object = serde_json::Map::new();
+    object.insert("timestamp".to_string(), format_timestamp(timestamp).into());
+    object.insert("timestamp_unix".to_string(), timestamp.into());
+    for (series, value) in grid.targets.iter().zip(values) {
+        object.insert(series.target.clone(), value.into());
+    }
+    serde_json::Value::Object(object).to_string()
+}
+
+/// A row as a JSON object
+fn json_row(row: &ExportRow) -> String {
+    serde_json::to_string(row).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProbeType;
+
+    fn point(timestamp: i64, target: &str, latency_ms: Option<f64>) -> PingDataPoint {
+        PingDataPoint {
+            timestamp: String::new(),
+            timestamp_unix: timestamp,
+            target: target.to_string(),
+            target_name: Some("Home, Router".to_string()),
+            sequence: 1,
+            probe_type: ProbeType::Icmp,
+            success: latency_ms.is_some(),
+            latency_ms,
+            metric_type: String::new(),
+            site: None,
+        }
+    }
+
+    fn collect(mut rx: mpsc::Receiver<Result<Bytes, std::io::Error>>) -> String {
+        let mut out = String::new();
+        while let Some(chunk) = rx.blocking_recv() {
+            out.push_str(std::str::from_utf8(&chunk.unwrap()).unwrap());
+        }
+        out
+    }
+
+    fn send(points: Vec<PingDataPoint>, format: ExportFormat) -> String {
+        let (tx, rx) = mpsc::channel(STREAM_BUFFERED_CHUNKS + points.len());
+        let rows = points.into_iter().map(|p| Ok(export_row(p, None)));
+        send_rows(&tx, rows, format);
+        drop(tx);
+        collect(rx)
+    }
+
+    #[test]
+    fn test_export_rows_anonymized() {
+        let points = vec![
+            point(1, "192.168.1.1", Some(1.5)),
+            point(2, "192.168.1.1", None),
+            point(3, "8.8.8.8", Some(12.0)),
+        ];
+        let mut anonymizer = Anonymizer::new();
+        let rows: Vec<ExportRow> = points
+            .into_iter()
+            .map(|p| export_row(p, Some(&mut anonymizer)))
+            .collect();
+
+        assert_eq!(rows[0].target, rows[1].target);
+        assert_ne!(rows[0].target, rows[2].target);
+        assert!(rows.iter().all(|r| r.target_name.is_none()));
+        assert!(rows.iter().all(|r| !r.target.contains("192.168")));
+        assert_eq!(rows[0].latency_ms, Some(1.5));
+        assert_eq!(rows[1].latency_ms, None);
+    }
+
+    #[test]
+    fn test_send_rows_csv() {
+        let csv = send(vec![point(1, "192.168.1.1", Some(1.5))], ExportFormat::Csv);
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            "timestamp,timestamp_unix,target,target_name,success,latency_ms"
+        );
+        assert_eq!(
+            lines.next().unwrap(),
+            ",1,192.168.1.1,\"Home, Router\",true,1.5"
+        );
+    }
+
+    #[test]
+    fn test_send_rows_json() {
+        let points = vec![
+            point(1, "192.168.1.1", None),
+            point(2, "192.168.1.1", Some(1.5)),
+        ];
+        let ndjson = send(points.clone(), ExportFormat::Ndjson);
+        let lines: Vec<&str> = ndjson.lines().collect();
+        assert_eq!(lines.len(), 2);
+        let value: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(value["target"], "192.168.1.1");
+        assert_eq!(value["success"], false);
+        assert!(value["latency_ms"].is_null());
+
+        let json = send(points, ExportFormat::Json);
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value.as_array().unwrap().len(), 2);
+        assert_eq!(value[1]["latency_ms"], 1.5);
+
+        let empty = send(Vec::new(), ExportFormat::Json);
+        assert_eq!(empty.trim_end(), "[]");
+    }
+
+    #[test]
+    fn test_pivot_csv() {
+        let mut resampler = Resampler::new(0, 120, 60);
+        for p in [
+            point(10, "192.168.1.1", Some(1.5)),
+            point(70, "8.8.8.8", Some(12.0)),
+        ] {
+            resampler.add(export_row(p, None));
+        }
+        let grid = resampler.finish(FillPolicy::None);
+
+        assert_eq!(
+            pivot_csv_header(&grid),
+            "timestamp,timestamp_unix,192.168.1.1,8.8.8.8\n"
+        );
+        let rows: Vec<String> = grid
+            .pivot_rows(PivotValue::Latency)
+            .map(|(ts, values)| pivot_csv_row(ts, &values))
+            .collect();
+        assert!(rows[0].ends_with(",0,1.5,"));
+        assert!(rows[1].ends_with(",60,,12"));
+
+        let line = pivot_json_row(&grid, 60, vec![None, Some(12.0)]);
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert!(value["192.168.1.1"].is_null());
+        assert_eq!(value["8.8.8.8"], 12.0);
+    }
+}
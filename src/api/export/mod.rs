@@ -0,0 +1,4 @@
+pub mod anonymize;
+pub mod dto;
+pub mod handlers;
+mod resample;
@@ -0,0 +1,422 @@
+//! Resampling of exported ping results onto a fixed time grid.
+//!
+//! Raw results arrive at each target's own interval and jitter, so rows of
+//! different targets rarely share a timestamp. Resampling sorts them into
+//! `step` wide cells aligned to multiples of `step` since the epoch, fills
+//! cells without results according to a [`FillPolicy`], and can pivot the
+//! grid to one row per timestamp with one column per target, the shape
+//! pandas and spreadsheets expect.
+
+use super::dto::{ExportRow, ResampledExportRow};
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+
+/// Grid timestamps a single export may produce
+pub(super) const MAX_GRID_TIMESTAMPS: i64 = 1_000_000;
+
+/// How cells without any results are filled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum FillPolicy {
+    /// Leave them empty
+    None,
+    /// Repeat the previous cell with results
+    Previous,
+    /// Interpolate between the surrounding cells with results
+    Linear,
+    /// Report zero
+    Zero,
+}
+
+impl FillPolicy {
+    pub(super) fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "none" => Ok(Self::None),
+            "previous" | "ffill" => Ok(Self::Previous),
+            "linear" => Ok(Self::Linear),
+            "zero" => Ok(Self::Zero),
+            other => Err(format!(
+                "Unknown fill policy '{}'. Supported: none, previous, linear, zero",
+                other
+            )),
+        }
+    }
+}
+
+/// Value in the per-target columns of a pivoted export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum PivotValue {
+    Latency,
+    Loss,
+}
+
+impl PivotValue {
+    pub(super) fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "latency" => Ok(Self::Latency),
+            "loss" => Ok(Self::Loss),
+            other => Err(format!(
+                "Unknown pivot value '{}'. Supported: latency, loss",
+                other
+            )),
+        }
+    }
+}
+
+/// Grid layout and gap handling of a resampled export
+#[derive(Debug, Clone, Copy)]
+pub(super) struct ResampleOptions {
+    pub step: i64,
+    pub fill: FillPolicy,
+    /// One column per target holding this value, instead of one row per
+    /// target and timestamp
+    pub pivot: Option<PivotValue>,
+}
+
+/// Number of grid timestamps covering `[from, to)` with cells of `step` seconds
+pub(super) fn grid_len(from: i64, to: i64, step: i64) -> i64 {
+    let start = from.div_euclid(step) * step;
+    if to <= start {
+        0
+    } else {
+        (to - start + step - 1) / step
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Cell {
+    successful: usize,
+    failed: usize,
+    latency_sum: f64,
+}
+
+/// Results of one target, keyed by cell index
+#[derive(Debug, Default)]
+struct Series {
+    target_name: Option<String>,
+    cells: BTreeMap<usize, Cell>,
+}
+
+/// Accumulates export rows into grid cells. Only cells with results are
+/// held, so memory grows with the grid rather than with the raw results.
+#[derive(Debug)]
+pub(super) struct Resampler {
+    start: i64,
+    step: i64,
+    len: usize,
+    series: BTreeMap<String, Series>,
+}
+
+impl Resampler {
+    pub(super) fn new(from: i64, to: i64, step: i64) -> Self {
+        Self {
+            start: from.div_euclid(step) * step,
+            step,
+            len: grid_len(from, to, step).max(0) as usize,
+            series: BTreeMap::new(),
+        }
+    }
+
+    pub(super) fn add(&mut self, row: ExportRow) {
+        let index = (row.timestamp_unix - self.start).div_euclid(self.step);
+        if index < 0 || index as usize >= self.len {
+            return;
+        }
+
+        let series = self.series.entry(row.target).or_default();
+        if series.target_name.is_none() {
+            series.target_name = row.target_name;
+        }
+        let cell = series.cells.entry(index as usize).or_default();
+        match row.latency_ms {
+            Some(latency) if row.success => {
+                cell.successful += 1;
+                cell.latency_sum += latency;
+            }
+            _ => cell.failed += 1,
+        }
+    }
+
+    /// Lay the cells out on the full grid and fill the gaps
+    pub(super) fn finish(self, fill: FillPolicy) -> ResampledGrid {
+        let len = self.len;
+        let targets = self
+            .series
+            .into_iter()
+            .map(|(target, series)| {
+                let mut cells = vec![Cell::default(); len];
+                for (index, cell) in series.cells {
+                    cells[index] = cell;
+                }
+                let gaps: Vec<bool> = cells.iter().map(|c| c.successful + c.failed == 0).collect();
+                let mut loss: Vec<Option<f64>> = cells
+                    .iter()
+                    .map(|c| {
+                        let total = c.successful + c.failed;
+                        (total > 0).then(|| c.failed as f64 / total as f64 * 100.0)
+                    })
+                    .collect();
+                let mut latency: Vec<Option<f64>> = cells
+                    .iter()
+                    .map(|c| (c.successful > 0).then(|| c.latency_sum / c.successful as f64))
+                    .collect();
+                fill_gaps(&mut loss, &gaps, fill);
+                fill_gaps(&mut latency, &gaps, fill);
+
+                let cells = cells
+                    .iter()
+                    .zip(gaps)
+                    .zip(loss.into_iter().zip(latency))
+                    .map(|((cell, gap), (loss_percent, latency_ms))| ResampledCell {
+                        successful_count: cell.successful,
+                        failed_count: cell.failed,
+                        loss_percent,
+                        latency_ms,
+                        filled: gap && (loss_percent.is_some() || latency_ms.is_some()),
+                    })
+                    .collect();
+                ResampledSeries {
+                    target,
+                    target_name: series.target_name,
+                    cells,
+                }
+            })
+            .collect();
+
+        ResampledGrid {
+            start: self.start,
+            step: self.step,
+            len,
+            targets,
+        }
+    }
+}
+
+/// Fill the values at gap positions according to the policy. Gaps take their
+/// value from the neighbouring cells with results; cells with results but no
+/// value (e.g. no latency because every ping failed) are never filled.
+fn fill_gaps(values: &mut [Option<f64>], gaps: &[bool], policy: FillPolicy) {
+    match policy {
+        FillPolicy::None => {}
+        FillPolicy::Zero => {
+            for (value, gap) in values.iter_mut().zip(gaps) {
+                if *gap {
+                    *value = Some(0.0);
+                }
+            }
+        }
+        FillPolicy::Previous => {
+            let mut last = None;
+            for (value, gap) in values.iter_mut().zip(gaps) {
+                if *gap {
+                    *value = last;
+                } else {
+                    last = *value;
+                }
+            }
+        }
+        FillPolicy::Linear => {
+            let mut previous: Option<usize> = None;
+            for (index, _) in gaps.iter().enumerate().filter(|(_, gap)| !**gap) {
+                if let Some(p) = previous {
+                    if let (Some(a), Some(b)) = (values[p], values[index]) {
+                        let span = (index - p) as f64;
+                        for (offset, value) in values[p + 1..index].iter_mut().enumerate() {
+                            *value = Some(a + (b - a) * (offset + 1) as f64 / span);
+                        }
+                    }
+                }
+                previous = Some(index);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(super) struct ResampledCell {
+    pub successful_count: usize,
+    pub failed_count: usize,
+    pub loss_percent: Option<f64>,
+    pub latency_ms: Option<f64>,
+    /// No results fell into the cell; its values come from the fill policy
+    pub filled: bool,
+}
+
+#[derive(Debug)]
+pub(super) struct ResampledSeries {
+    pub target: String,
+    pub target_name: Option<String>,
+    pub cells: Vec<ResampledCell>,
+}
+
+/// Every target's cells on a common grid of timestamps
+#[derive(Debug)]
+pub(super) struct ResampledGrid {
+    start: i64,
+    step: i64,
+    len: usize,
+    pub targets: Vec<ResampledSeries>,
+}
+
+impl ResampledGrid {
+    /// Grid timestamps (cell starts), oldest first
+    pub(super) fn timestamps(&self) -> impl Iterator<Item = i64> + '_ {
+        (0..self.len as i64).map(|i| self.start + i * self.step)
+    }
+
+    /// One row per timestamp and target, ordered by timestamp then target
+    pub(super) fn rows(&self) -> impl Iterator<Item = ResampledExportRow> + '_ {
+        self.timestamps().enumerate().flat_map(move |(index, ts)| {
+            self.targets.iter().map(move |series| {
+                let cell = series.cells[index];
+                ResampledExportRow {
+                    timestamp: format_timestamp(ts),
+                    timestamp_unix: ts,
+                    target: series.target.clone(),
+                    target_name: series.target_name.clone(),
+                    successful_count: cell.successful_count,
+                    failed_count: cell.failed_count,
+                    loss_percent: cell.loss_percent,
+                    latency_ms: cell.latency_ms,
+                    filled: cell.filled,
+                }
+            })
+        })
+    }
+
+    /// One row per timestamp with the chosen value of every target, in the
+    /// order of `targets`
+    pub(super) fn pivot_rows(
+        &self,
+        value: PivotValue,
+    ) -> impl Iterator<Item = (i64, Vec<Option<f64>>)> + '_ {
+        self.timestamps().enumerate().map(move |(index, ts)| {
+            let values = self
+                .targets
+                .iter()
+                .map(|series| {
+                    let cell = &series.cells[index];
+                    match value {
+                        PivotValue::Latency => cell.latency_ms,
+                        PivotValue::Loss => cell.loss_percent,
+                    }
+                })
+                .collect();
+            (ts, values)
+        })
+    }
+}
+
+pub(super) fn format_timestamp(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp, 0)
+        .unwrap_or_else(Utc::now)
+        .to_rfc3339()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(timestamp: i64, target: &str, latency_ms: Option<f64>) -> ExportRow {
+        ExportRow {
+            timestamp: String::new(),
+            timestamp_unix: timestamp,
+            target: target.to_string(),
+            target_name: None,
+            success: latency_ms.is_some(),
+            latency_ms,
+        }
+    }
+
+    fn latencies(grid: &ResampledGrid, target: usize) -> Vec<Option<f64>> {
+        grid.targets[target]
+            .cells
+            .iter()
+            .map(|c| c.latency_ms)
+            .collect()
+    }
+
+    #[test]
+    fn test_grid_is_aligned_to_step() {
+        assert_eq!(grid_len(65, 180, 60), 2);
+        assert_eq!(grid_len(65, 181, 60), 3);
+        assert_eq!(grid_len(180, 60, 60), 0);
+
+        let mut resampler = Resampler::new(65, 181, 60);
+        resampler.add(row(70, "a", Some(10.0)));
+        resampler.add(row(100, "a", Some(20.0)));
+        resampler.add(row(130, "a", None));
+        resampler.add(row(500, "a", Some(1.0)));
+        let grid = resampler.finish(FillPolicy::None);
+
+        assert_eq!(grid.timestamps().collect::<Vec<_>>(), vec![60, 120, 180]);
+        let cell = grid.targets[0].cells[0];
+        assert_eq!(cell.successful_count, 2);
+        assert_eq!(cell.latency_ms, Some(15.0));
+        let cell = grid.targets[0].cells[1];
+        assert_eq!(cell.loss_percent, Some(100.0));
+        assert_eq!(cell.latency_ms, None);
+        assert!(!cell.filled);
+        assert_eq!(grid.targets[0].cells[2].loss_percent, None);
+    }
+
+    #[test]
+    fn test_fill_policies() {
+        let build = |fill| {
+            let mut resampler = Resampler::new(0, 50, 10);
+            resampler.add(row(0, "a", Some(10.0)));
+            resampler.add(row(30, "a", Some(40.0)));
+            resampler.finish(fill)
+        };
+
+        assert_eq!(
+            latencies(&build(FillPolicy::None), 0),
+            vec![Some(10.0), None, None, Some(40.0), None]
+        );
+        assert_eq!(
+            latencies(&build(FillPolicy::Previous), 0),
+            vec![Some(10.0), Some(10.0), Some(10.0), Some(40.0), Some(40.0)]
+        );
+        assert_eq!(
+            latencies(&build(FillPolicy::Linear), 0),
+            vec![Some(10.0), Some(20.0), Some(30.0), Some(40.0), None]
+        );
+        let grid = build(FillPolicy::Zero);
+        assert_eq!(
+            latencies(&grid, 0),
+            vec![Some(10.0), Some(0.0), Some(0.0), Some(40.0), Some(0.0)]
+        );
+        assert!(grid.targets[0].cells[1].filled);
+        assert!(!grid.targets[0].cells[3].filled);
+    }
+
+    #[test]
+    fn test_failed_cells_are_not_interpolated_across() {
+        let mut values = vec![Some(10.0), None, None, Some(30.0)];
+        let gaps = vec![false, false, true, false];
+        fill_gaps(&mut values, &gaps, FillPolicy::Linear);
+        assert_eq!(values, vec![Some(10.0), None, None, Some(30.0)]);
+    }
+
+    #[test]
+    fn test_pivot_rows() {
+        let mut resampler = Resampler::new(0, 20, 10);
+        resampler.add(row(1, "b", Some(5.0)));
+        resampler.add(row(2, "a", Some(1.0)));
+        resampler.add(row(12, "a", None));
+        let grid = resampler.finish(FillPolicy::None);
+
+        let targets: Vec<&str> = grid.targets.iter().map(|s| s.target.as_str()).collect();
+        assert_eq!(targets, vec!["a", "b"]);
+        let rows: Vec<_> = grid.pivot_rows(PivotValue::Loss).collect();
+        assert_eq!(rows[0], (0, vec![Some(0.0), Some(0.0)]));
+        assert_eq!(rows[1], (10, vec![Some(100.0), None]));
+        assert_eq!(grid.rows().count(), 4);
+    }
+
+    #[test]
+    fn test_parse_options() {
+        assert_eq!(FillPolicy::parse("ffill"), Ok(FillPolicy::Previous));
+        assert!(FillPolicy::parse("spline").is_err());
+        assert_eq!(PivotValue::parse("loss"), Ok(PivotValue::Loss));
+    }
+}
@@ -0,0 +1,155 @@
+//! Request extractors that reject with the `ApiError` envelope.
+//!
+//! axum's `Query` and `Json` answer malformed requests with a plain-text
+//! body. These wrappers run them and turn the rejection into an `ApiError`
+//! (`invalid_request`, keeping axum's status and message), so clients get
+//! the same `{"code": "...", "message": "..."}` body as for every other
+//! error. `Json` also serves as response body, rendered by axum's.
+
+use crate::api::error::{ApiError, ErrorCode};
+use axum::async_trait;
+use axum::extract::rejection::{JsonRejection, QueryRejection};
+use axum::extract::{FromRequest, FromRequestParts, Request};
+use axum::http::request::Parts;
+use axum::response::{IntoResponse, Response};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Query string deserialized into `T`
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Query<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Query<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        axum::extract::Query::<T>::from_request_parts(parts, state)
+            .await
+            .map(|axum::extract::Query(value)| Query(value))
+            .map_err(query_rejection)
+    }
+}
+
+/// JSON request body deserialized into `T`, or a JSON response body
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Json<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for Json<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        axum::Json::<T>::from_request(req, state)
+            .await
+            .map(|axum::Json(value)| Json(value))
+            .map_err(json_rejection)
+    }
+}
+
+impl<T: Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Response {
+        axum::Json(self.0).into_response()
+    }
+}
+
+fn query_rejection(rejection: QueryRejection) -> ApiError {
+    ApiError::new(
+        rejection.status(),
+        ErrorCode::InvalidRequest,
+        rejection.body_text(),
+    )
+}
+
+/// Bad JSON is a 400, a missing content type a 415 and a body that does not
+/// match the expected shape a 422
+fn json_rejection(rejection: JsonRejection) -> ApiError {
+    ApiError::new(
+        rejection.status(),
+        ErrorCode::InvalidRequest,
+        rejection.body_text(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{header, StatusCode};
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize)]
+    struct Params {
+        limit: usize,
+    }
+
+    #[tokio::test]
+    async fn test_query_rejection() {
+        let request = Request::builder()
+            .uri("/api/ping/data?limit=ten")
+            .body(Body::empty())
+            .unwrap();
+        let (mut parts, _) = request.into_parts();
+        let error = Query::<Params>::from_request_parts(&mut parts, &())
+            .await
+            .unwrap_err();
+        assert_eq!(error.status, StatusCode::BAD_REQUEST);
+        assert_eq!(error.code, ErrorCode::InvalidRequest);
+        assert!(error.message.contains("limit"));
+
+        let request = Request::builder()
+            .uri("/api/ping/data?limit=10")
+            .body(Body::empty())
+            .unwrap();
+        let (mut parts, _) = request.into_parts();
+        let Query(params) = Query::<Params>::from_request_parts(&mut parts, &())
+            .await
+            .unwrap();
+        assert_eq!(params.limit, 10);
+    }
+
+    #[tokio::test]
+    async fn test_json_rejection() {
+        let request = |content_type: &str, body: &str| {
+            Request::builder()
+                .method("POST")
+                .header(header::CONTENT_TYPE, content_type)
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+
+        let error = Json::<Params>::from_request(request("application/json", "{"), &())
+            .await
+            .unwrap_err();
+        assert_eq!(error.status, StatusCode::BAD_REQUEST);
+        assert_eq!(error.code, ErrorCode::InvalidRequest);
+
+        let body = r#"{"limit": -1}"#;
+        let error = Json::<Params>::from_request(request("application/json", body), &())
+            .await
+            .unwrap_err();
+        assert_eq!(error.status, StatusCode::UNPROCESSABLE_ENTITY);
+
+        let error = Json::<Params>::from_request(request("text/plain", body), &())
+            .await
+            .unwrap_err();
+        assert_eq!(error.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        // The envelope is what clients see
+        let response = error.into_response();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(json["code"], "invalid_request");
+        assert!(json["message"].is_string());
+    }
+}
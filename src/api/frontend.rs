@@ -0,0 +1,50 @@
+//! Frontend embedded into the binary (`embed-frontend` feature).
+//!
+//! `frontend/dist` is compiled in, so a single binary serves the dashboard
+//! without `STATIC_DIR`. Build the frontend before building with the
+//! feature. A static directory, when present, still takes precedence (see
+//! `create_router`), so a newer frontend can be dropped in without
+//! rebuilding.
+
+use axum::body::Body;
+use axum::http::{header, HeaderValue, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use rust_embed::RustEmbed;
+
+#[derive(RustEmbed)]
+#[folder = "frontend/dist/"]
+struct FrontendAssets;
+
+/// Serve an embedded file, or `index.html` for any other path so the SPA
+/// router can handle it. Cache headers match directory serving: hashed
+/// `assets/` forever, everything else revalidated.
+pub(crate) async fn serve_embedded_frontend(uri: Uri) -> Response {
+    let path = uri.path().trim_start_matches('/');
+    let (path, file) = match FrontendAssets::get(path).filter(|_| !path.is_empty()) {
+        Some(file) => (path, file),
+        None => match FrontendAssets::get("index.html") {
+            Some(file) => ("index.html", file),
+            None => return StatusCode::NOT_FOUND.into_response(),
+        },
+    };
+
+    let cache_control = if path.starts_with("assets/") {
+        "public, max-age=31536000, immutable"
+    } else {
+        "no-cache, must-revalidate"
+    };
+    let content_type = HeaderValue::from_str(file.metadata.mimetype())
+        .unwrap_or(HeaderValue::from_static("application/octet-stream"));
+
+    (
+        [
+            (header::CONTENT_TYPE, content_type),
+            (
+                header::CACHE_CONTROL,
+                HeaderValue::from_static(cache_control),
+            ),
+        ],
+        Body::from(file.data.into_owned()),
+    )
+        .into_response()
+}
@@ -0,0 +1,63 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Body of POST /api/grafana/search
+#[derive(Debug, Default, Deserialize)]
+pub struct SearchRequest {
+    /// Text typed into the query editor; series containing it are returned
+    #[serde(default)]
+    pub target: String,
+}
+
+/// A series offered in Grafana's query editor
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SearchResult {
+    /// Display name (e.g., "Router latency")
+    pub text: String,
+    /// Series ID sent back as the query target (e.g., "router:latency")
+    pub value: String,
+}
+
+/// Body of POST /api/grafana/query, as sent by Grafana
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryRequest {
+    pub range: QueryRange,
+    /// Suggested spacing of points in milliseconds
+    #[serde(default)]
+    pub interval_ms: Option<i64>,
+    /// Upper bound on points per series (the panel width)
+    #[serde(default)]
+    pub max_data_points: Option<i64>,
+    #[serde(default)]
+    pub targets: Vec<QueryTarget>,
+}
+
+/// Dashboard time range
+#[derive(Debug, Deserialize)]
+pub struct QueryRange {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+}
+
+/// One query of a panel
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryTarget {
+    /// Series ID from the search endpoint
+    #[serde(default)]
+    pub target: String,
+    #[serde(default)]
+    pub ref_id: Option<String>,
+    /// Queries hidden in the panel are not answered
+    #[serde(default)]
+    pub hide: bool,
+}
+
+/// A time series in Grafana's format
+#[derive(Debug, PartialEq, Serialize)]
+pub struct TimeSeries {
+    pub target: String,
+    /// `[value, unix timestamp in milliseconds]` pairs
+    pub datapoints: Vec<(f64, i64)>,
+}
@@ -0,0 +1,151 @@
+use super::dto::{QueryRequest, SearchRequest, SearchResult, TimeSeries};
+use super::series::{bucket_seconds, datapoints, parse_series_id, series_id, SeriesMetric};
+use crate::api::error::{ApiError, ErrorCode};
+use crate::api::extract::Json;
+use crate::api::ping::query::query_ping_aggregated_with_rollups;
+use crate::api::AppState;
+use crate::config::Target;
+use axum::extract::State;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{error, info};
+
+/// HTTP handler for GET /api/grafana/
+///
+/// Grafana's "Save & test" for a JSON datasource expects a 200 here.
+pub(crate) async fn check_connection() -> &'static str {
+    "OK"
+}
+
+/// Display name of a target in series names
+fn display_name(target: &Target) -> &str {
+    target.name.as_deref().unwrap_or(&target.address)
+}
+
+fn configured_targets(state: &AppState) -> Result<Vec<Target>, ApiError> {
+    let config = state.config.read().map_err(|e| {
+        error!("Failed to read config: {}", e);
+        ApiError::internal(ErrorCode::ConfigUnavailable, "Failed to read configuration")
+    })?;
+    Ok(config.targets.clone())
+}
+
+/// HTTP handler for POST /api/grafana/search
+///
+/// Lists a latency, min/max latency and loss series per target, filtered by
+/// the text typed into Grafana's query editor.
+pub(crate) async fn search(
+    State(state): State<AppState>,
+    body: Option<Json<SearchRequest>>,
+) -> Result<Json<Vec<SearchResult>>, ApiError> {
+    let filter = body
+        .map(|Json(request)| request.target.trim().to_lowercase())
+        .unwrap_or_default();
+
+    let results = configured_targets(&state)?
+        .iter()
+        .flat_map(|target| {
+            SeriesMetric::ALL
+                .into_iter()
+                .map(move |metric| SearchResult {
+                    text: format!("{} {}", display_name(target), metric.label()),
+                    value: series_id(&target.id, metric),
+                })
+        })
+        .filter(|result| {
+            filter.is_empty()
+                || result.text.to_lowercase().contains(&filter)
+                || result.value.to_lowercase().contains(&filter)
+        })
+        .collect();
+
+    Ok(Json(results))
+}
+
+/// HTTP handler for POST /api/grafana/query
+///
+/// Answers each (non-hidden) query with a time series over the dashboard's
+/// time range, bucketed by Grafana's interval. Targets queried for several
+/// metrics are read once.
+pub(crate) async fn query(
+    State(state): State<AppState>,
+    Json(request): Json<QueryRequest>,
+) -> Result<Json<Vec<TimeSeries>>, ApiError> {
+    info!("Grafana query: {:?}", request);
+
+    let from = request.range.from.timestamp();
+    let to = request.range.to.timestamp();
+    if to < from {
+        return Err(ApiError::bad_request(
+            ErrorCode::InvalidTimeRange,
+            "'to' must not be before 'from'",
+        ));
+    }
+    let bucket = bucket_seconds(from, to, request.interval_ms, request.max_data_points);
+
+    let targets = configured_targets(&state)?;
+    let mut queries = Vec::new();
+    for query in request.targets.iter().filter(|q| !q.hide) {
+        let (target_id, metric) = parse_series_id(&query.target).ok_or_else(|| {
+            ApiError::bad_request(
+                ErrorCode::InvalidRequest,
+                format!("Unknown series '{}'", query.target),
+            )
+            .with_details(serde_json::json!({ "ref_id": query.ref_id }))
+        })?;
+        let target = targets
+            .iter()
+            .find(|t| t.id == target_id)
+            .cloned()
+            .ok_or_else(|| {
+                ApiError::not_found(
+                    ErrorCode::TargetNotFound,
+                    format!("Target '{}' not found", target_id),
+                )
+                .with_details(serde_json::json!({ "ref_id": query.ref_id }))
+            })?;
+        queries.push((target, metric));
+    }
+
+    let storage = Arc::clone(&state.storage);
+    let coverage = state.downsampler.coverage();
+    let series = tokio::task::spawn_blocking(move || {
+        let mut buckets_by_target = HashMap::new();
+        let mut series = Vec::with_capacity(queries.len());
+        for (target, metric) in &queries {
+            let buckets = match buckets_by_target.entry(target.id.clone()) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let (buckets, _, _) = query_ping_aggregated_with_rollups(
+                        &*storage,
+                        &coverage,
+                        Some(&target.address),
+                        Some(target),
+                        from,
+                        to,
+                        bucket,
+                        false,
+                    )?;
+                    entry.insert(buckets)
+                }
+            };
+            series.push(TimeSeries {
+                target: format!("{} {}", display_name(target), metric.label()),
+                datapoints: datapoints(buckets, *metric),
+            });
+        }
+        Ok::<_, Box<dyn std::error::Error + Send + Sync>>(series)
+    })
+    .await
+    .map_err(|e| {
+        error!("Task join error: {}", e);
+        ApiError::internal(ErrorCode::Internal, e.to_string())
+    })?
+    .map_err(|e| {
+        error!("Error querying Grafana series: {}", e);
+        ApiError::internal(ErrorCode::StorageError, e.to_string())
+    })?;
+
+    Ok(Json(series))
+}
@@ -0,0 +1,173 @@
+//! Series offered to Grafana and their conversion from aggregated buckets.
+
+use crate::api::ping::dto::BucketDataPoint;
+
+/// Smallest bucket a query is answered with
+const MIN_BUCKET_SECONDS: i64 = 10;
+
+/// Values a series can show for a target
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SeriesMetric {
+    /// Average latency in milliseconds
+    Latency,
+    LatencyMin,
+    LatencyMax,
+    /// Failed pings in percent
+    Loss,
+}
+
+impl SeriesMetric {
+    pub(crate) const ALL: [SeriesMetric; 4] = [
+        SeriesMetric::Latency,
+        SeriesMetric::LatencyMin,
+        SeriesMetric::LatencyMax,
+        SeriesMetric::Loss,
+    ];
+
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            SeriesMetric::Latency => "latency",
+            SeriesMetric::LatencyMin => "latency_min",
+            SeriesMetric::LatencyMax => "latency_max",
+            SeriesMetric::Loss => "loss",
+        }
+    }
+
+    /// Suffix of the series name shown in Grafana
+    pub(crate) fn label(&self) -> &'static str {
+        match self {
+            SeriesMetric::Latency => "latency",
+            SeriesMetric::LatencyMin => "min latency",
+            SeriesMetric::LatencyMax => "max latency",
+            SeriesMetric::Loss => "loss",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|m| m.as_str() == s)
+    }
+
+    /// Value of a bucket, if it has one
+    fn value(&self, bucket: &BucketDataPoint) -> Option<f64> {
+        match self {
+            SeriesMetric::Latency => bucket.avg,
+            SeriesMetric::LatencyMin => bucket.min,
+            SeriesMetric::LatencyMax => bucket.max,
+            SeriesMetric::Loss => {
+                let total = bucket.successful_count + bucket.failed_count;
+                (total > 0).then(|| bucket.failed_count as f64 * 100.0 / total as f64)
+            }
+        }
+    }
+}
+
+/// ID of a series: "<target id>:<metric>"
+pub(crate) fn series_id(target_id: &str, metric: SeriesMetric) -> String {
+    format!("{}:{}", target_id, metric.as_str())
+}
+
+/// Split a series ID into the target ID and metric
+pub(crate) fn parse_series_id(id: &str) -> Option<(&str, SeriesMetric)> {
+    let (target_id, metric) = id.trim().rsplit_once(':')?;
+    Some((target_id, SeriesMetric::parse(metric)?)).filter(|(t, _)| !t.is_empty())
+}
+
+/// Bucket duration for a query: Grafana's interval, widened so the range
+/// fits into `max_data_points`. Buckets of a minute or more are rounded up
+/// to whole minutes so they can be read from the rollups.
+pub(crate) fn bucket_seconds(
+    from: i64,
+    to: i64,
+    interval_ms: Option<i64>,
+    max_data_points: Option<i64>,
+) -> i64 {
+    let mut seconds = interval_ms.unwrap_or(0) / 1000;
+    if let Some(max_points) = max_data_points.filter(|m| *m > 0) {
+        let span = (to - from).max(0);
+        seconds = seconds.max((span + max_points - 1) / max_points);
+    }
+    let seconds = seconds.max(MIN_BUCKET_SECONDS);
+    if seconds >= 60 {
+        (seconds + 59) / 60 * 60
+    } else {
+        seconds
+    }
+}
+
+/// Grafana datapoints of a metric, skipping buckets without a value
+pub(crate) fn datapoints(buckets: &[BucketDataPoint], metric: SeriesMetric) -> Vec<(f64, i64)> {
+    buckets
+        .iter()
+        .filter_map(|b| Some((metric.value(b)?, b.timestamp_unix * 1000)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bucket(start: i64, avg: Option<f64>, successful: usize, failed: usize) -> BucketDataPoint {
+        BucketDataPoint {
+            timestamp: start.to_string(),
+            timestamp_unix: start,
+            timestamp_end_unix: start + 60,
+            target: "192.168.1.1".to_string(),
+            target_name: None,
+            min: avg,
+            max: avg,
+            avg,
+            percentiles: None,
+            count: successful + failed,
+            successful_count: successful,
+            failed_count: failed,
+            resolution: None,
+            complete: true,
+            loss_percent: None,
+        }
+    }
+
+    #[test]
+    fn test_series_id_roundtrip() {
+        let id = series_id("router", SeriesMetric::LatencyMax);
+        assert_eq!(id, "router:latency_max");
+        assert_eq!(
+            parse_series_id(&id),
+            Some(("router", SeriesMetric::LatencyMax))
+        );
+        assert_eq!(
+            parse_series_id("fe80::1:loss"),
+            Some(("fe80::1", SeriesMetric::Loss))
+        );
+        assert_eq!(parse_series_id("router:jitter"), None);
+        assert_eq!(parse_series_id(":loss"), None);
+        assert_eq!(parse_series_id("router"), None);
+    }
+
+    #[test]
+    fn test_bucket_seconds() {
+        // 6h panel, 1000 points wide, 20s interval
+        assert_eq!(bucket_seconds(0, 21_600, Some(20_000), Some(1000)), 60);
+        assert_eq!(bucket_seconds(0, 3600, Some(15_000), Some(1000)), 15);
+        assert_eq!(bucket_seconds(0, 3600, None, None), MIN_BUCKET_SECONDS);
+        // Rounded up to whole minutes
+        assert_eq!(bucket_seconds(0, 86_400, Some(90_000), None), 120);
+    }
+
+    #[test]
+    fn test_datapoints() {
+        let buckets = vec![
+            bucket(60, Some(12.5), 3, 1),
+            bucket(120, None, 0, 4),
+            bucket(180, None, 0, 0),
+        ];
+
+        assert_eq!(
+            datapoints(&buckets, SeriesMetric::Latency),
+            vec![(12.5, 60_000)]
+        );
+        assert_eq!(
+            datapoints(&buckets, SeriesMetric::Loss),
+            vec![(25.0, 60_000), (100.0, 120_000)]
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use crate::config::{ProbeType, Target};
+use crate::notifications::target_state::classify;
+use crate::rollups::TargetRollups;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// What SparkPing exposes to Home Assistant, for the add-on and integrations
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DiscoveryInfo {
+    /// Always "sparkping"
+    #[schema(value_type = String)]
+    pub service: &'static str,
+    #[schema(value_type = String)]
+    pub version: &'static str,
+    /// Newest served API version
+    pub api_version: u32,
+    /// Whether SparkPing runs as a Home Assistant add-on
+    pub addon: bool,
+    /// Whether only Home Assistant ingress may access the API
+    pub ingress_only: bool,
+    /// Whether API requests need a token
+    pub auth_enabled: bool,
+    pub port: u16,
+    pub target_count: usize,
+    /// Path of the per-target sensor summaries
+    pub sensors_path: String,
+}
+
+/// Up/down state of a target as a Home Assistant sensor value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SensorState {
+    Online,
+    Offline,
+    /// No results yet, or only some pings of the current cycle failed
+    Unknown,
+    Paused,
+}
+
+/// Flat latency/availability summary of a target, suited for Home
+/// Assistant REST sensors (`value_template: "{{ value_json.latency_ms }}"`)
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SensorSummary {
+    pub target_id: String,
+    /// Target name, or the address for unnamed targets
+    pub name: String,
+    pub address: String,
+    pub probe_type: ProbeType,
+    pub state: SensorState,
+    /// Whether the target is up (None while unknown or paused)
+    pub available: Option<bool>,
+    /// Latency of the most recent successful ping in milliseconds
+    pub latency_ms: Option<f64>,
+    /// Average latency over the last 5 minutes in milliseconds
+    pub avg_latency_ms: Option<f64>,
+    pub min_latency_ms: Option<f64>,
+    pub max_latency_ms: Option<f64>,
+    /// Packet loss over the last 5 minutes as a percentage (0-100)
+    pub loss_percent: Option<f64>,
+    /// Unix timestamp in seconds of the most recent ping result
+    pub last_seen_unix: Option<i64>,
+}
+
+impl SensorSummary {
+    pub fn new(target: &Target, rollups: Option<&TargetRollups>) -> Self {
+        let available = if target.paused {
+            None
+        } else {
+            rollups.and_then(|r| classify(r, target.ping_count))
+        };
+        let state = match available {
+            Some(true) => SensorState::Online,
+            Some(false) => SensorState::Offline,
+            None if target.paused => SensorState::Paused,
+            None => SensorState::Unknown,
+        };
+        let window = rollups.map(|r| &r.five_minutes);
+
+        Self {
+            target_id: target.id.clone(),
+            name: target
+                .name
+                .clone()
+                .unwrap_or_else(|| target.address.clone()),
+            address: target.address.clone(),
+            probe_type: target.probe_type,
+            state,
+            available,
+            latency_ms: rollups.and_then(|r| r.last_latency_ms),
+            avg_latency_ms: window.and_then(|w| w.avg_latency_ms),
+            min_latency_ms: window.and_then(|w| w.min_latency_ms),
+            max_latency_ms: window.and_then(|w| w.max_latency_ms),
+            loss_percent: window.and_then(|w| w.loss_percent),
+            last_seen_unix: rollups.and_then(|r| r.last_sample_unix),
+        }
+    }
+}
+
+/// API response for GET /api/ha/sensors
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SensorsResponse {
+    /// Unix timestamp in seconds the rollups were evaluated at
+    pub timestamp_unix: i64,
+    pub targets: Vec<SensorSummary>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rollups::RollingAggregator;
+
+    fn target(paused: bool) -> Target {
+        Target {
+            id: "t1".to_string(),
+            address: "192.168.1.1".to_string(),
+            ping_count: 2,
+            paused,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_sensor_summary() {
+        let rollups = RollingAggregator::new();
+        let summary = SensorSummary::new(&target(false), None);
+        assert_eq!(summary.state, SensorState::Unknown);
+        assert_eq!(summary.name, "192.168.1.1");
+
+        rollups.record("t1", 100, Some(10.0));
+        rollups.record("t1", 101, Some(20.0));
+        let current = rollups.rollups("t1", 101).unwrap();
+        let summary = SensorSummary::new(&target(false), Some(&current));
+        assert_eq!(summary.state, SensorState::Online);
+        assert_eq!(summary.available, Some(true));
+        assert_eq!(summary.latency_ms, Some(20.0));
+        assert_eq!(summary.avg_latency_ms, Some(15.0));
+        assert_eq!(summary.loss_percent, Some(0.0));
+        assert_eq!(summary.last_seen_unix, Some(101));
+
+        rollups.record("t1", 102, None);
+        rollups.record("t1", 103, None);
+        let current = rollups.rollups("t1", 103).unwrap();
+        let summary = SensorSummary::new(&target(false), Some(&current));
+        assert_eq!(summary.state, SensorState::Offline);
+        assert_eq!(summary.loss_percent, Some(50.0));
+
+        let summary = SensorSummary::new(&target(true), Some(&current));
+        assert_eq!(summary.state, SensorState::Paused);
+        assert_eq!(summary.available, None);
+    }
+}
@@ -0,0 +1,113 @@
+use super::dto::{DiscoveryInfo, SensorSummary, SensorsResponse};
+use crate::api::error::{ApiError, ErrorCode, ErrorResponse};
+use crate::api::versioning::CURRENT_API_VERSION;
+use crate::api::AppState;
+use crate::config::AppConfig;
+use crate::ha_addon::running_as_addon;
+use axum::extract::{Path, State};
+use axum::response::Json;
+use std::sync::RwLockReadGuard;
+use tracing::error;
+
+fn read_config(state: &AppState) -> Result<RwLockReadGuard<'_, AppConfig>, ApiError> {
+    state.config.read().map_err(|e| {
+        error!("Failed to read config: {}", e);
+        ApiError::internal(ErrorCode::ConfigUnavailable, "Failed to read configuration")
+    })
+}
+
+/// HTTP handler for GET /api/ha/discovery_info
+///
+/// Describes this instance for the Home Assistant add-on and integrations:
+/// version, whether it runs as an add-on, access restrictions and where the
+/// sensor summaries are served.
+#[utoipa::path(
+    get,
+    path = "/api/ha/discovery_info",
+    tag = "home_assistant",
+    summary = "Instance info for Home Assistant",
+    responses(
+        (status = 200, description = "Instance info", body = DiscoveryInfo),
+        (status = 500, description = "Configuration unavailable", body = ErrorResponse),
+    )
+)]
+pub(crate) async fn get_discovery_info(
+    State(state): State<AppState>,
+) -> Result<Json<DiscoveryInfo>, ApiError> {
+    let config = read_config(&state)?;
+
+    Ok(Json(DiscoveryInfo {
+        service: "sparkping",
+        version: env!("CARGO_PKG_VERSION"),
+        api_version: CURRENT_API_VERSION,
+        addon: running_as_addon(),
+        ingress_only: config.server.home_assistant_ingress_only,
+        auth_enabled: config.auth.enabled,
+        port: config.server.port,
+        target_count: config.targets.len(),
+        sensors_path: format!("/api/v{}/ha/sensors", CURRENT_API_VERSION),
+    }))
+}
+
+/// HTTP handler for GET /api/ha/sensors
+///
+/// Flat latency, loss and up/down summary of every target from the in-memory
+/// rollups.
+#[utoipa::path(
+    get,
+    path = "/api/ha/sensors",
+    tag = "home_assistant",
+    summary = "Sensor summary of every target",
+    responses(
+        (status = 200, description = "Sensor summaries", body = SensorsResponse),
+        (status = 500, description = "Configuration unavailable", body = ErrorResponse),
+    )
+)]
+pub(crate) async fn get_sensors(
+    State(state): State<AppState>,
+) -> Result<Json<SensorsResponse>, ApiError> {
+    let config = read_config(&state)?;
+
+    let now = chrono::Utc::now().timestamp();
+    let targets = config
+        .targets
+        .iter()
+        .map(|t| SensorSummary::new(t, state.rollups.rollups(&t.id, now).as_ref()))
+        .collect();
+
+    Ok(Json(SensorsResponse {
+        timestamp_unix: now,
+        targets,
+    }))
+}
+
+/// HTTP handler for GET /api/ha/sensors/:id
+///
+/// Summary of a single target, for one Home Assistant REST sensor per target.
+#[utoipa::path(
+    get,
+    path = "/api/ha/sensors/{id}",
+    tag = "home_assistant",
+    summary = "Sensor summary of a target",
+    params(("id" = String, Path, description = "Target ID")),
+    responses(
+        (status = 200, description = "Sensor summary", body = SensorSummary),
+        (status = 404, description = "Target not found", body = ErrorResponse),
+    )
+)]
+pub(crate) async fn get_sensor(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<SensorSummary>, ApiError> {
+    let config = read_config(&state)?;
+    let target = config.targets.iter().find(|t| t.id == id).ok_or_else(|| {
+        ApiError::not_found(
+            ErrorCode::TargetNotFound,
+            format!("Target '{}' not found", id),
+        )
+    })?;
+
+    let now = chrono::Utc::now().timestamp();
+    let rollups = state.rollups.rollups(&target.id, now);
+    Ok(Json(SensorSummary::new(target, rollups.as_ref())))
+}
@@ -0,0 +1,77 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Response for GET /healthz
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LivenessResponse {
+    /// Always "ok"; the process answers as long as it is alive
+    #[schema(value_type = String)]
+    pub status: &'static str,
+    /// Seconds since the daemon started
+    pub uptime_seconds: u64,
+}
+
+/// A single readiness check
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ReadinessCheck {
+    /// Check name (`startup`, `config`, `storage`)
+    #[schema(value_type = String)]
+    pub name: &'static str,
+    pub ok: bool,
+    /// Why the check failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ReadinessCheck {
+    pub fn new(name: &'static str, result: Result<(), String>) -> Self {
+        Self {
+            name,
+            ok: result.is_ok(),
+            error: result.err(),
+        }
+    }
+}
+
+/// Response for GET /readyz
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ReadinessResponse {
+    /// Whether every check passed
+    pub ready: bool,
+    pub checks: Vec<ReadinessCheck>,
+}
+
+impl ReadinessResponse {
+    pub fn new(checks: Vec<ReadinessCheck>) -> Self {
+        Self {
+            ready: checks.iter().all(|c| c.ok),
+            checks,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_readiness_requires_all_checks() {
+        let response = ReadinessResponse::new(vec![
+            ReadinessCheck::new("startup", Ok(())),
+            ReadinessCheck::new("storage", Ok(())),
+        ]);
+        assert!(response.ready);
+
+        let response = ReadinessResponse::new(vec![
+            ReadinessCheck::new("startup", Ok(())),
+            ReadinessCheck::new("storage", Err("not a directory".to_string())),
+        ]);
+        assert!(!response.ready);
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(
+            json["checks"][0],
+            serde_json::json!({ "name": "startup", "ok": true })
+        );
+        assert_eq!(json["checks"][1]["error"], "not a directory");
+    }
+}
@@ -0,0 +1,70 @@
+use super::dto::{LivenessResponse, ReadinessCheck, ReadinessResponse};
+use crate::api::AppState;
+use crate::health::health;
+use axum::{extract::State, http::StatusCode, response::Json};
+use std::path::Path;
+
+/// HTTP handler for GET /healthz
+///
+/// Liveness probe: answers 200 while the server runs, without touching
+/// storage or config.
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    tag = "health",
+    summary = "Liveness probe",
+    responses((status = 200, description = "The process is alive", body = LivenessResponse))
+)]
+pub(crate) async fn get_healthz() -> Json<LivenessResponse> {
+    Json(LivenessResponse {
+        status: "ok",
+        uptime_seconds: health().uptime_seconds(),
+    })
+}
+
+/// HTTP handler for GET /readyz
+///
+/// Readiness probe: 200 once storage is open and the ping tasks run, the
+/// config is readable and the data directory exists; 503 otherwise, and
+/// from the moment shutdown begins.
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "health",
+    summary = "Readiness probe",
+    responses(
+        (status = 200, description = "Ready to serve", body = ReadinessResponse),
+        (status = 503, description = "Starting, shutting down or unhealthy", body = ReadinessResponse),
+    )
+)]
+pub(crate) async fn get_readyz(
+    State(state): State<AppState>,
+) -> (StatusCode, Json<ReadinessResponse>) {
+    let startup = if health().is_ready() {
+        Ok(())
+    } else {
+        Err("Not started or shutting down".to_string())
+    };
+    let data_path = state
+        .config
+        .read()
+        .map(|c| c.database.data_path().to_string())
+        .map_err(|e| format!("Failed to read configuration: {}", e));
+    let storage = match &data_path {
+        Ok(path) if Path::new(path).is_dir() => Ok(()),
+        Ok(path) => Err(format!("Data directory {} is missing", path)),
+        Err(_) => Err("Data directory unknown".to_string()),
+    };
+
+    let response = ReadinessResponse::new(vec![
+        ReadinessCheck::new("startup", startup),
+        ReadinessCheck::new("config", data_path.map(|_| ())),
+        ReadinessCheck::new("storage", storage),
+    ]);
+    let status = if response.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(response))
+}
@@ -0,0 +1,348 @@
+//! Validation of externally submitted ping results.
+//!
+//! A batch is accepted or rejected as a whole. Accepted results are stored
+//! like results of SparkPing's own ping tasks, plus a `source = "ingest"`
+//! label and the probe's own labels, so they show up in all queries and
+//! dashboards.
+
+use super::dto::IngestResult;
+use crate::config::{validate_label_name, ProbeType, Target};
+use crate::ping::PingResult;
+use crate::storage::{is_valid_site, ping_result_row, SITE_LABEL};
+use chrono::DateTime;
+use tsink::{Label, Row};
+
+/// Label marking results written through the ingest API
+pub const SOURCE_LABEL: &str = "source";
+
+const MAX_TARGET_ID_LEN: usize = 128;
+const MAX_LABELS: usize = 8;
+const MAX_LABEL_NAME_LEN: usize = 64;
+const MAX_LABEL_VALUE_LEN: usize = 256;
+/// Latencies above this are treated as garbage rather than a slow reply
+const MAX_LATENCY_MS: f64 = 60_000.0;
+/// Allowed clock difference between a probe and SparkPing
+const MAX_CLOCK_SKEW_SECS: i64 = 60;
+
+/// Validation failure of a single result in a batch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IngestError {
+    /// Position of the result in the batch
+    pub index: usize,
+    pub field: &'static str,
+    pub message: String,
+}
+
+impl IngestError {
+    fn new(index: usize, field: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            index,
+            field,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for IngestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "results[{}].{}: {}",
+            self.index, self.field, self.message
+        )
+    }
+}
+
+impl std::error::Error for IngestError {}
+
+/// Validated batch, ready to be written
+#[derive(Debug)]
+pub struct PreparedBatch {
+    /// Results in submission order, for rollups and the live feed
+    pub results: Vec<PingResult>,
+    pub rows: Vec<Row>,
+}
+
+/// Validate `batch` and convert it into storage rows.
+/// Timestamps must lie within `[now - max_age, now + MAX_CLOCK_SKEW_SECS]`.
+pub fn prepare_batch(
+    batch: &[IngestResult],
+    targets: &[Target],
+    now: i64,
+    max_age: u64,
+) -> Result<PreparedBatch, IngestError> {
+    let oldest = now.saturating_sub(max_age.min(i64::MAX as u64) as i64);
+    let mut results = Vec::with_capacity(batch.len());
+    let mut rows = Vec::with_capacity(batch.len());
+
+    for (index, item) in batch.iter().enumerate() {
+        validate_result(index, item, oldest, now)?;
+
+        let target = targets.iter().find(|t| t.id == item.target_id);
+        let timestamp = DateTime::from_timestamp(item.timestamp, 0)
+            .ok_or_else(|| IngestError::new(index, "timestamp", "Timestamp is out of range"))?;
+        let result = PingResult {
+            timestamp,
+            target_id: item.target_id.clone(),
+            // Unconfigured targets use their ID as address, like storage stats
+            target: target.map_or_else(|| item.target_id.clone(), |t| t.address.clone()),
+            target_name: target.and_then(|t| t.name.clone()),
+            sequence: item.sequence.unwrap_or(0),
+            resolved_ip: None,
+            probe_type: target.map_or(ProbeType::Icmp, |t| t.probe_type),
+            port: target.and_then(|t| t.tcp_port()),
+            success: !item.failed,
+            latency_ms: if item.failed { None } else { item.latency_ms },
+            correction_ms: None,
+            flow: None,
+        };
+
+        let mut labels = vec![Label::new(SOURCE_LABEL, "ingest")];
+        labels.extend(
+            item.labels
+                .iter()
+                .map(|(name, value)| Label::new(name, value)),
+        );
+        rows.push(ping_result_row(&result, labels));
+        results.push(result);
+    }
+
+    Ok(PreparedBatch { results, rows })
+}
+
+fn validate_result(
+    index: usize,
+    item: &IngestResult,
+    oldest: i64,
+    now: i64,
+) -> Result<(), IngestError> {
+    if item.target_id.is_empty() || item.target_id.len() > MAX_TARGET_ID_LEN {
+        return Err(IngestError::new(
+            index,
+            "target_id",
+            format!("Target ID must be 1-{} characters", MAX_TARGET_ID_LEN),
+        ));
+    }
+
+    if item.timestamp < oldest {
+        return Err(IngestError::new(
+            index,
+            "timestamp",
+            format!("Timestamp is older than {} seconds", now - oldest),
+        ));
+    }
+    if item.timestamp > now + MAX_CLOCK_SKEW_SECS {
+        return Err(IngestError::new(
+            index,
+            "timestamp",
+            "Timestamp is in the future",
+        ));
+    }
+
+    match (item.failed, item.latency_ms) {
+        (true, Some(_)) => {
+            return Err(IngestError::new(
+                index,
+                "latency_ms",
+                "Failed results must not have a latency",
+            ));
+        }
+        (false, None) => {
+            return Err(IngestError::new(
+                index,
+                "latency_ms",
+                "Latency is required unless the result failed",
+            ));
+        }
+        (false, Some(latency)) if !(0.0..=MAX_LATENCY_MS).contains(&latency) => {
+            return Err(IngestError::new(
+                index,
+                "latency_ms",
+                format!("Latency must be between 0 and {} ms", MAX_LATENCY_MS),
+            ));
+        }
+        _ => {}
+    }
+
+    if item.labels.len() > MAX_LABELS {
+        return Err(IngestError::new(
+            index,
+            "labels",
+            format!("At most {} labels are allowed", MAX_LABELS),
+        ));
+    }
+    for (name, value) in &item.labels {
+        if name.len() > MAX_LABEL_NAME_LEN {
+            return Err(IngestError::new(
+                index,
+                "labels",
+                format!("Label name '{}' exceeds {} bytes", name, MAX_LABEL_NAME_LEN),
+            ));
+        }
+        // Agents label their results with their site, the one reserved
+        // label a probe may set
+        if name == SITE_LABEL {
+            if !is_valid_site(value) {
+                return Err(IngestError::new(
+                    index,
+                    "labels",
+                    format!("Site '{}' is reserved", value),
+                ));
+            }
+        } else {
+            validate_label_name(name)
+                .map_err(|message| IngestError::new(index, "labels", message))?;
+        }
+        if value.len() > MAX_LABEL_VALUE_LEN {
+            return Err(IngestError::new(
+                index,
+                "labels",
+                format!(
+                    "Value of label '{}' exceeds {} bytes",
+                    name, MAX_LABEL_VALUE_LEN
+                ),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Compare a bearer token without leaking the matching prefix length
+pub fn token_matches(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn result(target_id: &str, timestamp: i64, latency_ms: Option<f64>) -> IngestResult {
+        IngestResult {
+            target_id: target_id.to_string(),
+            timestamp,
+            latency_ms,
+            failed: latency_ms.is_none(),
+            sequence: None,
+            labels: BTreeMap::new(),
+        }
+    }
+
+    fn router() -> Target {
+        Target {
+            id: "router".to_string(),
+            address: "192.168.1.1".to_string(),
+            name: Some("Router".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_prepare_batch_writes_labeled_rows() {
+        let mut with_label = result("router", NOW - 10, Some(4.2));
+        with_label
+            .labels
+            .insert("probe".to_string(), "busybox".to_string());
+        let batch = vec![with_label, result("wan", NOW - 5, None)];
+
+        let prepared = prepare_batch(&batch, &[router()], NOW, 3600).unwrap();
+        assert_eq!(prepared.results.len(), 2);
+        assert_eq!(prepared.results[0].target, "192.168.1.1");
+        assert_eq!(prepared.results[0].target_name.as_deref(), Some("Router"));
+        assert_eq!(prepared.results[1].target, "wan");
+        assert!(!prepared.results[1].success);
+
+        let storage = crate::storage::memory_storage();
+        storage.insert_rows(&prepared.rows).unwrap();
+
+        let latency = storage.select_all("ping_latency", 0, NOW + 1).unwrap();
+        assert_eq!(latency.len(), 1);
+        let (labels, points) = &latency[0];
+        let label = |name: &str| {
+            labels
+                .iter()
+                .find(|l| l.name == name)
+                .map(|l| l.value.as_str())
+        };
+        assert_eq!(label("target_id"), Some("router"));
+        assert_eq!(label("source"), Some("ingest"));
+        assert_eq!(label("probe"), Some("busybox"));
+        assert_eq!(points[0].value, 4.2);
+
+        let failed = storage.select_all("ping_failed", 0, NOW + 1).unwrap();
+        assert_eq!(failed.len(), 1);
+    }
+
+    #[test]
+    fn test_prepare_batch_rejects_invalid_results() {
+        let check = |item: IngestResult| prepare_batch(&[item], &[], NOW, 3600).unwrap_err();
+
+        assert_eq!(check(result("", NOW, Some(1.0))).field, "target_id");
+        assert_eq!(
+            check(result("wan", NOW - 7200, Some(1.0))).field,
+            "timestamp"
+        );
+        assert_eq!(
+            check(result("wan", NOW + 600, Some(1.0))).field,
+            "timestamp"
+        );
+        assert_eq!(check(result("wan", NOW, Some(-1.0))).field, "latency_ms");
+        assert_eq!(
+            check(result("wan", NOW, Some(f64::NAN))).field,
+            "latency_ms"
+        );
+
+        let mut missing_latency = result("wan", NOW, None);
+        missing_latency.failed = false;
+        assert_eq!(check(missing_latency).field, "latency_ms");
+
+        let mut reserved = result("wan", NOW, Some(1.0));
+        reserved
+            .labels
+            .insert("target_id".to_string(), "other".to_string());
+        assert_eq!(check(reserved).field, "labels");
+
+        for name in ["flow", "resolution", "window", "source"] {
+            let mut forged = result("wan", NOW, Some(1.0));
+            forged.labels.insert(name.to_string(), "x".to_string());
+            assert_eq!(check(forged).field, "labels");
+        }
+
+        let mut invalid_name = result("wan", NOW, Some(1.0));
+        invalid_name
+            .labels
+            .insert("1st-hop".to_string(), "x".to_string());
+        assert_eq!(check(invalid_name).field, "labels");
+
+        let mut reserved_site = result("wan", NOW, Some(1.0));
+        reserved_site
+            .labels
+            .insert(SITE_LABEL.to_string(), "local".to_string());
+        assert_eq!(check(reserved_site).field, "labels");
+
+        // The index points at the offending result
+        let batch = vec![result("wan", NOW, Some(1.0)), result("", NOW, Some(1.0))];
+        let error = prepare_batch(&batch, &[], NOW, 3600).unwrap_err();
+        assert_eq!(error.index, 1);
+        assert_eq!(
+            error.to_string(),
+            "results[1].target_id: Target ID must be 1-128 characters"
+        );
+    }
+
+    #[test]
+    fn test_token_matches() {
+        assert!(token_matches("secret", "secret"));
+        assert!(!token_matches("secreT", "secret"));
+        assert!(!token_matches("secret2", "secret"));
+        assert!(!token_matches("", "secret"));
+    }
+}
@@ -0,0 +1,31 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A single ping result sent by an external probe (or pushed by an agent)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct IngestResult {
+    /// Target the result belongs to; configured targets contribute their
+    /// address, name, and probe type
+    pub target_id: String,
+    /// Unix timestamp in seconds
+    pub timestamp: i64,
+    /// Round-trip latency in milliseconds, required unless `failed`
+    #[serde(default)]
+    pub latency_ms: Option<f64>,
+    /// True if the probe got no reply
+    #[serde(default)]
+    pub failed: bool,
+    /// Sequence number within the probe's batch (default: 0)
+    #[serde(default)]
+    pub sequence: Option<u16>,
+    /// Extra labels stored with the result (e.g. `probe = "router"`)
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+/// API response for POST /api/ingest/batch
+#[derive(Debug, Serialize)]
+pub struct IngestBatchResponse {
+    /// Number of results written
+    pub accepted: usize,
+}
@@ -0,0 +1,145 @@
+use super::batch::{prepare_batch, token_matches};
+use super::dto::{IngestBatchResponse, IngestResult};
+use crate::api::error::{ApiError, ErrorCode};
+use crate::api::extract::Json;
+use crate::api::AppState;
+use crate::health::health;
+use crate::storage::SITE_LABEL;
+use crate::telemetry::timed_write;
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{header, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::Response;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+
+/// Require `Authorization: Bearer <[ingest] token>` on ingest routes.
+///
+/// Runs before the body is parsed, so unauthenticated requests are rejected
+/// without reading their payload.
+pub(crate) async fn ingest_auth_middleware(
+    State(state): State<AppState>,
+    req: Request<Body>,
+    next: Next,
+) -> Result<Response, ApiError> {
+    // Read the token per request so config hot reloads apply immediately
+    let expected = state
+        .config
+        .read()
+        .ok()
+        .and_then(|c| c.ingest.token.clone())
+        .filter(|t| !t.is_empty())
+        .ok_or_else(|| {
+            ApiError::new(
+                StatusCode::FORBIDDEN,
+                ErrorCode::Forbidden,
+                "Ingest API is disabled (set [ingest] token)",
+            )
+        })?;
+
+    let given = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| {
+            let (scheme, token) = h.split_once(' ')?;
+            scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+        });
+
+    match given {
+        Some(token) if token_matches(token, &expected) => Ok(next.run(req).await),
+        _ => {
+            warn!("Rejected ingest request with missing or invalid token");
+            Err(ApiError::new(
+                StatusCode::UNAUTHORIZED,
+                ErrorCode::Unauthorized,
+                "Missing or invalid ingest token",
+            ))
+        }
+    }
+}
+
+/// HTTP handler for POST /api/ingest/batch
+///
+/// Validates and stores ping results from external probes. The batch is
+/// written only if every result is valid.
+pub(crate) async fn ingest_batch(
+    State(state): State<AppState>,
+    Json(batch): Json<Vec<IngestResult>>,
+) -> Result<Json<IngestBatchResponse>, ApiError> {
+    let (targets, ingest) = {
+        let config = state.config.read().map_err(|e| {
+            error!("Failed to read config: {}", e);
+            ApiError::internal(ErrorCode::ConfigUnavailable, "Failed to read configuration")
+        })?;
+        (config.targets.clone(), config.ingest.clone())
+    };
+
+    if batch.is_empty() {
+        return Err(ApiError::bad_request(
+            ErrorCode::InvalidRequest,
+            "Batch must contain at least one result",
+        ));
+    }
+    if batch.len() > ingest.max_batch {
+        return Err(ApiError::bad_request(
+            ErrorCode::InvalidRequest,
+            format!(
+                "Batch of {} results exceeds the limit of {}",
+                batch.len(),
+                ingest.max_batch
+            ),
+        )
+        .with_details(serde_json::json!({ "max_batch": ingest.max_batch })));
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let prepared = prepare_batch(&batch, &targets, now, ingest.max_age).map_err(|e| {
+        ApiError::bad_request(ErrorCode::InvalidRequest, e.to_string())
+            .with_details(serde_json::json!({ "index": e.index, "field": e.field }))
+    })?;
+
+    let storage = Arc::clone(&state.storage);
+    let writer = Arc::clone(&state.writer);
+    let rows = prepared.rows;
+    tokio::task::spawn_blocking(move || {
+        timed_write(|| storage.insert_rows(&rows)).map_err(|e| e.to_string())?;
+        writer.aggregate_cache().invalidate(&rows);
+        Ok::<_, String>(())
+    })
+    .await
+    .map_err(|e| {
+        error!("Task join error: {}", e);
+        ApiError::internal(ErrorCode::Internal, e.to_string())
+    })?
+    .map_err(|e| {
+        error!("Error writing ingested results: {}", e);
+        health().record_write_error(chrono::Utc::now().timestamp());
+        ApiError::internal(ErrorCode::StorageError, e)
+    })?;
+
+    // Only configured targets have rollups; results of others are only
+    // stored. Results an agent measured from its site would blend into the
+    // rollups and live results of this server's own pings, so they are only
+    // published for clients selecting their site.
+    for (item, result) in batch.iter().zip(&prepared.results) {
+        if let Some(site) = item.labels.get(SITE_LABEL) {
+            state.live.publish_from_site(result, site);
+            continue;
+        }
+        if targets.iter().any(|t| t.id == result.target_id) {
+            state.rollups.record(
+                &result.target_id,
+                result.timestamp.timestamp(),
+                result.latency_ms,
+            );
+        }
+        state.live.publish(result);
+    }
+
+    info!("Ingested {} results", prepared.results.len());
+    Ok(Json(IngestBatchResponse {
+        accepted: prepared.results.len(),
+    }))
+}
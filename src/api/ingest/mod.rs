@@ -0,0 +1,3 @@
+pub mod batch;
+pub mod dto;
+pub mod handlers;
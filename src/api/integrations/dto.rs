@@ -0,0 +1,13 @@
+use crate::home_assistant::HaDevice;
+use serde::Serialize;
+
+/// A Home Assistant device offered as a ping target suggestion
+#[derive(Debug, Serialize)]
+pub struct HaDeviceSuggestion {
+    #[serde(flatten)]
+    pub device: HaDevice,
+    /// Address to prefill when adding the device as a target
+    pub suggested_address: String,
+    /// Whether any of the device's addresses is already a configured target
+    pub already_monitored: bool,
+}
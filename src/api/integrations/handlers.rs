@@ -0,0 +1,68 @@
+use super::dto::HaDeviceSuggestion;
+use crate::api::error::{ApiError, ErrorCode};
+use crate::api::AppState;
+use crate::home_assistant::{fetch_devices, HaConnection};
+use axum::{extract::State, http::StatusCode, response::Json};
+use std::collections::HashSet;
+use tracing::{error, info};
+
+/// HTTP handler for GET /api/integrations/ha/devices
+///
+/// Lists Home Assistant devices with IP addresses as ping target suggestions.
+/// Uses `[home_assistant]` config or the Supervisor token when running as an
+/// add-on.
+pub(crate) async fn get_ha_devices(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<HaDeviceSuggestion>>, ApiError> {
+    let (ha_config, monitored) = {
+        let config = state.config.read().map_err(|e| {
+            error!("Failed to read config: {}", e);
+            ApiError::internal(ErrorCode::ConfigUnavailable, "Failed to read configuration")
+        })?;
+        let monitored: HashSet<String> = config.targets.iter().map(|t| t.address.clone()).collect();
+        (config.home_assistant.clone(), monitored)
+    };
+
+    let connection = HaConnection::resolve(&ha_config).ok_or_else(|| {
+        ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::IntegrationNotConfigured,
+            "Home Assistant is not configured (set [home_assistant] url and token)",
+        )
+    })?;
+
+    let devices = fetch_devices(&connection).await.map_err(|e| {
+        error!("Failed to fetch Home Assistant devices: {}", e);
+        ApiError::new(
+            StatusCode::BAD_GATEWAY,
+            ErrorCode::IntegrationError,
+            format!("Failed to fetch Home Assistant devices: {}", e),
+        )
+    })?;
+    info!(
+        "Found {} Home Assistant devices with IP addresses",
+        devices.len()
+    );
+
+    let suggestions = devices
+        .into_iter()
+        .map(|device| {
+            let already_monitored = device.addresses.iter().any(|a| monitored.contains(a));
+            // Prefer an address that is not monitored yet
+            let suggested_address = device
+                .addresses
+                .iter()
+                .find(|a| !monitored.contains(*a))
+                .or(device.addresses.first())
+                .cloned()
+                .unwrap_or_default();
+            HaDeviceSuggestion {
+                device,
+                suggested_address,
+                already_monitored,
+            }
+        })
+        .collect();
+
+    Ok(Json(suggestions))
+}
@@ -0,0 +1,457 @@
+use crate::api::ping::dto::StorageStatsResponse;
+use crate::config::Target;
+use crate::rollups::{TargetRollups, WindowStats};
+use crate::telemetry::{HistogramSnapshot, TelemetrySnapshot};
+use crate::unified_discovery::DiscoveryStreamSnapshot;
+use std::fmt::Write;
+
+/// Escape a label value for the Prometheus text exposition format
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Format a label set, e.g. `{target_id="a",target="1.1.1.1"}`
+fn format_labels(labels: &[(&str, &str)]) -> String {
+    let inner: Vec<String> = labels
+        .iter()
+        .map(|(name, value)| format!("{}=\"{}\"", name, escape_label_value(value)))
+        .collect();
+    format!("{{{}}}", inner.join(","))
+}
+
+/// A metric family with its samples (name suffix, labels, value)
+struct Family {
+    name: &'static str,
+    help: &'static str,
+    kind: &'static str,
+    samples: Vec<(&'static str, String, f64)>,
+}
+
+impl Family {
+    fn new(name: &'static str, kind: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            kind,
+            samples: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, labels: &[(&str, &str)], value: f64) {
+        self.samples.push(("", format_labels(labels), value));
+    }
+
+    /// Add the `_bucket`, `_sum` and `_count` samples of a histogram
+    fn add_histogram(&mut self, labels: &[(&str, &str)], histogram: &HistogramSnapshot) {
+        let buckets = histogram
+            .buckets
+            .iter()
+            .map(|b| (b.le.to_string(), b.count))
+            .chain(std::iter::once(("+Inf".to_string(), histogram.count)));
+        for (le, count) in buckets {
+            let mut bucket_labels = labels.to_vec();
+            bucket_labels.push(("le", &le));
+            self.samples
+                .push(("_bucket", format_labels(&bucket_labels), count as f64));
+        }
+        self.samples
+            .push(("_sum", format_labels(labels), histogram.sum_seconds));
+        self.samples
+            .push(("_count", format_labels(labels), histogram.count as f64));
+    }
+
+    fn write_to(&self, out: &mut String) {
+        if self.samples.is_empty() {
+            return;
+        }
+        // Writing to a String cannot fail
+        let _ = writeln!(out, "# HELP {} {}", self.name, self.help);
+        let _ = writeln!(out, "# TYPE {} {}", self.name, self.kind);
+        for (suffix, labels, value) in &self.samples {
+            let _ = writeln!(out, "{}{}{} {}", self.name, suffix, labels, value);
+        }
+    }
+}
+
+fn window_label(stats: &WindowStats) -> &'static str {
+    match stats.window_seconds {
+        60 => "1m",
+        300 => "5m",
+        _ => "1h",
+    }
+}
+
+/// The labels set on a target in the config
+fn custom_labels(target: &Target) -> impl Iterator<Item = (&str, &str)> {
+    target
+        .labels
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+}
+
+/// Render per-target ping metrics, storage stats, discovery stream
+/// backpressure and the daemon's self-metrics in Prometheus text format
+pub(super) fn render_metrics(
+    targets: &[(Target, Option<TargetRollups>)],
+    storage_stats: Option<&StorageStatsResponse>,
+    discovery: &DiscoveryStreamSnapshot,
+    telemetry: &TelemetrySnapshot,
+) -> String {
+    let mut success_total = Family::new(
+        "sparkping_ping_success_total",
+        "counter",
+        "Successful pings since SparkPing started",
+    );
+    let mut failure_total = Family::new(
+        "sparkping_ping_failure_total",
+        "counter",
+        "Failed pings since SparkPing started",
+    );
+    let mut up = Family::new(
+        "sparkping_ping_up",
+        "gauge",
+        "Whether the most recent ping succeeded (1) or failed (0)",
+    );
+    let mut last_latency = Family::new(
+        "sparkping_ping_last_latency_seconds",
+        "gauge",
+        "Round-trip time of the most recent successful ping",
+    );
+    let mut avg_latency = Family::new(
+        "sparkping_ping_latency_avg_seconds",
+        "gauge",
+        "Average round-trip time over a rolling window",
+    );
+    let mut min_latency = Family::new(
+        "sparkping_ping_latency_min_seconds",
+        "gauge",
+        "Minimum round-trip time over a rolling window",
+    );
+    let mut max_latency = Family::new(
+        "sparkping_ping_latency_max_seconds",
+        "gauge",
+        "Maximum round-trip time over a rolling window",
+    );
+    let mut success_ratio = Family::new(
+        "sparkping_ping_success_ratio",
+        "gauge",
+        "Share of successful pings (0-1) over a rolling window",
+    );
+    let mut storage_bytes = Family::new(
+        "sparkping_storage_size_bytes",
+        "gauge",
+        "On-disk size of stored ping data per target",
+    );
+    let mut storage_points = Family::new(
+        "sparkping_storage_data_points",
+        "gauge",
+        "Number of stored data points per target",
+    );
+    let mut storage_total = Family::new(
+        "sparkping_storage_total_size_bytes",
+        "gauge",
+        "On-disk size of all stored ping data",
+    );
+    let mut discovery_streams = Family::new(
+        "sparkping_discovery_streams_active",
+        "gauge",
+        "Number of open discovery event streams",
+    );
+    let mut discovery_dropped = Family::new(
+        "sparkping_discovery_events_dropped_total",
+        "counter",
+        "Discovery device updates dropped because a client was not keeping up",
+    );
+    let mut discovery_queue_peak = Family::new(
+        "sparkping_discovery_queue_depth_peak",
+        "gauge",
+        "Highest number of queued events seen on a discovery stream",
+    );
+    let mut pings_performed = Family::new(
+        "sparkping_pings_performed_total",
+        "counter",
+        "Pings sent by all ping tasks since SparkPing started",
+    );
+    let mut ping_task_restarts = Family::new(
+        "sparkping_ping_task_restarts_total",
+        "counter",
+        "Crashed or stalled ping tasks restarted by the supervisor since SparkPing started",
+    );
+    let mut storage_write_duration = Family::new(
+        "sparkping_storage_write_duration_seconds",
+        "histogram",
+        "Duration of ping result writes to storage",
+    );
+    let mut api_request_duration = Family::new(
+        "sparkping_api_request_duration_seconds",
+        "histogram",
+        "Duration of API requests per method and route",
+    );
+    let mut discovery_run_duration = Family::new(
+        "sparkping_discovery_run_duration_seconds",
+        "histogram",
+        "Duration of finished device discovery runs",
+    );
+
+    for (target, rollups) in targets {
+        let name = target.name.as_deref().unwrap_or("");
+        let mut labels = vec![
+            ("target_id", target.id.as_str()),
+            ("target", target.address.as_str()),
+            ("target_name", name),
+        ];
+        labels.extend(custom_labels(target));
+
+        let Some(rollups) = rollups else {
+            continue;
+        };
+
+        success_total.add(&labels, rollups.total_successful_count as f64);
+        failure_total.add(&labels, rollups.total_failed_count as f64);
+        if let Some(success) = rollups.last_success {
+            up.add(&labels, if success { 1.0 } else { 0.0 });
+        }
+        if let Some(latency) = rollups.last_latency_ms {
+            last_latency.add(&labels, latency / 1000.0);
+        }
+
+        for stats in [
+            &rollups.one_minute,
+            &rollups.five_minutes,
+            &rollups.one_hour,
+        ] {
+            let mut labels = labels.clone();
+            labels.push(("window", window_label(stats)));
+            if let Some(avg) = stats.avg_latency_ms {
+                avg_latency.add(&labels, avg / 1000.0);
+            }
+            if let Some(min) = stats.min_latency_ms {
+                min_latency.add(&labels, min / 1000.0);
+            }
+            if let Some(max) = stats.max_latency_ms {
+                max_latency.add(&labels, max / 1000.0);
+            }
+            if stats.count > 0 {
+                success_ratio.add(&labels, stats.successful_count as f64 / stats.count as f64);
+            }
+        }
+    }
+
+    if let Some(stats) = storage_stats {
+        for target_stats in &stats.targets {
+            // Label storage series like ping series when the target still exists
+            let target = targets
+                .iter()
+                .map(|(t, _)| t)
+                .find(|t| t.id == target_stats.target_id);
+            let mut labels = vec![
+                ("target_id", target_stats.target_id.as_str()),
+                ("target", target.map(|t| t.address.as_str()).unwrap_or("")),
+                (
+                    "target_name",
+                    target.and_then(|t| t.name.as_deref()).unwrap_or(""),
+                ),
+            ];
+            labels.extend(target.into_iter().flat_map(custom_labels));
+            storage_bytes.add(&labels, target_stats.size_bytes as f64);
+            storage_points.add(&labels, target_stats.data_point_count as f64);
+        }
+        storage_total.add(&[], stats.total_size_bytes as f64);
+    }
+
+    discovery_streams.add(&[], discovery.active_streams as f64);
+    discovery_dropped.add(&[], discovery.dropped_events as f64);
+    discovery_queue_peak.add(&[], discovery.peak_queue_depth as f64);
+
+    pings_performed.add(&[("result", "success")], telemetry.pings_succeeded as f64);
+    pings_performed.add(&[("result", "failure")], telemetry.pings_failed as f64);
+    ping_task_restarts.add(&[], telemetry.ping_task_restarts as f64);
+    storage_write_duration.add_histogram(&[], &telemetry.storage_write_latency);
+    for route in &telemetry.api_requests {
+        api_request_duration.add_histogram(
+            &[
+                ("method", route.method.as_str()),
+                ("route", route.route.as_str()),
+            ],
+            &route.latency,
+        );
+    }
+    discovery_run_duration.add_histogram(&[], &telemetry.discovery_runs);
+
+    let mut out = String::new();
+    for family in [
+        &success_total,
+        &failure_total,
+        &up,
+        &last_latency,
+        &avg_latency,
+        &min_latency,
+        &max_latency,
+        &success_ratio,
+        &storage_bytes,
+        &storage_points,
+        &storage_total,
+        &discovery_streams,
+        &discovery_dropped,
+        &discovery_queue_peak,
+        &pings_performed,
+        &ping_task_restarts,
+        &storage_write_duration,
+        &api_request_duration,
+        &discovery_run_duration,
+    ] {
+        family.write_to(&mut out);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rollups::RollingAggregator;
+    use crate::telemetry::{HistogramBucket, RouteLatency};
+
+    fn histogram(buckets: &[(f64, u64)], count: u64, sum_seconds: f64) -> HistogramSnapshot {
+        HistogramSnapshot {
+            count,
+            sum_seconds,
+            buckets: buckets
+                .iter()
+                .map(|&(le, count)| HistogramBucket { le, count })
+                .collect(),
+        }
+    }
+
+    fn telemetry() -> TelemetrySnapshot {
+        TelemetrySnapshot {
+            pings_succeeded: 5,
+            pings_failed: 1,
+            ping_task_restarts: 2,
+            storage_write_latency: histogram(&[(0.01, 0)], 0, 0.0),
+            api_requests: vec![RouteLatency {
+                method: "GET".to_string(),
+                route: "/api/targets/:id".to_string(),
+                latency: histogram(&[(0.01, 1), (0.1, 2)], 3, 2.055),
+            }],
+            discovery_runs: histogram(&[(1.0, 0)], 0, 0.0),
+        }
+    }
+
+    fn target(id: &str, name: Option<&str>) -> Target {
+        Target {
+            id: id.to_string(),
+            address: "192.168.1.1".to_string(),
+            name: name.map(|n| n.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_escape_label_value() {
+        assert_eq!(escape_label_value(r#"a"b\c"#), r#"a\"b\\c"#);
+        assert_eq!(escape_label_value("line\nbreak"), "line\\nbreak");
+    }
+
+    #[test]
+    fn test_render_metrics() {
+        let aggregator = RollingAggregator::new();
+        aggregator.record("router", 100, Some(12.0));
+        aggregator.record("router", 101, None);
+
+        let targets = vec![
+            (
+                target("router", Some("Home \"Router\"")),
+                aggregator.rollups("router", 101),
+            ),
+            (target("idle", None), None),
+        ];
+        let discovery = DiscoveryStreamSnapshot {
+            active_streams: 1,
+            dropped_events: 7,
+            peak_queue_depth: 100,
+        };
+        let output = render_metrics(&targets, None, &discovery, &telemetry());
+
+        assert!(output.contains("# TYPE sparkping_ping_success_total counter"));
+        assert!(output.contains(
+            r#"sparkping_ping_success_total{target_id="router",target="192.168.1.1",target_name="Home \"Router\""} 1"#
+        ));
+        assert!(output.contains(
+            r#"sparkping_ping_up{target_id="router",target="192.168.1.1",target_name="Home \"Router\""} 0"#
+        ));
+        assert!(output.contains(
+            r#"sparkping_ping_success_ratio{target_id="router",target="192.168.1.1",target_name="Home \"Router\"",window="1m"} 0.5"#
+        ));
+        assert!(
+            output.contains("sparkping_ping_last_latency_seconds{") && output.contains("} 0.012")
+        );
+        // Targets without results and missing storage stats produce no series
+        assert!(!output.contains("idle"));
+        assert!(!output.contains("sparkping_storage"));
+        assert!(output.contains("sparkping_discovery_streams_active{} 1"));
+        assert!(output.contains("sparkping_discovery_events_dropped_total{} 7"));
+        assert!(output.contains("sparkping_discovery_queue_depth_peak{} 100"));
+        assert!(output.contains(r#"sparkping_pings_performed_total{result="success"} 5"#));
+        assert!(output.contains("sparkping_ping_task_restarts_total{} 2"));
+    }
+
+    #[test]
+    fn test_render_target_labels() {
+        let aggregator = RollingAggregator::new();
+        aggregator.record("router", 100, Some(12.0));
+        let mut router = target("router", None);
+        router
+            .labels
+            .insert("location".to_string(), "basement".to_string());
+
+        let discovery = DiscoveryStreamSnapshot {
+            active_streams: 0,
+            dropped_events: 0,
+            peak_queue_depth: 0,
+        };
+        let output = render_metrics(
+            &[(router, aggregator.rollups("router", 100))],
+            None,
+            &discovery,
+            &telemetry(),
+        );
+
+        assert!(output.contains(
+            r#"sparkping_ping_up{target_id="router",target="192.168.1.1",target_name="",location="basement"} 1"#
+        ));
+        assert!(output.contains(
+            r#"sparkping_ping_success_ratio{target_id="router",target="192.168.1.1",target_name="",location="basement",window="1m"} 1"#
+        ));
+    }
+
+    #[test]
+    fn test_render_histograms() {
+        let discovery = DiscoveryStreamSnapshot {
+            active_streams: 0,
+            dropped_events: 0,
+            peak_queue_depth: 0,
+        };
+        let output = render_metrics(&[], None, &discovery, &telemetry());
+
+        assert!(output.contains("# TYPE sparkping_api_request_duration_seconds histogram"));
+        assert!(output.contains(
+            r#"sparkping_api_request_duration_seconds_bucket{method="GET",route="/api/targets/:id",le="0.01"} 1"#
+        ));
+        assert!(output.contains(
+            r#"sparkping_api_request_duration_seconds_bucket{method="GET",route="/api/targets/:id",le="0.1"} 2"#
+        ));
+        assert!(output.contains(
+            r#"sparkping_api_request_duration_seconds_bucket{method="GET",route="/api/targets/:id",le="+Inf"} 3"#
+        ));
+        assert!(output.contains(
+            r#"sparkping_api_request_duration_seconds_sum{method="GET",route="/api/targets/:id"} 2.055"#
+        ));
+        assert!(output.contains(
+            r#"sparkping_api_request_duration_seconds_count{method="GET",route="/api/targets/:id"} 3"#
+        ));
+        assert!(output.contains("sparkping_storage_write_duration_seconds_count{} 0"));
+    }
+}
@@ -0,0 +1,109 @@
+use super::exposition::render_metrics;
+use crate::api::error::{ApiError, ErrorCode};
+use crate::api::extract::Query;
+use crate::api::ping::labels::LabelFilter;
+use crate::api::AppState;
+use crate::telemetry::telemetry;
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::{error, warn};
+
+/// Query parameters of the metrics endpoint
+#[derive(Debug, Deserialize)]
+pub(crate) struct MetricsQuery {
+    /// Only targets carrying these labels, e.g. "class:critical"
+    label: Option<String>,
+}
+
+/// HTTP handler for GET /metrics
+///
+/// Exposes live per-target ping metrics (from the rolling aggregator) and
+/// storage stats in Prometheus text exposition format. Disabled unless
+/// `[metrics] enabled = true`. Discovery stream backpressure counters are
+/// included so slow discovery clients are visible, as are the daemon's
+/// self-metrics (pings performed, storage write, API request and discovery
+/// run durations). `label` limits the per-target series to targets carrying
+/// the given labels, which are exported with every target's series.
+pub(crate) async fn get_metrics(
+    State(state): State<AppState>,
+    Query(query): Query<MetricsQuery>,
+) -> Result<Response, ApiError> {
+    let filter = query
+        .label
+        .as_deref()
+        .map(LabelFilter::parse)
+        .transpose()
+        .map_err(|e| {
+            ApiError::bad_request(ErrorCode::InvalidRequest, e)
+                .with_details(serde_json::json!({ "field": "label" }))
+        })?;
+
+    let (enabled, targets) = {
+        let config = state.config.read().map_err(|e| {
+            error!("Failed to read config: {}", e);
+            ApiError::internal(ErrorCode::ConfigUnavailable, "Failed to read configuration")
+        })?;
+        (config.metrics.enabled, config.targets.clone())
+    };
+
+    if !enabled {
+        return Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            ErrorCode::InvalidRequest,
+            "Metrics endpoint is disabled (set [metrics] enabled = true)",
+        ));
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let targets: Vec<_> = targets
+        .into_iter()
+        .filter(|t| filter.as_ref().is_none_or(|f| f.matches(t)))
+        .map(|t| {
+            let rollups = state.rollups.rollups(&t.id, now);
+            (t, rollups)
+        })
+        .collect();
+
+    // Storage stats walk partition files on disk, keep them off the async runtime
+    let storage = Arc::clone(&state.storage);
+    let storage_stats = tokio::task::spawn_blocking(move || storage.stats())
+        .await
+        .map_err(|e| {
+            error!("Task join error: {}", e);
+            ApiError::internal(ErrorCode::Internal, e.to_string())
+        })?;
+    let storage_stats = match storage_stats {
+        Ok(mut stats) => {
+            if filter.is_some() {
+                stats
+                    .targets
+                    .retain(|s| targets.iter().any(|(t, _)| t.id == s.target_id));
+            }
+            Some(stats)
+        }
+        Err(e) => {
+            // Still serve ping metrics if storage stats are unavailable
+            warn!("Failed to calculate storage stats for metrics: {}", e);
+            None
+        }
+    };
+
+    Ok((
+        [(
+            header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        render_metrics(
+            &targets,
+            storage_stats.as_ref(),
+            &state.discovery_stats.snapshot(),
+            &telemetry().snapshot(),
+        ),
+    )
+        .into_response())
+}
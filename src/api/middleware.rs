@@ -1,8 +1,16 @@
+use crate::api::error::{ApiError, ErrorCode};
+use crate::api::AppState;
+use crate::auth::Authenticated;
+use crate::config::Role;
+use crate::telemetry::telemetry;
 use axum::body::Body;
-use axum::http::Request;
+use axum::extract::{MatchedPath, State};
+use axum::http::{Method, Request};
 use axum::{extract::ConnectInfo, http::StatusCode, middleware::Next, response::Response};
+use futures::StreamExt;
 use std::net::SocketAddr;
-use tracing::{debug, warn};
+use std::time::Instant;
+use tracing::{debug, error, warn};
 
 /// Home Assistant ingress IP addresses
 /// The ingress gateway can be at either 172.30.32.1 or 172.30.32.2 depending on the setup
@@ -19,14 +27,14 @@ pub(crate) fn check_xff_contains_ingress_ip(xff_header: &str) -> bool {
     xff_header
         .split(',')
         .map(|s| s.trim())
-        .any(|ip| is_allowed_ingress_ip(ip))
+        .any(is_allowed_ingress_ip)
 }
 
 /// Create middleware for Home Assistant ingress IP filtering
 pub(crate) async fn ingress_ip_filter_middleware(
     req: Request<Body>,
     next: Next,
-) -> Result<Response, StatusCode> {
+) -> Result<Response, ApiError> {
     let start = std::time::Instant::now();
 
     // Determine the remote peer IP from the connection info.
@@ -72,7 +80,11 @@ pub(crate) async fn ingress_ip_filter_middleware(
             peer_ip.map(|ip| ip.to_string()),
             forwarded_for
         );
-        return Err(StatusCode::FORBIDDEN);
+        return Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            ErrorCode::Forbidden,
+            "Access is restricted to Home Assistant ingress",
+        ));
     }
 
     let check_elapsed = start.elapsed();
@@ -89,10 +101,280 @@ pub(crate) async fn ingress_ip_filter_middleware(
     Ok(result)
 }
 
+/// Record the latency of routed requests per method and route pattern in the
+/// self-metrics. Streaming responses are timed until their headers are sent.
+pub(crate) async fn request_telemetry_middleware(req: Request<Body>, next: Next) -> Response {
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string());
+    let method = req.method().clone();
+    let start = Instant::now();
+    let response = next.run(req).await;
+    if let Some(route) = route {
+        telemetry().record_api_request(method.as_str(), &route, start.elapsed());
+    }
+    response
+}
+
+/// Routes open to everyone even with `[auth] enabled`. Ingest checks its own
+/// token; health probes carry none.
+fn is_public_route(path: &str) -> bool {
+    path == "/api/auth/login"
+        || path == "/api/auth/status"
+        || path == "/healthz"
+        || path == "/readyz"
+        || path.starts_with("/api/ingest/")
+}
+
+/// Role needed for a request.
+///
+/// Reading needs a viewer and anything that changes state needs an editor.
+/// Admins are needed for managing targets or tokens, discovery scans (which
+/// start on GET) and jobs, pruning, quarantining or compacting storage, and
+/// reading the audit log. Editors may still pause, resume, diagnose and
+/// reorder targets (PATCH) and restart their ping tasks. The Grafana
+/// datasource reads with POST, so its routes only need a viewer.
+pub(crate) fn required_role(method: &Method, path: &str) -> Role {
+    let read_only = matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+        || path.starts_with("/api/grafana");
+
+    if path.starts_with("/api/auth/tokens")
+        || path == "/api/discovery/unified"
+        || path == "/api/storage/prune"
+        || path == "/api/storage/quarantine"
+        || path == "/api/storage/compact"
+        || path == "/api/audit"
+    {
+        return Role::Admin;
+    }
+    if path.starts_with("/api/discovery/jobs") && !read_only {
+        return Role::Admin;
+    }
+    if let Some(rest) = path.strip_prefix("/api/targets") {
+        let editor_action = rest.ends_with("/pause")
+            || rest.ends_with("/resume")
+            || rest.ends_with("/diagnose")
+            || rest.ends_with("/restart")
+            || *method == Method::PATCH;
+        if !read_only && !editor_action {
+            return Role::Admin;
+        }
+    }
+    if read_only || path == "/api/auth/logout" {
+        Role::Viewer
+    } else {
+        Role::Editor
+    }
+}
+
+/// Whether `[server] read_only` refuses a request: everything that needs more
+/// than a viewer, i.e. target and token management, config and storage
+/// operations, tests, scans and ingest. Logging in and validating a config
+/// change nothing.
+pub(crate) fn is_mutating(method: &Method, path: &str) -> bool {
+    path != "/api/auth/login"
+        && path != "/api/config/validate"
+        && required_role(method, path) > Role::Viewer
+}
+
+/// Refuse mutating requests with 403 `read_only` while `[server] read_only`
+/// is set, regardless of who sends them
+pub(crate) async fn read_only_middleware(
+    State(state): State<AppState>,
+    req: Request<Body>,
+    next: Next,
+) -> Result<Response, ApiError> {
+    // Read per request so config hot reloads apply immediately
+    let read_only = state
+        .config
+        .read()
+        .map(|c| c.server.read_only)
+        .map_err(|e| {
+            error!("Failed to read config: {}", e);
+            ApiError::internal(ErrorCode::ConfigUnavailable, "Failed to read configuration")
+        })?;
+    if read_only && is_mutating(req.method(), req.uri().path()) {
+        debug!(
+            "Rejected {} {} in read-only mode",
+            req.method(),
+            req.uri().path()
+        );
+        return Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            ErrorCode::ReadOnly,
+            "The server is in read-only mode",
+        ));
+    }
+    Ok(next.run(req).await)
+}
+
+/// Enforce the role each route requires when `[auth] enabled`. Runs after
+/// `auth_middleware`, which authenticates the bearer token. Viewer routes are
+/// open without a token while `public_read` is set.
+pub(crate) async fn role_middleware(
+    State(state): State<AppState>,
+    req: Request<Body>,
+    next: Next,
+) -> Result<Response, ApiError> {
+    // Read settings per request so config hot reloads apply immediately
+    let auth = state.config.read().map(|c| c.auth.clone()).map_err(|e| {
+        error!("Failed to read config: {}", e);
+        ApiError::internal(ErrorCode::ConfigUnavailable, "Failed to read configuration")
+    })?;
+    let path = req.uri().path().to_string();
+    if !auth.enabled || is_public_route(&path) {
+        return Ok(next.run(req).await);
+    }
+
+    let required = required_role(req.method(), &path);
+    match req.extensions().get::<Authenticated>() {
+        Some(authenticated) if authenticated.role >= required => Ok(next.run(req).await),
+        Some(authenticated) => {
+            warn!(
+                "Rejected {} {} for {:?} with role {}",
+                req.method(),
+                path,
+                authenticated.principal,
+                authenticated.role.as_str()
+            );
+            Err(ApiError::new(
+                StatusCode::FORBIDDEN,
+                ErrorCode::Forbidden,
+                format!("This requires the {} role", required.as_str()),
+            )
+            .with_details(serde_json::json!({ "required_role": required })))
+        }
+        None if required == Role::Viewer && auth.public_read => Ok(next.run(req).await),
+        None => {
+            warn!("Rejected {} {} without valid token", req.method(), path);
+            Err(ApiError::new(
+                StatusCode::UNAUTHORIZED,
+                ErrorCode::Unauthorized,
+                "Missing or invalid token",
+            ))
+        }
+    }
+}
+
+/// Keep `guard` alive until the body of `response` has been sent or the
+/// client went away, so that a limit covers streamed responses for as long
+/// as they run rather than only until the response head
+pub(crate) fn hold_until_body_ends<G: Send + 'static>(response: Response, guard: G) -> Response {
+    let (parts, body) = response.into_parts();
+    let body = body.into_data_stream().map(move |chunk| {
+        let _ = &guard;
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(body))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_public_routes() {
+        assert!(is_public_route("/healthz"));
+        assert!(is_public_route("/readyz"));
+        assert!(is_public_route("/api/auth/login"));
+        assert!(!is_public_route("/api/status"));
+    }
+
+    #[test]
+    fn test_required_role() {
+        assert_eq!(
+            required_role(&Method::GET, "/api/ping/aggregated"),
+            Role::Viewer
+        );
+        assert_eq!(required_role(&Method::GET, "/api/targets"), Role::Viewer);
+        assert_eq!(
+            required_role(&Method::POST, "/api/auth/logout"),
+            Role::Viewer
+        );
+        assert_eq!(
+            required_role(&Method::POST, "/api/grafana/query"),
+            Role::Viewer
+        );
+
+        assert_eq!(
+            required_role(&Method::POST, "/api/targets/router/pause"),
+            Role::Editor
+        );
+        assert_eq!(
+            required_role(&Method::POST, "/api/targets/router/diagnose"),
+            Role::Editor
+        );
+        assert_eq!(
+            required_role(&Method::POST, "/api/targets/router/restart"),
+            Role::Editor
+        );
+        assert_eq!(
+            required_role(&Method::PATCH, "/api/targets/router"),
+            Role::Editor
+        );
+        assert_eq!(required_role(&Method::POST, "/api/ping/test"), Role::Editor);
+        assert_eq!(
+            required_role(&Method::PUT, "/api/preferences"),
+            Role::Editor
+        );
+
+        assert_eq!(required_role(&Method::POST, "/api/targets"), Role::Admin);
+        assert_eq!(
+            required_role(&Method::PUT, "/api/targets/router"),
+            Role::Admin
+        );
+        assert_eq!(
+            required_role(&Method::DELETE, "/api/targets/router"),
+            Role::Admin
+        );
+        assert_eq!(
+            required_role(&Method::GET, "/api/discovery/unified"),
+            Role::Admin
+        );
+        assert_eq!(
+            required_role(&Method::DELETE, "/api/discovery/jobs/abc"),
+            Role::Admin
+        );
+        assert_eq!(
+            required_role(&Method::GET, "/api/discovery/jobs"),
+            Role::Viewer
+        );
+        assert_eq!(required_role(&Method::GET, "/api/auth/tokens"), Role::Admin);
+        assert_eq!(
+            required_role(&Method::POST, "/api/storage/prune"),
+            Role::Admin
+        );
+        assert_eq!(
+            required_role(&Method::POST, "/api/storage/quarantine"),
+            Role::Admin
+        );
+        assert_eq!(
+            required_role(&Method::GET, "/api/storage/verify"),
+            Role::Viewer
+        );
+        assert_eq!(required_role(&Method::GET, "/api/audit"), Role::Admin);
+    }
+
+    #[test]
+    fn test_is_mutating() {
+        assert!(!is_mutating(&Method::GET, "/api/targets"));
+        assert!(!is_mutating(&Method::GET, "/api/status"));
+        assert!(!is_mutating(&Method::POST, "/api/grafana/query"));
+        assert!(!is_mutating(&Method::POST, "/api/auth/login"));
+        assert!(!is_mutating(&Method::POST, "/api/config/validate"));
+
+        assert!(is_mutating(&Method::POST, "/api/targets"));
+        assert!(is_mutating(&Method::DELETE, "/api/targets/router"));
+        assert!(is_mutating(&Method::POST, "/api/targets/router/pause"));
+        assert!(is_mutating(&Method::POST, "/api/storage/prune"));
+        assert!(is_mutating(&Method::POST, "/api/storage/quarantine"));
+        assert!(!is_mutating(&Method::GET, "/api/storage/verify"));
+        assert!(is_mutating(&Method::GET, "/api/discovery/unified"));
+        assert!(is_mutating(&Method::POST, "/api/auth/tokens"));
+        assert!(is_mutating(&Method::POST, "/api/ingest/batch"));
+    }
+
     #[test]
     fn test_is_allowed_ingress_ip_valid_ips() {
         // Both known Home Assistant ingress IPs should be allowed
@@ -1,9 +1,38 @@
+mod admission;
+mod alerts;
+mod audit;
+mod auth;
+mod caching;
+mod config;
+mod cors;
+mod dashboard;
+mod diagnostics;
 mod discovery;
+pub mod error;
+mod export;
+mod extract;
+#[cfg(feature = "embed-frontend")]
+mod frontend;
+mod grafana;
+mod ha;
+mod health;
+pub mod ingest;
+mod integrations;
+mod metrics;
 mod middleware;
+mod notifications;
+mod openapi;
+mod outages;
 pub mod ping;
+mod preferences;
+mod quota;
+mod reports;
 mod router;
 mod state;
+mod status;
 pub mod targets;
+mod traceroute;
+mod versioning;
 
 pub use router::create_router;
 pub use state::AppState;
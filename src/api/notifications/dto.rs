@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+/// Request body for POST /api/notifications/test
+#[derive(Debug, Default, Deserialize)]
+pub struct TestNotificationRequest {
+    /// Channel to test; all channels if omitted
+    pub channel: Option<String>,
+}
+
+/// Delivery result for one channel
+#[derive(Debug, Serialize)]
+pub struct ChannelTestResult {
+    pub channel: String,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Response for POST /api/notifications/test
+#[derive(Debug, Serialize)]
+pub struct TestNotificationResponse {
+    pub results: Vec<ChannelTestResult>,
+}
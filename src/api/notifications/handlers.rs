@@ -0,0 +1,66 @@
+use super::dto::{ChannelTestResult, TestNotificationRequest, TestNotificationResponse};
+use crate::api::error::{ApiError, ErrorCode};
+use crate::api::AppState;
+use crate::notifications::{send, Notification};
+use axum::{extract::State, http::StatusCode, response::Json};
+use tracing::{error, warn};
+
+/// HTTP handler for POST /api/notifications/test
+///
+/// Sends a test notification to one channel (`{"channel": "name"}`) or to all
+/// configured channels, ignoring their `events` filter, and reports the
+/// delivery result per channel.
+pub(crate) async fn test_notifications(
+    State(state): State<AppState>,
+    body: Option<Json<TestNotificationRequest>>,
+) -> Result<Json<TestNotificationResponse>, ApiError> {
+    let request = body.map(|Json(r)| r).unwrap_or_default();
+
+    let channels = {
+        let config = state.config.read().map_err(|e| {
+            error!("Failed to read config: {}", e);
+            ApiError::internal(ErrorCode::ConfigUnavailable, "Failed to read configuration")
+        })?;
+        config.notifications.channels.clone()
+    };
+
+    if channels.is_empty() {
+        return Err(ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::IntegrationNotConfigured,
+            "No notification channels configured (add [[notifications.channels]])",
+        ));
+    }
+
+    let selected: Vec<_> = match request.channel {
+        Some(ref name) => channels.into_iter().filter(|c| &c.name == name).collect(),
+        None => channels,
+    };
+    if selected.is_empty() {
+        let name = request.channel.unwrap_or_default();
+        return Err(ApiError::not_found(
+            ErrorCode::InvalidRequest,
+            format!("Notification channel '{}' not found", name),
+        )
+        .with_details(serde_json::json!({ "channel": name })));
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let mut results = Vec::with_capacity(selected.len());
+    for channel in &selected {
+        let result = send(channel, &Notification::test(&channel.name, now)).await;
+        if let Err(ref e) = result {
+            warn!(
+                "Test notification to channel '{}' failed: {}",
+                channel.name, e
+            );
+        }
+        results.push(ChannelTestResult {
+            channel: channel.name.clone(),
+            success: result.is_ok(),
+            error: result.err().map(|e| e.to_string()),
+        });
+    }
+
+    Ok(Json(TestNotificationResponse { results }))
+}
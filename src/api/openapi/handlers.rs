@@ -0,0 +1,33 @@
+use super::spec::ApiDoc;
+use axum::response::{Html, Json};
+use utoipa::OpenApi;
+
+/// Swagger UI for the spec, loaded from a CDN. The spec URL is relative, so
+/// the page also works below `/api/v<N>/` and behind Home Assistant ingress.
+const SWAGGER_UI_HTML: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <meta name="viewport" content="width=device-width, initial-scale=1">
+  <title>SparkPing API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js" crossorigin></script>
+  <script>
+    window.ui = SwaggerUIBundle({ url: "openapi.json", dom_id: "#swagger-ui" });
+  </script>
+</body>
+</html>
+"##;
+
+/// HTTP handler for GET /api/openapi.json
+pub(crate) async fn get_openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// HTTP handler for GET /api/docs
+pub(crate) async fn get_swagger_ui() -> Html<&'static str> {
+    Html(SWAGGER_UI_HTML)
+}
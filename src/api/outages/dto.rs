@@ -0,0 +1,25 @@
+use crate::api::ping::dto::{deserialize_time_range, TimeRangeValue};
+use crate::outages::TargetOutage;
+use serde::{Deserialize, Serialize};
+
+/// Query parameters for the outages API
+#[derive(Debug, Deserialize)]
+pub struct OutagesQuery {
+    /// Filter by target address or ID (optional, all targets if not specified)
+    #[serde(default)]
+    pub target: Option<String>,
+    /// Start timestamp (Unix timestamp in seconds) or relative time range (e.g., "24h", "7d").
+    /// Default: "7d"
+    #[serde(default, deserialize_with = "deserialize_time_range")]
+    pub from: Option<TimeRangeValue>,
+    /// End timestamp (Unix timestamp in seconds, optional)
+    #[serde(default)]
+    pub to: Option<i64>,
+}
+
+/// API response containing detected outages
+#[derive(Debug, Serialize)]
+pub struct OutagesResponse {
+    /// Outages overlapping the requested range, oldest first
+    pub outages: Vec<TargetOutage>,
+}
@@ -0,0 +1,55 @@
+use super::dto::{OutagesQuery, OutagesResponse};
+use crate::api::error::{ApiError, ErrorCode};
+use crate::api::extract::Query;
+use crate::api::ping::dto::TimeRangeValue;
+use crate::api::ping::query::resolve_time_range_value;
+use crate::api::AppState;
+use crate::outages::query_outages;
+use axum::extract::State;
+use axum::response::Json;
+use std::sync::Arc;
+use tracing::{error, info};
+
+/// HTTP handler for GET /api/outages
+///
+/// Lists outages (runs of consecutive failed pings, see `[ping] outage_after`)
+/// that overlap the requested range, including ongoing ones.
+pub(crate) async fn get_outages(
+    State(state): State<AppState>,
+    Query(query): Query<OutagesQuery>,
+) -> Result<Json<OutagesResponse>, ApiError> {
+    info!("Querying outages: {:?}", query);
+
+    let from_value = query
+        .from
+        .clone()
+        .unwrap_or_else(|| TimeRangeValue::Relative("7d".to_string()));
+    let from = resolve_time_range_value(&from_value).map_err(|e| {
+        error!("Invalid time range: {}", e);
+        ApiError::bad_request(ErrorCode::InvalidTimeRange, e)
+    })?;
+    let to = query.to.unwrap_or_else(|| chrono::Utc::now().timestamp());
+    if to < from {
+        return Err(ApiError::bad_request(
+            ErrorCode::InvalidTimeRange,
+            "'to' must not be before 'from'",
+        ));
+    }
+
+    let storage = Arc::clone(&state.storage);
+    let target = query.target.clone();
+    let outages = tokio::task::spawn_blocking(move || {
+        query_outages(&*storage, target.as_deref(), from, to).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| {
+        error!("Task join error: {}", e);
+        ApiError::internal(ErrorCode::Internal, e.to_string())
+    })?
+    .map_err(|e| {
+        error!("Error querying outages: {}", e);
+        ApiError::internal(ErrorCode::StorageError, e)
+    })?;
+
+    Ok(Json(OutagesResponse { outages }))
+}
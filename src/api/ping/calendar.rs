@@ -0,0 +1,412 @@
+//! Time zone aware bucket boundaries.
+//!
+//! Plain buckets are multiples of the bucket duration since the Unix epoch,
+//! so "1d" buckets split at UTC midnight. With `tz=<IANA name>`, buckets of
+//! whole days start at local midnight instead, and weekly buckets on Monday;
+//! buckets of whole hours that divide a day (e.g. "6h") start at local wall
+//! clock hours counted from midnight. They are built by merging hourly (or,
+//! in zones with half-hour offsets, 15-minute) buckets, so a local day is 23
+//! or 25 hours long on DST changes, and so is the bucket holding the skipped
+//! or repeated hour.
+//!
+//! Buckets in weeks ("1w") and months ("1mo") follow the calendar with or
+//! without `tz` (in UTC without one): weeks start on Monday, months on the
+//! first, and "3mo" buckets are quarters starting in January, April, July
+//! and October.
+
+use super::dto::BucketDataPoint;
+use super::query::split_duration;
+use crate::downsample::merge_bucket;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Offset, TimeZone, Timelike};
+use chrono_tz::Tz;
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+
+const SECONDS_PER_DAY: i64 = 86400;
+const SECONDS_PER_HOUR: i64 = 3600;
+
+/// Parse a `tz` parameter (IANA name, e.g. "Europe/Berlin")
+pub(crate) fn parse_tz(name: &str) -> Result<Tz, String> {
+    name.trim().parse::<Tz>().map_err(|_| {
+        format!(
+            "Unknown time zone '{}' (expected an IANA name, e.g. Europe/Berlin)",
+            name
+        )
+    })
+}
+
+/// Local hour of day (0-23) of a Unix timestamp
+pub(crate) fn local_hour(tz: Tz, timestamp: i64) -> usize {
+    DateTime::from_timestamp(timestamp, 0)
+        .unwrap_or_default()
+        .with_timezone(&tz)
+        .hour() as usize
+}
+
+/// Unix timestamp of the local midnight starting the day `days_back` days
+/// before the one containing `timestamp`
+pub(crate) fn local_midnight(tz: Tz, timestamp: i64, days_back: i64) -> i64 {
+    let date = DateTime::from_timestamp(timestamp, 0)
+        .unwrap_or_default()
+        .with_timezone(&tz)
+        .date_naive();
+    LocalBuckets {
+        tz,
+        hours: 24,
+        months: 0,
+    }
+    .hour_start(date - Duration::days(days_back), 0)
+}
+
+/// Buckets of calendar months, of whole local days, or of whole local hours
+/// within a day
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct LocalBuckets {
+    tz: Tz,
+    hours: i64,
+    /// Months per bucket; 0 for buckets of `hours`
+    months: i64,
+}
+
+impl LocalBuckets {
+    /// Local buckets for a bucket duration. Buckets of a day or longer must
+    /// be whole days. Shorter ones are local if they are whole hours dividing
+    /// a day; others keep their epoch alignment (None).
+    pub(crate) fn new(tz: Tz, bucket_duration_seconds: i64) -> Result<Option<Self>, String> {
+        if bucket_duration_seconds < SECONDS_PER_DAY {
+            let local = bucket_duration_seconds >= SECONDS_PER_HOUR
+                && bucket_duration_seconds % SECONDS_PER_HOUR == 0
+                && SECONDS_PER_DAY % bucket_duration_seconds == 0;
+            return Ok(local.then_some(Self {
+                tz,
+                hours: bucket_duration_seconds / SECONDS_PER_HOUR,
+                months: 0,
+            }));
+        }
+        if bucket_duration_seconds % SECONDS_PER_DAY != 0 {
+            return Err("With tz, buckets of a day or longer must be whole days".to_string());
+        }
+        Ok(Some(Self {
+            tz,
+            hours: bucket_duration_seconds / SECONDS_PER_HOUR,
+            months: 0,
+        }))
+    }
+
+    /// Calendar buckets for a `bucket` parameter in weeks (e.g. "1w") or
+    /// months (e.g. "1mo"); None for other units
+    pub(crate) fn calendar(tz: Tz, bucket: &str) -> Result<Option<Self>, String> {
+        let (number, unit) = split_duration(bucket)?;
+        if number <= 0 {
+            return Err("Bucket duration must be positive".to_string());
+        }
+        match unit.as_str() {
+            "w" | "week" | "weeks" => Self::new(tz, number * 7 * SECONDS_PER_DAY),
+            "mo" | "month" | "months" => Ok(Some(Self {
+                tz,
+                hours: 0,
+                months: number,
+            })),
+            _ => Ok(None),
+        }
+    }
+
+    /// Epoch-aligned bucket duration to query before [`Self::regroup`]:
+    /// hours, or 15 minutes if the zone is off by half an hour at either end
+    /// of `[from, to]` (e.g. Asia/Kolkata)
+    pub(crate) fn base_bucket_seconds(&self, from: i64, to: i64) -> i64 {
+        let whole_hours = [from, to].iter().all(|&timestamp| {
+            let utc = DateTime::from_timestamp(timestamp, 0).unwrap_or_default();
+            let offset = self.tz.offset_from_utc_datetime(&utc.naive_utc()).fix();
+            offset.local_minus_utc() % 3600 == 0
+        });
+        if whole_hours {
+            3600
+        } else {
+            900
+        }
+    }
+
+    /// First day of the bucket of `days` days containing `date`. Multiples
+    /// of a week start on Monday, other day counts are counted from
+    /// 1970-01-01.
+    fn first_day(days: i64, date: NaiveDate) -> NaiveDate {
+        let anchor = if days % 7 == 0 {
+            NaiveDate::from_ymd_opt(1969, 12, 29)
+        } else {
+            NaiveDate::from_ymd_opt(1970, 1, 1)
+        }
+        .unwrap_or_default();
+        let offset = (date - anchor).num_days().div_euclid(days) * days;
+        anchor + Duration::days(offset)
+    }
+
+    /// Unix timestamp of the local `hour` (0-23) of `date`; a repeated hour
+    /// starts at its first occurrence
+    fn hour_start(&self, date: NaiveDate, hour: i64) -> i64 {
+        let local = date.and_hms_opt(0, 0, 0).unwrap_or_default() + Duration::hours(hour);
+        // An hour skipped by DST starts where the next one does
+        [local, local + Duration::hours(1)]
+            .iter()
+            .find_map(|local| self.tz.from_local_datetime(local).earliest())
+            .map(|start| start.timestamp())
+            .unwrap_or_else(|| local.and_utc().timestamp())
+    }
+
+    /// Start and end (Unix seconds) of the bucket containing `timestamp`
+    pub(crate) fn bounds(&self, timestamp: i64) -> (i64, i64) {
+        let local = DateTime::from_timestamp(timestamp, 0)
+            .unwrap_or_default()
+            .with_timezone(&self.tz);
+        let date = local.date_naive();
+        if self.months > 0 {
+            // Months since year 0, buckets counted from January
+            let month = date.year() as i64 * 12 + date.month0() as i64;
+            let first = month.div_euclid(self.months) * self.months;
+            let first_day = |month: i64| {
+                NaiveDate::from_ymd_opt(
+                    month.div_euclid(12) as i32,
+                    month.rem_euclid(12) as u32 + 1,
+                    1,
+                )
+                .unwrap_or_default()
+            };
+            return (
+                self.hour_start(first_day(first), 0),
+                self.hour_start(first_day(first + self.months), 0),
+            );
+        }
+        if self.hours < 24 {
+            let hour = local.hour() as i64 / self.hours * self.hours;
+            let end = if hour + self.hours < 24 {
+                self.hour_start(date, hour + self.hours)
+            } else {
+                self.hour_start(date + Duration::days(1), 0)
+            };
+            return (self.hour_start(date, hour), end);
+        }
+        let days = self.hours / 24;
+        let first = Self::first_day(days, date);
+        (
+            self.hour_start(first, 0),
+            self.hour_start(first + Duration::days(days), 0),
+        )
+    }
+
+    /// Merge buckets of [`Self::base_bucket_seconds`] into local buckets,
+    /// sorted by target and time. Percentiles cannot be merged and
+    /// are dropped.
+    pub(crate) fn regroup(&self, buckets: Vec<BucketDataPoint>) -> Vec<BucketDataPoint> {
+        let mut merged: BTreeMap<(String, i64), BucketDataPoint> = BTreeMap::new();
+
+        for mut bucket in buckets {
+            let (start, end) = self.bounds(bucket.timestamp_unix);
+            match merged.entry((bucket.target.clone(), start)) {
+                Entry::Occupied(mut entry) => merge_bucket(entry.get_mut(), &bucket),
+                Entry::Vacant(entry) => {
+                    bucket.timestamp = DateTime::from_timestamp(start, 0)
+                        .unwrap_or_default()
+                        .with_timezone(&self.tz)
+                        .to_rfc3339();
+                    bucket.timestamp_unix = start;
+                    bucket.timestamp_end_unix = end;
+                    bucket.percentiles = None;
+                    entry.insert(bucket);
+                }
+            }
+        }
+
+        merged.into_values().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hour(start: i64, failed_count: usize) -> BucketDataPoint {
+        BucketDataPoint {
+            timestamp: String::new(),
+            timestamp_unix: start,
+            timestamp_end_unix: start + 3600,
+            target: "192.168.1.1".to_string(),
+            target_name: None,
+            min: Some(1.0),
+            max: Some(1.0),
+            avg: Some(1.0),
+            percentiles: None,
+            count: 10,
+            successful_count: 10 - failed_count,
+            failed_count,
+            resolution: None,
+            complete: true,
+            loss_percent: None,
+        }
+    }
+
+    fn utc(date: &str) -> i64 {
+        DateTime::parse_from_rfc3339(date).unwrap().timestamp()
+    }
+
+    #[test]
+    fn test_parse_tz() {
+        assert_eq!(
+            parse_tz("Europe/Berlin").unwrap(),
+            chrono_tz::Europe::Berlin
+        );
+        assert!(parse_tz("Mars/Olympus").is_err());
+    }
+
+    #[test]
+    fn test_local_days_bounds() {
+        let berlin = LocalBuckets::new(chrono_tz::Europe::Berlin, 86400)
+            .unwrap()
+            .unwrap();
+        // 23:30 UTC on 2024-01-15 is already the 16th in Berlin
+        assert_eq!(
+            berlin.bounds(utc("2024-01-15T23:30:00Z")),
+            (utc("2024-01-15T23:00:00Z"), utc("2024-01-16T23:00:00Z"))
+        );
+        // DST starts on 2024-03-31: a 23-hour day
+        let (start, end) = berlin.bounds(utc("2024-03-31T12:00:00Z"));
+        assert_eq!(end - start, 23 * 3600);
+
+        // Weeks start on Monday (2024-01-15)
+        let weeks = LocalBuckets::new(chrono_tz::Europe::Berlin, 7 * 86400)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            weeks.bounds(utc("2024-01-18T12:00:00Z")),
+            (utc("2024-01-14T23:00:00Z"), utc("2024-01-21T23:00:00Z"))
+        );
+
+        assert!(LocalBuckets::new(chrono_tz::UTC, 1800).unwrap().is_none());
+        assert!(LocalBuckets::new(chrono_tz::UTC, 5 * 3600)
+            .unwrap()
+            .is_none());
+        assert!(LocalBuckets::new(chrono_tz::UTC, 36 * 3600).is_err());
+    }
+
+    #[test]
+    fn test_calendar_bounds() {
+        let months = LocalBuckets::calendar(chrono_tz::UTC, "1mo")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            months.bounds(utc("2024-02-15T12:00:00Z")),
+            (utc("2024-02-01T00:00:00Z"), utc("2024-03-01T00:00:00Z"))
+        );
+        // Local months, across the start of DST on 2024-03-31
+        let berlin = LocalBuckets::calendar(chrono_tz::Europe::Berlin, "1month")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            berlin.bounds(utc("2024-03-31T22:30:00Z")),
+            (utc("2024-03-31T22:00:00Z"), utc("2024-04-30T22:00:00Z"))
+        );
+        // Quarters, across the turn of the year
+        let quarters = LocalBuckets::calendar(chrono_tz::UTC, "3mo")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            quarters.bounds(utc("2024-11-20T00:00:00Z")),
+            (utc("2024-10-01T00:00:00Z"), utc("2025-01-01T00:00:00Z"))
+        );
+
+        // Weeks start on Monday in UTC too, not on Thursday like epoch
+        // aligned 7-day buckets
+        let weeks = LocalBuckets::calendar(chrono_tz::UTC, "1w")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            weeks.bounds(utc("2024-01-18T12:00:00Z")),
+            (utc("2024-01-15T00:00:00Z"), utc("2024-01-22T00:00:00Z"))
+        );
+
+        assert!(LocalBuckets::calendar(chrono_tz::UTC, "1d")
+            .unwrap()
+            .is_none());
+        assert!(LocalBuckets::calendar(chrono_tz::UTC, "0mo").is_err());
+    }
+
+    #[test]
+    fn test_local_midnight() {
+        let now = utc("2024-01-15T12:00:00Z");
+        assert_eq!(
+            local_midnight(chrono_tz::Europe::Berlin, now, 0),
+            utc("2024-01-14T23:00:00Z")
+        );
+        // Across the start of DST on 2024-03-31
+        assert_eq!(
+            local_midnight(chrono_tz::Europe::Berlin, utc("2024-04-01T12:00:00Z"), 2),
+            utc("2024-03-29T23:00:00Z")
+        );
+    }
+
+    #[test]
+    fn test_local_hours_bounds() {
+        let berlin = LocalBuckets::new(chrono_tz::Europe::Berlin, 6 * 3600)
+            .unwrap()
+            .unwrap();
+        // 05:30 UTC is 06:30 in Berlin: the 06:00-12:00 bucket
+        assert_eq!(
+            berlin.bounds(utc("2024-01-15T05:30:00Z")),
+            (utc("2024-01-15T05:00:00Z"), utc("2024-01-15T11:00:00Z"))
+        );
+        // The last bucket of the day ends at local midnight
+        assert_eq!(
+            berlin.bounds(utc("2024-01-15T22:30:00Z")),
+            (utc("2024-01-15T17:00:00Z"), utc("2024-01-15T23:00:00Z"))
+        );
+
+        // DST ends on 2024-10-27: 02:00-03:00 happens twice
+        let hours = LocalBuckets::new(chrono_tz::Europe::Berlin, 3600)
+            .unwrap()
+            .unwrap();
+        let (start, end) = hours.bounds(utc("2024-10-27T01:30:00Z"));
+        assert_eq!(start, utc("2024-10-27T00:00:00Z"));
+        assert_eq!(end - start, 2 * 3600);
+
+        // Half-hour offset: local hours start at half past in UTC
+        let kolkata = LocalBuckets::new(chrono_tz::Asia::Kolkata, 3600)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            kolkata.bounds(utc("2024-01-15T10:00:00Z")),
+            (utc("2024-01-15T09:30:00Z"), utc("2024-01-15T10:30:00Z"))
+        );
+    }
+
+    #[test]
+    fn test_base_bucket_seconds() {
+        let now = utc("2024-01-15T00:00:00Z");
+        let berlin = LocalBuckets::new(chrono_tz::Europe::Berlin, 86400)
+            .unwrap()
+            .unwrap();
+        assert_eq!(berlin.base_bucket_seconds(now - 86400, now), 3600);
+        let kolkata = LocalBuckets::new(chrono_tz::Asia::Kolkata, 86400)
+            .unwrap()
+            .unwrap();
+        assert_eq!(kolkata.base_bucket_seconds(now - 86400, now), 900);
+    }
+
+    #[test]
+    fn test_regroup_to_local_days() {
+        let berlin = LocalBuckets::new(chrono_tz::Europe::Berlin, 86400)
+            .unwrap()
+            .unwrap();
+        // 22:00 and 23:00 UTC fall on different Berlin days
+        let buckets = vec![
+            hour(utc("2024-01-15T21:00:00Z"), 0),
+            hour(utc("2024-01-15T22:00:00Z"), 5),
+            hour(utc("2024-01-15T23:00:00Z"), 10),
+        ];
+        let days = berlin.regroup(buckets);
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[0].timestamp_unix, utc("2024-01-14T23:00:00Z"));
+        assert_eq!(days[0].failed_count, 5);
+        assert_eq!(days[0].count, 20);
+        assert_eq!(days[1].timestamp, "2024-01-16T00:00:00+01:00");
+        assert_eq!(days[1].failed_count, 10);
+    }
+}
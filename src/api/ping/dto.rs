@@ -1,13 +1,18 @@
+use crate::api::targets::filter::TargetState;
+use crate::config::{ProbeType, SocketType};
 use serde::{Deserialize, Deserializer, Serialize};
 use std::collections::HashMap;
+use utoipa::{IntoParams, ToSchema};
 
 /// Query parameters for the ping data API
-#[derive(Debug)]
+#[derive(Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct PingDataQuery {
     /// Filter by target address (optional)
     pub target: Option<String>,
     /// Start timestamp (Unix timestamp in seconds) or relative time range (e.g., "24h", "7d")
     /// Can be either a number (absolute timestamp) or a string (relative time range)
+    #[param(value_type = Option<String>)]
     pub from: Option<TimeRangeValue>,
     /// End timestamp (Unix timestamp in seconds, optional)
     pub to: Option<i64>,
@@ -15,6 +20,18 @@ pub struct PingDataQuery {
     pub metric: Option<String>,
     /// Maximum number of results to return (optional, no limit if not specified)
     pub limit: Option<usize>,
+    /// Only targets carrying these labels, e.g. "location:basement" or
+    /// "location:basement,class:critical" (optional)
+    pub label: Option<String>,
+    /// "asc" (oldest first, default) or "desc" (newest first)
+    pub order: Option<String>,
+    /// `next_cursor` of the previous page, to continue after its last result
+    /// (with otherwise unchanged parameters)
+    pub cursor: Option<String>,
+    /// Site whose results to return: omitted or "local" for this server's
+    /// own pings, an agent's site for the results it pushed, or "*" for all
+    /// of them, each result carrying its `site`
+    pub site: Option<String>,
 }
 
 /// Represents either an absolute timestamp or a relative time range string
@@ -37,6 +54,10 @@ impl<'de> Deserialize<'de> for PingDataQuery {
             to: Option<i64>,
             metric: Option<String>,
             limit: Option<usize>,
+            label: Option<String>,
+            order: Option<String>,
+            cursor: Option<String>,
+            site: Option<String>,
         }
 
         let helper = PingDataQueryHelper::deserialize(deserializer)?;
@@ -46,13 +67,19 @@ impl<'de> Deserialize<'de> for PingDataQuery {
             to: helper.to,
             metric: helper.metric,
             limit: helper.limit,
+            label: helper.label,
+            order: helper.order,
+            cursor: helper.cursor,
+            site: helper.site,
         })
     }
 }
 
 /// Custom deserializer for time range values
 /// Tries to parse as i64 first (absolute timestamp), otherwise treats as relative string
-fn deserialize_time_range<'de, D>(deserializer: D) -> Result<Option<TimeRangeValue>, D::Error>
+pub(crate) fn deserialize_time_range<'de, D>(
+    deserializer: D,
+) -> Result<Option<TimeRangeValue>, D::Error>
 where
     D: Deserializer<'de>,
 {
@@ -124,21 +151,48 @@ where
 }
 
 /// Query parameters for the aggregated ping data API
-#[derive(Debug)]
+#[derive(Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct PingAggregatedQuery {
     /// Filter by target address (optional)
     pub target: Option<String>,
     /// Start timestamp (Unix timestamp in seconds) or relative time range (e.g., "24h", "7d")
     /// Can be either a number (absolute timestamp) or a string (relative time range)
+    #[param(value_type = Option<String>)]
     pub from: Option<TimeRangeValue>,
     /// End timestamp (Unix timestamp in seconds, optional)
     pub to: Option<i64>,
-    /// Filter by metric type: "latency", "failed", or "all" (default: "all")
+    /// Filter by metric type: "latency", "failed", or "all" (default: "all").
+    /// "loss" returns only the loss series: buckets with `loss_percent` and
+    /// counts but without latency. "storage_size" aggregates recorded storage
+    /// size snapshots (bytes) instead, "jitter" the RTT deltas within batches
+    /// (ms), and "batch_loss" the loss percentage of each batch
     pub metric: Option<String>,
-    /// Time bucket duration (e.g., "5m", "1h", "30s"). Default: "5m"
+    /// Time bucket duration (e.g., "5m", "1h", "30s"), or calendar weeks
+    /// ("1w", starting on Monday) and months ("1mo", "3mo" for quarters).
+    /// Default: "5m"
+    #[param(value_type = Option<String>)]
     pub bucket: String,
     /// Include percentile data for histogram visualization (default: false)
     pub include_percentiles: Option<bool>,
+    /// Merge the buckets of targets sharing a tag value, e.g. "tag:site"
+    /// for targets tagged "site:<value>", or "site" for one series per
+    /// agent site (and "local" for this server) of the selected targets
+    /// (optional)
+    pub group_by: Option<String>,
+    /// IANA time zone (e.g., "Europe/Berlin") that buckets of whole days
+    /// start at local midnight in (weeks on Monday), and buckets of whole
+    /// hours dividing a day (e.g. "1h", "6h") at local hours, across DST
+    /// changes. Default: epoch (UTC) aligned
+    pub tz: Option<String>,
+    /// Only targets carrying these labels, e.g. "location:basement" or
+    /// "location:basement,class:critical" (optional)
+    pub label: Option<String>,
+    /// Site whose results to aggregate: omitted or "local" for this
+    /// server's own pings, or an agent's site for the results it pushed;
+    /// "*" for all of them with `group_by=site`, which selects every site
+    /// if omitted
+    pub site: Option<String>,
 }
 
 impl<'de> Deserialize<'de> for PingAggregatedQuery {
@@ -148,6 +202,7 @@ impl<'de> Deserialize<'de> for PingAggregatedQuery {
     {
         #[derive(Deserialize)]
         #[serde(default)]
+        #[derive(Default)]
         struct PingAggregatedQueryHelper {
             target: Option<String>,
             #[serde(deserialize_with = "deserialize_time_range")]
@@ -156,19 +211,10 @@ impl<'de> Deserialize<'de> for PingAggregatedQuery {
             metric: Option<String>,
             bucket: Option<String>,
             include_percentiles: Option<bool>,
-        }
-
-        impl Default for PingAggregatedQueryHelper {
-            fn default() -> Self {
-                PingAggregatedQueryHelper {
-                    target: None,
-                    from: None,
-                    to: None,
-                    metric: None,
-                    bucket: None,
-                    include_percentiles: None,
-                }
-            }
+            group_by: Option<String>,
+            tz: Option<String>,
+            label: Option<String>,
+            site: Option<String>,
         }
 
         let helper = PingAggregatedQueryHelper::deserialize(deserializer)?;
@@ -179,6 +225,10 @@ impl<'de> Deserialize<'de> for PingAggregatedQuery {
             metric: helper.metric,
             bucket: helper.bucket.unwrap_or_else(default_bucket),
             include_percentiles: helper.include_percentiles,
+            group_by: helper.group_by,
+            tz: helper.tz,
+            label: helper.label,
+            site: helper.site,
         })
     }
 }
@@ -188,7 +238,7 @@ fn default_bucket() -> String {
 }
 
 /// Detailed ping data point with all available information
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct PingDataPoint {
     /// ISO 8601 formatted timestamp
     pub timestamp: String,
@@ -200,16 +250,22 @@ pub struct PingDataPoint {
     pub target_name: Option<String>,
     /// Sequence number of the ping
     pub sequence: u16,
+    /// Probe type that produced the result
+    pub probe_type: ProbeType,
     /// Whether the ping was successful
     pub success: bool,
     /// Latency in milliseconds (None if ping failed)
     pub latency_ms: Option<f64>,
     /// Metric type: "ping_latency" or "ping_failed"
     pub metric_type: String,
+    /// Site of the agent that measured the result; absent for this server's
+    /// own pings
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub site: Option<String>,
 }
 
 /// Statistics aggregated from the query results
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct PingStatistics {
     /// Total number of successful pings
     pub successful_count: usize,
@@ -223,10 +279,16 @@ pub struct PingStatistics {
     pub max_latency_ms: Option<f64>,
     /// Success rate as a percentage (0-100)
     pub success_rate: f64,
+    /// Average RTT delta between consecutive pings of a batch in milliseconds
+    pub avg_jitter_ms: Option<f64>,
+    /// Largest RTT delta between consecutive pings of a batch in milliseconds
+    pub max_jitter_ms: Option<f64>,
+    /// Average loss percentage of the completed batches
+    pub avg_batch_loss_percent: Option<f64>,
 }
 
 /// API response containing ping data and metadata
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct PingDataResponse {
     /// Query metadata
     pub query: QueryMetadata,
@@ -236,10 +298,13 @@ pub struct PingDataResponse {
     pub statistics: PingStatistics,
     /// Total number of data points returned
     pub total_count: usize,
+    /// Cursor for the next page when `limit` cut the results short; null on
+    /// the last page
+    pub next_cursor: Option<String>,
 }
 
 /// Metadata about the query that was executed
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct QueryMetadata {
     /// Target filter applied (if any)
     pub target_filter: Option<String>,
@@ -256,7 +321,7 @@ pub struct QueryMetadata {
 }
 
 /// Time range of the actual data returned
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct TimeRange {
     /// Earliest timestamp in the results
     pub earliest: i64,
@@ -265,7 +330,7 @@ pub struct TimeRange {
 }
 
 /// Percentile values for histogram data
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Clone, ToSchema)]
 pub struct Percentiles {
     /// 50th percentile (median)
     pub p50: f64,
@@ -280,7 +345,7 @@ pub struct Percentiles {
 }
 
 /// Aggregated data point for a time bucket
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Clone, ToSchema)]
 pub struct BucketDataPoint {
     /// ISO 8601 formatted timestamp (start of bucket)
     pub timestamp: String,
@@ -307,10 +372,22 @@ pub struct BucketDataPoint {
     pub successful_count: usize,
     /// Number of failed pings in this bucket
     pub failed_count: usize,
+    /// Data the bucket was computed from: "raw", "1m", "1h", or "mixed" when
+    /// it combines rollups with raw data
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>)]
+    pub resolution: Option<&'static str>,
+    /// False if the bucket extends past the queried range (or into the
+    /// future), so its statistics only cover part of it
+    pub complete: bool,
+    /// Packet loss in percent, `failed_count / count * 100`; only for ping
+    /// results in /api/ping/aggregated, and not for buckets without pings
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loss_percent: Option<f64>,
 }
 
 /// API response containing aggregated ping data
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct PingAggregatedResponse {
     /// Query metadata
     pub query: QueryMetadata,
@@ -320,10 +397,19 @@ pub struct PingAggregatedResponse {
     pub total_count: usize,
     /// Bucket duration in seconds
     pub bucket_duration_seconds: i64,
+    /// Data the buckets were computed from: "raw", or "1m"/"1h" when
+    /// downsampled rollups were used
+    pub resolution: String,
+    /// Grouping applied; buckets then carry the group value as `target`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group_by: Option<String>,
+    /// Time zone whose local days or hours the buckets are aligned to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tz: Option<String>,
 }
 
 /// Storage statistics per target
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Clone, ToSchema)]
 pub struct TargetStorageStats {
     /// Target ID
     pub target_id: String,
@@ -338,7 +424,7 @@ pub struct TargetStorageStats {
 }
 
 /// API response for storage statistics
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct StorageStatsResponse {
     /// Total storage size in bytes (all targets)
     pub total_size_bytes: u64,
@@ -346,6 +432,274 @@ pub struct StorageStatsResponse {
     pub targets: Vec<TargetStorageStats>,
 }
 
+/// Query parameters for GET /api/ping/trend
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TrendQuery {
+    /// Target ID (or address) to analyze
+    pub target_id: String,
+    /// Period the trend is fitted on, e.g. "30d" (default: "30d", max: "90d")
+    #[serde(default)]
+    pub window: Option<String>,
+    /// How far to forecast, e.g. "7d" (default: "7d", max: "30d")
+    #[serde(default)]
+    pub horizon: Option<String>,
+    /// IANA time zone whose local hours the daily profile uses (default: UTC)
+    #[serde(default)]
+    pub tz: Option<String>,
+}
+
+/// Forecast value with its 95% prediction band
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ForecastPoint {
+    /// Unix timestamp in seconds
+    pub timestamp_unix: i64,
+    pub value: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+/// Trend of one metric over the window
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MetricTrend {
+    /// Number of hourly samples the trend was fitted on
+    pub samples: usize,
+    /// Linear change per day (ms/day for latency, percentage points/day for loss)
+    pub slope_per_day: f64,
+    /// Trend value at the end of the window
+    pub current: f64,
+    /// Linear change between the first sample and the end of the window
+    pub change_over_window: f64,
+    /// Whether a daily profile was fitted (window of at least two days)
+    pub seasonal: bool,
+    /// Standard deviation of the samples around the fitted trend
+    pub residual_stddev: f64,
+    /// Hourly forecast past the end of the window
+    pub forecast: Vec<ForecastPoint>,
+}
+
+/// Response for GET /api/ping/trend
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TrendResponse {
+    pub target_id: String,
+    /// Unix timestamp in seconds of the window start
+    pub from: i64,
+    /// Unix timestamp in seconds of the window end
+    pub to: i64,
+    /// Average latency trend (None without enough successful pings)
+    pub latency: Option<MetricTrend>,
+    /// Packet loss trend in percent (None without enough pings)
+    pub loss: Option<MetricTrend>,
+}
+
+/// Query parameters for GET /api/ping/live
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PingLiveQuery {
+    /// Only stream results of this target (address or id, optional)
+    #[serde(default)]
+    pub target: Option<String>,
+    /// Comma-separated targets (addresses or ids) to stream, combined with `target`
+    #[serde(default)]
+    pub targets: Option<String>,
+    /// Maximum frame rate, e.g. "1/s", "10/m", "1/5s". When set, results are
+    /// coalesced into periodic `summary` events instead of `ping` events.
+    #[serde(default)]
+    pub max_rate: Option<String>,
+    /// Site whose results to return: omitted or "local" for this server's
+    /// own pings, an agent's site for the results it pushed, or "*" for all
+    /// of them, each result carrying its `site`
+    #[serde(default)]
+    pub site: Option<String>,
+}
+
+/// Request body for POST /api/ping/test
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PingTestRequest {
+    /// IP address or hostname to probe
+    pub address: String,
+    /// Number of probes to send (default: 3, max: 10)
+    #[serde(default)]
+    pub count: Option<u16>,
+    /// Timeout per probe in milliseconds (default: 5000, max: 10000)
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    /// ICMP socket type (default: `[ping] socket_type`)
+    #[serde(default)]
+    pub socket_type: Option<SocketType>,
+    /// How the address is probed (default: icmp)
+    #[serde(default)]
+    pub probe_type: Option<ProbeType>,
+    /// Port for TCP probes (default: 80)
+    #[serde(default)]
+    pub port: Option<u16>,
+}
+
+/// Outcome of a single test probe
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PingTestPacket {
+    pub sequence: u16,
+    pub success: bool,
+    /// Round-trip (ICMP) or connect (TCP) time in milliseconds
+    pub latency_ms: Option<f64>,
+    /// Why the probe failed
+    pub error: Option<String>,
+}
+
+/// Response for POST /api/ping/test
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PingTestResponse {
+    pub address: String,
+    /// Address that was probed (None if resolution failed)
+    pub resolved_ip: Option<String>,
+    pub probe_type: ProbeType,
+    /// Port of TCP probes
+    pub port: Option<u16>,
+    /// Socket type of ICMP probes
+    pub socket_type: Option<SocketType>,
+    pub packets: Vec<PingTestPacket>,
+    pub sent: usize,
+    pub received: usize,
+    pub loss_percent: f64,
+    pub min_latency_ms: Option<f64>,
+    pub avg_latency_ms: Option<f64>,
+    pub max_latency_ms: Option<f64>,
+    /// Resolution error; no probes are sent in that case
+    pub error: Option<String>,
+}
+
+/// Query parameters for GET /api/ping/summary
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SummaryQuery {
+    /// Number of sparkline buckets over the last 24 hours (default: 24, max: 288)
+    #[serde(default)]
+    pub buckets: Option<usize>,
+    /// Order: "latency" or "loss" of the last hour, worst first (default:
+    /// config order)
+    #[serde(default)]
+    pub sort: Option<String>,
+    /// Only the first N targets (after sorting)
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// Sparkline bucket of a target summary
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct SparklinePoint {
+    /// Unix timestamp in seconds (start of bucket)
+    pub timestamp_unix: i64,
+    /// Average latency of successful pings in milliseconds
+    pub avg_latency_ms: Option<f64>,
+    /// Packet loss in percent (None without pings)
+    pub loss_percent: Option<f64>,
+}
+
+/// Overview of one target
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct TargetSummary {
+    pub target_id: String,
+    pub address: String,
+    pub name: Option<String>,
+    pub state: TargetState,
+    /// Unix timestamp in seconds of the last ping
+    pub last_sample_unix: Option<i64>,
+    /// Latency of the last ping in milliseconds (None if it failed)
+    pub last_latency_ms: Option<f64>,
+    /// Average latency over the last hour in milliseconds
+    pub avg_latency_1h_ms: Option<f64>,
+    /// Packet loss over the last hour in percent
+    pub loss_percent_1h: Option<f64>,
+    /// Average latency over the sparkline range in milliseconds
+    pub avg_latency_24h_ms: Option<f64>,
+    /// Packet loss over the sparkline range in percent
+    pub loss_percent_24h: Option<f64>,
+    /// Buckets over the last 24 hours, oldest first; buckets without pings
+    /// are included with empty values
+    pub sparkline: Vec<SparklinePoint>,
+}
+
+/// API response for GET /api/ping/summary
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SummaryResponse {
+    /// Unix timestamp in seconds the summary was computed at
+    pub timestamp_unix: i64,
+    /// Duration of a sparkline bucket in seconds
+    pub bucket_duration_seconds: i64,
+    /// Data the sparklines were computed from: "raw", "1m", or "1h"
+    pub resolution: String,
+    pub targets: Vec<TargetSummary>,
+}
+
+/// Query parameters for GET /api/ping/histogram
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HistogramQuery {
+    /// Target ID (or address)
+    pub target: String,
+    /// Start timestamp (Unix timestamp in seconds) or relative time range
+    /// (e.g., "24h", "7d"). Default: "24h"
+    #[serde(default, deserialize_with = "deserialize_time_range")]
+    #[param(value_type = Option<String>)]
+    pub from: Option<TimeRangeValue>,
+    /// End timestamp (Unix timestamp in seconds, optional)
+    #[serde(default)]
+    pub to: Option<i64>,
+    /// Time slice duration (e.g., "5m", "1h"). Default: "1h"
+    #[serde(default)]
+    pub slice: Option<String>,
+    /// Comma-separated upper bin bounds in milliseconds, increasing
+    /// (default: "1,2,5,10,20,50,100,200,500,1000")
+    #[serde(default)]
+    pub bounds: Option<String>,
+    /// Data to count: "raw" results or "rollup" (1-minute averages, faster
+    /// over long ranges). Default: raw for ranges up to 2 days
+    #[serde(default)]
+    pub source: Option<String>,
+}
+
+/// Latency distribution of one time slice
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct HistogramSlice {
+    /// Unix timestamp in seconds (start of slice)
+    pub timestamp_unix: i64,
+    /// Successful pings per bin: `counts[i]` up to `bounds[i]` (above the
+    /// previous bound), the last entry above the highest bound
+    pub counts: Vec<u64>,
+    /// Failed pings in this slice
+    pub failed_count: u64,
+}
+
+/// API response for GET /api/ping/histogram
+#[derive(Debug, Serialize, ToSchema)]
+pub struct HistogramResponse {
+    pub target_id: String,
+    /// Target IP address
+    pub target: String,
+    /// Unix timestamp in seconds of the range start
+    pub from_timestamp: i64,
+    /// Unix timestamp in seconds of the range end
+    pub to_timestamp: i64,
+    /// Duration of a slice in seconds
+    pub slice_seconds: i64,
+    /// Upper bin bounds in milliseconds
+    pub bounds: Vec<f64>,
+    /// Data the histogram was counted from: "raw", "1m", "1h", or "mixed"
+    pub resolution: String,
+    /// Slices of the range, oldest first; slices without pings are included
+    /// with zero counts
+    pub slices: Vec<HistogramSlice>,
+}
+
+/// Query parameters for POST /api/storage/prune
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PruneQuery {
+    /// Only report what would be pruned (default: false)
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
 /// Metadata structure for tsink partition files
 #[derive(Debug, Deserialize)]
 pub struct PartitionMetadata {
@@ -360,14 +714,10 @@ pub struct PartitionMetadata {
 
 #[derive(Debug, Deserialize)]
 pub struct MetricMetadata {
-    #[allow(dead_code)]
     pub name: String,
-    #[allow(dead_code)]
     pub offset: u64,
     pub encoded_size: u64,
-    #[allow(dead_code)]
     pub min_timestamp: i64,
-    #[allow(dead_code)]
     pub max_timestamp: i64,
     pub num_data_points: u64,
 }
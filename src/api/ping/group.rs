@@ -0,0 +1,181 @@
+//! Aggregation of per-target buckets across targets sharing a tag.
+//!
+//! Tags of the form `key:value` (e.g. `site:berlin`) act as labels;
+//! `group_by=tag:site` merges the buckets of all targets tagged
+//! `site:<value>` into one series per value. `group_by=site` groups by the
+//! agent site that measured the results instead, which the aggregated query
+//! does itself (see `query_site_aggregated`).
+
+use super::dto::BucketDataPoint;
+use crate::config::Target;
+use crate::downsample::merge_bucket;
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+
+/// What targets are grouped by
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum GroupBy {
+    /// Value of the target's `<key>:<value>` tag
+    Tag(String),
+    /// Agent site of the results, merged while querying
+    Site,
+}
+
+impl GroupBy {
+    /// Parse a `group_by` parameter (`tag:<key>` or `site`)
+    pub(crate) fn parse(value: &str) -> Result<Self, String> {
+        if value.trim() == "site" {
+            return Ok(GroupBy::Site);
+        }
+        match value.split_once(':') {
+            Some(("tag", key)) if !key.trim().is_empty() => {
+                Ok(GroupBy::Tag(key.trim().to_lowercase()))
+            }
+            _ => Err(format!(
+                "Unsupported group_by '{}' (expected tag:<key>, e.g. tag:site, or site)",
+                value
+            )),
+        }
+    }
+
+    /// Group of a target, None if it has no matching tag
+    fn group_of<'a>(&self, target: &'a Target) -> Option<&'a str> {
+        match self {
+            GroupBy::Tag(key) => target.tags.iter().find_map(|tag| {
+                let (tag_key, value) = tag.split_once(':')?;
+                (tag_key.trim().eq_ignore_ascii_case(key) && !value.trim().is_empty())
+                    .then(|| value.trim())
+            }),
+            GroupBy::Site => None,
+        }
+    }
+}
+
+/// Merge per-target buckets into one series per group.
+///
+/// Buckets are matched to targets by address; buckets of targets without a
+/// group are dropped. Group buckets carry the group value as `target` and
+/// the tag (e.g. `site:berlin`) as `target_name`. Buckets grouped by site
+/// are already merged and returned unchanged.
+pub(crate) fn group_buckets(
+    buckets: Vec<BucketDataPoint>,
+    targets: &[Target],
+    group_by: &GroupBy,
+) -> Vec<BucketDataPoint> {
+    let key = match group_by {
+        GroupBy::Tag(key) => key,
+        GroupBy::Site => return buckets,
+    };
+    let mut groups: BTreeMap<(String, i64), BucketDataPoint> = BTreeMap::new();
+
+    for mut bucket in buckets {
+        let Some(group) = targets
+            .iter()
+            .find(|t| t.address == bucket.target)
+            .and_then(|t| group_by.group_of(t))
+        else {
+            continue;
+        };
+
+        match groups.entry((group.to_string(), bucket.timestamp_unix)) {
+            Entry::Occupied(mut entry) => merge_bucket(entry.get_mut(), &bucket),
+            Entry::Vacant(entry) => {
+                bucket.target = group.to_string();
+                bucket.target_name = Some(format!("{}:{}", key, group));
+                bucket.percentiles = None;
+                entry.insert(bucket);
+            }
+        }
+    }
+
+    // Keyed by (group, bucket start), so already sorted like ungrouped results
+    groups.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(address: &str, tags: &[&str]) -> Target {
+        Target {
+            id: address.to_string(),
+            address: address.to_string(),
+            ping_count: 1,
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn bucket(
+        target: &str,
+        start: i64,
+        avg: f64,
+        successful: usize,
+        failed: usize,
+    ) -> BucketDataPoint {
+        BucketDataPoint {
+            timestamp: String::new(),
+            timestamp_unix: start,
+            timestamp_end_unix: start + 60,
+            target: target.to_string(),
+            target_name: None,
+            min: Some(avg),
+            max: Some(avg),
+            avg: Some(avg),
+            percentiles: None,
+            count: successful + failed,
+            successful_count: successful,
+            failed_count: failed,
+            resolution: None,
+            complete: true,
+            loss_percent: None,
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            GroupBy::parse("tag:Site"),
+            Ok(GroupBy::Tag("site".to_string()))
+        );
+        assert!(GroupBy::parse("tag:").is_err());
+        assert_eq!(GroupBy::parse("site"), Ok(GroupBy::Site));
+        assert!(GroupBy::parse("sites").is_err());
+        assert!(GroupBy::parse("probe:site").is_err());
+    }
+
+    #[test]
+    fn test_group_buckets() {
+        let targets = vec![
+            target("10.0.0.1", &["site:berlin", "office"]),
+            target("10.0.0.2", &["Site:berlin"]),
+            target("10.1.0.1", &["site:paris"]),
+            target("10.2.0.1", &["office"]),
+        ];
+        let buckets = vec![
+            bucket("10.0.0.1", 0, 10.0, 3, 0),
+            bucket("10.0.0.2", 0, 20.0, 1, 2),
+            bucket("10.1.0.1", 0, 5.0, 3, 0),
+            bucket("10.0.0.1", 60, 12.0, 3, 0),
+            bucket("10.2.0.1", 0, 1.0, 3, 0),
+        ];
+
+        let grouped = group_buckets(buckets, &targets, &GroupBy::Tag("site".to_string()));
+        assert_eq!(grouped.len(), 3);
+
+        let berlin = &grouped[0];
+        assert_eq!(berlin.target, "berlin");
+        assert_eq!(berlin.target_name.as_deref(), Some("site:berlin"));
+        assert_eq!(berlin.timestamp_unix, 0);
+        assert_eq!(berlin.successful_count, 4);
+        assert_eq!(berlin.failed_count, 2);
+        assert_eq!(berlin.avg, Some(12.5));
+        assert_eq!((berlin.min, berlin.max), (Some(10.0), Some(20.0)));
+
+        assert_eq!(
+            (grouped[1].target.as_str(), grouped[1].timestamp_unix),
+            ("berlin", 60)
+        );
+        assert_eq!(grouped[2].target, "paris");
+    }
+}
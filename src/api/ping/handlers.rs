@@ -1,24 +1,54 @@
+use super::calendar::{parse_tz, LocalBuckets};
 use super::dto::{
-    PingAggregatedQuery, PingAggregatedResponse, PingDataQuery, PingDataResponse, QueryMetadata,
-    TimeRange,
+    HistogramQuery, HistogramResponse, PingAggregatedQuery, PingAggregatedResponse, PingDataPoint,
+    PingDataQuery, PingDataResponse, PingLiveQuery, PingTestPacket, PingTestRequest,
+    PingTestResponse, PruneQuery, QueryMetadata, StorageStatsResponse, SummaryQuery,
+    SummaryResponse, TimeRange, TrendQuery, TrendResponse,
 };
+use super::group::{group_buckets, GroupBy};
+use super::histogram::{
+    parse_bounds, LatencyHistogram, DEFAULT_BOUNDS_MS, MAX_RAW_RANGE_SECONDS, MAX_SLICES,
+};
+use super::labels::LabelFilter;
 use super::query::{
-    calculate_statistics, calculate_storage_stats, parse_bucket_duration,
-    query_ping_aggregated_chunked, query_ping_data_with_labels, resolve_time_range_value,
-    ResolvedPingDataQuery,
+    add_batch_statistics, annotate_buckets, calculate_statistics, parse_bucket_duration,
+    parse_relative_time_range, query_aggregated_chunked, query_ping_aggregated_cached,
+    query_ping_aggregated_with_rollups, query_ping_data_page, query_site_aggregated,
+    resolve_time_range_value, set_loss_percent, PingDataCursor, PingDataStream,
+    ResolvedPingDataQuery, PING_METRICS, STORAGE_SIZE_METRIC,
+};
+use super::summary::{
+    sort_summaries, sparkline_range, summarize, SummarySort, DEFAULT_SPARKLINE_BUCKETS,
+    MAX_SPARKLINE_BUCKETS,
 };
+use super::trend::{fit_trend, series_from_buckets, TREND_BUCKET_SECONDS};
+use crate::api::error::{ApiError, ErrorCode, ErrorResponse};
+use crate::api::extract::{Json, Query};
 use crate::api::AppState;
-use crate::config::Target;
+use crate::config::{ProbeType, Target, DEFAULT_TCP_PORT};
+use crate::integrity::{compact_partitions, verify_partitions, CompactReport, VerifyReport};
+use crate::live::{parse_max_rate, LiveCoalescer, LiveFilter};
+use crate::ping::{probe_once, Probe, ProbeOptions, PROBE_TIMEOUT};
+use crate::resolver::HostResolver;
+use crate::retention::PruneReport;
+use crate::storage::{SiteSelector, BATCH_LOSS_METRIC, JITTER_METRIC};
+use async_stream::stream;
 use axum::{
-    extract::{Query, State},
-    http::StatusCode,
-    response::Json,
+    extract::State,
+    response::sse::{Event, KeepAlive, Sse},
 };
+use futures::Stream;
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tracing::{error, info};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::MissedTickBehavior;
+use tracing::{error, info, warn};
 
 /// Look up a target's config by address (or id).
-fn find_target_config(state: &AppState, target_addr: &str) -> Option<Target> {
+pub(crate) fn find_target_config(state: &AppState, target_addr: &str) -> Option<Target> {
     let config = state.config.read().ok()?;
     config
         .targets
@@ -27,18 +57,59 @@ fn find_target_config(state: &AppState, target_addr: &str) -> Option<Target> {
         .cloned()
 }
 
+/// Addresses of the targets matching a `label` filter, None without one
+fn label_addresses(
+    state: &AppState,
+    label: Option<&str>,
+) -> Result<Option<HashSet<String>>, ApiError> {
+    let Some(label) = label else {
+        return Ok(None);
+    };
+    let filter = LabelFilter::parse(label).map_err(|e| {
+        ApiError::bad_request(ErrorCode::InvalidRequest, e)
+            .with_details(serde_json::json!({ "field": "label" }))
+    })?;
+    let config = state.config.read().map_err(|e| {
+        error!("Failed to read config: {}", e);
+        ApiError::internal(ErrorCode::ConfigUnavailable, "Failed to read configuration")
+    })?;
+    Ok(Some(filter.addresses(&config.targets)))
+}
+
+/// Raise a query start to the oldest stored data so open-ended raw queries
+/// don't walk empty time ranges
+pub(crate) fn clamp_query_start(state: &AppState, from: i64) -> i64 {
+    let now = chrono::Utc::now().timestamp();
+    from.max(state.storage.earliest_timestamp(now))
+}
+
 /// HTTP handler for GET /api/ping/data
+///
+/// Results are ordered by timestamp (then target, sequence and metric), so
+/// pages fetched with `limit` and `cursor` cover every result exactly once.
+#[utoipa::path(
+    get,
+    path = "/api/ping/data",
+    tag = "ping",
+    summary = "Raw ping results with statistics",
+    params(PingDataQuery),
+    responses(
+        (status = 200, description = "Ping results", body = PingDataResponse),
+        (status = 400, description = "Invalid time range, order or cursor", body = ErrorResponse),
+        (status = 500, description = "Storage query failed", body = ErrorResponse),
+    )
+)]
 pub(crate) async fn get_ping_data(
     State(state): State<AppState>,
     Query(query): Query<PingDataQuery>,
-) -> Result<Json<PingDataResponse>, (StatusCode, String)> {
+) -> Result<Json<PingDataResponse>, ApiError> {
     info!("Querying ping data: {:?}", query);
 
     // Resolve relative time range to absolute timestamp
     let resolved_from = if let Some(ref from_value) = query.from {
         resolve_time_range_value(from_value).map_err(|e| {
             error!("Invalid time range: {}", e);
-            (StatusCode::BAD_REQUEST, e)
+            ApiError::bad_request(ErrorCode::InvalidTimeRange, e)
         })?
     } else {
         0
@@ -48,6 +119,27 @@ pub(crate) async fn get_ping_data(
     // Store resolved timestamp for response metadata
     let resolved_from_timestamp = Some(resolved_from);
 
+    let descending = match query.order.as_deref() {
+        None | Some("asc") => false,
+        Some("desc") => true,
+        Some(other) => {
+            return Err(ApiError::bad_request(
+                ErrorCode::InvalidRequest,
+                format!("Unsupported order '{}' (expected asc or desc)", other),
+            )
+            .with_details(serde_json::json!({ "field": "order" })))
+        }
+    };
+    let after = query
+        .cursor
+        .as_deref()
+        .map(PingDataCursor::decode)
+        .transpose()
+        .map_err(|e| {
+            ApiError::bad_request(ErrorCode::InvalidRequest, e)
+                .with_details(serde_json::json!({ "field": "cursor" }))
+        })?;
+
     // Look up target config for fast-path label matching
     let target_config = query
         .target
@@ -58,38 +150,46 @@ pub(crate) async fn get_ping_data(
     let resolved_query = ResolvedPingDataQuery {
         target: query.target.clone(),
         target_config,
-        from: resolved_from,
+        from: clamp_query_start(&state, resolved_from),
         to: resolved_to,
         metric: query.metric.clone(),
         limit: query.limit,
+        addresses: label_addresses(&state, query.label.as_deref())?,
+        descending,
+        after,
+        site: SiteSelector::parse(query.site.as_deref()),
     };
 
     // Run blocking storage query on a dedicated thread to avoid blocking the async runtime
     let storage = Arc::clone(&state.storage);
-    let points = tokio::task::spawn_blocking(move || {
-        query_ping_data_with_labels(&*storage, &resolved_query)
+    let (points, next_cursor, statistics) = tokio::task::spawn_blocking(move || {
+        let (points, next_cursor) = query_ping_data_page(&*storage, &resolved_query)?;
+        let mut statistics = calculate_statistics(&points);
+        // Jitter and batch loss over the span of the returned results
+        if let Some(range) = time_range(&points) {
+            add_batch_statistics(
+                &*storage,
+                &mut statistics,
+                resolved_query.target.as_deref(),
+                resolved_query.target_config.as_ref(),
+                resolved_query.addresses.as_ref(),
+                range.earliest,
+                range.latest + 1,
+            )?;
+        }
+        Ok::<_, Box<dyn std::error::Error + Send + Sync>>((points, next_cursor, statistics))
     })
     .await
     .map_err(|e| {
         error!("Task join error: {}", e);
-        (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        ApiError::internal(ErrorCode::Internal, e.to_string())
     })?
     .map_err(|e| {
         error!("Error querying ping data: {}", e);
-        (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        ApiError::internal(ErrorCode::StorageError, e.to_string())
     })?;
 
-    let statistics = calculate_statistics(&points);
-
-    let data_time_range = if !points.is_empty() {
-        Some(TimeRange {
-            earliest: points.first().unwrap().timestamp_unix,
-            latest: points.last().unwrap().timestamp_unix,
-        })
-    } else {
-        None
-    };
-
+    let data_time_range = time_range(&points);
     let total_count = points.len();
 
     let response = PingDataResponse {
@@ -104,29 +204,64 @@ pub(crate) async fn get_ping_data(
         data: points,
         statistics,
         total_count,
+        next_cursor: next_cursor.map(|cursor| cursor.encode()),
     };
 
     Ok(Json(response))
 }
 
+/// Time range of results in either order
+fn time_range(points: &[PingDataPoint]) -> Option<TimeRange> {
+    let (first, last) = (points.first()?, points.last()?);
+    Some(TimeRange {
+        earliest: first.timestamp_unix.min(last.timestamp_unix),
+        latest: first.timestamp_unix.max(last.timestamp_unix),
+    })
+}
+
 /// HTTP handler for GET /api/ping/aggregated
+///
+/// Ping queries read downsampled 1m/1h rollups where available and fall
+/// back to raw data elsewhere; the resolution used is reported in the response.
+#[utoipa::path(
+    get,
+    path = "/api/ping/aggregated",
+    tag = "ping",
+    summary = "Ping results aggregated into time buckets",
+    params(PingAggregatedQuery),
+    responses(
+        (status = 200, description = "Buckets per target", body = PingAggregatedResponse),
+        (status = 400, description = "Invalid time range, bucket or time zone", body = ErrorResponse),
+        (status = 500, description = "Storage query failed", body = ErrorResponse),
+    )
+)]
 pub(crate) async fn get_ping_aggregated(
     State(state): State<AppState>,
     Query(query): Query<PingAggregatedQuery>,
-) -> Result<Json<PingAggregatedResponse>, (StatusCode, String)> {
+) -> Result<Json<PingAggregatedResponse>, ApiError> {
     info!("Querying aggregated ping data: {:?}", query);
 
     // Parse bucket duration
     let bucket_duration_seconds = parse_bucket_duration(&query.bucket).map_err(|e| {
         error!("Invalid bucket duration: {}", e);
-        (StatusCode::BAD_REQUEST, e)
+        ApiError::bad_request(ErrorCode::InvalidDuration, e)
     })?;
 
+    let group_by = query
+        .group_by
+        .as_deref()
+        .map(GroupBy::parse)
+        .transpose()
+        .map_err(|e| {
+            ApiError::bad_request(ErrorCode::InvalidRequest, e)
+                .with_details(serde_json::json!({ "field": "group_by" }))
+        })?;
+
     // Resolve relative time range to absolute timestamp
     let resolved_from = if let Some(ref from_value) = query.from {
         resolve_time_range_value(from_value).map_err(|e| {
             error!("Invalid time range: {}", e);
-            (StatusCode::BAD_REQUEST, e)
+            ApiError::bad_request(ErrorCode::InvalidTimeRange, e)
         })?
     } else {
         0
@@ -135,6 +270,61 @@ pub(crate) async fn get_ping_aggregated(
 
     let resolved_from_timestamp = Some(resolved_from);
     let include_percentiles = query.include_percentiles.unwrap_or(false);
+    if include_percentiles && group_by.is_some() {
+        return Err(ApiError::bad_request(
+            ErrorCode::InvalidRequest,
+            "Percentiles are not available for grouped buckets",
+        )
+        .with_details(serde_json::json!({ "field": "include_percentiles" })));
+    }
+
+    // Weeks and months follow the calendar (in UTC without tz), buckets of
+    // whole days or hours the local time of tz; all are merged from hourly
+    // (or 15 minute) buckets
+    let tz_error = |e: String| {
+        ApiError::bad_request(ErrorCode::InvalidRequest, e)
+            .with_details(serde_json::json!({ "field": "tz" }))
+    };
+    let tz = query
+        .tz
+        .as_deref()
+        .map(parse_tz)
+        .transpose()
+        .map_err(tz_error)?;
+    let calendar = LocalBuckets::calendar(tz.unwrap_or(chrono_tz::UTC), &query.bucket)
+        .map_err(|e| ApiError::bad_request(ErrorCode::InvalidDuration, e))?;
+    let local_buckets = match (calendar, tz) {
+        (Some(calendar), _) => Some(calendar),
+        (None, Some(tz)) => LocalBuckets::new(tz, bucket_duration_seconds).map_err(tz_error)?,
+        (None, None) => None,
+    };
+    if include_percentiles && local_buckets.is_some() {
+        return Err(ApiError::bad_request(
+            ErrorCode::InvalidRequest,
+            "Percentiles are not available for calendar or time zone aligned buckets",
+        )
+        .with_details(serde_json::json!({ "field": "include_percentiles" })));
+    }
+    let query_bucket_seconds = local_buckets.map_or(bucket_duration_seconds, |local| {
+        local.base_bucket_seconds(resolved_from, resolved_to)
+    });
+
+    // Grouping by site reads every site unless one is selected; mixing
+    // sites is only meaningful per site
+    let group_by_site = group_by == Some(GroupBy::Site);
+    let site = match query.site.as_deref() {
+        None if group_by_site => SiteSelector::All,
+        site => SiteSelector::parse(site),
+    };
+    if site == SiteSelector::All && !group_by_site {
+        return Err(ApiError::bad_request(
+            ErrorCode::InvalidRequest,
+            "Results of all sites can only be aggregated with group_by=site",
+        )
+        .with_details(serde_json::json!({ "field": "site" })));
+    }
+
+    let addresses = label_addresses(&state, query.label.as_deref())?;
 
     // Look up target config for fast-path label matching
     let target_config = query
@@ -145,27 +335,98 @@ pub(crate) async fn get_ping_aggregated(
     // Run blocking storage query on a dedicated thread to avoid blocking the async runtime
     let storage = Arc::clone(&state.storage);
     let target_filter = query.target.clone();
-    let (bucket_data, data_time_range) = tokio::task::spawn_blocking(move || {
-        query_ping_aggregated_chunked(
-            &*storage,
-            target_filter.as_deref(),
-            target_config.as_ref(),
-            resolved_from,
-            resolved_to,
-            bucket_duration_seconds,
-            include_percentiles,
-        )
+    let site_addresses = addresses.clone();
+    // Metrics other than ping results are aggregated from raw points
+    let raw_metric = match query.metric.as_deref() {
+        Some("storage_size") => Some(STORAGE_SIZE_METRIC),
+        Some("jitter") => Some(JITTER_METRIC),
+        Some("batch_loss") => Some(BATCH_LOSS_METRIC),
+        _ => None,
+    };
+    let coverage = state.downsampler.coverage();
+    let writer = Arc::clone(&state.writer);
+    let now = chrono::Utc::now().timestamp();
+    let (bucket_data, data_time_range, resolution) = tokio::task::spawn_blocking(move || {
+        // Agents' results have no rollups or cached buckets
+        if site != SiteSelector::Local || group_by_site {
+            let metrics = raw_metric.map_or(PING_METRICS.to_vec(), |m| vec![m]);
+            let target_filter = target_config
+                .as_ref()
+                .map(|t| t.address.clone())
+                .or(target_filter);
+            let (buckets, time_range) = query_site_aggregated(
+                &*storage,
+                &metrics,
+                target_filter.as_deref(),
+                site_addresses.as_ref(),
+                &site,
+                group_by_site,
+                resolved_from,
+                resolved_to,
+                query_bucket_seconds,
+                include_percentiles,
+            )?;
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>((buckets, time_range, "raw"))
+        } else if let Some(metric) = raw_metric {
+            let (buckets, time_range) = query_aggregated_chunked(
+                &*storage,
+                &[metric],
+                target_filter.as_deref(),
+                target_config.as_ref(),
+                resolved_from,
+                resolved_to,
+                query_bucket_seconds,
+                include_percentiles,
+            )?;
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>((buckets, time_range, "raw"))
+        } else {
+            query_ping_aggregated_cached(
+                writer.aggregate_cache(),
+                &*storage,
+                &coverage,
+                target_filter.as_deref(),
+                target_config.as_ref(),
+                resolved_from,
+                resolved_to,
+                query_bucket_seconds,
+                include_percentiles,
+                now,
+            )
+        }
     })
     .await
     .map_err(|e| {
         error!("Task join error: {}", e);
-        (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        ApiError::internal(ErrorCode::Internal, e.to_string())
     })?
     .map_err(|e| {
         error!("Error querying aggregated ping data: {}", e);
-        (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        ApiError::internal(ErrorCode::StorageError, e.to_string())
     })?;
 
+    let mut bucket_data = match local_buckets {
+        Some(local) => local.regroup(bucket_data),
+        None => bucket_data,
+    };
+    if let Some(addresses) = addresses.as_ref().filter(|_| !group_by_site) {
+        bucket_data.retain(|bucket| addresses.contains(&bucket.target));
+    }
+    let mut bucket_data = match &group_by {
+        Some(group_by) => {
+            let config = state.config.read().map_err(|e| {
+                error!("Failed to read config: {}", e);
+                ApiError::internal(ErrorCode::ConfigUnavailable, "Failed to read configuration")
+            })?;
+            group_buckets(bucket_data, &config.targets, group_by)
+        }
+        None => bucket_data,
+    };
+    // Buckets reaching past the range or into the future are partial
+    let complete_until = resolved_to.min(chrono::Utc::now().timestamp());
+    annotate_buckets(&mut bucket_data, resolved_from, complete_until, resolution);
+    if raw_metric.is_none() {
+        set_loss_percent(&mut bucket_data, query.metric.as_deref() == Some("loss"));
+    }
     let total_count = bucket_data.len();
 
     let response = PingAggregatedResponse {
@@ -180,33 +441,840 @@ pub(crate) async fn get_ping_aggregated(
         data: bucket_data,
         total_count,
         bucket_duration_seconds,
+        resolution: resolution.to_string(),
+        group_by: group_by.and(query.group_by),
+        tz: local_buckets.and(query.tz),
     };
 
     Ok(Json(response))
 }
 
-/// HTTP handler for GET /api/storage/stats
-pub(crate) async fn get_storage_stats(
+/// HTTP handler for GET /api/ping/summary
+///
+/// Overview of every target for the dashboard: state, last latency and
+/// last-hour latency and loss from the live rollups, plus 24h figures and
+/// sparkline buckets from one aggregated query over all targets.
+#[utoipa::path(
+    get,
+    path = "/api/ping/summary",
+    tag = "ping",
+    summary = "Status, latency, loss and sparklines of every target",
+    params(SummaryQuery),
+    responses(
+        (status = 200, description = "Summary per target", body = SummaryResponse),
+        (status = 400, description = "Invalid bucket count or sort", body = ErrorResponse),
+        (status = 500, description = "Storage query failed", body = ErrorResponse),
+    )
+)]
+pub(crate) async fn get_ping_summary(
     State(state): State<AppState>,
-) -> Result<Json<super::dto::StorageStatsResponse>, (StatusCode, String)> {
-    let config = state.config.read().map_err(|e| {
-        error!("Failed to read config: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to read configuration".to_string(),
+    Query(query): Query<SummaryQuery>,
+) -> Result<Json<SummaryResponse>, ApiError> {
+    let count = query.buckets.unwrap_or(DEFAULT_SPARKLINE_BUCKETS);
+    if count == 0 || count > MAX_SPARKLINE_BUCKETS {
+        return Err(ApiError::bad_request(
+            ErrorCode::InvalidRequest,
+            format!("Buckets must be between 1 and {}", MAX_SPARKLINE_BUCKETS),
         )
+        .with_details(serde_json::json!({ "field": "buckets" })));
+    }
+    let sort = query
+        .sort
+        .as_deref()
+        .filter(|s| !s.is_empty())
+        .map(SummarySort::parse)
+        .transpose()
+        .map_err(|e| {
+            ApiError::bad_request(ErrorCode::InvalidRequest, e)
+                .with_details(serde_json::json!({ "field": "sort" }))
+        })?;
+
+    let now = chrono::Utc::now().timestamp();
+    let targets = state
+        .config
+        .read()
+        .map_err(|e| {
+            error!("Failed to read config: {}", e);
+            ApiError::internal(ErrorCode::ConfigUnavailable, "Failed to read configuration")
+        })?
+        .targets
+        .clone();
+    let rollups: Vec<_> = targets
+        .iter()
+        .map(|t| state.rollups.rollups(&t.id, now))
+        .collect();
+
+    let (bucket_duration, from) = sparkline_range(now, count);
+    let storage = Arc::clone(&state.storage);
+    let coverage = state.downsampler.coverage();
+    let (buckets, _, resolution) = tokio::task::spawn_blocking(move || {
+        query_ping_aggregated_with_rollups(
+            &*storage,
+            &coverage,
+            None,
+            None,
+            from,
+            now,
+            bucket_duration,
+            false,
+        )
+    })
+    .await
+    .map_err(|e| {
+        error!("Task join error: {}", e);
+        ApiError::internal(ErrorCode::Internal, e.to_string())
+    })?
+    .map_err(|e| {
+        error!("Error querying ping summary: {}", e);
+        ApiError::internal(ErrorCode::StorageError, e.to_string())
     })?;
 
-    let data_path = config.database.path.clone();
-    drop(config);
+    let mut summaries = summarize(&targets, &rollups, &buckets, from, bucket_duration, count);
+    if let Some(sort) = sort {
+        sort_summaries(&mut summaries, sort);
+    }
+    if let Some(limit) = query.limit {
+        summaries.truncate(limit);
+    }
+
+    Ok(Json(SummaryResponse {
+        timestamp_unix: now,
+        bucket_duration_seconds: bucket_duration,
+        resolution: resolution.to_string(),
+        targets: summaries,
+    }))
+}
+
+/// HTTP handler for GET /api/ping/histogram
+///
+/// Counts a target's latencies into bins per time slice for heatmaps (how
+/// often pings took 1-2ms, 2-5ms, ... in each hour), which show bimodal
+/// latency and queueing that averages and percentiles hide.
+#[utoipa::path(
+    get,
+    path = "/api/ping/histogram",
+    tag = "ping",
+    summary = "Latency distribution per time slice",
+    params(HistogramQuery),
+    responses(
+        (status = 200, description = "Latency histogram of the target", body = HistogramResponse),
+        (status = 400, description = "Invalid time range, slice, bounds or source", body = ErrorResponse),
+        (status = 404, description = "Target not found", body = ErrorResponse),
+        (status = 500, description = "Storage query failed", body = ErrorResponse),
+    )
+)]
+pub(crate) async fn get_ping_histogram(
+    State(state): State<AppState>,
+    Query(query): Query<HistogramQuery>,
+) -> Result<Json<HistogramResponse>, ApiError> {
+    info!("Querying latency histogram: {:?}", query);
 
-    let stats = calculate_storage_stats(&data_path).map_err(|e| {
-        error!("Failed to calculate storage stats: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to calculate storage stats: {}", e),
+    let target = find_target_config(&state, &query.target).ok_or_else(|| {
+        ApiError::not_found(
+            ErrorCode::TargetNotFound,
+            format!("Target '{}' not found", query.target),
         )
     })?;
+    let slice_seconds =
+        parse_bucket_duration(query.slice.as_deref().unwrap_or("1h")).map_err(|e| {
+            ApiError::bad_request(ErrorCode::InvalidDuration, e)
+                .with_details(serde_json::json!({ "field": "slice" }))
+        })?;
+    let bounds = match query.bounds.as_deref() {
+        Some(bounds) => parse_bounds(bounds).map_err(|e| {
+            ApiError::bad_request(ErrorCode::InvalidRequest, e)
+                .with_details(serde_json::json!({ "field": "bounds" }))
+        })?,
+        None => DEFAULT_BOUNDS_MS.to_vec(),
+    };
+
+    let to = query.to.unwrap_or_else(|| chrono::Utc::now().timestamp());
+    let from = match query.from {
+        Some(ref from) => resolve_time_range_value(from).map_err(|e| {
+            error!("Invalid time range: {}", e);
+            ApiError::bad_request(ErrorCode::InvalidTimeRange, e)
+        })?,
+        None => to - 86400,
+    };
+    if from >= to {
+        return Err(ApiError::bad_request(
+            ErrorCode::InvalidTimeRange,
+            "The range must start before it ends",
+        )
+        .with_details(serde_json::json!({ "field": "from" })));
+    }
+    if (to - from) / slice_seconds >= MAX_SLICES {
+        return Err(ApiError::bad_request(
+            ErrorCode::InvalidRequest,
+            format!(
+                "The range would have more than {} slices; use a larger slice or a shorter range",
+                MAX_SLICES
+            ),
+        )
+        .with_details(serde_json::json!({ "field": "slice" })));
+    }
+
+    let rollup = match query.source.as_deref() {
+        None => to - from > MAX_RAW_RANGE_SECONDS,
+        Some("raw") => false,
+        Some("rollup") => true,
+        Some(other) => {
+            return Err(ApiError::bad_request(
+                ErrorCode::InvalidRequest,
+                format!("Unsupported source '{}' (expected raw or rollup)", other),
+            )
+            .with_details(serde_json::json!({ "field": "source" })));
+        }
+    };
+    if rollup && slice_seconds % 60 != 0 {
+        return Err(ApiError::bad_request(
+            ErrorCode::InvalidDuration,
+            "Slices of rollup histograms must be whole minutes",
+        )
+        .with_details(serde_json::json!({ "field": "slice" })));
+    }
+
+    let mut histogram = LatencyHistogram::new(bounds.clone(), from, to, slice_seconds);
+    let raw_from = clamp_query_start(&state, from);
+    let storage = Arc::clone(&state.storage);
+    let coverage = state.downsampler.coverage();
+    let (target_id, address) = (target.id.clone(), target.address.clone());
+    let (slices, resolution) = tokio::task::spawn_blocking(move || {
+        let resolution = if rollup {
+            let (buckets, _, resolution) = query_ping_aggregated_with_rollups(
+                &*storage,
+                &coverage,
+                Some(&target.address),
+                Some(&target),
+                from,
+                to,
+                60,
+                false,
+            )?;
+            for bucket in &buckets {
+                histogram.add_bucket(bucket);
+            }
+            resolution
+        } else {
+            let resolved_query = ResolvedPingDataQuery {
+                target: Some(target.address.clone()),
+                target_config: Some(target),
+                from: raw_from,
+                to,
+                metric: None,
+                limit: None,
+                addresses: None,
+                descending: false,
+                after: None,
+                site: SiteSelector::Local,
+            };
+            for point in PingDataStream::new(&*storage, &resolved_query) {
+                histogram.add_point(&point?);
+            }
+            "raw"
+        };
+        Ok::<_, Box<dyn std::error::Error + Send + Sync>>((histogram.into_slices(), resolution))
+    })
+    .await
+    .map_err(|e| {
+        error!("Task join error: {}", e);
+        ApiError::internal(ErrorCode::Internal, e.to_string())
+    })?
+    .map_err(|e| {
+        error!("Error querying latency histogram: {}", e);
+        ApiError::internal(ErrorCode::StorageError, e.to_string())
+    })?;
+
+    Ok(Json(HistogramResponse {
+        target_id,
+        target: address,
+        from_timestamp: from,
+        to_timestamp: to,
+        slice_seconds,
+        bounds,
+        resolution: resolution.to_string(),
+        slices,
+    }))
+}
+
+/// Longest window a trend may be fitted on
+const MAX_TREND_WINDOW_SECONDS: i64 = 90 * 86400;
+/// Longest trend forecast
+const MAX_TREND_HORIZON_SECONDS: i64 = 30 * 86400;
+
+/// Parse a `window`/`horizon` duration, falling back to `default` and
+/// rejecting values above `max`
+fn parse_trend_duration(
+    field: &str,
+    value: Option<&str>,
+    default: &str,
+    max: i64,
+) -> Result<i64, ApiError> {
+    let seconds = parse_relative_time_range(value.unwrap_or(default)).map_err(|e| {
+        ApiError::bad_request(ErrorCode::InvalidDuration, e)
+            .with_details(serde_json::json!({ "field": field }))
+    })?;
+    if seconds <= 0 || seconds > max {
+        return Err(ApiError::bad_request(
+            ErrorCode::InvalidDuration,
+            format!(
+                "{} must be positive and at most {} days",
+                field,
+                max / 86400
+            ),
+        )
+        .with_details(serde_json::json!({ "field": field })));
+    }
+    Ok(seconds)
+}
+
+/// HTTP handler for GET /api/ping/trend
+///
+/// Fits a linear trend plus daily profile to hourly latency and loss of a
+/// target over `window` and forecasts `horizon` ahead with 95% bands, to spot
+/// slow degradation (failing cable, overloaded AP) before it causes outages.
+#[utoipa::path(
+    get,
+    path = "/api/ping/trend",
+    tag = "ping",
+    summary = "Latency and loss trend with forecast",
+    params(TrendQuery),
+    responses(
+        (status = 200, description = "Trend of the target", body = TrendResponse),
+        (status = 400, description = "Invalid window, horizon or time zone", body = ErrorResponse),
+        (status = 404, description = "Target not found", body = ErrorResponse),
+    )
+)]
+pub(crate) async fn get_ping_trend(
+    State(state): State<AppState>,
+    Query(query): Query<TrendQuery>,
+) -> Result<Json<TrendResponse>, ApiError> {
+    info!("Computing ping trend: {:?}", query);
+
+    let target = find_target_config(&state, &query.target_id).ok_or_else(|| {
+        ApiError::not_found(
+            ErrorCode::TargetNotFound,
+            format!("Target '{}' not found", query.target_id),
+        )
+    })?;
+    let window = parse_trend_duration(
+        "window",
+        query.window.as_deref(),
+        "30d",
+        MAX_TREND_WINDOW_SECONDS,
+    )?;
+    let horizon = parse_trend_duration(
+        "horizon",
+        query.horizon.as_deref(),
+        "7d",
+        MAX_TREND_HORIZON_SECONDS,
+    )?;
+
+    let tz = query
+        .tz
+        .as_deref()
+        .map(parse_tz)
+        .transpose()
+        .map_err(|e| {
+            ApiError::bad_request(ErrorCode::InvalidRequest, e)
+                .with_details(serde_json::json!({ "field": "tz" }))
+        })?
+        .unwrap_or(chrono_tz::UTC);
+
+    let to = chrono::Utc::now().timestamp();
+    let from = to - window;
+
+    let storage = Arc::clone(&state.storage);
+    let coverage = state.downsampler.coverage();
+    let target_id = target.id.clone();
+    let (latency, loss) = tokio::task::spawn_blocking(move || {
+        let (buckets, _, _) = query_ping_aggregated_with_rollups(
+            &*storage,
+            &coverage,
+            Some(&target.address),
+            Some(&target),
+            from,
+            to,
+            TREND_BUCKET_SECONDS,
+            false,
+        )?;
+        // The current hour is incomplete
+        let complete: Vec<_> = buckets
+            .into_iter()
+            .filter(|b| b.timestamp_end_unix <= to)
+            .collect();
+        let (latency, loss) = series_from_buckets(&complete);
+        Ok::<_, Box<dyn std::error::Error + Send + Sync>>((
+            fit_trend(&latency, to, horizon, 0.0, f64::MAX, tz),
+            fit_trend(&loss, to, horizon, 0.0, 100.0, tz),
+        ))
+    })
+    .await
+    .map_err(|e| {
+        error!("Task join error: {}", e);
+        ApiError::internal(ErrorCode::Internal, e.to_string())
+    })?
+    .map_err(|e| {
+        error!("Error querying trend data: {}", e);
+        ApiError::internal(ErrorCode::StorageError, e.to_string())
+    })?;
+
+    Ok(Json(TrendResponse {
+        target_id,
+        from,
+        to,
+        latency,
+        loss,
+    }))
+}
+
+/// HTTP handler for GET /api/ping/live (SSE endpoint)
+///
+/// Streams every new ping result as a `ping` event, shaped like the points of
+/// /api/ping/data, optionally for selected targets (`target`/`targets`,
+/// address or id). With `max_rate`, results are coalesced per target into at
+/// most one `summary` event per interval, so wallboards following many fast
+/// targets aren't flooded. Clients that fall behind receive a `lagged` event
+/// with the number of skipped results instead of slowing down the ping tasks.
+#[utoipa::path(
+    get,
+    path = "/api/ping/live",
+    tag = "ping",
+    summary = "Stream ping results as they are written (Server-Sent Events)",
+    params(PingLiveQuery),
+    responses(
+        (status = 200, description = "`ping` events with a PingDataPoint, or `summary` events with `max_rate`", content_type = "text/event-stream", body = String),
+        (status = 400, description = "Invalid max_rate", body = ErrorResponse),
+    )
+)]
+pub(crate) async fn get_ping_live(
+    State(state): State<AppState>,
+    Query(query): Query<PingLiveQuery>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let frame_interval = query
+        .max_rate
+        .as_deref()
+        .map(parse_max_rate)
+        .transpose()
+        .map_err(|e| {
+            ApiError::bad_request(ErrorCode::InvalidRequest, e)
+                .with_details(serde_json::json!({ "field": "max_rate" }))
+        })?;
+    info!(
+        "Live ping stream opened (target: {:?}, targets: {:?}, site: {:?}, max_rate: {:?})",
+        query.target, query.targets, query.site, query.max_rate
+    );
+
+    let mut rx = state.live.subscribe();
+    let filter = LiveFilter::new(query.target.as_deref(), query.targets.as_deref())
+        .with_site(SiteSelector::parse(query.site.as_deref()));
+    let mut ticker = frame_interval.map(|interval| {
+        let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        ticker
+    });
+
+    let stream = stream! {
+        let mut coalescer = LiveCoalescer::default();
+        loop {
+            // None when the next summary frame is due
+            let received = match ticker.as_mut() {
+                Some(ticker) => tokio::select! {
+                    received = rx.recv() => Some(received),
+                    _ = ticker.tick() => None,
+                },
+                None => Some(rx.recv().await),
+            };
+
+            match received {
+                Some(Ok(live)) => {
+                    if !filter.matches(&live) {
+                        continue;
+                    }
+                    if ticker.is_some() {
+                        coalescer.add(&live);
+                        continue;
+                    }
+                    match serde_json::to_string(&live.point) {
+                        Ok(json) => yield Ok(Event::default().event("ping").data(json)),
+                        Err(e) => error!("Failed to serialize live ping result: {}", e),
+                    }
+                }
+                Some(Err(RecvError::Lagged(skipped))) => {
+                    warn!("Live ping client fell behind, skipped {} results", skipped);
+                    let data = serde_json::json!({ "skipped": skipped }).to_string();
+                    yield Ok(Event::default().event("lagged").data(data));
+                }
+                Some(Err(RecvError::Closed)) => break,
+                None => {
+                    let Some(summary) = coalescer.flush() else {
+                        continue;
+                    };
+                    match serde_json::to_string(&summary) {
+                        Ok(json) => yield Ok(Event::default().event("summary").data(json)),
+                        Err(e) => error!("Failed to serialize live summary: {}", e),
+                    }
+                }
+            }
+        }
+    };
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// Maximum number of probes of a ping test
+const MAX_TEST_COUNT: u16 = 10;
+/// Maximum per-probe timeout of a ping test
+const MAX_TEST_TIMEOUT_MS: u64 = 10_000;
+/// Pause between the probes of a ping test
+const TEST_PROBE_INTERVAL: Duration = Duration::from_millis(200);
+
+/// HTTP handler for POST /api/ping/test
+///
+/// Probes an address right away and returns per-probe latencies, e.g. to
+/// check a target before adding it. Nothing is written to storage.
+#[utoipa::path(
+    post,
+    path = "/api/ping/test",
+    tag = "ping",
+    summary = "Probe an address once without storing results",
+    request_body = PingTestRequest,
+    responses(
+        (status = 200, description = "Probe results", body = PingTestResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+    )
+)]
+pub(crate) async fn test_ping(
+    State(state): State<AppState>,
+    Json(request): Json<PingTestRequest>,
+) -> Result<Json<PingTestResponse>, ApiError> {
+    let address = request.address.trim().to_string();
+    if address.is_empty() {
+        return Err(
+            ApiError::bad_request(ErrorCode::InvalidRequest, "Address is required")
+                .with_details(serde_json::json!({ "field": "address" })),
+        );
+    }
+    let count = request.count.unwrap_or(3);
+    if count == 0 || count > MAX_TEST_COUNT {
+        return Err(ApiError::bad_request(
+            ErrorCode::InvalidRequest,
+            format!("Count must be between 1 and {}", MAX_TEST_COUNT),
+        )
+        .with_details(serde_json::json!({ "field": "count" })));
+    }
+    let timeout_ms = request
+        .timeout_ms
+        .unwrap_or(PROBE_TIMEOUT.as_millis() as u64);
+    if timeout_ms == 0 || timeout_ms > MAX_TEST_TIMEOUT_MS {
+        return Err(ApiError::bad_request(
+            ErrorCode::InvalidRequest,
+            format!("Timeout must be between 1 and {} ms", MAX_TEST_TIMEOUT_MS),
+        )
+        .with_details(serde_json::json!({ "field": "timeout_ms" })));
+    }
+    if request.port == Some(0) {
+        return Err(ApiError::bad_request(
+            ErrorCode::InvalidRequest,
+            "Port must be between 1 and 65535",
+        )
+        .with_details(serde_json::json!({ "field": "port" })));
+    }
+
+    let probe = match request.probe_type.unwrap_or_default() {
+        ProbeType::Tcp => Probe::Tcp(request.port.unwrap_or(DEFAULT_TCP_PORT)),
+        ProbeType::Icmp => {
+            let socket_type = match request.socket_type {
+                Some(socket_type) => socket_type,
+                None => {
+                    let config = state.config.read().map_err(|e| {
+                        error!("Failed to read config: {}", e);
+                        ApiError::internal(
+                            ErrorCode::ConfigUnavailable,
+                            "Failed to read configuration",
+                        )
+                    })?;
+                    config.ping.socket_type
+                }
+            };
+            Probe::Icmp(socket_type)
+        }
+    };
+    let (port, socket_type) = match probe {
+        Probe::Tcp(port) => (Some(port), None),
+        Probe::Icmp(socket_type) => (None, Some(socket_type)),
+    };
+
+    info!("Testing {} with {} {:?} probes", address, count, probe);
+
+    let mut response = PingTestResponse {
+        address: address.clone(),
+        resolved_ip: None,
+        probe_type: probe.probe_type(),
+        port,
+        socket_type,
+        packets: Vec::new(),
+        sent: 0,
+        received: 0,
+        loss_percent: 0.0,
+        min_latency_ms: None,
+        avg_latency_ms: None,
+        max_latency_ms: None,
+        error: None,
+    };
+
+    let ip_addr = match HostResolver::new(&address, 0).resolve().await {
+        Ok(ip) => ip,
+        Err(e) => {
+            response.error = Some(e);
+            return Ok(Json(response));
+        }
+    };
+    response.resolved_ip = Some(ip_addr.to_string());
+
+    let options = ProbeOptions::with_timeout(Duration::from_millis(timeout_ms));
+    for sequence in 1..=count {
+        if sequence > 1 {
+            tokio::time::sleep(TEST_PROBE_INTERVAL).await;
+        }
+        let packet = match probe_once(ip_addr, probe, options, None).await {
+            Ok(latency_ms) => PingTestPacket {
+                sequence,
+                success: true,
+                latency_ms: Some(latency_ms),
+                error: None,
+            },
+            Err(e) => PingTestPacket {
+                sequence,
+                success: false,
+                latency_ms: None,
+                error: Some(e.to_string()),
+            },
+        };
+        response.packets.push(packet);
+    }
+
+    let latencies: Vec<f64> = response
+        .packets
+        .iter()
+        .filter_map(|p| p.latency_ms)
+        .collect();
+    response.sent = response.packets.len();
+    response.received = latencies.len();
+    response.loss_percent =
+        (response.sent - response.received) as f64 / response.sent as f64 * 100.0;
+    if !latencies.is_empty() {
+        response.min_latency_ms = latencies.iter().copied().reduce(f64::min);
+        response.max_latency_ms = latencies.iter().copied().reduce(f64::max);
+        response.avg_latency_ms = Some(latencies.iter().sum::<f64>() / latencies.len() as f64);
+    }
+
+    Ok(Json(response))
+}
+
+/// HTTP handler for GET /api/storage/stats
+#[utoipa::path(
+    get,
+    path = "/api/storage/stats",
+    tag = "storage",
+    summary = "Storage size and data points per target",
+    responses(
+        (status = 200, description = "Storage statistics", body = StorageStatsResponse),
+        (status = 500, description = "Storage could not be read", body = ErrorResponse),
+    )
+)]
+pub(crate) async fn get_storage_stats(
+    State(state): State<AppState>,
+) -> Result<Json<StorageStatsResponse>, ApiError> {
+    let storage = Arc::clone(&state.storage);
+    let stats = tokio::task::spawn_blocking(move || storage.stats())
+        .await
+        .map_err(|e| {
+            error!("Task join error: {}", e);
+            ApiError::internal(ErrorCode::Internal, e.to_string())
+        })?
+        .map_err(|e| {
+            error!("Failed to calculate storage stats: {}", e);
+            ApiError::internal(
+                ErrorCode::StorageError,
+                format!("Failed to calculate storage stats: {}", e),
+            )
+        })?;
 
     Ok(Json(stats))
 }
+
+/// HTTP handler for POST /api/storage/prune
+///
+/// Prunes ping data past each target's retention right away instead of
+/// waiting for the background task. `?dry_run=true` only reports what would
+/// be pruned.
+#[utoipa::path(
+    post,
+    path = "/api/storage/prune",
+    tag = "storage",
+    summary = "Delete ping data past its retention",
+    params(PruneQuery),
+    responses(
+        (status = 200, description = "What was (or would be) pruned", body = PruneReport),
+        (status = 500, description = "Prune failed", body = ErrorResponse),
+    )
+)]
+pub(crate) async fn prune_storage(
+    State(state): State<AppState>,
+    Query(query): Query<PruneQuery>,
+) -> Result<Json<PruneReport>, ApiError> {
+    let (default_days, targets) = {
+        let config = state.config.read().map_err(|e| {
+            error!("Failed to read config: {}", e);
+            ApiError::internal(ErrorCode::ConfigUnavailable, "Failed to read configuration")
+        })?;
+        (config.database.retention_days, config.targets.clone())
+    };
+
+    let now = chrono::Utc::now().timestamp();
+    let storage = Arc::clone(&state.storage);
+    let writer = Arc::clone(&state.writer);
+    let report = tokio::task::spawn_blocking(move || {
+        let report = storage.prune(&targets, default_days, now, query.dry_run)?;
+        if !query.dry_run {
+            writer.aggregate_cache().clear();
+        }
+        Ok::<_, crate::storage::StorageError>(report)
+    })
+    .await
+    .map_err(|e| {
+        error!("Task join error: {}", e);
+        ApiError::internal(ErrorCode::Internal, e.to_string())
+    })?
+    .map_err(|e| {
+        error!("Failed to prune storage: {}", e);
+        ApiError::internal(
+            ErrorCode::StorageError,
+            format!("Failed to prune storage: {}", e),
+        )
+    })?;
+
+    Ok(Json(report))
+}
+
+/// Partition directory of the storage. Verify and compact work on tsink
+/// partitions, so backends without them are rejected.
+fn storage_data_path(state: &AppState) -> Result<PathBuf, ApiError> {
+    state
+        .storage
+        .partition_dir()
+        .map(Path::to_path_buf)
+        .ok_or_else(|| {
+            ApiError::bad_request(
+                ErrorCode::InvalidRequest,
+                format!(
+                    "Not supported by the {} storage backend",
+                    state.storage.name()
+                ),
+            )
+        })
+}
+
+/// Verify the partitions, moving corrupt ones into quarantine if
+/// `quarantine` is set
+async fn run_verify(state: &AppState, quarantine: bool) -> Result<VerifyReport, ApiError> {
+    let data_path = storage_data_path(state)?;
+
+    let now = chrono::Utc::now().timestamp();
+    tokio::task::spawn_blocking(move || verify_partitions(&data_path, now, quarantine))
+        .await
+        .map_err(|e| {
+            error!("Task join error: {}", e);
+            ApiError::internal(ErrorCode::Internal, e.to_string())
+        })?
+        .map_err(|e| {
+            error!("Failed to verify storage: {}", e);
+            ApiError::internal(
+                ErrorCode::StorageError,
+                format!("Failed to verify storage: {}", e),
+            )
+        })
+}
+
+/// HTTP handler for GET /api/storage/verify
+///
+/// Checks every partition's metadata against its data file and lists the
+/// corrupt ones without changing anything; see POST /api/storage/quarantine.
+#[utoipa::path(
+    get,
+    path = "/api/storage/verify",
+    tag = "storage",
+    summary = "Check storage partitions for corruption",
+    responses(
+        (status = 200, description = "Corrupt partitions found", body = VerifyReport),
+        (status = 500, description = "Verification failed", body = ErrorResponse),
+    )
+)]
+pub(crate) async fn verify_storage(
+    State(state): State<AppState>,
+) -> Result<Json<VerifyReport>, ApiError> {
+    Ok(Json(run_verify(&state, false).await?))
+}
+
+/// HTTP handler for POST /api/storage/quarantine
+///
+/// Verifies the partitions like GET /api/storage/verify and moves the
+/// corrupt ones to `<data path>/quarantine/`, so tsink no longer loads them
+/// on the next start.
+#[utoipa::path(
+    post,
+    path = "/api/storage/quarantine",
+    tag = "storage",
+    summary = "Move corrupt storage partitions into quarantine",
+    responses(
+        (status = 200, description = "Corrupt partitions found and quarantined", body = VerifyReport),
+        (status = 500, description = "Verification failed", body = ErrorResponse),
+    )
+)]
+pub(crate) async fn quarantine_storage(
+    State(state): State<AppState>,
+) -> Result<Json<VerifyReport>, ApiError> {
+    let report = run_verify(&state, true).await?;
+    // Quarantined partitions are no longer read
+    state.writer.aggregate_cache().clear();
+
+    Ok(Json(report))
+}
+
+/// HTTP handler for POST /api/storage/compact
+///
+/// Rewrites partitions whose data file holds bytes no series refers to.
+/// Corrupt partitions are skipped; see POST /api/storage/quarantine.
+#[utoipa::path(
+    post,
+    path = "/api/storage/compact",
+    tag = "storage",
+    summary = "Compact storage partitions",
+    responses(
+        (status = 200, description = "Compacted partitions and reclaimed bytes", body = CompactReport),
+        (status = 500, description = "Compaction failed", body = ErrorResponse),
+    )
+)]
+pub(crate) async fn compact_storage(
+    State(state): State<AppState>,
+) -> Result<Json<CompactReport>, ApiError> {
+    let data_path = storage_data_path(&state)?;
+
+    let now = chrono::Utc::now().timestamp();
+    let report = tokio::task::spawn_blocking(move || compact_partitions(&data_path, now))
+        .await
+        .map_err(|e| {
+            error!("Task join error: {}", e);
+            ApiError::internal(ErrorCode::Internal, e.to_string())
+        })?
+        .map_err(|e| {
+            error!("Failed to compact storage: {}", e);
+            ApiError::internal(
+                ErrorCode::StorageError,
+                format!("Failed to compact storage: {}", e),
+            )
+        })?;
+
+    Ok(Json(report))
+}
@@ -0,0 +1,208 @@
+//! Latency distributions for GET /api/ping/histogram.
+//!
+//! Latencies are counted into bins between configurable bounds, one row of
+//! bins per time slice, for smokeping-style heatmaps. Raw results are counted
+//! one by one. Over longer ranges the 1-minute rollups are read instead and
+//! each minute's average counts once per successful ping, so spread within a
+//! minute is lost.
+
+use super::dto::{BucketDataPoint, HistogramSlice, PingDataPoint};
+
+/// Default bin bounds in milliseconds
+pub(crate) const DEFAULT_BOUNDS_MS: &[f64] =
+    &[1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0, 200.0, 500.0, 1000.0];
+
+/// Most bin bounds a request may set
+const MAX_BOUNDS: usize = 64;
+
+/// Most time slices of a response
+pub(crate) const MAX_SLICES: i64 = 2000;
+
+/// Ranges up to this length are counted from raw results by default
+pub(crate) const MAX_RAW_RANGE_SECONDS: i64 = 2 * 86400;
+
+/// Parse comma-separated bin bounds in milliseconds; they must be positive
+/// and increasing
+pub(crate) fn parse_bounds(value: &str) -> Result<Vec<f64>, String> {
+    let bounds = value
+        .split(',')
+        .map(|b| {
+            b.trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|b| b.is_finite() && *b > 0.0)
+                .ok_or_else(|| format!("Invalid bound '{}' (expected milliseconds)", b.trim()))
+        })
+        .collect::<Result<Vec<f64>, String>>()?;
+    if bounds.len() > MAX_BOUNDS {
+        return Err(format!("At most {} bounds are supported", MAX_BOUNDS));
+    }
+    if bounds.windows(2).any(|w| w[0] >= w[1]) {
+        return Err("Bounds must be increasing".to_string());
+    }
+    Ok(bounds)
+}
+
+/// Latency counts per time slice
+pub(crate) struct LatencyHistogram {
+    bounds: Vec<f64>,
+    from: i64,
+    slice_seconds: i64,
+    slices: Vec<HistogramSlice>,
+}
+
+impl LatencyHistogram {
+    /// Empty slices covering `from..to`, aligned to multiples of
+    /// `slice_seconds` since the epoch
+    pub(crate) fn new(bounds: Vec<f64>, from: i64, to: i64, slice_seconds: i64) -> Self {
+        let first = from - from.rem_euclid(slice_seconds);
+        let count = (to - first + slice_seconds - 1) / slice_seconds;
+        let slices = (0..count.max(0))
+            .map(|i| HistogramSlice {
+                timestamp_unix: first + i * slice_seconds,
+                counts: vec![0; bounds.len() + 1],
+                failed_count: 0,
+            })
+            .collect();
+        Self {
+            bounds,
+            from: first,
+            slice_seconds,
+            slices,
+        }
+    }
+
+    /// Count `weight` results at `timestamp`; None latencies are failures
+    fn add(&mut self, timestamp: i64, latency_ms: Option<f64>, weight: u64) {
+        let index = (timestamp - self.from).div_euclid(self.slice_seconds);
+        let Some(slice) = usize::try_from(index)
+            .ok()
+            .and_then(|i| self.slices.get_mut(i))
+        else {
+            return;
+        };
+        match latency_ms {
+            Some(latency) => {
+                let bin = self
+                    .bounds
+                    .iter()
+                    .position(|&bound| latency <= bound)
+                    .unwrap_or(self.bounds.len());
+                slice.counts[bin] += weight;
+            }
+            None => slice.failed_count += weight,
+        }
+    }
+
+    /// Count a raw ping result
+    pub(crate) fn add_point(&mut self, point: &PingDataPoint) {
+        let latency = point.latency_ms.filter(|_| point.success);
+        self.add(point.timestamp_unix, latency, 1);
+    }
+
+    /// Count an aggregated bucket: its average once per successful ping
+    pub(crate) fn add_bucket(&mut self, bucket: &BucketDataPoint) {
+        if let Some(avg) = bucket.avg {
+            self.add(
+                bucket.timestamp_unix,
+                Some(avg),
+                bucket.successful_count as u64,
+            );
+        }
+        self.add(bucket.timestamp_unix, None, bucket.failed_count as u64);
+    }
+
+    pub(crate) fn into_slices(self) -> Vec<HistogramSlice> {
+        self.slices
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProbeType;
+
+    fn point(timestamp: i64, latency_ms: Option<f64>) -> PingDataPoint {
+        PingDataPoint {
+            timestamp: String::new(),
+            timestamp_unix: timestamp,
+            target: "10.0.0.1".to_string(),
+            target_name: None,
+            sequence: 1,
+            probe_type: ProbeType::Icmp,
+            success: latency_ms.is_some(),
+            latency_ms,
+            metric_type: String::new(),
+            site: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_bounds() {
+        assert_eq!(parse_bounds("1, 5,10.5"), Ok(vec![1.0, 5.0, 10.5]));
+        assert!(parse_bounds("5,1").is_err());
+        assert!(parse_bounds("1,1").is_err());
+        assert!(parse_bounds("0,1").is_err());
+        assert!(parse_bounds("1,fast").is_err());
+        assert!(parse_bounds("").is_err());
+    }
+
+    #[test]
+    fn test_raw_points() {
+        let mut histogram = LatencyHistogram::new(vec![10.0, 50.0], 90, 250, 100);
+        for p in [
+            point(50, Some(1.0)),
+            point(100, Some(5.0)),
+            point(110, Some(10.0)),
+            point(120, Some(30.0)),
+            point(150, None),
+            point(210, Some(80.0)),
+            point(260, Some(1.0)),
+        ] {
+            histogram.add_point(&p);
+        }
+
+        let slices = histogram.into_slices();
+        // Slices start at the aligned 0, 100, 200
+        let summary: Vec<(i64, Vec<u64>, u64)> = slices
+            .into_iter()
+            .map(|s| (s.timestamp_unix, s.counts, s.failed_count))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (0, vec![1, 0, 0], 0),
+                (100, vec![2, 1, 0], 1),
+                (200, vec![0, 0, 1], 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rollup_buckets() {
+        let mut histogram = LatencyHistogram::new(vec![10.0], 0, 120, 60);
+        histogram.add_bucket(&BucketDataPoint {
+            timestamp: String::new(),
+            timestamp_unix: 60,
+            timestamp_end_unix: 120,
+            target: "10.0.0.1".to_string(),
+            target_name: None,
+            min: Some(8.0),
+            max: Some(20.0),
+            avg: Some(12.0),
+            percentiles: None,
+            count: 4,
+            successful_count: 3,
+            failed_count: 1,
+            resolution: Some("1m"),
+            complete: true,
+            loss_percent: None,
+        });
+
+        let slices = histogram.into_slices();
+        assert_eq!(slices.len(), 2);
+        assert_eq!(slices[0].counts, vec![0, 0]);
+        assert_eq!(slices[1].counts, vec![0, 3]);
+        assert_eq!(slices[1].failed_count, 1);
+    }
+}
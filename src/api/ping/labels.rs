@@ -0,0 +1,89 @@
+//! Filtering of ping queries by target labels.
+//!
+//! `label=location:basement,class:critical` keeps the results of targets
+//! carrying all listed labels. Targets are matched by their current labels
+//! in the config, so results stored before a label was set are included.
+
+use crate::config::Target;
+use std::collections::HashSet;
+
+/// Labels a target must carry, all of them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct LabelFilter(Vec<(String, String)>);
+
+impl LabelFilter {
+    /// Parse a `label` parameter (`<name>:<value>`, comma-separated)
+    pub(crate) fn parse(value: &str) -> Result<Self, String> {
+        let pairs = value
+            .split(',')
+            .map(|pair| match pair.split_once(':') {
+                Some((name, value)) if !name.trim().is_empty() && !value.trim().is_empty() => {
+                    Ok((name.trim().to_string(), value.trim().to_string()))
+                }
+                _ => Err(format!(
+                    "Invalid label filter '{}' (expected <name>:<value>, e.g. location:basement)",
+                    pair
+                )),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self(pairs))
+    }
+
+    pub(crate) fn matches(&self, target: &Target) -> bool {
+        self.0
+            .iter()
+            .all(|(name, value)| target.labels.get(name) == Some(value))
+    }
+
+    /// Addresses of the matching targets, as results and buckets carry them
+    /// in `target`
+    pub(crate) fn addresses(&self, targets: &[Target]) -> HashSet<String> {
+        targets
+            .iter()
+            .filter(|t| self.matches(t))
+            .map(|t| t.address.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(address: &str, labels: &[(&str, &str)]) -> Target {
+        Target {
+            address: address.to_string(),
+            labels: labels
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_label_filter() {
+        let targets = vec![
+            target(
+                "10.0.0.1",
+                &[("location", "basement"), ("class", "critical")],
+            ),
+            target("10.0.0.2", &[("location", "basement")]),
+            target("10.0.0.3", &[]),
+        ];
+
+        let filter = LabelFilter::parse("location:basement").unwrap();
+        assert_eq!(
+            filter.addresses(&targets),
+            HashSet::from(["10.0.0.1".to_string(), "10.0.0.2".to_string()])
+        );
+        let filter = LabelFilter::parse("location: basement , class:critical").unwrap();
+        assert_eq!(
+            filter.addresses(&targets),
+            HashSet::from(["10.0.0.1".to_string()])
+        );
+
+        assert!(LabelFilter::parse("location").is_err());
+        assert!(LabelFilter::parse("location:basement,").is_err());
+    }
+}
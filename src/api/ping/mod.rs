@@ -1,3 +1,9 @@
+pub(crate) mod calendar;
 pub mod dto;
+mod group;
 pub mod handlers;
+mod histogram;
+pub(crate) mod labels;
 pub mod query;
+pub(crate) mod summary;
+pub mod trend;
@@ -1,30 +1,185 @@
+use super::calendar::LocalBuckets;
 use super::dto::{
     BucketDataPoint, PartitionMetadata, Percentiles, PingDataPoint, PingStatistics,
     TargetStorageStats, TimeRangeValue,
 };
-use crate::config::Target;
+use crate::config::{HistorySource, ProbeType, Target};
+use crate::downsample::{merge_bucket, select_rollups, Coverage, Resolution};
+use crate::ping::Flow;
+use crate::storage::cache::{AggregateCache, CachedBuckets};
+use crate::storage::{
+    series_site, target_labels, SiteSelector, StorageBackend, BATCH_LOSS_METRIC, FLOW_LABEL,
+    JITTER_METRIC, LATENCY_CORRECTED_LABEL, LOCAL_SITE, SITE_LABEL,
+};
 use chrono::{DateTime, Utc};
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
 use tracing::{debug, warn};
-use tsink::{DataPoint, Label, Storage};
+use tsink::{DataPoint, Label};
 
 /// Internal query structure with resolved timestamps
-pub(super) struct ResolvedPingDataQuery {
+pub(crate) struct ResolvedPingDataQuery {
     pub target: Option<String>,
     pub target_config: Option<Target>,
     pub from: i64,
     pub to: i64,
     pub metric: Option<String>,
     pub limit: Option<usize>,
+    /// Only results of these target addresses (from a label filter)
+    pub addresses: Option<HashSet<String>>,
+    /// Newest results first
+    pub descending: bool,
+    /// Only results after this position, in the query's order
+    pub after: Option<PingDataCursor>,
+    /// Sites whose results are read
+    pub site: SiteSelector,
+}
+
+/// Position of a result in the order of a raw data query: timestamp, then
+/// target, sequence and metric, so results sharing a second are ordered too.
+/// Sent to clients as an opaque `next_cursor` token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PingDataCursor {
+    timestamp: i64,
+    target: String,
+    sequence: u16,
+    metric: String,
+}
+
+impl PingDataCursor {
+    fn of(point: &PingDataPoint) -> Self {
+        Self {
+            timestamp: point.timestamp_unix,
+            target: point.target.clone(),
+            sequence: point.sequence,
+            metric: point.metric_type.clone(),
+        }
+    }
+
+    /// Timestamp of the result the cursor points at
+    pub(crate) fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    pub(crate) fn encode(&self) -> String {
+        hex::encode(format!(
+            "{}:{}:{}:{}",
+            self.timestamp, self.sequence, self.metric, self.target
+        ))
+    }
+
+    pub(crate) fn decode(token: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid cursor '{}'", token);
+        let bytes = hex::decode(token.trim()).map_err(|_| invalid())?;
+        let text = String::from_utf8(bytes).map_err(|_| invalid())?;
+        let mut parts = text.splitn(4, ':');
+        let mut next = || parts.next().ok_or_else(invalid);
+        Ok(Self {
+            timestamp: next()?.parse().map_err(|_| invalid())?,
+            sequence: next()?.parse().map_err(|_| invalid())?,
+            metric: next()?.to_string(),
+            target: next()?.to_string(),
+        })
+    }
+
+    /// Order of a result relative to the cursor, ascending
+    fn cmp_point(&self, point: &PingDataPoint) -> Ordering {
+        (
+            self.timestamp,
+            self.target.as_str(),
+            self.sequence,
+            self.metric.as_str(),
+        )
+            .cmp(&point_key(point))
+    }
+}
+
+/// Sort key of a result, see [`PingDataCursor`]
+fn point_key(point: &PingDataPoint) -> (i64, &str, u16, &str) {
+    (
+        point.timestamp_unix,
+        point.target.as_str(),
+        point.sequence,
+        point.metric_type.as_str(),
+    )
 }
 
 /// Query a specific target's data using exact label matching (fast path).
 /// Constructs the full label set from the target config and queries each
 /// sequence separately via storage.select(), which does a direct hash lookup
 /// instead of scanning all series.
-fn select_target_data(
-    storage: &dyn Storage,
+///
+/// Hostname targets carry a `resolved_ip` label that changes with DNS, so
+/// their series are found by scanning for the target ID instead. Series of
+/// the target's earlier identities (its `history`) are found by scanning too.
+/// Only this server's own results are selected, not those agents pushed for
+/// the target.
+pub(crate) fn select_target_data(
+    storage: &dyn StorageBackend,
+    metric: &str,
+    target_config: &Target,
+    from: i64,
+    to: i64,
+) -> Result<Vec<DataPoint>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut all_points = select_current_data(storage, metric, target_config, from, to)?;
+
+    if !target_config.history.is_empty() {
+        for (labels, points) in storage.select_all(metric, from, to)? {
+            if series_site(&labels).is_some() {
+                continue;
+            }
+            let label = |name: &str| {
+                labels
+                    .iter()
+                    .find(|l| l.name == name)
+                    .map(|l| l.value.as_str())
+            };
+            let from_history = target_config.history.iter().any(|source| {
+                is_history_series(target_config, source, label("target_id"), label("target"))
+            });
+            if from_history {
+                all_points.extend(points);
+            }
+        }
+    }
+
+    Ok(all_points)
+}
+
+/// Whether a series labeled with `target_id` and `address` was stored under
+/// the earlier identity `source` of `target`, and not already selected as
+/// one of the target's current series
+fn is_history_series(
+    target: &Target,
+    source: &HistorySource,
+    target_id: Option<&str>,
+    address: Option<&str>,
+) -> bool {
+    if target_id != Some(source.id.as_str()) {
+        return false;
+    }
+    if source
+        .address
+        .as_deref()
+        .is_some_and(|a| address != Some(a))
+    {
+        return false;
+    }
+    if source.id == target.id {
+        // Hostname targets select every series with their ID already
+        return target.address.parse::<IpAddr>().is_ok()
+            && address != Some(target.address.as_str());
+    }
+    true
+}
+
+/// Series stored under the target's current ID and address
+fn select_current_data(
+    storage: &dyn StorageBackend,
     metric: &str,
     target_config: &Target,
     from: i64,
@@ -32,160 +187,365 @@ fn select_target_data(
 ) -> Result<Vec<DataPoint>, Box<dyn std::error::Error + Send + Sync>> {
     let mut all_points = Vec::new();
 
-    for seq in 0..target_config.ping_count {
-        let mut labels = vec![
-            Label::new("target_id", &target_config.id),
-            Label::new("target", &target_config.address),
-            Label::new("sequence", seq.to_string()),
-        ];
-        if let Some(ref name) = target_config.name {
-            labels.push(Label::new("target_name", name));
+    if target_config.address.parse::<IpAddr>().is_err() {
+        for (labels, points) in storage.select_all(metric, from, to)? {
+            if series_site(&labels).is_none()
+                && labels
+                    .iter()
+                    .any(|l| l.name == "target_id" && l.value == target_config.id)
+            {
+                all_points.extend(points);
+            }
+        }
+        return Ok(all_points);
+    }
+
+    // ICMP results written before probe types existed have no probe_type label;
+    // calibrated ones are additionally labeled as corrected
+    let probe_labels = match target_config.tcp_port() {
+        None => vec![
+            Vec::new(),
+            vec![Label::new("probe_type", ProbeType::Icmp.as_str())],
+            vec![
+                Label::new("probe_type", ProbeType::Icmp.as_str()),
+                Label::new(LATENCY_CORRECTED_LABEL, "true"),
+            ],
+        ],
+        Some(port) => vec![vec![
+            Label::new("probe_type", ProbeType::Tcp.as_str()),
+            Label::new("port", port.to_string()),
+        ]],
+    };
+
+    // Results carry the target's custom labels; those from before the
+    // labels were set do not
+    let custom_labels = target_labels(target_config);
+    let mut select = |labels: &[Label]| -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        all_points.extend(storage.select(metric, labels, from, to)?);
+        if !custom_labels.is_empty() {
+            let mut labels = labels.to_vec();
+            labels.extend(custom_labels.iter().cloned());
+            all_points.extend(storage.select(metric, &labels, from, to)?);
+        }
+        Ok(())
+    };
+
+    // Ping tasks number the pings of a batch 1..=ping_count
+    for seq in 1..=target_config.ping_count {
+        // With ECMP flows each sequence is sent on a fixed flow; series from
+        // before flows were enabled have no flow label
+        let flow = Flow::for_sequence(target_config, seq);
+        for extra in &probe_labels {
+            let mut labels = vec![
+                Label::new("target_id", &target_config.id),
+                Label::new("target", &target_config.address),
+                Label::new("sequence", seq.to_string()),
+            ];
+            if let Some(ref name) = target_config.name {
+                labels.push(Label::new("target_name", name));
+            }
+            labels.extend(extra.iter().cloned());
+            select(&labels)?;
+            if let Some(flow) = flow {
+                labels.push(Label::new(FLOW_LABEL, flow.index.to_string()));
+                select(&labels)?;
+            }
         }
-        let points = storage.select(metric, &labels, from, to)?;
-        all_points.extend(points);
     }
 
     Ok(all_points)
 }
 
-/// Query ping data with labels properly extracted
-pub(super) fn query_ping_data_with_labels(
-    storage: &dyn Storage,
+/// Probe type of a stored series; series without the label are ICMP
+fn probe_type_from_labels(labels: &[Label]) -> ProbeType {
+    match labels.iter().find(|l| l.name == "probe_type") {
+        Some(l) if l.value == ProbeType::Tcp.as_str() => ProbeType::Tcp,
+        _ => ProbeType::Icmp,
+    }
+}
+
+/// Query ping data with labels properly extracted.
+///
+/// Reads through [`PingDataStream`], so with a `limit` only as many chunks
+/// of the time range as needed to fill it are loaded.
+pub(crate) fn query_ping_data_with_labels(
+    storage: &dyn StorageBackend,
     query: &ResolvedPingDataQuery,
 ) -> Result<Vec<PingDataPoint>, Box<dyn std::error::Error + Send + Sync>> {
-    let from_ts = query.from;
-    let to_ts = query.to;
-
-    let mut all_points = Vec::new();
+    let stream = PingDataStream::new(storage, query);
+    match query.limit {
+        Some(limit) => stream.take(limit).collect(),
+        None => stream.collect(),
+    }
+}
 
-    // Query both metrics if needed
-    let metrics_to_query = match query.metric.as_deref() {
-        Some("latency") => vec!["ping_latency"],
-        Some("failed") => vec!["ping_failed"],
-        _ => vec!["ping_latency", "ping_failed"],
+/// One page of a raw data query: up to `limit` results after the query's
+/// cursor, and the cursor to continue from if more results follow
+pub(crate) fn query_ping_data_page(
+    storage: &dyn StorageBackend,
+    query: &ResolvedPingDataQuery,
+) -> Result<(Vec<PingDataPoint>, Option<PingDataCursor>), Box<dyn std::error::Error + Send + Sync>>
+{
+    let stream = PingDataStream::new(storage, query);
+    let Some(limit) = query.limit else {
+        return Ok((stream.collect::<Result<_, _>>()?, None));
     };
+    // One more result than requested tells whether there is a next page
+    let mut points = stream.take(limit + 1).collect::<Result<Vec<_>, _>>()?;
+    if points.len() <= limit {
+        return Ok((points, None));
+    }
+    points.truncate(limit);
+    let next = points.last().map(PingDataCursor::of);
+    Ok((points, next))
+}
+
+/// Iterator over the ping results of a query in timestamp order (newest
+/// first if `descending`), starting after the query's cursor.
+///
+/// The time range is walked in `CHUNK_DURATION_SECS` slices and only one
+/// slice is held in memory at a time, so consumers that stop early never
+/// load the rest of the range. Target queries read the target's series by
+/// exact labels (see [`select_target_data`]); only slices where that finds
+/// nothing, and queries of agents' sites, scan every series of the metric.
+pub(crate) struct PingDataStream<'a> {
+    storage: &'a dyn StorageBackend,
+    query: &'a ResolvedPingDataQuery,
+    metrics: &'static [&'static str],
+    /// Part of the time range not loaded yet, `[from, to)`
+    remaining: (i64, i64),
+    buffered: std::vec::IntoIter<PingDataPoint>,
+}
+
+impl<'a> PingDataStream<'a> {
+    pub(crate) fn new(storage: &'a dyn StorageBackend, query: &'a ResolvedPingDataQuery) -> Self {
+        let metrics: &'static [&'static str] = match query.metric.as_deref() {
+            Some("latency") => &PING_METRICS[..1],
+            Some("failed") => &PING_METRICS[1..],
+            _ => &PING_METRICS,
+        };
+        // Results before the cursor's timestamp (after it, descending) are
+        // never read
+        let (mut from, mut to) = (query.from, query.to);
+        match &query.after {
+            Some(after) if query.descending => to = to.min(after.timestamp() + 1),
+            Some(after) => from = from.max(after.timestamp()),
+            None => {}
+        }
+        Self {
+            storage,
+            query,
+            metrics,
+            remaining: (from, to),
+            buffered: Vec::new().into_iter(),
+        }
+    }
 
-    for metric_name in metrics_to_query {
-        if let Some(ref target) = &query.target {
-            let success = metric_name == "ping_latency";
-            let target_name = query.target_config.as_ref().and_then(|tc| tc.name.clone());
-
-            // Try fast path with exact label matching
-            let mut fast_path_points = Vec::new();
-            if let Some(ref tc) = query.target_config {
-                fast_path_points = select_target_data(storage, metric_name, tc, from_ts, to_ts)?;
-            }
-
-            if !fast_path_points.is_empty() {
-                for point in fast_path_points {
-                    all_points.push(PingDataPoint {
-                        timestamp: DateTime::from_timestamp(point.timestamp, 0)
-                            .unwrap_or_else(|| Utc::now())
-                            .to_rfc3339(),
-                        timestamp_unix: point.timestamp,
-                        target: target.clone(),
-                        target_name: target_name.clone(),
-                        sequence: 0,
+    /// Load one slice of the time range, sorted by timestamp
+    fn load_chunk(
+        &self,
+        from: i64,
+        to: i64,
+    ) -> Result<Vec<PingDataPoint>, Box<dyn std::error::Error + Send + Sync>> {
+        let mut points = Vec::new();
+
+        let site = &self.query.site;
+        if let (Some(target), Some(tc), SiteSelector::Local) =
+            (&self.query.target, &self.query.target_config, site)
+        {
+            for metric_name in self.metrics {
+                let success = *metric_name == "ping_latency";
+                for point in select_target_data(self.storage, metric_name, tc, from, to)? {
+                    points.push(ping_data_point(
+                        point,
+                        metric_name,
+                        target,
+                        tc.name.clone(),
+                        0,
+                        tc.probe_type,
                         success,
-                        latency_ms: if success { Some(point.value) } else { None },
-                        metric_type: metric_name.to_string(),
-                    });
-                }
-            } else {
-                // Fallback: select_all and filter by target
-                debug!(
-                    "Fast path empty for target {}, falling back to select_all",
-                    target
-                );
-                let all_results = storage.select_all(metric_name, from_ts, to_ts)?;
-                for (labels, points) in all_results {
-                    let matches_target = labels
-                        .iter()
-                        .any(|l| l.name == "target" && &l.value == target);
-
-                    if matches_target {
-                        let label_target_name = labels
-                            .iter()
-                            .find(|l| l.name == "target_name")
-                            .map(|l| l.value.clone());
-
-                        let sequence = labels
-                            .iter()
-                            .find(|l| l.name == "sequence")
-                            .and_then(|l| l.value.parse::<u16>().ok())
-                            .unwrap_or(0);
-
-                        for point in points {
-                            all_points.push(PingDataPoint {
-                                timestamp: DateTime::from_timestamp(point.timestamp, 0)
-                                    .unwrap_or_else(|| Utc::now())
-                                    .to_rfc3339(),
-                                timestamp_unix: point.timestamp,
-                                target: target.clone(),
-                                target_name: label_target_name.clone(),
-                                sequence,
-                                success,
-                                latency_ms: if success { Some(point.value) } else { None },
-                                metric_type: metric_name.to_string(),
-                            });
-                        }
-                    }
+                    ));
                 }
             }
-        } else {
-            // Query all label combinations
-            let all_results = storage.select_all(metric_name, from_ts, to_ts)?;
-            for (labels, points) in all_results {
-                let target = labels
-                    .iter()
-                    .find(|l| l.name == "target")
-                    .map(|l| l.value.clone())
-                    .unwrap_or_else(|| "unknown".to_string());
-
-                let target_name = labels
-                    .iter()
-                    .find(|l| l.name == "target_name")
-                    .map(|l| l.value.clone());
+            if !points.is_empty() {
+                points.sort_by(|a, b| point_key(a).cmp(&point_key(b)));
+                return Ok(points);
+            }
+            debug!(
+                "Fast path empty for target {} in [{}, {}), falling back to select_all",
+                target, from, to
+            );
+        }
 
-                let sequence = labels
-                    .iter()
-                    .find(|l| l.name == "sequence")
+        for metric_name in self.metrics {
+            let success = *metric_name == "ping_latency";
+            for (labels, series) in self.storage.select_all(metric_name, from, to)? {
+                if !site.matches(&labels) {
+                    continue;
+                }
+                let label = |name: &str| labels.iter().find(|l| l.name == name);
+                let from_site = series_site(&labels);
+                let target = match (&self.query.target, label("target")) {
+                    (Some(filter), Some(l)) if &l.value == filter => filter.clone(),
+                    // Agents' results of targets this server does not ping
+                    // carry the ID as address too
+                    (Some(filter), Some(l))
+                        if from_site.is_some()
+                            && label("target_id").is_some_and(|id| &id.value == filter) =>
+                    {
+                        l.value.clone()
+                    }
+                    (Some(_), _) => continue,
+                    (None, l) => l
+                        .map(|l| l.value.clone())
+                        .unwrap_or_else(|| "unknown".to_string()),
+                };
+                let target_name = label("target_name").map(|l| l.value.clone());
+                let sequence = label("sequence")
                     .and_then(|l| l.value.parse::<u16>().ok())
                     .unwrap_or(0);
+                let probe_type = probe_type_from_labels(&labels);
 
-                let success = metric_name == "ping_latency";
-
-                for point in points {
-                    all_points.push(PingDataPoint {
-                        timestamp: DateTime::from_timestamp(point.timestamp, 0)
-                            .unwrap_or_else(|| Utc::now())
-                            .to_rfc3339(),
-                        timestamp_unix: point.timestamp,
-                        target: target.clone(),
-                        target_name: target_name.clone(),
+                for point in series {
+                    let mut point = ping_data_point(
+                        point,
+                        metric_name,
+                        &target,
+                        target_name.clone(),
                         sequence,
+                        probe_type,
                         success,
-                        latency_ms: if success { Some(point.value) } else { None },
-                        metric_type: metric_name.to_string(),
-                    });
+                    );
+                    point.site = from_site.map(str::to_string);
+                    points.push(point);
                 }
             }
         }
+
+        points.sort_by(|a, b| point_key(a).cmp(&point_key(b)));
+        Ok(points)
     }
+}
+
+impl Iterator for PingDataStream<'_> {
+    type Item = Result<PingDataPoint, Box<dyn std::error::Error + Send + Sync>>;
 
-    // Sort by timestamp
-    all_points.sort_by_key(|p| p.timestamp_unix);
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(point) = self.buffered.next() {
+                return Some(Ok(point));
+            }
+            let (from, to) = self.remaining;
+            if from >= to {
+                return None;
+            }
 
-    // Apply limit if specified
-    if let Some(limit) = query.limit {
-        all_points.truncate(limit);
+            let (chunk_start, chunk_end) = if self.query.descending {
+                ((to - CHUNK_DURATION_SECS).max(from), to)
+            } else {
+                (from, (from + CHUNK_DURATION_SECS).min(to))
+            };
+            match self.load_chunk(chunk_start, chunk_end) {
+                Ok(mut points) => {
+                    if let Some(addresses) = &self.query.addresses {
+                        points.retain(|p| addresses.contains(&p.target));
+                    }
+                    if self.query.descending {
+                        points.reverse();
+                    }
+                    if let Some(after) = &self.query.after {
+                        // The cursor precedes the kept results in query order
+                        let kept = if self.query.descending {
+                            Ordering::Greater
+                        } else {
+                            Ordering::Less
+                        };
+                        points.retain(|p| after.cmp_point(p) == kept);
+                    }
+                    self.buffered = points.into_iter();
+                }
+                Err(e) => {
+                    // Stop after the first error
+                    self.remaining = (to, to);
+                    return Some(Err(e));
+                }
+            }
+            self.remaining = if self.query.descending {
+                (from, chunk_start)
+            } else {
+                (chunk_end, to)
+            };
+        }
     }
+}
 
-    Ok(all_points)
+fn ping_data_point(
+    point: DataPoint,
+    metric_name: &str,
+    target: &str,
+    target_name: Option<String>,
+    sequence: u16,
+    probe_type: ProbeType,
+    success: bool,
+) -> PingDataPoint {
+    PingDataPoint {
+        timestamp: DateTime::from_timestamp(point.timestamp, 0)
+            .unwrap_or_else(Utc::now)
+            .to_rfc3339(),
+        timestamp_unix: point.timestamp,
+        target: target.to_string(),
+        target_name,
+        sequence,
+        probe_type,
+        success,
+        latency_ms: if success { Some(point.value) } else { None },
+        metric_type: metric_name.to_string(),
+        site: None,
+    }
 }
 
-/// Parse bucket duration string (e.g., "5m", "1h", "30s") into seconds
-pub(super) fn parse_bucket_duration(bucket_str: &str) -> Result<i64, String> {
+/// Span covered by partitions tsink has not flushed to disk yet (the writable
+/// head partitions), with headroom
+const UNFLUSHED_SPAN_SECS: i64 = 4 * CHUNK_DURATION_SECS;
+
+/// Earliest timestamp any stored point can have, used to clamp open-ended
+/// queries (`from` = 0) so they don't walk decades of empty chunks.
+///
+/// Flushed partitions are named `p-<min>-<max>` (meta.json is read for other
+/// names); anything newer lives in the in-memory head partitions.
+pub(crate) fn earliest_data_timestamp(data_path: &Path, now: i64) -> i64 {
+    let flushed = fs::read_dir(data_path)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name();
+            let suffix = name.to_str()?.strip_prefix("p-")?;
+            match suffix.split('-').next().and_then(|min| min.parse().ok()) {
+                Some(min) => Some(min),
+                None => partition_min_timestamp(&entry.path()),
+            }
+        })
+        .min();
+
+    let unflushed = now - UNFLUSHED_SPAN_SECS;
+    flushed.map_or(unflushed, |min| min.min(unflushed))
+}
+
+/// `min_timestamp` from a partition's meta.json
+fn partition_min_timestamp(partition: &Path) -> Option<i64> {
+    #[derive(serde::Deserialize)]
+    struct Meta {
+        min_timestamp: i64,
+    }
+    let content = fs::read_to_string(partition.join("meta.json")).ok()?;
+    serde_json::from_str::<Meta>(&content)
+        .ok()
+        .map(|m| m.min_timestamp)
+}
+
+/// Split a duration string (e.g., "5m") into its number and lowercase unit
+pub(crate) fn split_duration(bucket_str: &str) -> Result<(i64, String), String> {
     if bucket_str.is_empty() {
         return Err("Bucket duration cannot be empty".to_string());
     }
@@ -205,14 +565,25 @@ pub(super) fn parse_bucket_duration(bucket_str: &str) -> Result<i64, String> {
         .parse()
         .map_err(|_| format!("Invalid number in bucket duration: '{}'", number_str))?;
 
-    let seconds = match unit {
+    Ok((number, unit.to_string()))
+}
+
+/// Parse bucket duration string (e.g., "5m", "1h", "30s", "1w") into seconds.
+/// A month ("1mo") counts as 30 days here; aggregation by calendar months
+/// goes through `LocalBuckets::calendar`.
+pub(crate) fn parse_bucket_duration(bucket_str: &str) -> Result<i64, String> {
+    let (number, unit) = split_duration(bucket_str)?;
+
+    let seconds = match unit.as_str() {
         "s" | "sec" | "second" | "seconds" => number,
         "m" | "min" | "minute" | "minutes" => number * 60,
         "h" | "hour" | "hours" => number * 3600,
         "d" | "day" | "days" => number * 86400,
+        "w" | "week" | "weeks" => number * 7 * 86400,
+        "mo" | "month" | "months" => number * 30 * 86400,
         _ => {
             return Err(format!(
-                "Unknown time unit: '{}'. Supported: s, m, h, d",
+                "Unknown time unit: '{}'. Supported: s, m, h, d, w, mo",
                 unit
             ))
         }
@@ -227,14 +598,14 @@ pub(super) fn parse_bucket_duration(bucket_str: &str) -> Result<i64, String> {
 
 /// Parse relative time range string (e.g., "1h", "24h", "7d") into seconds
 /// Uses the same logic as parse_bucket_duration for consistency
-pub(super) fn parse_relative_time_range(range_str: &str) -> Result<i64, String> {
+pub(crate) fn parse_relative_time_range(range_str: &str) -> Result<i64, String> {
     parse_bucket_duration(range_str)
 }
 
 /// Resolve a TimeRangeValue to an absolute timestamp
 /// If it's already absolute, return it as-is
 /// If it's relative, parse it and calculate: current_time - seconds
-pub(super) fn resolve_time_range_value(value: &TimeRangeValue) -> Result<i64, String> {
+pub(crate) fn resolve_time_range_value(value: &TimeRangeValue) -> Result<i64, String> {
     match value {
         TimeRangeValue::Absolute(timestamp) => Ok(*timestamp),
         TimeRangeValue::Relative(range_str) => {
@@ -248,6 +619,12 @@ pub(super) fn resolve_time_range_value(value: &TimeRangeValue) -> Result<i64, St
 /// Chunk duration for time-chunked queries (6 hours, matching tsink partition duration)
 const CHUNK_DURATION_SECS: i64 = 6 * 3600;
 
+/// Metrics holding ping results (successful latency, failures)
+pub(crate) const PING_METRICS: [&str; 2] = ["ping_latency", "ping_failed"];
+
+/// Metric recording per-target storage size snapshots (see `tasks::start_storage_stats_task`)
+pub(crate) const STORAGE_SIZE_METRIC: &str = "storage_size_bytes";
+
 /// Running accumulator for a single (target, bucket) pair.
 /// Avoids materializing intermediate PingDataPoint structs.
 struct BucketAccumulator {
@@ -326,7 +703,7 @@ impl BucketAccumulator {
 
         BucketDataPoint {
             timestamp: DateTime::from_timestamp(self.bucket_start, 0)
-                .unwrap_or_else(|| Utc::now())
+                .unwrap_or_else(Utc::now)
                 .to_rfc3339(),
             timestamp_unix: self.bucket_start,
             timestamp_end_unix: self.bucket_start + self.bucket_duration,
@@ -339,6 +716,9 @@ impl BucketAccumulator {
             count: self.successful_count + self.failed_count,
             successful_count: self.successful_count,
             failed_count: self.failed_count,
+            resolution: None,
+            complete: true,
+            loss_percent: None,
         }
     }
 }
@@ -351,8 +731,8 @@ impl BucketAccumulator {
 /// 3. Directly aggregates raw DataPoints into per-bucket accumulators
 /// 4. Discards raw data between chunks
 #[allow(clippy::too_many_arguments)]
-pub(super) fn query_ping_aggregated_chunked(
-    storage: &dyn Storage,
+pub(crate) fn query_ping_aggregated_chunked(
+    storage: &dyn StorageBackend,
     target_filter: Option<&str>,
     target_config: Option<&Target>,
     from: i64,
@@ -363,155 +743,518 @@ pub(super) fn query_ping_aggregated_chunked(
     (Vec<BucketDataPoint>, Option<super::dto::TimeRange>),
     Box<dyn std::error::Error + Send + Sync>,
 > {
-    // Key: (target, bucket_start)
-    let mut accumulators: HashMap<(String, i64), BucketAccumulator> = HashMap::new();
+    query_aggregated_chunked(
+        storage,
+        &PING_METRICS,
+        target_filter,
+        target_config,
+        from,
+        to,
+        bucket_duration_seconds,
+        include_percentiles,
+    )
+}
+
+/// Aggregated ping query that reads 1m/1h rollups for the hours the
+/// downsampling job has covered and raw points everywhere else.
+///
+/// The coarsest rollup resolution that fits evenly into the bucket duration is
+/// used. Percentile queries and buckets that are not whole minutes always read
+/// raw data. Returns the resolution that was read ("raw", "1m", or "1h").
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn query_ping_aggregated_with_rollups(
+    storage: &dyn StorageBackend,
+    coverage: &Coverage,
+    target_filter: Option<&str>,
+    target_config: Option<&Target>,
+    from: i64,
+    to: i64,
+    bucket_duration_seconds: i64,
+    include_percentiles: bool,
+) -> Result<
+    (
+        Vec<BucketDataPoint>,
+        Option<super::dto::TimeRange>,
+        &'static str,
+    ),
+    Box<dyn std::error::Error + Send + Sync>,
+> {
+    let segments = coverage.split(from, to);
+    let resolution = Resolution::for_bucket(bucket_duration_seconds)
+        .filter(|_| !include_percentiles && segments.iter().any(|s| s.2));
+    let Some(resolution) = resolution else {
+        let (mut buckets, time_range) = query_ping_aggregated_chunked(
+            storage,
+            target_filter,
+            target_config,
+            from,
+            to,
+            bucket_duration_seconds,
+            include_percentiles,
+        )?;
+        for bucket in &mut buckets {
+            bucket.resolution = Some("raw");
+        }
+        return Ok((buckets, time_range, "raw"));
+    };
+
+    let mut merged: HashMap<(String, i64), BucketDataPoint> = HashMap::new();
     let mut earliest_ts: Option<i64> = None;
     let mut latest_ts: Option<i64> = None;
 
-    let metrics = ["ping_latency", "ping_failed"];
+    for (start, end, covered) in segments {
+        let buckets = if covered {
+            let history = target_config.map_or(&[][..], |t| t.history.as_slice());
+            let rollups = select_rollups(storage, resolution, target_filter, history, start, end)?;
+            for bucket in &rollups {
+                earliest_ts = Some(
+                    earliest_ts.map_or(bucket.timestamp_unix, |e| e.min(bucket.timestamp_unix)),
+                );
+                latest_ts =
+                    Some(latest_ts.map_or(bucket.timestamp_unix, |l| l.max(bucket.timestamp_unix)));
+            }
+            rollups
+        } else {
+            let (buckets, time_range) = query_ping_aggregated_chunked(
+                storage,
+                target_filter,
+                target_config,
+                start,
+                end,
+                bucket_duration_seconds,
+                false,
+            )?;
+            if let Some(range) = time_range {
+                earliest_ts = Some(earliest_ts.map_or(range.earliest, |e| e.min(range.earliest)));
+                latest_ts = Some(latest_ts.map_or(range.latest, |l| l.max(range.latest)));
+            }
+            buckets
+        };
 
-    // Fast path: when we have a target config, use select() for direct label lookup
-    // instead of select_all() which scans every series
-    let mut used_fast_path = false;
-    if let (Some(filter), Some(tc)) = (target_filter, target_config) {
-        let target_name = tc.name.clone();
+        // Segment boundaries are hour-aligned, so a bucket can be split
+        // between rollup and raw segments
+        for mut bucket in buckets {
+            bucket.resolution = Some(if covered { resolution.as_str() } else { "raw" });
+            let bucket_start =
+                (bucket.timestamp_unix / bucket_duration_seconds) * bucket_duration_seconds;
+            match merged.entry((bucket.target.clone(), bucket_start)) {
+                Entry::Occupied(mut entry) => merge_bucket(entry.get_mut(), &bucket),
+                Entry::Vacant(entry) => {
+                    bucket.timestamp = DateTime::from_timestamp(bucket_start, 0)
+                        .unwrap_or_else(Utc::now)
+                        .to_rfc3339();
+                    bucket.timestamp_unix = bucket_start;
+                    bucket.timestamp_end_unix = bucket_start + bucket_duration_seconds;
+                    entry.insert(bucket);
+                }
+            }
+        }
+    }
 
-        for metric_name in &metrics {
-            let is_latency = *metric_name == "ping_latency";
-            let points = select_target_data(storage, metric_name, tc, from, to)?;
+    let data_time_range = match (earliest_ts, latest_ts) {
+        (Some(e), Some(l)) => Some(super::dto::TimeRange {
+            earliest: e,
+            latest: l,
+        }),
+        _ => None,
+    };
 
-            if points.is_empty() && is_latency {
-                debug!(
-                    "Fast path empty for target {}, falling back to select_all",
-                    filter
-                );
-                break;
+    let mut bucket_points: Vec<BucketDataPoint> = merged.into_values().collect();
+    bucket_points.sort_by(|a, b| {
+        a.target
+            .cmp(&b.target)
+            .then_with(|| a.timestamp_unix.cmp(&b.timestamp_unix))
+    });
+
+    Ok((bucket_points, data_time_range, resolution.as_str()))
+}
+
+/// [`query_ping_aggregated_with_rollups`] through the aggregate cache.
+///
+/// Buckets lying within `[from, to)` that ended by `now` are served from
+/// `cache` once computed. Storage is read for the partial first and the
+/// open last bucket, and for the span of buckets not cached (yet, or any
+/// more after late writes).
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn query_ping_aggregated_cached(
+    cache: &AggregateCache,
+    storage: &dyn StorageBackend,
+    coverage: &Coverage,
+    target_filter: Option<&str>,
+    target_config: Option<&Target>,
+    from: i64,
+    to: i64,
+    bucket_duration_seconds: i64,
+    include_percentiles: bool,
+    now: i64,
+) -> Result<
+    (
+        Vec<BucketDataPoint>,
+        Option<super::dto::TimeRange>,
+        &'static str,
+    ),
+    Box<dyn std::error::Error + Send + Sync>,
+> {
+    let bucket = bucket_duration_seconds.max(1);
+    let first = (from + bucket - 1).div_euclid(bucket) * bucket;
+    let end = to.min(now).div_euclid(bucket) * bucket;
+    let query = |from: i64, to: i64| {
+        query_ping_aggregated_with_rollups(
+            storage,
+            coverage,
+            target_filter,
+            target_config,
+            from,
+            to,
+            bucket,
+            include_percentiles,
+        )
+    };
+    if first >= end {
+        return query(from, to);
+    }
+
+    let key = (
+        target_filter.map(str::to_string),
+        bucket,
+        include_percentiles,
+    );
+    let mut cached = cache.begin_fill(&key, first, end);
+    let mut ranges = vec![(from, first), (end, to)];
+    let missing = (first..end)
+        .step_by(bucket as usize)
+        .filter(|start| !cached.contains_key(start));
+    if let (Some(lo), Some(hi)) = (missing.clone().next(), missing.last()) {
+        // Recomputed as one span, replacing cached buckets inside it
+        cached.retain(|start, _| !(lo..=hi).contains(start));
+        ranges.push((lo, hi + bucket));
+    }
+
+    let mut computed: BTreeMap<i64, CachedBuckets> = BTreeMap::new();
+    let mut buckets = Vec::new();
+    let mut time_range: Option<(i64, i64)> = None;
+    let mut resolutions = HashSet::new();
+    let mut extend_range = |earliest: i64, latest: i64| {
+        time_range = Some(time_range.map_or((earliest, latest), |(e, l)| {
+            (e.min(earliest), l.max(latest))
+        }));
+    };
+    let result = ranges
+        .into_iter()
+        .filter(|(from, to)| from < to)
+        .try_for_each(|(from, to)| {
+            let (part, range, resolution) = query(from, to)?;
+            if let Some(range) = range {
+                extend_range(range.earliest, range.latest);
+            }
+            resolutions.insert(resolution);
+            if from >= first && to <= end {
+                for start in (from..to).step_by(bucket as usize) {
+                    computed.insert(start, (Vec::new(), resolution));
+                }
+                for point in &part {
+                    if let Some((buckets, _)) = computed.get_mut(&point.timestamp_unix) {
+                        buckets.push(point.clone());
+                    }
+                }
             }
+            buckets.extend(part);
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>(())
+        });
+    cache.end_fill(
+        &key,
+        if result.is_ok() {
+            computed
+        } else {
+            BTreeMap::new()
+        },
+    );
+    result?;
+
+    for (cached_buckets, resolution) in cached.into_values() {
+        resolutions.insert(resolution);
+        for point in &cached_buckets {
+            extend_range(point.timestamp_unix, point.timestamp_unix);
+        }
+        buckets.extend(cached_buckets);
+    }
+    buckets.sort_by(|a, b| {
+        a.target
+            .cmp(&b.target)
+            .then_with(|| a.timestamp_unix.cmp(&b.timestamp_unix))
+    });
+    let resolution = match resolutions.into_iter().collect::<Vec<_>>()[..] {
+        [resolution] => resolution,
+        _ => "mixed",
+    };
+    Ok((
+        buckets,
+        time_range.map(|(earliest, latest)| super::dto::TimeRange { earliest, latest }),
+        resolution,
+    ))
+}
 
-            for point in &points {
-                earliest_ts =
-                    Some(earliest_ts.map_or(point.timestamp, |e: i64| e.min(point.timestamp)));
-                latest_ts =
-                    Some(latest_ts.map_or(point.timestamp, |l: i64| l.max(point.timestamp)));
+/// Flag buckets extending past `[from, to]` as incomplete, and set the
+/// resolution of buckets that have none
+pub(crate) fn annotate_buckets(
+    buckets: &mut [BucketDataPoint],
+    from: i64,
+    to: i64,
+    resolution: &'static str,
+) {
+    for bucket in buckets {
+        bucket.complete = bucket.timestamp_unix >= from && bucket.timestamp_end_unix <= to;
+        bucket.resolution.get_or_insert(resolution);
+    }
+}
 
-                let bucket_start_ts =
-                    (point.timestamp / bucket_duration_seconds) * bucket_duration_seconds;
-                let key = (filter.to_string(), bucket_start_ts);
+/// Fill in the packet loss of ping result buckets. With `loss_only`, the
+/// latency statistics are dropped, leaving the loss series.
+pub(crate) fn set_loss_percent(buckets: &mut [BucketDataPoint], loss_only: bool) {
+    for bucket in buckets {
+        bucket.loss_percent =
+            (bucket.count > 0).then(|| bucket.failed_count as f64 / bucket.count as f64 * 100.0);
+        if loss_only {
+            bucket.min = None;
+            bucket.max = None;
+            bucket.avg = None;
+            bucket.percentiles = None;
+        }
+    }
+}
 
-                let acc = accumulators.entry(key).or_insert_with(|| {
+/// Buckets of an aggregation being filled, keyed by (series, bucket start)
+struct BucketSet {
+    bucket_duration: i64,
+    include_percentiles: bool,
+    accumulators: HashMap<(String, i64), BucketAccumulator>,
+    earliest: Option<i64>,
+    latest: Option<i64>,
+}
+
+impl BucketSet {
+    fn new(bucket_duration: i64, include_percentiles: bool) -> Self {
+        Self {
+            bucket_duration,
+            include_percentiles,
+            accumulators: HashMap::new(),
+            earliest: None,
+            latest: None,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.accumulators.is_empty()
+    }
+
+    /// Add `points` to the buckets of `series`; points of `ping_failed` only
+    /// count as failures
+    fn add(&mut self, series: &str, name: Option<&String>, metric: &str, points: &[DataPoint]) {
+        let is_latency = metric != "ping_failed";
+        let (duration, include_percentiles) = (self.bucket_duration, self.include_percentiles);
+        for point in points {
+            self.earliest = Some(
+                self.earliest
+                    .map_or(point.timestamp, |e| e.min(point.timestamp)),
+            );
+            self.latest = Some(
+                self.latest
+                    .map_or(point.timestamp, |l| l.max(point.timestamp)),
+            );
+
+            let bucket_start = (point.timestamp / duration) * duration;
+            let acc = self
+                .accumulators
+                .entry((series.to_string(), bucket_start))
+                .or_insert_with(|| {
                     BucketAccumulator::new(
-                        filter.to_string(),
-                        target_name.clone(),
-                        bucket_start_ts,
-                        bucket_duration_seconds,
+                        series.to_string(),
+                        name.cloned(),
+                        bucket_start,
+                        duration,
                         include_percentiles,
                     )
                 });
-
-                if is_latency {
-                    acc.add_latency(point.value);
-                } else {
-                    acc.add_failure();
-                }
+            if is_latency {
+                acc.add_latency(point.value);
+            } else {
+                acc.add_failure();
             }
         }
-
-        used_fast_path = !accumulators.is_empty();
     }
 
-    // Fallback / no-target-filter path: use select_all with time chunking
-    if !used_fast_path {
-        accumulators.clear();
-        earliest_ts = None;
-        latest_ts = None;
-
+    /// Add every series of `metrics` in `[from, to)`, read in
+    /// `CHUNK_DURATION_SECS` slices, to the series `series_of` assigns it
+    /// (with its name), skipping those it assigns none
+    fn add_all(
+        &mut self,
+        storage: &dyn StorageBackend,
+        metrics: &[&str],
+        from: i64,
+        to: i64,
+        series_of: impl Fn(&[Label]) -> Option<(String, Option<String>)>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let mut chunk_start = from;
         while chunk_start < to {
             let chunk_end = (chunk_start + CHUNK_DURATION_SECS).min(to);
+            for metric_name in metrics {
+                for (labels, points) in storage.select_all(metric_name, chunk_start, chunk_end)? {
+                    if let Some((series, name)) = series_of(&labels) {
+                        self.add(&series, name.as_ref(), metric_name, &points);
+                    }
+                }
+            }
+            chunk_start = chunk_end;
+        }
+        Ok(())
+    }
 
-            for metric_name in &metrics {
-                let is_latency = *metric_name == "ping_latency";
+    /// Buckets ordered by series and start, and the span of the points
+    fn finish(self) -> (Vec<BucketDataPoint>, Option<super::dto::TimeRange>) {
+        let data_time_range = match (self.earliest, self.latest) {
+            (Some(e), Some(l)) => Some(super::dto::TimeRange {
+                earliest: e,
+                latest: l,
+            }),
+            _ => None,
+        };
 
-                let results = storage.select_all(metric_name, chunk_start, chunk_end)?;
+        let mut bucket_points: Vec<BucketDataPoint> = self
+            .accumulators
+            .into_values()
+            .map(|acc| acc.into_bucket_data_point())
+            .collect();
 
-                for (labels, points) in results {
-                    // Extract target from labels
-                    let target = match labels.iter().find(|l| l.name == "target") {
-                        Some(l) => &l.value,
-                        None => continue,
-                    };
+        bucket_points.sort_by(|a, b| {
+            a.target
+                .cmp(&b.target)
+                .then_with(|| a.timestamp_unix.cmp(&b.timestamp_unix))
+        });
 
-                    // Apply target filter
-                    if let Some(filter) = target_filter {
-                        if target != filter {
-                            continue;
-                        }
-                    }
+        (bucket_points, data_time_range)
+    }
+}
 
-                    let target_name = labels
-                        .iter()
-                        .find(|l| l.name == "target_name")
-                        .map(|l| l.value.clone());
-
-                    for point in &points {
-                        earliest_ts = Some(
-                            earliest_ts.map_or(point.timestamp, |e: i64| e.min(point.timestamp)),
-                        );
-                        latest_ts = Some(
-                            latest_ts.map_or(point.timestamp, |l: i64| l.max(point.timestamp)),
-                        );
-
-                        let bucket_start_ts =
-                            (point.timestamp / bucket_duration_seconds) * bucket_duration_seconds;
-                        let key = (target.clone(), bucket_start_ts);
-
-                        let acc = accumulators.entry(key).or_insert_with(|| {
-                            BucketAccumulator::new(
-                                target.clone(),
-                                target_name.clone(),
-                                bucket_start_ts,
-                                bucket_duration_seconds,
-                                include_percentiles,
-                            )
-                        });
-
-                        if is_latency {
-                            acc.add_latency(point.value);
-                        } else {
-                            acc.add_failure();
-                        }
-                    }
-                }
+/// Time-chunked aggregation of arbitrary per-target metrics.
+///
+/// Values of all metrics except `ping_failed` are aggregated into
+/// min/max/avg; `ping_failed` points only count as failures. Only this
+/// server's own results are read; see [`query_site_aggregated`] for the
+/// results of agents.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn query_aggregated_chunked(
+    storage: &dyn StorageBackend,
+    metrics: &[&str],
+    target_filter: Option<&str>,
+    target_config: Option<&Target>,
+    from: i64,
+    to: i64,
+    bucket_duration_seconds: i64,
+    include_percentiles: bool,
+) -> Result<
+    (Vec<BucketDataPoint>, Option<super::dto::TimeRange>),
+    Box<dyn std::error::Error + Send + Sync>,
+> {
+    let mut buckets = BucketSet::new(bucket_duration_seconds, include_percentiles);
+
+    // Fast path: when we have a target config, use select() for direct label lookup
+    // instead of select_all() which scans every series
+    if let (Some(filter), Some(tc)) = (target_filter, target_config) {
+        for metric_name in metrics {
+            let points = select_target_data(storage, metric_name, tc, from, to)?;
+
+            if points.is_empty() && *metric_name != "ping_failed" {
+                debug!(
+                    "Fast path empty for target {}, falling back to select_all",
+                    filter
+                );
+                break;
             }
+            buckets.add(filter, tc.name.as_ref(), metric_name, &points);
+        }
 
-            chunk_start = chunk_end;
+        if !buckets.is_empty() {
+            return Ok(buckets.finish());
         }
     }
 
-    let data_time_range = match (earliest_ts, latest_ts) {
-        (Some(e), Some(l)) => Some(super::dto::TimeRange {
-            earliest: e,
-            latest: l,
-        }),
-        _ => None,
-    };
-
-    let mut bucket_points: Vec<BucketDataPoint> = accumulators
-        .into_values()
-        .map(|acc| acc.into_bucket_data_point())
-        .collect();
-
-    bucket_points.sort_by(|a, b| {
-        a.target
-            .cmp(&b.target)
-            .then_with(|| a.timestamp_unix.cmp(&b.timestamp_unix))
-    });
+    // Fallback / no-target-filter path: use select_all with time chunking
+    let mut buckets = BucketSet::new(bucket_duration_seconds, include_percentiles);
+    buckets.add_all(storage, metrics, from, to, |labels| {
+        // Agents' results are only read per site
+        if series_site(labels).is_some() {
+            return None;
+        }
+        let target = &labels.iter().find(|l| l.name == "target")?.value;
+        if target_filter.is_some_and(|filter| target != filter) {
+            return None;
+        }
+        let target_name = labels
+            .iter()
+            .find(|l| l.name == "target_name")
+            .map(|l| l.value.clone());
+        Some((target.clone(), target_name))
+    })?;
+    Ok(buckets.finish())
+}
 
-    Ok((bucket_points, data_time_range))
+/// Aggregation of raw results of the sites `site` selects, of the target
+/// `target_filter` (address or ID) or of all targets in `addresses`.
+///
+/// With `group_by_site`, all selected targets' results of a site are merged
+/// into one series per site, which carries the site (or [`LOCAL_SITE`]) as
+/// `target` and `site:<site>` as `target_name`, like tag groups. Otherwise
+/// there is one series per target.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn query_site_aggregated(
+    storage: &dyn StorageBackend,
+    metrics: &[&str],
+    target_filter: Option<&str>,
+    addresses: Option<&HashSet<String>>,
+    site: &SiteSelector,
+    group_by_site: bool,
+    from: i64,
+    to: i64,
+    bucket_duration_seconds: i64,
+    include_percentiles: bool,
+) -> Result<
+    (Vec<BucketDataPoint>, Option<super::dto::TimeRange>),
+    Box<dyn std::error::Error + Send + Sync>,
+> {
+    let mut buckets = BucketSet::new(bucket_duration_seconds, include_percentiles);
+    buckets.add_all(storage, metrics, from, to, |labels| {
+        if !site.matches(labels) {
+            return None;
+        }
+        let label = |name: &str| {
+            labels
+                .iter()
+                .find(|l| l.name == name)
+                .map(|l| l.value.clone())
+        };
+        let target = label("target")?;
+        if let Some(filter) = target_filter {
+            // Agents' results of targets this server does not ping carry
+            // the ID as address too
+            if target != filter && label("target_id").as_deref() != Some(filter) {
+                return None;
+            }
+        }
+        if addresses.is_some_and(|addresses| !addresses.contains(&target)) {
+            return None;
+        }
+        if group_by_site {
+            let site = series_site(labels).unwrap_or(LOCAL_SITE).to_string();
+            let name = format!("{}:{}", SITE_LABEL, site);
+            return Some((site, Some(name)));
+        }
+        Some((target, label("target_name")))
+    })?;
+    Ok(buckets.finish())
 }
 
 /// Calculate percentiles from a sorted vector of values
-fn calculate_percentiles(sorted_values: &[f64]) -> Option<Percentiles> {
+pub(crate) fn calculate_percentiles(sorted_values: &[f64]) -> Option<Percentiles> {
     if sorted_values.is_empty() {
         return None;
     }
@@ -530,34 +1273,41 @@ fn calculate_percentiles(sorted_values: &[f64]) -> Option<Percentiles> {
     })
 }
 
-/// Aggregate ping data points into time buckets, grouped by target
+/// Aggregate ping data points into time buckets, grouped by target. With
+/// `calendar`, buckets follow its weeks, months or local days instead of
+/// multiples of `bucket_duration_seconds` since the epoch.
 #[allow(dead_code)]
 pub(super) fn aggregate_into_buckets(
     points: &[PingDataPoint],
     bucket_duration_seconds: i64,
     include_percentiles: bool,
+    calendar: Option<&LocalBuckets>,
 ) -> Vec<BucketDataPoint> {
     if points.is_empty() || bucket_duration_seconds <= 0 {
         return Vec::new();
     }
 
     // Create buckets grouped by (target, bucket_start)
-    let mut buckets: HashMap<(String, i64), Vec<&PingDataPoint>> = HashMap::new();
+    let mut buckets: HashMap<(String, i64, i64), Vec<&PingDataPoint>> = HashMap::new();
 
     for point in points {
         // Calculate which bucket this point belongs to
-        let bucket_start =
-            (point.timestamp_unix / bucket_duration_seconds) * bucket_duration_seconds;
-        let key = (point.target.clone(), bucket_start);
-        buckets.entry(key).or_insert_with(Vec::new).push(point);
+        let (bucket_start, bucket_end) = match calendar {
+            Some(calendar) => calendar.bounds(point.timestamp_unix),
+            None => {
+                let start =
+                    (point.timestamp_unix / bucket_duration_seconds) * bucket_duration_seconds;
+                (start, start + bucket_duration_seconds)
+            }
+        };
+        let key = (point.target.clone(), bucket_start, bucket_end);
+        buckets.entry(key).or_default().push(point);
     }
 
     // Convert buckets to sorted vector of BucketDataPoint
     let mut bucket_points: Vec<_> = buckets
         .into_iter()
-        .map(|((target, bucket_start), bucket_points)| {
-            let bucket_end = bucket_start + bucket_duration_seconds;
-
+        .map(|((target, bucket_start, bucket_end), bucket_points)| {
             // Get target_name from first point (should be same for all points with same target)
             let target_name = bucket_points.first().and_then(|p| p.target_name.clone());
 
@@ -586,7 +1336,7 @@ pub(super) fn aggregate_into_buckets(
 
             BucketDataPoint {
                 timestamp: DateTime::from_timestamp(bucket_start, 0)
-                    .unwrap_or_else(|| Utc::now())
+                    .unwrap_or_else(Utc::now)
                     .to_rfc3339(),
                 timestamp_unix: bucket_start,
                 timestamp_end_unix: bucket_end,
@@ -599,6 +1349,9 @@ pub(super) fn aggregate_into_buckets(
                 count: bucket_points.len(),
                 successful_count: successful.len(),
                 failed_count: failed.len(),
+                resolution: None,
+                complete: true,
+                loss_percent: None,
             }
         })
         .collect();
@@ -646,12 +1399,62 @@ pub(super) fn calculate_statistics(points: &[PingDataPoint]) -> PingStatistics {
         min_latency_ms,
         max_latency_ms,
         success_rate,
+        avg_jitter_ms: None,
+        max_jitter_ms: None,
+        avg_batch_loss_percent: None,
     }
 }
 
+/// Fill in the jitter and batch loss statistics from the metrics stored
+/// alongside the results in `[from, to]`, of the targets at `addresses` if
+/// given
+pub(crate) fn add_batch_statistics(
+    storage: &dyn StorageBackend,
+    statistics: &mut PingStatistics,
+    target_filter: Option<&str>,
+    target_config: Option<&Target>,
+    addresses: Option<&HashSet<String>>,
+    from: i64,
+    to: i64,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let select = |metric: &str| -> Result<Vec<f64>, Box<dyn std::error::Error + Send + Sync>> {
+        if let Some(tc) = target_config {
+            let points = select_target_data(storage, metric, tc, from, to)?;
+            return Ok(points.iter().map(|p| p.value).collect());
+        }
+        let mut values = Vec::new();
+        for (labels, points) in storage.select_all(metric, from, to)? {
+            let matches = series_site(&labels).is_none()
+                && target_filter.is_none_or(|filter| {
+                    labels
+                        .iter()
+                        .any(|l| (l.name == "target" || l.name == "target_id") && l.value == filter)
+                })
+                && addresses.is_none_or(|addresses| {
+                    labels
+                        .iter()
+                        .any(|l| l.name == "target" && addresses.contains(&l.value))
+                });
+            if matches {
+                values.extend(points.iter().map(|p| p.value));
+            }
+        }
+        Ok(values)
+    };
+    let average = |values: &[f64]| {
+        (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+    };
+
+    let jitter = select(JITTER_METRIC)?;
+    statistics.avg_jitter_ms = average(&jitter);
+    statistics.max_jitter_ms = jitter.iter().copied().reduce(f64::max);
+    statistics.avg_batch_loss_percent = average(&select(BATCH_LOSS_METRIC)?);
+    Ok(())
+}
+
 /// Extract target_id from a hex-encoded metric name
 /// The format is: 2-byte LE length + metric_name, then pairs of (2-byte LE length + label_name, 2-byte LE length + label_value)
-fn extract_target_id_from_metric_name(hex_name: &str) -> Option<String> {
+pub(crate) fn extract_target_id_from_metric_name(hex_name: &str) -> Option<String> {
     // Decode hex to bytes
     let bytes = hex::decode(hex_name).ok()?;
 
@@ -690,7 +1493,7 @@ fn extract_target_id_from_metric_name(hex_name: &str) -> Option<String> {
 }
 
 /// Calculate storage statistics per target by reading tsink partition metadata
-pub(super) fn calculate_storage_stats(
+pub(crate) fn calculate_storage_stats(
     data_path: &str,
 ) -> Result<super::dto::StorageStatsResponse, Box<dyn std::error::Error + Send + Sync>> {
     use super::dto::StorageStatsResponse;
@@ -797,7 +1600,7 @@ pub(super) fn calculate_storage_stats(
     }
 
     let mut targets: Vec<TargetStorageStats> = target_stats.into_values().collect();
-    targets.sort_by(|a, b| b.size_bytes.cmp(&a.size_bytes));
+    targets.sort_by_key(|t| std::cmp::Reverse(t.size_bytes));
 
     Ok(StorageStatsResponse {
         total_size_bytes: total_size,
@@ -808,6 +1611,7 @@ pub(super) fn calculate_storage_stats(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tsink::Row;
 
     #[test]
     fn test_calculate_percentiles() {
@@ -845,4 +1649,342 @@ mod tests {
         assert_eq!(percentiles.p95, 42.0);
         assert_eq!(percentiles.p99, 42.0);
     }
+
+    #[test]
+    fn test_parse_bucket_duration() {
+        assert_eq!(parse_bucket_duration("5m"), Ok(300));
+        assert_eq!(parse_bucket_duration("2w"), Ok(14 * 86400));
+        assert_eq!(parse_bucket_duration("1mo"), Ok(30 * 86400));
+        assert!(parse_bucket_duration("1y").is_err());
+        assert!(parse_bucket_duration("0h").is_err());
+    }
+
+    fn stream_storage() -> std::sync::Arc<dyn StorageBackend> {
+        let storage = crate::storage::memory_storage();
+        let labels = |address: &str| {
+            vec![
+                Label::new("target_id", address),
+                Label::new("target", address),
+                Label::new("sequence", "1"),
+            ]
+        };
+        let mut rows = Vec::new();
+        // One point per hour over two days, spanning several chunks
+        for hour in 0..48 {
+            let ts = 1_000_000 + hour * 3600;
+            rows.push(Row::with_labels(
+                "ping_latency",
+                labels("192.168.1.1"),
+                DataPoint::new(ts, hour as f64),
+            ));
+            rows.push(Row::with_labels(
+                "ping_failed",
+                labels("192.168.1.2"),
+                DataPoint::new(ts + 1, 0.0),
+            ));
+        }
+        storage.insert_rows(&rows).unwrap();
+        storage
+    }
+
+    fn resolved(target: Option<&str>, limit: Option<usize>) -> ResolvedPingDataQuery {
+        ResolvedPingDataQuery {
+            target: target.map(|t| t.to_string()),
+            target_config: None,
+            from: 1_000_000,
+            to: 1_000_000 + 48 * 3600,
+            metric: None,
+            limit,
+            addresses: None,
+            descending: false,
+            after: None,
+            site: SiteSelector::Local,
+        }
+    }
+
+    #[test]
+    fn test_query_ping_data_streams_in_order() {
+        let storage = stream_storage();
+
+        let all = query_ping_data_with_labels(&*storage, &resolved(None, None)).unwrap();
+        assert_eq!(all.len(), 96);
+        assert!(all
+            .windows(2)
+            .all(|w| w[0].timestamp_unix <= w[1].timestamp_unix));
+
+        let limited = query_ping_data_with_labels(&*storage, &resolved(None, Some(3))).unwrap();
+        let timestamps: Vec<i64> = limited.iter().map(|p| p.timestamp_unix).collect();
+        assert_eq!(timestamps, vec![1_000_000, 1_000_001, 1_003_600]);
+    }
+
+    #[test]
+    fn test_query_ping_data_selects_site() {
+        let storage = stream_storage();
+        let site_labels = vec![
+            Label::new("target_id", "192.168.1.1"),
+            Label::new("target", "192.168.1.1"),
+            Label::new(SITE_LABEL, "berlin"),
+        ];
+        storage
+            .insert_rows(&[Row::with_labels(
+                "ping_latency",
+                site_labels,
+                DataPoint::new(1_000_002, 7.0),
+            )])
+            .unwrap();
+        let query = |site| ResolvedPingDataQuery {
+            site,
+            ..resolved(Some("192.168.1.1"), None)
+        };
+
+        let own = query_ping_data_with_labels(&*storage, &query(SiteSelector::Local)).unwrap();
+        assert_eq!(own.len(), 48);
+        assert!(own.iter().all(|p| p.site.is_none()));
+
+        let berlin = SiteSelector::Site("berlin".to_string());
+        let site = query_ping_data_with_labels(&*storage, &query(berlin)).unwrap();
+        assert_eq!(site.len(), 1);
+        assert_eq!(site[0].site.as_deref(), Some("berlin"));
+        assert_eq!(site[0].latency_ms, Some(7.0));
+
+        let all = query_ping_data_with_labels(&*storage, &query(SiteSelector::All)).unwrap();
+        assert_eq!(all.len(), 49);
+    }
+
+    #[test]
+    fn test_select_target_data_all_sequences() {
+        let storage = crate::storage::memory_storage();
+        let target = Target {
+            id: "router".to_string(),
+            address: "192.168.1.1".to_string(),
+            ping_interval: 10,
+            ..Default::default()
+        };
+        let rows: Vec<Row> = (1..=3)
+            .map(|sequence| {
+                let result = crate::ping::PingResult {
+                    timestamp: DateTime::from_timestamp(100 + sequence as i64, 0).unwrap(),
+                    target_id: target.id.clone(),
+                    target: target.address.clone(),
+                    target_name: None,
+                    sequence,
+                    resolved_ip: None,
+                    probe_type: ProbeType::Icmp,
+                    port: None,
+                    success: true,
+                    latency_ms: Some(sequence as f64),
+                    correction_ms: None,
+                    flow: None,
+                };
+                crate::storage::ping_result_row(&result, Vec::new())
+            })
+            .collect();
+        storage.insert_rows(&rows).unwrap();
+
+        // The last sequence of a batch is selected as well
+        let points = select_target_data(&*storage, "ping_latency", &target, 0, 200).unwrap();
+        let mut latencies: Vec<f64> = points.iter().map(|p| p.value).collect();
+        latencies.sort_by(f64::total_cmp);
+        assert_eq!(latencies, vec![1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn test_query_ping_data_pages() {
+        let storage = stream_storage();
+
+        for descending in [false, true] {
+            let all = query_ping_data_with_labels(
+                &*storage,
+                &ResolvedPingDataQuery {
+                    descending,
+                    ..resolved(None, None)
+                },
+            )
+            .unwrap();
+
+            // Pages of 5 across chunk boundaries return every result once
+            let mut paged = Vec::new();
+            let mut after = None;
+            loop {
+                let query = ResolvedPingDataQuery {
+                    descending,
+                    after: after
+                        .as_ref()
+                        .map(|c: &PingDataCursor| PingDataCursor::decode(&c.encode()).unwrap()),
+                    ..resolved(None, Some(5))
+                };
+                let (points, next) = query_ping_data_page(&*storage, &query).unwrap();
+                paged.extend(points);
+                match next {
+                    Some(next) => after = Some(next),
+                    None => break,
+                }
+            }
+            let keys = |points: &[PingDataPoint]| -> Vec<(i64, String)> {
+                points
+                    .iter()
+                    .map(|p| (p.timestamp_unix, p.target.clone()))
+                    .collect()
+            };
+            assert_eq!(keys(&paged), keys(&all));
+            assert_eq!(paged.len(), 96);
+            assert_eq!(
+                paged[0].timestamp_unix,
+                if descending { 1_169_201 } else { 1_000_000 }
+            );
+        }
+
+        assert!(PingDataCursor::decode("not-a-cursor").is_err());
+    }
+
+    #[test]
+    fn test_query_ping_data_target_filter() {
+        let storage = stream_storage();
+
+        let points =
+            query_ping_data_with_labels(&*storage, &resolved(Some("192.168.1.2"), Some(10)))
+                .unwrap();
+        assert_eq!(points.len(), 10);
+        assert!(points
+            .iter()
+            .all(|p| p.target == "192.168.1.2" && !p.success));
+    }
+
+    #[test]
+    fn test_annotate_buckets() {
+        let bucket = |start: i64, resolution: Option<&'static str>| BucketDataPoint {
+            timestamp: String::new(),
+            timestamp_unix: start,
+            timestamp_end_unix: start + 3600,
+            target: "192.168.1.1".to_string(),
+            target_name: None,
+            min: None,
+            max: None,
+            avg: None,
+            percentiles: None,
+            count: 0,
+            successful_count: 0,
+            failed_count: 0,
+            resolution,
+            complete: true,
+            loss_percent: None,
+        };
+
+        // A bucket split between rollups and raw data
+        let mut split = bucket(3600, Some("1h"));
+        merge_bucket(&mut split, &bucket(3600, Some("raw")));
+        assert_eq!(split.resolution, Some("mixed"));
+
+        let mut buckets = vec![bucket(0, None), split, bucket(7200, Some("1h"))];
+        annotate_buckets(&mut buckets, 1800, 9000, "raw");
+        let summary: Vec<_> = buckets.iter().map(|b| (b.resolution, b.complete)).collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Some("raw"), false),
+                (Some("mixed"), true),
+                (Some("1h"), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_set_loss_percent() {
+        let bucket = |count: usize, failed_count: usize| BucketDataPoint {
+            timestamp: String::new(),
+            timestamp_unix: 0,
+            timestamp_end_unix: 60,
+            target: "192.168.1.1".to_string(),
+            target_name: None,
+            min: Some(1.0),
+            max: Some(3.0),
+            avg: Some(2.0),
+            percentiles: None,
+            count,
+            successful_count: count - failed_count,
+            failed_count,
+            resolution: None,
+            complete: true,
+            loss_percent: None,
+        };
+
+        let mut buckets = vec![bucket(4, 1), bucket(0, 0)];
+        set_loss_percent(&mut buckets, false);
+        assert_eq!(buckets[0].loss_percent, Some(25.0));
+        assert_eq!(buckets[0].avg, Some(2.0));
+        assert_eq!(buckets[1].loss_percent, None);
+
+        set_loss_percent(&mut buckets, true);
+        assert_eq!(buckets[0].loss_percent, Some(25.0));
+        assert_eq!((buckets[0].min, buckets[0].avg), (None, None));
+    }
+
+    #[test]
+    fn test_query_ping_aggregated_cached() {
+        let storage = stream_storage();
+        let cache = AggregateCache::new();
+        let coverage = Coverage::default();
+        let (from, to) = (1_000_000 + 1800, 1_000_000 + 48 * 3600);
+        let summary = |buckets: &[BucketDataPoint]| {
+            buckets
+                .iter()
+                .map(|b| (b.target.clone(), b.timestamp_unix, b.count, b.max))
+                .collect::<Vec<_>>()
+        };
+        let cached = |now: i64| {
+            let (buckets, _, resolution) = query_ping_aggregated_cached(
+                &cache, &*storage, &coverage, None, None, from, to, 21600, false, now,
+            )
+            .unwrap();
+            assert_eq!(resolution, "raw");
+            summary(&buckets)
+        };
+        let (direct, _, _) = query_ping_aggregated_with_rollups(
+            &*storage, &coverage, None, None, from, to, 21600, false,
+        )
+        .unwrap();
+
+        // Filled, then served from the cache with the same result
+        assert_eq!(cached(to), summary(&direct));
+        assert_eq!(cached(to), summary(&direct));
+
+        // A write the cache was not told about stays hidden ...
+        let late = vec![Row::with_labels(
+            "ping_latency",
+            vec![
+                Label::new("target_id", "192.168.1.1"),
+                Label::new("target", "192.168.1.1"),
+                Label::new("sequence", "2"),
+            ],
+            DataPoint::new(1_000_000 + 10 * 3600 + 60, 500.0),
+        )];
+        storage.insert_rows(&late).unwrap();
+        assert_eq!(cached(to), summary(&direct));
+
+        // ... until it invalidates the bucket holding it
+        cache.invalidate(&late);
+        let (direct, _, _) = query_ping_aggregated_with_rollups(
+            &*storage, &coverage, None, None, from, to, 21600, false,
+        )
+        .unwrap();
+        assert_eq!(cached(to), summary(&direct));
+        assert!(direct.iter().any(|b| b.max == Some(500.0)));
+    }
+
+    #[test]
+    fn test_earliest_data_timestamp() {
+        let dir = std::env::temp_dir().join(format!("sparkping-query-{}", uuid::Uuid::new_v4()));
+        let now = 10_000_000;
+        fs::create_dir_all(&dir).unwrap();
+        assert_eq!(
+            earliest_data_timestamp(&dir, now),
+            now - UNFLUSHED_SPAN_SECS
+        );
+
+        fs::create_dir_all(dir.join("p-5000-26600")).unwrap();
+        fs::create_dir_all(dir.join("p-26600-48200")).unwrap();
+        fs::create_dir_all(dir.join("wal")).unwrap();
+        assert_eq!(earliest_data_timestamp(&dir, now), 5000);
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,259 @@
+//! Per-target overview for GET /api/ping/summary.
+//!
+//! The current state, last latency and last-hour figures come from the
+//! in-memory rollups; the 24h figures and sparklines from one aggregated
+//! query over all targets, so the dashboard overview needs a single request.
+
+use super::dto::{BucketDataPoint, SparklinePoint, TargetSummary};
+use crate::api::targets::filter::TargetState;
+use crate::config::Target;
+use crate::rollups::TargetRollups;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// Range covered by the sparklines
+pub(crate) const SUMMARY_RANGE_SECONDS: i64 = 86400;
+
+/// Default number of sparkline buckets
+pub(crate) const DEFAULT_SPARKLINE_BUCKETS: usize = 24;
+
+/// Most sparkline buckets (5 minute buckets over a day)
+pub(crate) const MAX_SPARKLINE_BUCKETS: usize = 288;
+
+/// Order of the summarized targets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SummarySort {
+    /// Highest average latency of the last hour first
+    Latency,
+    /// Highest loss of the last hour first
+    Loss,
+}
+
+impl SummarySort {
+    pub(crate) fn parse(value: &str) -> Result<Self, String> {
+        match value.to_ascii_lowercase().as_str() {
+            "latency" => Ok(SummarySort::Latency),
+            "loss" => Ok(SummarySort::Loss),
+            _ => Err(format!(
+                "Unsupported sort '{}' (expected latency or loss)",
+                value
+            )),
+        }
+    }
+}
+
+/// Sparkline buckets: `count` buckets of whole minutes ending with the one
+/// that contains `now`, aligned like aggregated query buckets. Returns the
+/// bucket duration and the start of the first bucket.
+pub(crate) fn sparkline_range(now: i64, count: usize) -> (i64, i64) {
+    let duration = (SUMMARY_RANGE_SECONDS / count as i64 / 60).max(1) * 60;
+    let last_start = now - now.rem_euclid(duration);
+    (duration, last_start - (count as i64 - 1) * duration)
+}
+
+/// Summaries of `targets`. `rollups` holds each target's rollups by index;
+/// `buckets` are the aggregated buckets of all targets, matched by address.
+pub(crate) fn summarize(
+    targets: &[Target],
+    rollups: &[Option<TargetRollups>],
+    buckets: &[BucketDataPoint],
+    from: i64,
+    bucket_duration: i64,
+    count: usize,
+) -> Vec<TargetSummary> {
+    let mut by_address: HashMap<&str, Vec<&BucketDataPoint>> = HashMap::new();
+    for bucket in buckets {
+        by_address.entry(&bucket.target).or_default().push(bucket);
+    }
+
+    targets
+        .iter()
+        .enumerate()
+        .map(|(i, target)| {
+            let rollups = rollups.get(i).and_then(Option::as_ref);
+            let mut sparkline: Vec<SparklinePoint> = (0..count as i64)
+                .map(|slot| SparklinePoint {
+                    timestamp_unix: from + slot * bucket_duration,
+                    avg_latency_ms: None,
+                    loss_percent: None,
+                })
+                .collect();
+
+            let (mut successful, mut failed, mut latency_sum) = (0usize, 0usize, 0.0);
+            let target_buckets = by_address.get(target.address.as_str());
+            for bucket in target_buckets.into_iter().flatten() {
+                let slot = (bucket.timestamp_unix - from).div_euclid(bucket_duration);
+                let Some(point) = usize::try_from(slot)
+                    .ok()
+                    .and_then(|s| sparkline.get_mut(s))
+                else {
+                    continue;
+                };
+                point.avg_latency_ms = bucket.avg;
+                point.loss_percent = loss_percent(bucket.successful_count, bucket.failed_count);
+                successful += bucket.successful_count;
+                failed += bucket.failed_count;
+                latency_sum += bucket.avg.unwrap_or(0.0) * bucket.successful_count as f64;
+            }
+
+            TargetSummary {
+                target_id: target.id.clone(),
+                address: target.address.clone(),
+                name: target.name.clone(),
+                state: TargetState::of(target, rollups),
+                last_sample_unix: rollups.and_then(|r| r.last_sample_unix),
+                last_latency_ms: rollups.and_then(|r| r.last_latency_ms),
+                avg_latency_1h_ms: rollups.and_then(|r| r.one_hour.avg_latency_ms),
+                loss_percent_1h: rollups.and_then(|r| r.one_hour.loss_percent),
+                avg_latency_24h_ms: (successful > 0).then(|| latency_sum / successful as f64),
+                loss_percent_24h: loss_percent(successful, failed),
+                sparkline,
+            }
+        })
+        .collect()
+}
+
+fn loss_percent(successful: usize, failed: usize) -> Option<f64> {
+    let sent = successful + failed;
+    (sent > 0).then(|| failed as f64 / sent as f64 * 100.0)
+}
+
+/// Sort summaries worst first; targets without a value come last
+pub(crate) fn sort_summaries(summaries: &mut [TargetSummary], sort: SummarySort) {
+    let value = |summary: &TargetSummary| match sort {
+        SummarySort::Latency => summary.avg_latency_1h_ms,
+        SummarySort::Loss => summary.loss_percent_1h,
+    };
+    summaries.sort_by(|a, b| match (value(a), value(b)) {
+        (Some(a), Some(b)) => b.partial_cmp(&a).unwrap_or(Ordering::Equal),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rollups::RollingAggregator;
+
+    fn target(id: &str, address: &str) -> Target {
+        Target {
+            id: id.to_string(),
+            address: address.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn bucket(
+        address: &str,
+        start: i64,
+        avg: Option<f64>,
+        ok: usize,
+        failed: usize,
+    ) -> BucketDataPoint {
+        BucketDataPoint {
+            timestamp: String::new(),
+            timestamp_unix: start,
+            timestamp_end_unix: start + 3600,
+            target: address.to_string(),
+            target_name: None,
+            min: avg,
+            max: avg,
+            avg,
+            percentiles: None,
+            count: ok + failed,
+            successful_count: ok,
+            failed_count: failed,
+            resolution: None,
+            complete: true,
+            loss_percent: None,
+        }
+    }
+
+    #[test]
+    fn test_sparkline_range() {
+        assert_eq!(
+            sparkline_range(7 * 3600 + 120, 24),
+            (3600, 7 * 3600 - 23 * 3600)
+        );
+        // Durations are rounded down to whole minutes
+        assert_eq!(sparkline_range(0, 7).0, 12300);
+        assert_eq!(sparkline_range(0, 288).0, 300);
+    }
+
+    #[test]
+    fn test_summarize() {
+        let targets = vec![target("a", "10.0.0.1"), target("b", "10.0.0.2")];
+        let aggregator = RollingAggregator::new();
+        aggregator.record("a", 10_000, Some(12.0));
+        let rollups = vec![aggregator.rollups("a", 10_000), None];
+        let buckets = vec![
+            bucket("10.0.0.1", 0, Some(10.0), 3, 1),
+            bucket("10.0.0.1", 7200, Some(20.0), 1, 0),
+            // Outside the sparkline range
+            bucket("10.0.0.1", 10_800, Some(90.0), 1, 0),
+            bucket("10.0.0.9", 0, Some(5.0), 1, 0),
+        ];
+
+        let summaries = summarize(&targets, &rollups, &buckets, 0, 3600, 3);
+        let a = &summaries[0];
+        assert_eq!(a.state, TargetState::Up);
+        assert_eq!(a.last_latency_ms, Some(12.0));
+        assert_eq!(a.avg_latency_24h_ms, Some(12.5));
+        assert_eq!(a.loss_percent_24h, Some(20.0));
+        assert_eq!(
+            a.sparkline,
+            vec![
+                SparklinePoint {
+                    timestamp_unix: 0,
+                    avg_latency_ms: Some(10.0),
+                    loss_percent: Some(25.0),
+                },
+                SparklinePoint {
+                    timestamp_unix: 3600,
+                    avg_latency_ms: None,
+                    loss_percent: None,
+                },
+                SparklinePoint {
+                    timestamp_unix: 7200,
+                    avg_latency_ms: Some(20.0),
+                    loss_percent: Some(0.0),
+                },
+            ]
+        );
+
+        let b = &summaries[1];
+        assert_eq!(b.state, TargetState::Unknown);
+        assert_eq!(b.avg_latency_24h_ms, None);
+        assert_eq!(b.sparkline.len(), 3);
+    }
+
+    #[test]
+    fn test_sort_worst_first() {
+        let targets = vec![
+            target("a", "10.0.0.1"),
+            target("b", "10.0.0.2"),
+            target("c", "10.0.0.3"),
+        ];
+        let aggregator = RollingAggregator::new();
+        aggregator.record("a", 100, Some(5.0));
+        aggregator.record("c", 100, Some(50.0));
+        aggregator.record("c", 101, None);
+        let rollups: Vec<_> = ["a", "b", "c"]
+            .iter()
+            .map(|id| aggregator.rollups(id, 101))
+            .collect();
+        let mut summaries = summarize(&targets, &rollups, &[], 0, 3600, 1);
+
+        sort_summaries(&mut summaries, SummarySort::Latency);
+        let order: Vec<&str> = summaries.iter().map(|s| s.target_id.as_str()).collect();
+        assert_eq!(order, vec!["c", "a", "b"]);
+
+        sort_summaries(&mut summaries, SummarySort::Loss);
+        assert_eq!(summaries[0].target_id, "c");
+        assert_eq!(summaries[0].loss_percent_1h, Some(50.0));
+
+        assert!(SummarySort::parse("jitter").is_err());
+    }
+}
@@ -0,0 +1,195 @@
+//! Trend fitting for GET /api/ping/trend.
+//!
+//! Hourly latency averages and loss percentages are decomposed into a linear
+//! trend (least squares) plus, when the window holds at least two days, a
+//! daily profile (mean residual per local hour of day). The forecast extends both
+//! and adds a 95% prediction band from the remaining residual spread.
+
+use super::calendar::local_hour;
+use super::dto::{BucketDataPoint, ForecastPoint, MetricTrend};
+use chrono_tz::Tz;
+
+/// Bucket size the trend is fitted on
+pub(crate) const TREND_BUCKET_SECONDS: i64 = 3600;
+
+/// Minimum number of hourly samples needed to fit a trend
+const MIN_SAMPLES: usize = 12;
+
+/// z-score of the two-sided 95% prediction band
+const Z_95: f64 = 1.96;
+
+const SECONDS_PER_DAY: f64 = 86400.0;
+
+/// Hourly (timestamp, latency avg) and (timestamp, loss %) series from buckets
+pub(crate) fn series_from_buckets(
+    buckets: &[BucketDataPoint],
+) -> (Vec<(i64, f64)>, Vec<(i64, f64)>) {
+    let mut latency = Vec::new();
+    let mut loss = Vec::new();
+    for bucket in buckets {
+        if let Some(avg) = bucket.avg {
+            latency.push((bucket.timestamp_unix, avg));
+        }
+        let sent = bucket.successful_count + bucket.failed_count;
+        if sent > 0 {
+            loss.push((
+                bucket.timestamp_unix,
+                bucket.failed_count as f64 / sent as f64 * 100.0,
+            ));
+        }
+    }
+    (latency, loss)
+}
+
+/// Fit a trend to `samples` and forecast `horizon` seconds past `to`.
+/// Forecast values are clamped to `[min, max]`. The daily profile uses the
+/// hours of `tz`. None if there are too few samples.
+pub(crate) fn fit_trend(
+    samples: &[(i64, f64)],
+    to: i64,
+    horizon: i64,
+    min: f64,
+    max: f64,
+    tz: Tz,
+) -> Option<MetricTrend> {
+    let n = samples.len();
+    if n < MIN_SAMPLES {
+        return None;
+    }
+
+    // Time in days since the first sample keeps the regression well-conditioned
+    let origin = samples[0].0;
+    let days = |ts: i64| (ts - origin) as f64 / SECONDS_PER_DAY;
+
+    let mean_t = samples.iter().map(|&(ts, _)| days(ts)).sum::<f64>() / n as f64;
+    let mean_y = samples.iter().map(|&(_, y)| y).sum::<f64>() / n as f64;
+    let sxx: f64 = samples
+        .iter()
+        .map(|&(ts, _)| (days(ts) - mean_t).powi(2))
+        .sum();
+    let sxy: f64 = samples
+        .iter()
+        .map(|&(ts, y)| (days(ts) - mean_t) * (y - mean_y))
+        .sum();
+    let slope = if sxx > 0.0 { sxy / sxx } else { 0.0 };
+    let intercept = mean_y - slope * mean_t;
+    let linear = |ts: i64| intercept + slope * days(ts);
+
+    // Daily profile from the detrended residuals
+    let span_days = days(samples[n - 1].0);
+    let seasonal = span_days >= 2.0;
+    let mut profile = [0.0; 24];
+    if seasonal {
+        let mut counts = [0usize; 24];
+        for &(ts, y) in samples {
+            let hour = local_hour(tz, ts);
+            profile[hour] += y - linear(ts);
+            counts[hour] += 1;
+        }
+        for (value, count) in profile.iter_mut().zip(counts) {
+            if count > 0 {
+                *value /= count as f64;
+            }
+        }
+    }
+    let fitted = |ts: i64| linear(ts) + profile[local_hour(tz, ts)];
+
+    let residual_ss: f64 = samples
+        .iter()
+        .map(|&(ts, y)| (y - fitted(ts)).powi(2))
+        .sum();
+    // Degrees of freedom: slope, intercept, and the 24 profile means
+    let params = if seasonal { 26 } else { 2 };
+    let residual_stddev = (residual_ss / n.saturating_sub(params).max(1) as f64).sqrt();
+
+    let first_forecast = (to.div_euclid(TREND_BUCKET_SECONDS) + 1) * TREND_BUCKET_SECONDS;
+    let forecast = (first_forecast..=to + horizon)
+        .step_by(TREND_BUCKET_SECONDS as usize)
+        .map(|ts| {
+            let t = days(ts);
+            // Prediction interval of the linear fit widens away from the samples
+            let leverage = if sxx > 0.0 {
+                1.0 / n as f64 + (t - mean_t).powi(2) / sxx
+            } else {
+                0.0
+            };
+            let spread = Z_95 * residual_stddev * (1.0 + leverage).sqrt();
+            let value = fitted(ts);
+            ForecastPoint {
+                timestamp_unix: ts,
+                value: value.clamp(min, max),
+                lower: (value - spread).clamp(min, max),
+                upper: (value + spread).clamp(min, max),
+            }
+        })
+        .collect();
+
+    Some(MetricTrend {
+        samples: n,
+        slope_per_day: slope,
+        current: linear(to),
+        change_over_window: slope * span_days,
+        seasonal,
+        residual_stddev,
+        forecast,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit_linear_trend() {
+        // 0.5 ms per day increase over 10 days, no noise
+        let samples: Vec<(i64, f64)> = (0..240)
+            .map(|h| (h * 3600, 10.0 + 0.5 * h as f64 / 24.0))
+            .collect();
+        let to = 240 * 3600;
+        let trend = fit_trend(&samples, to, 86400, 0.0, f64::MAX, chrono_tz::UTC).unwrap();
+
+        assert!((trend.slope_per_day - 0.5).abs() < 1e-9);
+        assert!((trend.current - 15.0).abs() < 1e-6);
+        assert!(trend.residual_stddev < 1e-6);
+        assert_eq!(trend.forecast.len(), 24);
+        let last = trend.forecast.last().unwrap();
+        assert_eq!(last.timestamp_unix, to + 86400);
+        assert!((last.value - 15.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_fit_daily_profile() {
+        // Flat trend with +5 ms every evening (18:00-23:00)
+        let samples: Vec<(i64, f64)> = (0..72)
+            .map(|h| {
+                let evening = h % 24 >= 18;
+                (h * 3600, if evening { 25.0 } else { 20.0 })
+            })
+            .collect();
+        let trend = fit_trend(&samples, 72 * 3600, 86400, 0.0, f64::MAX, chrono_tz::UTC).unwrap();
+
+        assert!(trend.seasonal);
+        let at_hour = |hour: i64| {
+            trend
+                .forecast
+                .iter()
+                .find(|p| p.timestamp_unix % 86400 == hour * 3600)
+                .unwrap()
+                .value
+        };
+        assert!(at_hour(20) - at_hour(8) > 4.0);
+    }
+
+    #[test]
+    fn test_fit_trend_clamps_and_requires_samples() {
+        assert!(fit_trend(&[(0, 1.0); 3], 3600, 3600, 0.0, 100.0, chrono_tz::UTC).is_none());
+
+        // Loss falling towards zero never forecasts negative values
+        let samples: Vec<(i64, f64)> = (0..48).map(|h| (h * 3600, 48.0 - h as f64)).collect();
+        let trend = fit_trend(&samples, 48 * 3600, 7 * 86400, 0.0, 100.0, chrono_tz::UTC).unwrap();
+        assert!(trend
+            .forecast
+            .iter()
+            .all(|p| p.lower >= 0.0 && p.value >= 0.0));
+    }
+}
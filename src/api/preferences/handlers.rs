@@ -0,0 +1,43 @@
+use crate::api::error::{ApiError, ErrorCode};
+use crate::api::extract::Json;
+use crate::api::AppState;
+use crate::preferences::{Preferences, PreferencesError};
+use axum::extract::State;
+use std::sync::Arc;
+use tracing::error;
+
+/// HTTP handler for GET /api/preferences
+///
+/// Returns all stored UI preferences as a JSON object.
+pub(crate) async fn get_preferences(State(state): State<AppState>) -> Json<Preferences> {
+    Json(state.preferences.get())
+}
+
+/// HTTP handler for PUT /api/preferences
+///
+/// Merges the given keys into the stored preferences (`null` removes a key)
+/// and returns the full result.
+pub(crate) async fn update_preferences(
+    State(state): State<AppState>,
+    Json(update): Json<Preferences>,
+) -> Result<Json<Preferences>, ApiError> {
+    let preferences = Arc::clone(&state.preferences);
+    let result = tokio::task::spawn_blocking(move || preferences.update(update))
+        .await
+        .map_err(|e| {
+            error!("Task join error: {}", e);
+            ApiError::internal(ErrorCode::Internal, e.to_string())
+        })?;
+
+    match result {
+        Ok(preferences) => Ok(Json(preferences)),
+        Err(e @ PreferencesError::Invalid(_)) => Err(ApiError::bad_request(
+            ErrorCode::InvalidRequest,
+            e.to_string(),
+        )),
+        Err(e @ PreferencesError::Io(_)) => {
+            error!("{}", e);
+            Err(ApiError::internal(ErrorCode::Internal, e.to_string()))
+        }
+    }
+}
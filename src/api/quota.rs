@@ -0,0 +1,563 @@
+//! Per-requester quotas for expensive endpoints.
+//!
+//! Historical queries scan tsink partitions and discovery scans, test pings,
+//! traceroutes and diagnoses send probes for seconds to minutes, so each
+//! requester gets limits on them (`[server.query_quota]`):
+//!
+//! - every request counts against the daily budget (`daily_budget`)
+//! - historical queries count against `max_concurrent`
+//! - expensive requests, that is scans and raw data queries without a
+//!   `limit`, count against `[server.query_quota.expensive]`: a per-minute
+//!   rate and a cap on how many run at once (default 30 per minute and 2)
+//!
+//! 0 disables a limit. Requesters are identified by the user or API token
+//! they authenticated as, otherwise by client IP; behind the Home Assistant
+//! ingress proxy the forwarded client IP is used instead of the proxy's. A
+//! request's concurrency slots are held until its response body has been
+//! sent.
+//!
+//! This is the only per-requester limit. It runs before the server-wide
+//! admission queue of historical queries (see `admission`), so a requester
+//! over its quota is rejected without taking a place in that queue, and a
+//! rejected request does not count against any of its limits.
+
+use crate::api::error::{ApiError, ErrorCode};
+use crate::api::middleware::{hold_until_body_ends, is_allowed_ingress_ip};
+use crate::api::AppState;
+use crate::auth::{Authenticated, Principal};
+use crate::config::QueryQuotaConfig;
+use axum::body::Body;
+use axum::extract::{ConnectInfo, State};
+use axum::http::{header, HeaderMap, HeaderValue, Request, StatusCode, Uri};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+/// Number of tracked requesters above which stale entries are pruned
+const PRUNE_THRESHOLD: usize = 256;
+
+/// Which limits a request counts against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestCost {
+    /// A historical query
+    Query,
+    /// A historical query without a row limit
+    ExpensiveQuery,
+    /// A discovery scan, test ping, traceroute or diagnosis
+    Scan,
+}
+
+impl RequestCost {
+    fn is_query(self) -> bool {
+        matches!(self, RequestCost::Query | RequestCost::ExpensiveQuery)
+    }
+
+    fn is_expensive(self) -> bool {
+        matches!(self, RequestCost::ExpensiveQuery | RequestCost::Scan)
+    }
+}
+
+/// Usage of a single requester
+#[derive(Debug, Default)]
+struct RequesterUsage {
+    /// Days since the Unix epoch (UTC) that `used` refers to
+    day: i64,
+    used: u32,
+    /// Expensive requests left of the per-minute rate, refilled
+    /// continuously
+    tokens: f64,
+    refilled_at: i64,
+    /// Running historical queries
+    in_flight: u32,
+    /// Running expensive requests
+    expensive_in_flight: u32,
+}
+
+/// Reason a request was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaExceeded {
+    Daily {
+        limit: u32,
+    },
+    /// Out of expensive requests for now; one is available again after
+    /// `retry_after` seconds
+    Rate {
+        limit: u32,
+        retry_after: u64,
+    },
+    Concurrent {
+        limit: u32,
+    },
+    ConcurrentExpensive {
+        limit: u32,
+    },
+}
+
+/// Usage per requester, shared through `AppState`
+#[derive(Debug, Default)]
+pub struct QueryQuotas {
+    usage: Mutex<HashMap<String, RequesterUsage>>,
+}
+
+/// Releases a request's concurrency slots when dropped
+#[derive(Debug)]
+pub struct QuotaGuard {
+    quotas: Arc<QueryQuotas>,
+    requester: String,
+    cost: RequestCost,
+}
+
+impl Drop for QuotaGuard {
+    fn drop(&mut self) {
+        let mut usage = self.quotas.usage.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(entry) = usage.get_mut(&self.requester) {
+            if self.cost.is_query() {
+                entry.in_flight = entry.in_flight.saturating_sub(1);
+            }
+            if self.cost.is_expensive() {
+                entry.expensive_in_flight = entry.expensive_in_flight.saturating_sub(1);
+            }
+        }
+    }
+}
+
+impl QueryQuotas {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for one request of `cost` by `requester` at Unix time `now`.
+    /// Limits are checked in the order concurrency, rate, daily budget.
+    ///
+    /// Returns a guard that holds the request's concurrency slots until
+    /// dropped.
+    pub fn try_acquire(
+        self: &Arc<Self>,
+        requester: &str,
+        limits: QueryQuotaConfig,
+        cost: RequestCost,
+        now: i64,
+    ) -> Result<QuotaGuard, QuotaExceeded> {
+        let today = now.div_euclid(86400);
+        let mut usage = self.usage.lock().unwrap_or_else(|e| e.into_inner());
+
+        if usage.len() > PRUNE_THRESHOLD {
+            usage.retain(|_, u| u.day == today || u.in_flight > 0 || u.expensive_in_flight > 0);
+        }
+
+        let expensive = limits.expensive;
+        let rate = f64::from(expensive.requests_per_minute);
+        let entry = usage
+            .entry(requester.to_string())
+            .or_insert_with(|| RequesterUsage {
+                tokens: rate,
+                refilled_at: now,
+                ..Default::default()
+            });
+        if entry.day != today {
+            entry.day = today;
+            entry.used = 0;
+        }
+        let elapsed = (now - entry.refilled_at).max(0) as f64;
+        entry.tokens = (entry.tokens + elapsed * rate / 60.0).min(rate);
+        entry.refilled_at = now;
+
+        if cost.is_query() && limits.max_concurrent > 0 && entry.in_flight >= limits.max_concurrent
+        {
+            return Err(QuotaExceeded::Concurrent {
+                limit: limits.max_concurrent,
+            });
+        }
+        if cost.is_expensive() {
+            if expensive.max_concurrent > 0 && entry.expensive_in_flight >= expensive.max_concurrent
+            {
+                return Err(QuotaExceeded::ConcurrentExpensive {
+                    limit: expensive.max_concurrent,
+                });
+            }
+            if expensive.requests_per_minute > 0 && entry.tokens < 1.0 {
+                let retry_after = ((1.0 - entry.tokens) * 60.0 / rate).ceil() as u64;
+                return Err(QuotaExceeded::Rate {
+                    limit: expensive.requests_per_minute,
+                    retry_after: retry_after.max(1),
+                });
+            }
+        }
+        if limits.daily_budget > 0 && entry.used >= limits.daily_budget {
+            return Err(QuotaExceeded::Daily {
+                limit: limits.daily_budget,
+            });
+        }
+
+        entry.used += 1;
+        if cost.is_query() {
+            entry.in_flight += 1;
+        }
+        if cost.is_expensive() {
+            if expensive.requests_per_minute > 0 {
+                entry.tokens -= 1.0;
+            }
+            entry.expensive_in_flight += 1;
+        }
+
+        Ok(QuotaGuard {
+            quotas: Arc::clone(self),
+            requester: requester.to_string(),
+            cost,
+        })
+    }
+}
+
+/// Identify the requester of a request: the user or API token it was
+/// authenticated as, so that clients sharing an IP (or one user with many
+/// IPs) are told apart, otherwise its client IP (see [`client_ip`])
+pub(crate) fn requester_key(req: &Request<Body>) -> String {
+    match req
+        .extensions()
+        .get::<Authenticated>()
+        .map(|a| &a.principal)
+    {
+        Some(Principal::User(name)) => return format!("user:{}", name),
+        Some(Principal::Token(name)) => return format!("token:{}", name),
+        None => {}
+    }
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ci| ci.0);
+    client_ip(peer, req.headers())
+}
+
+/// Client IP of a request from `peer`: the forwarded client IP behind the
+/// Home Assistant ingress proxy, the TCP peer IP otherwise
+pub(crate) fn client_ip(peer: Option<SocketAddr>, headers: &HeaderMap) -> String {
+    let peer_ip = peer.map(|addr| addr.ip().to_string());
+
+    if let Some(ref ip) = peer_ip {
+        if is_allowed_ingress_ip(ip) {
+            let forwarded = headers
+                .get("x-forwarded-for")
+                .and_then(|h| h.to_str().ok())
+                .and_then(|xff| xff.split(',').next())
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty());
+            if let Some(client) = forwarded {
+                return client;
+            }
+        }
+    }
+
+    peer_ip.unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Whether a historical query is a raw data query without a row limit
+fn is_unbounded_data_query(uri: &Uri) -> bool {
+    uri.path() == "/api/ping/data"
+        && !uri
+            .query()
+            .is_some_and(|q| q.split('&').any(|p| p.starts_with("limit=")))
+}
+
+/// Whether any limit applies to requests of `cost`
+fn is_limited(limits: &QueryQuotaConfig, cost: RequestCost) -> bool {
+    limits.daily_budget > 0
+        || (cost.is_query() && limits.max_concurrent > 0)
+        || (cost.is_expensive()
+            && (limits.expensive.requests_per_minute > 0 || limits.expensive.max_concurrent > 0))
+}
+
+/// Middleware enforcing `[server.query_quota]` on historical query routes
+pub(crate) async fn query_quota_middleware(
+    State(state): State<AppState>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let cost = if is_unbounded_data_query(req.uri()) {
+        RequestCost::ExpensiveQuery
+    } else {
+        RequestCost::Query
+    };
+    enforce_quota(&state, req, next, cost).await
+}
+
+/// Middleware enforcing `[server.query_quota]` on scans, test pings,
+/// traceroutes and diagnoses
+pub(crate) async fn scan_quota_middleware(
+    State(state): State<AppState>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    enforce_quota(&state, req, next, RequestCost::Scan).await
+}
+
+async fn enforce_quota(
+    state: &AppState,
+    req: Request<Body>,
+    next: Next,
+    cost: RequestCost,
+) -> Response {
+    // Read limits per request so config hot reloads apply immediately
+    let limits = state
+        .config
+        .read()
+        .map(|c| c.server.query_quota)
+        .unwrap_or_default();
+    if !is_limited(&limits, cost) {
+        return next.run(req).await;
+    }
+
+    let requester = requester_key(&req);
+    let now = chrono::Utc::now().timestamp();
+
+    let guard = match state.quotas.try_acquire(&requester, limits, cost, now) {
+        Ok(guard) => guard,
+        Err(exceeded) => {
+            warn!(
+                "Quota exceeded for {} on {}: {:?}",
+                requester,
+                req.uri().path(),
+                exceeded
+            );
+            return quota_exceeded_response(exceeded);
+        }
+    };
+
+    // Streamed responses and scans keep running after the response head
+    hold_until_body_ends(next.run(req).await, guard)
+}
+
+fn quota_exceeded_response(exceeded: QuotaExceeded) -> Response {
+    let (message, details, retry_after) = match exceeded {
+        QuotaExceeded::Daily { limit } => (
+            format!("Daily query budget of {} exceeded", limit),
+            serde_json::json!({ "quota": "daily", "limit": limit }),
+            None,
+        ),
+        QuotaExceeded::Rate { limit, retry_after } => (
+            format!(
+                "Rate limit of {} expensive requests per minute exceeded",
+                limit
+            ),
+            serde_json::json!({ "quota": "rate", "limit": limit, "retry_after_seconds": retry_after }),
+            Some(retry_after),
+        ),
+        QuotaExceeded::Concurrent { limit } => (
+            format!("Too many concurrent queries (limit {})", limit),
+            serde_json::json!({ "quota": "concurrent", "limit": limit }),
+            None,
+        ),
+        QuotaExceeded::ConcurrentExpensive { limit } => (
+            format!("Too many expensive requests running (limit {})", limit),
+            serde_json::json!({ "quota": "concurrent_expensive", "limit": limit }),
+            None,
+        ),
+    };
+    let mut response = ApiError::new(
+        StatusCode::TOO_MANY_REQUESTS,
+        ErrorCode::QuotaExceeded,
+        message,
+    )
+    .with_details(details)
+    .into_response();
+    if let Some(retry_after) = retry_after {
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ExpensiveQuotaConfig, Role};
+
+    fn limits(daily_budget: u32, max_concurrent: u32) -> QueryQuotaConfig {
+        QueryQuotaConfig {
+            daily_budget,
+            max_concurrent,
+            expensive: ExpensiveQuotaConfig {
+                requests_per_minute: 0,
+                max_concurrent: 0,
+            },
+        }
+    }
+
+    fn expensive_limits(requests_per_minute: u32, max_concurrent: u32) -> QueryQuotaConfig {
+        QueryQuotaConfig {
+            expensive: ExpensiveQuotaConfig {
+                requests_per_minute,
+                max_concurrent,
+            },
+            ..limits(0, 0)
+        }
+    }
+
+    #[test]
+    fn test_daily_budget() {
+        let quotas = Arc::new(QueryQuotas::new());
+        let limits = limits(2, 0);
+        let query = RequestCost::Query;
+
+        assert!(quotas.try_acquire("a", limits, query, 0).is_ok());
+        assert!(quotas
+            .try_acquire("a", limits, RequestCost::Scan, 10)
+            .is_ok());
+        assert_eq!(
+            quotas.try_acquire("a", limits, query, 20).unwrap_err(),
+            QuotaExceeded::Daily { limit: 2 }
+        );
+
+        // Other requesters have their own budget
+        assert!(quotas.try_acquire("b", limits, query, 20).is_ok());
+
+        // Budget resets on the next UTC day
+        assert!(quotas.try_acquire("a", limits, query, 86400).is_ok());
+    }
+
+    #[test]
+    fn test_rate_refills() {
+        let quotas = Arc::new(QueryQuotas::new());
+        let limits = expensive_limits(2, 0);
+        let scan = RequestCost::Scan;
+
+        assert!(quotas.try_acquire("a", limits, scan, 0).is_ok());
+        assert!(quotas
+            .try_acquire("a", limits, RequestCost::ExpensiveQuery, 0)
+            .is_ok());
+        assert_eq!(
+            quotas.try_acquire("a", limits, scan, 0).unwrap_err(),
+            QuotaExceeded::Rate {
+                limit: 2,
+                retry_after: 30
+            }
+        );
+        // Queries with a limit are not rate limited
+        assert!(quotas
+            .try_acquire("a", limits, RequestCost::Query, 0)
+            .is_ok());
+        // Other requesters have their own rate
+        assert!(quotas.try_acquire("b", limits, scan, 0).is_ok());
+        // One request per 30 seconds comes back
+        assert!(quotas.try_acquire("a", limits, scan, 30).is_ok());
+        assert!(quotas.try_acquire("a", limits, scan, 30).is_err());
+    }
+
+    #[test]
+    fn test_rejected_requests_are_not_counted() {
+        let quotas = Arc::new(QueryQuotas::new());
+        let limits = QueryQuotaConfig {
+            daily_budget: 2,
+            ..expensive_limits(60, 1)
+        };
+        let scan = RequestCost::Scan;
+
+        let guard = quotas.try_acquire("a", limits, scan, 0).unwrap();
+        for _ in 0..5 {
+            assert_eq!(
+                quotas.try_acquire("a", limits, scan, 0).unwrap_err(),
+                QuotaExceeded::ConcurrentExpensive { limit: 1 }
+            );
+        }
+        drop(guard);
+        assert!(quotas.try_acquire("a", limits, scan, 0).is_ok());
+    }
+
+    #[test]
+    fn test_concurrent_cap() {
+        let quotas = Arc::new(QueryQuotas::new());
+        let limits = limits(0, 1);
+        let query = RequestCost::Query;
+
+        let guard = quotas.try_acquire("a", limits, query, 0).unwrap();
+        assert_eq!(
+            quotas.try_acquire("a", limits, query, 0).unwrap_err(),
+            QuotaExceeded::Concurrent { limit: 1 }
+        );
+        // Scans don't take a query slot
+        assert!(quotas
+            .try_acquire("a", limits, RequestCost::Scan, 0)
+            .is_ok());
+
+        // Slot is released when the query finishes
+        drop(guard);
+        assert!(quotas.try_acquire("a", limits, query, 0).is_ok());
+    }
+
+    #[test]
+    fn test_expensive_concurrent_cap() {
+        let quotas = Arc::new(QueryQuotas::new());
+        let limits = expensive_limits(0, 1);
+
+        let scan = quotas
+            .try_acquire("a", limits, RequestCost::Scan, 0)
+            .unwrap();
+        assert_eq!(
+            quotas
+                .try_acquire("a", limits, RequestCost::ExpensiveQuery, 0)
+                .unwrap_err(),
+            QuotaExceeded::ConcurrentExpensive { limit: 1 }
+        );
+        assert!(quotas
+            .try_acquire("a", limits, RequestCost::Query, 0)
+            .is_ok());
+        drop(scan);
+        assert!(quotas
+            .try_acquire("a", limits, RequestCost::ExpensiveQuery, 0)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_is_unbounded_data_query() {
+        let uri = |s: &str| s.parse::<Uri>().unwrap();
+        assert!(is_unbounded_data_query(&uri("/api/ping/data")));
+        assert!(is_unbounded_data_query(&uri(
+            "/api/ping/data?target=a&from=30d"
+        )));
+        assert!(!is_unbounded_data_query(&uri(
+            "/api/ping/data?target=a&limit=100"
+        )));
+        assert!(!is_unbounded_data_query(&uri("/api/ping/aggregated")));
+    }
+
+    #[test]
+    fn test_requester_key_behind_ingress() {
+        let mut req = Request::builder()
+            .header("x-forwarded-for", "192.168.1.50, 172.30.32.2")
+            .body(Body::empty())
+            .unwrap();
+        req.extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([172, 30, 32, 2], 1234))));
+        assert_eq!(requester_key(&req), "192.168.1.50");
+
+        // Forwarded headers from non-ingress peers are not trusted
+        let mut req = Request::builder()
+            .header("x-forwarded-for", "10.0.0.1")
+            .body(Body::empty())
+            .unwrap();
+        req.extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([192, 168, 1, 7], 1234))));
+        assert_eq!(requester_key(&req), "192.168.1.7");
+    }
+
+    #[test]
+    fn test_requester_key_authenticated() {
+        let mut req = Request::builder().body(Body::empty()).unwrap();
+        req.extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([192, 168, 1, 7], 1234))));
+        req.extensions_mut().insert(Authenticated {
+            principal: Principal::Token("grafana".to_string()),
+            role: Role::Viewer,
+        });
+        assert_eq!(requester_key(&req), "token:grafana");
+
+        req.extensions_mut().insert(Authenticated {
+            principal: Principal::User("admin".to_string()),
+            role: Role::Admin,
+        });
+        assert_eq!(requester_key(&req), "user:admin");
+    }
+}
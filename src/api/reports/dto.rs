@@ -0,0 +1,154 @@
+use crate::api::ping::dto::{deserialize_time_range, Percentiles, TimeRangeValue};
+use serde::{Deserialize, Serialize};
+
+/// Query parameters for the ISP evidence report
+#[derive(Debug, Deserialize)]
+pub struct IspEvidenceQuery {
+    /// Target ID (or address) to report on
+    pub target_id: String,
+    /// Start timestamp (Unix timestamp in seconds) or relative time range (e.g., "24h", "7d").
+    /// Default: "7d"
+    #[serde(default, deserialize_with = "deserialize_time_range")]
+    pub from: Option<TimeRangeValue>,
+    /// End timestamp (Unix timestamp in seconds, optional)
+    #[serde(default)]
+    pub to: Option<i64>,
+    /// Minimum packet loss (percent) for a minute to count as an outage. Default: 50
+    #[serde(default)]
+    pub min_loss: Option<f64>,
+    /// Output format: "markdown" (default) or "json"
+    #[serde(default)]
+    pub format: Option<String>,
+    /// IANA time zone (e.g., "Europe/Berlin") the report's times are given
+    /// in. Default: UTC
+    #[serde(default)]
+    pub tz: Option<String>,
+}
+
+/// A period of consecutive minutes with high packet loss
+#[derive(Debug, Clone, Serialize)]
+pub struct Outage {
+    /// ISO 8601 formatted start, in the report's time zone (default UTC)
+    pub start: String,
+    /// Unix timestamp in seconds of the start
+    pub start_unix: i64,
+    /// ISO 8601 formatted end, in the report's time zone (default UTC)
+    pub end: String,
+    /// Unix timestamp in seconds of the end
+    pub end_unix: i64,
+    /// Duration in seconds
+    pub duration_seconds: i64,
+    /// Packet loss during the outage as a percentage (0-100)
+    pub loss_percent: f64,
+    /// Number of pings sent during the outage
+    pub pings_sent: usize,
+    /// Number of pings lost during the outage
+    pub pings_lost: usize,
+}
+
+/// Overall latency statistics for successful pings
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencySummary {
+    pub min_ms: Option<f64>,
+    pub avg_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+    /// Percentiles over all successful pings
+    pub percentiles: Option<Percentiles>,
+}
+
+/// Structured ISP evidence report
+#[derive(Debug, Clone, Serialize)]
+pub struct IspEvidenceReport {
+    pub target_id: String,
+    pub target_address: String,
+    pub target_name: Option<String>,
+    /// ISO 8601 formatted report generation time (UTC)
+    pub generated_at: String,
+    /// Start of the reported period (Unix seconds)
+    pub from_timestamp: i64,
+    /// End of the reported period (Unix seconds)
+    pub to_timestamp: i64,
+    /// Pings sent per measurement cycle
+    pub ping_count: u16,
+    /// Seconds between measurement cycles
+    pub ping_interval: u64,
+    /// Loss threshold (percent) used to classify a minute as an outage
+    pub outage_loss_threshold_percent: f64,
+    pub pings_sent: usize,
+    pub pings_lost: usize,
+    /// Packet loss over the whole period as a percentage (0-100)
+    pub loss_percent: Option<f64>,
+    /// Minutes with at least one measurement
+    pub measured_minutes: usize,
+    /// Measured minutes that were not part of an outage, as a percentage (0-100)
+    pub availability_percent: Option<f64>,
+    pub total_outage_seconds: i64,
+    pub latency: LatencySummary,
+    /// Outages, oldest first
+    pub outages: Vec<Outage>,
+    /// Time zone of the outage times and the Markdown report
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tz: Option<String>,
+}
+
+/// Query parameters for the uptime report
+#[derive(Debug, Deserialize)]
+pub struct UptimeQuery {
+    /// Target ID (or address) to report on. Default: all targets
+    #[serde(default)]
+    pub target_id: Option<String>,
+    /// Comma-separated windows ending now (e.g., "1d,7d,30d"). Default: "1d,7d,30d"
+    #[serde(default)]
+    pub windows: Option<String>,
+    /// Minimum packet loss (percent) for a minute to count as an outage. Default: 50
+    #[serde(default)]
+    pub min_loss: Option<f64>,
+    /// IANA time zone (e.g., "Europe/Berlin"). Windows of whole days then
+    /// cover local calendar days: "7d" is today and the 6 days before it,
+    /// from local midnight. Default: windows end now and start that long
+    /// before it
+    #[serde(default)]
+    pub tz: Option<String>,
+}
+
+/// Availability of a target over one window
+#[derive(Debug, Clone, Serialize)]
+pub struct UptimeWindow {
+    /// Window as requested (e.g., "30d")
+    pub window: String,
+    /// Start of the window (Unix seconds)
+    pub from_timestamp: i64,
+    /// Minutes with at least one measurement
+    pub measured_minutes: usize,
+    /// Measured minutes that were not part of an outage, as a percentage (0-100)
+    pub availability_percent: Option<f64>,
+    pub outage_count: usize,
+    pub total_outage_seconds: i64,
+    /// Longest outage within the window
+    pub longest_outage: Option<Outage>,
+}
+
+/// Uptime of a single target
+#[derive(Debug, Clone, Serialize)]
+pub struct TargetUptime {
+    pub target_id: String,
+    pub target_address: String,
+    pub target_name: Option<String>,
+    /// Windows in the requested order
+    pub windows: Vec<UptimeWindow>,
+}
+
+/// Uptime/SLA report
+#[derive(Debug, Clone, Serialize)]
+pub struct UptimeReport {
+    /// ISO 8601 formatted report generation time (UTC)
+    pub generated_at: String,
+    /// End of every window (Unix seconds)
+    pub to_timestamp: i64,
+    /// Loss threshold (percent) used to classify a minute as an outage
+    pub outage_loss_threshold_percent: f64,
+    /// Time zone whose calendar days the windows of whole days cover
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tz: Option<String>,
+    pub targets: Vec<TargetUptime>,
+}
@@ -0,0 +1,188 @@
+use super::dto::{IspEvidenceQuery, IspEvidenceReport, UptimeQuery, UptimeReport};
+use super::isp_evidence::{build_report, render_markdown};
+use super::uptime::{build_target_uptime, parse_windows, DEFAULT_WINDOWS};
+use crate::api::error::{ApiError, ErrorCode};
+use crate::api::extract::Query;
+use crate::api::ping::calendar::parse_tz;
+use crate::api::ping::dto::TimeRangeValue;
+use crate::api::ping::handlers::find_target_config;
+use crate::api::ping::query::resolve_time_range_value;
+use crate::api::AppState;
+use axum::{
+    extract::State,
+    http::header,
+    response::{IntoResponse, Json, Response},
+};
+use chrono_tz::Tz;
+use std::sync::Arc;
+use tracing::{error, info};
+
+/// Longest period a single report may cover
+const MAX_REPORT_SECONDS: i64 = 31 * 86400;
+
+/// Parse the optional `tz` parameter of a report
+fn report_tz(tz: Option<&str>) -> Result<Option<Tz>, ApiError> {
+    tz.map(parse_tz).transpose().map_err(|e| {
+        ApiError::bad_request(ErrorCode::InvalidRequest, e)
+            .with_details(serde_json::json!({ "field": "tz" }))
+    })
+}
+
+/// HTTP handler for GET /api/reports/isp-evidence
+///
+/// Builds an outage report (outage list, loss, latency percentiles and a
+/// methodology note) for attaching to an ISP support ticket. Returns Markdown
+/// by default, or the structured report with `format=json`. Times are given
+/// in `tz` (default UTC).
+pub(crate) async fn get_isp_evidence(
+    State(state): State<AppState>,
+    Query(query): Query<IspEvidenceQuery>,
+) -> Result<Response, ApiError> {
+    info!("Generating ISP evidence report: {:?}", query);
+
+    let markdown = match query.format.as_deref() {
+        None | Some("markdown") | Some("md") => true,
+        Some("json") => false,
+        Some(other) => {
+            return Err(ApiError::bad_request(
+                ErrorCode::InvalidRequest,
+                format!("Unsupported report format '{}'", other),
+            )
+            .with_details(serde_json::json!({ "field": "format" })))
+        }
+    };
+
+    let tz = report_tz(query.tz.as_deref())?;
+
+    let target = find_target_config(&state, &query.target_id).ok_or_else(|| {
+        ApiError::not_found(
+            ErrorCode::TargetNotFound,
+            format!("Target '{}' not found", query.target_id),
+        )
+    })?;
+
+    let from_value = query
+        .from
+        .clone()
+        .unwrap_or_else(|| TimeRangeValue::Relative("7d".to_string()));
+    let from = resolve_time_range_value(&from_value).map_err(|e| {
+        error!("Invalid time range: {}", e);
+        ApiError::bad_request(ErrorCode::InvalidTimeRange, e)
+    })?;
+    let to = query.to.unwrap_or_else(|| chrono::Utc::now().timestamp());
+    if to <= from || to - from > MAX_REPORT_SECONDS {
+        return Err(ApiError::bad_request(
+            ErrorCode::InvalidTimeRange,
+            "Report period must be positive and at most 31 days",
+        ));
+    }
+
+    let min_loss = query.min_loss.unwrap_or(50.0);
+    if !(min_loss > 0.0 && min_loss <= 100.0) {
+        return Err(ApiError::bad_request(
+            ErrorCode::InvalidRequest,
+            "min_loss must be between 0 and 100",
+        )
+        .with_details(serde_json::json!({ "field": "min_loss" })));
+    }
+
+    let storage = Arc::clone(&state.storage);
+    let report: IspEvidenceReport = tokio::task::spawn_blocking(move || {
+        build_report(&*storage, &target, from, to, min_loss, tz).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| {
+        error!("Task join error: {}", e);
+        ApiError::internal(ErrorCode::Internal, e.to_string())
+    })?
+    .map_err(|e| {
+        error!("Error building ISP evidence report: {}", e);
+        ApiError::internal(ErrorCode::StorageError, e)
+    })?;
+
+    if markdown {
+        Ok((
+            [(header::CONTENT_TYPE, "text/markdown; charset=utf-8")],
+            render_markdown(&report),
+        )
+            .into_response())
+    } else {
+        Ok(Json(report).into_response())
+    }
+}
+
+/// HTTP handler for GET /api/reports/uptime
+///
+/// Computes per-target availability over one or more windows ending now
+/// (e.g., `1d,7d,30d`), with the number of outages and the longest outage in
+/// each. Availability is the share of measured minutes that were not part of
+/// an outage. With `tz`, windows of whole days cover local calendar days.
+pub(crate) async fn get_uptime(
+    State(state): State<AppState>,
+    Query(query): Query<UptimeQuery>,
+) -> Result<Json<UptimeReport>, ApiError> {
+    info!("Generating uptime report: {:?}", query);
+
+    let windows =
+        parse_windows(query.windows.as_deref().unwrap_or(DEFAULT_WINDOWS)).map_err(|e| {
+            ApiError::bad_request(ErrorCode::InvalidTimeRange, e)
+                .with_details(serde_json::json!({ "field": "windows" }))
+        })?;
+
+    let min_loss = query.min_loss.unwrap_or(50.0);
+    if !(min_loss > 0.0 && min_loss <= 100.0) {
+        return Err(ApiError::bad_request(
+            ErrorCode::InvalidRequest,
+            "min_loss must be between 0 and 100",
+        )
+        .with_details(serde_json::json!({ "field": "min_loss" })));
+    }
+
+    let tz = report_tz(query.tz.as_deref())?;
+
+    let targets = match &query.target_id {
+        Some(target_id) => vec![find_target_config(&state, target_id).ok_or_else(|| {
+            ApiError::not_found(
+                ErrorCode::TargetNotFound,
+                format!("Target '{}' not found", target_id),
+            )
+        })?],
+        None => {
+            let config = state.config.read().map_err(|e| {
+                error!("Failed to read config: {}", e);
+                ApiError::internal(ErrorCode::ConfigUnavailable, "Failed to read configuration")
+            })?;
+            config.targets.clone()
+        }
+    };
+
+    let now = chrono::Utc::now().timestamp();
+    let storage = Arc::clone(&state.storage);
+    let coverage = state.downsampler.coverage();
+    let targets = tokio::task::spawn_blocking(move || {
+        targets
+            .iter()
+            .map(|target| {
+                build_target_uptime(&*storage, &coverage, target, &windows, now, min_loss, tz)
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| {
+        error!("Task join error: {}", e);
+        ApiError::internal(ErrorCode::Internal, e.to_string())
+    })?
+    .map_err(|e| {
+        error!("Error building uptime report: {}", e);
+        ApiError::internal(ErrorCode::StorageError, e)
+    })?;
+
+    Ok(Json(UptimeReport {
+        generated_at: chrono::Utc::now().to_rfc3339(),
+        to_timestamp: now,
+        outage_loss_threshold_percent: min_loss,
+        tz: tz.map(|tz| tz.name().to_string()),
+        targets,
+    }))
+}
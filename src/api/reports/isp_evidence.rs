@@ -0,0 +1,442 @@
+use super::dto::{IspEvidenceReport, LatencySummary, Outage};
+use crate::api::ping::calendar::parse_tz;
+use crate::api::ping::dto::BucketDataPoint;
+use crate::api::ping::query::{
+    calculate_percentiles, query_ping_aggregated_chunked, select_target_data,
+};
+use crate::config::Target;
+use crate::storage::{series_site, StorageBackend};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use std::fmt::Write;
+
+/// Outages are detected on one-minute buckets
+pub(super) const OUTAGE_BUCKET_SECONDS: i64 = 60;
+
+fn rfc3339(timestamp: i64, tz: Tz) -> String {
+    DateTime::from_timestamp(timestamp, 0)
+        .unwrap_or_else(Utc::now)
+        .with_timezone(&tz)
+        .to_rfc3339()
+}
+
+/// Time with zone abbreviation, e.g. "2024-01-15 08:00:00 CET"
+fn format_local(timestamp: i64, tz: Tz) -> String {
+    DateTime::from_timestamp(timestamp, 0)
+        .map(|t| {
+            t.with_timezone(&tz)
+                .format("%Y-%m-%d %H:%M:%S %Z")
+                .to_string()
+        })
+        .unwrap_or_default()
+}
+
+/// Human readable duration, e.g. "1h 05m", "3m 20s", "45s"
+fn format_duration(seconds: i64) -> String {
+    let (hours, minutes, secs) = (seconds / 3600, (seconds % 3600) / 60, seconds % 60);
+    if hours > 0 {
+        format!("{}h {:02}m", hours, minutes)
+    } else if minutes > 0 && secs > 0 {
+        format!("{}m {:02}s", minutes, secs)
+    } else if minutes > 0 {
+        format!("{}m", minutes)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+fn bucket_loss_percent(bucket: &BucketDataPoint) -> f64 {
+    if bucket.count == 0 {
+        return 0.0;
+    }
+    bucket.failed_count as f64 / bucket.count as f64 * 100.0
+}
+
+/// Merge consecutive one-minute buckets with at least `min_loss_percent`
+/// packet loss into outages.
+///
+/// Minutes without any measurement end an outage: they are monitoring gaps,
+/// not evidence of a connection problem. Outage times are given in `tz`.
+pub(super) fn detect_outages(
+    buckets: &[BucketDataPoint],
+    min_loss_percent: f64,
+    tz: Tz,
+) -> Vec<Outage> {
+    let mut outages: Vec<Outage> = Vec::new();
+    let mut current: Option<Outage> = None;
+
+    for bucket in buckets {
+        let is_outage = bucket.count > 0 && bucket_loss_percent(bucket) >= min_loss_percent;
+
+        match current.as_mut() {
+            Some(outage) if is_outage && outage.end_unix == bucket.timestamp_unix => {
+                outage.end_unix = bucket.timestamp_end_unix;
+                outage.pings_sent += bucket.count;
+                outage.pings_lost += bucket.failed_count;
+            }
+            _ => {
+                outages.extend(current.take());
+                if is_outage {
+                    current = Some(Outage {
+                        start: String::new(),
+                        start_unix: bucket.timestamp_unix,
+                        end: String::new(),
+                        end_unix: bucket.timestamp_end_unix,
+                        duration_seconds: 0,
+                        loss_percent: 0.0,
+                        pings_sent: bucket.count,
+                        pings_lost: bucket.failed_count,
+                    });
+                }
+            }
+        }
+    }
+    outages.extend(current);
+
+    for outage in &mut outages {
+        outage.start = rfc3339(outage.start_unix, tz);
+        outage.end = rfc3339(outage.end_unix, tz);
+        outage.duration_seconds = outage.end_unix - outage.start_unix;
+        outage.loss_percent = outage.pings_lost as f64 / outage.pings_sent.max(1) as f64 * 100.0;
+    }
+
+    outages
+}
+
+/// Collect all successful latency samples for a target, sorted ascending
+fn collect_latencies(
+    storage: &dyn StorageBackend,
+    target: &Target,
+    from: i64,
+    to: i64,
+) -> Result<Vec<f64>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut latencies: Vec<f64> = select_target_data(storage, "ping_latency", target, from, to)?
+        .iter()
+        .map(|p| p.value)
+        .collect();
+
+    // Fallback: label set changed (e.g. renamed target), scan by target_id
+    if latencies.is_empty() {
+        for (labels, points) in storage.select_all("ping_latency", from, to)? {
+            if series_site(&labels).is_none()
+                && labels
+                    .iter()
+                    .any(|l| l.name == "target_id" && l.value == target.id)
+            {
+                latencies.extend(points.iter().map(|p| p.value));
+            }
+        }
+    }
+
+    latencies.sort_by(|a, b| a.total_cmp(b));
+    Ok(latencies)
+}
+
+/// Build the ISP evidence report for a target over `[from, to]`, with
+/// times in `tz` (UTC without one)
+pub(super) fn build_report(
+    storage: &dyn StorageBackend,
+    target: &Target,
+    from: i64,
+    to: i64,
+    min_loss_percent: f64,
+    tz: Option<Tz>,
+) -> Result<IspEvidenceReport, Box<dyn std::error::Error + Send + Sync>> {
+    let (buckets, _) = query_ping_aggregated_chunked(
+        storage,
+        Some(&target.address),
+        Some(target),
+        from,
+        to,
+        OUTAGE_BUCKET_SECONDS,
+        false,
+    )?;
+
+    let outages = detect_outages(&buckets, min_loss_percent, tz.unwrap_or(chrono_tz::UTC));
+
+    let pings_sent: usize = buckets.iter().map(|b| b.count).sum();
+    let pings_lost: usize = buckets.iter().map(|b| b.failed_count).sum();
+    let measured_minutes = buckets.iter().filter(|b| b.count > 0).count();
+    let outage_minutes: i64 = outages
+        .iter()
+        .map(|o| o.duration_seconds / OUTAGE_BUCKET_SECONDS)
+        .sum();
+
+    let latencies = collect_latencies(storage, target, from, to)?;
+    let latency = LatencySummary {
+        min_ms: latencies.first().copied(),
+        avg_ms: if latencies.is_empty() {
+            None
+        } else {
+            Some(latencies.iter().sum::<f64>() / latencies.len() as f64)
+        },
+        max_ms: latencies.last().copied(),
+        percentiles: calculate_percentiles(&latencies),
+    };
+
+    Ok(IspEvidenceReport {
+        target_id: target.id.clone(),
+        target_address: target.address.clone(),
+        target_name: target.name.clone(),
+        generated_at: Utc::now().to_rfc3339(),
+        from_timestamp: from,
+        to_timestamp: to,
+        ping_count: target.ping_count,
+        ping_interval: target.ping_interval,
+        outage_loss_threshold_percent: min_loss_percent,
+        pings_sent,
+        pings_lost,
+        loss_percent: if pings_sent > 0 {
+            Some(pings_lost as f64 / pings_sent as f64 * 100.0)
+        } else {
+            None
+        },
+        measured_minutes,
+        availability_percent: if measured_minutes > 0 {
+            Some(
+                (measured_minutes as f64 - outage_minutes as f64) / measured_minutes as f64 * 100.0,
+            )
+        } else {
+            None
+        },
+        total_outage_seconds: outages.iter().map(|o| o.duration_seconds).sum(),
+        latency,
+        outages,
+        tz: tz.map(|tz| tz.name().to_string()),
+    })
+}
+
+fn format_ms(value: Option<f64>) -> String {
+    value
+        .map(|v| format!("{:.1} ms", v))
+        .unwrap_or_else(|| "n/a".to_string())
+}
+
+fn format_percent(value: Option<f64>) -> String {
+    value
+        .map(|v| format!("{:.2}%", v))
+        .unwrap_or_else(|| "n/a".to_string())
+}
+
+/// Render the report as Markdown suitable for an ISP support ticket
+/// (converts cleanly to HTML or PDF)
+pub(super) fn render_markdown(report: &IspEvidenceReport) -> String {
+    let mut md = String::new();
+    let tz = report
+        .tz
+        .as_deref()
+        .and_then(|tz| parse_tz(tz).ok())
+        .unwrap_or(chrono_tz::UTC);
+    let target_label = match report.target_name {
+        Some(ref name) => format!("{} ({})", name, report.target_address),
+        None => report.target_address.clone(),
+    };
+
+    // Writing to a String cannot fail
+    let _ = writeln!(md, "# Connection Quality Report: {}\n", target_label);
+    let _ = writeln!(md, "| | |");
+    let _ = writeln!(md, "|---|---|");
+    let _ = writeln!(md, "| Monitored host | {} |", target_label);
+    let _ = writeln!(
+        md,
+        "| Period | {} to {} |",
+        format_local(report.from_timestamp, tz),
+        format_local(report.to_timestamp, tz)
+    );
+    let _ = writeln!(md, "| Report generated | {} |", report.generated_at);
+    let _ = writeln!(md);
+
+    let _ = writeln!(md, "## Summary\n");
+    let _ = writeln!(md, "- Pings sent: {}", report.pings_sent);
+    let _ = writeln!(
+        md,
+        "- Pings lost: {} ({})",
+        report.pings_lost,
+        format_percent(report.loss_percent)
+    );
+    let _ = writeln!(
+        md,
+        "- Availability: {} of {} measured minutes",
+        format_percent(report.availability_percent),
+        report.measured_minutes
+    );
+    let longest = report.outages.iter().map(|o| o.duration_seconds).max();
+    let _ = writeln!(
+        md,
+        "- Outages: {} (total {}{})",
+        report.outages.len(),
+        format_duration(report.total_outage_seconds),
+        longest
+            .map(|l| format!(", longest {}", format_duration(l)))
+            .unwrap_or_default()
+    );
+    let _ = writeln!(md);
+
+    let _ = writeln!(md, "## Latency (successful pings)\n");
+    let p = report.latency.percentiles.as_ref();
+    let _ = writeln!(md, "| Min | Avg | Median | p95 | p99 | Max |");
+    let _ = writeln!(md, "|---|---|---|---|---|---|");
+    let _ = writeln!(
+        md,
+        "| {} | {} | {} | {} | {} | {} |",
+        format_ms(report.latency.min_ms),
+        format_ms(report.latency.avg_ms),
+        format_ms(p.map(|p| p.p50)),
+        format_ms(p.map(|p| p.p95)),
+        format_ms(p.map(|p| p.p99)),
+        format_ms(report.latency.max_ms)
+    );
+    let _ = writeln!(md);
+
+    let _ = writeln!(md, "## Outages\n");
+    if report.outages.is_empty() {
+        let _ = writeln!(md, "No outages detected in this period.");
+    } else {
+        let _ = writeln!(
+            md,
+            "| # | Start | End | Duration | Packet loss | Pings lost |"
+        );
+        let _ = writeln!(md, "|---|---|---|---|---|---|");
+        for (i, outage) in report.outages.iter().enumerate() {
+            let _ = writeln!(
+                md,
+                "| {} | {} | {} | {} | {:.1}% | {} of {} |",
+                i + 1,
+                format_local(outage.start_unix, tz),
+                format_local(outage.end_unix, tz),
+                format_duration(outage.duration_seconds),
+                outage.loss_percent,
+                outage.pings_lost,
+                outage.pings_sent
+            );
+        }
+    }
+    let _ = writeln!(md);
+
+    let _ = writeln!(md, "## Methodology\n");
+    let _ = writeln!(
+        md,
+        "Measurements were taken with SparkPing, which sends {} ICMP echo requests \
+         to {} every {} second(s) and records the round-trip time of each reply \
+         or a failure if no reply arrives. Results are grouped into one-minute \
+         intervals; an interval counts as an outage when at least {:.0}% of its \
+         pings were lost, and consecutive outage intervals are merged. Intervals \
+         without any measurement (e.g. while the monitoring host was offline) are \
+         excluded from availability and are never counted as outages. Latency \
+         statistics are computed over all successful pings. All times are {}.",
+        report.ping_count,
+        report.target_address,
+        report.ping_interval,
+        report.outage_loss_threshold_percent,
+        tz.name()
+    );
+
+    md
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bucket(start: i64, count: usize, failed_count: usize) -> BucketDataPoint {
+        BucketDataPoint {
+            timestamp: String::new(),
+            timestamp_unix: start,
+            timestamp_end_unix: start + 60,
+            target: "1.1.1.1".to_string(),
+            target_name: None,
+            min: None,
+            max: None,
+            avg: None,
+            percentiles: None,
+            count,
+            successful_count: count - failed_count,
+            failed_count,
+            resolution: None,
+            complete: true,
+            loss_percent: None,
+        }
+    }
+
+    #[test]
+    fn test_detect_outages_merges_consecutive_minutes() {
+        let buckets = vec![
+            bucket(0, 60, 0),
+            bucket(60, 60, 60),
+            bucket(120, 60, 45),
+            bucket(180, 60, 0),
+            bucket(240, 60, 60),
+        ];
+        let outages = detect_outages(&buckets, 50.0, chrono_tz::UTC);
+
+        assert_eq!(outages.len(), 2);
+        assert_eq!(outages[0].start_unix, 60);
+        assert_eq!(outages[0].end_unix, 180);
+        assert_eq!(outages[0].duration_seconds, 120);
+        assert_eq!(outages[0].pings_lost, 105);
+        assert_eq!(outages[0].loss_percent, 87.5);
+        assert_eq!(outages[1].start_unix, 240);
+    }
+
+    #[test]
+    fn test_detect_outages_split_by_monitoring_gap() {
+        // Minute 120-180 has no data: not an outage, and it splits the run
+        let buckets = vec![bucket(60, 10, 10), bucket(180, 10, 10)];
+        let outages = detect_outages(&buckets, 50.0, chrono_tz::UTC);
+        assert_eq!(outages.len(), 2);
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(45), "45s");
+        assert_eq!(format_duration(180), "3m");
+        assert_eq!(format_duration(200), "3m 20s");
+        assert_eq!(format_duration(3900), "1h 05m");
+    }
+
+    #[test]
+    fn test_render_markdown() {
+        let report = IspEvidenceReport {
+            target_id: "t1".to_string(),
+            target_address: "1.1.1.1".to_string(),
+            target_name: Some("Cloudflare".to_string()),
+            generated_at: String::new(),
+            from_timestamp: 0,
+            to_timestamp: 300,
+            ping_count: 3,
+            ping_interval: 1,
+            outage_loss_threshold_percent: 50.0,
+            pings_sent: 300,
+            pings_lost: 60,
+            loss_percent: Some(20.0),
+            measured_minutes: 5,
+            availability_percent: Some(80.0),
+            total_outage_seconds: 60,
+            latency: LatencySummary {
+                min_ms: Some(10.0),
+                avg_ms: Some(12.0),
+                max_ms: Some(30.0),
+                percentiles: None,
+            },
+            outages: detect_outages(&[bucket(60, 60, 60)], 50.0, chrono_tz::UTC),
+            tz: None,
+        };
+        let md = render_markdown(&report);
+
+        assert!(md.starts_with("# Connection Quality Report: Cloudflare (1.1.1.1)"));
+        assert!(md.contains(
+            "| 1 | 1970-01-01 00:01:00 UTC | 1970-01-01 00:02:00 UTC | 1m | 100.0% | 60 of 60 |"
+        ));
+        assert!(md.contains("## Methodology"));
+
+        let berlin = IspEvidenceReport {
+            outages: detect_outages(&[bucket(60, 60, 60)], 50.0, chrono_tz::Europe::Berlin),
+            tz: Some("Europe/Berlin".to_string()),
+            ..report
+        };
+        assert_eq!(berlin.outages[0].start, "1970-01-01T01:01:00+01:00");
+        let md = render_markdown(&berlin);
+        assert!(md.contains("| 1 | 1970-01-01 01:01:00 CET | 1970-01-01 01:02:00 CET |"));
+        assert!(md.contains("All times are Europe/Berlin."));
+    }
+}
@@ -0,0 +1,4 @@
+pub mod dto;
+pub mod handlers;
+pub mod isp_evidence;
+mod uptime;
@@ -0,0 +1,221 @@
+use super::dto::{TargetUptime, UptimeWindow};
+use super::isp_evidence::{detect_outages, OUTAGE_BUCKET_SECONDS};
+use crate::api::ping::calendar::local_midnight;
+use crate::api::ping::dto::BucketDataPoint;
+use crate::api::ping::query::{parse_relative_time_range, query_ping_aggregated_with_rollups};
+use crate::config::Target;
+use crate::downsample::Coverage;
+use crate::storage::StorageBackend;
+use chrono_tz::Tz;
+
+/// Windows reported when none are requested
+pub(super) const DEFAULT_WINDOWS: &str = "1d,7d,30d";
+
+/// Longest window an uptime report may cover
+const MAX_WINDOW_SECONDS: i64 = 92 * 86400;
+
+/// Parse a comma-separated window list (e.g. "1d,7d,30d") into
+/// `(label, seconds)` pairs
+pub(super) fn parse_windows(value: &str) -> Result<Vec<(String, i64)>, String> {
+    let mut windows = Vec::new();
+    for label in value.split(',').map(str::trim).filter(|w| !w.is_empty()) {
+        let seconds = parse_relative_time_range(label)?;
+        if seconds < OUTAGE_BUCKET_SECONDS || seconds > MAX_WINDOW_SECONDS {
+            return Err(format!(
+                "Window '{}' must be between 1 minute and {} days",
+                label,
+                MAX_WINDOW_SECONDS / 86400
+            ));
+        }
+        windows.push((label.to_string(), seconds));
+    }
+    if windows.is_empty() {
+        return Err("At least one window is required".to_string());
+    }
+    Ok(windows)
+}
+
+/// Availability, outage count, and longest outage over the one-minute
+/// buckets starting at or after `from`, with outage times in `tz`
+pub(super) fn summarize_window(
+    buckets: &[BucketDataPoint],
+    window: &str,
+    from: i64,
+    min_loss_percent: f64,
+    tz: Tz,
+) -> UptimeWindow {
+    let start = buckets.partition_point(|b| b.timestamp_unix < from);
+    let buckets = &buckets[start..];
+
+    let outages = detect_outages(buckets, min_loss_percent, tz);
+    let measured_minutes = buckets.iter().filter(|b| b.count > 0).count();
+    let outage_minutes: i64 = outages
+        .iter()
+        .map(|o| o.duration_seconds / OUTAGE_BUCKET_SECONDS)
+        .sum();
+
+    UptimeWindow {
+        window: window.to_string(),
+        from_timestamp: from,
+        measured_minutes,
+        availability_percent: (measured_minutes > 0).then(|| {
+            (measured_minutes as f64 - outage_minutes as f64) / measured_minutes as f64 * 100.0
+        }),
+        outage_count: outages.len(),
+        total_outage_seconds: outages.iter().map(|o| o.duration_seconds).sum(),
+        longest_outage: outages
+            .iter()
+            // First of equally long outages
+            .min_by_key(|o| (std::cmp::Reverse(o.duration_seconds), o.start_unix))
+            .cloned(),
+    }
+}
+
+/// Start of a window of `seconds` ending at `now`. With a time zone, windows
+/// of whole days start at local midnight, the current day being the last of
+/// them; other windows start on the minute.
+pub(super) fn window_start(seconds: i64, now: i64, tz: Option<Tz>) -> i64 {
+    match tz {
+        Some(tz) if seconds % 86400 == 0 => local_midnight(tz, now, seconds / 86400 - 1),
+        _ => ((now - seconds) / OUTAGE_BUCKET_SECONDS) * OUTAGE_BUCKET_SECONDS,
+    }
+}
+
+/// Uptime of a target over every window ending at `now`.
+///
+/// The longest window is read once as one-minute buckets, from the 1m
+/// rollups where the downsampling job has covered it.
+pub(super) fn build_target_uptime(
+    storage: &dyn StorageBackend,
+    coverage: &Coverage,
+    target: &Target,
+    windows: &[(String, i64)],
+    now: i64,
+    min_loss_percent: f64,
+    tz: Option<Tz>,
+) -> Result<TargetUptime, Box<dyn std::error::Error + Send + Sync>> {
+    let earliest = windows
+        .iter()
+        .map(|(_, seconds)| window_start(*seconds, now, tz))
+        .min()
+        .unwrap_or(now);
+
+    let (buckets, _, _) = query_ping_aggregated_with_rollups(
+        storage,
+        coverage,
+        Some(&target.address),
+        Some(target),
+        earliest,
+        now,
+        OUTAGE_BUCKET_SECONDS,
+        false,
+    )?;
+
+    Ok(TargetUptime {
+        target_id: target.id.clone(),
+        target_address: target.address.clone(),
+        target_name: target.name.clone(),
+        windows: windows
+            .iter()
+            .map(|(label, seconds)| {
+                summarize_window(
+                    &buckets,
+                    label,
+                    window_start(*seconds, now, tz),
+                    min_loss_percent,
+                    tz.unwrap_or(chrono_tz::UTC),
+                )
+            })
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn minute(start: i64, count: usize, failed_count: usize) -> BucketDataPoint {
+        BucketDataPoint {
+            timestamp: String::new(),
+            timestamp_unix: start,
+            timestamp_end_unix: start + 60,
+            target: "192.168.1.1".to_string(),
+            target_name: None,
+            min: None,
+            max: None,
+            avg: None,
+            percentiles: None,
+            count,
+            successful_count: count - failed_count,
+            failed_count,
+            resolution: None,
+            complete: true,
+            loss_percent: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_windows() {
+        let windows = parse_windows("1d, 7d,30d").unwrap();
+        assert_eq!(
+            windows,
+            vec![
+                ("1d".to_string(), 86400),
+                ("7d".to_string(), 7 * 86400),
+                ("30d".to_string(), 30 * 86400),
+            ]
+        );
+        assert!(parse_windows("").is_err());
+        assert!(parse_windows("365d").is_err());
+        assert!(parse_windows("soon").is_err());
+    }
+
+    #[test]
+    fn test_summarize_window() {
+        // 100 measured minutes with a 2-minute and a 3-minute outage
+        let buckets: Vec<BucketDataPoint> = (0..100)
+            .map(|i| {
+                let failed = if (10..12).contains(&i) || (50..53).contains(&i) {
+                    3
+                } else {
+                    0
+                };
+                minute(i * 60, 3, failed)
+            })
+            .collect();
+
+        let all = summarize_window(&buckets, "100m", 0, 50.0, chrono_tz::UTC);
+        assert_eq!(all.measured_minutes, 100);
+        assert_eq!(all.outage_count, 2);
+        assert_eq!(all.total_outage_seconds, 300);
+        assert_eq!(all.availability_percent, Some(95.0));
+        let longest = all.longest_outage.unwrap();
+        assert_eq!((longest.start_unix, longest.duration_seconds), (3000, 180));
+
+        // The last 40 minutes contain no outage
+        let recent = summarize_window(&buckets, "40m", 60 * 60, 50.0, chrono_tz::UTC);
+        assert_eq!(recent.measured_minutes, 40);
+        assert_eq!(recent.outage_count, 0);
+        assert_eq!(recent.availability_percent, Some(100.0));
+        assert!(recent.longest_outage.is_none());
+
+        let empty = summarize_window(&[], "1d", 0, 50.0, chrono_tz::UTC);
+        assert_eq!(empty.availability_percent, None);
+    }
+
+    #[test]
+    fn test_window_start() {
+        // 2024-01-15T12:00:30Z
+        let now = 1_705_320_030;
+        assert_eq!(window_start(86400, now, None), now - 30 - 86400);
+        // Today from local midnight, and the 6 days before it
+        let berlin = Some(chrono_tz::Europe::Berlin);
+        assert_eq!(window_start(86400, now, berlin), 1_705_273_200);
+        assert_eq!(
+            window_start(7 * 86400, now, berlin),
+            1_705_273_200 - 6 * 86400
+        );
+        // Windows of hours stay relative to now
+        assert_eq!(window_start(3600, now, berlin), now - 30 - 3600);
+    }
+}
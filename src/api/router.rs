@@ -1,34 +1,91 @@
+use crate::alerts::AlertEngine;
 use crate::api::{
-    discovery::{get_subnets, start_unified_discovery},
-    middleware::ingress_ip_filter_middleware,
+    admission::{query_admission_middleware, QueryAdmission},
+    alerts::handlers as alert_handlers,
+    audit::handlers as audit_handlers,
+    auth::handlers as auth_handlers,
+    caching::etag_middleware,
+    config::handlers as config_handlers,
+    cors::cors_layer,
+    dashboard::handlers as dashboard_handlers,
+    diagnostics::handlers as diagnostics_handlers,
+    discovery::{
+        cancel_discovery_job, get_discovery_status, get_port_history, get_presence, get_subnets,
+        get_untracked_devices, list_discovery_jobs, start_discovery_job, start_unified_discovery,
+    },
+    error::localize_errors_middleware,
+    export::handlers as export_handlers,
+    grafana::handlers as grafana_handlers,
+    ha::handlers as ha_handlers,
+    health::handlers as health_handlers,
+    ingest::handlers as ingest_handlers,
+    integrations::handlers as integration_handlers,
+    metrics::handlers as metrics_handlers,
+    middleware::{
+        ingress_ip_filter_middleware, read_only_middleware, request_telemetry_middleware,
+        role_middleware,
+    },
+    notifications::handlers as notification_handlers,
+    openapi::handlers as openapi_handlers,
+    outages::handlers as outage_handlers,
     ping::handlers as ping_handlers,
+    preferences::handlers as preference_handlers,
+    quota::{query_quota_middleware, scan_quota_middleware, QueryQuotas},
+    reports::handlers as report_handlers,
+    status::handlers as status_handlers,
     targets::handlers as target_handlers,
+    traceroute::handlers as traceroute_handlers,
+    versioning::api_version_middleware,
     AppState,
 };
+use crate::audit_log::AuditLog;
+use crate::auth::{AuthSessions, LoginThrottle};
 use crate::config::AppConfig;
+use crate::discovery_session::DiscoverySessions;
+use crate::downsample::Downsampler;
+use crate::live::LiveFeed;
+use crate::preferences::PreferencesStore;
+use crate::presence::PresenceTracker;
+use crate::rollups::RollingAggregator;
+use crate::startup_audit::StartupAudit;
+use crate::storage::{StorageBackend, WriteBuffer};
+use crate::tasks::monitor::TaskMonitor;
+use crate::unified_discovery::DiscoveryStreamStats;
+use crate::update_check::UpdateChecker;
 use axum::http::{header, HeaderValue};
 use axum::{
-    routing::{get, put},
+    routing::{delete, get, post, put},
     Router,
 };
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 use std::sync::RwLock;
 use tower::ServiceBuilder;
+use tower_http::compression::CompressionLayer;
 use tower_http::services::{ServeDir, ServeFile};
 use tower_http::set_header::SetResponseHeaderLayer;
 use tracing::info;
-use tsink::Storage;
 
 /// Create the API router
+#[allow(clippy::too_many_arguments)]
 pub fn create_router(
-    storage: Arc<dyn Storage>,
+    storage: Arc<dyn StorageBackend>,
+    writer: Arc<WriteBuffer>,
+    rollups: Arc<RollingAggregator>,
+    live: Arc<LiveFeed>,
+    alerts: Arc<AlertEngine>,
+    updates: Arc<UpdateChecker>,
     config: Arc<RwLock<AppConfig>>,
     task_handles: Arc<RwLock<HashMap<String, tokio::task::AbortHandle>>>,
+    task_monitor: Arc<TaskMonitor>,
     write_flag: Arc<AtomicBool>,
     config_path: PathBuf,
+    startup_audit: Arc<StartupAudit>,
+    downsampler: Arc<Downsampler>,
+    presence: Arc<PresenceTracker>,
+    preferences: Arc<PreferencesStore>,
     static_dir: Option<PathBuf>,
 ) -> Router {
     // Convert config_path to actual file path (config crate uses path without extension)
@@ -38,13 +95,34 @@ pub fn create_router(
     } else {
         config_path
     };
+    let audit = {
+        let config = config.read().unwrap_or_else(|e| e.into_inner());
+        Arc::new(AuditLog::new(Path::new(config.database.data_path())))
+    };
 
     let state = AppState {
         storage,
+        writer,
+        rollups,
+        live,
         config,
         task_handles,
+        task_monitor,
         write_flag,
         config_path: config_file_path,
+        quotas: Arc::new(QueryQuotas::new()),
+        admission: Arc::new(QueryAdmission::new()),
+        auth_sessions: Arc::new(AuthSessions::new()),
+        login_throttle: Arc::new(LoginThrottle::new()),
+        alerts,
+        updates,
+        startup_audit,
+        discovery_stats: Arc::new(DiscoveryStreamStats::new()),
+        discovery_sessions: Arc::new(DiscoverySessions::new()),
+        downsampler,
+        presence,
+        preferences,
+        audit,
     };
 
     // Check if ingress-only filtering is enabled
@@ -54,24 +132,226 @@ pub fn create_router(
             .map(|c| c.server.home_assistant_ingress_only)
             .unwrap_or(false)
     };
+    let cors_config = state
+        .config
+        .read()
+        .map(|c| c.server.cors.clone())
+        .unwrap_or_default();
 
-    let mut router = Router::new()
+    // Polled by dashboards: unchanged responses are answered with 304 Not
+    // Modified, others compressed
+    let polled_routes = Router::new()
         .route("/api/ping/data", get(ping_handlers::get_ping_data))
         .route(
             "/api/ping/aggregated",
             get(ping_handlers::get_ping_aggregated),
         )
+        .route("/api/ping/summary", get(ping_handlers::get_ping_summary))
+        .route_layer(axum::middleware::from_fn(etag_middleware))
+        .route_layer(CompressionLayer::new());
+
+    // Historical query endpoints are subject to per-requester quotas first,
+    // then wait for one of the server-wide query slots
+    let query_routes = Router::new()
+        .merge(polled_routes)
+        .route("/api/ping/trend", get(ping_handlers::get_ping_trend))
+        .route(
+            "/api/ping/histogram",
+            get(ping_handlers::get_ping_histogram),
+        )
+        .route(
+            "/api/targets/:id/gaps",
+            get(target_handlers::get_target_gaps),
+        )
+        .route(
+            "/api/targets/:id/tunnel",
+            get(target_handlers::get_tunnel_overhead),
+        )
+        .route(
+            "/api/targets/:id/flows",
+            get(target_handlers::get_target_flows),
+        )
+        .route(
+            "/api/dashboard/snapshot.svg",
+            get(dashboard_handlers::get_snapshot_svg),
+        )
+        .route(
+            "/api/dashboard/snapshot.png",
+            get(dashboard_handlers::get_snapshot_png),
+        )
+        .route("/api/discovery/ports", get(get_port_history))
+        .route("/api/discovery/presence", get(get_presence))
+        .route("/api/discovery/status", get(get_discovery_status))
+        .route("/api/discovery/untracked", get(get_untracked_devices))
+        .route(
+            "/api/traceroute/history",
+            get(traceroute_handlers::get_traceroute_history),
+        )
+        .route("/api/export", get(export_handlers::get_export))
+        .route(
+            "/api/reports/isp-evidence",
+            get(report_handlers::get_isp_evidence),
+        )
+        .route("/api/reports/uptime", get(report_handlers::get_uptime))
+        .route("/api/outages", get(outage_handlers::get_outages))
+        .route("/api/storage/verify", get(ping_handlers::verify_storage))
+        .route("/api/grafana/query", post(grafana_handlers::query))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            query_admission_middleware,
+        ))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            query_quota_middleware,
+        ));
+
+    // Scans and on-demand probes are expensive requests under the same
+    // per-requester quotas, but run for minutes and don't take a
+    // server-wide query slot
+    let scan_routes = Router::new()
+        .route("/api/discovery/unified", get(start_unified_discovery))
+        .route("/api/discovery/jobs", post(start_discovery_job))
+        .route("/api/ping/test", post(ping_handlers::test_ping))
+        .route("/api/traceroute", post(traceroute_handlers::run_traceroute))
+        .route(
+            "/api/targets/:id/diagnose",
+            post(target_handlers::diagnose_target),
+        )
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            scan_quota_middleware,
+        ));
+
+    // External probes authenticate with the `[ingest]` bearer token
+    let ingest_routes = Router::new()
+        .route("/api/ingest/batch", post(ingest_handlers::ingest_batch))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            ingest_handlers::ingest_auth_middleware,
+        ));
+
+    let mut router = Router::new()
+        .merge(query_routes)
+        .merge(scan_routes)
+        .merge(ingest_routes)
         .route(
             "/api/targets",
             get(target_handlers::get_targets).post(target_handlers::create_target),
         )
         .route(
             "/api/targets/:id",
-            put(target_handlers::update_target).delete(target_handlers::delete_target),
+            get(target_handlers::get_target)
+                .put(target_handlers::update_target)
+                .patch(target_handlers::patch_target)
+                .delete(target_handlers::delete_target),
+        )
+        .route(
+            "/api/targets/:id/pause",
+            post(target_handlers::pause_target),
+        )
+        .route(
+            "/api/targets/:id/resume",
+            post(target_handlers::resume_target),
+        )
+        .route(
+            "/api/targets/:id/task",
+            get(target_handlers::get_target_task),
+        )
+        .route(
+            "/api/targets/:id/restart",
+            post(target_handlers::restart_target_task),
+        )
+        .route(
+            "/api/targets/:id/migrate-history",
+            post(target_handlers::migrate_target_history),
         )
+        .route("/api/ping/live", get(ping_handlers::get_ping_live))
         .route("/api/storage/stats", get(ping_handlers::get_storage_stats))
+        .route("/api/storage/prune", post(ping_handlers::prune_storage))
+        .route(
+            "/api/storage/quarantine",
+            post(ping_handlers::quarantine_storage),
+        )
+        .route("/api/storage/compact", post(ping_handlers::compact_storage))
+        .route("/api/status", get(status_handlers::get_status))
+        .route(
+            "/api/preferences",
+            get(preference_handlers::get_preferences).put(preference_handlers::update_preferences),
+        )
+        .route(
+            "/api/diagnostics",
+            get(diagnostics_handlers::get_diagnostics),
+        )
+        .route(
+            "/api/system/diagnostics",
+            get(diagnostics_handlers::get_diagnostics),
+        )
+        .route(
+            "/api/system/info",
+            get(diagnostics_handlers::get_system_info),
+        )
+        .route("/api/alerts", get(alert_handlers::get_alerts))
+        .route("/api/audit", get(audit_handlers::get_audit))
+        .route(
+            "/api/notifications/test",
+            post(notification_handlers::test_notifications),
+        )
         .route("/api/discovery/subnets", get(get_subnets))
-        .route("/api/discovery/unified", get(start_unified_discovery))
+        .route("/api/discovery/jobs", get(list_discovery_jobs))
+        .route("/api/discovery/jobs/:id", delete(cancel_discovery_job))
+        .route(
+            "/api/integrations/ha/devices",
+            get(integration_handlers::get_ha_devices),
+        )
+        .route(
+            "/api/ha/discovery_info",
+            get(ha_handlers::get_discovery_info),
+        )
+        .route("/api/ha/sensors", get(ha_handlers::get_sensors))
+        .route("/api/ha/sensors/:id", get(ha_handlers::get_sensor))
+        .route("/metrics", get(metrics_handlers::get_metrics))
+        .route("/api/config/validate", post(config_handlers::validate))
+        .route(
+            "/api/config/status",
+            get(config_handlers::get_config_status),
+        )
+        .route("/healthz", get(health_handlers::get_healthz))
+        .route("/readyz", get(health_handlers::get_readyz))
+        .route("/api/openapi.json", get(openapi_handlers::get_openapi))
+        .route("/api/docs", get(openapi_handlers::get_swagger_ui))
+        .route("/api/grafana", get(grafana_handlers::check_connection))
+        .route("/api/grafana/", get(grafana_handlers::check_connection))
+        .route("/api/grafana/search", post(grafana_handlers::search))
+        .route("/api/auth/login", post(auth_handlers::login))
+        .route("/api/auth/logout", post(auth_handlers::logout))
+        .route("/api/auth/status", get(auth_handlers::get_auth_status))
+        .route(
+            "/api/auth/tokens",
+            get(auth_handlers::get_tokens).post(auth_handlers::create_token),
+        )
+        .route("/api/auth/tokens/:id", delete(auth_handlers::delete_token))
+        // Time every routed request, including auth, for the self-metrics
+        .route_layer(axum::middleware::from_fn(request_telemetry_middleware))
+        // With `[auth] enabled`, every route above needs a bearer token
+        // whose role allows it (read-only ones only without `public_read`)
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            role_middleware,
+        ))
+        // Record changes with their sender, including refused attempts
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            audit_handlers::audit_middleware,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            auth_handlers::auth_middleware,
+        ))
+        // `[server] read_only` refuses changes before anyone is authenticated
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            read_only_middleware,
+        ))
         .with_state(state);
 
     // Apply IP filtering middleware if home_assistant_ingress_only is enabled
@@ -80,7 +360,11 @@ pub fn create_router(
         router = router.layer(axum::middleware::from_fn(ingress_ip_filter_middleware));
     }
 
-    // Add static file serving if static directory is provided
+    // Translate error messages according to Accept-Language
+    router = router.layer(axum::middleware::from_fn(localize_errors_middleware));
+
+    // Add static file serving if static directory is provided; it takes
+    // precedence over an embedded frontend
     if let Some(static_path) = static_dir {
         let index_path = static_path.join("index.html");
         let assets_path = static_path.join("assets");
@@ -106,7 +390,27 @@ pub fn create_router(
             .service(ServeDir::new(&static_path).not_found_service(ServeFile::new(&index_path)));
 
         router = router.nest_service("/", serve_dir);
+    } else {
+        // Without a static directory, serve the frontend compiled into the binary
+        #[cfg(feature = "embed-frontend")]
+        {
+            router = router.fallback(crate::api::frontend::serve_embedded_frontend);
+        }
+    }
+
+    // Strip `/api/v<N>` before routing; layers on the router itself only
+    // run once a route has matched
+    let mut app = Router::new().fallback_service(
+        ServiceBuilder::new()
+            .layer(axum::middleware::from_fn(api_version_middleware))
+            .service(router),
+    );
+
+    // Outermost, so that preflight requests are answered before auth
+    if let Some(cors) = cors_layer(&cors_config) {
+        info!("CORS enabled for origins {:?}", cors_config.allowed_origins);
+        app = app.layer(cors);
     }
 
-    router
+    app
 }
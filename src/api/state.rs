@@ -1,17 +1,54 @@
+use crate::alerts::AlertEngine;
+use crate::api::admission::QueryAdmission;
+use crate::api::quota::QueryQuotas;
+use crate::audit_log::AuditLog;
+use crate::auth::{AuthSessions, LoginThrottle};
 use crate::config::AppConfig;
+use crate::discovery_session::DiscoverySessions;
+use crate::downsample::Downsampler;
+use crate::live::LiveFeed;
+use crate::preferences::PreferencesStore;
+use crate::presence::PresenceTracker;
+use crate::rollups::RollingAggregator;
+use crate::startup_audit::StartupAudit;
+use crate::storage::{StorageBackend, WriteBuffer};
+use crate::tasks::monitor::TaskMonitor;
+use crate::unified_discovery::DiscoveryStreamStats;
+use crate::update_check::UpdateChecker;
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 use std::sync::RwLock;
-use tsink::Storage;
 
 /// Application state for API routes
 #[derive(Clone)]
 pub struct AppState {
-    pub storage: Arc<dyn Storage>,
+    pub storage: Arc<dyn StorageBackend>,
+    /// Batched writes of ping results
+    pub writer: Arc<WriteBuffer>,
+    pub rollups: Arc<RollingAggregator>,
+    pub live: Arc<LiveFeed>,
     pub config: Arc<RwLock<AppConfig>>,
     pub task_handles: Arc<RwLock<HashMap<String, tokio::task::AbortHandle>>>,
+    /// Progress of the ping tasks (GET /api/targets/:id/task)
+    pub task_monitor: Arc<TaskMonitor>,
     pub write_flag: Arc<AtomicBool>,
     pub config_path: PathBuf,
+    pub quotas: Arc<QueryQuotas>,
+    pub admission: Arc<QueryAdmission>,
+    pub auth_sessions: Arc<AuthSessions>,
+    /// Failed logins per client (POST /api/auth/login)
+    pub login_throttle: Arc<LoginThrottle>,
+    pub alerts: Arc<AlertEngine>,
+    pub updates: Arc<UpdateChecker>,
+    pub startup_audit: Arc<StartupAudit>,
+    pub discovery_stats: Arc<DiscoveryStreamStats>,
+    /// Discovery scans shared by the streams asking for them
+    pub discovery_sessions: Arc<DiscoverySessions>,
+    pub downsampler: Arc<Downsampler>,
+    pub presence: Arc<PresenceTracker>,
+    pub preferences: Arc<PreferencesStore>,
+    /// Changes made through the API (GET /api/audit)
+    pub audit: Arc<AuditLog>,
 }
@@ -0,0 +1,55 @@
+use crate::capabilities::PingCapability;
+use crate::health::ConfigReload;
+use crate::rollups::TargetRollups;
+use crate::telemetry::TelemetrySnapshot;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Live status of a single target, served from the in-memory rollups
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TargetStatus {
+    /// Target ID
+    pub target_id: String,
+    /// Target IP address
+    pub address: String,
+    /// Target name (if available)
+    pub name: Option<String>,
+    /// 1m/5m/1h rollups (None until the first ping result arrives)
+    pub rollups: Option<TargetRollups>,
+}
+
+/// Internal diagnostics of the daemon
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DaemonStatus {
+    /// Seconds since the daemon started
+    pub uptime_seconds: u64,
+    /// Unix timestamp in seconds the daemon started at
+    pub started_at_unix: Option<i64>,
+    /// Running ping tasks (one per unpaused target)
+    pub active_ping_tasks: usize,
+    /// Failed storage writes within the last hour
+    pub storage_write_errors_last_hour: usize,
+    /// Size of the tsink write-ahead log (None if it could not be read)
+    pub wal_size_bytes: Option<u64>,
+    /// Outcome of the last config file reload (None if not reloaded since
+    /// startup)
+    pub config_reload: Option<ConfigReload>,
+    /// ICMP socket types that work and the one pings use (None until
+    /// detected at startup)
+    pub ping_capability: Option<PingCapability>,
+}
+
+/// API response for live target status
+#[derive(Debug, Serialize, ToSchema)]
+pub struct StatusResponse {
+    /// Unix timestamp in seconds the rollups were evaluated at
+    pub timestamp_unix: i64,
+    /// True if `[server] read_only` refuses every change, so clients can
+    /// hide editing controls
+    pub read_only: bool,
+    pub daemon: DaemonStatus,
+    /// Self-metrics since the daemon started
+    pub telemetry: TelemetrySnapshot,
+    /// Status per configured target
+    pub targets: Vec<TargetStatus>,
+}
@@ -0,0 +1,82 @@
+use super::dto::{DaemonStatus, StatusResponse, TargetStatus};
+use crate::api::error::{ApiError, ErrorCode, ErrorResponse};
+use crate::api::AppState;
+use crate::capabilities;
+use crate::health::{health, wal_size};
+use crate::telemetry::telemetry;
+use axum::{extract::State, response::Json};
+use std::path::Path;
+use tracing::{error, warn};
+
+/// HTTP handler for GET /api/status
+///
+/// Returns 1m/5m/1h rollups for every configured target from the shared
+/// rolling aggregator, without querying tsink, along with uptime, running
+/// ping tasks, recent storage write errors, WAL size, the last config reload,
+/// the detected ICMP socket capabilities and the self-metrics. `read_only` tells clients whether changes are
+/// refused.
+#[utoipa::path(
+    get,
+    path = "/api/status",
+    tag = "status",
+    summary = "Live 1m/5m/1h rollups per target and daemon diagnostics",
+    responses(
+        (status = 200, description = "Rollups per target", body = StatusResponse),
+        (status = 500, description = "Configuration unavailable", body = ErrorResponse),
+    )
+)]
+pub(crate) async fn get_status(
+    State(state): State<AppState>,
+) -> Result<Json<StatusResponse>, ApiError> {
+    let now = chrono::Utc::now().timestamp();
+    let (targets, data_path, read_only) = {
+        let config = state.config.read().map_err(|e| {
+            error!("Failed to read config: {}", e);
+            ApiError::internal(ErrorCode::ConfigUnavailable, "Failed to read configuration")
+        })?;
+        let targets = config
+            .targets
+            .iter()
+            .map(|t| TargetStatus {
+                target_id: t.id.clone(),
+                address: t.address.clone(),
+                name: t.name.clone(),
+                rollups: state.rollups.rollups(&t.id, now),
+            })
+            .collect();
+        (
+            targets,
+            config.database.data_path().to_string(),
+            config.server.read_only,
+        )
+    };
+
+    let active_ping_tasks = state
+        .task_handles
+        .read()
+        .map(|handles| handles.values().filter(|h| !h.is_finished()).count())
+        .unwrap_or(0);
+    let wal_size_bytes = tokio::task::spawn_blocking(move || wal_size(Path::new(&data_path)))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|size| size.map_err(|e| e.to_string()))
+        .map_err(|e| warn!("Failed to read WAL size: {}", e))
+        .ok();
+
+    let health = health();
+    Ok(Json(StatusResponse {
+        timestamp_unix: now,
+        read_only,
+        daemon: DaemonStatus {
+            uptime_seconds: health.uptime_seconds(),
+            started_at_unix: health.started_at_unix(),
+            active_ping_tasks,
+            storage_write_errors_last_hour: health.recent_write_errors(now),
+            wal_size_bytes,
+            config_reload: health.last_config_reload(),
+            ping_capability: capabilities::current().cloned(),
+        },
+        telemetry: telemetry().snapshot(),
+        targets,
+    }))
+}
@@ -1,11 +1,284 @@
-use serde::Deserialize;
+use super::filter::TargetState;
+use crate::api::ping::dto::{deserialize_time_range, TargetStorageStats, TimeRangeValue};
+use crate::config::{ProbeType, Target};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::{IntoParams, ToSchema};
 
 /// Request body for creating/updating a target
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct TargetRequest {
     pub id: Option<String>,
     pub address: String,
     pub name: Option<String>,
     pub ping_count: Option<u16>,
     pub ping_interval: Option<u64>,
+    pub probe_type: Option<ProbeType>,
+    /// Port for TCP probes
+    pub port: Option<u16>,
+    /// Days the target's ping data is kept
+    pub retention_days: Option<u32>,
+    /// Labels for grouping and filtering (kept on update when omitted)
+    pub tags: Option<Vec<String>>,
+    /// Key/value labels stored with every result, e.g. {"location":
+    /// "basement"} (kept on update when omitted, an empty object removes them)
+    pub labels: Option<BTreeMap<String, String>>,
+    /// ID of the outside reference target of a VPN tunnel target (kept on
+    /// update when omitted, an empty string removes it)
+    pub tunnel_reference: Option<String>,
+    /// Number of ECMP flows to spread each batch over (kept on update when
+    /// omitted, 0 turns flows off)
+    pub ecmp_flows: Option<u16>,
+    /// Milliseconds a probe waits for its reply (kept on update when
+    /// omitted, 0 uses the `[ping]` default)
+    pub timeout_ms: Option<u64>,
+    /// Time to live of ICMP echo requests (kept on update when omitted, 0
+    /// uses the `[ping]` default)
+    pub ttl: Option<u32>,
+    /// Payload bytes of ICMP echo requests (kept on update when omitted, 0
+    /// uses the `[ping]` default)
+    pub payload_size: Option<usize>,
+    /// Time windows the target is pinged in, e.g. "mon-fri 08:00-18:00"
+    /// (kept on update when omitted, an empty list pings always)
+    pub schedule: Option<Vec<String>>,
+}
+
+/// Request body for PATCH /api/targets/{id}; omitted fields are kept
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct TargetPatchRequest {
+    /// List the target first with `sort=order`
+    pub favorite: Option<bool>,
+    /// Position in the user-chosen order, lower first (0 = unordered)
+    pub sort_order: Option<u32>,
+}
+
+/// Request body for merging an earlier identity into a target's history
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct MigrateHistoryRequest {
+    /// Target ID the data was stored under (default: the target's own ID)
+    pub from_id: Option<String>,
+    /// Address the data was stored with (default: any address of `from_id`)
+    pub from_address: Option<String>,
+}
+
+/// Query parameters for listing targets
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TargetListQuery {
+    /// Case-insensitive substring of the target ID, name, or address
+    #[serde(default)]
+    pub q: Option<String>,
+    /// Only targets carrying this tag (case-insensitive)
+    #[serde(default)]
+    pub tag: Option<String>,
+    /// Only targets in this state: "up", "down", "unknown", or "paused"
+    #[serde(default)]
+    pub state: Option<String>,
+    /// Only favorites (true) or only other targets (false)
+    #[serde(default)]
+    pub favorite: Option<bool>,
+    /// Sort order: "name", "address", "latency", or "order" (favorites first,
+    /// then by `sort_order`) (default: config order)
+    #[serde(default)]
+    pub sort: Option<String>,
+}
+
+/// Live status of a target
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TargetStatus {
+    pub state: TargetState,
+    /// Whether the target's ping task is running (not while paused or
+    /// after it stopped)
+    pub task_running: bool,
+    /// Unix timestamp in seconds of the last ping
+    pub last_sample_unix: Option<i64>,
+    /// Whether the last ping succeeded
+    pub last_success: Option<bool>,
+    /// Latency of the last ping in milliseconds (None if it failed)
+    pub last_latency_ms: Option<f64>,
+    /// Failed pings since the last successful one
+    pub consecutive_failed_count: u64,
+    /// Average latency over the last 24 hours in milliseconds
+    pub avg_latency_24h_ms: Option<f64>,
+    /// Packet loss over the last 24 hours in percent
+    pub loss_percent_24h: Option<f64>,
+}
+
+/// API response for GET /api/targets/{id}
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TargetDetailResponse {
+    pub target: Target,
+    pub status: TargetStatus,
+    /// Stored data of the target; None before anything was stored or when
+    /// the storage could not be read
+    pub storage: Option<TargetStorageStats>,
+}
+
+/// Query parameters for the target gap report
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct GapQuery {
+    /// Start timestamp (Unix timestamp in seconds) or relative time range (e.g., "24h", "7d").
+    /// Default: "24h"
+    #[serde(default, deserialize_with = "deserialize_time_range")]
+    #[param(value_type = Option<String>)]
+    pub from: Option<TimeRangeValue>,
+    /// End timestamp (Unix timestamp in seconds, optional)
+    #[serde(default)]
+    pub to: Option<i64>,
+    /// Minimum gap duration to report (e.g., "5m", "1h"). Default: three
+    /// ping intervals of the target, at least one minute
+    #[serde(default)]
+    pub min_gap: Option<String>,
+}
+
+/// Whether other targets were also missing data during a gap
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum GapScope {
+    /// Only this target had no data (task stalled/crashed or target paused)
+    Target,
+    /// No target had data (SparkPing itself was not running)
+    AllTargets,
+}
+
+/// An interval without any stored result for a target
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DataGap {
+    /// ISO 8601 formatted timestamp of the last result before the gap
+    pub start: String,
+    /// Unix timestamp in seconds of the last result before the gap
+    pub start_unix: i64,
+    /// ISO 8601 formatted timestamp of the first result after the gap (or query end)
+    pub end: String,
+    /// Unix timestamp in seconds of the first result after the gap (or query end)
+    pub end_unix: i64,
+    /// Gap length in seconds
+    pub duration_seconds: i64,
+    /// Whether the gap affected only this target or all targets
+    pub scope: GapScope,
+}
+
+/// API response for the target gap report
+#[derive(Debug, Serialize, ToSchema)]
+pub struct GapReportResponse {
+    /// Target ID
+    pub target_id: String,
+    /// Start of the analyzed range (Unix seconds)
+    pub from_timestamp: i64,
+    /// End of the analyzed range (Unix seconds)
+    pub to_timestamp: i64,
+    /// Minimum gap duration in seconds
+    pub min_gap_seconds: i64,
+    /// Gaps found, oldest first
+    pub gaps: Vec<DataGap>,
+    /// Total time without data in seconds
+    pub total_gap_seconds: i64,
+}
+
+/// Query parameters for the tunnel overhead series
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TunnelQuery {
+    /// Start timestamp (Unix timestamp in seconds) or relative time range (e.g., "24h", "7d").
+    /// Default: "24h"
+    #[serde(default, deserialize_with = "deserialize_time_range")]
+    #[param(value_type = Option<String>)]
+    pub from: Option<TimeRangeValue>,
+    /// End timestamp (Unix timestamp in seconds, optional)
+    #[serde(default)]
+    pub to: Option<i64>,
+    /// Bucket duration (e.g., "1m", "5m", "1h"). Default: "5m"
+    #[serde(default)]
+    pub bucket: Option<String>,
+}
+
+/// Tunnel overhead of one bucket. Overheads are None unless both the tunnel
+/// target and its reference have data in the bucket.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TunnelOverheadPoint {
+    /// ISO 8601 formatted timestamp (start of bucket)
+    pub timestamp: String,
+    /// Unix timestamp in seconds (start of bucket)
+    pub timestamp_unix: i64,
+    /// Average latency through the tunnel in milliseconds
+    pub tunnel_avg_ms: Option<f64>,
+    /// Average latency of the outside reference in milliseconds
+    pub reference_avg_ms: Option<f64>,
+    /// Latency added by the tunnel (tunnel minus reference) in milliseconds
+    pub delta_latency_ms: Option<f64>,
+    /// Packet loss through the tunnel in percent
+    pub tunnel_loss_percent: Option<f64>,
+    /// Packet loss of the outside reference in percent
+    pub reference_loss_percent: Option<f64>,
+    /// Loss added by the tunnel (tunnel minus reference) in percentage points
+    pub differential_loss_percent: Option<f64>,
+}
+
+/// API response for the tunnel overhead series
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TunnelOverheadResponse {
+    /// Tunnel target ID
+    pub target_id: String,
+    /// Outside reference target ID
+    pub reference_id: String,
+    /// Start of the analyzed range (Unix seconds)
+    pub from_timestamp: i64,
+    /// End of the analyzed range (Unix seconds)
+    pub to_timestamp: i64,
+    pub bucket_duration_seconds: i64,
+    /// Overhead per bucket, oldest first
+    pub data: Vec<TunnelOverheadPoint>,
+    /// Mean latency added by the tunnel over buckets with data on both sides
+    pub avg_delta_latency_ms: Option<f64>,
+    /// Loss added by the tunnel over the whole range, in percentage points
+    pub differential_loss_percent: Option<f64>,
+}
+
+/// Query parameters for the per-flow report
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FlowQuery {
+    /// Start timestamp (Unix timestamp in seconds) or relative time range (e.g., "24h", "7d").
+    /// Default: "24h"
+    #[serde(default, deserialize_with = "deserialize_time_range")]
+    #[param(value_type = Option<String>)]
+    pub from: Option<TimeRangeValue>,
+    /// End timestamp (Unix timestamp in seconds, optional)
+    #[serde(default)]
+    pub to: Option<i64>,
+}
+
+/// Results of one ECMP flow
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct FlowStats {
+    /// Flow index (the `flow` label)
+    pub flow: u16,
+    pub successful_count: usize,
+    pub failed_count: usize,
+    /// Packet loss in percent
+    pub loss_percent: Option<f64>,
+    /// Average latency of successful pings in milliseconds
+    pub avg_latency_ms: Option<f64>,
+}
+
+/// API response for the per-flow report of a target with `ecmp_flows`
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FlowReportResponse {
+    pub target_id: String,
+    /// Configured number of flows (0 when flows are off)
+    pub ecmp_flows: u16,
+    /// Start of the analyzed range (Unix seconds)
+    pub from_timestamp: i64,
+    /// End of the analyzed range (Unix seconds)
+    pub to_timestamp: i64,
+    /// Statistics per flow, by flow index
+    pub flows: Vec<FlowStats>,
+    /// Highest minus lowest loss across flows, in percentage points. High
+    /// values mean loss is concentrated on some paths.
+    pub loss_divergence_percent: Option<f64>,
+    /// Highest minus lowest average latency across flows in milliseconds
+    pub latency_divergence_ms: Option<f64>,
+    /// Flow with the most loss, when loss diverges by 5 percentage points or more
+    pub suspect_flow: Option<u16>,
 }
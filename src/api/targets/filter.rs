@@ -0,0 +1,361 @@
+//! Search, filtering, and sorting of the target list.
+//!
+//! States and latencies come from the in-memory rollups, so filtering by
+//! `state=down` or sorting by latency never touches tsink.
+
+use super::dto::TargetListQuery;
+use crate::config::Target;
+use crate::notifications::target_state::classify;
+use crate::rollups::TargetRollups;
+use serde::Serialize;
+use std::cmp::Ordering;
+use utoipa::ToSchema;
+
+/// Current state of a target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TargetState {
+    Up,
+    Down,
+    /// No results yet, or some but not all pings of the current cycle failed
+    Unknown,
+    Paused,
+}
+
+impl TargetState {
+    fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "up" => Some(TargetState::Up),
+            "down" => Some(TargetState::Down),
+            "unknown" => Some(TargetState::Unknown),
+            "paused" => Some(TargetState::Paused),
+            _ => None,
+        }
+    }
+
+    /// State of a target given its rollups, using the same up/down rule as
+    /// the target-down notifications
+    pub(crate) fn of(target: &Target, rollups: Option<&TargetRollups>) -> Self {
+        if target.paused {
+            return TargetState::Paused;
+        }
+        match rollups.and_then(|r| classify(r, target.ping_count)) {
+            Some(true) => TargetState::Up,
+            Some(false) => TargetState::Down,
+            None => TargetState::Unknown,
+        }
+    }
+}
+
+/// Sort order of the target list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TargetSort {
+    Name,
+    Address,
+    /// Average latency of the last five minutes, targets without one last
+    Latency,
+    /// User-chosen order: favorites first, then by `sort_order`
+    Order,
+}
+
+impl TargetSort {
+    fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "name" => Some(TargetSort::Name),
+            "address" => Some(TargetSort::Address),
+            "latency" => Some(TargetSort::Latency),
+            "order" => Some(TargetSort::Order),
+            _ => None,
+        }
+    }
+}
+
+/// Validated list query
+#[derive(Debug, Default)]
+pub(super) struct TargetFilter {
+    q: Option<String>,
+    tag: Option<String>,
+    favorite: Option<bool>,
+    state: Option<TargetState>,
+    sort: Option<TargetSort>,
+}
+
+/// A query parameter with an unsupported value
+#[derive(Debug, PartialEq, Eq)]
+pub(super) struct InvalidParam {
+    pub field: &'static str,
+    pub message: String,
+}
+
+impl TargetFilter {
+    pub(super) fn from_query(query: &TargetListQuery) -> Result<Self, InvalidParam> {
+        let state = match query.state.as_deref() {
+            None | Some("") => None,
+            Some(value) => Some(TargetState::parse(value).ok_or_else(|| InvalidParam {
+                field: "state",
+                message: format!(
+                    "Unsupported state '{}' (expected up, down, unknown, or paused)",
+                    value
+                ),
+            })?),
+        };
+        let sort = match query.sort.as_deref() {
+            None | Some("") => None,
+            Some(value) => Some(TargetSort::parse(value).ok_or_else(|| InvalidParam {
+                field: "sort",
+                message: format!(
+                    "Unsupported sort '{}' (expected name, address, latency, or order)",
+                    value
+                ),
+            })?),
+        };
+        let normalize = |value: &Option<String>| {
+            value
+                .as_deref()
+                .map(|v| v.trim().to_lowercase())
+                .filter(|v| !v.is_empty())
+        };
+
+        Ok(Self {
+            q: normalize(&query.q),
+            tag: normalize(&query.tag),
+            favorite: query.favorite,
+            state,
+            sort,
+        })
+    }
+
+    /// Whether the filter needs the targets' rollups
+    pub(super) fn needs_rollups(&self) -> bool {
+        self.state.is_some() || self.sort == Some(TargetSort::Latency)
+    }
+
+    fn matches(&self, target: &Target, rollups: Option<&TargetRollups>) -> bool {
+        if let Some(q) = &self.q {
+            let found = target.id.to_lowercase().contains(q)
+                || target.address.to_lowercase().contains(q)
+                || target
+                    .name
+                    .as_deref()
+                    .is_some_and(|n| n.to_lowercase().contains(q));
+            if !found {
+                return false;
+            }
+        }
+        if let Some(tag) = &self.tag {
+            if !target.tags.iter().any(|t| t.to_lowercase() == *tag) {
+                return false;
+            }
+        }
+        if self
+            .favorite
+            .is_some_and(|favorite| target.favorite != favorite)
+        {
+            return false;
+        }
+        if let Some(state) = self.state {
+            if TargetState::of(target, rollups) != state {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Filter and sort `targets`; `rollups` holds the rollups of each target
+    /// by index (empty when [`needs_rollups`](Self::needs_rollups) is false)
+    pub(super) fn apply(
+        &self,
+        targets: &[Target],
+        rollups: &[Option<TargetRollups>],
+    ) -> Vec<Target> {
+        let rollups_of = |i: usize| rollups.get(i).and_then(Option::as_ref);
+        let mut selected: Vec<usize> = (0..targets.len())
+            .filter(|&i| self.matches(&targets[i], rollups_of(i)))
+            .collect();
+
+        match self.sort {
+            None => {}
+            Some(TargetSort::Name) => selected.sort_by_cached_key(|&i| {
+                let target = &targets[i];
+                target
+                    .name
+                    .as_deref()
+                    .unwrap_or(&target.address)
+                    .to_lowercase()
+            }),
+            Some(TargetSort::Address) => {
+                selected.sort_by(|&a, &b| targets[a].address.cmp(&targets[b].address))
+            }
+            Some(TargetSort::Latency) => {
+                let latency = |i: usize| rollups_of(i).and_then(|r| r.five_minutes.avg_latency_ms);
+                selected.sort_by(|&a, &b| match (latency(a), latency(b)) {
+                    (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+                    (Some(_), None) => Ordering::Less,
+                    (None, Some(_)) => Ordering::Greater,
+                    (None, None) => Ordering::Equal,
+                });
+            }
+            // Stable, so equal positions keep their config order
+            Some(TargetSort::Order) => selected.sort_by_key(|&i| {
+                let target = &targets[i];
+                (!target.favorite, target.sort_order)
+            }),
+        }
+
+        selected.into_iter().map(|i| targets[i].clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rollups::RollingAggregator;
+
+    fn target(id: &str, address: &str, name: Option<&str>, tags: &[&str]) -> Target {
+        Target {
+            id: id.to_string(),
+            address: address.to_string(),
+            name: name.map(str::to_string),
+            ping_count: 2,
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn targets() -> Vec<Target> {
+        vec![
+            target("router", "192.168.1.1", Some("Router"), &["office"]),
+            target("dns", "1.1.1.1", Some("Cloudflare DNS"), &["Internet"]),
+            target("nas", "192.168.1.20", None, &["office", "storage"]),
+        ]
+    }
+
+    fn query(
+        q: Option<&str>,
+        tag: Option<&str>,
+        state: Option<&str>,
+        sort: Option<&str>,
+    ) -> TargetListQuery {
+        TargetListQuery {
+            q: q.map(str::to_string),
+            tag: tag.map(str::to_string),
+            favorite: None,
+            state: state.map(str::to_string),
+            sort: sort.map(str::to_string),
+        }
+    }
+
+    fn ids(targets: &[Target]) -> Vec<&str> {
+        targets.iter().map(|t| t.id.as_str()).collect()
+    }
+
+    fn rollups(targets: &[Target], aggregator: &RollingAggregator) -> Vec<Option<TargetRollups>> {
+        targets
+            .iter()
+            .map(|t| aggregator.rollups(&t.id, 10))
+            .collect()
+    }
+
+    #[test]
+    fn test_search_and_tag() {
+        let targets = targets();
+
+        let filter = TargetFilter::from_query(&query(Some("192.168"), None, None, None)).unwrap();
+        assert_eq!(ids(&filter.apply(&targets, &[])), vec!["router", "nas"]);
+
+        // Name match, case-insensitive
+        let filter =
+            TargetFilter::from_query(&query(Some("cloudflare"), None, None, None)).unwrap();
+        assert_eq!(ids(&filter.apply(&targets, &[])), vec!["dns"]);
+
+        let filter = TargetFilter::from_query(&query(None, Some("internet"), None, None)).unwrap();
+        assert_eq!(ids(&filter.apply(&targets, &[])), vec!["dns"]);
+
+        let filter =
+            TargetFilter::from_query(&query(Some("nas"), Some("office"), None, None)).unwrap();
+        assert_eq!(ids(&filter.apply(&targets, &[])), vec!["nas"]);
+
+        // Empty parameters do not filter
+        let filter = TargetFilter::from_query(&query(Some(" "), Some(""), None, None)).unwrap();
+        assert_eq!(filter.apply(&targets, &[]).len(), 3);
+    }
+
+    #[test]
+    fn test_state_filter() {
+        let mut targets = targets();
+        targets[2].paused = true;
+        let aggregator = RollingAggregator::new();
+        aggregator.record("router", 9, Some(2.0));
+        aggregator.record("dns", 9, None);
+        aggregator.record("dns", 10, None);
+        let rollups = rollups(&targets, &aggregator);
+
+        let filter = TargetFilter::from_query(&query(None, None, Some("down"), None)).unwrap();
+        assert!(filter.needs_rollups());
+        assert_eq!(ids(&filter.apply(&targets, &rollups)), vec!["dns"]);
+
+        let filter = TargetFilter::from_query(&query(None, None, Some("up"), None)).unwrap();
+        assert_eq!(ids(&filter.apply(&targets, &rollups)), vec!["router"]);
+
+        let filter = TargetFilter::from_query(&query(None, None, Some("paused"), None)).unwrap();
+        assert_eq!(ids(&filter.apply(&targets, &rollups)), vec!["nas"]);
+    }
+
+    #[test]
+    fn test_sort() {
+        let targets = targets();
+        let aggregator = RollingAggregator::new();
+        aggregator.record("router", 10, Some(2.0));
+        aggregator.record("dns", 10, Some(1.0));
+        let rollups = rollups(&targets, &aggregator);
+
+        // Targets without latency go last
+        let filter = TargetFilter::from_query(&query(None, None, None, Some("latency"))).unwrap();
+        assert_eq!(
+            ids(&filter.apply(&targets, &rollups)),
+            vec!["dns", "router", "nas"]
+        );
+
+        // Unnamed targets sort by address
+        let filter = TargetFilter::from_query(&query(None, None, None, Some("name"))).unwrap();
+        assert_eq!(
+            ids(&filter.apply(&targets, &[])),
+            vec!["nas", "dns", "router"]
+        );
+    }
+
+    #[test]
+    fn test_user_order_and_favorites() {
+        let mut targets = targets();
+        targets[0].sort_order = 2;
+        targets[1].sort_order = 1;
+        targets[2].favorite = true;
+        targets[2].sort_order = 5;
+
+        let filter = TargetFilter::from_query(&query(None, None, None, Some("order"))).unwrap();
+        assert_eq!(
+            ids(&filter.apply(&targets, &[])),
+            vec!["nas", "dns", "router"]
+        );
+
+        // Equal positions keep the config order
+        targets[0].sort_order = 1;
+        assert_eq!(
+            ids(&filter.apply(&targets, &[])),
+            vec!["nas", "router", "dns"]
+        );
+
+        let mut favorites = query(None, None, None, None);
+        favorites.favorite = Some(true);
+        let filter = TargetFilter::from_query(&favorites).unwrap();
+        assert_eq!(ids(&filter.apply(&targets, &[])), vec!["nas"]);
+    }
+
+    #[test]
+    fn test_invalid_params() {
+        let err = TargetFilter::from_query(&query(None, None, Some("sideways"), None)).unwrap_err();
+        assert_eq!(err.field, "state");
+        let err = TargetFilter::from_query(&query(None, None, None, Some("age"))).unwrap_err();
+        assert_eq!(err.field, "sort");
+    }
+}
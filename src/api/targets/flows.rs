@@ -0,0 +1,184 @@
+//! Per-flow statistics of targets with `ecmp_flows`.
+//!
+//! Each ping of such a target is sent on one of several flows (distinct ICMP
+//! echo identifiers or TCP source ports, see `ping::Flow`), and load
+//! balancers hashing on them spread the flows over the paths of an ECMP or
+//! bonded uplink. A path that drops packets then only hurts the flows hashed
+//! onto it: loss that is concentrated on some flows instead of spread evenly
+//! points at one bad path rather than the target or the line as a whole.
+
+use super::dto::FlowStats;
+use crate::config::Target;
+use crate::storage::{StorageBackend, FLOW_LABEL};
+use std::collections::BTreeMap;
+
+const METRICS: [&str; 2] = ["ping_latency", "ping_failed"];
+
+/// Loss divergence in percentage points above which the flow with the most
+/// loss is reported as suspect
+const SUSPECT_DIVERGENCE_PERCENT: f64 = 5.0;
+
+/// Results a flow needs before it is compared with the others
+const MIN_FLOW_RESULTS: usize = 10;
+
+/// A single stored result: flow index and latency (None for failed pings)
+pub(super) type FlowResult = (u16, Option<f64>);
+
+/// Per-flow statistics and how far the flows diverge
+#[derive(Debug)]
+pub(super) struct FlowSummary {
+    pub flows: Vec<FlowStats>,
+    /// Highest minus lowest loss of the compared flows, in percentage points
+    pub loss_divergence_percent: Option<f64>,
+    /// Highest minus lowest average latency of the compared flows
+    pub latency_divergence_ms: Option<f64>,
+    /// Flow with the most loss, when the loss divergence is significant
+    pub suspect_flow: Option<u16>,
+}
+
+fn spread(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let (min, max) = values.fold(None, |range: Option<(f64, f64)>, v| match range {
+        Some((min, max)) => Some((min.min(v), max.max(v))),
+        None => Some((v, v)),
+    })?;
+    Some(max - min)
+}
+
+/// Summarize results per flow
+pub(super) fn summarize_flows(results: impl IntoIterator<Item = FlowResult>) -> FlowSummary {
+    let mut per_flow: BTreeMap<u16, (usize, usize, f64)> = BTreeMap::new();
+    for (flow, latency) in results {
+        let entry = per_flow.entry(flow).or_default();
+        match latency {
+            Some(latency) => {
+                entry.0 += 1;
+                entry.2 += latency;
+            }
+            None => entry.1 += 1,
+        }
+    }
+
+    let flows: Vec<FlowStats> = per_flow
+        .into_iter()
+        .map(|(flow, (successful, failed, latency_sum))| {
+            let total = successful + failed;
+            FlowStats {
+                flow,
+                successful_count: successful,
+                failed_count: failed,
+                loss_percent: (total > 0).then(|| failed as f64 / total as f64 * 100.0),
+                avg_latency_ms: (successful > 0).then(|| latency_sum / successful as f64),
+            }
+        })
+        .collect();
+
+    // Flows with few results would diverge by chance alone
+    let compared: Vec<&FlowStats> = flows
+        .iter()
+        .filter(|f| f.successful_count + f.failed_count >= MIN_FLOW_RESULTS)
+        .collect();
+    let (loss_divergence_percent, latency_divergence_ms) = if compared.len() < 2 {
+        (None, None)
+    } else {
+        (
+            spread(compared.iter().filter_map(|f| f.loss_percent)),
+            spread(compared.iter().filter_map(|f| f.avg_latency_ms)),
+        )
+    };
+    let suspect_flow = loss_divergence_percent
+        .filter(|d| *d >= SUSPECT_DIVERGENCE_PERCENT)
+        .and_then(|_| {
+            compared
+                .iter()
+                .max_by(|a, b| {
+                    a.loss_percent
+                        .unwrap_or(0.0)
+                        .total_cmp(&b.loss_percent.unwrap_or(0.0))
+                })
+                .map(|f| f.flow)
+        });
+
+    FlowSummary {
+        flows,
+        loss_divergence_percent,
+        latency_divergence_ms,
+        suspect_flow,
+    }
+}
+
+/// Read the target's flow-labeled results in `[from, to]` and summarize them.
+/// Results from before flows were enabled carry no flow label and are skipped.
+pub(super) fn query_flow_summary(
+    storage: &dyn StorageBackend,
+    target: &Target,
+    from: i64,
+    to: i64,
+) -> Result<FlowSummary, Box<dyn std::error::Error + Send + Sync>> {
+    let mut results = Vec::new();
+    for metric in METRICS {
+        let success = metric == "ping_latency";
+        for (labels, points) in storage.select_all(metric, from, to)? {
+            if !labels
+                .iter()
+                .any(|l| l.name == "target_id" && l.value == target.id)
+            {
+                continue;
+            }
+            let Some(flow) = labels
+                .iter()
+                .find(|l| l.name == FLOW_LABEL)
+                .and_then(|l| l.value.parse::<u16>().ok())
+            else {
+                continue;
+            };
+            results.extend(points.iter().map(|p| (flow, success.then_some(p.value))));
+        }
+    }
+    Ok(summarize_flows(results))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn results(flow: u16, successful: usize, failed: usize, latency: f64) -> Vec<FlowResult> {
+        let mut results = vec![(flow, Some(latency)); successful];
+        results.extend(vec![(flow, None); failed]);
+        results
+    }
+
+    #[test]
+    fn test_one_bad_flow_is_suspect() {
+        let mut all = results(0, 20, 0, 10.0);
+        all.extend(results(1, 15, 5, 12.0));
+        all.extend(results(2, 20, 0, 11.0));
+
+        let summary = summarize_flows(all);
+        assert_eq!(summary.flows.len(), 3);
+        assert_eq!(summary.flows[1].loss_percent, Some(25.0));
+        assert_eq!(summary.loss_divergence_percent, Some(25.0));
+        assert_eq!(summary.latency_divergence_ms, Some(2.0));
+        assert_eq!(summary.suspect_flow, Some(1));
+    }
+
+    #[test]
+    fn test_evenly_spread_loss_has_no_suspect() {
+        let mut all = results(0, 18, 2, 10.0);
+        all.extend(results(1, 18, 2, 10.0));
+
+        let summary = summarize_flows(all);
+        assert_eq!(summary.loss_divergence_percent, Some(0.0));
+        assert_eq!(summary.suspect_flow, None);
+    }
+
+    #[test]
+    fn test_flows_with_few_results_are_not_compared() {
+        let mut all = results(0, 20, 0, 10.0);
+        all.extend(results(1, 1, 2, 10.0));
+
+        let summary = summarize_flows(all);
+        assert_eq!(summary.flows.len(), 2);
+        assert_eq!(summary.loss_divergence_percent, None);
+        assert_eq!(summary.suspect_flow, None);
+    }
+}
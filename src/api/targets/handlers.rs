@@ -1,48 +1,376 @@
-use super::dto::TargetRequest;
+use super::dto::{
+    FlowQuery, FlowReportResponse, GapQuery, GapReportResponse, MigrateHistoryRequest,
+    TargetDetailResponse, TargetListQuery, TargetPatchRequest, TargetRequest, TargetStatus,
+    TunnelOverheadResponse, TunnelQuery,
+};
+use super::filter::TargetFilter;
+use super::flows::query_flow_summary;
+use super::query::{default_min_gap, query_target_gaps};
+use super::tunnel::query_tunnel_overhead;
+use crate::api::error::{ApiError, ErrorCode, ErrorResponse};
+use crate::api::extract::{Json, Query};
+use crate::api::ping::dto::TimeRangeValue;
+use crate::api::ping::query::{
+    parse_bucket_duration, query_ping_aggregated_with_rollups, resolve_time_range_value,
+};
+use crate::api::ping::summary::{sparkline_range, summarize, DEFAULT_SPARKLINE_BUCKETS};
 use crate::api::AppState;
-use crate::config::Target;
+use crate::config::{
+    validate_label_name, HistorySource, Target, MAX_ECMP_FLOWS, MAX_PAYLOAD_SIZE, MAX_TTL,
+};
 use crate::config_file;
+use crate::diagnose::{diagnose, DiagnosisEvent, DiagnosisOptions};
+use crate::storage::write_pause_transition;
+use crate::tasks::monitor::TaskHealth;
+use crate::tasks::schedule::ScheduleWindow;
 use crate::tasks::start_ping_task;
+use async_stream::stream;
 use axum::{
     extract::{Path, State},
     http::StatusCode,
-    response::Json,
+    response::sse::{Event, KeepAlive, Sse},
 };
+use futures::Stream;
+use std::collections::BTreeMap;
+use std::convert::Infallible;
 use std::sync::Arc;
-use tracing::error;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
 use uuid::Uuid;
 
+/// Reject port 0, which cannot be connected to
+fn validate_port(request: &TargetRequest) -> Result<(), ApiError> {
+    if request.port == Some(0) {
+        return Err(ApiError::bad_request(
+            ErrorCode::InvalidRequest,
+            "Port must be between 1 and 65535",
+        )
+        .with_details(serde_json::json!({ "field": "port" })));
+    }
+    Ok(())
+}
+
+/// Check `ecmp_flows`: each ping of a batch uses one flow, so there can be
+/// at most `ping_count` flows. 0 turns flows off.
+fn resolve_ecmp_flows(flows: u16, ping_count: u16) -> Result<Option<u16>, ApiError> {
+    let invalid = |message: String| {
+        ApiError::bad_request(ErrorCode::InvalidRequest, message)
+            .with_details(serde_json::json!({ "field": "ecmp_flows" }))
+    };
+    if flows > MAX_ECMP_FLOWS {
+        return Err(invalid(format!(
+            "At most {} ECMP flows are supported",
+            MAX_ECMP_FLOWS
+        )));
+    }
+    if flows > ping_count {
+        return Err(invalid(format!(
+            "ECMP flows ({}) cannot exceed ping_count ({})",
+            flows, ping_count
+        )));
+    }
+    Ok((flows > 1).then_some(flows))
+}
+
+/// Reject a TTL or payload size no IP packet can carry
+fn validate_probe_options(request: &TargetRequest) -> Result<(), ApiError> {
+    if request.ttl.is_some_and(|ttl| ttl > MAX_TTL) {
+        return Err(ApiError::bad_request(
+            ErrorCode::InvalidRequest,
+            format!("TTL must be between 1 and {}", MAX_TTL),
+        )
+        .with_details(serde_json::json!({ "field": "ttl" })));
+    }
+    if request
+        .payload_size
+        .is_some_and(|size| size > MAX_PAYLOAD_SIZE)
+    {
+        return Err(ApiError::bad_request(
+            ErrorCode::InvalidRequest,
+            format!("Payload size must be at most {} bytes", MAX_PAYLOAD_SIZE),
+        )
+        .with_details(serde_json::json!({ "field": "payload_size" })));
+    }
+    Ok(())
+}
+
+/// Probe option override of a target: the requested value, none for 0 (use
+/// the `[ping]` default), or the current value when not requested
+fn probe_override<T: Copy + Default + PartialEq>(
+    requested: Option<T>,
+    current: Option<T>,
+) -> Option<T> {
+    match requested {
+        Some(value) if value == T::default() => None,
+        Some(value) => Some(value),
+        None => current,
+    }
+}
+
+/// Parse the schedule windows of a request
+fn parse_schedule(windows: &[String]) -> Result<Vec<ScheduleWindow>, ApiError> {
+    windows
+        .iter()
+        .map(|window| {
+            window.parse().map_err(|e: String| {
+                ApiError::bad_request(ErrorCode::InvalidRequest, e)
+                    .with_details(serde_json::json!({ "field": "schedule" }))
+            })
+        })
+        .collect()
+}
+
+/// Trim tags, dropping empty and repeated ones
+fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = tag.trim();
+        if !tag.is_empty() && !normalized.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+            normalized.push(tag.to_string());
+        }
+    }
+    normalized
+}
+
+/// Trim label names and values, rejecting invalid or reserved names and
+/// empty values
+fn normalize_labels(
+    labels: BTreeMap<String, String>,
+) -> Result<BTreeMap<String, String>, ApiError> {
+    let invalid = |message: String| {
+        ApiError::bad_request(ErrorCode::InvalidRequest, message)
+            .with_details(serde_json::json!({ "field": "labels" }))
+    };
+    let mut normalized = BTreeMap::new();
+    for (name, value) in labels {
+        let name = name.trim();
+        validate_label_name(name).map_err(invalid)?;
+        let value = value.trim();
+        if value.is_empty() {
+            return Err(invalid(format!("Label '{}' needs a value", name)));
+        }
+        normalized.insert(name.to_string(), value.to_string());
+    }
+    Ok(normalized)
+}
+
+/// Check the tunnel reference of target `id`; an empty reference removes it
+fn resolve_tunnel_reference(
+    targets: &[Target],
+    id: &str,
+    reference: &str,
+) -> Result<Option<String>, ApiError> {
+    let reference = reference.trim();
+    if reference.is_empty() {
+        return Ok(None);
+    }
+    let invalid = |message: String| {
+        ApiError::bad_request(ErrorCode::InvalidRequest, message)
+            .with_details(serde_json::json!({ "field": "tunnel_reference" }))
+    };
+    if reference == id {
+        return Err(invalid(
+            "A target cannot be its own tunnel reference".to_string(),
+        ));
+    }
+    if !targets.iter().any(|t| t.id == reference) {
+        return Err(invalid(format!(
+            "Tunnel reference '{}' is not a target ID",
+            reference
+        )));
+    }
+    Ok(Some(reference.to_string()))
+}
+
 /// HTTP handler for GET /api/targets
+///
+/// Optionally searches (`q`), filters by `tag` and `state`, and sorts
+/// (`sort`) the targets, using the live rollups for state and latency.
+#[utoipa::path(
+    get,
+    path = "/api/targets",
+    tag = "targets",
+    summary = "List targets",
+    params(TargetListQuery),
+    responses(
+        (status = 200, description = "Matching targets", body = Vec<Target>),
+        (status = 400, description = "Invalid filter or sort", body = ErrorResponse),
+    )
+)]
 pub(crate) async fn get_targets(
     State(state): State<AppState>,
-) -> Result<Json<Vec<Target>>, (StatusCode, String)> {
+    Query(query): Query<TargetListQuery>,
+) -> Result<Json<Vec<Target>>, ApiError> {
+    let filter = TargetFilter::from_query(&query).map_err(|e| {
+        ApiError::bad_request(ErrorCode::InvalidRequest, e.message)
+            .with_details(serde_json::json!({ "field": e.field }))
+    })?;
+
     let config = state.config.read().map_err(|e| {
         error!("Failed to read config: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to read configuration".to_string(),
-        )
+        ApiError::internal(ErrorCode::ConfigUnavailable, "Failed to read configuration")
     })?;
 
-    Ok(Json(config.targets.clone()))
+    let rollups = if filter.needs_rollups() {
+        let now = chrono::Utc::now().timestamp();
+        config
+            .targets
+            .iter()
+            .map(|t| state.rollups.rollups(&t.id, now))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    Ok(Json(filter.apply(&config.targets, &rollups)))
+}
+
+/// HTTP handler for GET /api/targets/:id
+///
+/// The target definition with its live status (state, last ping and task
+/// from the rollups and task handles, 24h figures computed like in
+/// GET /api/ping/summary) and its storage usage.
+#[utoipa::path(
+    get,
+    path = "/api/targets/{id}",
+    tag = "targets",
+    summary = "Get a target with its current status and storage usage",
+    params(("id" = String, Path, description = "Target ID")),
+    responses(
+        (status = 200, description = "The target and its status", body = TargetDetailResponse),
+        (status = 404, description = "Target not found", body = ErrorResponse),
+        (status = 500, description = "Storage query failed", body = ErrorResponse),
+    )
+)]
+pub(crate) async fn get_target(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<TargetDetailResponse>, ApiError> {
+    let target = state
+        .config
+        .read()
+        .map_err(|e| {
+            error!("Failed to read config: {}", e);
+            ApiError::internal(ErrorCode::ConfigUnavailable, "Failed to read configuration")
+        })?
+        .targets
+        .iter()
+        .find(|t| t.id == id)
+        .cloned()
+        .ok_or_else(|| {
+            ApiError::not_found(
+                ErrorCode::TargetNotFound,
+                format!("Target with id '{}' not found", id),
+            )
+        })?;
+
+    let task_running = state
+        .task_handles
+        .read()
+        .map(|handles| handles.get(&id).is_some_and(|h| !h.is_finished()))
+        .unwrap_or(false);
+
+    let now = chrono::Utc::now().timestamp();
+    let rollups = state.rollups.rollups(&id, now);
+    let (bucket_duration, from) = sparkline_range(now, DEFAULT_SPARKLINE_BUCKETS);
+    let storage = Arc::clone(&state.storage);
+    let coverage = state.downsampler.coverage();
+    let query_target = target.clone();
+    let (buckets, storage_stats) = tokio::task::spawn_blocking(move || {
+        let (buckets, _, _) = query_ping_aggregated_with_rollups(
+            &*storage,
+            &coverage,
+            Some(&query_target.address),
+            Some(&query_target),
+            from,
+            now,
+            bucket_duration,
+            false,
+        )?;
+        Ok::<_, Box<dyn std::error::Error + Send + Sync>>((buckets, storage.stats()))
+    })
+    .await
+    .map_err(|e| {
+        error!("Task join error: {}", e);
+        ApiError::internal(ErrorCode::Internal, e.to_string())
+    })?
+    .map_err(|e| {
+        error!("Error querying target status: {}", e);
+        ApiError::internal(ErrorCode::StorageError, e.to_string())
+    })?;
+
+    let storage = match storage_stats {
+        Ok(stats) => stats.targets.into_iter().find(|s| s.target_id == id),
+        Err(e) => {
+            // The status is still useful without storage usage
+            warn!(
+                "Failed to calculate storage stats for target '{}': {}",
+                id, e
+            );
+            None
+        }
+    };
+
+    let summary = summarize(
+        std::slice::from_ref(&target),
+        std::slice::from_ref(&rollups),
+        &buckets,
+        from,
+        bucket_duration,
+        DEFAULT_SPARKLINE_BUCKETS,
+    )
+    .remove(0);
+    let status = TargetStatus {
+        state: summary.state,
+        task_running,
+        last_sample_unix: summary.last_sample_unix,
+        last_success: rollups.as_ref().and_then(|r| r.last_success),
+        last_latency_ms: summary.last_latency_ms,
+        consecutive_failed_count: rollups.map_or(0, |r| r.consecutive_failed_count),
+        avg_latency_24h_ms: summary.avg_latency_24h_ms,
+        loss_percent_24h: summary.loss_percent_24h,
+    };
+
+    Ok(Json(TargetDetailResponse {
+        target,
+        status,
+        storage,
+    }))
 }
 
 /// HTTP handler for POST /api/targets
+#[utoipa::path(
+    post,
+    path = "/api/targets",
+    tag = "targets",
+    summary = "Add a target and start pinging it",
+    request_body = TargetRequest,
+    responses(
+        (status = 200, description = "The created target", body = Target),
+        (status = 400, description = "Invalid target", body = ErrorResponse),
+        (status = 409, description = "A target with the ID already exists", body = ErrorResponse),
+    )
+)]
 pub(crate) async fn create_target(
     State(state): State<AppState>,
     Json(request): Json<TargetRequest>,
-) -> Result<Json<Target>, (StatusCode, String)> {
+) -> Result<Json<Target>, ApiError> {
     // Validate address
     if request.address.is_empty() {
-        return Err((StatusCode::BAD_REQUEST, "Address is required".to_string()));
+        return Err(
+            ApiError::bad_request(ErrorCode::InvalidRequest, "Address is required")
+                .with_details(serde_json::json!({ "field": "address" })),
+        );
     }
+    validate_port(&request)?;
+    validate_probe_options(&request)?;
 
     // Read current config
     let mut config = state.config.write().map_err(|e| {
         error!("Failed to write config: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to access configuration".to_string(),
+        ApiError::internal(
+            ErrorCode::ConfigUnavailable,
+            "Failed to access configuration",
         )
     })?;
 
@@ -51,26 +379,50 @@ pub(crate) async fn create_target(
 
     // Check if ID already exists
     if config.targets.iter().any(|t| t.id == id) {
-        return Err((
-            StatusCode::CONFLICT,
+        return Err(ApiError::conflict(
+            ErrorCode::TargetAlreadyExists,
             format!("Target with id '{}' already exists", id),
         ));
     }
 
+    let tunnel_reference = match request.tunnel_reference {
+        Some(ref reference) => resolve_tunnel_reference(&config.targets, &id, reference)?,
+        None => None,
+    };
+    let ping_count = request.ping_count.unwrap_or(3);
+    let ecmp_flows = resolve_ecmp_flows(request.ecmp_flows.unwrap_or(0), ping_count)?;
+    let schedule = parse_schedule(request.schedule.as_deref().unwrap_or_default())?;
+    let labels = normalize_labels(request.labels.unwrap_or_default())?;
+
     // Create new target
     let new_target = Target {
         id: id.clone(),
         address: request.address,
         name: request.name,
-        ping_count: request.ping_count.unwrap_or(3),
+        ping_count,
         ping_interval: request.ping_interval.unwrap_or(1),
+        probe_type: request.probe_type.unwrap_or_default(),
+        port: request.port,
+        retention_days: request.retention_days,
+        paused: false,
+        favorite: false,
+        sort_order: 0,
+        tags: normalize_tags(request.tags.unwrap_or_default()),
+        labels,
+        tunnel_reference,
+        ecmp_flows,
+        timeout_ms: probe_override(request.timeout_ms, None),
+        ttl: probe_override(request.ttl, None),
+        payload_size: probe_override(request.payload_size, None),
+        schedule,
+        history: Vec::new(),
     };
 
     // Read config file
     let mut doc = config_file::read_config_file(&state.config_path).map_err(|e| {
         error!("Failed to read config file: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
+        ApiError::internal(
+            ErrorCode::ConfigFileError,
             format!("Failed to read config file: {}", e),
         )
     })?;
@@ -78,8 +430,8 @@ pub(crate) async fn create_target(
     // Add target to document
     config_file::add_target(&mut doc, &new_target).map_err(|e| {
         error!("Failed to add target: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
+        ApiError::internal(
+            ErrorCode::ConfigFileError,
             format!("Failed to add target: {}", e),
         )
     })?;
@@ -87,8 +439,8 @@ pub(crate) async fn create_target(
     // Write config file
     config_file::write_config_file(&state.config_path, &doc, &state.write_flag).map_err(|e| {
         error!("Failed to write config file: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
+        ApiError::internal(
+            ErrorCode::ConfigFileError,
             format!("Failed to write config file: {}", e),
         )
     })?;
@@ -96,27 +448,33 @@ pub(crate) async fn create_target(
     // Update in-memory config
     config.targets.push(new_target.clone());
     drop(config);
+    // Cached buckets were computed without this target's series, which a
+    // re-created target may still have
+    state.writer.aggregate_cache().clear();
 
     // Start ping task immediately
     {
         let config = state.config.read().map_err(|e| {
             error!("Failed to read config: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to access config".to_string(),
-            )
+            ApiError::internal(ErrorCode::ConfigUnavailable, "Failed to access config")
         })?;
-        let socket_type = config.ping.socket_type;
+        let ping_config = config.ping.clone();
         drop(config);
 
         let mut handles = state.task_handles.write().map_err(|e| {
             error!("Failed to write task handles: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to access task handles".to_string(),
-            )
+            ApiError::internal(ErrorCode::Internal, "Failed to access task handles")
         })?;
-        let handle = start_ping_task(&new_target, Arc::clone(&state.storage), socket_type, 0);
+        let handle = start_ping_task(
+            &new_target,
+            Arc::clone(&state.storage),
+            Arc::clone(&state.writer),
+            Arc::clone(&state.rollups),
+            Arc::clone(&state.live),
+            Arc::clone(&state.task_monitor),
+            &ping_config,
+            0,
+        );
         handles.insert(new_target.id.clone(), handle);
     }
 
@@ -124,22 +482,40 @@ pub(crate) async fn create_target(
 }
 
 /// HTTP handler for PUT /api/targets/{id}
+#[utoipa::path(
+    put,
+    path = "/api/targets/{id}",
+    tag = "targets",
+    summary = "Update a target",
+    params(("id" = String, Path, description = "Target ID")),
+    request_body = TargetRequest,
+    responses(
+        (status = 200, description = "The updated target", body = Target),
+        (status = 400, description = "Invalid target", body = ErrorResponse),
+        (status = 404, description = "Target not found", body = ErrorResponse),
+    )
+)]
 pub(crate) async fn update_target(
     State(state): State<AppState>,
     Path(id): Path<String>,
     Json(request): Json<TargetRequest>,
-) -> Result<Json<Target>, (StatusCode, String)> {
+) -> Result<Json<Target>, ApiError> {
     // Validate address
     if request.address.is_empty() {
-        return Err((StatusCode::BAD_REQUEST, "Address is required".to_string()));
+        return Err(
+            ApiError::bad_request(ErrorCode::InvalidRequest, "Address is required")
+                .with_details(serde_json::json!({ "field": "address" })),
+        );
     }
+    validate_port(&request)?;
+    validate_probe_options(&request)?;
 
     // Read current config
     let mut config = state.config.write().map_err(|e| {
         error!("Failed to write config: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to access configuration".to_string(),
+        ApiError::internal(
+            ErrorCode::ConfigUnavailable,
+            "Failed to access configuration",
         )
     })?;
 
@@ -149,32 +525,78 @@ pub(crate) async fn update_target(
         .iter()
         .position(|t| t.id == id)
         .ok_or_else(|| {
-            (
-                StatusCode::NOT_FOUND,
+            ApiError::not_found(
+                ErrorCode::TargetNotFound,
                 format!("Target with id '{}' not found", id),
             )
         })?;
 
+    let new_id = request
+        .id
+        .unwrap_or_else(|| config.targets[target_idx].id.clone());
+    let tunnel_reference = match request.tunnel_reference {
+        Some(ref reference) => resolve_tunnel_reference(&config.targets, &new_id, reference)?,
+        None => config.targets[target_idx].tunnel_reference.clone(),
+    };
+    let ping_count = request
+        .ping_count
+        .unwrap_or(config.targets[target_idx].ping_count);
+    let ecmp_flows = resolve_ecmp_flows(
+        request
+            .ecmp_flows
+            .unwrap_or(config.targets[target_idx].flow_count()),
+        ping_count,
+    )?;
+    let schedule = match request.schedule {
+        Some(ref windows) => parse_schedule(windows)?,
+        None => config.targets[target_idx].schedule.clone(),
+    };
+    let labels = match request.labels {
+        Some(labels) => normalize_labels(labels)?,
+        None => config.targets[target_idx].labels.clone(),
+    };
+
     // Create updated target
     let updated_target = Target {
-        id: request
-            .id
-            .unwrap_or_else(|| config.targets[target_idx].id.clone()),
+        id: new_id,
         address: request.address,
         name: request.name,
-        ping_count: request
-            .ping_count
-            .unwrap_or(config.targets[target_idx].ping_count),
+        ping_count,
         ping_interval: request
             .ping_interval
             .unwrap_or(config.targets[target_idx].ping_interval),
+        probe_type: request
+            .probe_type
+            .unwrap_or(config.targets[target_idx].probe_type),
+        port: request.port.or(config.targets[target_idx].port),
+        retention_days: request
+            .retention_days
+            .or(config.targets[target_idx].retention_days),
+        paused: config.targets[target_idx].paused,
+        favorite: config.targets[target_idx].favorite,
+        sort_order: config.targets[target_idx].sort_order,
+        tags: match request.tags {
+            Some(tags) => normalize_tags(tags),
+            None => config.targets[target_idx].tags.clone(),
+        },
+        labels,
+        tunnel_reference,
+        ecmp_flows,
+        timeout_ms: probe_override(request.timeout_ms, config.targets[target_idx].timeout_ms),
+        ttl: probe_override(request.ttl, config.targets[target_idx].ttl),
+        payload_size: probe_override(
+            request.payload_size,
+            config.targets[target_idx].payload_size,
+        ),
+        schedule,
+        history: config.targets[target_idx].history.clone(),
     };
 
     // Read config file
     let mut doc = config_file::read_config_file(&state.config_path).map_err(|e| {
         error!("Failed to read config file: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
+        ApiError::internal(
+            ErrorCode::ConfigFileError,
             format!("Failed to read config file: {}", e),
         )
     })?;
@@ -182,8 +604,8 @@ pub(crate) async fn update_target(
     // Update target in document
     config_file::update_target(&mut doc, &id, &updated_target).map_err(|e| {
         error!("Failed to update target: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
+        ApiError::internal(
+            ErrorCode::ConfigFileError,
             format!("Failed to update target: {}", e),
         )
     })?;
@@ -191,54 +613,79 @@ pub(crate) async fn update_target(
     // Write config file
     config_file::write_config_file(&state.config_path, &doc, &state.write_flag).map_err(|e| {
         error!("Failed to write config file: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
+        ApiError::internal(
+            ErrorCode::ConfigFileError,
             format!("Failed to write config file: {}", e),
         )
     })?;
 
-    // Update in-memory config and get socket_type before dropping
+    // Update in-memory config and get ping settings before dropping
     config.targets[target_idx] = updated_target.clone();
-    let socket_type = config.ping.socket_type;
+    let ping_config = config.ping.clone();
     drop(config);
+    // Cached buckets were selected by the old address, ID and name
+    state.writer.aggregate_cache().clear();
 
     // Restart ping task immediately
     {
         let mut handles = state.task_handles.write().map_err(|e| {
             error!("Failed to write task handles: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to access task handles".to_string(),
-            )
+            ApiError::internal(ErrorCode::Internal, "Failed to access task handles")
         })?;
         if let Some(old_handle) = handles.remove(&id) {
             old_handle.abort();
         }
-        let handle = start_ping_task(&updated_target, Arc::clone(&state.storage), socket_type, 0);
-        handles.insert(updated_target.id.clone(), handle);
+        if updated_target.id != id {
+            state.rollups.remove(&id);
+            state.task_monitor.remove(&id);
+        }
+        if !updated_target.paused {
+            let handle = start_ping_task(
+                &updated_target,
+                Arc::clone(&state.storage),
+                Arc::clone(&state.writer),
+                Arc::clone(&state.rollups),
+                Arc::clone(&state.live),
+                Arc::clone(&state.task_monitor),
+                &ping_config,
+                0,
+            );
+            handles.insert(updated_target.id.clone(), handle);
+        }
     }
 
     Ok(Json(updated_target))
 }
 
 /// HTTP handler for DELETE /api/targets/{id}
+#[utoipa::path(
+    delete,
+    path = "/api/targets/{id}",
+    tag = "targets",
+    summary = "Delete a target (stored data is kept)",
+    params(("id" = String, Path, description = "Target ID")),
+    responses(
+        (status = 204, description = "Target deleted"),
+        (status = 404, description = "Target not found", body = ErrorResponse),
+    )
+)]
 pub(crate) async fn delete_target(
     State(state): State<AppState>,
     Path(id): Path<String>,
-) -> Result<StatusCode, (StatusCode, String)> {
+) -> Result<StatusCode, ApiError> {
     // Read current config
     let mut config = state.config.write().map_err(|e| {
         error!("Failed to write config: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to access configuration".to_string(),
+        ApiError::internal(
+            ErrorCode::ConfigUnavailable,
+            "Failed to access configuration",
         )
     })?;
 
     // Check if target exists
     if !config.targets.iter().any(|t| t.id == id) {
-        return Err((
-            StatusCode::NOT_FOUND,
+        return Err(ApiError::not_found(
+            ErrorCode::TargetNotFound,
             format!("Target with id '{}' not found", id),
         ));
     }
@@ -246,8 +693,8 @@ pub(crate) async fn delete_target(
     // Read config file
     let mut doc = config_file::read_config_file(&state.config_path).map_err(|e| {
         error!("Failed to read config file: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
+        ApiError::internal(
+            ErrorCode::ConfigFileError,
             format!("Failed to read config file: {}", e),
         )
     })?;
@@ -255,8 +702,8 @@ pub(crate) async fn delete_target(
     // Remove target from document
     config_file::remove_target(&mut doc, &id).map_err(|e| {
         error!("Failed to remove target: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
+        ApiError::internal(
+            ErrorCode::ConfigFileError,
             format!("Failed to remove target: {}", e),
         )
     })?;
@@ -264,8 +711,8 @@ pub(crate) async fn delete_target(
     // Write config file
     config_file::write_config_file(&state.config_path, &doc, &state.write_flag).map_err(|e| {
         error!("Failed to write config file: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
+        ApiError::internal(
+            ErrorCode::ConfigFileError,
             format!("Failed to write config file: {}", e),
         )
     })?;
@@ -273,20 +720,846 @@ pub(crate) async fn delete_target(
     // Update in-memory config
     config.targets.retain(|t| t.id != id);
     drop(config);
+    state.writer.aggregate_cache().clear();
 
     // Stop ping task
     {
         let mut handles = state.task_handles.write().map_err(|e| {
             error!("Failed to write task handles: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to access task handles".to_string(),
-            )
+            ApiError::internal(ErrorCode::Internal, "Failed to access task handles")
         })?;
         if let Some(handle) = handles.remove(&id) {
             handle.abort();
         }
     }
+    state.rollups.remove(&id);
+    state.task_monitor.remove(&id);
 
     Ok(StatusCode::NO_CONTENT)
 }
+
+/// HTTP handler for POST /api/targets/:id/pause
+///
+/// Stops pinging the target without deleting it; stored data is kept.
+#[utoipa::path(
+    post,
+    path = "/api/targets/{id}/pause",
+    tag = "targets",
+    summary = "Stop pinging a target",
+    params(("id" = String, Path, description = "Target ID")),
+    responses(
+        (status = 200, description = "The paused target", body = Target),
+        (status = 404, description = "Target not found", body = ErrorResponse),
+    )
+)]
+pub(crate) async fn pause_target(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Target>, ApiError> {
+    set_target_paused(&state, &id, true)
+}
+
+/// HTTP handler for POST /api/targets/:id/resume
+#[utoipa::path(
+    post,
+    path = "/api/targets/{id}/resume",
+    tag = "targets",
+    summary = "Resume pinging a paused target",
+    params(("id" = String, Path, description = "Target ID")),
+    responses(
+        (status = 200, description = "The resumed target", body = Target),
+        (status = 404, description = "Target not found", body = ErrorResponse),
+    )
+)]
+pub(crate) async fn resume_target(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Target>, ApiError> {
+    set_target_paused(&state, &id, false)
+}
+
+/// Persist the paused flag and stop or start the target's ping task
+fn set_target_paused(state: &AppState, id: &str, paused: bool) -> Result<Json<Target>, ApiError> {
+    let mut config = state.config.write().map_err(|e| {
+        error!("Failed to write config: {}", e);
+        ApiError::internal(
+            ErrorCode::ConfigUnavailable,
+            "Failed to access configuration",
+        )
+    })?;
+
+    let target_idx = config
+        .targets
+        .iter()
+        .position(|t| t.id == id)
+        .ok_or_else(|| {
+            ApiError::not_found(
+                ErrorCode::TargetNotFound,
+                format!("Target with id '{}' not found", id),
+            )
+        })?;
+
+    // Already in the requested state: nothing to write or restart
+    if config.targets[target_idx].paused == paused {
+        return Ok(Json(config.targets[target_idx].clone()));
+    }
+
+    // Read config file
+    let mut doc = config_file::read_config_file(&state.config_path).map_err(|e| {
+        error!("Failed to read config file: {}", e);
+        ApiError::internal(
+            ErrorCode::ConfigFileError,
+            format!("Failed to read config file: {}", e),
+        )
+    })?;
+
+    // Update paused flag in document
+    config_file::set_target_paused(&mut doc, id, paused).map_err(|e| {
+        error!("Failed to update target: {}", e);
+        ApiError::internal(
+            ErrorCode::ConfigFileError,
+            format!("Failed to update target: {}", e),
+        )
+    })?;
+
+    // Write config file
+    config_file::write_config_file(&state.config_path, &doc, &state.write_flag).map_err(|e| {
+        error!("Failed to write config file: {}", e);
+        ApiError::internal(
+            ErrorCode::ConfigFileError,
+            format!("Failed to write config file: {}", e),
+        )
+    })?;
+
+    // Update in-memory config and get ping settings before dropping
+    config.targets[target_idx].paused = paused;
+    let target = config.targets[target_idx].clone();
+    let ping_config = config.ping.clone();
+    drop(config);
+
+    // Gap reports leave out the time the target was paused
+    let storage = Arc::clone(&state.storage);
+    let target_id = id.to_string();
+    tokio::task::spawn_blocking(move || {
+        let now = chrono::Utc::now().timestamp();
+        if let Err(e) = write_pause_transition(&*storage, &target_id, paused, now) {
+            warn!("Failed to record pause of target {}: {}", target_id, e);
+        }
+    });
+
+    {
+        let mut handles = state.task_handles.write().map_err(|e| {
+            error!("Failed to write task handles: {}", e);
+            ApiError::internal(ErrorCode::Internal, "Failed to access task handles")
+        })?;
+        if let Some(handle) = handles.remove(id) {
+            handle.abort();
+        }
+        if paused {
+            info!("Paused target {}", id);
+            state.rollups.remove(id);
+            state.task_monitor.remove(id);
+        } else {
+            info!("Resumed target {}", id);
+            let handle = start_ping_task(
+                &target,
+                Arc::clone(&state.storage),
+                Arc::clone(&state.writer),
+                Arc::clone(&state.rollups),
+                Arc::clone(&state.live),
+                Arc::clone(&state.task_monitor),
+                &ping_config,
+                0,
+            );
+            handles.insert(target.id.clone(), handle);
+        }
+    }
+
+    Ok(Json(target))
+}
+
+/// HTTP handler for GET /api/targets/:id/task
+///
+/// Whether the target's ping task is alive and still completing batches. A
+/// task that ended (e.g. by a panic) is not running; one without a batch for
+/// several cycles is stalled. Either is fixed by POST
+/// /api/targets/:id/restart.
+#[utoipa::path(
+    get,
+    path = "/api/targets/{id}/task",
+    tag = "targets",
+    summary = "Health of a target's ping task",
+    params(("id" = String, Path, description = "Target ID")),
+    responses(
+        (status = 200, description = "Health of the task", body = TaskHealth),
+        (status = 404, description = "Target not found", body = ErrorResponse),
+    )
+)]
+pub(crate) async fn get_target_task(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<TaskHealth>, ApiError> {
+    let paused = state
+        .config
+        .read()
+        .map_err(|e| {
+            error!("Failed to read config: {}", e);
+            ApiError::internal(ErrorCode::ConfigUnavailable, "Failed to read configuration")
+        })?
+        .targets
+        .iter()
+        .find(|t| t.id == id)
+        .map(|t| t.paused)
+        .ok_or_else(|| {
+            ApiError::not_found(
+                ErrorCode::TargetNotFound,
+                format!("Target with id '{}' not found", id),
+            )
+        })?;
+
+    Ok(Json(task_health(&state, &id, paused)?))
+}
+
+/// HTTP handler for POST /api/targets/:id/restart
+///
+/// Aborts the target's ping task, if any is left, and starts a new one.
+/// Nothing is written to the config file.
+#[utoipa::path(
+    post,
+    path = "/api/targets/{id}/restart",
+    tag = "targets",
+    summary = "Restart a target's ping task",
+    params(("id" = String, Path, description = "Target ID")),
+    responses(
+        (status = 200, description = "Health of the new task", body = TaskHealth),
+        (status = 404, description = "Target not found", body = ErrorResponse),
+        (status = 409, description = "The target is paused", body = ErrorResponse),
+    )
+)]
+pub(crate) async fn restart_target_task(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<TaskHealth>, ApiError> {
+    let config = state.config.read().map_err(|e| {
+        error!("Failed to read config: {}", e);
+        ApiError::internal(ErrorCode::ConfigUnavailable, "Failed to read configuration")
+    })?;
+    let target = config
+        .targets
+        .iter()
+        .find(|t| t.id == id)
+        .cloned()
+        .ok_or_else(|| {
+            ApiError::not_found(
+                ErrorCode::TargetNotFound,
+                format!("Target with id '{}' not found", id),
+            )
+        })?;
+    let ping_config = config.ping.clone();
+    drop(config);
+
+    if target.paused {
+        return Err(ApiError::conflict(
+            ErrorCode::InvalidRequest,
+            format!("Target '{}' is paused, resume it instead", id),
+        ));
+    }
+
+    {
+        let mut handles = state.task_handles.write().map_err(|e| {
+            error!("Failed to write task handles: {}", e);
+            ApiError::internal(ErrorCode::Internal, "Failed to access task handles")
+        })?;
+        if let Some(handle) = handles.remove(&id) {
+            handle.abort();
+        }
+        info!("Restarting ping task of target {}", id);
+        let handle = start_ping_task(
+            &target,
+            Arc::clone(&state.storage),
+            Arc::clone(&state.writer),
+            Arc::clone(&state.rollups),
+            Arc::clone(&state.live),
+            Arc::clone(&state.task_monitor),
+            &ping_config,
+            0,
+        );
+        handles.insert(id.clone(), handle);
+    }
+
+    Ok(Json(task_health(&state, &id, false)?))
+}
+
+/// Health of a target's task, using its handle to tell whether it runs
+fn task_health(state: &AppState, id: &str, paused: bool) -> Result<TaskHealth, ApiError> {
+    let running = state
+        .task_handles
+        .read()
+        .map_err(|e| {
+            error!("Failed to read task handles: {}", e);
+            ApiError::internal(ErrorCode::Internal, "Failed to access task handles")
+        })?
+        .get(id)
+        .is_some_and(|h| !h.is_finished());
+    let now = chrono::Utc::now().timestamp();
+    Ok(state.task_monitor.health(id, running, paused, now))
+}
+
+/// HTTP handler for PATCH /api/targets/:id
+///
+/// Sets how the target is presented: its favorite flag and its position in
+/// the user-chosen order (`sort=order` on GET /api/targets). The ping task
+/// keeps running.
+#[utoipa::path(
+    patch,
+    path = "/api/targets/{id}",
+    tag = "targets",
+    summary = "Mark a target as favorite or move it in the target order",
+    params(("id" = String, Path, description = "Target ID")),
+    request_body = TargetPatchRequest,
+    responses(
+        (status = 200, description = "The updated target", body = Target),
+        (status = 404, description = "Target not found", body = ErrorResponse),
+    )
+)]
+pub(crate) async fn patch_target(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(request): Json<TargetPatchRequest>,
+) -> Result<Json<Target>, ApiError> {
+    let mut config = state.config.write().map_err(|e| {
+        error!("Failed to write config: {}", e);
+        ApiError::internal(
+            ErrorCode::ConfigUnavailable,
+            "Failed to access configuration",
+        )
+    })?;
+
+    let target_idx = config
+        .targets
+        .iter()
+        .position(|t| t.id == id)
+        .ok_or_else(|| {
+            ApiError::not_found(
+                ErrorCode::TargetNotFound,
+                format!("Target with id '{}' not found", id),
+            )
+        })?;
+
+    let mut updated_target = config.targets[target_idx].clone();
+    if let Some(favorite) = request.favorite {
+        updated_target.favorite = favorite;
+    }
+    if let Some(sort_order) = request.sort_order {
+        updated_target.sort_order = sort_order;
+    }
+    // Nothing changed: nothing to write
+    if updated_target.favorite == config.targets[target_idx].favorite
+        && updated_target.sort_order == config.targets[target_idx].sort_order
+    {
+        return Ok(Json(updated_target));
+    }
+
+    // Read config file
+    let mut doc = config_file::read_config_file(&state.config_path).map_err(|e| {
+        error!("Failed to read config file: {}", e);
+        ApiError::internal(
+            ErrorCode::ConfigFileError,
+            format!("Failed to read config file: {}", e),
+        )
+    })?;
+
+    // Update target in document
+    config_file::update_target(&mut doc, &id, &updated_target).map_err(|e| {
+        error!("Failed to update target: {}", e);
+        ApiError::internal(
+            ErrorCode::ConfigFileError,
+            format!("Failed to update target: {}", e),
+        )
+    })?;
+
+    // Write config file
+    config_file::write_config_file(&state.config_path, &doc, &state.write_flag).map_err(|e| {
+        error!("Failed to write config file: {}", e);
+        ApiError::internal(
+            ErrorCode::ConfigFileError,
+            format!("Failed to write config file: {}", e),
+        )
+    })?;
+
+    config.targets[target_idx] = updated_target.clone();
+    drop(config);
+    // Cached buckets carry the old name
+    state.writer.aggregate_cache().clear();
+
+    Ok(Json(updated_target))
+}
+
+/// HTTP handler for POST /api/targets/:id/migrate-history
+///
+/// Adds an earlier identity (an old target ID, or the target's own ID with
+/// its old address) to the target's `history`, so data stored under it is
+/// read as part of this target's series. Stored data is not rewritten; old
+/// and new series are merged when queried.
+#[utoipa::path(
+    post,
+    path = "/api/targets/{id}/migrate-history",
+    tag = "targets",
+    summary = "Merge the history of an earlier target ID or address",
+    params(("id" = String, Path, description = "Target ID")),
+    request_body = MigrateHistoryRequest,
+    responses(
+        (status = 200, description = "The target with its updated history", body = Target),
+        (status = 400, description = "Invalid source identity", body = ErrorResponse),
+        (status = 404, description = "Target not found", body = ErrorResponse),
+        (status = 409, description = "The source ID belongs to another configured target", body = ErrorResponse),
+    )
+)]
+pub(crate) async fn migrate_target_history(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(request): Json<MigrateHistoryRequest>,
+) -> Result<Json<Target>, ApiError> {
+    let from_id = request.from_id.filter(|s| !s.is_empty());
+    let from_address = request.from_address.filter(|s| !s.is_empty());
+    if from_id.is_none() && from_address.is_none() {
+        return Err(ApiError::bad_request(
+            ErrorCode::InvalidRequest,
+            "from_id or from_address is required",
+        )
+        .with_details(serde_json::json!({ "field": "from_id" })));
+    }
+    let source = HistorySource {
+        id: from_id.unwrap_or_else(|| id.clone()),
+        address: from_address,
+    };
+
+    let mut config = state.config.write().map_err(|e| {
+        error!("Failed to write config: {}", e);
+        ApiError::internal(
+            ErrorCode::ConfigUnavailable,
+            "Failed to access configuration",
+        )
+    })?;
+
+    let target_idx = config
+        .targets
+        .iter()
+        .position(|t| t.id == id)
+        .ok_or_else(|| {
+            ApiError::not_found(
+                ErrorCode::TargetNotFound,
+                format!("Target with id '{}' not found", id),
+            )
+        })?;
+    let target = &config.targets[target_idx];
+
+    if source.id == target.id
+        && source
+            .address
+            .as_deref()
+            .is_none_or(|a| a == target.address)
+    {
+        return Err(ApiError::bad_request(
+            ErrorCode::InvalidRequest,
+            "The source is the target's current ID and address",
+        )
+        .with_details(serde_json::json!({ "field": "from_address" })));
+    }
+    // A live target keeps writing under its ID; merging it would mix two
+    // targets' data going forward
+    if source.id != id && config.targets.iter().any(|t| t.id == source.id) {
+        return Err(ApiError::conflict(
+            ErrorCode::TargetAlreadyExists,
+            format!(
+                "Target '{}' still exists; delete it before merging its history",
+                source.id
+            ),
+        )
+        .with_details(serde_json::json!({ "field": "from_id" })));
+    }
+
+    // Already merged: nothing to write
+    if target.history.contains(&source) {
+        return Ok(Json(target.clone()));
+    }
+    let mut updated_target = target.clone();
+    updated_target.history.push(source);
+
+    // Read config file
+    let mut doc = config_file::read_config_file(&state.config_path).map_err(|e| {
+        error!("Failed to read config file: {}", e);
+        ApiError::internal(
+            ErrorCode::ConfigFileError,
+            format!("Failed to read config file: {}", e),
+        )
+    })?;
+
+    // Update target in document
+    config_file::update_target(&mut doc, &id, &updated_target).map_err(|e| {
+        error!("Failed to update target: {}", e);
+        ApiError::internal(
+            ErrorCode::ConfigFileError,
+            format!("Failed to update target: {}", e),
+        )
+    })?;
+
+    // Write config file
+    config_file::write_config_file(&state.config_path, &doc, &state.write_flag).map_err(|e| {
+        error!("Failed to write config file: {}", e);
+        ApiError::internal(
+            ErrorCode::ConfigFileError,
+            format!("Failed to write config file: {}", e),
+        )
+    })?;
+
+    // The ping task does not use the history, so it keeps running
+    config.targets[target_idx] = updated_target.clone();
+    drop(config);
+    // Cached buckets lack the merged series
+    state.writer.aggregate_cache().clear();
+    info!("Merged history into target {}", id);
+
+    Ok(Json(updated_target))
+}
+
+/// HTTP handler for GET /api/targets/:id/gaps
+///
+/// Lists intervals longer than `min_gap` in which no result (successful or
+/// failed) was stored for the target. Failed pings are outages and are not
+/// gaps; a gap means nothing was measured at all.
+/// Time the target was paused is left out.
+#[utoipa::path(
+    get,
+    path = "/api/targets/{id}/gaps",
+    tag = "targets",
+    summary = "Intervals without stored results",
+    params(("id" = String, Path, description = "Target ID"), GapQuery),
+    responses(
+        (status = 200, description = "Gaps of the target", body = GapReportResponse),
+        (status = 400, description = "Invalid time range or min_gap", body = ErrorResponse),
+        (status = 404, description = "Target not found", body = ErrorResponse),
+    )
+)]
+pub(crate) async fn get_target_gaps(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<GapQuery>,
+) -> Result<Json<GapReportResponse>, ApiError> {
+    info!("Querying data gaps for target {}: {:?}", id, query);
+
+    let target = {
+        let config = state.config.read().map_err(|e| {
+            error!("Failed to read config: {}", e);
+            ApiError::internal(ErrorCode::ConfigUnavailable, "Failed to read configuration")
+        })?;
+        config
+            .targets
+            .iter()
+            .find(|t| t.id == id)
+            .cloned()
+            .ok_or_else(|| {
+                ApiError::not_found(
+                    ErrorCode::TargetNotFound,
+                    format!("Target with id '{}' not found", id),
+                )
+            })?
+    };
+
+    let from_value = query
+        .from
+        .clone()
+        .unwrap_or_else(|| TimeRangeValue::Relative("24h".to_string()));
+    let from = resolve_time_range_value(&from_value).map_err(|e| {
+        error!("Invalid time range: {}", e);
+        ApiError::bad_request(ErrorCode::InvalidTimeRange, e)
+    })?;
+    let to = query.to.unwrap_or_else(|| chrono::Utc::now().timestamp());
+
+    let min_gap_seconds = match query.min_gap.as_deref() {
+        Some(min_gap) => parse_bucket_duration(min_gap).map_err(|e| {
+            error!("Invalid min_gap: {}", e);
+            ApiError::bad_request(ErrorCode::InvalidDuration, e)
+        })?,
+        None => default_min_gap(&target),
+    };
+
+    let storage = Arc::clone(&state.storage);
+    let gaps = tokio::task::spawn_blocking(move || {
+        query_target_gaps(&*storage, &target, from, to, min_gap_seconds).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| {
+        error!("Task join error: {}", e);
+        ApiError::internal(ErrorCode::Internal, e.to_string())
+    })?
+    .map_err(|e| {
+        error!("Error querying data gaps: {}", e);
+        ApiError::internal(ErrorCode::StorageError, e)
+    })?;
+
+    let total_gap_seconds = gaps.iter().map(|g| g.duration_seconds).sum();
+
+    Ok(Json(GapReportResponse {
+        target_id: id,
+        from_timestamp: from,
+        to_timestamp: to,
+        min_gap_seconds,
+        gaps,
+        total_gap_seconds,
+    }))
+}
+
+/// HTTP handler for GET /api/targets/{id}/tunnel
+///
+/// Derived series of a tunnel target: latency and loss added by the tunnel,
+/// relative to its outside reference target, per bucket.
+#[utoipa::path(
+    get,
+    path = "/api/targets/{id}/tunnel",
+    tag = "targets",
+    summary = "Latency and loss added by a VPN tunnel",
+    params(("id" = String, Path, description = "Target ID"), TunnelQuery),
+    responses(
+        (status = 200, description = "Overhead per bucket", body = TunnelOverheadResponse),
+        (status = 400, description = "Invalid range, or not a tunnel target", body = ErrorResponse),
+        (status = 404, description = "Target not found", body = ErrorResponse),
+    )
+)]
+pub(crate) async fn get_tunnel_overhead(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<TunnelQuery>,
+) -> Result<Json<TunnelOverheadResponse>, ApiError> {
+    info!("Querying tunnel overhead for target {}: {:?}", id, query);
+
+    let (tunnel, reference) = {
+        let config = state.config.read().map_err(|e| {
+            error!("Failed to read config: {}", e);
+            ApiError::internal(ErrorCode::ConfigUnavailable, "Failed to read configuration")
+        })?;
+        let find = |id: &str| {
+            config
+                .targets
+                .iter()
+                .find(|t| t.id == id)
+                .cloned()
+                .ok_or_else(|| {
+                    ApiError::not_found(
+                        ErrorCode::TargetNotFound,
+                        format!("Target with id '{}' not found", id),
+                    )
+                })
+        };
+        let tunnel = find(&id)?;
+        let reference_id = tunnel.tunnel_reference.clone().ok_or_else(|| {
+            ApiError::bad_request(
+                ErrorCode::InvalidRequest,
+                format!("Target '{}' has no tunnel_reference", id),
+            )
+        })?;
+        let reference = find(&reference_id)?;
+        (tunnel, reference)
+    };
+
+    let from_value = query
+        .from
+        .clone()
+        .unwrap_or_else(|| TimeRangeValue::Relative("24h".to_string()));
+    let from = resolve_time_range_value(&from_value).map_err(|e| {
+        error!("Invalid time range: {}", e);
+        ApiError::bad_request(ErrorCode::InvalidTimeRange, e)
+    })?;
+    let to = query.to.unwrap_or_else(|| chrono::Utc::now().timestamp());
+
+    let bucket_duration_seconds = parse_bucket_duration(query.bucket.as_deref().unwrap_or("5m"))
+        .map_err(|e| {
+            error!("Invalid bucket duration: {}", e);
+            ApiError::bad_request(ErrorCode::InvalidDuration, e)
+        })?;
+
+    let storage = Arc::clone(&state.storage);
+    let coverage = state.downsampler.coverage();
+    let reference_id = reference.id.clone();
+    let (data, avg_delta_latency_ms, differential_loss_percent) =
+        tokio::task::spawn_blocking(move || {
+            query_tunnel_overhead(
+                &*storage,
+                &coverage,
+                &tunnel,
+                &reference,
+                from,
+                to,
+                bucket_duration_seconds,
+            )
+        })
+        .await
+        .map_err(|e| {
+            error!("Task join error: {}", e);
+            ApiError::internal(ErrorCode::Internal, e.to_string())
+        })?
+        .map_err(|e| {
+            error!("Error querying tunnel overhead: {}", e);
+            ApiError::internal(ErrorCode::StorageError, e.to_string())
+        })?;
+
+    Ok(Json(TunnelOverheadResponse {
+        target_id: id,
+        reference_id,
+        from_timestamp: from,
+        to_timestamp: to,
+        bucket_duration_seconds,
+        data,
+        avg_delta_latency_ms,
+        differential_loss_percent,
+    }))
+}
+
+/// HTTP handler for GET /api/targets/{id}/flows
+///
+/// Loss and latency per ECMP flow of a target with `ecmp_flows`, and how far
+/// the flows diverge.
+#[utoipa::path(
+    get,
+    path = "/api/targets/{id}/flows",
+    tag = "targets",
+    summary = "Loss and latency per ECMP flow",
+    params(("id" = String, Path, description = "Target ID"), FlowQuery),
+    responses(
+        (status = 200, description = "Statistics per flow", body = FlowReportResponse),
+        (status = 400, description = "Invalid time range", body = ErrorResponse),
+        (status = 404, description = "Target not found", body = ErrorResponse),
+    )
+)]
+pub(crate) async fn get_target_flows(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<FlowQuery>,
+) -> Result<Json<FlowReportResponse>, ApiError> {
+    info!("Querying flows for target {}: {:?}", id, query);
+
+    let target = {
+        let config = state.config.read().map_err(|e| {
+            error!("Failed to read config: {}", e);
+            ApiError::internal(ErrorCode::ConfigUnavailable, "Failed to read configuration")
+        })?;
+        config
+            .targets
+            .iter()
+            .find(|t| t.id == id)
+            .cloned()
+            .ok_or_else(|| {
+                ApiError::not_found(
+                    ErrorCode::TargetNotFound,
+                    format!("Target with id '{}' not found", id),
+                )
+            })?
+    };
+
+    let from_value = query
+        .from
+        .clone()
+        .unwrap_or_else(|| TimeRangeValue::Relative("24h".to_string()));
+    let from = resolve_time_range_value(&from_value).map_err(|e| {
+        error!("Invalid time range: {}", e);
+        ApiError::bad_request(ErrorCode::InvalidTimeRange, e)
+    })?;
+    let to = query.to.unwrap_or_else(|| chrono::Utc::now().timestamp());
+
+    let storage = Arc::clone(&state.storage);
+    let ecmp_flows = target.flow_count();
+    let summary =
+        tokio::task::spawn_blocking(move || query_flow_summary(&*storage, &target, from, to))
+            .await
+            .map_err(|e| {
+                error!("Task join error: {}", e);
+                ApiError::internal(ErrorCode::Internal, e.to_string())
+            })?
+            .map_err(|e| {
+                error!("Error querying flows: {}", e);
+                ApiError::internal(ErrorCode::StorageError, e.to_string())
+            })?;
+
+    Ok(Json(FlowReportResponse {
+        target_id: id,
+        ecmp_flows,
+        from_timestamp: from,
+        to_timestamp: to,
+        flows: summary.flows,
+        loss_divergence_percent: summary.loss_divergence_percent,
+        latency_divergence_ms: summary.latency_divergence_ms,
+        suspect_flow: summary.suspect_flow,
+    }))
+}
+
+/// HTTP handler for POST /api/targets/:id/diagnose
+///
+/// Runs the troubleshooting battery (see `crate::diagnose`) against the
+/// target and streams it as SSE: a `running` event when a step starts, a
+/// `step` event with its result, and a final `verdict` event with the full
+/// report. A run takes up to about a minute.
+#[utoipa::path(
+    post,
+    path = "/api/targets/{id}/diagnose",
+    tag = "targets",
+    summary = "Run troubleshooting steps against a target (Server-Sent Events)",
+    params(("id" = String, Path, description = "Target ID")),
+    responses(
+        (status = 200, description = "`running`, `step` and `verdict` events", content_type = "text/event-stream", body = String),
+        (status = 404, description = "Target not found", body = ErrorResponse),
+    )
+)]
+pub(crate) async fn diagnose_target(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let (target, options) = {
+        let config = state.config.read().map_err(|e| {
+            error!("Failed to read config: {}", e);
+            ApiError::internal(ErrorCode::ConfigUnavailable, "Failed to read configuration")
+        })?;
+        let target = config
+            .targets
+            .iter()
+            .find(|t| t.id == id)
+            .cloned()
+            .ok_or_else(|| {
+                ApiError::not_found(
+                    ErrorCode::TargetNotFound,
+                    format!("Target with id '{}' not found", id),
+                )
+            })?;
+        let options = DiagnosisOptions {
+            socket_type: config.ping.socket_type,
+            max_hops: config.traceroute.max_hops,
+        };
+        (target, options)
+    };
+    info!("Diagnosing target {} ({})", target.id, target.address);
+
+    let (tx, mut rx) = mpsc::channel(16);
+    let run = tokio::spawn(async move { diagnose(&target, options, &tx).await });
+
+    let stream = stream! {
+        while let Some(event) = rx.recv().await {
+            let (name, data) = match event {
+                DiagnosisEvent::Running(step) => ("running", serde_json::json!({ "step": step })),
+                DiagnosisEvent::Step(result) => ("step", serde_json::json!(result)),
+            };
+            yield Ok(Event::default().event(name).data(data.to_string()));
+        }
+        // The sender is dropped when the run ends
+        match run.await {
+            Ok(report) => match serde_json::to_string(&report) {
+                Ok(json) => {
+                    info!("Diagnosis of {}: {}", report.target_id, report.verdict);
+                    yield Ok(Event::default().event("verdict").data(json));
+                }
+                Err(e) => error!("Failed to serialize diagnosis report: {}", e),
+            },
+            Err(e) => error!("Diagnosis task failed: {}", e),
+        }
+    };
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
@@ -1,2 +1,6 @@
 pub mod dto;
+pub(crate) mod filter;
+mod flows;
 pub mod handlers;
+mod query;
+mod tunnel;
@@ -0,0 +1,270 @@
+use super::dto::{DataGap, GapScope};
+use crate::api::ping::query::select_target_data;
+use crate::config::Target;
+use crate::storage::{series_site, StorageBackend, PAUSED_METRIC};
+use chrono::{DateTime, Utc};
+
+const METRICS: [&str; 2] = ["ping_latency", "ping_failed"];
+
+/// Batches a target must miss in a row before the silence is a gap, when no
+/// `min_gap` is given
+const MISSED_BATCHES: i64 = 3;
+
+/// Shortest gap reported by default, so targets pinged every second do not
+/// report every delayed batch
+const MIN_DEFAULT_GAP_SECONDS: i64 = 60;
+
+/// Default `min_gap` of a target: a few of its ping intervals
+pub(super) fn default_min_gap(target: &Target) -> i64 {
+    (target.ping_interval as i64)
+        .saturating_mul(MISSED_BATCHES)
+        .max(MIN_DEFAULT_GAP_SECONDS)
+}
+
+/// Collect the sorted, de-duplicated timestamps of all results (successful or
+/// failed) stored for a target in `[from, to]`.
+pub(super) fn collect_target_timestamps(
+    storage: &dyn StorageBackend,
+    target: &Target,
+    from: i64,
+    to: i64,
+) -> Result<Vec<i64>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut timestamps = Vec::new();
+
+    for metric in METRICS {
+        let points = select_target_data(storage, metric, target, from, to)?;
+        timestamps.extend(points.iter().map(|p| p.timestamp));
+    }
+
+    // Fallback: label set changed (e.g. renamed target), scan by target_id
+    if timestamps.is_empty() {
+        for metric in METRICS {
+            for (labels, points) in storage.select_all(metric, from, to)? {
+                if series_site(&labels).is_none()
+                    && labels
+                        .iter()
+                        .any(|l| l.name == "target_id" && l.value == target.id)
+                {
+                    timestamps.extend(points.iter().map(|p| p.timestamp));
+                }
+            }
+        }
+    }
+
+    timestamps.sort_unstable();
+    timestamps.dedup();
+    Ok(timestamps)
+}
+
+/// Find intervals of `[from, to]` longer than `min_gap` seconds without any
+/// stored result.
+///
+/// Gaps are detected before the first result, between consecutive results
+/// and after the last one (data collection stopped); a range without any
+/// result is one gap.
+pub(super) fn find_gaps(timestamps: &[i64], from: i64, to: i64, min_gap: i64) -> Vec<(i64, i64)> {
+    std::iter::once(from)
+        .chain(timestamps.iter().copied())
+        .chain(std::iter::once(to))
+        .collect::<Vec<_>>()
+        .windows(2)
+        .filter(|pair| pair[1] - pair[0] > min_gap)
+        .map(|pair| (pair[0], pair[1]))
+        .collect()
+}
+
+/// Intervals of `[from, to]` in which the target was paused, from its
+/// recorded pause transitions (sorted by time). A pause still open at the
+/// end of the range lasts until `to`.
+fn paused_intervals(transitions: &[(i64, bool)], from: i64, to: i64) -> Vec<(i64, i64)> {
+    let mut intervals = Vec::new();
+    let mut paused_since = None;
+    for &(timestamp, paused) in transitions {
+        match (paused, paused_since) {
+            (true, None) => paused_since = Some(timestamp),
+            (false, Some(start)) => {
+                intervals.push((start, timestamp));
+                paused_since = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(start) = paused_since {
+        intervals.push((start, to));
+    }
+    intervals
+        .into_iter()
+        .map(|(start, end)| (start.max(from), end.min(to)))
+        .filter(|(start, end)| start < end)
+        .collect()
+}
+
+/// Parts of `gaps` outside the `paused` intervals that are still longer than
+/// `min_gap`
+fn without_paused(gaps: Vec<(i64, i64)>, paused: &[(i64, i64)], min_gap: i64) -> Vec<(i64, i64)> {
+    let mut remaining = Vec::new();
+    for (start, end) in gaps {
+        let mut start = start;
+        for &(pause_start, pause_end) in paused {
+            if pause_end <= start || pause_start >= end {
+                continue;
+            }
+            if pause_start - start > min_gap {
+                remaining.push((start, pause_start));
+            }
+            start = start.max(pause_end);
+        }
+        if end - start > min_gap {
+            remaining.push((start, end));
+        }
+    }
+    remaining
+}
+
+/// Pause transitions (`true` = paused) of a target, oldest first
+fn pause_transitions(
+    storage: &dyn StorageBackend,
+    target: &Target,
+    to: i64,
+) -> Result<Vec<(i64, bool)>, Box<dyn std::error::Error + Send + Sync>> {
+    // A pause may have started long before the range
+    let mut transitions: Vec<(i64, bool)> = storage
+        .select_all(PAUSED_METRIC, 0, to + 1)?
+        .into_iter()
+        .filter(|(labels, _)| {
+            labels
+                .iter()
+                .any(|l| l.name == "target_id" && l.value == target.id)
+        })
+        .flat_map(|(_, points)| points)
+        .map(|p| (p.timestamp, p.value > 0.5))
+        .collect();
+    transitions.sort_by_key(|&(timestamp, _)| timestamp);
+    Ok(transitions)
+}
+
+/// Check whether any target stored results strictly inside `(start, end)`.
+fn any_data_between(
+    storage: &dyn StorageBackend,
+    start: i64,
+    end: i64,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    if end - start < 2 {
+        return Ok(false);
+    }
+    for metric in METRICS {
+        let results = storage.select_all(metric, start + 1, end - 1)?;
+        if results.iter().any(|(_, points)| !points.is_empty()) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Build the gap report for a target, classifying each gap by whether other
+/// targets kept collecting data during it. Time the target was paused is
+/// not a gap.
+pub(super) fn query_target_gaps(
+    storage: &dyn StorageBackend,
+    target: &Target,
+    from: i64,
+    to: i64,
+    min_gap: i64,
+) -> Result<Vec<DataGap>, Box<dyn std::error::Error + Send + Sync>> {
+    let timestamps = collect_target_timestamps(storage, target, from, to)?;
+    let mut paused = paused_intervals(&pause_transitions(storage, target, to)?, from, to);
+    if target.paused && paused.last().is_none_or(|&(_, end)| end < to) {
+        // Paused without a recorded transition: since its last result
+        let since = timestamps.last().copied().unwrap_or(from);
+        paused.push((since, to));
+    }
+
+    let mut gaps = Vec::new();
+    let found = find_gaps(&timestamps, from, to, min_gap);
+    for (start, end) in without_paused(found, &paused, min_gap) {
+        let scope = if any_data_between(storage, start, end)? {
+            GapScope::Target
+        } else {
+            GapScope::AllTargets
+        };
+        gaps.push(DataGap {
+            start: DateTime::from_timestamp(start, 0)
+                .unwrap_or_else(Utc::now)
+                .to_rfc3339(),
+            start_unix: start,
+            end: DateTime::from_timestamp(end, 0)
+                .unwrap_or_else(Utc::now)
+                .to_rfc3339(),
+            end_unix: end,
+            duration_seconds: end - start,
+            scope,
+        });
+    }
+
+    Ok(gaps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_gaps() {
+        let timestamps = vec![0, 10, 20, 400, 410];
+        let gaps = find_gaps(&timestamps, 0, 420, 300);
+        assert_eq!(gaps, vec![(20, 400)]);
+    }
+
+    #[test]
+    fn test_find_gaps_leading_and_trailing() {
+        // Collection started at t=500 and stopped after t=510
+        let timestamps = vec![500, 510];
+        let gaps = find_gaps(&timestamps, 0, 1000, 300);
+        assert_eq!(gaps, vec![(0, 500), (510, 1000)]);
+    }
+
+    #[test]
+    fn test_find_gaps_exact_threshold() {
+        // A gap equal to min_gap is not reported
+        let timestamps = vec![0, 300];
+        assert!(find_gaps(&timestamps, 0, 300, 300).is_empty());
+    }
+
+    #[test]
+    fn test_find_gaps_no_data() {
+        assert_eq!(find_gaps(&[], 0, 1000, 300), vec![(0, 1000)]);
+        assert!(find_gaps(&[], 0, 200, 300).is_empty());
+    }
+
+    #[test]
+    fn test_gaps_skip_paused_intervals() {
+        // Paused 100-400, resumed, paused again at 800 until now
+        let transitions = [(100, true), (400, false), (800, true)];
+        let paused = paused_intervals(&transitions, 0, 1000);
+        assert_eq!(paused, vec![(100, 400), (800, 1000)]);
+
+        let gaps = vec![(0, 500), (600, 1000)];
+        // 0-100 and 400-500 are too short, 600-800 remains
+        assert_eq!(
+            without_paused(gaps, &paused, 50),
+            vec![(0, 100), (400, 500), (600, 800)]
+        );
+        assert_eq!(
+            without_paused(vec![(0, 500), (600, 1000)], &paused, 150),
+            vec![(600, 800)]
+        );
+    }
+
+    #[test]
+    fn test_default_min_gap() {
+        let mut target = Target {
+            id: "t1".to_string(),
+            address: "10.0.0.1".to_string(),
+            ping_count: 1,
+            ..Default::default()
+        };
+        assert_eq!(default_min_gap(&target), MIN_DEFAULT_GAP_SECONDS);
+        target.ping_interval = 600;
+        assert_eq!(default_min_gap(&target), 1800);
+    }
+}
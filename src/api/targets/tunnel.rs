@@ -0,0 +1,180 @@
+//! Tunnel overhead of VPN tunnel targets.
+//!
+//! A tunnel target is pinged through a VPN tunnel and names a target pinged
+//! outside of it (usually the VPN server's public address) as its
+//! `tunnel_reference`. Subtracting the reference's latency and loss from the
+//! tunnel's, bucket by bucket, separates what the tunnel adds from problems
+//! of the underlying connection: if both degrade, the underlay is at fault;
+//! if only the tunnel does, the tunnel is.
+
+use super::dto::TunnelOverheadPoint;
+use crate::api::ping::dto::BucketDataPoint;
+use crate::api::ping::query::query_ping_aggregated_with_rollups;
+use crate::config::Target;
+use crate::downsample::Coverage;
+use crate::storage::StorageBackend;
+use std::collections::BTreeMap;
+
+/// Overhead series with the mean latency delta and the differential loss
+/// over the whole range
+pub(super) type TunnelOverhead = (Vec<TunnelOverheadPoint>, Option<f64>, Option<f64>);
+
+fn loss_percent(failed: usize, successful: usize) -> Option<f64> {
+    let total = failed + successful;
+    (total > 0).then(|| failed as f64 / total as f64 * 100.0)
+}
+
+fn difference(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    Some(a? - b?)
+}
+
+/// Pair the tunnel's and the reference's buckets by start time
+pub(super) fn tunnel_overhead(
+    tunnel: &[BucketDataPoint],
+    reference: &[BucketDataPoint],
+) -> TunnelOverhead {
+    let mut paired: BTreeMap<i64, (Option<&BucketDataPoint>, Option<&BucketDataPoint>)> =
+        BTreeMap::new();
+    for bucket in tunnel {
+        paired.entry(bucket.timestamp_unix).or_default().0 = Some(bucket);
+    }
+    for bucket in reference {
+        paired.entry(bucket.timestamp_unix).or_default().1 = Some(bucket);
+    }
+
+    let mut points = Vec::with_capacity(paired.len());
+    let mut deltas = Vec::new();
+    let (mut tunnel_counts, mut reference_counts) = ((0, 0), (0, 0));
+    for (start, (tunnel, reference)) in paired {
+        let timestamp = tunnel
+            .or(reference)
+            .map(|b| b.timestamp.clone())
+            .unwrap_or_default();
+        let tunnel_avg_ms = tunnel.and_then(|b| b.avg);
+        let reference_avg_ms = reference.and_then(|b| b.avg);
+        let tunnel_loss_percent =
+            tunnel.and_then(|b| loss_percent(b.failed_count, b.successful_count));
+        let reference_loss_percent =
+            reference.and_then(|b| loss_percent(b.failed_count, b.successful_count));
+
+        let delta_latency_ms = difference(tunnel_avg_ms, reference_avg_ms);
+        let differential_loss_percent = difference(tunnel_loss_percent, reference_loss_percent);
+        deltas.extend(delta_latency_ms);
+        // Loss over the range only counts buckets measured on both sides
+        if let (Some(tunnel), Some(reference)) = (tunnel, reference) {
+            tunnel_counts.0 += tunnel.failed_count;
+            tunnel_counts.1 += tunnel.successful_count;
+            reference_counts.0 += reference.failed_count;
+            reference_counts.1 += reference.successful_count;
+        }
+
+        points.push(TunnelOverheadPoint {
+            timestamp,
+            timestamp_unix: start,
+            tunnel_avg_ms,
+            reference_avg_ms,
+            delta_latency_ms,
+            tunnel_loss_percent,
+            reference_loss_percent,
+            differential_loss_percent,
+        });
+    }
+
+    let avg_delta_latency_ms =
+        (!deltas.is_empty()).then(|| deltas.iter().sum::<f64>() / deltas.len() as f64);
+    let differential_loss_percent = difference(
+        loss_percent(tunnel_counts.0, tunnel_counts.1),
+        loss_percent(reference_counts.0, reference_counts.1),
+    );
+    (points, avg_delta_latency_ms, differential_loss_percent)
+}
+
+/// Query both targets in `[from, to]` and compute the tunnel overhead
+pub(super) fn query_tunnel_overhead(
+    storage: &dyn StorageBackend,
+    coverage: &Coverage,
+    tunnel: &Target,
+    reference: &Target,
+    from: i64,
+    to: i64,
+    bucket_duration_seconds: i64,
+) -> Result<TunnelOverhead, Box<dyn std::error::Error + Send + Sync>> {
+    let query = |target: &Target| {
+        query_ping_aggregated_with_rollups(
+            storage,
+            coverage,
+            Some(&target.address),
+            Some(target),
+            from,
+            to,
+            bucket_duration_seconds,
+            false,
+        )
+        .map(|(buckets, _, _)| buckets)
+    };
+    let tunnel_buckets = query(tunnel)?;
+    let reference_buckets = query(reference)?;
+    Ok(tunnel_overhead(&tunnel_buckets, &reference_buckets))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bucket(start: i64, avg: Option<f64>, successful: usize, failed: usize) -> BucketDataPoint {
+        BucketDataPoint {
+            timestamp: start.to_string(),
+            timestamp_unix: start,
+            timestamp_end_unix: start + 300,
+            target: String::new(),
+            target_name: None,
+            min: avg,
+            max: avg,
+            avg,
+            percentiles: None,
+            count: successful + failed,
+            successful_count: successful,
+            failed_count: failed,
+            resolution: None,
+            complete: true,
+            loss_percent: None,
+        }
+    }
+
+    #[test]
+    fn test_tunnel_overhead() {
+        let tunnel = vec![
+            bucket(0, Some(30.0), 9, 1),
+            bucket(300, Some(50.0), 5, 5),
+            // No reference data
+            bucket(600, Some(40.0), 10, 0),
+        ];
+        let reference = vec![bucket(0, Some(20.0), 10, 0), bucket(300, Some(20.0), 10, 0)];
+
+        let (points, avg_delta, differential_loss) = tunnel_overhead(&tunnel, &reference);
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[0].delta_latency_ms, Some(10.0));
+        assert_eq!(points[0].differential_loss_percent, Some(10.0));
+        assert_eq!(points[1].delta_latency_ms, Some(30.0));
+        assert_eq!(points[1].differential_loss_percent, Some(50.0));
+        assert_eq!(points[2].tunnel_avg_ms, Some(40.0));
+        assert_eq!(points[2].delta_latency_ms, None);
+
+        assert_eq!(avg_delta, Some(20.0));
+        // 6 of 20 pings lost through the tunnel, none outside
+        assert_eq!(differential_loss, Some(30.0));
+    }
+
+    #[test]
+    fn test_tunnel_overhead_fully_lost_bucket() {
+        // The tunnel is down while the underlay works
+        let tunnel = vec![bucket(0, None, 0, 10)];
+        let reference = vec![bucket(0, Some(20.0), 10, 0)];
+
+        let (points, avg_delta, differential_loss) = tunnel_overhead(&tunnel, &reference);
+        assert_eq!(points[0].delta_latency_ms, None);
+        assert_eq!(points[0].differential_loss_percent, Some(100.0));
+        assert_eq!(avg_delta, None);
+        assert_eq!(differential_loss, Some(100.0));
+    }
+}
@@ -0,0 +1,54 @@
+use crate::api::ping::dto::{deserialize_time_range, TimeRangeValue};
+use crate::config::SocketType;
+use crate::traceroute::{StoredTraceroute, TracerouteHop};
+use serde::{Deserialize, Serialize};
+
+/// Request body for POST /api/traceroute
+#[derive(Debug, Deserialize)]
+pub struct TracerouteRequest {
+    /// IP address or hostname to trace
+    pub address: String,
+    /// Highest TTL probed (default: 30, max: 64)
+    #[serde(default)]
+    pub max_hops: Option<u8>,
+    /// Probes per TTL (default: 3, max: 5)
+    #[serde(default)]
+    pub probes_per_hop: Option<u8>,
+    /// Timeout per probe in milliseconds (default: 1000, max: 5000)
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    /// ICMP socket type (default: `[ping] socket_type`)
+    #[serde(default)]
+    pub socket_type: Option<SocketType>,
+}
+
+/// Response for POST /api/traceroute
+#[derive(Debug, Serialize)]
+pub struct TracerouteResponse {
+    pub address: String,
+    pub resolved_ip: String,
+    /// Whether the destination answered
+    pub reached: bool,
+    pub hops: Vec<TracerouteHop>,
+}
+
+/// Query parameters for GET /api/traceroute/history
+#[derive(Debug, Deserialize)]
+pub struct TracerouteHistoryQuery {
+    pub target_id: String,
+    /// Start timestamp (Unix timestamp in seconds) or relative time range (e.g., "24h", "7d").
+    /// Default: "7d"
+    #[serde(default, deserialize_with = "deserialize_time_range")]
+    pub from: Option<TimeRangeValue>,
+    /// End timestamp (Unix timestamp in seconds, optional)
+    #[serde(default)]
+    pub to: Option<i64>,
+}
+
+/// Response for GET /api/traceroute/history
+#[derive(Debug, Serialize)]
+pub struct TracerouteHistoryResponse {
+    pub target_id: String,
+    /// Stored scheduled runs, oldest first
+    pub runs: Vec<StoredTraceroute>,
+}
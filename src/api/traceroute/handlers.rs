@@ -0,0 +1,140 @@
+use super::dto::{
+    TracerouteHistoryQuery, TracerouteHistoryResponse, TracerouteRequest, TracerouteResponse,
+};
+use crate::api::error::{ApiError, ErrorCode};
+use crate::api::extract::{Json, Query};
+use crate::api::ping::dto::TimeRangeValue;
+use crate::api::ping::query::resolve_time_range_value;
+use crate::api::AppState;
+use crate::resolver::HostResolver;
+use crate::traceroute::{query_traceroutes, traceroute, TracerouteOptions};
+use axum::extract::State;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info};
+
+/// Maximum TTL of an on-demand run
+const MAX_HOPS: u8 = 64;
+/// Maximum probes per TTL of an on-demand run
+const MAX_PROBES_PER_HOP: u8 = 5;
+/// Maximum per-probe timeout of an on-demand run
+const MAX_TIMEOUT_MS: u64 = 5000;
+
+/// Reject `value` outside `1..=max` with a field-specific error
+fn check_range(field: &str, value: u64, max: u64) -> Result<(), ApiError> {
+    if value == 0 || value > max {
+        return Err(ApiError::bad_request(
+            ErrorCode::InvalidRequest,
+            format!("{} must be between 1 and {}", field, max),
+        )
+        .with_details(serde_json::json!({ "field": field })));
+    }
+    Ok(())
+}
+
+/// HTTP handler for POST /api/traceroute
+///
+/// Traces the path to an address right away. The result is returned only;
+/// scheduled runs (`[traceroute]`) are the ones that get stored.
+pub(crate) async fn run_traceroute(
+    State(state): State<AppState>,
+    Json(request): Json<TracerouteRequest>,
+) -> Result<Json<TracerouteResponse>, ApiError> {
+    let address = request.address.trim().to_string();
+    if address.is_empty() {
+        return Err(
+            ApiError::bad_request(ErrorCode::InvalidRequest, "Address is required")
+                .with_details(serde_json::json!({ "field": "address" })),
+        );
+    }
+
+    let (settings, default_socket_type) = {
+        let config = state.config.read().map_err(|e| {
+            error!("Failed to read config: {}", e);
+            ApiError::internal(ErrorCode::ConfigUnavailable, "Failed to read configuration")
+        })?;
+        (config.traceroute.clone(), config.ping.socket_type)
+    };
+    let max_hops = request.max_hops.unwrap_or(settings.max_hops);
+    let probes_per_hop = request.probes_per_hop.unwrap_or(settings.probes_per_hop);
+    let timeout_ms = request.timeout_ms.unwrap_or(settings.timeout_ms);
+    check_range("max_hops", max_hops as u64, MAX_HOPS as u64)?;
+    check_range(
+        "probes_per_hop",
+        probes_per_hop as u64,
+        MAX_PROBES_PER_HOP as u64,
+    )?;
+    check_range("timeout_ms", timeout_ms, MAX_TIMEOUT_MS)?;
+
+    let ip = HostResolver::new(&address, 0)
+        .resolve()
+        .await
+        .map_err(|e| {
+            ApiError::bad_request(ErrorCode::InvalidRequest, e)
+                .with_details(serde_json::json!({ "field": "address" }))
+        })?;
+
+    info!("Running traceroute to {} ({})", address, ip);
+    let options = TracerouteOptions {
+        max_hops,
+        probes_per_hop,
+        timeout: Duration::from_millis(timeout_ms),
+        socket_type: request.socket_type.unwrap_or(default_socket_type),
+    };
+    let hops = traceroute(ip, options).await.map_err(|e| {
+        error!("Traceroute to {} failed: {}", address, e);
+        ApiError::internal(ErrorCode::Internal, format!("Traceroute failed: {}", e))
+    })?;
+
+    let resolved_ip = ip.to_string();
+    let reached = hops
+        .last()
+        .is_some_and(|h| h.address.as_deref() == Some(resolved_ip.as_str()));
+    Ok(Json(TracerouteResponse {
+        address,
+        resolved_ip,
+        reached,
+        hops,
+    }))
+}
+
+/// HTTP handler for GET /api/traceroute/history
+///
+/// Lists the stored scheduled runs of a target, flagging runs whose path
+/// differs from the run before.
+pub(crate) async fn get_traceroute_history(
+    State(state): State<AppState>,
+    Query(query): Query<TracerouteHistoryQuery>,
+) -> Result<Json<TracerouteHistoryResponse>, ApiError> {
+    info!("Querying traceroute history: {:?}", query);
+
+    let from_value = query
+        .from
+        .clone()
+        .unwrap_or_else(|| TimeRangeValue::Relative("7d".to_string()));
+    let from = resolve_time_range_value(&from_value).map_err(|e| {
+        error!("Invalid time range: {}", e);
+        ApiError::bad_request(ErrorCode::InvalidTimeRange, e)
+    })?;
+    let to = query.to.unwrap_or_else(|| chrono::Utc::now().timestamp());
+
+    let storage = Arc::clone(&state.storage);
+    let target_id = query.target_id.clone();
+    let runs = tokio::task::spawn_blocking(move || {
+        query_traceroutes(&*storage, &target_id, from, to).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| {
+        error!("Task join error: {}", e);
+        ApiError::internal(ErrorCode::Internal, e.to_string())
+    })?
+    .map_err(|e| {
+        error!("Error querying traceroutes: {}", e);
+        ApiError::internal(ErrorCode::StorageError, e)
+    })?;
+
+    Ok(Json(TracerouteHistoryResponse {
+        target_id: query.target_id,
+        runs,
+    }))
+}
@@ -0,0 +1,155 @@
+//! API versioning.
+//!
+//! API routes are served under `/api/v<N>/...`. The version prefix is
+//! removed before routing, so every version shares the route table, and the
+//! version served is echoed in the `API-Version` response header. The
+//! unversioned `/api/...` paths stay available as a compatibility shim for
+//! existing integrations: they are served as the version requested with the
+//! `API-Version` header (default: the current one) and marked deprecated via
+//! `Deprecation` and a `Link` to the versioned path.
+
+use crate::api::error::{ApiError, ErrorCode};
+use axum::body::Body;
+use axum::http::{header, HeaderName, HeaderValue, Request, Uri};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+/// Newest API version
+pub const CURRENT_API_VERSION: u32 = 1;
+
+/// Versions that are still served
+const SUPPORTED_API_VERSIONS: &[u32] = &[1];
+
+/// Request and response header naming the API version
+const API_VERSION_HEADER: HeaderName = HeaderName::from_static("api-version");
+
+/// Split `/api/v<N>/rest` into the version and the unversioned path
+/// `/api/rest`. None for paths without a version prefix.
+fn split_version(path: &str) -> Option<(&str, String)> {
+    let rest = path.strip_prefix("/api/v")?;
+    let (version, rest) = rest.split_once('/').unwrap_or((rest, ""));
+    if version.is_empty() || !version.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some((version, format!("/api/{}", rest)))
+}
+
+/// Parse a version as given in the path or the `API-Version` header
+/// ("1" or "v1"); None if it is not served
+fn parse_version(value: &str) -> Option<u32> {
+    let value = value.trim();
+    let number = value.strip_prefix(['v', 'V']).unwrap_or(value);
+    number
+        .parse()
+        .ok()
+        .filter(|v| SUPPORTED_API_VERSIONS.contains(v))
+}
+
+fn unsupported_version(version: &str) -> Response {
+    ApiError::bad_request(
+        ErrorCode::UnsupportedApiVersion,
+        format!("API version '{}' is not supported", version),
+    )
+    .with_details(serde_json::json!({ "supported": SUPPORTED_API_VERSIONS }))
+    .into_response()
+}
+
+/// Replace the path of a URI, keeping its query string
+fn with_path(uri: &Uri, path: &str) -> Option<Uri> {
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{}?{}", path, query),
+        None => path.to_string(),
+    };
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(path_and_query.parse().ok()?);
+    Uri::from_parts(parts).ok()
+}
+
+/// Map versioned API paths onto the route table before routing, and mark
+/// unversioned ones deprecated
+pub(crate) async fn api_version_middleware(mut req: Request<Body>, next: Next) -> Response {
+    let path = req.uri().path().to_string();
+    if !path.starts_with("/api/") {
+        return next.run(req).await;
+    }
+
+    let (version, successor) = match split_version(&path) {
+        Some((version, unversioned)) => {
+            let Some(parsed) = parse_version(version) else {
+                return unsupported_version(version);
+            };
+            let Some(uri) = with_path(req.uri(), &unversioned) else {
+                return unsupported_version(version);
+            };
+            *req.uri_mut() = uri;
+            (parsed, None)
+        }
+        None => {
+            let requested = req
+                .headers()
+                .get(&API_VERSION_HEADER)
+                .and_then(|h| h.to_str().ok())
+                .map(|v| (v.to_string(), parse_version(v)));
+            let version = match requested {
+                Some((_, Some(version))) => version,
+                Some((value, None)) => return unsupported_version(&value),
+                None => CURRENT_API_VERSION,
+            };
+            let successor = path.replacen("/api/", &format!("/api/v{}/", version), 1);
+            (version, Some(successor))
+        }
+    };
+
+    let mut response = next.run(req).await;
+
+    let headers = response.headers_mut();
+    headers.insert(API_VERSION_HEADER.clone(), HeaderValue::from(version));
+    if let Some(successor) = successor {
+        headers.insert("deprecation", HeaderValue::from_static("true"));
+        if let Ok(link) =
+            HeaderValue::from_str(&format!("<{}>; rel=\"successor-version\"", successor))
+        {
+            headers.insert(header::LINK, link);
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_version() {
+        assert_eq!(
+            split_version("/api/v1/ping/data"),
+            Some(("1", "/api/ping/data".to_string()))
+        );
+        assert_eq!(
+            split_version("/api/v12/targets/x/gaps"),
+            Some(("12", "/api/targets/x/gaps".to_string()))
+        );
+        assert_eq!(split_version("/api/v1"), Some(("1", "/api/".to_string())));
+        // Unversioned
+        assert_eq!(split_version("/api/ping/data"), None);
+        assert_eq!(split_version("/api/vendors"), None);
+        assert_eq!(split_version("/metrics"), None);
+    }
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(parse_version("1"), Some(1));
+        assert_eq!(parse_version(" v1 "), Some(1));
+        assert_eq!(parse_version("2"), None);
+        assert_eq!(parse_version("latest"), None);
+    }
+
+    #[test]
+    fn test_with_path_keeps_query() {
+        let uri: Uri = "/api/v1/ping/data?target=router&from=24h".parse().unwrap();
+        assert_eq!(
+            with_path(&uri, "/api/ping/data").unwrap(),
+            "/api/ping/data?target=router&from=24h"
+        );
+    }
+}
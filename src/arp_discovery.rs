@@ -0,0 +1,238 @@
+//! Neighbor table (ARP/NDP) discovery.
+//!
+//! Finds devices that expose no open ports and no mDNS services but still
+//! answer ARP, and reports their MAC addresses. When a range is given, an
+//! empty UDP datagram is sent to every address in it first, which makes the
+//! kernel resolve each address; the neighbor table is read once those
+//! resolutions have had time to complete.
+
+use crate::discovery::{DiscoveredDevice, DiscoveryEvent};
+use crate::ip_scan::{parse_range_spec, IpRangeSpec};
+use crate::presence::{read_neighbors, Neighbor};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tracing::{debug, error, info};
+
+/// Destination port of the datagrams that trigger address resolution
+/// (discard service, so nothing answers even if the host listens)
+const SWEEP_PORT: u16 = 9;
+
+/// Time given to the kernel to resolve the swept addresses
+const RESOLVE_WAIT: Duration = Duration::from_secs(3);
+
+/// Link-layer addresses that never belong to a device
+fn is_placeholder_mac(mac: &str) -> bool {
+    mac == "00:00:00:00:00:00" || mac == "ff:ff:ff:ff:ff:ff"
+}
+
+/// Build one device per MAC address from neighbor table entries.
+///
+/// Only addresses within `range` are kept when a range is given. The
+/// primary address is the first IPv4 address, or the first IPv6 address if
+/// the device has none.
+fn neighbor_devices(
+    neighbors: &[Neighbor],
+    range: Option<(Ipv4Addr, Ipv4Addr)>,
+) -> Vec<DiscoveredDevice> {
+    let mut by_mac: Vec<(String, Vec<IpAddr>)> = Vec::new();
+    let mut index: HashMap<&str, usize> = HashMap::new();
+
+    for neighbor in neighbors {
+        if is_placeholder_mac(&neighbor.mac) {
+            continue;
+        }
+        let Ok(ip) = neighbor.address.parse::<IpAddr>() else {
+            continue;
+        };
+        if ip.is_multicast() {
+            continue;
+        }
+        if let Some((start, end)) = range {
+            match ip {
+                IpAddr::V4(v4) if (start..=end).contains(&v4) => {}
+                _ => continue,
+            }
+        }
+
+        let i = *index.entry(neighbor.mac.as_str()).or_insert_with(|| {
+            by_mac.push((neighbor.mac.clone(), Vec::new()));
+            by_mac.len() - 1
+        });
+        if !by_mac[i].1.contains(&ip) {
+            by_mac[i].1.push(ip);
+        }
+    }
+
+    by_mac
+        .into_iter()
+        .filter_map(|(mac, ips)| {
+            let primary = ips.iter().find(|ip| ip.is_ipv4()).or(ips.first())?;
+            let address = primary.to_string();
+            Some(DiscoveredDevice {
+                name: address.clone(),
+                address: address.clone(),
+                addresses: ips.iter().map(|ip| ip.to_string()).collect(),
+                hostname: address,
+                services: vec![],
+                txt_properties: HashMap::new(),
+                ttl: None,
+                discovery_method: "arp".to_string(),
+                vendor_info: None,
+                open_ports: Vec::new(),
+                gateway: None,
+                mac: Some(mac),
+            })
+        })
+        .collect()
+}
+
+/// Send an empty datagram to every address in the range so the kernel
+/// resolves it. Returns early once the client has disconnected.
+async fn sweep(
+    tx: &mpsc::Sender<DiscoveryEvent>,
+    start: Ipv4Addr,
+    end: Ipv4Addr,
+) -> std::io::Result<()> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    for ip in u32::from(start)..=u32::from(end) {
+        if tx.is_closed() {
+            break;
+        }
+        // Unreachable addresses fail once resolution does; that is expected
+        if let Err(e) = socket.send_to(&[], (Ipv4Addr::from(ip), SWEEP_PORT)).await {
+            debug!("ARP sweep to {} failed: {}", Ipv4Addr::from(ip), e);
+        }
+    }
+    Ok(())
+}
+
+/// Run neighbor table discovery and send discovered devices to the channel
+pub async fn run_arp_discovery(tx: mpsc::Sender<DiscoveryEvent>, range: Option<IpRangeSpec>) {
+    info!("Starting ARP discovery");
+
+    if tx
+        .send(DiscoveryEvent::Started {
+            message: "Reading neighbor table...".to_string(),
+        })
+        .await
+        .is_err()
+    {
+        return;
+    }
+
+    let range = match range.as_ref().map(parse_range_spec).transpose() {
+        Ok(range) => range,
+        Err(e) => {
+            error!("Failed to parse ARP discovery range: {}", e);
+            let _ = tx
+                .send(DiscoveryEvent::Error {
+                    message: format!("Invalid IP range: {}", e),
+                })
+                .await;
+            return;
+        }
+    };
+
+    if let Some((start, end)) = range {
+        if let Err(e) = sweep(&tx, start, end).await {
+            // The table may still hold entries, so carry on without the sweep
+            error!("ARP sweep failed: {}", e);
+        }
+        tokio::time::sleep(RESOLVE_WAIT).await;
+    }
+
+    let neighbors = match tokio::task::spawn_blocking(read_neighbors).await {
+        Ok(Ok(neighbors)) => neighbors,
+        Ok(Err(e)) => {
+            error!("Failed to read neighbor table: {}", e);
+            let _ = tx
+                .send(DiscoveryEvent::Error {
+                    message: format!("Failed to read neighbor table: {}", e),
+                })
+                .await;
+            return;
+        }
+        Err(e) => {
+            error!("Neighbor table task failed: {}", e);
+            let _ = tx
+                .send(DiscoveryEvent::Error {
+                    message: "Failed to read neighbor table".to_string(),
+                })
+                .await;
+            return;
+        }
+    };
+
+    let devices = neighbor_devices(&neighbors, range);
+    let device_count = devices.len();
+    for device in devices {
+        if tx
+            .send(DiscoveryEvent::DeviceFound { device })
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
+
+    info!("ARP discovery completed, found {} devices", device_count);
+    let _ = tx
+        .send(DiscoveryEvent::Completed {
+            message: format!("Neighbor table read. Found {} devices.", device_count),
+            device_count,
+        })
+        .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn neighbor(address: &str, mac: &str) -> Neighbor {
+        Neighbor {
+            address: address.to_string(),
+            mac: mac.to_string(),
+            interface: "eth0".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_neighbor_devices_groups_by_mac() {
+        let neighbors = vec![
+            neighbor("fe80::1", "aa:bb:cc:dd:ee:01"),
+            neighbor("192.168.1.1", "aa:bb:cc:dd:ee:01"),
+            neighbor("192.168.1.20", "aa:bb:cc:dd:ee:02"),
+            neighbor("192.168.1.255", "ff:ff:ff:ff:ff:ff"),
+            neighbor("224.0.0.251", "01:00:5e:00:00:fb"),
+        ];
+
+        let devices = neighbor_devices(&neighbors, None);
+        assert_eq!(devices.len(), 2);
+        // IPv4 is preferred as the primary address
+        assert_eq!(devices[0].address, "192.168.1.1");
+        assert_eq!(devices[0].addresses, vec!["fe80::1", "192.168.1.1"]);
+        assert_eq!(devices[0].mac.as_deref(), Some("aa:bb:cc:dd:ee:01"));
+        assert_eq!(devices[0].discovery_method, "arp");
+        assert_eq!(devices[1].address, "192.168.1.20");
+    }
+
+    #[test]
+    fn test_neighbor_devices_within_range() {
+        let neighbors = vec![
+            neighbor("192.168.1.1", "aa:bb:cc:dd:ee:01"),
+            neighbor("10.0.0.5", "aa:bb:cc:dd:ee:02"),
+            neighbor("fe80::2", "aa:bb:cc:dd:ee:02"),
+        ];
+        let range = parse_range_spec(&IpRangeSpec::Cidr {
+            cidr: "192.168.1.0/24".to_string(),
+        })
+        .unwrap();
+
+        let devices = neighbor_devices(&neighbors, Some(range));
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].address, "192.168.1.1");
+    }
+}
@@ -0,0 +1,242 @@
+//! Audit log of changes made through the API, persisted as
+//! `<data path>/audit.jsonl` (one JSON entry per line, oldest first) so
+//! shared installations can see who changed what.
+//!
+//! Entries are recorded by `audit_middleware` for target and token
+//! management, preference updates and storage operations. Since targets and
+//! tokens live in the config file, each entry carries the lines the request
+//! changed in it, with secrets redacted.
+
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// File (inside the data path) holding the audit log
+const AUDIT_FILE: &str = "audit.jsonl";
+
+/// Longest config diff stored per entry, in lines
+const MAX_DIFF_LINES: usize = 200;
+
+/// Above this many line pairs, changed regions are listed as a whole instead
+/// of being diffed line by line
+const MAX_DIFF_CELLS: usize = 1_000_000;
+
+/// Config keys whose values are never written to the audit log
+const SECRET_KEYS: [&str; 4] = ["password", "token", "hash", "secret"];
+
+/// One audited request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Unix timestamp in seconds the request completed at
+    pub timestamp: i64,
+    pub method: String,
+    /// Request path with query string
+    pub path: String,
+    /// HTTP status of the response
+    pub status: u16,
+    /// Logged-in user who sent the request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// Name of the API token that sent the request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_name: Option<String>,
+    /// Client IP address
+    pub client: String,
+    /// Changed config file lines, prefixed with "-" (removed) or "+" (added)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub config_diff: Vec<String>,
+}
+
+/// Append-only audit log in the data path
+#[derive(Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl AuditLog {
+    pub fn new(data_path: &Path) -> Self {
+        Self {
+            path: data_path.join(AUDIT_FILE),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Append an entry
+    pub fn record(&self, entry: &AuditEntry) -> std::io::Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(line.as_bytes())
+    }
+
+    /// The newest `limit` entries at or after `since`, newest first.
+    /// Unreadable lines are skipped.
+    pub fn entries(&self, since: i64, limit: usize) -> std::io::Result<Vec<AuditEntry>> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let file = match fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let mut entries: Vec<AuditEntry> = BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str::<AuditEntry>(&line).ok())
+            .filter(|entry| entry.timestamp >= since)
+            .collect();
+        entries.reverse();
+        entries.truncate(limit);
+        Ok(entries)
+    }
+}
+
+/// Line diff of two config file versions with secret values redacted
+pub fn config_diff(before: &str, after: &str) -> Vec<String> {
+    let before: Vec<String> = before.lines().map(redact_line).collect();
+    let after: Vec<String> = after.lines().map(redact_line).collect();
+
+    // Only the region between the common prefix and suffix is diffed
+    let prefix = before
+        .iter()
+        .zip(&after)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = before[prefix..]
+        .iter()
+        .rev()
+        .zip(after[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let removed = &before[prefix..before.len() - suffix];
+    let added = &after[prefix..after.len() - suffix];
+
+    let mut diff = if removed.len() * added.len() > MAX_DIFF_CELLS {
+        removed
+            .iter()
+            .map(|line| format!("-{}", line))
+            .chain(added.iter().map(|line| format!("+{}", line)))
+            .collect()
+    } else {
+        lcs_diff(removed, added)
+    };
+    if diff.len() > MAX_DIFF_LINES {
+        let omitted = diff.len() - MAX_DIFF_LINES;
+        diff.truncate(MAX_DIFF_LINES);
+        diff.push(format!("... {} more lines", omitted));
+    }
+    diff
+}
+
+/// Removed and added lines along a longest common subsequence
+fn lcs_diff(a: &[String], b: &[String]) -> Vec<String> {
+    // lengths[i][j]: LCS length of a[i..] and b[j..]
+    let mut lengths = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lengths[i][j] = if a[i] == b[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            i += 1;
+            j += 1;
+        } else if j == b.len() || (i < a.len() && lengths[i + 1][j] >= lengths[i][j + 1]) {
+            diff.push(format!("-{}", a[i]));
+            i += 1;
+        } else {
+            diff.push(format!("+{}", b[j]));
+            j += 1;
+        }
+    }
+    diff
+}
+
+/// Replace the value of `key = value` lines whose key names a secret
+fn redact_line(line: &str) -> String {
+    let Some((key, _)) = line.split_once('=') else {
+        return line.to_string();
+    };
+    let name = key.trim().to_lowercase();
+    if SECRET_KEYS.iter().any(|secret| name.contains(secret)) {
+        format!("{}= \"<redacted>\"", key)
+    } else {
+        line.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_diff() {
+        let before = "[server]\nport = 8080\n\n[[targets]]\nid = \"a\"\naddress = \"10.0.0.1\"\n";
+        let after = "[server]\nport = 8080\n\n[[targets]]\nid = \"a\"\naddress = \"10.0.0.2\"\n\n[[targets]]\nid = \"b\"\n";
+        assert_eq!(
+            config_diff(before, after),
+            vec![
+                "-address = \"10.0.0.1\"",
+                "+address = \"10.0.0.2\"",
+                "+",
+                "+[[targets]]",
+                "+id = \"b\"",
+            ]
+        );
+        assert!(config_diff(before, before).is_empty());
+    }
+
+    #[test]
+    fn test_config_diff_redacts_secrets() {
+        let before = "[auth]\nenabled = true\n";
+        let after = "[auth]\nenabled = true\n\n[[auth.tokens]]\nname = \"ci\"\nhash = \"abc123\"\n";
+        let diff = config_diff(before, after);
+        assert!(diff.contains(&"+name = \"ci\"".to_string()));
+        assert!(diff.contains(&"+hash = \"<redacted>\"".to_string()));
+        assert!(!diff.iter().any(|line| line.contains("abc123")));
+    }
+
+    #[test]
+    fn test_record_and_read() {
+        let dir = std::env::temp_dir().join(format!("sparkping-audit-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let log = AuditLog::new(&dir);
+        assert!(log.entries(0, 10).unwrap().is_empty());
+
+        for (timestamp, path) in [(10, "/api/targets"), (20, "/api/targets/a")] {
+            log.record(&AuditEntry {
+                timestamp,
+                method: "POST".to_string(),
+                path: path.to_string(),
+                status: 200,
+                user: Some("admin".to_string()),
+                token_name: None,
+                client: "127.0.0.1".to_string(),
+                config_diff: Vec::new(),
+            })
+            .unwrap();
+        }
+
+        let entries = log.entries(0, 10).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].path, "/api/targets/a");
+        assert_eq!(log.entries(0, 1).unwrap().len(), 1);
+        assert_eq!(log.entries(15, 10).unwrap()[0].timestamp, 20);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
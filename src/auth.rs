@@ -0,0 +1,337 @@
+//! Authentication of API clients.
+//!
+//! With `[auth] enabled`, API requests need `Authorization: Bearer <token>`,
+//! except read-only requests while `public_read` is set. A token is either an
+//! API token of `[[auth.tokens]]` or a session token returned by
+//! POST /api/auth/login for a user of `[[auth.users]]`. The login also sets
+//! the session token as HttpOnly cookie, which is how the web UI, and its
+//! EventSource streams that cannot send headers, authenticate. The config only holds
+//! hashes: Argon2 for passwords, which may be weak, and SHA-256 for API
+//! tokens, which are random and checked on every request. Each user and token
+//! has a [`Role`]; `api::middleware::role_middleware` checks it per route.
+
+use crate::config::{AuthConfig, AuthUser, Role};
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// Prefix of generated tokens, so they are recognizable in logs and secrets
+const TOKEN_PREFIX: &str = "spk_";
+
+/// Hash a password for `[[auth.users]] password_hash`
+pub fn hash_password(password: &str) -> Result<String, String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| format!("Failed to hash password: {}", e))
+}
+
+/// Check a password against an Argon2 hash; false for malformed hashes
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash)
+        .map(|parsed| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &parsed)
+                .is_ok()
+        })
+        .unwrap_or(false)
+}
+
+/// Generate a random token (244 bits of entropy)
+pub fn generate_token() -> String {
+    format!(
+        "{}{}{}",
+        TOKEN_PREFIX,
+        Uuid::new_v4().simple(),
+        Uuid::new_v4().simple()
+    )
+}
+
+/// SHA-256 hash (hex) under which a token is stored
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Who a request was authenticated as
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Principal {
+    /// Logged-in user
+    User(String),
+    /// API token, by name
+    Token(String),
+}
+
+/// An authenticated request's principal and role. Stored as a request
+/// extension by `auth_middleware`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Authenticated {
+    pub principal: Principal,
+    pub role: Role,
+}
+
+#[derive(Debug)]
+struct Session {
+    user: String,
+    expires_at: i64,
+}
+
+/// Login sessions, keyed by token hash. Sessions are kept in memory only and
+/// end when the server restarts.
+#[derive(Debug, Default)]
+pub struct AuthSessions {
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+impl AuthSessions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Session>> {
+        self.sessions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Start a session for `user`; returns the token and its expiry
+    pub fn create(&self, user: &str, now: i64, ttl_seconds: u64) -> (String, i64) {
+        let token = generate_token();
+        let expires_at = now.saturating_add(ttl_seconds as i64);
+        let mut sessions = self.lock();
+        sessions.retain(|_, session| session.expires_at > now);
+        sessions.insert(
+            hash_token(&token),
+            Session {
+                user: user.to_string(),
+                expires_at,
+            },
+        );
+        (token, expires_at)
+    }
+
+    /// User of an unexpired session token
+    pub fn user(&self, token: &str, now: i64) -> Option<String> {
+        self.lock()
+            .get(&hash_token(token))
+            .filter(|session| session.expires_at > now)
+            .map(|session| session.user.clone())
+    }
+
+    /// End a session; false if the token was not a session token
+    pub fn remove(&self, token: &str) -> bool {
+        self.lock().remove(&hash_token(token)).is_some()
+    }
+}
+
+/// Failed logins a client may make before it is locked out
+const MAX_FAILED_LOGINS: u32 = 5;
+/// First lockout; it doubles with every further failure
+const LOGIN_LOCKOUT_SECONDS: i64 = 60;
+/// Longest lockout, and how long failures are remembered
+const MAX_LOGIN_LOCKOUT_SECONDS: i64 = 3600;
+
+#[derive(Debug)]
+struct FailedLogins {
+    count: u32,
+    last_at: i64,
+}
+
+/// Failed logins per client, to slow down password guessing. After
+/// `MAX_FAILED_LOGINS` failures a client is locked out for a minute, twice as
+/// long after every further failure, up to an hour. A successful login or an
+/// hour without failures forgives it.
+#[derive(Debug, Default)]
+pub struct LoginThrottle {
+    failures: Mutex<HashMap<String, FailedLogins>>,
+}
+
+impl LoginThrottle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, FailedLogins>> {
+        self.failures.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Seconds until `client` may try again; None if it may now
+    pub fn retry_after(&self, client: &str, now: i64) -> Option<u64> {
+        let failures = self.lock();
+        let failed = failures.get(client)?;
+        let excess = failed.count.checked_sub(MAX_FAILED_LOGINS)?;
+        let lockout = LOGIN_LOCKOUT_SECONDS
+            .saturating_mul(1 << excess.min(6))
+            .min(MAX_LOGIN_LOCKOUT_SECONDS);
+        let until = failed.last_at.saturating_add(lockout);
+        (until > now).then(|| (until - now) as u64)
+    }
+
+    /// Record a failed login of `client`
+    pub fn failed(&self, client: &str, now: i64) {
+        let mut failures = self.lock();
+        failures.retain(|_, f| f.last_at + MAX_LOGIN_LOCKOUT_SECONDS > now);
+        let failed = failures.entry(client.to_string()).or_insert(FailedLogins {
+            count: 0,
+            last_at: now,
+        });
+        failed.count += 1;
+        failed.last_at = now;
+    }
+
+    /// Forget the failures of `client` after a successful login
+    pub fn succeeded(&self, client: &str) {
+        self.lock().remove(client);
+    }
+}
+
+/// Resolve a bearer token to a principal and its role. Sessions of users that
+/// were removed from the config are no longer accepted, and role changes apply
+/// to running sessions.
+pub fn authenticate(
+    config: &AuthConfig,
+    sessions: &AuthSessions,
+    token: &str,
+    now: i64,
+) -> Option<Authenticated> {
+    if let Some(user) = sessions.user(token, now) {
+        return config
+            .users
+            .iter()
+            .find(|u| u.name == user)
+            .map(|u| Authenticated {
+                principal: Principal::User(user),
+                role: u.role,
+            });
+    }
+
+    // Hashes are compared rather than tokens, so timing reveals nothing
+    // useful about a stored token
+    let hash = hash_token(token);
+    config
+        .tokens
+        .iter()
+        .find(|t| t.hash == hash)
+        .map(|t| Authenticated {
+            principal: Principal::Token(t.name.clone()),
+            role: t.role,
+        })
+}
+
+/// Check a user's password; None for unknown users and wrong passwords
+pub fn login<'a>(config: &'a AuthConfig, name: &str, password: &str) -> Option<&'a AuthUser> {
+    let user = config.users.iter().find(|u| u.name == name)?;
+    verify_password(password, &user.password_hash).then_some(user)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ApiToken;
+
+    fn config() -> AuthConfig {
+        AuthConfig {
+            enabled: true,
+            users: vec![AuthUser {
+                name: "admin".to_string(),
+                password_hash: hash_password("secret").unwrap(),
+                role: Role::Editor,
+            }],
+            tokens: vec![ApiToken {
+                id: "1".to_string(),
+                name: "grafana".to_string(),
+                hash: hash_token("spk_grafana"),
+                created_at: 0,
+                role: Role::Viewer,
+            }],
+            ..AuthConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_password_hash() {
+        let hash = hash_password("secret").unwrap();
+        assert!(hash.starts_with("$argon2"));
+        assert!(verify_password("secret", &hash));
+        assert!(!verify_password("wrong", &hash));
+        assert!(!verify_password("secret", "not a hash"));
+    }
+
+    #[test]
+    fn test_generate_token() {
+        let token = generate_token();
+        assert!(token.starts_with(TOKEN_PREFIX));
+        assert_eq!(token.len(), TOKEN_PREFIX.len() + 64);
+        assert_ne!(token, generate_token());
+    }
+
+    #[test]
+    fn test_authenticate() {
+        let config = config();
+        let sessions = AuthSessions::new();
+
+        assert_eq!(
+            authenticate(&config, &sessions, "spk_grafana", 0),
+            Some(Authenticated {
+                principal: Principal::Token("grafana".to_string()),
+                role: Role::Viewer,
+            })
+        );
+        assert_eq!(authenticate(&config, &sessions, "spk_other", 0), None);
+
+        assert_eq!(login(&config, "admin", "wrong"), None);
+        let user = login(&config, "admin", "secret").unwrap();
+        let (token, expires_at) = sessions.create(&user.name, 1000, 60);
+        assert_eq!(expires_at, 1060);
+        assert_eq!(
+            authenticate(&config, &sessions, &token, 1059),
+            Some(Authenticated {
+                principal: Principal::User("admin".to_string()),
+                role: Role::Editor,
+            })
+        );
+        // Expired
+        assert_eq!(authenticate(&config, &sessions, &token, 1060), None);
+
+        // Removed from the config
+        let (token, _) = sessions.create(&user.name, 1000, 60);
+        let without_users = AuthConfig {
+            users: Vec::new(),
+            ..config.clone()
+        };
+        assert_eq!(authenticate(&without_users, &sessions, &token, 1000), None);
+
+        assert!(sessions.remove(&token));
+        assert_eq!(authenticate(&config, &sessions, &token, 1000), None);
+    }
+
+    #[test]
+    fn test_login_throttle() {
+        let throttle = LoginThrottle::new();
+        for _ in 0..MAX_FAILED_LOGINS - 1 {
+            throttle.failed("10.0.0.1", 1000);
+        }
+        assert_eq!(throttle.retry_after("10.0.0.1", 1000), None);
+        throttle.failed("10.0.0.1", 1000);
+        assert_eq!(throttle.retry_after("10.0.0.1", 1000), Some(60));
+        assert_eq!(throttle.retry_after("10.0.0.1", 1060), None);
+        // Other clients are not affected
+        assert_eq!(throttle.retry_after("10.0.0.2", 1000), None);
+
+        // Every further failure doubles the lockout
+        throttle.failed("10.0.0.1", 1060);
+        assert_eq!(throttle.retry_after("10.0.0.1", 1060), Some(120));
+
+        // Forgotten after an hour without failures
+        throttle.failed("10.0.0.1", 1060 + MAX_LOGIN_LOCKOUT_SECONDS);
+        assert_eq!(
+            throttle.retry_after("10.0.0.1", 1060 + MAX_LOGIN_LOCKOUT_SECONDS),
+            None
+        );
+
+        throttle.succeeded("10.0.0.1");
+        assert_eq!(throttle.lock().len(), 0);
+    }
+}
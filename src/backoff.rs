@@ -0,0 +1,31 @@
+//! Retry delays of the push exporters (`influx_export`, `agent`).
+//!
+//! Failed pushes are retried after exponentially growing delays, so an
+//! endpoint that is down is not hammered while results keep being buffered.
+
+use std::time::Duration;
+
+/// Delay before the first retry; doubled for every further attempt up to
+/// `MAX_BACKOFF`
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Delay before retry number `attempt` (1-based)
+pub fn backoff(attempt: u32) -> Duration {
+    INITIAL_BACKOFF
+        .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(MAX_BACKOFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(1), Duration::from_secs(1));
+        assert_eq!(backoff(3), Duration::from_secs(4));
+        assert_eq!(backoff(10), MAX_BACKOFF);
+        assert_eq!(backoff(100), MAX_BACKOFF);
+    }
+}
@@ -0,0 +1,181 @@
+//! Latency calibration for DGRAM sockets (`[ping] calibrate = true`).
+//!
+//! DGRAM sockets add a small, host-specific overhead compared to RAW
+//! sockets. At startup, loopback round-trip times are measured with the
+//! configured socket type and with a RAW socket; the median difference is
+//! subtracted from every ICMP result so sub-millisecond LAN latencies are
+//! comparable across hosts. Corrected results carry a `latency_corrected`
+//! label and the constant itself is stored as `latency_correction_ms`.
+//!
+//! Measuring the RAW baseline requires elevated privileges; without them the
+//! calibration is skipped and results stay uncorrected.
+
+use crate::config::SocketType;
+use crate::ping::{probe_once, PingResult, Probe, ProbeOptions};
+use serde::Serialize;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::OnceLock;
+use tracing::{info, warn};
+
+/// Loopback probes per socket type
+const SAMPLES: u16 = 20;
+
+/// Result of the startup calibration
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencyCalibration {
+    /// Socket type the correction applies to
+    pub socket_type: SocketType,
+    /// Median loopback RTT with `socket_type` in milliseconds
+    pub baseline_ms: f64,
+    /// Median loopback RTT with a RAW socket in milliseconds
+    pub raw_baseline_ms: f64,
+    /// Subtracted from ICMP latencies (never negative)
+    pub correction_ms: f64,
+    /// Unix timestamp in seconds
+    pub measured_at: i64,
+}
+
+static CALIBRATION: OnceLock<LatencyCalibration> = OnceLock::new();
+
+/// The calibration measured at startup, if any
+pub fn current() -> Option<&'static LatencyCalibration> {
+    CALIBRATION.get()
+}
+
+/// Correction for results of the given socket type. None if no calibration
+/// ran or it was measured for a different socket type (e.g. after a reload).
+pub fn correction_for(socket_type: SocketType) -> Option<f64> {
+    current()
+        .filter(|c| c.socket_type == socket_type)
+        .map(|c| c.correction_ms)
+}
+
+fn median(mut samples: Vec<f64>) -> Option<f64> {
+    if samples.is_empty() {
+        return None;
+    }
+    samples.sort_by(|a, b| a.total_cmp(b));
+    let mid = samples.len() / 2;
+    Some(if samples.len().is_multiple_of(2) {
+        (samples[mid - 1] + samples[mid]) / 2.0
+    } else {
+        samples[mid]
+    })
+}
+
+/// Median loopback RTT for a socket type. Takes up to a few seconds.
+async fn loopback_baseline(socket_type: SocketType) -> Result<f64, String> {
+    let loopback = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    let mut samples = Vec::with_capacity(SAMPLES as usize);
+    for _ in 0..SAMPLES {
+        let rtt = probe_once(
+            loopback,
+            Probe::Icmp(socket_type),
+            ProbeOptions::default(),
+            None,
+        )
+        .await
+        .map_err(|e| format!("{:?} loopback ping failed: {}", socket_type, e))?;
+        samples.push(rtt);
+    }
+    median(samples).ok_or_else(|| "no samples".to_string())
+}
+
+/// Measure the DGRAM overhead and store it for the ping tasks. Call before
+/// starting the ping tasks.
+pub async fn run_calibration(socket_type: SocketType) -> Option<&'static LatencyCalibration> {
+    if socket_type == SocketType::Raw {
+        info!("Latency calibration skipped: RAW sockets need no correction");
+        return None;
+    }
+
+    let measured = match loopback_baseline(socket_type).await {
+        Ok(baseline_ms) => loopback_baseline(SocketType::Raw)
+            .await
+            .map(|raw_baseline_ms| (baseline_ms, raw_baseline_ms)),
+        Err(e) => Err(e),
+    };
+    let (baseline_ms, raw_baseline_ms) = match measured {
+        Ok(baselines) => baselines,
+        Err(e) => {
+            warn!("Latency calibration skipped: {}", e);
+            return None;
+        }
+    };
+
+    let calibration = LatencyCalibration {
+        socket_type,
+        baseline_ms,
+        raw_baseline_ms,
+        correction_ms: (baseline_ms - raw_baseline_ms).max(0.0),
+        measured_at: chrono::Utc::now().timestamp(),
+    };
+    info!(
+        "Latency calibration: {:?} loopback {:.3}ms, RAW {:.3}ms, correction {:.3}ms",
+        socket_type, baseline_ms, raw_baseline_ms, calibration.correction_ms
+    );
+    let _ = CALIBRATION.set(calibration);
+    current()
+}
+
+/// Subtract the correction from an ICMP result
+pub fn apply(result: &mut PingResult, probe: Probe, correction_ms: f64) {
+    if !matches!(probe, Probe::Icmp(_)) {
+        return;
+    }
+    if let Some(latency) = result.latency_ms {
+        result.latency_ms = Some((latency - correction_ms).max(0.0));
+        result.correction_ms = Some(correction_ms);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProbeType;
+
+    #[test]
+    fn test_median() {
+        assert_eq!(median(vec![]), None);
+        assert_eq!(median(vec![3.0, 1.0, 2.0]), Some(2.0));
+        assert_eq!(median(vec![4.0, 1.0, 2.0, 3.0]), Some(2.5));
+    }
+
+    #[test]
+    fn test_apply_correction() {
+        let result = |latency_ms: Option<f64>| PingResult {
+            timestamp: chrono::Utc::now(),
+            target_id: "t1".to_string(),
+            target: "192.168.1.1".to_string(),
+            target_name: None,
+            sequence: 1,
+            resolved_ip: None,
+            probe_type: ProbeType::Icmp,
+            port: None,
+            success: latency_ms.is_some(),
+            latency_ms,
+            correction_ms: None,
+            flow: None,
+        };
+        let icmp = Probe::Icmp(SocketType::DgramNative);
+
+        let mut corrected = result(Some(1.0));
+        apply(&mut corrected, icmp, 0.25);
+        assert_eq!(corrected.latency_ms, Some(0.75));
+        assert_eq!(corrected.correction_ms, Some(0.25));
+
+        // Never below zero
+        let mut clamped = result(Some(0.1));
+        apply(&mut clamped, icmp, 0.25);
+        assert_eq!(clamped.latency_ms, Some(0.0));
+
+        // Failed pings and TCP probes are left alone
+        let mut failed = result(None);
+        apply(&mut failed, icmp, 0.25);
+        assert_eq!(failed.correction_ms, None);
+
+        let mut tcp = result(Some(1.0));
+        apply(&mut tcp, Probe::Tcp(443), 0.25);
+        assert_eq!(tcp.latency_ms, Some(1.0));
+    }
+}
@@ -0,0 +1,176 @@
+//! ICMP socket capability detection at startup.
+//!
+//! DGRAM ICMP sockets need the process's group in
+//! `net.ipv4.ping_group_range`, RAW sockets root or CAP_NET_RAW. Both are
+//! tried once against loopback. When the configured `[ping] socket_type`
+//! does not work but the other one does, pings use the working one, so an
+//! install without the expected privileges does not fail every ICMP ping.
+//! The outcome is logged with what to change and shown in `/api/status`.
+
+use crate::config::SocketType;
+use crate::config_wizard::{test_ping_capabilities, SocketTestResult};
+use serde::Serialize;
+use std::sync::OnceLock;
+use tracing::{error, info, warn};
+use utoipa::ToSchema;
+
+/// How to allow either socket type
+const PERMISSION_HINT: &str = "Allow unprivileged ICMP sockets with `sysctl -w net.ipv4.ping_group_range=\"0 2147483647\"`, or grant RAW sockets with `setcap cap_net_raw+ep` on the binary (Docker: --cap-add NET_RAW)";
+
+/// Whether a socket type could send an echo request to loopback
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SocketCapability {
+    pub works: bool,
+    /// Why it did not work
+    pub error: Option<String>,
+}
+
+impl From<SocketTestResult> for SocketCapability {
+    fn from(result: SocketTestResult) -> Self {
+        Self {
+            works: result.works,
+            error: result.error,
+        }
+    }
+}
+
+/// ICMP socket types available to the process
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PingCapability {
+    /// `[ping] socket_type` at startup
+    pub configured: SocketType,
+    /// Socket type pings use; differs from `configured` if that did not work
+    pub effective: SocketType,
+    pub dgram: SocketCapability,
+    pub raw: SocketCapability,
+    /// Unix timestamp in seconds
+    pub checked_at: i64,
+}
+
+impl PingCapability {
+    /// `configured`, unless it does not work and the other socket type does
+    fn select(&self, configured: SocketType) -> SocketType {
+        let (own, other, other_type) = match configured {
+            SocketType::Raw => (&self.raw, &self.dgram, SocketType::DgramNative),
+            _ => (&self.dgram, &self.raw, SocketType::Raw),
+        };
+        if !own.works && other.works {
+            other_type
+        } else {
+            configured
+        }
+    }
+}
+
+static CAPABILITY: OnceLock<PingCapability> = OnceLock::new();
+
+/// The capabilities detected at startup, if any
+pub fn current() -> Option<&'static PingCapability> {
+    CAPABILITY.get()
+}
+
+/// Socket type pings should use for a configured one, e.g. after a config
+/// reload. Unchanged before detection ran.
+pub fn effective_socket_type(configured: SocketType) -> SocketType {
+    current().map_or(configured, |c| c.select(configured))
+}
+
+/// Try both socket types, log the outcome and remember it
+pub async fn detect(configured: SocketType) -> PingCapability {
+    let (dgram, raw) = tokio::task::spawn_blocking(test_ping_capabilities)
+        .await
+        .unwrap_or_else(|e| {
+            let failed = || SocketTestResult {
+                works: false,
+                error: Some(format!("Capability check failed: {}", e)),
+            };
+            (failed(), failed())
+        });
+    let mut capability = PingCapability {
+        configured,
+        effective: configured,
+        dgram: dgram.into(),
+        raw: raw.into(),
+        checked_at: chrono::Utc::now().timestamp(),
+    };
+    capability.effective = capability.select(configured);
+
+    let describe = |c: &SocketCapability| match &c.error {
+        None => "works".to_string(),
+        Some(e) => format!("fails ({})", e),
+    };
+    if !capability.dgram.works && !capability.raw.works {
+        error!(
+            "No ICMP socket type works, ICMP pings will fail (dgram {}, raw {}). {}",
+            describe(&capability.dgram),
+            describe(&capability.raw),
+            PERMISSION_HINT
+        );
+    } else if capability.effective != configured {
+        warn!(
+            "[ping] socket_type = \"{}\" does not work, using \"{}\" instead (dgram {}, raw {}). Set socket_type = \"{}\" or: {}",
+            configured.as_str(),
+            capability.effective.as_str(),
+            describe(&capability.dgram),
+            describe(&capability.raw),
+            capability.effective.as_str(),
+            PERMISSION_HINT
+        );
+    } else {
+        info!(
+            "ICMP sockets: dgram {}, raw {}; using \"{}\"",
+            describe(&capability.dgram),
+            describe(&capability.raw),
+            configured.as_str()
+        );
+    }
+
+    let _ = CAPABILITY.set(capability.clone());
+    capability
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capability(dgram: bool, raw: bool) -> PingCapability {
+        let socket = |works| SocketCapability {
+            works,
+            error: (!works).then(|| "Operation not permitted".to_string()),
+        };
+        PingCapability {
+            configured: SocketType::DgramNative,
+            effective: SocketType::DgramNative,
+            dgram: socket(dgram),
+            raw: socket(raw),
+            checked_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_select_falls_back_to_working_socket_type() {
+        // Configured type works
+        assert_eq!(
+            capability(true, true).select(SocketType::DgramNative),
+            SocketType::DgramNative
+        );
+        assert_eq!(
+            capability(true, true).select(SocketType::Raw),
+            SocketType::Raw
+        );
+        // Only the other type works
+        assert_eq!(
+            capability(false, true).select(SocketType::Dgram),
+            SocketType::Raw
+        );
+        assert_eq!(
+            capability(true, false).select(SocketType::Raw),
+            SocketType::DgramNative
+        );
+        // Nothing works: keep the configured type
+        assert_eq!(
+            capability(false, false).select(SocketType::Raw),
+            SocketType::Raw
+        );
+    }
+}
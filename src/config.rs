@@ -1,4 +1,7 @@
+use crate::tasks::schedule::ScheduleWindow;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct AppConfig {
@@ -8,37 +11,751 @@ pub struct AppConfig {
     #[serde(default)]
     pub ping: PingConfig,
     #[serde(default)]
+    pub metrics: MetricsConfig,
+    #[serde(default)]
+    pub home_assistant: HomeAssistantConfig,
+    #[serde(default)]
+    pub alerts: AlertsConfig,
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+    #[serde(default)]
+    pub presence: PresenceConfig,
+    #[serde(default)]
+    pub ingest: IngestConfig,
+    #[serde(default)]
+    pub agent: AgentConfig,
+    #[serde(default)]
+    pub traceroute: TracerouteConfig,
+    #[serde(default)]
+    pub auth: AuthConfig,
+    #[serde(default)]
+    pub updates: UpdatesConfig,
+    #[serde(default)]
+    pub influx_export: InfluxExportConfig,
+    #[serde(default)]
+    pub mqtt: MqttConfig,
+    #[serde(default)]
     pub targets: Vec<Target>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub struct PingConfig {
     /// Socket type to use for ICMP pings: "dgram" (default, unprivileged) or "raw" (requires root)
     #[serde(default)]
     pub socket_type: SocketType,
+    /// Seconds before hostname targets are resolved again (default: 300)
+    #[serde(default = "default_dns_ttl")]
+    pub dns_ttl: u64,
+    /// Measure the DGRAM socket overhead at startup and subtract it from
+    /// ICMP latencies (default: false, needs RAW socket privileges)
+    #[serde(default)]
+    pub calibrate: bool,
+    /// Consecutive failed pings of a target that open an outage (default: 3)
+    #[serde(default = "default_outage_after")]
+    pub outage_after: u16,
+    /// Milliseconds a probe waits for its reply (default: 5000); targets
+    /// can set their own `timeout_ms`
+    #[serde(default = "default_probe_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Time to live of ICMP echo requests (default: 64)
+    #[serde(default = "default_probe_ttl")]
+    pub ttl: u32,
+    /// Payload bytes of ICMP echo requests (default: 24)
+    #[serde(default = "default_payload_size")]
+    pub payload_size: usize,
+    /// IANA time zone of target schedules, e.g. "Europe/Berlin" (default:
+    /// the system time zone)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
 }
 
 impl Default for PingConfig {
     fn default() -> Self {
         Self {
             socket_type: SocketType::default(),
+            dns_ttl: default_dns_ttl(),
+            calibrate: false,
+            outage_after: default_outage_after(),
+            timeout_ms: default_probe_timeout_ms(),
+            ttl: default_probe_ttl(),
+            payload_size: default_payload_size(),
+            timezone: None,
         }
     }
 }
 
-/// Socket type for ICMP ping operations
+fn default_dns_ttl() -> u64 {
+    300
+}
+
+fn default_outage_after() -> u16 {
+    3
+}
+
+fn default_probe_timeout_ms() -> u64 {
+    5000
+}
+
+fn default_probe_ttl() -> u32 {
+    64
+}
+
+fn default_payload_size() -> usize {
+    24
+}
+
+/// Highest time to live of an IP packet
+pub const MAX_TTL: u32 = 255;
+
+/// Largest ICMP echo payload that fits an IPv4 packet
+pub const MAX_PAYLOAD_SIZE: usize = 65_507;
+
+/// Label names SparkPing writes itself, which target labels and ingested
+/// results cannot use (agents' results may carry `site`)
+pub(crate) const RESERVED_LABELS: [&str; 13] = [
+    "target_id",
+    "target",
+    "target_name",
+    "sequence",
+    "resolved_ip",
+    "probe_type",
+    "port",
+    "latency_corrected",
+    "flow",
+    "source",
+    "resolution",
+    "window",
+    crate::storage::SITE_LABEL,
+];
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct MetricsConfig {
+    /// Expose Prometheus metrics at GET /metrics (default: false)
+    #[serde(default)]
+    pub enabled: bool,
+}
+
+/// Home Assistant API access for target suggestions.
+/// Not needed when running as an add-on (the Supervisor token is used).
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct HomeAssistantConfig {
+    /// Base URL of Home Assistant, e.g. "http://homeassistant.local:8123"
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Long-lived access token
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+/// Layer-2 presence detection from the kernel ARP/NDP neighbor table.
+/// Settings are re-read on every poll.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct PresenceConfig {
+    /// Poll the neighbor table (default: false)
+    #[serde(default)]
+    pub enabled: bool,
+    /// Seconds between neighbor table polls (default: 30)
+    #[serde(default = "default_presence_poll_interval")]
+    pub poll_interval: u64,
+    /// Seconds a device must be missing from the table before it is
+    /// reported as departed (default: 300)
+    #[serde(default = "default_presence_away_after")]
+    pub away_after: u64,
+}
+
+impl Default for PresenceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            poll_interval: default_presence_poll_interval(),
+            away_after: default_presence_away_after(),
+        }
+    }
+}
+
+fn default_presence_poll_interval() -> u64 {
+    30
+}
+
+fn default_presence_away_after() -> u64 {
+    300
+}
+
+/// POST /api/ingest/batch for external probes. Settings are re-read on
+/// every request.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct IngestConfig {
+    /// Bearer token external probes must send; ingest is disabled without one
+    #[serde(default)]
+    pub token: Option<String>,
+    /// Maximum number of results per batch (default: 1000)
+    #[serde(default = "default_ingest_max_batch")]
+    pub max_batch: usize,
+    /// Oldest accepted result age in seconds (default: 604800, 7 days)
+    #[serde(default = "default_ingest_max_age")]
+    pub max_age: u64,
+}
+
+impl Default for IngestConfig {
+    fn default() -> Self {
+        Self {
+            token: None,
+            max_batch: default_ingest_max_batch(),
+            max_age: default_ingest_max_age(),
+        }
+    }
+}
+
+fn default_ingest_max_batch() -> usize {
+    1000
+}
+
+fn default_ingest_max_age() -> u64 {
+    7 * 24 * 3600
+}
+
+/// Agent mode: push every ping result to the POST /api/ingest/batch of a
+/// central SparkPing. Settings are re-read every push interval.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct AgentConfig {
+    /// Base URL of the central SparkPing, e.g. "https://sparkping.example.com";
+    /// agent mode is off without one
+    #[serde(default)]
+    pub server: Option<String>,
+    /// `[ingest] token` of the central SparkPing
+    #[serde(default)]
+    pub token: Option<String>,
+    /// Name of this site, sent as `site` label with every result (default:
+    /// the hostname)
+    #[serde(default)]
+    pub site: Option<String>,
+    /// Results per request, at most the central `[ingest] max_batch`
+    /// (default: 500)
+    #[serde(default = "default_agent_batch_size")]
+    pub batch_size: usize,
+    /// Seconds after which a partial batch is pushed (default: 10)
+    #[serde(default = "default_agent_push_interval")]
+    pub push_interval: u64,
+}
+
+impl Default for AgentConfig {
+    fn default() -> Self {
+        Self {
+            server: None,
+            token: None,
+            site: None,
+            batch_size: default_agent_batch_size(),
+            push_interval: default_agent_push_interval(),
+        }
+    }
+}
+
+fn default_agent_batch_size() -> usize {
+    500
+}
+
+fn default_agent_push_interval() -> u64 {
+    10
+}
+
+/// Authentication of API clients. Settings are re-read on every request.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AuthConfig {
+    /// Require a bearer token for API requests (default: false)
+    #[serde(default)]
+    pub enabled: bool,
+    /// Leave read-only (GET) requests open without a token (default: true)
+    #[serde(default = "default_auth_public_read")]
+    pub public_read: bool,
+    /// Seconds a login session stays valid (default: 86400)
+    #[serde(default = "default_auth_session_ttl")]
+    pub session_ttl: u64,
+    /// Users who can log in with a password
+    #[serde(default)]
+    pub users: Vec<AuthUser>,
+    /// API tokens, created through POST /api/auth/tokens
+    #[serde(default)]
+    pub tokens: Vec<ApiToken>,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            public_read: default_auth_public_read(),
+            session_ttl: default_auth_session_ttl(),
+            users: Vec::new(),
+            tokens: Vec::new(),
+        }
+    }
+}
+
+fn default_auth_public_read() -> bool {
+    true
+}
+
+fn default_auth_session_ttl() -> u64 {
+    24 * 3600
+}
+
+/// What a user or API token may do. Each role includes the ones before it.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Read dashboards and data
+    Viewer,
+    /// Also run tests and traceroutes, pause targets and change preferences
+    Editor,
+    /// Also manage targets and API tokens, run discovery scans and prune
+    /// storage. Default, as users and tokens had full access before roles.
+    #[default]
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+/// A user of `[[auth.users]]`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AuthUser {
+    pub name: String,
+    /// Argon2 hash of the password (`sparkping --hash-password`)
+    pub password_hash: String,
+    /// Role (default: admin)
+    #[serde(default)]
+    pub role: Role,
+}
+
+/// An API token of `[[auth.tokens]]`; only its hash is stored
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ApiToken {
+    pub id: String,
+    /// What the token is used for (e.g. "grafana")
+    pub name: String,
+    /// SHA-256 hash of the token (hex)
+    pub hash: String,
+    /// Unix timestamp in seconds of creation
+    #[serde(default)]
+    pub created_at: i64,
+    /// Role (default: admin)
+    #[serde(default)]
+    pub role: Role,
+}
+
+/// Scheduled traceroutes whose hops are stored for path change analysis.
+/// Settings are re-read before every round.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TracerouteConfig {
+    /// Run scheduled traceroutes (default: false)
+    #[serde(default)]
+    pub enabled: bool,
+    /// Seconds between rounds (default: 3600)
+    #[serde(default = "default_traceroute_interval")]
+    pub interval: u64,
+    /// Target IDs to trace, or "*" for all targets (default: ["*"])
+    #[serde(default = "default_traceroute_targets")]
+    pub targets: Vec<String>,
+    /// Highest TTL probed (default: 30)
+    #[serde(default = "default_traceroute_max_hops")]
+    pub max_hops: u8,
+    /// Probes sent per TTL (default: 3)
+    #[serde(default = "default_traceroute_probes_per_hop")]
+    pub probes_per_hop: u8,
+    /// Timeout of each probe in milliseconds (default: 1000)
+    #[serde(default = "default_traceroute_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+impl Default for TracerouteConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: default_traceroute_interval(),
+            targets: default_traceroute_targets(),
+            max_hops: default_traceroute_max_hops(),
+            probes_per_hop: default_traceroute_probes_per_hop(),
+            timeout_ms: default_traceroute_timeout_ms(),
+        }
+    }
+}
+
+impl TracerouteConfig {
+    /// Whether scheduled runs include the target
+    pub fn includes(&self, target_id: &str) -> bool {
+        self.targets.iter().any(|t| t == "*" || t == target_id)
+    }
+}
+
+/// Opt-in check for new releases. Settings are re-read before every check.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct UpdatesConfig {
+    /// Check the release feed periodically (default: false)
+    #[serde(default)]
+    pub check: bool,
+    /// Release feed returning the latest release as JSON with a `tag_name`
+    /// (default: the project's GitHub releases API)
+    #[serde(default = "default_updates_url")]
+    pub url: String,
+    /// Seconds between checks (default: 86400, at least 3600)
+    #[serde(default = "default_updates_interval")]
+    pub interval: u64,
+}
+
+impl Default for UpdatesConfig {
+    fn default() -> Self {
+        Self {
+            check: false,
+            url: default_updates_url(),
+            interval: default_updates_interval(),
+        }
+    }
+}
+
+fn default_updates_url() -> String {
+    "https://api.github.com/repos/hco/SparkPing/releases/latest".to_string()
+}
+
+fn default_updates_interval() -> u64 {
+    86400
+}
+
+/// Mirror of every ping result to an InfluxDB line protocol endpoint
+/// (InfluxDB 1.x/2.x, VictoriaMetrics). Settings are re-read every flush
+/// interval.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct InfluxExportConfig {
+    /// Export ping results (default: false)
+    #[serde(default)]
+    pub enabled: bool,
+    /// Write endpoint, e.g. "http://influxdb:8086/api/v2/write?org=home&bucket=sparkping"
+    /// or "http://victoriametrics:8428/write"
+    #[serde(default)]
+    pub url: Option<String>,
+    /// InfluxDB 2.x API token, sent as "Authorization: Token <token>"
+    #[serde(default)]
+    pub token: Option<String>,
+    /// Basic auth for InfluxDB 1.x or a proxy in front of VictoriaMetrics
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Measurement name (default: "sparkping")
+    #[serde(default = "default_influx_measurement")]
+    pub measurement: String,
+    /// Results per write request (default: 500)
+    #[serde(default = "default_influx_batch_size")]
+    pub batch_size: usize,
+    /// Seconds after which a partial batch is written (default: 10)
+    #[serde(default = "default_influx_flush_interval")]
+    pub flush_interval: u64,
+    /// Retries of a failed write before the batch is dropped (default: 5)
+    #[serde(default = "default_influx_max_retries")]
+    pub max_retries: u32,
+}
+
+impl Default for InfluxExportConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: None,
+            token: None,
+            username: None,
+            password: None,
+            measurement: default_influx_measurement(),
+            batch_size: default_influx_batch_size(),
+            flush_interval: default_influx_flush_interval(),
+            max_retries: default_influx_max_retries(),
+        }
+    }
+}
+
+fn default_influx_measurement() -> String {
+    "sparkping".to_string()
+}
+
+fn default_influx_batch_size() -> usize {
+    500
+}
+
+fn default_influx_flush_interval() -> u64 {
+    10
+}
+
+fn default_influx_max_retries() -> u32 {
+    5
+}
+
+/// Publishing of ping results, target up/down states and presence events to
+/// an MQTT broker, with Home Assistant MQTT discovery. Settings are re-read
+/// every few seconds; a change reconnects.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct MqttConfig {
+    /// Publish to the broker (default: false)
+    #[serde(default)]
+    pub enabled: bool,
+    /// Broker hostname or IP address
+    #[serde(default)]
+    pub host: Option<String>,
+    /// Broker port (default: 1883)
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Client ID (default: "sparkping-<hostname>")
+    #[serde(default)]
+    pub client_id: Option<String>,
+    /// Prefix of all published topics (default: "sparkping")
+    #[serde(default = "default_mqtt_topic_prefix")]
+    pub topic_prefix: String,
+    /// Publish Home Assistant MQTT discovery configs (default: true)
+    #[serde(default = "default_mqtt_ha_discovery")]
+    pub ha_discovery: bool,
+    /// Home Assistant discovery prefix (default: "homeassistant")
+    #[serde(default = "default_mqtt_discovery_prefix")]
+    pub discovery_prefix: String,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: None,
+            port: default_mqtt_port(),
+            username: None,
+            password: None,
+            client_id: None,
+            topic_prefix: default_mqtt_topic_prefix(),
+            ha_discovery: default_mqtt_ha_discovery(),
+            discovery_prefix: default_mqtt_discovery_prefix(),
+        }
+    }
+}
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+fn default_mqtt_topic_prefix() -> String {
+    "sparkping".to_string()
+}
+
+fn default_mqtt_ha_discovery() -> bool {
+    true
+}
+
+fn default_mqtt_discovery_prefix() -> String {
+    "homeassistant".to_string()
+}
+
+fn default_traceroute_interval() -> u64 {
+    3600
+}
+
+fn default_traceroute_targets() -> Vec<String> {
+    vec!["*".to_string()]
+}
+
+fn default_traceroute_max_hops() -> u8 {
+    30
+}
+
+fn default_traceroute_probes_per_hop() -> u8 {
+    3
+}
+
+fn default_traceroute_timeout_ms() -> u64 {
+    1000
+}
+
+/// Alert rules. Rules are re-read on every evaluation, so config file changes
+/// apply without a restart. Transitions are sent to `[notifications]` channels.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AlertsConfig {
+    /// Seconds between rule evaluations (default: 30)
+    #[serde(default = "default_alert_evaluation_interval")]
+    pub evaluation_interval: u64,
+    #[serde(default)]
+    pub rules: Vec<AlertRule>,
+}
+
+impl Default for AlertsConfig {
+    fn default() -> Self {
+        Self {
+            evaluation_interval: default_alert_evaluation_interval(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+/// A threshold rule evaluated against stored ping results
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AlertRule {
+    /// Unique rule name
+    pub name: String,
+    /// Target ID the rule applies to, or "*" for all targets (default: "*")
+    #[serde(default = "default_alert_target")]
+    pub target: String,
+    pub condition: AlertCondition,
+    /// Latency in milliseconds or loss in percent; unused for "down"
+    #[serde(default)]
+    pub threshold: f64,
+    /// Evaluation window for latency and loss rules, e.g. "5m" (default: "1m")
+    #[serde(default = "default_alert_window", rename = "for")]
+    pub for_duration: String,
+    /// Consecutive failed ping cycles for "down" rules (default: 3)
+    #[serde(default = "default_alert_intervals")]
+    pub intervals: u32,
+}
+
+/// What an alert rule checks
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertCondition {
+    /// Average latency over the window is above the threshold
+    LatencyMs,
+    /// Packet loss over the window is above the threshold
+    LossPercent,
+    /// All pings of the last `intervals` cycles failed
+    Down,
+}
+
+/// Notification channels for target up/down transitions and alerts
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct NotificationsConfig {
+    /// Notify when a target goes down (all pings of a cycle failed) or comes
+    /// back up (default: true)
+    #[serde(default = "default_notify_target_state")]
+    pub target_state: bool,
+    #[serde(default)]
+    pub channels: Vec<ChannelConfig>,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            target_state: true,
+            channels: Vec::new(),
+        }
+    }
+}
+
+/// A named notification channel
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ChannelConfig {
+    /// Unique channel name
+    pub name: String,
+    /// Events sent to this channel; empty means all events
+    #[serde(default)]
+    pub events: Vec<NotificationKind>,
+    #[serde(flatten)]
+    pub kind: ChannelKind,
+}
+
+/// Notification channel type and its settings
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChannelKind {
+    /// HTTP POST of the notification as JSON
+    Webhook { url: String },
+    /// Slack-compatible incoming webhook (`{"text": ...}` payload)
+    Slack { url: String },
+    /// Email via SMTP
+    Email {
+        smtp_host: String,
+        /// SMTP port (default: 587)
+        #[serde(default = "default_smtp_port")]
+        smtp_port: u16,
+        /// Connection security: "starttls" (default), "tls", or "none"
+        #[serde(default)]
+        security: SmtpSecurity,
+        #[serde(default)]
+        username: Option<String>,
+        #[serde(default)]
+        password: Option<String>,
+        from: String,
+        to: Vec<String>,
+    },
+}
+
+/// SMTP connection security
 #[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
 #[serde(rename_all = "snake_case")]
+pub enum SmtpSecurity {
+    #[default]
+    Starttls,
+    Tls,
+    None,
+}
+
+/// Event types that can be sent to notification channels
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    TargetDown,
+    TargetUp,
+    AlertFiring,
+    AlertResolved,
+    UpdateAvailable,
+    Test,
+}
+
+fn default_notify_target_state() -> bool {
+    true
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+fn default_alert_evaluation_interval() -> u64 {
+    30
+}
+
+fn default_alert_target() -> String {
+    "*".to_string()
+}
+
+fn default_alert_window() -> String {
+    "1m".to_string()
+}
+
+fn default_alert_intervals() -> u32 {
+    3
+}
+
+/// Socket type for ICMP ping operations
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default, ToSchema)]
+#[serde(rename_all = "snake_case")]
 pub enum SocketType {
-    /// Native DGRAM implementation - handles DGRAM reply format correctly (default)
+    /// Unprivileged DGRAM socket (default)
     #[default]
     DgramNative,
-    /// DGRAM socket via ping crate - unprivileged, works without root on modern systems
+    /// Same as `DgramNative`; kept for existing configs
     Dgram,
-    /// RAW socket via ping crate - requires elevated privileges (root/sudo)
+    /// RAW socket - requires elevated privileges (root/sudo or CAP_NET_RAW)
     Raw,
 }
 
+impl SocketType {
+    /// Config file value of the socket type
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SocketType::DgramNative => "dgram_native",
+            SocketType::Dgram => "dgram",
+            SocketType::Raw => "raw",
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct ServerConfig {
     pub host: String,
@@ -46,20 +763,318 @@ pub struct ServerConfig {
     /// When true, restrict access to only Home Assistant ingress IP (172.30.32.2)
     #[serde(default)]
     pub home_assistant_ingress_only: bool,
+    /// Per-requester limits for historical query endpoints and scans
+    #[serde(default)]
+    pub query_quota: QueryQuotaConfig,
+    /// Server-wide limit on concurrently running historical queries
+    #[serde(default)]
+    pub query_admission: QueryAdmissionConfig,
+    /// Cross-origin requests from browsers (e.g. a separately hosted frontend)
+    #[serde(default)]
+    pub cors: CorsConfig,
+    /// Seconds to wait on shutdown for ping tasks to finish their batch and
+    /// for in-flight requests before storage is closed (default: 10)
+    #[serde(default = "default_shutdown_timeout")]
+    pub shutdown_timeout: u64,
+    /// Refuse every request that changes something (targets, config,
+    /// storage operations, tests, ingest), e.g. for a public status page
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+fn default_shutdown_timeout() -> u64 {
+    10
+}
+
+/// CORS policy of the API. Read at startup.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct CorsConfig {
+    /// Origins allowed to call the API (e.g. "https://grafana.example.com"),
+    /// or "*" for any. Empty disables CORS (default).
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    /// Allowed methods (default: GET, POST, PUT, PATCH, DELETE)
+    #[serde(default = "default_cors_allowed_methods")]
+    pub allowed_methods: Vec<String>,
+    /// Allowed request headers (default: Authorization, Content-Type,
+    /// API-Version)
+    #[serde(default = "default_cors_allowed_headers")]
+    pub allowed_headers: Vec<String>,
+    /// Seconds browsers may cache a preflight response (default: 3600)
+    #[serde(default = "default_cors_max_age")]
+    pub max_age: u64,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: Vec::new(),
+            allowed_methods: default_cors_allowed_methods(),
+            allowed_headers: default_cors_allowed_headers(),
+            max_age: default_cors_max_age(),
+        }
+    }
+}
+
+fn default_cors_allowed_methods() -> Vec<String> {
+    ["GET", "POST", "PUT", "PATCH", "DELETE"]
+        .into_iter()
+        .map(String::from)
+        .collect()
 }
 
+fn default_cors_allowed_headers() -> Vec<String> {
+    ["Authorization", "Content-Type", "API-Version"]
+        .into_iter()
+        .map(String::from)
+        .collect()
+}
+
+fn default_cors_max_age() -> u64 {
+    3600
+}
+
+/// Per-requester limits for historical query endpoints and scans
+/// (0 = unlimited)
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueryQuotaConfig {
+    /// Maximum number of requests per requester per UTC day
+    #[serde(default)]
+    pub daily_budget: u32,
+    /// Maximum number of concurrent historical queries per requester
+    #[serde(default)]
+    pub max_concurrent: u32,
+    /// Limits of expensive requests: scans, test pings, traceroutes,
+    /// diagnoses and raw data queries without a limit
+    #[serde(default)]
+    pub expensive: ExpensiveQuotaConfig,
+}
+
+/// Per-requester limits for expensive requests (0 = unlimited)
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub struct ExpensiveQuotaConfig {
+    /// Expensive requests per minute, refilled continuously (default: 30)
+    #[serde(default = "default_expensive_requests_per_minute")]
+    pub requests_per_minute: u32,
+    /// Expensive requests running at once; streamed responses count until
+    /// they end (default: 2)
+    #[serde(default = "default_expensive_max_concurrent")]
+    pub max_concurrent: u32,
+}
+
+impl Default for ExpensiveQuotaConfig {
+    fn default() -> Self {
+        Self {
+            requests_per_minute: default_expensive_requests_per_minute(),
+            max_concurrent: default_expensive_max_concurrent(),
+        }
+    }
+}
+
+fn default_expensive_requests_per_minute() -> u32 {
+    30
+}
+
+fn default_expensive_max_concurrent() -> u32 {
+    2
+}
+
+/// Server-wide admission control for historical query endpoints
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub struct QueryAdmissionConfig {
+    /// Maximum number of historical queries running at once (default: 4, 0 = unlimited)
+    #[serde(default = "default_admission_max_concurrent")]
+    pub max_concurrent: u32,
+    /// Maximum number of queries waiting for a slot before new ones are
+    /// rejected (default: 32)
+    #[serde(default = "default_admission_max_queued")]
+    pub max_queued: u32,
+    /// Milliseconds a query may wait for a slot (default: 10000)
+    #[serde(default = "default_admission_queue_timeout_ms")]
+    pub queue_timeout_ms: u64,
+}
+
+impl Default for QueryAdmissionConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent: default_admission_max_concurrent(),
+            max_queued: default_admission_max_queued(),
+            queue_timeout_ms: default_admission_queue_timeout_ms(),
+        }
+    }
+}
+
+fn default_admission_max_concurrent() -> u32 {
+    4
+}
+
+fn default_admission_max_queued() -> u32 {
+    32
+}
+
+fn default_admission_queue_timeout_ms() -> u64 {
+    10_000
+}
+
+/// Log output, read at startup only
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct LoggingConfig {
     pub level: String,
     pub file: String,
+    /// Level of the console output (default: `level`)
+    #[serde(default)]
+    pub console_level: Option<String>,
+    /// Level of the log file (default: `level`)
+    #[serde(default)]
+    pub file_level: Option<String>,
+    /// Format of console and file output (default: text)
+    #[serde(default)]
+    pub format: LogFormat,
+    /// Megabytes after which the log file is rotated (default: 0, no size
+    /// limit)
+    #[serde(default)]
+    pub max_size_mb: u64,
+    /// Also rotate the log file when the hour or day changes (default: never)
+    #[serde(default)]
+    pub rotation: LogRotation,
+    /// Rotated files kept as `<file>.1` (newest) to `<file>.<max_files>`
+    /// (default: 5)
+    #[serde(default = "default_log_max_files")]
+    pub max_files: usize,
+}
+
+fn default_log_max_files() -> usize {
+    5
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines
+    #[default]
+    Text,
+    /// One JSON object per line, for log shippers
+    Json,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    #[default]
+    Never,
+    Hourly,
+    Daily,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct DatabaseConfig {
     pub path: String,
+    /// Storage backend (default: tsink; read at startup only)
+    #[serde(default)]
+    pub backend: StorageBackendKind,
+    /// Seconds between storage size snapshots recorded into tsink
+    /// (default: 3600, 0 disables)
+    #[serde(default = "default_storage_stats_interval")]
+    pub stats_interval: u64,
+    /// Days ping data is kept for targets without their own retention
+    /// (default: 0, keep forever)
+    #[serde(default)]
+    pub retention_days: u32,
+    /// Seconds between runs of the pruning task (default: 3600, 0 disables)
+    #[serde(default = "default_prune_interval")]
+    pub prune_interval: u64,
+    /// Roll raw ping data up into 1m/1h series for long-range queries
+    /// (default: true)
+    #[serde(default = "default_downsample")]
+    pub downsample: bool,
+    /// Milliseconds ping results of all targets are collected before they
+    /// are written to tsink together (default: 0, each target's batch is
+    /// written when it completes; read at startup only)
+    #[serde(default)]
+    pub write_flush_ms: u64,
+    /// Encryption of the data directory at rest (read at startup only)
+    #[serde(default)]
+    pub encryption: EncryptionConfig,
+}
+
+impl DatabaseConfig {
+    /// Directory tsink and the other stores work in: `path`, or the
+    /// decrypted working copy when encryption is enabled
+    pub fn data_path(&self) -> &str {
+        if self.encryption.enabled {
+            &self.encryption.work_path
+        } else {
+            &self.path
+        }
+    }
+}
+
+/// Where ping data is stored, see `storage::backend`
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackendKind {
+    /// tsink partitions in the data directory
+    #[default]
+    Tsink,
+    /// A SQLite database (`sparkping.sqlite`) in the data directory
+    Sqlite,
 }
 
+/// Encryption at rest of the data directory.
+///
+/// When enabled, `[database] path` only holds encrypted files. They are
+/// decrypted into `work_path` at startup and sealed back periodically and
+/// on shutdown.
 #[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct EncryptionConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// File holding the 32-byte key as 64 hex characters; the
+    /// `SPARKPING_STORAGE_KEY` environment variable takes precedence
+    #[serde(default)]
+    pub key_file: Option<String>,
+    /// Plaintext working directory, ideally on a tmpfs
+    /// (default: "/dev/shm/sparkping")
+    #[serde(default = "default_encryption_work_path")]
+    pub work_path: String,
+    /// Seconds between sealing the working directory back into `path`
+    /// (default: 900, 0 only seals on shutdown)
+    #[serde(default = "default_seal_interval")]
+    pub seal_interval: u64,
+}
+
+impl Default for EncryptionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            key_file: None,
+            work_path: default_encryption_work_path(),
+            seal_interval: default_seal_interval(),
+        }
+    }
+}
+
+fn default_encryption_work_path() -> String {
+    "/dev/shm/sparkping".to_string()
+}
+
+fn default_seal_interval() -> u64 {
+    900
+}
+
+fn default_storage_stats_interval() -> u64 {
+    3600
+}
+
+fn default_prune_interval() -> u64 {
+    3600
+}
+
+fn default_downsample() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
 pub struct Target {
     #[serde(default)]
     pub id: String,
@@ -71,6 +1086,165 @@ pub struct Target {
     /// Delay between individual pings in seconds (default: 1)
     #[serde(default = "default_ping_interval")]
     pub ping_interval: u64,
+    /// How the target is probed (default: icmp)
+    #[serde(default)]
+    pub probe_type: ProbeType,
+    /// Port for TCP probes (default: 80)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    /// Days this target's ping data is kept (default: `[database] retention_days`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retention_days: Option<u32>,
+    /// Paused targets keep their history but are not pinged (default: false)
+    #[serde(default)]
+    pub paused: bool,
+    /// Favorites are listed first by `sort=order` (default: false)
+    #[serde(default)]
+    pub favorite: bool,
+    /// Position in the user-chosen order, lower first; targets with the same
+    /// position keep their config order (default: 0)
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub sort_order: u32,
+    /// Free-form labels for grouping and filtering targets (e.g., "office")
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Key/value labels (e.g. `location = "basement"`), stored with every
+    /// result and exported to Prometheus
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    /// ID of the target pinged outside the VPN tunnel this target is reached
+    /// through; makes this a tunnel target with derived overhead series
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tunnel_reference: Option<String>,
+    /// Spread each batch's pings over this many flows (distinct ICMP echo
+    /// identifiers or TCP source ports), so that loss on one path of an
+    /// ECMP or bonded uplink shows up as per-flow divergence (default: off)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ecmp_flows: Option<u16>,
+    /// Milliseconds a probe waits for its reply (default: `[ping] timeout_ms`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    /// Time to live of ICMP echo requests (default: `[ping] ttl`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u32>,
+    /// Payload bytes of ICMP echo requests (default: `[ping] payload_size`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload_size: Option<usize>,
+    /// Time windows the target is pinged in, e.g. "mon-fri 08:00-18:00"
+    /// (default: always)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[schema(value_type = Vec<String>)]
+    pub schedule: Vec<ScheduleWindow>,
+    /// Identities the target's ping data was stored under before, read as
+    /// part of its history (set by POST /api/targets/{id}/migrate-history)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<HistorySource>,
+}
+
+impl Default for Target {
+    fn default() -> Self {
+        Self {
+            id: String::new(),
+            address: String::new(),
+            name: None,
+            ping_count: default_ping_count(),
+            ping_interval: default_ping_interval(),
+            probe_type: ProbeType::default(),
+            port: None,
+            retention_days: None,
+            paused: false,
+            favorite: false,
+            sort_order: 0,
+            tags: Vec::new(),
+            labels: BTreeMap::new(),
+            tunnel_reference: None,
+            ecmp_flows: None,
+            timeout_ms: None,
+            ttl: None,
+            payload_size: None,
+            schedule: Vec::new(),
+            history: Vec::new(),
+        }
+    }
+}
+
+/// An earlier identity of a target: another target ID (e.g. of a deleted and
+/// re-created target), or the target's own ID with its old address
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, ToSchema)]
+pub struct HistorySource {
+    pub id: String,
+    /// Address the series were stored with (default: any)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+}
+
+/// Default port of TCP probes without an explicit port
+pub const DEFAULT_TCP_PORT: u16 = 80;
+
+/// Most flows a target's pings can be spread over
+pub const MAX_ECMP_FLOWS: u16 = 16;
+
+impl Target {
+    /// Number of flows the target's pings are spread over; 0 or 1 means
+    /// flows are off
+    pub fn flow_count(&self) -> u16 {
+        self.ecmp_flows.unwrap_or(0).min(MAX_ECMP_FLOWS)
+    }
+
+    /// Port connected to by TCP probes, None for ICMP targets
+    pub fn tcp_port(&self) -> Option<u16> {
+        match self.probe_type {
+            ProbeType::Icmp => None,
+            ProbeType::Tcp => Some(self.port.unwrap_or(DEFAULT_TCP_PORT)),
+        }
+    }
+}
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
+/// Check a target label name: a Prometheus label name (letters, digits and
+/// underscores, not starting with a digit or `__`) that SparkPing does not
+/// write itself
+pub fn validate_label_name(name: &str) -> Result<(), String> {
+    let valid = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !name.starts_with("__");
+    if !valid {
+        return Err(format!(
+            "Invalid label name '{}' (letters, digits and underscores, not starting with a digit)",
+            name
+        ));
+    }
+    if RESERVED_LABELS.contains(&name) {
+        return Err(format!("Label name '{}' is reserved", name));
+    }
+    Ok(())
+}
+
+/// Probe type of a target
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ProbeType {
+    /// ICMP echo (socket type from `[ping]`)
+    #[default]
+    Icmp,
+    /// TCP connect latency to `port`
+    Tcp,
+}
+
+impl ProbeType {
+    /// Value of the `probe_type` label
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProbeType::Icmp => "icmp",
+            ProbeType::Tcp => "tcp",
+        }
+    }
 }
 
 fn default_ping_count() -> u16 {
@@ -80,3 +1254,20 @@ fn default_ping_count() -> u16 {
 fn default_ping_interval() -> u64 {
     1
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_label_name() {
+        assert!(validate_label_name("location").is_ok());
+        assert!(validate_label_name("_rack2").is_ok());
+        assert!(validate_label_name("2nd").is_err());
+        assert!(validate_label_name("__name__").is_err());
+        assert!(validate_label_name("room-1").is_err());
+        assert!(validate_label_name("target_id").is_err());
+        // Results with a site label belong to an agent, not to this server
+        assert!(validate_label_name("site").is_err());
+    }
+}
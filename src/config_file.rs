@@ -1,4 +1,6 @@
-use crate::config::Target;
+use crate::config::{ApiToken, HistorySource, ProbeType, Target};
+use crate::tasks::schedule::ScheduleWindow;
+use std::collections::BTreeMap;
 use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
@@ -128,6 +130,44 @@ pub fn add_target(
         )));
     }
 
+    if target.probe_type != ProbeType::Icmp {
+        target_table["probe_type"] = Item::Value(Value::String(toml_edit::Formatted::new(
+            target.probe_type.as_str().to_string(),
+        )));
+    }
+
+    if let Some(port) = target.port {
+        target_table["port"] = Item::Value(Value::Integer(toml_edit::Formatted::new(port as i64)));
+    }
+
+    if let Some(days) = target.retention_days {
+        target_table["retention_days"] =
+            Item::Value(Value::Integer(toml_edit::Formatted::new(days as i64)));
+    }
+
+    if target.paused {
+        target_table["paused"] = Item::Value(Value::Boolean(toml_edit::Formatted::new(true)));
+    }
+
+    set_display_entries(&mut target_table, target);
+
+    set_tags_entry(&mut target_table, &target.tags);
+    set_labels_entry(&mut target_table, &target.labels);
+
+    if let Some(ref reference) = target.tunnel_reference {
+        target_table["tunnel_reference"] =
+            Item::Value(Value::String(toml_edit::Formatted::new(reference.clone())));
+    }
+
+    if let Some(flows) = target.ecmp_flows {
+        target_table["ecmp_flows"] =
+            Item::Value(Value::Integer(toml_edit::Formatted::new(flows as i64)));
+    }
+
+    set_probe_option_entries(&mut target_table, target);
+    set_schedule_entry(&mut target_table, &target.schedule);
+    set_history_entry(&mut target_table, &target.history);
+
     targets_array.push(target_table);
 
     Ok(id)
@@ -168,7 +208,70 @@ pub fn update_target(
                 target_table["ping_interval"] = Item::Value(Value::Integer(
                     toml_edit::Formatted::new(target.ping_interval as i64),
                 ));
+                target_table["probe_type"] = Item::Value(Value::String(toml_edit::Formatted::new(
+                    target.probe_type.as_str().to_string(),
+                )));
+
+                if let Some(port) = target.port {
+                    target_table["port"] =
+                        Item::Value(Value::Integer(toml_edit::Formatted::new(port as i64)));
+                } else {
+                    target_table.remove("port");
+                }
+
+                if let Some(days) = target.retention_days {
+                    target_table["retention_days"] =
+                        Item::Value(Value::Integer(toml_edit::Formatted::new(days as i64)));
+                } else {
+                    target_table.remove("retention_days");
+                }
+
+                set_paused_entry(target_table, target.paused);
+                set_display_entries(target_table, target);
+                set_tags_entry(target_table, &target.tags);
+                set_labels_entry(target_table, &target.labels);
+
+                if let Some(ref reference) = target.tunnel_reference {
+                    target_table["tunnel_reference"] =
+                        Item::Value(Value::String(toml_edit::Formatted::new(reference.clone())));
+                } else {
+                    target_table.remove("tunnel_reference");
+                }
+
+                if let Some(flows) = target.ecmp_flows {
+                    target_table["ecmp_flows"] =
+                        Item::Value(Value::Integer(toml_edit::Formatted::new(flows as i64)));
+                } else {
+                    target_table.remove("ecmp_flows");
+                }
+
+                set_probe_option_entries(target_table, target);
+                set_schedule_entry(target_table, &target.schedule);
+                set_history_entry(target_table, &target.history);
+
+                return Ok(());
+            }
+        }
+    }
+
+    Err(format!("Target with id '{}' not found", id).into())
+}
+
+/// Set the paused flag of a target in the config document by ID
+pub fn set_target_paused(
+    doc: &mut DocumentMut,
+    id: &str,
+    paused: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let targets_array = doc
+        .get_mut("targets")
+        .and_then(|item| item.as_array_of_tables_mut())
+        .ok_or("targets array not found or invalid")?;
 
+    for target_table in targets_array.iter_mut() {
+        if let Some(Item::Value(Value::String(existing_id))) = target_table.get("id") {
+            if existing_id.value() == id {
+                set_paused_entry(target_table, paused);
                 return Ok(());
             }
         }
@@ -177,6 +280,108 @@ pub fn update_target(
     Err(format!("Target with id '{}' not found", id).into())
 }
 
+/// Write `paused = true`, or drop the key for running targets
+fn set_paused_entry(target_table: &mut Table, paused: bool) {
+    if paused {
+        target_table["paused"] = Item::Value(Value::Boolean(toml_edit::Formatted::new(true)));
+    } else {
+        target_table.remove("paused");
+    }
+}
+
+/// Write `favorite = true` and a non-zero `sort_order`, dropping the keys
+/// otherwise
+fn set_display_entries(target_table: &mut Table, target: &Target) {
+    if target.favorite {
+        target_table["favorite"] = Item::Value(Value::Boolean(toml_edit::Formatted::new(true)));
+    } else {
+        target_table.remove("favorite");
+    }
+    if target.sort_order > 0 {
+        target_table["sort_order"] = Item::Value(Value::Integer(toml_edit::Formatted::new(
+            target.sort_order as i64,
+        )));
+    } else {
+        target_table.remove("sort_order");
+    }
+}
+
+/// Write the target's tags as an inline array, or drop the key when empty
+fn set_tags_entry(target_table: &mut Table, tags: &[String]) {
+    if tags.is_empty() {
+        target_table.remove("tags");
+    } else {
+        let array: toml_edit::Array = tags.iter().map(String::as_str).collect();
+        target_table["tags"] = Item::Value(Value::Array(array));
+    }
+}
+
+/// Write the target's labels as an inline table, or drop the key when it has
+/// none
+fn set_labels_entry(target_table: &mut Table, labels: &BTreeMap<String, String>) {
+    if labels.is_empty() {
+        target_table.remove("labels");
+        return;
+    }
+    let mut table = toml_edit::InlineTable::new();
+    for (name, value) in labels {
+        table.insert(name, value.as_str().into());
+    }
+    target_table["labels"] = Item::Value(Value::InlineTable(table));
+}
+
+/// Write the target's timeout, TTL and payload size overrides, dropping the
+/// keys it takes from `[ping]`
+fn set_probe_option_entries(target_table: &mut Table, target: &Target) {
+    let options = [
+        ("timeout_ms", target.timeout_ms.map(|ms| ms as i64)),
+        ("ttl", target.ttl.map(i64::from)),
+        ("payload_size", target.payload_size.map(|size| size as i64)),
+    ];
+    for (key, value) in options {
+        match value {
+            Some(value) => {
+                target_table[key] = Item::Value(Value::Integer(toml_edit::Formatted::new(value)))
+            }
+            None => {
+                target_table.remove(key);
+            }
+        }
+    }
+}
+
+/// Write the target's schedule windows as an inline array, or drop the key
+/// when it is always pinged
+fn set_schedule_entry(target_table: &mut Table, schedule: &[ScheduleWindow]) {
+    if schedule.is_empty() {
+        target_table.remove("schedule");
+    } else {
+        let array: toml_edit::Array = schedule.iter().map(|w| w.to_string()).collect();
+        target_table["schedule"] = Item::Value(Value::Array(array));
+    }
+}
+
+/// Write the target's earlier identities as an array of inline tables, or
+/// drop the key when it has none
+fn set_history_entry(target_table: &mut Table, history: &[HistorySource]) {
+    if history.is_empty() {
+        target_table.remove("history");
+        return;
+    }
+    let array: toml_edit::Array = history
+        .iter()
+        .map(|source| {
+            let mut table = toml_edit::InlineTable::new();
+            table.insert("id", source.id.as_str().into());
+            if let Some(ref address) = source.address {
+                table.insert("address", address.as_str().into());
+            }
+            Value::InlineTable(table)
+        })
+        .collect();
+    target_table["history"] = Item::Value(Value::Array(array));
+}
+
 /// Remove a target from the config document by ID
 pub fn remove_target(doc: &mut DocumentMut, id: &str) -> Result<(), Box<dyn std::error::Error>> {
     let targets_array = doc
@@ -202,3 +407,66 @@ pub fn remove_target(doc: &mut DocumentMut, id: &str) -> Result<(), Box<dyn std:
         Err(format!("Target with id '{}' not found", id).into())
     }
 }
+
+/// Get the `[[auth.tokens]]` array, creating `[auth]` and the array if necessary
+fn auth_tokens_array(
+    doc: &mut DocumentMut,
+) -> Result<&mut toml_edit::ArrayOfTables, Box<dyn std::error::Error>> {
+    if doc.get("auth").is_none() {
+        let mut auth = Table::new();
+        auth.set_implicit(true);
+        doc["auth"] = Item::Table(auth);
+    }
+    let auth = doc["auth"]
+        .as_table_mut()
+        .ok_or("auth section is not a table")?;
+    if auth.get("tokens").is_none() {
+        auth["tokens"] = Item::ArrayOfTables(toml_edit::ArrayOfTables::new());
+    }
+    auth.get_mut("tokens")
+        .and_then(|item| item.as_array_of_tables_mut())
+        .ok_or_else(|| "auth.tokens array not found or invalid".into())
+}
+
+/// Add an API token (its hash) to the config document
+pub fn add_api_token(
+    doc: &mut DocumentMut,
+    token: &ApiToken,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let tokens_array = auth_tokens_array(doc)?;
+
+    let mut token_table = Table::new();
+    token_table["id"] = Item::Value(Value::String(toml_edit::Formatted::new(token.id.clone())));
+    token_table["name"] = Item::Value(Value::String(toml_edit::Formatted::new(token.name.clone())));
+    token_table["hash"] = Item::Value(Value::String(toml_edit::Formatted::new(token.hash.clone())));
+    token_table["created_at"] =
+        Item::Value(Value::Integer(toml_edit::Formatted::new(token.created_at)));
+    token_table["role"] = Item::Value(Value::String(toml_edit::Formatted::new(
+        token.role.as_str().to_string(),
+    )));
+    tokens_array.push(token_table);
+
+    Ok(())
+}
+
+/// Remove an API token from the config document by ID
+pub fn remove_api_token(doc: &mut DocumentMut, id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let tokens_array = auth_tokens_array(doc)?;
+
+    let mut index_to_remove = None;
+    for (idx, token_table) in tokens_array.iter().enumerate() {
+        if let Some(Item::Value(Value::String(existing_id))) = token_table.get("id") {
+            if existing_id.value() == id {
+                index_to_remove = Some(idx);
+                break;
+            }
+        }
+    }
+
+    if let Some(idx) = index_to_remove {
+        tokens_array.remove(idx);
+        Ok(())
+    } else {
+        Err(format!("API token with id '{}' not found", id).into())
+    }
+}
@@ -0,0 +1,481 @@
+//! Strict validation of config files.
+//!
+//! The config crate that loads `config.toml` silently ignores unknown keys and
+//! stops at the first bad value. Validation parses the TOML itself, reports
+//! every key no setting reads (usually a typo), the first value that does not
+//! deserialize (e.g. an unknown socket type) with its path, and checks that
+//! target IDs are unique, ping counts and intervals are positive, label
+//! names are valid, probe timeouts, TTLs and payload sizes are in range and
+//! the schedule time zone exists. It runs for `POST /api/config/validate` and before a changed
+//! config file is reloaded.
+
+use crate::api::ping::calendar::parse_tz;
+use crate::config::{validate_label_name, AppConfig, MAX_PAYLOAD_SIZE, MAX_TTL};
+use crate::storage::is_valid_site;
+use serde::Serialize;
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+/// Category of a config problem
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigIssueKind {
+    /// The file is not valid TOML
+    Syntax,
+    /// A key no setting reads
+    UnknownKey,
+    /// A value of the wrong type or outside its allowed values
+    InvalidValue,
+    /// Two targets share an ID
+    DuplicateTargetId,
+    /// A ping count or interval of zero
+    InvalidInterval,
+    /// The config passed validation but could not be loaded
+    LoadError,
+}
+
+/// A problem found in a config file
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct ConfigIssue {
+    pub kind: ConfigIssueKind,
+    /// Dotted path of the offending setting, e.g. `targets[1].ping_interval`
+    /// (empty if the whole file is affected)
+    pub path: String,
+    pub message: String,
+    /// 1-based line, for syntax errors
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+}
+
+impl ConfigIssue {
+    fn new(kind: ConfigIssueKind, path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            path: path.into(),
+            message: message.into(),
+            line: None,
+        }
+    }
+
+    /// An error from loading a config that passed validation
+    pub fn load_error(message: impl Into<String>) -> Self {
+        Self::new(ConfigIssueKind::LoadError, "", message)
+    }
+}
+
+/// Validate the contents of a config file. Returns the parsed config, or
+/// every problem found.
+pub fn validate_config(text: &str) -> Result<AppConfig, Vec<ConfigIssue>> {
+    let table: toml::Table = text.parse().map_err(|e: toml::de::Error| {
+        let line = e
+            .span()
+            .map(|span| text[..span.start.min(text.len())].matches('\n').count() + 1);
+        vec![ConfigIssue {
+            line,
+            ..ConfigIssue::new(ConfigIssueKind::Syntax, "", e.message())
+        }]
+    })?;
+
+    let mut issues = Vec::new();
+    let mut record_unknown = |path: serde_ignored::Path| {
+        issues.push(ConfigIssue::new(
+            ConfigIssueKind::UnknownKey,
+            format_path(&path),
+            "Unknown setting",
+        ));
+    };
+    let result: Result<AppConfig, _> = serde_path_to_error::deserialize(
+        serde_ignored::Deserializer::new(toml::Value::Table(table), &mut record_unknown),
+    );
+
+    let config = match result {
+        Ok(config) => Some(config),
+        Err(e) => {
+            // The root path is displayed as "."
+            let path = e.path().to_string().trim_start_matches('.').to_string();
+            issues.push(ConfigIssue::new(
+                ConfigIssueKind::InvalidValue,
+                path,
+                e.inner().to_string(),
+            ));
+            None
+        }
+    };
+    if let Some(config) = &config {
+        issues.extend(check_timezone(config));
+        issues.extend(check_probe_options(config));
+        issues.extend(check_targets(config));
+        issues.extend(check_agent(config));
+    }
+
+    match config {
+        Some(config) if issues.is_empty() => Ok(config),
+        _ => Err(issues),
+    }
+}
+
+/// An unknown `[ping] timezone` of target schedules
+fn check_timezone(config: &AppConfig) -> Option<ConfigIssue> {
+    let name = config.ping.timezone.as_deref()?;
+    parse_tz(name)
+        .err()
+        .map(|e| ConfigIssue::new(ConfigIssueKind::InvalidValue, "ping.timezone", e))
+}
+
+/// Zero timeouts or TTLs and TTLs or payload sizes no IP packet can carry,
+/// in `[ping]` and target overrides
+fn check_probe_options(config: &AppConfig) -> Vec<ConfigIssue> {
+    let mut issues = Vec::new();
+    let mut check =
+        |prefix: &str, timeout_ms: Option<u64>, ttl: Option<u32>, payload_size: Option<usize>| {
+            if timeout_ms == Some(0) {
+                issues.push(ConfigIssue::new(
+                    ConfigIssueKind::InvalidValue,
+                    format!("{}.timeout_ms", prefix),
+                    "Must be at least 1 millisecond",
+                ));
+            }
+            if ttl.is_some_and(|ttl| ttl == 0 || ttl > MAX_TTL) {
+                issues.push(ConfigIssue::new(
+                    ConfigIssueKind::InvalidValue,
+                    format!("{}.ttl", prefix),
+                    format!("Must be between 1 and {}", MAX_TTL),
+                ));
+            }
+            if payload_size.is_some_and(|size| size > MAX_PAYLOAD_SIZE) {
+                issues.push(ConfigIssue::new(
+                    ConfigIssueKind::InvalidValue,
+                    format!("{}.payload_size", prefix),
+                    format!("Must be at most {} bytes", MAX_PAYLOAD_SIZE),
+                ));
+            }
+        };
+
+    let ping = &config.ping;
+    check(
+        "ping",
+        Some(ping.timeout_ms),
+        Some(ping.ttl),
+        Some(ping.payload_size),
+    );
+    for (i, target) in config.targets.iter().enumerate() {
+        check(
+            &format!("targets[{}]", i),
+            target.timeout_ms,
+            target.ttl,
+            target.payload_size,
+        );
+    }
+
+    issues
+}
+
+/// Agent settings the central server would refuse: a server that is not an
+/// HTTP(S) URL, no token, or empty batches
+fn check_agent(config: &AppConfig) -> Vec<ConfigIssue> {
+    let agent = &config.agent;
+    let mut issues = Vec::new();
+    let Some(server) = agent.server.as_deref() else {
+        return issues;
+    };
+    let invalid =
+        |path: &str, message: &str| ConfigIssue::new(ConfigIssueKind::InvalidValue, path, message);
+    if !(server.starts_with("http://") || server.starts_with("https://")) {
+        issues.push(invalid(
+            "agent.server",
+            "Must be an http:// or https:// URL",
+        ));
+    }
+    if agent.token.as_deref().is_none_or(str::is_empty) {
+        issues.push(invalid(
+            "agent.token",
+            "Required with agent.server (the central [ingest] token)",
+        ));
+    }
+    if agent.batch_size == 0 {
+        issues.push(invalid("agent.batch_size", "Must be at least 1"));
+    }
+    // An empty site falls back to the hostname
+    if agent
+        .site
+        .as_deref()
+        .is_some_and(|site| !site.is_empty() && !is_valid_site(site))
+    {
+        issues.push(invalid(
+            "agent.site",
+            "\"local\" and \"*\" are reserved for the central server and all sites",
+        ));
+    }
+    issues
+}
+
+/// Duplicate IDs, zero ping counts or intervals and invalid label names.
+/// Targets without an ID get one generated at startup, so only set IDs are
+/// compared.
+fn check_targets(config: &AppConfig) -> Vec<ConfigIssue> {
+    let mut issues = Vec::new();
+    let mut first_index: HashMap<&str, usize> = HashMap::new();
+
+    for (i, target) in config.targets.iter().enumerate() {
+        if !target.id.is_empty() {
+            if let Some(first) = first_index.get(target.id.as_str()) {
+                issues.push(ConfigIssue::new(
+                    ConfigIssueKind::DuplicateTargetId,
+                    format!("targets[{}].id", i),
+                    format!(
+                        "Target ID '{}' is already used by targets[{}]",
+                        target.id, first
+                    ),
+                ));
+            } else {
+                first_index.insert(&target.id, i);
+            }
+        }
+        if target.ping_count == 0 {
+            issues.push(ConfigIssue::new(
+                ConfigIssueKind::InvalidInterval,
+                format!("targets[{}].ping_count", i),
+                "Must be at least 1",
+            ));
+        }
+        if target.ping_interval == 0 {
+            issues.push(ConfigIssue::new(
+                ConfigIssueKind::InvalidInterval,
+                format!("targets[{}].ping_interval", i),
+                "Must be at least 1 second",
+            ));
+        }
+        for name in target.labels.keys() {
+            if let Err(message) = validate_label_name(name) {
+                issues.push(ConfigIssue::new(
+                    ConfigIssueKind::InvalidValue,
+                    format!("targets[{}].labels.{}", i, name),
+                    message,
+                ));
+            }
+        }
+    }
+
+    issues
+}
+
+/// Format a path like `serde_path_to_error` does: `targets[1].name`
+fn format_path(path: &serde_ignored::Path) -> String {
+    use serde_ignored::Path;
+    match path {
+        Path::Root => String::new(),
+        Path::Seq { parent, index } => format!("{}[{}]", format_path(parent), index),
+        Path::Map { parent, key } => {
+            let parent = format_path(parent);
+            if parent.is_empty() {
+                key.to_string()
+            } else {
+                format!("{}.{}", parent, key)
+            }
+        }
+        Path::Some { parent }
+        | Path::NewtypeStruct { parent }
+        | Path::NewtypeVariant { parent } => format_path(parent),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = r#"
+[server]
+host = "0.0.0.0"
+port = 8080
+
+[logging]
+level = "info"
+file = "sparkping.log"
+
+[database]
+path = "./tsink-data"
+"#;
+
+    fn kinds(issues: &[ConfigIssue]) -> Vec<(ConfigIssueKind, &str)> {
+        issues.iter().map(|i| (i.kind, i.path.as_str())).collect()
+    }
+
+    #[test]
+    fn test_valid_config() {
+        let text = format!(
+            "{}\n[ping]\nsocket_type = \"raw\"\n\n[[targets]]\nid = \"router\"\naddress = \"192.168.1.1\"\n",
+            BASE
+        );
+        let config = validate_config(&text).unwrap();
+        assert_eq!(config.targets.len(), 1);
+    }
+
+    #[test]
+    fn test_syntax_error_has_line() {
+        let text = format!("{}\n[ping\n", BASE);
+        let issues = validate_config(&text).unwrap_err();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].kind, ConfigIssueKind::Syntax);
+        assert_eq!(issues[0].line, Some(BASE.lines().count() + 2));
+    }
+
+    #[test]
+    fn test_unknown_keys() {
+        let text = format!(
+            "{}\n[ping]\nsocket_typ = \"raw\"\n\n[[targets]]\naddress = \"192.168.1.1\"\nintervall = 5\n",
+            BASE
+        );
+        let issues = validate_config(&text).unwrap_err();
+        assert_eq!(
+            kinds(&issues),
+            vec![
+                (ConfigIssueKind::UnknownKey, "ping.socket_typ"),
+                (ConfigIssueKind::UnknownKey, "targets[0].intervall"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_socket_type() {
+        let text = format!("{}\n[ping]\nsocket_type = \"icmp\"\n", BASE);
+        let issues = validate_config(&text).unwrap_err();
+        assert_eq!(
+            kinds(&issues),
+            vec![(ConfigIssueKind::InvalidValue, "ping.socket_type")]
+        );
+        assert!(issues[0].message.contains("dgram_native"));
+    }
+
+    #[test]
+    fn test_duplicate_ids_and_intervals() {
+        let text = format!(
+            r#"{}
+[[targets]]
+id = "router"
+address = "192.168.1.1"
+
+[[targets]]
+address = "192.168.1.2"
+
+[[targets]]
+address = "192.168.1.3"
+
+[[targets]]
+id = "router"
+address = "192.168.1.4"
+ping_count = 0
+ping_interval = 0
+"#,
+            BASE
+        );
+        let issues = validate_config(&text).unwrap_err();
+        assert_eq!(
+            kinds(&issues),
+            vec![
+                (ConfigIssueKind::DuplicateTargetId, "targets[3].id"),
+                (ConfigIssueKind::InvalidInterval, "targets[3].ping_count"),
+                (ConfigIssueKind::InvalidInterval, "targets[3].ping_interval"),
+            ]
+        );
+        assert!(issues[0].message.contains("targets[0]"));
+    }
+
+    #[test]
+    fn test_invalid_schedule() {
+        let text = format!(
+            "{}\n[ping]\ntimezone = \"Mars/Olympus\"\n\n[[targets]]\naddress = \"10.8.0.1\"\nschedule = [\"mon-fri 08:00-18:00\"]\n",
+            BASE
+        );
+        let issues = validate_config(&text).unwrap_err();
+        assert_eq!(
+            kinds(&issues),
+            vec![(ConfigIssueKind::InvalidValue, "ping.timezone")]
+        );
+
+        let text = format!(
+            "{}\n[[targets]]\naddress = \"10.8.0.1\"\nschedule = [\"weekdays 08:00-18:00\"]\n",
+            BASE
+        );
+        let issues = validate_config(&text).unwrap_err();
+        assert_eq!(issues[0].kind, ConfigIssueKind::InvalidValue);
+        assert!(issues[0].path.starts_with("targets[0].schedule"));
+    }
+
+    #[test]
+    fn test_probe_options_out_of_range() {
+        let text = format!(
+            r#"{}
+[ping]
+ttl = 0
+
+[[targets]]
+address = "192.168.1.1"
+timeout_ms = 0
+ttl = 300
+payload_size = 70000
+
+[[targets]]
+address = "192.168.1.2"
+timeout_ms = 2000
+ttl = 128
+payload_size = 1400
+"#,
+            BASE
+        );
+        let issues = validate_config(&text).unwrap_err();
+        assert_eq!(
+            kinds(&issues),
+            vec![
+                (ConfigIssueKind::InvalidValue, "ping.ttl"),
+                (ConfigIssueKind::InvalidValue, "targets[0].timeout_ms"),
+                (ConfigIssueKind::InvalidValue, "targets[0].ttl"),
+                (ConfigIssueKind::InvalidValue, "targets[0].payload_size"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_label_names() {
+        let text = format!(
+            r#"{}
+[[targets]]
+address = "192.168.1.1"
+labels = {{ location = "basement", "2nd" = "x", target_id = "y" }}
+"#,
+            BASE
+        );
+        let issues = validate_config(&text).unwrap_err();
+        assert_eq!(
+            kinds(&issues),
+            vec![
+                (ConfigIssueKind::InvalidValue, "targets[0].labels.2nd"),
+                (ConfigIssueKind::InvalidValue, "targets[0].labels.target_id"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_agent_settings() {
+        let text = format!(
+            "{}\n[agent]\nserver = \"sparkping.example.com\"\nbatch_size = 0\nsite = \"local\"\n",
+            BASE
+        );
+        let issues = validate_config(&text).unwrap_err();
+        assert_eq!(
+            kinds(&issues),
+            vec![
+                (ConfigIssueKind::InvalidValue, "agent.server"),
+                (ConfigIssueKind::InvalidValue, "agent.token"),
+                (ConfigIssueKind::InvalidValue, "agent.batch_size"),
+                (ConfigIssueKind::InvalidValue, "agent.site"),
+            ]
+        );
+
+        let text = format!(
+            "{}\n[agent]\nserver = \"https://sparkping.example.com\"\ntoken = \"secret\"\nsite = \"berlin\"\n",
+            BASE
+        );
+        let config = validate_config(&text).unwrap();
+        assert_eq!(config.agent.site.as_deref(), Some("berlin"));
+    }
+}
@@ -1,11 +1,14 @@
 use crate::config::SocketType;
 use console::{style, Term};
-use dialoguer::{Confirm, Input, Select};
+use dialoguer::{Confirm, Input, Password, Select};
 use std::io::IsTerminal;
 use std::net::IpAddr;
 use std::path::Path;
 use std::time::Duration;
 
+/// Data directory of generated configs
+pub const DEFAULT_DB_PATH: &str = "./data";
+
 /// Result of testing a ping socket type
 #[derive(Debug, Clone)]
 pub struct SocketTestResult {
@@ -20,7 +23,15 @@ fn test_socket_type(socket_type: SocketType) -> SocketTestResult {
 
     let result: Result<(), String> = match socket_type {
         SocketType::DgramNative => {
-            crate::icmp::ping_dgram(test_addr, Duration::from_secs(2), 1, 1)
+            crate::icmp::ping_dgram(
+                test_addr,
+                Duration::from_secs(2),
+                1,
+                1,
+                false,
+                crate::ping::DEFAULT_TTL,
+                crate::ping::DEFAULT_PAYLOAD_SIZE,
+            )
                 .map(|_| ())
                 .map_err(|e| e.to_string())
         }
@@ -92,7 +103,7 @@ pub fn run_config_wizard(config_path: &Path) -> Result<String, Box<dyn std::erro
 
     let db_path: String = Input::new()
         .with_prompt("Database path")
-        .default("./data".to_string())
+        .default(DEFAULT_DB_PATH.to_string())
         .interact_text()?;
 
     term.write_line("")?;
@@ -367,6 +378,32 @@ socket_type = "{socket_type_str}"
     )
 }
 
+/// Config of a zero-config start: built-in defaults, listening on all
+/// interfaces, and no targets. Everything else is set up through the API.
+pub fn default_config() -> String {
+    generate_config(DEFAULT_DB_PATH, "0.0.0.0", SocketType::default())
+}
+
+/// Ask for a password (or read it from stdin when not interactive) and
+/// return its hash for `[[auth.users]] password_hash`
+pub fn prompt_password_hash() -> Result<String, Box<dyn std::error::Error>> {
+    let password = if is_interactive() {
+        Password::new()
+            .with_prompt("Password")
+            .with_confirmation("Repeat password", "Passwords do not match")
+            .interact()?
+    } else {
+        let mut line = String::new();
+        std::io::stdin().read_line(&mut line)?;
+        line.trim_end_matches(['\r', '\n']).to_string()
+    };
+    if password.is_empty() {
+        return Err("Password must not be empty".into());
+    }
+
+    Ok(crate::auth::hash_password(&password)?)
+}
+
 /// Ask user if they want to generate a default config (for interactive mode when config is missing)
 pub fn prompt_create_config(config_path: &Path) -> Result<bool, Box<dyn std::error::Error>> {
     let term = Term::stdout();
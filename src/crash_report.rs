@@ -0,0 +1,236 @@
+//! Crash reports written by the panic hook.
+//!
+//! Once the data directory is known, every panic writes
+//! `<data path>/crashes/crash-<unix ms>.json` with the panic message,
+//! location, backtrace, version, the most recent log lines, and the ping
+//! tasks that were running. The newest report is loaded at startup and shown
+//! at GET `/api/system/diagnostics`.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io::Write;
+use std::panic::PanicHookInfo;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use tokio::task::AbortHandle;
+
+/// Directory (inside the data path) holding crash reports
+const CRASH_DIR: &str = "crashes";
+/// Log lines kept for crash reports
+const LOG_RING_CAPACITY: usize = 200;
+/// Crash reports kept on disk; older ones are removed when a new one is written
+const MAX_REPORTS: usize = 10;
+
+type TaskHandles = Arc<RwLock<HashMap<String, AbortHandle>>>;
+
+static RECENT_LOGS: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+static CRASH_DIR_PATH: OnceLock<PathBuf> = OnceLock::new();
+static TASKS: OnceLock<TaskHandles> = OnceLock::new();
+static LAST_CRASH: OnceLock<Option<CrashReport>> = OnceLock::new();
+
+/// Post-mortem information about a panic
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrashReport {
+    /// ISO 8601 formatted time of the panic
+    pub timestamp: String,
+    pub version: String,
+    pub thread: Option<String>,
+    pub message: String,
+    /// `file:line:column` of the panic
+    pub location: Option<String>,
+    pub backtrace: String,
+    /// Most recent log lines before the panic, oldest first
+    pub recent_logs: Vec<String>,
+    /// Target IDs whose ping tasks were running
+    pub active_tasks: Vec<String>,
+}
+
+/// `tracing` writer that keeps the last log lines in memory
+#[derive(Clone, Copy, Default)]
+pub struct RecentLogWriter;
+
+impl Write for RecentLogWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // Never block or panic here: this also runs while the panic hook logs
+        if let Ok(mut logs) = RECENT_LOGS.try_lock() {
+            for line in String::from_utf8_lossy(buf).lines() {
+                if logs.len() == LOG_RING_CAPACITY {
+                    logs.pop_front();
+                }
+                logs.push_back(line.to_string());
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> tracing_subscriber::fmt::MakeWriter<'a> for RecentLogWriter {
+    type Writer = RecentLogWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        *self
+    }
+}
+
+/// Enable crash reports under `data_path` and load the newest existing one
+pub fn init(data_path: &Path) {
+    let dir = data_path.join(CRASH_DIR);
+    let _ = LAST_CRASH.set(latest_report(&dir));
+    let _ = CRASH_DIR_PATH.set(dir);
+}
+
+/// Register the ping task handles listed in crash reports
+pub fn register_tasks(tasks: TaskHandles) {
+    let _ = TASKS.set(tasks);
+}
+
+/// The newest crash report found at startup, if any
+pub fn last_crash() -> Option<&'static CrashReport> {
+    LAST_CRASH.get().and_then(|c| c.as_ref())
+}
+
+/// Build a report for a panic and write it; called from the panic hook.
+/// Returns the path of the written report.
+pub fn write_crash_report(info: &PanicHookInfo<'_>) -> Option<PathBuf> {
+    let dir = CRASH_DIR_PATH.get()?;
+    let report = build_report(info);
+    match save_report(dir, &report) {
+        Ok(path) => Some(path),
+        Err(e) => {
+            eprintln!("Failed to write crash report to {}: {}", dir.display(), e);
+            None
+        }
+    }
+}
+
+fn build_report(info: &PanicHookInfo<'_>) -> CrashReport {
+    let message = if let Some(s) = info.payload().downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = info.payload().downcast_ref::<String>() {
+        s.clone()
+    } else {
+        info.to_string()
+    };
+
+    let recent_logs = RECENT_LOGS
+        .try_lock()
+        .map(|logs| logs.iter().cloned().collect())
+        .unwrap_or_default();
+
+    let mut active_tasks: Vec<String> = TASKS
+        .get()
+        .and_then(|tasks| {
+            tasks.try_read().ok().map(|handles| {
+                handles
+                    .iter()
+                    .filter(|(_, handle)| !handle.is_finished())
+                    .map(|(id, _)| id.clone())
+                    .collect()
+            })
+        })
+        .unwrap_or_default();
+    active_tasks.sort();
+
+    CrashReport {
+        timestamp: Utc::now().to_rfc3339(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        thread: std::thread::current().name().map(|n| n.to_string()),
+        message,
+        location: info
+            .location()
+            .map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column())),
+        backtrace: std::backtrace::Backtrace::force_capture().to_string(),
+        recent_logs,
+        active_tasks,
+    }
+}
+
+fn save_report(dir: &Path, report: &CrashReport) -> std::io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!("crash-{}.json", Utc::now().timestamp_millis()));
+    fs::write(&path, serde_json::to_vec_pretty(report)?)?;
+
+    let mut reports = report_files(dir);
+    while reports.len() > MAX_REPORTS {
+        let _ = fs::remove_file(reports.remove(0));
+    }
+    Ok(path)
+}
+
+/// Crash report files, oldest first
+fn report_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with("crash-") && n.ends_with(".json"))
+        })
+        .collect();
+    // Names carry the same number of digits, so they sort chronologically
+    files.sort();
+    files
+}
+
+fn latest_report(dir: &Path) -> Option<CrashReport> {
+    let path = report_files(dir).pop()?;
+    let content = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(message: &str) -> CrashReport {
+        CrashReport {
+            timestamp: Utc::now().to_rfc3339(),
+            version: "0.0.0".to_string(),
+            thread: Some("main".to_string()),
+            message: message.to_string(),
+            location: None,
+            backtrace: String::new(),
+            recent_logs: vec!["INFO started".to_string()],
+            active_tasks: vec!["router".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_save_keeps_latest_reports() {
+        let dir = std::env::temp_dir().join(format!("sparkping-crashes-{}", uuid::Uuid::new_v4()));
+        for i in 0..MAX_REPORTS + 2 {
+            save_report(&dir, &report(&format!("panic {}", i))).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+
+        assert_eq!(report_files(&dir).len(), MAX_REPORTS);
+        let latest = latest_report(&dir).unwrap();
+        assert_eq!(latest.message, format!("panic {}", MAX_REPORTS + 1));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_recent_log_writer_ring() {
+        let mut writer = RecentLogWriter;
+        for i in 0..LOG_RING_CAPACITY + 5 {
+            writer
+                .write_all(format!("line {}\n", i).as_bytes())
+                .unwrap();
+        }
+        let logs = RECENT_LOGS.lock().unwrap();
+        assert_eq!(logs.len(), LOG_RING_CAPACITY);
+        assert_eq!(
+            logs.back().unwrap(),
+            &format!("line {}", LOG_RING_CAPACITY + 4)
+        );
+    }
+}
@@ -1,7 +1,8 @@
 //! Device identification module.
 //!
 //! This module provides functionality to identify devices based on their
-//! mDNS services, TXT records, and vendor-specific information.
+//! mDNS services, WS-Discovery replies, TXT records, and vendor-specific
+//! information.
 //!
 //! The identification process extracts high-level device information such as:
 //! - Device type (e.g., "Smart Speaker", "Printer")
@@ -13,7 +14,9 @@
 mod parsers;
 
 use crate::discovery::{DiscoveredDevice, DiscoveredService};
+use crate::ip_scan::GatewayInfo;
 use crate::vendor_discovery::VendorInfo;
+use crate::ws_discovery::WS_DISCOVERY_SERVICE_TYPE;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -46,7 +49,8 @@ pub struct DeviceInfo {
     /// Firmware/software version
     #[serde(skip_serializing_if = "Option::is_none")]
     pub firmware_version: Option<String>,
-    /// MAC address (when available from TXT records or vendor info)
+    /// MAC address (when available from TXT records, vendor info or the
+    /// neighbor table)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub mac_address: Option<String>,
     /// Hint for frontend icon selection (e.g., "sonos", "apple", "printer")
@@ -68,6 +72,16 @@ pub enum DiscoverySource {
         /// Ports that responded
         ports: Vec<u16>,
     },
+    /// Device found in the neighbor (ARP/NDP) table
+    Arp {
+        /// Link-layer address of the device
+        mac: String,
+    },
+    /// Device answered a WS-Discovery probe
+    WsDiscovery {
+        /// Type QNames it announced (e.g., "wprt:PrintDeviceType")
+        types: Vec<String>,
+    },
 }
 
 /// Raw discovery data preserved for detailed inspection
@@ -94,6 +108,31 @@ pub struct IdentifiedDevice {
     pub discovery_sources: Vec<DiscoverySource>,
     /// Raw discovery data for detailed inspection
     pub raw_discovery: RawDiscoveryData,
+    /// Set when the device is the gateway of a scanned subnet
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gateway: Option<GatewayInfo>,
+    /// Configured target the device already is, set by the discovery API
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tracked_target: Option<TargetMatch>,
+}
+
+/// A configured target matching a discovered device
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TargetMatch {
+    pub target_id: String,
+    pub matched_by: TargetMatchKind,
+}
+
+/// How a device was matched to a target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TargetMatchKind {
+    /// The target address is one of the device's IP addresses or its
+    /// hostname
+    Address,
+    /// The neighbor table maps the target address to the device's MAC
+    /// address, e.g. after the device got a new DHCP lease
+    Mac,
 }
 
 impl DeviceInfo {
@@ -157,6 +196,26 @@ pub enum IdentifiedDiscoveryEvent {
     },
     /// An error occurred during discovery
     Error { message: String },
+    /// Periodic progress of an IP scan
+    Progress {
+        /// Addresses checked so far
+        scanned: usize,
+        /// Addresses in the scanned range
+        total: usize,
+        /// Devices the IP scan found so far
+        found: usize,
+        /// Estimated seconds until the IP scan completes, once known
+        eta_seconds: Option<u64>,
+    },
+    /// Periodic stream health report
+    Heartbeat {
+        /// Events waiting to be sent to the client
+        queue_depth: usize,
+        /// Capacity of the client queue
+        queue_capacity: usize,
+        /// Device updates dropped because the client was not keeping up
+        dropped_events: u64,
+    },
 }
 
 /// Convert a DiscoveredDevice to an IdentifiedDevice
@@ -174,18 +233,33 @@ pub fn convert_to_identified(device: DiscoveredDevice) -> IdentifiedDevice {
                 service_types: device
                     .services
                     .iter()
+                    .filter(|s| s.service_type != WS_DISCOVERY_SERVICE_TYPE)
                     .map(|s| s.service_type.clone())
                     .collect(),
             });
         } else if method.contains("ip_scan") {
-            // Extract ports from services or use empty vec
-            let ports: Vec<u16> = device.services.iter().map(|s| s.port).collect();
-            discovery_sources.push(DiscoverySource::IpScan { ports });
+            discovery_sources.push(DiscoverySource::IpScan {
+                ports: device.open_ports.clone(),
+            });
+        } else if method == "ws_discovery" {
+            discovery_sources.push(DiscoverySource::WsDiscovery {
+                types: device
+                    .services
+                    .iter()
+                    .filter(|s| s.service_type == WS_DISCOVERY_SERVICE_TYPE)
+                    .filter_map(|s| s.txt_properties.get("types"))
+                    .flat_map(|t| t.split_whitespace().map(|t| t.to_string()))
+                    .collect(),
+            });
+        } else if method == "arp" {
+            if let Some(mac) = &device.mac {
+                discovery_sources.push(DiscoverySource::Arp { mac: mac.clone() });
+            }
         }
     }
 
     // Identify the device
-    let device_info = identify_device(
+    let mut device_info = identify_device(
         &device.name,
         &device.address,
         &device.addresses,
@@ -195,6 +269,14 @@ pub fn convert_to_identified(device: DiscoveredDevice) -> IdentifiedDevice {
         device.vendor_info.as_ref(),
     );
 
+    if device_info.mac_address.is_none() {
+        device_info.mac_address = device.mac.clone();
+    }
+
+    if device.gateway.is_some() && device_info.device_type.is_none() {
+        device_info.device_type = Some("Gateway".to_string());
+    }
+
     // Build raw discovery data
     let raw_discovery = RawDiscoveryData {
         services: device.services.clone(),
@@ -207,6 +289,8 @@ pub fn convert_to_identified(device: DiscoveredDevice) -> IdentifiedDevice {
         device_info,
         discovery_sources,
         raw_discovery,
+        gateway: device.gateway,
+        tracked_target: None,
     }
 }
 
@@ -1,11 +1,13 @@
 //! Device parsers for various service types and vendors.
 //!
 //! This module contains the logic for parsing device information from
-//! mDNS services, TXT records, and vendor-specific information.
+//! mDNS services, WS-Discovery replies, TXT records, and vendor-specific
+//! information.
 
 use super::DeviceInfo;
 use crate::discovery::DiscoveredService;
 use crate::vendor_discovery::VendorInfo;
+use crate::ws_discovery::{onvif_scope, WS_DISCOVERY_SERVICE_TYPE};
 use std::collections::HashMap;
 use tracing::debug;
 
@@ -168,6 +170,53 @@ fn parse_vendor_info(vendor_info: &VendorInfo) -> ParsedInfo {
             friendly_name: Some(sonos.zone_name.clone()),
             icon_hint: Some("sonos".to_string()),
         },
+        VendorInfo::Shelly(shelly) => {
+            let code = shelly
+                .app
+                .as_deref()
+                .or_else(|| shelly_gen1_app_code(&shelly.model));
+            let model = code
+                .map(shelly_model_name)
+                .unwrap_or_else(|| shelly.model.clone());
+            ParsedInfo {
+                device_type: Some(format!("Shelly {}", model)),
+                manufacturer: Some("Shelly".to_string()),
+                model: Some(model),
+                firmware_version: shelly.firmware_version.clone(),
+                mac_address: shelly.mac_address.clone(),
+                friendly_name: shelly.name.clone(),
+                icon_hint: Some("shelly".to_string()),
+            }
+        }
+        VendorInfo::Hue(hue) => {
+            let (device_type, model) = hue
+                .model_id
+                .as_deref()
+                .and_then(hue_model)
+                .unwrap_or(("Smart Home Hub", "Hue Bridge"));
+            ParsedInfo {
+                device_type: Some(device_type.to_string()),
+                manufacturer: Some("Philips".to_string()),
+                model: Some(model.to_string()),
+                firmware_version: hue.software_version.clone(),
+                mac_address: hue.mac_address.clone(),
+                friendly_name: Some(hue.name.clone()),
+                icon_hint: Some("philips".to_string()),
+            }
+        }
+        VendorInfo::Esphome(esphome) => ParsedInfo {
+            device_type: Some("ESPHome".to_string()),
+            manufacturer: Some("ESPHome".to_string()),
+            model: esphome
+                .project_name
+                .clone()
+                .or(esphome.board.clone())
+                .or(Some(esphome.name.clone())),
+            firmware_version: esphome.esphome_version.clone(),
+            mac_address: esphome.mac_address.clone(),
+            friendly_name: esphome.friendly_name.clone(),
+            icon_hint: Some("esphome".to_string()),
+        },
     }
 }
 
@@ -178,6 +227,9 @@ fn parse_service(service: &DiscoveredService) -> ParsedInfo {
     let instance_name = &service.instance_name;
 
     // Match service type to appropriate parser
+    if service_type == WS_DISCOVERY_SERVICE_TYPE {
+        return parse_ws_discovery(txt);
+    }
     if service_type.contains("_hap._tcp") || service_type.contains("_homekit._tcp") {
         return parse_homekit(txt);
     }
@@ -226,14 +278,15 @@ fn parse_service(service: &DiscoveredService) -> ParsedInfo {
 
 /// Parse combined TXT properties
 fn parse_txt_properties(txt: &HashMap<String, String>) -> ParsedInfo {
-    let mut info = ParsedInfo::default();
-
     // Try to extract manufacturer
-    info.manufacturer = txt
-        .get("manufacturer")
-        .or_else(|| txt.get("mfr"))
-        .or_else(|| txt.get("vendor"))
-        .cloned();
+    let mut info = ParsedInfo {
+        manufacturer: txt
+            .get("manufacturer")
+            .or_else(|| txt.get("mfr"))
+            .or_else(|| txt.get("vendor"))
+            .cloned(),
+        ..Default::default()
+    };
 
     // Try to extract model
     if info.model.is_none() {
@@ -272,36 +325,34 @@ fn parse_homekit(txt: &HashMap<String, String>) -> ParsedInfo {
     let category_id = txt.get("ci");
 
     // Map category ID to device type name
-    let device_type = category_id.and_then(|ci| {
-        Some(
-            match ci.as_str() {
-                "1" => "Other",
-                "2" => "Bridge",
-                "3" => "Fan",
-                "4" => "Garage Door Opener",
-                "5" => "Lightbulb",
-                "6" => "Door Lock",
-                "7" => "Outlet",
-                "8" => "Switch",
-                "9" => "Thermostat",
-                "10" => "Sensor",
-                "11" => "Security System",
-                "12" => "Door",
-                "13" => "Window",
-                "14" => "Window Covering",
-                "15" => "Programmable Switch",
-                "16" => "Range Extender",
-                "17" => "IP Camera",
-                "18" => "Video Doorbell",
-                "19" => "Air Purifier",
-                "20" => "Heater",
-                "21" => "Air Conditioner",
-                "22" => "Humidifier",
-                "23" => "Dehumidifier",
-                _ => "HomeKit Device",
-            }
-            .to_string(),
-        )
+    let device_type = category_id.map(|ci| {
+        match ci.as_str() {
+            "1" => "Other",
+            "2" => "Bridge",
+            "3" => "Fan",
+            "4" => "Garage Door Opener",
+            "5" => "Lightbulb",
+            "6" => "Door Lock",
+            "7" => "Outlet",
+            "8" => "Switch",
+            "9" => "Thermostat",
+            "10" => "Sensor",
+            "11" => "Security System",
+            "12" => "Door",
+            "13" => "Window",
+            "14" => "Window Covering",
+            "15" => "Programmable Switch",
+            "16" => "Range Extender",
+            "17" => "IP Camera",
+            "18" => "Video Doorbell",
+            "19" => "Air Purifier",
+            "20" => "Heater",
+            "21" => "Air Conditioner",
+            "22" => "Humidifier",
+            "23" => "Dehumidifier",
+            _ => "HomeKit Device",
+        }
+        .to_string()
     });
 
     ParsedInfo {
@@ -420,49 +471,7 @@ fn parse_shelly(txt: &HashMap<String, String>) -> ParsedInfo {
     let generation = txt.get("gen").cloned();
     let version = txt.get("ver").cloned();
 
-    // Map Shelly app codes to human-readable model names
-    let model_name = app_code.as_ref().map(|code| {
-        match code.as_str() {
-            // Gen 3
-            "PlugSG3" => "Plug S Gen 3",
-            "MiniG3" => "Mini Gen 3",
-            "Mini1G3" => "1PM Mini Gen 3",
-            "1G3" => "1 Gen 3",
-            "1PMG3" => "1PM Gen 3",
-            "2PMG3" => "2PM Gen 3",
-            // Gen 2 / Plus
-            "PlusPlugS" => "Plus Plug S",
-            "PlusPlugUS" => "Plus Plug US",
-            "Plus1" => "Plus 1",
-            "Plus1PM" => "Plus 1PM",
-            "Plus2PM" => "Plus 2PM",
-            "PlusI4" => "Plus i4",
-            "PlusHT" => "Plus H&T",
-            // Pro
-            "Pro1" => "Pro 1",
-            "Pro1PM" => "Pro 1PM",
-            "Pro2" => "Pro 2",
-            "Pro2PM" => "Pro 2PM",
-            "Pro3" => "Pro 3",
-            "Pro4PM" => "Pro 4PM",
-            // Gen 1
-            "1" => "1",
-            "1L" => "1L",
-            "1PM" => "1PM",
-            "25" => "2.5",
-            "Plug" => "Plug",
-            "PlugS" => "Plug S",
-            "Dimmer" => "Dimmer",
-            "RGBW2" => "RGBW2",
-            "Bulb" => "Bulb",
-            "EM" => "EM",
-            "3EM" => "3EM",
-            "HT" => "H&T",
-            _ => code.as_str(),
-        }
-        .to_string()
-    });
-
+    let model_name = app_code.as_deref().map(shelly_model_name);
     let device_type = model_name.as_ref().map(|m| format!("Shelly {}", m));
 
     ParsedInfo {
@@ -475,6 +484,68 @@ fn parse_shelly(txt: &HashMap<String, String>) -> ParsedInfo {
     }
 }
 
+/// Map Gen1 Shelly model identifiers to the app codes used by newer devices
+fn shelly_gen1_app_code(model: &str) -> Option<&'static str> {
+    Some(match model {
+        "SHSW-1" => "1",
+        "SHSW-L" => "1L",
+        "SHSW-PM" => "1PM",
+        "SHSW-25" => "25",
+        "SHPLG-1" | "SHPLG2-1" => "Plug",
+        "SHPLG-S" => "PlugS",
+        "SHDM-1" | "SHDM-2" => "Dimmer",
+        "SHRGBW2" => "RGBW2",
+        "SHBLB-1" => "Bulb",
+        "SHEM" => "EM",
+        "SHEM-3" => "3EM",
+        "SHHT-1" => "HT",
+        _ => return None,
+    })
+}
+
+/// Map Shelly app codes to human-readable model names
+fn shelly_model_name(code: &str) -> String {
+    match code {
+        // Gen 3
+        "PlugSG3" => "Plug S Gen 3",
+        "MiniG3" => "Mini Gen 3",
+        "Mini1G3" => "1PM Mini Gen 3",
+        "1G3" => "1 Gen 3",
+        "1PMG3" => "1PM Gen 3",
+        "2PMG3" => "2PM Gen 3",
+        // Gen 2 / Plus
+        "PlusPlugS" => "Plus Plug S",
+        "PlusPlugUS" => "Plus Plug US",
+        "Plus1" => "Plus 1",
+        "Plus1PM" => "Plus 1PM",
+        "Plus2PM" => "Plus 2PM",
+        "PlusI4" => "Plus i4",
+        "PlusHT" => "Plus H&T",
+        // Pro
+        "Pro1" => "Pro 1",
+        "Pro1PM" => "Pro 1PM",
+        "Pro2" => "Pro 2",
+        "Pro2PM" => "Pro 2PM",
+        "Pro3" => "Pro 3",
+        "Pro4PM" => "Pro 4PM",
+        // Gen 1
+        "1" => "1",
+        "1L" => "1L",
+        "1PM" => "1PM",
+        "25" => "2.5",
+        "Plug" => "Plug",
+        "PlugS" => "Plug S",
+        "Dimmer" => "Dimmer",
+        "RGBW2" => "RGBW2",
+        "Bulb" => "Bulb",
+        "EM" => "EM",
+        "3EM" => "3EM",
+        "HT" => "H&T",
+        _ => code,
+    }
+    .to_string()
+}
+
 /// Parse ESPHome device information
 fn parse_esphome(txt: &HashMap<String, String>, instance_name: &str) -> ParsedInfo {
     let version = txt.get("version").or_else(|| txt.get("ve")).cloned();
@@ -509,20 +580,27 @@ fn parse_spotify_connect(instance_name: &str) -> ParsedInfo {
     }
 }
 
+/// Map a Hue model ID to a device type and human-readable model name
+fn hue_model(model_id: &str) -> Option<(&'static str, &'static str)> {
+    Some(match model_id {
+        "BSB001" => ("Smart Home Hub", "Hue Bridge v1"),
+        "BSB002" => ("Smart Home Hub", "Hue Bridge v2"),
+        "BSB003" => ("Smart Home Hub", "Hue Bridge Pro"),
+        "HSB001" | "HSB1" => ("HDMI Sync Box", "Hue Play HDMI Sync Box"),
+        "HSB002" | "HSB2" => ("HDMI Sync Box", "Hue Play HDMI Sync Box 8K"),
+        id if id.starts_with("BSB") => ("Smart Home Hub", "Hue Bridge"),
+        id if id.starts_with("HSB") => ("HDMI Sync Box", "Hue Sync Box"),
+        _ => return None,
+    })
+}
+
 /// Parse Philips Hue device information
 fn parse_hue(txt: &HashMap<String, String>, instance_name: &str) -> ParsedInfo {
     let model_id = txt.get("modelid").cloned();
 
-    // Map Hue model IDs to human-readable model names
-    let (device_type, model) = match model_id.as_deref() {
-        Some("BSB001") => ("Smart Home Hub", "Hue Bridge v1"),
-        Some("BSB002") => ("Smart Home Hub", "Hue Bridge v2"),
-        Some("BSB003") => ("Smart Home Hub", "Hue Bridge Pro"),
-        Some("HSB001" | "HSB1") => ("HDMI Sync Box", "Hue Play HDMI Sync Box"),
-        Some("HSB002" | "HSB2") => ("HDMI Sync Box", "Hue Play HDMI Sync Box 8K"),
-        Some(id) if id.starts_with("BSB") => ("Smart Home Hub", "Hue Bridge"),
-        Some(id) if id.starts_with("HSB") => ("HDMI Sync Box", "Hue Sync Box"),
-        _ => {
+    let (device_type, model) = match model_id.as_deref().and_then(hue_model) {
+        Some(known) => known,
+        None => {
             // Try to infer from instance name
             let name_lower = instance_name.to_lowercase();
             if name_lower.contains("bridge") {
@@ -716,6 +794,78 @@ fn parse_aqara(
     }
 }
 
+/// Parse a WS-Discovery reply (types and scopes stored as TXT properties)
+fn parse_ws_discovery(txt: &HashMap<String, String>) -> ParsedInfo {
+    let scopes = txt.get("scopes").map(|s| s.as_str()).unwrap_or_default();
+    // Type QNames use whatever prefix the device declared
+    let types: Vec<String> = txt
+        .get("types")
+        .map(|t| {
+            t.split_whitespace()
+                .map(|qname| qname.rsplit(':').next().unwrap_or(qname).to_lowercase())
+                .collect()
+        })
+        .unwrap_or_default();
+    let has_type = |name: &str| types.iter().any(|t| t == name);
+
+    if has_type("networkvideotransmitter") || scopes.contains("onvif://www.onvif.org/") {
+        return parse_onvif(scopes);
+    }
+    if has_type("printdevicetype") {
+        return ParsedInfo {
+            device_type: Some("Printer".to_string()),
+            icon_hint: Some("printer".to_string()),
+            ..Default::default()
+        };
+    }
+    if has_type("scandevicetype") {
+        return ParsedInfo {
+            device_type: Some("Scanner".to_string()),
+            icon_hint: Some("printer".to_string()),
+            ..Default::default()
+        };
+    }
+    if has_type("computer") {
+        return ParsedInfo {
+            device_type: Some("Computer".to_string()),
+            ..Default::default()
+        };
+    }
+
+    ParsedInfo::default()
+}
+
+/// Parse ONVIF camera information from WS-Discovery scopes
+fn parse_onvif(scopes: &str) -> ParsedInfo {
+    let name = onvif_scope(scopes, "name");
+    let model = onvif_scope(scopes, "hardware");
+
+    // Cameras usually name themselves "<vendor>" or "<vendor> <model>"
+    let manufacturer = onvif_scope(scopes, "manufacturer").or_else(|| {
+        let vendor = name.as_deref()?.strip_suffix(model.as_deref()?)?.trim();
+        (!vendor.is_empty() && !vendor.contains(' ')).then(|| vendor.to_string())
+    });
+    let manufacturer = manufacturer.or_else(|| {
+        name.as_deref()
+            .filter(|n| model.is_some() && !n.contains(' '))
+            .map(|n| n.to_string())
+    });
+    let friendly_name = name.filter(|n| {
+        manufacturer.as_deref() != Some(n.as_str())
+            && !model.as_deref().is_some_and(|m| n.ends_with(m))
+    });
+
+    ParsedInfo {
+        device_type: Some("IP Camera".to_string()),
+        manufacturer,
+        model,
+        mac_address: onvif_scope(scopes, "MAC"),
+        friendly_name,
+        icon_hint: Some("camera".to_string()),
+        ..Default::default()
+    }
+}
+
 /// Parse generic HTTP/HTTPS service information
 fn parse_http_service(txt: &HashMap<String, String>, instance_name: &str) -> ParsedInfo {
     let instance_lower = instance_name.to_lowercase();
@@ -773,6 +923,107 @@ mod tests {
         assert_eq!(info.friendly_name, Some("Living Room".to_string()));
     }
 
+    #[test]
+    fn test_parse_ws_discovery_onvif() {
+        let mut txt = HashMap::new();
+        txt.insert(
+            "types".to_string(),
+            "dn:NetworkVideoTransmitter tds:Device".to_string(),
+        );
+        txt.insert(
+            "scopes".to_string(),
+            "onvif://www.onvif.org/name/HIKVISION%20DS-2CD2142FWD-I onvif://www.onvif.org/hardware/DS-2CD2142FWD-I".to_string(),
+        );
+
+        let info = parse_ws_discovery(&txt);
+        assert_eq!(info.device_type, Some("IP Camera".to_string()));
+        assert_eq!(info.manufacturer, Some("HIKVISION".to_string()));
+        assert_eq!(info.model, Some("DS-2CD2142FWD-I".to_string()));
+        assert_eq!(info.friendly_name, None);
+
+        txt.insert(
+            "scopes".to_string(),
+            "onvif://www.onvif.org/name/Front%20Door onvif://www.onvif.org/hardware/P1346"
+                .to_string(),
+        );
+        let info = parse_ws_discovery(&txt);
+        assert_eq!(info.manufacturer, None);
+        assert_eq!(info.friendly_name, Some("Front Door".to_string()));
+    }
+
+    #[test]
+    fn test_parse_ws_discovery_printer() {
+        let mut txt = HashMap::new();
+        txt.insert(
+            "types".to_string(),
+            "wsdp:Device wprt:PrintDeviceType wscn:ScanDeviceType".to_string(),
+        );
+
+        let info = parse_ws_discovery(&txt);
+        assert_eq!(info.device_type, Some("Printer".to_string()));
+        assert_eq!(info.icon_hint, Some("printer".to_string()));
+    }
+
+    #[test]
+    fn test_parse_vendor_info_hue() {
+        let hue = crate::vendor_discovery::hue::HueInfo {
+            name: "Hue Wohnzimmer".to_string(),
+            model_id: Some("BSB002".to_string()),
+            bridge_id: None,
+            software_version: Some("1967054020".to_string()),
+            api_version: None,
+            mac_address: Some("00:17:88:23:bf:c2".to_string()),
+            factory_new: Some(false),
+        };
+
+        let info = parse_vendor_info(&VendorInfo::Hue(hue));
+        assert_eq!(info.device_type, Some("Smart Home Hub".to_string()));
+        assert_eq!(info.model, Some("Hue Bridge v2".to_string()));
+        assert_eq!(info.friendly_name, Some("Hue Wohnzimmer".to_string()));
+        assert_eq!(info.firmware_version, Some("1967054020".to_string()));
+    }
+
+    #[test]
+    fn test_parse_vendor_info_shelly() {
+        let shelly = crate::vendor_discovery::shelly::ShellyInfo {
+            generation: 1,
+            model: "SHSW-PM".to_string(),
+            app: None,
+            device_id: None,
+            name: None,
+            mac_address: Some("A4CF12F45C3E".to_string()),
+            firmware_version: Some("v1.14.0".to_string()),
+            firmware_id: None,
+            auth_enabled: false,
+            cloud_enabled: Some(true),
+            cloud_connected: Some(false),
+        };
+
+        let info = parse_vendor_info(&VendorInfo::Shelly(shelly));
+        assert_eq!(info.device_type, Some("Shelly 1PM".to_string()));
+        assert_eq!(info.model, Some("1PM".to_string()));
+        assert_eq!(info.mac_address, Some("A4CF12F45C3E".to_string()));
+        assert_eq!(info.firmware_version, Some("v1.14.0".to_string()));
+    }
+
+    #[test]
+    fn test_parse_vendor_info_esphome() {
+        let esphome = crate::vendor_discovery::esphome::EsphomeInfo {
+            name: "living-room-sensor".to_string(),
+            friendly_name: Some("Living Room Sensor".to_string()),
+            mac_address: Some("AC:67:B2:3C:4D:5E".to_string()),
+            esphome_version: Some("2024.6.1".to_string()),
+            board: Some("esp32dev".to_string()),
+            ..Default::default()
+        };
+
+        let info = parse_vendor_info(&VendorInfo::Esphome(esphome));
+        assert_eq!(info.device_type, Some("ESPHome".to_string()));
+        assert_eq!(info.model, Some("esp32dev".to_string()));
+        assert_eq!(info.firmware_version, Some("2024.6.1".to_string()));
+        assert_eq!(info.friendly_name, Some("Living Room Sensor".to_string()));
+    }
+
     #[test]
     fn test_parse_shelly() {
         let mut txt = HashMap::new();
@@ -0,0 +1,773 @@
+//! Guided troubleshooting of a single target (POST
+//! `/api/targets/:id/diagnose`).
+//!
+//! Runs a fixed battery of checks: name resolution, ICMP echo over DGRAM
+//! and RAW sockets, TCP connects to common ports, a traceroute, and a path
+//! MTU sweep. The results are turned into a verdict naming the first thing
+//! that is broken ("name resolution fails", "blocked after hop 3"), so raw
+//! loss numbers come with an explanation. Progress is reported per step
+//! while the battery runs.
+
+use crate::config::{SocketType, Target};
+use crate::ping::{probe_once, Probe, ProbeOptions};
+use crate::resolver::HostResolver;
+use crate::traceroute::{traceroute, TracerouteOptions};
+use serde::Serialize;
+use std::io;
+use std::net::IpAddr;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Echo requests sent per ICMP step
+const ICMP_PROBES: u16 = 5;
+const ICMP_PROBE_INTERVAL: Duration = Duration::from_millis(200);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+const TCP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Ports tried by the TCP step, besides the target's own
+const COMMON_TCP_PORTS: [u16; 4] = [22, 53, 80, 443];
+
+/// IPv4 plus ICMP header bytes in front of an echo payload
+const ECHO_OVERHEAD: usize = 28;
+/// Payload of the smallest and largest echo requests of the MTU sweep
+const MTU_MIN_PAYLOAD: usize = 24;
+const MTU_MAX_PAYLOAD: usize = 1500 - ECHO_OVERHEAD;
+/// Attempts per packet size before it counts as too large
+const MTU_ATTEMPTS: u16 = 2;
+
+/// A check of the battery, in the order they run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepKind {
+    Dns,
+    IcmpDgram,
+    IcmpRaw,
+    Tcp,
+    Traceroute,
+    Mtu,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    Passed,
+    /// Worked, but not cleanly (e.g. some loss)
+    Warning,
+    Failed,
+    /// Not run: not applicable, not permitted, or an earlier step failed
+    Skipped,
+}
+
+/// Outcome of one step
+#[derive(Debug, Clone, Serialize)]
+pub struct StepResult {
+    pub step: StepKind,
+    pub status: StepStatus,
+    /// One-line explanation
+    pub summary: String,
+    /// Step-specific measurements
+    #[serde(skip_serializing_if = "serde_json::Value::is_null")]
+    pub details: serde_json::Value,
+}
+
+impl StepResult {
+    fn new(step: StepKind, status: StepStatus, summary: impl Into<String>) -> Self {
+        Self {
+            step,
+            status,
+            summary: summary.into(),
+            details: serde_json::Value::Null,
+        }
+    }
+
+    fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = details;
+        self
+    }
+}
+
+/// Result of a whole run
+#[derive(Debug, Clone, Serialize)]
+pub struct DiagnosisReport {
+    pub target_id: String,
+    pub address: String,
+    pub resolved_ip: Option<String>,
+    pub steps: Vec<StepResult>,
+    /// What is wrong, or that nothing is
+    pub verdict: String,
+    /// Step the verdict is based on (None if nothing is wrong)
+    pub failed_step: Option<StepKind>,
+}
+
+/// Progress of a run
+#[derive(Debug, Clone)]
+pub enum DiagnosisEvent {
+    /// A step started
+    Running(StepKind),
+    /// A step finished
+    Step(StepResult),
+}
+
+/// Settings of a run, taken from the config
+#[derive(Debug, Clone, Copy)]
+pub struct DiagnosisOptions {
+    /// Configured `[ping] socket_type`; RAW is tried in addition anyway
+    pub socket_type: SocketType,
+    pub max_hops: u8,
+}
+
+/// Echo results of an ICMP step
+#[derive(Debug, Clone, Copy, Default)]
+struct EchoOutcome {
+    sent: u16,
+    received: u16,
+    /// Every probe failed because the socket type is not permitted
+    not_permitted: bool,
+}
+
+impl EchoOutcome {
+    fn loss_percent(&self) -> f64 {
+        if self.sent == 0 {
+            return 0.0;
+        }
+        (self.sent - self.received) as f64 / self.sent as f64 * 100.0
+    }
+}
+
+/// How a TCP port answered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum PortState {
+    Open,
+    /// The host answered with a reset, so it is up
+    Refused,
+    /// No answer in time
+    Filtered,
+    Error,
+}
+
+/// Where the traceroute got to
+#[derive(Debug, Clone, Default)]
+struct TraceOutcome {
+    reached: bool,
+    /// Last answering hop before the destination: TTL and router address
+    last_hop: Option<(u8, String)>,
+}
+
+/// Facts collected by the steps, input of the verdict
+#[derive(Debug, Clone, Default)]
+struct Findings {
+    resolution_error: Option<String>,
+    dgram: Option<EchoOutcome>,
+    raw: Option<EchoOutcome>,
+    /// The target's own TCP port, for TCP targets
+    target_port: Option<u16>,
+    ports: Vec<(u16, PortState)>,
+    trace: Option<TraceOutcome>,
+    /// Largest packet that crossed the path, if the sweep ran
+    path_mtu: Option<usize>,
+}
+
+impl Findings {
+    fn port_state(&self, port: u16) -> Option<PortState> {
+        self.ports.iter().find(|(p, _)| *p == port).map(|(_, s)| *s)
+    }
+}
+
+fn is_permission_error(e: &io::Error) -> bool {
+    if e.kind() == io::ErrorKind::PermissionDenied {
+        return true;
+    }
+    // Wrapped socket errors may only carry the OS message as text
+    let message = e.to_string();
+    message.contains("Operation not permitted") || message.contains("Permission denied")
+}
+
+/// Conclude what is wrong from the findings
+fn verdict(findings: &Findings) -> (String, Option<StepKind>) {
+    if let Some(ref e) = findings.resolution_error {
+        return (format!("Name resolution fails: {}", e), Some(StepKind::Dns));
+    }
+
+    let dgram = findings.dgram.unwrap_or_default();
+    let raw = findings.raw.unwrap_or_default();
+    let icmp_answers = dgram.received > 0 || raw.received > 0;
+    let host_up_tcp = findings
+        .ports
+        .iter()
+        .find(|(_, state)| matches!(state, PortState::Open | PortState::Refused));
+
+    if let Some(port) = findings.target_port {
+        match findings.port_state(port) {
+            Some(PortState::Refused) => {
+                return (
+                    format!("Host is up but port {} refuses connections", port),
+                    Some(StepKind::Tcp),
+                )
+            }
+            Some(PortState::Filtered) if icmp_answers || host_up_tcp.is_some() => {
+                return (
+                    format!("Host is up but port {} is filtered", port),
+                    Some(StepKind::Tcp),
+                )
+            }
+            _ => {}
+        }
+    }
+
+    if !icmp_answers {
+        if let Some((port, _)) = host_up_tcp {
+            return (
+                format!(
+                    "Host is up but does not answer ICMP echo; probe it over TCP (port {} answers)",
+                    port
+                ),
+                Some(StepKind::IcmpDgram),
+            );
+        }
+        return match findings.trace {
+            Some(TraceOutcome {
+                last_hop: Some((ttl, ref address)),
+                reached: false,
+            }) => (
+                format!("Blocked after hop {} ({})", ttl, address),
+                Some(StepKind::Traceroute),
+            ),
+            Some(TraceOutcome {
+                last_hop: None,
+                reached: false,
+            }) => (
+                "No router on the path answers; check the local network and gateway".to_string(),
+                Some(StepKind::Traceroute),
+            ),
+            _ => (
+                "Target does not respond to ICMP or TCP".to_string(),
+                Some(StepKind::IcmpDgram),
+            ),
+        };
+    }
+
+    if dgram.received == 0 && raw.received > 0 {
+        let verdict = if dgram.not_permitted {
+            "Unprivileged ICMP sockets are not permitted; allow them with net.ipv4.ping_group_range or set socket_type = \"raw\""
+        } else {
+            "DGRAM ICMP sockets get no replies while RAW sockets do; set socket_type = \"raw\""
+        };
+        return (verdict.to_string(), Some(StepKind::IcmpDgram));
+    }
+
+    let loss = dgram.loss_percent();
+    if loss > 0.0 {
+        return (
+            format!("Reachable with {:.0}% loss", loss),
+            Some(StepKind::IcmpDgram),
+        );
+    }
+    if let Some(mtu) = findings.path_mtu.filter(|mtu| *mtu < 1500) {
+        return (
+            format!(
+                "Reachable, but the path MTU is {} bytes; larger packets are dropped",
+                mtu
+            ),
+            Some(StepKind::Mtu),
+        );
+    }
+    ("Reachable; no problem found".to_string(), None)
+}
+
+/// Largest payload in `[min, max]` for which `fits` holds, assuming `min`
+/// fits. Binary search, so `fits` is called about log2(max - min) times.
+fn largest_fitting(min: usize, max: usize, mut fits: impl FnMut(usize) -> bool) -> usize {
+    if fits(max) {
+        return max;
+    }
+    let (mut low, mut high) = (min, max);
+    while high - low > 1 {
+        let middle = low + (high - low) / 2;
+        if fits(middle) {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    low
+}
+
+async fn echo_step(
+    step: StepKind,
+    ip: IpAddr,
+    socket_type: SocketType,
+) -> (StepResult, EchoOutcome) {
+    let mut outcome = EchoOutcome::default();
+    let mut latencies = Vec::new();
+    let mut errors = Vec::new();
+    for sequence in 1..=ICMP_PROBES {
+        if sequence > 1 {
+            tokio::time::sleep(ICMP_PROBE_INTERVAL).await;
+        }
+        outcome.sent += 1;
+        let options = ProbeOptions::with_timeout(PROBE_TIMEOUT);
+        match probe_once(ip, Probe::Icmp(socket_type), options, None).await {
+            Ok(latency) => {
+                outcome.received += 1;
+                latencies.push(latency);
+            }
+            Err(e) => errors.push(e),
+        }
+    }
+    outcome.not_permitted = !errors.is_empty()
+        && errors.len() == outcome.sent as usize
+        && errors.iter().all(is_permission_error);
+
+    let avg_latency_ms =
+        (!latencies.is_empty()).then(|| latencies.iter().sum::<f64>() / latencies.len() as f64);
+    let details = serde_json::json!({
+        "socket_type": socket_type,
+        "sent": outcome.sent,
+        "received": outcome.received,
+        "loss_percent": outcome.loss_percent(),
+        "avg_latency_ms": avg_latency_ms,
+        "error": errors.last().map(|e| e.to_string()),
+    });
+    let result = if outcome.not_permitted {
+        StepResult::new(
+            step,
+            StepStatus::Skipped,
+            format!("{} ICMP sockets are not permitted", socket_type.as_str()),
+        )
+    } else if outcome.received == outcome.sent {
+        StepResult::new(
+            step,
+            StepStatus::Passed,
+            format!("{} of {} echo replies", outcome.received, outcome.sent),
+        )
+    } else if outcome.received > 0 {
+        StepResult::new(
+            step,
+            StepStatus::Warning,
+            format!("{:.0}% loss", outcome.loss_percent()),
+        )
+    } else {
+        StepResult::new(step, StepStatus::Failed, "No echo replies")
+    };
+    (result.with_details(details), outcome)
+}
+
+async fn tcp_step(ip: IpAddr, ports: &[u16]) -> (StepResult, Vec<(u16, PortState)>) {
+    let probes = ports.iter().map(|&port| async move {
+        let options = ProbeOptions::with_timeout(TCP_TIMEOUT);
+        let state = match probe_once(ip, Probe::Tcp(port), options, None).await {
+            Ok(_) => PortState::Open,
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => PortState::Refused,
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => PortState::Filtered,
+            Err(_) => PortState::Error,
+        };
+        (port, state)
+    });
+    let states = futures::future::join_all(probes).await;
+
+    let with_state = |wanted: PortState| -> Vec<u16> {
+        states
+            .iter()
+            .filter(|(_, s)| *s == wanted)
+            .map(|(p, _)| *p)
+            .collect()
+    };
+    let open = with_state(PortState::Open);
+    let refused = with_state(PortState::Refused);
+    let join = |ports: &[u16]| {
+        ports
+            .iter()
+            .map(|p| p.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let result = if !open.is_empty() {
+        StepResult::new(
+            StepKind::Tcp,
+            StepStatus::Passed,
+            format!("Open: {}", join(&open)),
+        )
+    } else if !refused.is_empty() {
+        StepResult::new(
+            StepKind::Tcp,
+            StepStatus::Warning,
+            format!("Host is up, but refused: {}", join(&refused)),
+        )
+    } else {
+        StepResult::new(StepKind::Tcp, StepStatus::Failed, "No port answered")
+    };
+    let details: serde_json::Map<String, serde_json::Value> = states
+        .iter()
+        .map(|(port, state)| (port.to_string(), serde_json::json!(state)))
+        .collect();
+    (result.with_details(details.into()), states)
+}
+
+async fn traceroute_step(
+    ip: IpAddr,
+    options: DiagnosisOptions,
+) -> (StepResult, Option<TraceOutcome>) {
+    let trace_options = TracerouteOptions {
+        max_hops: options.max_hops,
+        probes_per_hop: 2,
+        timeout: PROBE_TIMEOUT,
+        socket_type: options.socket_type,
+    };
+    let hops = match traceroute(ip, trace_options).await {
+        Ok(hops) => hops,
+        Err(e) => {
+            return (
+                StepResult::new(
+                    StepKind::Traceroute,
+                    StepStatus::Skipped,
+                    format!("Traceroute not possible: {}", e),
+                ),
+                None,
+            )
+        }
+    };
+
+    let destination = ip.to_string();
+    let reached = hops
+        .iter()
+        .any(|h| h.address.as_deref() == Some(destination.as_str()));
+    let last_hop = hops
+        .iter()
+        .filter(|h| h.address.as_deref() != Some(destination.as_str()))
+        .filter_map(|h| h.address.clone().map(|address| (h.ttl, address)))
+        .next_back();
+
+    let result = match (reached, &last_hop) {
+        (true, _) => StepResult::new(
+            StepKind::Traceroute,
+            StepStatus::Passed,
+            format!("Reached the target in {} hops", hops.len()),
+        ),
+        (false, Some((ttl, address))) => StepResult::new(
+            StepKind::Traceroute,
+            StepStatus::Failed,
+            format!("Blocked after hop {} ({})", ttl, address),
+        ),
+        (false, None) => {
+            StepResult::new(StepKind::Traceroute, StepStatus::Failed, "No hop answered")
+        }
+    };
+    (
+        result.with_details(serde_json::json!({ "hops": hops })),
+        Some(TraceOutcome { reached, last_hop }),
+    )
+}
+
+#[cfg(target_os = "linux")]
+async fn mtu_step(ip: IpAddr) -> (StepResult, Option<usize>) {
+    let sweep = tokio::task::spawn_blocking(move || {
+        let mut sequence = 0u16;
+        largest_fitting(MTU_MIN_PAYLOAD, MTU_MAX_PAYLOAD, |payload| {
+            (0..MTU_ATTEMPTS).any(|_| {
+                sequence = sequence.wrapping_add(1);
+                crate::icmp::ping_dgram_sized(ip, PROBE_TIMEOUT, sequence, payload).is_ok()
+            })
+        })
+    })
+    .await;
+
+    let payload = match sweep {
+        Ok(payload) => payload,
+        Err(e) => {
+            return (
+                StepResult::new(
+                    StepKind::Mtu,
+                    StepStatus::Skipped,
+                    format!("MTU sweep failed: {}", e),
+                ),
+                None,
+            )
+        }
+    };
+    let mtu = payload + ECHO_OVERHEAD;
+    let result = if payload == MTU_MAX_PAYLOAD {
+        StepResult::new(
+            StepKind::Mtu,
+            StepStatus::Passed,
+            format!("Packets of {} bytes pass", mtu),
+        )
+    } else {
+        StepResult::new(
+            StepKind::Mtu,
+            StepStatus::Warning,
+            format!("Path MTU is {} bytes", mtu),
+        )
+    };
+    (
+        result.with_details(serde_json::json!({ "path_mtu": mtu })),
+        Some(mtu),
+    )
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn mtu_step(_ip: IpAddr) -> (StepResult, Option<usize>) {
+    (
+        StepResult::new(
+            StepKind::Mtu,
+            StepStatus::Skipped,
+            "MTU sweep is only supported on Linux",
+        ),
+        None,
+    )
+}
+
+/// Report a finished step and add it to the run's results
+async fn finish(
+    progress: &mpsc::Sender<DiagnosisEvent>,
+    steps: &mut Vec<StepResult>,
+    result: StepResult,
+) {
+    let _ = progress.send(DiagnosisEvent::Step(result.clone())).await;
+    steps.push(result);
+}
+
+/// Run the battery against `target`, reporting progress on `progress`.
+/// The run stops early once the receiver is gone.
+pub async fn diagnose(
+    target: &Target,
+    options: DiagnosisOptions,
+    progress: &mpsc::Sender<DiagnosisEvent>,
+) -> DiagnosisReport {
+    let mut findings = Findings {
+        target_port: target.tcp_port(),
+        ..Findings::default()
+    };
+    let mut steps: Vec<StepResult> = Vec::new();
+
+    let _ = progress.send(DiagnosisEvent::Running(StepKind::Dns)).await;
+    let resolved = HostResolver::new(&target.address, 0).resolve().await;
+    let ip = match resolved {
+        Ok(ip) => {
+            let summary = if target.address.parse::<IpAddr>().is_ok() {
+                "Address is an IP literal".to_string()
+            } else {
+                format!("Resolved to {}", ip)
+            };
+            let result = StepResult::new(StepKind::Dns, StepStatus::Passed, summary);
+            finish(progress, &mut steps, result).await;
+            Some(ip)
+        }
+        Err(e) => {
+            let result = StepResult::new(
+                StepKind::Dns,
+                StepStatus::Failed,
+                format!("Resolution fails: {}", e),
+            );
+            finish(progress, &mut steps, result).await;
+            findings.resolution_error = Some(e);
+            None
+        }
+    };
+
+    let dgram_socket_type = match options.socket_type {
+        SocketType::Raw => SocketType::DgramNative,
+        socket_type => socket_type,
+    };
+    let remaining = [
+        StepKind::IcmpDgram,
+        StepKind::IcmpRaw,
+        StepKind::Tcp,
+        StepKind::Traceroute,
+        StepKind::Mtu,
+    ];
+    for step in remaining {
+        if progress.is_closed() {
+            break;
+        }
+        let Some(ip) = ip else {
+            let result = StepResult::new(step, StepStatus::Skipped, "Skipped: no address to test");
+            finish(progress, &mut steps, result).await;
+            continue;
+        };
+        let _ = progress.send(DiagnosisEvent::Running(step)).await;
+
+        let result = match step {
+            StepKind::Dns => continue,
+            StepKind::IcmpDgram => {
+                let (result, outcome) = echo_step(step, ip, dgram_socket_type).await;
+                findings.dgram = Some(outcome);
+                result
+            }
+            StepKind::IcmpRaw => {
+                let (result, outcome) = echo_step(step, ip, SocketType::Raw).await;
+                findings.raw = Some(outcome);
+                result
+            }
+            StepKind::Tcp => {
+                let mut ports = COMMON_TCP_PORTS.to_vec();
+                if let Some(port) = findings.target_port.filter(|p| !ports.contains(p)) {
+                    ports.insert(0, port);
+                }
+                let (result, states) = tcp_step(ip, &ports).await;
+                findings.ports = states;
+                result
+            }
+            StepKind::Traceroute | StepKind::Mtu if ip.is_ipv6() => {
+                StepResult::new(step, StepStatus::Skipped, "Only supported for IPv4 targets")
+            }
+            StepKind::Traceroute => {
+                let (result, trace) = traceroute_step(ip, options).await;
+                findings.trace = trace;
+                result
+            }
+            StepKind::Mtu => {
+                if findings.dgram.is_some_and(|o| o.received > 0) {
+                    let (result, mtu) = mtu_step(ip).await;
+                    findings.path_mtu = mtu;
+                    result
+                } else {
+                    StepResult::new(
+                        step,
+                        StepStatus::Skipped,
+                        "Skipped: the target does not answer DGRAM ICMP echo",
+                    )
+                }
+            }
+        };
+        finish(progress, &mut steps, result).await;
+    }
+
+    let (verdict, failed_step) = verdict(&findings);
+    DiagnosisReport {
+        target_id: target.id.clone(),
+        address: target.address.clone(),
+        resolved_ip: ip.map(|ip| ip.to_string()),
+        steps,
+        verdict,
+        failed_step,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn echo(sent: u16, received: u16) -> Option<EchoOutcome> {
+        Some(EchoOutcome {
+            sent,
+            received,
+            not_permitted: false,
+        })
+    }
+
+    #[test]
+    fn test_verdict_resolution_fails() {
+        let findings = Findings {
+            resolution_error: Some("no such host".to_string()),
+            ..Findings::default()
+        };
+        let (verdict, step) = verdict(&findings);
+        assert_eq!(verdict, "Name resolution fails: no such host");
+        assert_eq!(step, Some(StepKind::Dns));
+    }
+
+    #[test]
+    fn test_verdict_blocked_after_hop() {
+        let findings = Findings {
+            dgram: echo(5, 0),
+            raw: echo(5, 0),
+            ports: vec![(80, PortState::Filtered)],
+            trace: Some(TraceOutcome {
+                reached: false,
+                last_hop: Some((3, "10.0.0.1".to_string())),
+            }),
+            ..Findings::default()
+        };
+        let (verdict, step) = verdict(&findings);
+        assert_eq!(verdict, "Blocked after hop 3 (10.0.0.1)");
+        assert_eq!(step, Some(StepKind::Traceroute));
+    }
+
+    #[test]
+    fn test_verdict_icmp_filtered_but_tcp_answers() {
+        let findings = Findings {
+            dgram: echo(5, 0),
+            raw: echo(5, 0),
+            ports: vec![(22, PortState::Filtered), (443, PortState::Open)],
+            ..Findings::default()
+        };
+        let (verdict, step) = verdict(&findings);
+        assert!(verdict.contains("port 443 answers"));
+        assert_eq!(step, Some(StepKind::IcmpDgram));
+    }
+
+    #[test]
+    fn test_verdict_tcp_target_port_refused() {
+        let findings = Findings {
+            dgram: echo(5, 5),
+            target_port: Some(8080),
+            ports: vec![(8080, PortState::Refused)],
+            ..Findings::default()
+        };
+        assert_eq!(
+            verdict(&findings).0,
+            "Host is up but port 8080 refuses connections"
+        );
+    }
+
+    #[test]
+    fn test_verdict_dgram_not_permitted() {
+        let findings = Findings {
+            dgram: Some(EchoOutcome {
+                sent: 5,
+                received: 0,
+                not_permitted: true,
+            }),
+            raw: echo(5, 5),
+            ..Findings::default()
+        };
+        assert!(verdict(&findings).0.contains("ping_group_range"));
+    }
+
+    #[test]
+    fn test_verdict_loss_and_mtu() {
+        let lossy = Findings {
+            dgram: echo(5, 4),
+            ..Findings::default()
+        };
+        assert_eq!(verdict(&lossy).0, "Reachable with 20% loss");
+
+        let small_mtu = Findings {
+            dgram: echo(5, 5),
+            path_mtu: Some(1492),
+            ..Findings::default()
+        };
+        assert_eq!(verdict(&small_mtu).1, Some(StepKind::Mtu));
+
+        let healthy = Findings {
+            dgram: echo(5, 5),
+            path_mtu: Some(1500),
+            ..Findings::default()
+        };
+        assert_eq!(
+            verdict(&healthy),
+            ("Reachable; no problem found".to_string(), None)
+        );
+    }
+
+    #[test]
+    fn test_largest_fitting() {
+        let mut calls = 0;
+        let found = largest_fitting(MTU_MIN_PAYLOAD, MTU_MAX_PAYLOAD, |payload| {
+            calls += 1;
+            payload <= 1464
+        });
+        assert_eq!(found, 1464);
+        assert!(calls <= 12);
+        assert_eq!(
+            largest_fitting(MTU_MIN_PAYLOAD, MTU_MAX_PAYLOAD, |_| true),
+            MTU_MAX_PAYLOAD
+        );
+        assert_eq!(
+            largest_fitting(MTU_MIN_PAYLOAD, MTU_MAX_PAYLOAD, |p| p == MTU_MIN_PAYLOAD),
+            MTU_MIN_PAYLOAD
+        );
+    }
+}
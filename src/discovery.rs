@@ -6,6 +6,7 @@
 //! Uses pure Rust mDNS implementation (mdns-sd) that works on all platforms
 //! without requiring system dependencies.
 
+use crate::ip_scan::GatewayInfo;
 use crate::vendor_discovery::VendorInfo;
 use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
 use serde::Serialize;
@@ -51,6 +52,15 @@ pub struct DiscoveredDevice {
     /// Vendor-specific information (fetched from device APIs)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub vendor_info: Option<VendorInfo>,
+    /// TCP ports found open by an IP scan (empty for mDNS-only devices)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub open_ports: Vec<u16>,
+    /// Set when an IP scan identified this device as the subnet's gateway
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gateway: Option<GatewayInfo>,
+    /// MAC address from the neighbor table (ARP discovery only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mac: Option<String>,
 }
 
 /// Event sent during device discovery
@@ -71,6 +81,17 @@ pub enum DiscoveryEvent {
     },
     /// An error occurred during discovery
     Error { message: String },
+    /// Periodic progress of an IP scan
+    Progress {
+        /// Addresses checked so far
+        scanned: usize,
+        /// Addresses in the scanned range
+        total: usize,
+        /// Devices found so far
+        found: usize,
+        /// Estimated seconds until the scan completes, once known
+        eta_seconds: Option<u64>,
+    },
 }
 
 /// The DNS-SD meta-query service type that returns all available service types
@@ -400,6 +421,9 @@ fn create_device_from_service(
         ttl: None,
         discovery_method: "mdns".to_string(),
         vendor_info: None,
+        open_ports: Vec::new(),
+        gateway: None,
+        mac: None,
     }
 }
 
@@ -427,6 +451,9 @@ mod tests {
             ttl: None,
             discovery_method: "mdns".to_string(),
             vendor_info: None,
+            open_ports: Vec::new(),
+            gateway: None,
+            mac: None,
         };
 
         let event = DiscoveryEvent::DeviceFound { device };
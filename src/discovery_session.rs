@@ -0,0 +1,642 @@
+//! Shared discovery sessions.
+//!
+//! Every discovery stream used to start its own scan, so a dashboard open in
+//! several tabs ran several mDNS browsers and IP scans side by side. Streams
+//! asking for the same discovery (same methods and parameters) now subscribe
+//! to one running session instead. A subscriber joining late first receives
+//! the devices found so far, then the live events. The scan is stopped when
+//! the last subscriber disconnects, and a finished session is forgotten so
+//! the next request scans again.
+//!
+//! A session started as a job (POST /api/discovery/jobs) keeps running
+//! without subscribers until it completes or is cancelled by its id. At most
+//! `MAX_RUNNING_JOBS` jobs run at once.
+//! Devices found by any session are remembered for
+//! GET /api/discovery/untracked.
+
+use crate::device_identification::{IdentifiedDevice, IdentifiedDiscoveryEvent};
+use crate::ip_scan::ScanProgress;
+use crate::storage::StorageBackend;
+use crate::telemetry::telemetry;
+use crate::unified_discovery::{
+    run_unified_discovery, send_update, DiscoveryStreamStats, StreamBackpressure,
+    UnifiedDiscoveryConfig, CLIENT_CHANNEL_CAPACITY,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tokio::task::AbortHandle;
+use tracing::info;
+use utoipa::ToSchema;
+
+/// Capacity of the queue between the scan and the session's subscribers
+const SESSION_CHANNEL_CAPACITY: usize = 1000;
+
+/// Devices remembered from past sessions; the longest unseen are forgotten
+/// beyond this
+const MAX_SEEN_DEVICES: usize = 4096;
+
+/// Discovery jobs that may run at once; they scan without a client
+/// holding them open, so per-requester quotas don't bound them
+pub const MAX_RUNNING_JOBS: usize = 4;
+
+/// Running discovery sessions, keyed by their configuration
+#[derive(Debug, Default)]
+pub struct DiscoverySessions {
+    sessions: Mutex<HashMap<String, Arc<DiscoverySession>>>,
+    /// Held while a job is started, so that two requests cannot both take
+    /// the last job slot
+    job_start: Mutex<()>,
+    /// Latest state of every device any session found, by primary address,
+    /// with the Unix timestamp it was last reported at
+    seen: Mutex<HashMap<String, (IdentifiedDevice, i64)>>,
+}
+
+/// One running discovery shared by its subscribers
+#[derive(Debug)]
+struct DiscoverySession {
+    id: String,
+    key: String,
+    config: UnifiedDiscoveryConfig,
+    /// Unix timestamp in seconds
+    started_at: i64,
+    progress: Arc<ScanProgress>,
+    state: Mutex<SessionState>,
+}
+
+#[derive(Debug, Default)]
+struct SessionState {
+    /// The started event, replayed to late subscribers
+    started: Option<IdentifiedDiscoveryEvent>,
+    /// Latest state of every device found so far, in discovery order
+    devices: Vec<IdentifiedDevice>,
+    device_index: HashMap<String, usize>,
+    /// Latest IP scan progress, replayed after the devices
+    progress: Option<IdentifiedDiscoveryEvent>,
+    subscribers: Vec<Subscriber>,
+    next_subscriber: u64,
+    task: Option<AbortHandle>,
+    /// Started as a job: keeps running without subscribers
+    job: bool,
+    finished: bool,
+}
+
+#[derive(Debug)]
+struct Subscriber {
+    id: u64,
+    tx: mpsc::Sender<IdentifiedDiscoveryEvent>,
+    backpressure: Arc<StreamBackpressure>,
+}
+
+/// Status of a running session (GET /api/discovery/status)
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct DiscoverySessionStatus {
+    pub id: String,
+    /// Enabled methods, e.g. "mDNS", "IP Scan"
+    pub methods: Vec<String>,
+    /// Scanned range of the IP scan or ARP sweep (CIDR or "start-end")
+    pub range: Option<String>,
+    /// Unix timestamp in seconds
+    pub started_at: i64,
+    /// Started with POST /api/discovery/jobs
+    pub job: bool,
+    /// Open streams receiving the session's events
+    pub subscribers: usize,
+    /// Devices found so far
+    pub device_count: usize,
+    /// Addresses the IP scan has checked so far
+    pub scanned_ips: usize,
+    /// Addresses in the IP scan range (0 without an IP scan or before it
+    /// started)
+    pub total_ips: usize,
+}
+
+/// A stream's membership in a session; leaving the session when dropped
+/// stops the scan if it was the last subscriber
+#[derive(Debug)]
+pub struct DiscoverySubscription {
+    sessions: Arc<DiscoverySessions>,
+    session: Arc<DiscoverySession>,
+    id: u64,
+    /// Events of the session so far, to be sent before the live ones
+    pub replay: Vec<IdentifiedDiscoveryEvent>,
+    pub rx: mpsc::Receiver<IdentifiedDiscoveryEvent>,
+}
+
+impl Drop for DiscoverySubscription {
+    fn drop(&mut self) {
+        let mut state = self.session.lock_state();
+        state.subscribers.retain(|s| s.id != self.id);
+        if state.subscribers.is_empty() && !state.job && !state.finished {
+            info!(
+                "Last subscriber left discovery session {}, stopping it",
+                self.session.id
+            );
+            state.finished = true;
+            if let Some(task) = state.task.take() {
+                task.abort();
+            }
+            drop(state);
+            self.sessions.remove(&self.session);
+        }
+    }
+}
+
+impl DiscoverySession {
+    fn lock_state(&self) -> std::sync::MutexGuard<'_, SessionState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn is_running_job(&self) -> bool {
+        let state = self.lock_state();
+        state.job && !state.finished
+    }
+
+    fn status(&self) -> DiscoverySessionStatus {
+        let state = self.lock_state();
+        let config = &self.config;
+        let methods = [
+            (config.mdns_enabled, "mDNS"),
+            (
+                config.ip_scan_enabled && config.ip_scan.is_some(),
+                "IP Scan",
+            ),
+            (config.arp_enabled, "ARP"),
+            (config.ws_discovery_enabled, "WS-Discovery"),
+        ]
+        .into_iter()
+        .filter(|(enabled, _)| *enabled)
+        .map(|(_, method)| method.to_string())
+        .collect();
+        let range = config.ip_scan.as_ref().and_then(|scan| {
+            match (&scan.cidr, &scan.start_ip, &scan.end_ip) {
+                (Some(cidr), _, _) => Some(cidr.clone()),
+                (None, Some(start), Some(end)) => Some(format!("{}-{}", start, end)),
+                _ => None,
+            }
+        });
+        let (scanned_ips, total_ips) = self.progress.get();
+
+        DiscoverySessionStatus {
+            id: self.id.clone(),
+            methods,
+            range,
+            started_at: self.started_at,
+            job: state.job,
+            subscribers: state.subscribers.len(),
+            device_count: state.devices.len(),
+            scanned_ips,
+            total_ips,
+        }
+    }
+
+    /// Stop the scan and end every subscriber's stream with an error event.
+    /// Returns false if the session was already over.
+    fn cancel(&self) -> bool {
+        let mut state = self.lock_state();
+        if state.finished {
+            return false;
+        }
+        state.finished = true;
+        if let Some(task) = state.task.take() {
+            task.abort();
+        }
+        let event = IdentifiedDiscoveryEvent::Error {
+            message: "Discovery was cancelled".to_string(),
+        };
+        for subscriber in std::mem::take(&mut state.subscribers) {
+            send_final(subscriber.tx, event.clone());
+        }
+        true
+    }
+
+    /// Add a subscriber, returning its id, the replay and its receiver.
+    /// Runs under the state lock, so no event is missed or sent twice.
+    fn subscribe(
+        &self,
+        stats: &Arc<DiscoveryStreamStats>,
+    ) -> (
+        u64,
+        Vec<IdentifiedDiscoveryEvent>,
+        mpsc::Receiver<IdentifiedDiscoveryEvent>,
+        Arc<StreamBackpressure>,
+    ) {
+        let (tx, rx) = mpsc::channel(CLIENT_CHANNEL_CAPACITY);
+        let backpressure = stats.open_stream();
+        let mut state = self.lock_state();
+        let replay = state
+            .started
+            .iter()
+            .cloned()
+            .chain(
+                state
+                    .devices
+                    .iter()
+                    .map(|device| IdentifiedDiscoveryEvent::DeviceFound {
+                        device: device.clone(),
+                    }),
+            )
+            .chain(state.progress.iter().cloned())
+            .collect();
+        let id = state.next_subscriber;
+        state.next_subscriber += 1;
+        state.subscribers.push(Subscriber {
+            id,
+            tx,
+            backpressure: Arc::clone(&backpressure),
+        });
+        (id, replay, rx, backpressure)
+    }
+
+    /// Record an event and pass it on to every subscriber. Returns false
+    /// once the session is over.
+    fn publish(&self, event: IdentifiedDiscoveryEvent) -> bool {
+        let mut state = self.lock_state();
+        match &event {
+            IdentifiedDiscoveryEvent::Started { .. } => state.started = Some(event.clone()),
+            IdentifiedDiscoveryEvent::DeviceFound { device }
+            | IdentifiedDiscoveryEvent::DeviceUpdated { device } => {
+                let address = device.device_info.primary_address.clone();
+                match state.device_index.get(&address) {
+                    Some(&index) => state.devices[index] = device.clone(),
+                    None => {
+                        let index = state.devices.len();
+                        state.device_index.insert(address, index);
+                        state.devices.push(device.clone());
+                    }
+                }
+            }
+            IdentifiedDiscoveryEvent::Completed { .. } | IdentifiedDiscoveryEvent::Error { .. } => {
+                state.finished = true;
+            }
+            IdentifiedDiscoveryEvent::Progress { .. } => state.progress = Some(event.clone()),
+            IdentifiedDiscoveryEvent::Heartbeat { .. } => {}
+        }
+
+        // Slow subscribers lose device updates rather than holding up the
+        // others; the final event must reach everyone
+        if state.finished {
+            for subscriber in std::mem::take(&mut state.subscribers) {
+                send_final(subscriber.tx, event.clone());
+            }
+            return false;
+        }
+        state.subscribers.retain(|subscriber| {
+            send_update(&subscriber.tx, event.clone(), &subscriber.backpressure)
+        });
+        true
+    }
+}
+
+/// Send the last event of a stream, waiting for queue space if needed
+fn send_final(tx: mpsc::Sender<IdentifiedDiscoveryEvent>, event: IdentifiedDiscoveryEvent) {
+    if let Err(mpsc::error::TrySendError::Full(event)) = tx.try_send(event) {
+        tokio::spawn(async move {
+            let _ = tx.send(event).await;
+        });
+    }
+}
+
+/// Sessions with the same configuration are shared
+fn session_key(config: &UnifiedDiscoveryConfig) -> String {
+    serde_json::to_string(config).unwrap_or_default()
+}
+
+impl DiscoverySessions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Subscribe to the running session with the same configuration, or
+    /// start one
+    pub fn subscribe(
+        self: &Arc<Self>,
+        config: UnifiedDiscoveryConfig,
+        storage: Arc<dyn StorageBackend>,
+        stats: &Arc<DiscoveryStreamStats>,
+    ) -> (DiscoverySubscription, Arc<StreamBackpressure>) {
+        let (session, (id, replay, rx, backpressure)) =
+            self.open(config, storage, stats, |session| session.subscribe(stats));
+        let subscription = DiscoverySubscription {
+            sessions: Arc::clone(self),
+            session,
+            id,
+            replay,
+            rx,
+        };
+        (subscription, backpressure)
+    }
+
+    /// Start a job: a session that runs until it completes or is cancelled,
+    /// whether or not streams are subscribed. A running session with the
+    /// same configuration becomes the job. None if `MAX_RUNNING_JOBS` other
+    /// jobs are running.
+    pub fn start_job(
+        self: &Arc<Self>,
+        config: UnifiedDiscoveryConfig,
+        storage: Arc<dyn StorageBackend>,
+        stats: &Arc<DiscoveryStreamStats>,
+    ) -> Option<DiscoverySessionStatus> {
+        let _starting = self.job_start.lock().unwrap_or_else(|e| e.into_inner());
+        if !self.job_slot_free(&session_key(&config)) {
+            return None;
+        }
+        let (session, ()) = self.open(config, storage, stats, |session| {
+            session.lock_state().job = true;
+        });
+        Some(session.status())
+    }
+
+    /// Whether a job with the given key can start: it is already running,
+    /// or fewer than `MAX_RUNNING_JOBS` jobs are
+    fn job_slot_free(&self, key: &str) -> bool {
+        let sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        if sessions.get(key).is_some_and(|s| s.is_running_job()) {
+            return true;
+        }
+        sessions.values().filter(|s| s.is_running_job()).count() < MAX_RUNNING_JOBS
+    }
+
+    /// Cancel the running session with the given id. Returns false if there
+    /// is none.
+    pub fn cancel(&self, id: &str) -> bool {
+        let session = self
+            .sessions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .find(|session| session.id == id)
+            .cloned();
+        match session {
+            Some(session) if session.cancel() => {
+                info!("Cancelled discovery session {}", session.id);
+                self.remove(&session);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Find the running session with the same configuration or create one,
+    /// call `join` on it, then start the scan of a new session. `join` runs
+    /// before the scan starts, so a new session's first events reach the
+    /// first subscriber.
+    fn open<R>(
+        self: &Arc<Self>,
+        config: UnifiedDiscoveryConfig,
+        storage: Arc<dyn StorageBackend>,
+        stats: &Arc<DiscoveryStreamStats>,
+        join: impl FnOnce(&DiscoverySession) -> R,
+    ) -> (Arc<DiscoverySession>, R) {
+        let key = session_key(&config);
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+
+        // A session that just finished may not have been removed yet
+        let running = sessions
+            .get(&key)
+            .filter(|session| !session.lock_state().finished)
+            .cloned();
+        let (session, joined) = match running {
+            Some(session) => (session, true),
+            None => {
+                let session = Arc::new(DiscoverySession {
+                    id: uuid::Uuid::new_v4().to_string(),
+                    key: key.clone(),
+                    config: config.clone(),
+                    started_at: chrono::Utc::now().timestamp(),
+                    progress: Arc::new(ScanProgress::new()),
+                    state: Mutex::new(SessionState::default()),
+                });
+                sessions.insert(key, Arc::clone(&session));
+                (session, false)
+            }
+        };
+        let joined_with = join(&session);
+        drop(sessions);
+
+        if joined {
+            info!("Joined running discovery session {}", session.id);
+        } else {
+            info!("Starting discovery session {}", session.id);
+            let task = tokio::spawn(Self::run(
+                Arc::clone(self),
+                Arc::clone(&session),
+                config,
+                storage,
+                stats.session_backpressure(),
+            ));
+            let mut state = session.lock_state();
+            // Cancelled before the handle was stored
+            if state.finished {
+                task.abort();
+            } else {
+                state.task = Some(task.abort_handle());
+            }
+        }
+        (session, joined_with)
+    }
+
+    /// Status of every running session
+    pub fn status(&self) -> Vec<DiscoverySessionStatus> {
+        let sessions: Vec<Arc<DiscoverySession>> = self
+            .sessions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .cloned()
+            .collect();
+        let mut status: Vec<DiscoverySessionStatus> =
+            sessions.iter().map(|session| session.status()).collect();
+        status.sort_by_key(|s| s.started_at);
+        status
+    }
+
+    /// Devices found by discovery since startup, with the Unix timestamp
+    /// they were last reported at, most recent first
+    pub fn seen_devices(&self) -> Vec<(IdentifiedDevice, i64)> {
+        let seen = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+        let mut devices: Vec<(IdentifiedDevice, i64)> = seen.values().cloned().collect();
+        devices.sort_by(|a, b| b.1.cmp(&a.1));
+        devices
+    }
+
+    fn remember(&self, device: &IdentifiedDevice) {
+        let mut seen = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+        let now = chrono::Utc::now().timestamp();
+        seen.insert(
+            device.device_info.primary_address.clone(),
+            (device.clone(), now),
+        );
+        if seen.len() > MAX_SEEN_DEVICES {
+            if let Some(oldest) = seen
+                .iter()
+                .min_by_key(|(_, (_, seen_at))| *seen_at)
+                .map(|(address, _)| address.clone())
+            {
+                seen.remove(&oldest);
+            }
+        }
+    }
+
+    /// Forget a session unless a newer one took its place
+    fn remove(&self, session: &Arc<DiscoverySession>) {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        if sessions
+            .get(&session.key)
+            .is_some_and(|s| Arc::ptr_eq(s, session))
+        {
+            sessions.remove(&session.key);
+        }
+    }
+
+    /// Run the discovery and fan its events out to the subscribers
+    async fn run(
+        sessions: Arc<Self>,
+        session: Arc<DiscoverySession>,
+        config: UnifiedDiscoveryConfig,
+        storage: Arc<dyn StorageBackend>,
+        backpressure: Arc<StreamBackpressure>,
+    ) {
+        let started = std::time::Instant::now();
+        let progress = Arc::clone(&session.progress);
+        let (tx, mut rx) = mpsc::channel(SESSION_CHANNEL_CAPACITY);
+        let fan_out = async {
+            while let Some(event) = rx.recv().await {
+                if let IdentifiedDiscoveryEvent::DeviceFound { device }
+                | IdentifiedDiscoveryEvent::DeviceUpdated { device } = &event
+                {
+                    sessions.remember(device);
+                }
+                if !session.publish(event) {
+                    break;
+                }
+            }
+        };
+        tokio::join!(
+            run_unified_discovery(tx, config, storage, backpressure, progress),
+            fan_out
+        );
+        telemetry().record_discovery_run(started.elapsed());
+
+        // Close the streams of subscribers still waiting
+        {
+            let mut state = session.lock_state();
+            state.finished = true;
+            state.subscribers.clear();
+        }
+        sessions.remove(&session);
+        info!("Discovery session {} finished", session.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device_identification::convert_to_identified;
+    use crate::discovery::DiscoveredDevice;
+
+    fn device_event(address: &str, open_ports: Vec<u16>) -> IdentifiedDiscoveryEvent {
+        IdentifiedDiscoveryEvent::DeviceFound {
+            device: convert_to_identified(DiscoveredDevice {
+                name: address.to_string(),
+                address: address.to_string(),
+                addresses: vec![address.to_string()],
+                hostname: address.to_string(),
+                services: vec![],
+                txt_properties: HashMap::new(),
+                ttl: None,
+                discovery_method: "ip_scan".to_string(),
+                vendor_info: None,
+                open_ports,
+                gateway: None,
+                mac: None,
+            }),
+        }
+    }
+
+    fn session(key: &str) -> DiscoverySession {
+        DiscoverySession {
+            id: "test".to_string(),
+            key: key.to_string(),
+            config: serde_json::from_str("{}").unwrap(),
+            started_at: 0,
+            progress: Arc::new(ScanProgress::new()),
+            state: Mutex::new(SessionState::default()),
+        }
+    }
+
+    #[test]
+    fn test_job_slots() {
+        let sessions = DiscoverySessions::new();
+        for i in 0..MAX_RUNNING_JOBS {
+            let key = format!("job-{}", i);
+            assert!(sessions.job_slot_free(&key));
+            let job = session(&key);
+            job.lock_state().job = true;
+            sessions.sessions.lock().unwrap().insert(key, Arc::new(job));
+        }
+        assert!(!sessions.job_slot_free("another"));
+        // A running job can be started again, and streams don't count
+        assert!(sessions.job_slot_free("job-0"));
+        sessions
+            .sessions
+            .lock()
+            .unwrap()
+            .insert("stream".to_string(), Arc::new(session("stream")));
+        assert!(!sessions.job_slot_free("stream"));
+
+        // A finished job frees its slot
+        sessions.sessions.lock().unwrap()["job-1"]
+            .lock_state()
+            .finished = true;
+        assert!(sessions.job_slot_free("another"));
+    }
+
+    #[test]
+    fn test_late_subscriber_gets_devices_so_far() {
+        let stats = Arc::new(DiscoveryStreamStats::new());
+        let session = session("");
+        let (_, replay, mut first_rx, _) = session.subscribe(&stats);
+        assert!(replay.is_empty());
+
+        assert!(session.publish(IdentifiedDiscoveryEvent::Started {
+            message: "Starting discovery".to_string(),
+        }));
+        assert!(session.publish(device_event("192.168.1.10", vec![80])));
+        assert!(session.publish(device_event("192.168.1.11", vec![22])));
+        // An update replaces the device instead of adding one
+        assert!(session.publish(device_event("192.168.1.10", vec![80, 443])));
+
+        let (_, replay, _second_rx, _) = session.subscribe(&stats);
+        assert_eq!(replay.len(), 3);
+        assert!(matches!(
+            replay[0],
+            IdentifiedDiscoveryEvent::Started { .. }
+        ));
+        match &replay[1] {
+            IdentifiedDiscoveryEvent::DeviceFound { device } => {
+                assert_eq!(device.device_info.primary_address, "192.168.1.10");
+                assert_eq!(session.status().device_count, 2);
+                assert_eq!(session.status().subscribers, 2);
+            }
+            other => panic!("unexpected replay event {:?}", other),
+        }
+
+        // The first subscriber got every event live
+        let mut received = 0;
+        while first_rx.try_recv().is_ok() {
+            received += 1;
+        }
+        assert_eq!(received, 4);
+
+        assert!(!session.publish(IdentifiedDiscoveryEvent::Completed {
+            message: "done".to_string(),
+            device_count: 2,
+        }));
+        assert!(matches!(
+            first_rx.try_recv(),
+            Ok(IdentifiedDiscoveryEvent::Completed { .. })
+        ));
+    }
+}
@@ -0,0 +1,496 @@
+//! Downsampling of raw ping results into 1m/1h rollups.
+//!
+//! A background job aggregates each completed hour of raw `ping_latency` /
+//! `ping_failed` points into per-target rollup series (min/max/avg latency and
+//! successful/failed counts, labeled with `resolution` = `1m` or `1h`). The
+//! hours that have been rolled up are tracked in `<data path>/downsample.json`
+//! so aggregated queries can read rollups for covered hours and raw data for
+//! the rest.
+//!
+//! tsink only accepts writes into its recent partitions, so rollups are
+//! written going forward; after downtime, at most `CATCHUP_SECONDS` of raw
+//! data is rolled up and older hours stay raw-only.
+
+use crate::api::ping::dto::BucketDataPoint;
+use crate::api::ping::query::{query_aggregated_chunked, PING_METRICS};
+use crate::config::{HistorySource, Target};
+use crate::storage::StorageBackend;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use tracing::{info, warn};
+use tsink::{DataPoint, Label, Row};
+
+/// Minimum latency of a rollup bucket
+pub const ROLLUP_MIN_METRIC: &str = "ping_rollup_min";
+/// Maximum latency of a rollup bucket
+pub const ROLLUP_MAX_METRIC: &str = "ping_rollup_max";
+/// Average latency of a rollup bucket
+pub const ROLLUP_AVG_METRIC: &str = "ping_rollup_avg";
+/// Number of successful pings in a rollup bucket
+pub const ROLLUP_SUCCESSFUL_METRIC: &str = "ping_rollup_successful";
+/// Number of failed pings in a rollup bucket
+pub const ROLLUP_FAILED_METRIC: &str = "ping_rollup_failed";
+
+const ROLLUP_METRICS: [&str; 5] = [
+    ROLLUP_MIN_METRIC,
+    ROLLUP_MAX_METRIC,
+    ROLLUP_AVG_METRIC,
+    ROLLUP_SUCCESSFUL_METRIC,
+    ROLLUP_FAILED_METRIC,
+];
+
+/// File (inside the data path) recording which hours have been rolled up
+const COVERAGE_FILE: &str = "downsample.json";
+
+/// Seconds after the end of an hour before it is rolled up, so late pings of
+/// that hour are included
+const GRACE_SECONDS: i64 = 120;
+
+/// Furthest back the job rolls up after downtime; older data is outside the
+/// partitions tsink still accepts writes for
+const CATCHUP_SECONDS: i64 = 5 * 3600;
+
+/// Span of one rollup read when answering queries
+const QUERY_CHUNK_SECONDS: i64 = 24 * 3600;
+
+/// Rollup resolution
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    Minute,
+    Hour,
+}
+
+impl Resolution {
+    pub fn seconds(&self) -> i64 {
+        match self {
+            Resolution::Minute => 60,
+            Resolution::Hour => 3600,
+        }
+    }
+
+    /// Value of the `resolution` label
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Resolution::Minute => "1m",
+            Resolution::Hour => "1h",
+        }
+    }
+
+    /// Coarsest resolution whose buckets fit evenly into `bucket_seconds`
+    pub fn for_bucket(bucket_seconds: i64) -> Option<Self> {
+        if bucket_seconds % 3600 == 0 {
+            Some(Resolution::Hour)
+        } else if bucket_seconds % 60 == 0 {
+            Some(Resolution::Minute)
+        } else {
+            None
+        }
+    }
+}
+
+/// Hour-aligned `[start, end)` ranges that have been rolled up
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Coverage {
+    ranges: Vec<(i64, i64)>,
+}
+
+impl Coverage {
+    /// Add a range, merging it with adjacent or overlapping ones
+    fn add(&mut self, start: i64, end: i64) {
+        self.ranges.push((start, end));
+        self.ranges.sort();
+        let mut merged: Vec<(i64, i64)> = Vec::with_capacity(self.ranges.len());
+        for (start, end) in self.ranges.drain(..) {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        self.ranges = merged;
+    }
+
+    fn last_end(&self) -> Option<i64> {
+        self.ranges.last().map(|r| r.1)
+    }
+
+    /// Split `[from, to)` into consecutive segments, each flagged with
+    /// whether rollups cover it
+    pub fn split(&self, from: i64, to: i64) -> Vec<(i64, i64, bool)> {
+        let mut segments = Vec::new();
+        let mut cursor = from;
+        for &(start, end) in &self.ranges {
+            if end <= cursor || start >= to {
+                continue;
+            }
+            if start > cursor {
+                segments.push((cursor, start, false));
+            }
+            let covered_end = end.min(to);
+            segments.push((cursor.max(start), covered_end, true));
+            cursor = covered_end;
+        }
+        if cursor < to {
+            segments.push((cursor, to, false));
+        }
+        segments
+    }
+}
+
+/// Runs the rollup job and tracks which hours it has covered
+#[derive(Debug)]
+pub struct Downsampler {
+    path: PathBuf,
+    coverage: RwLock<Coverage>,
+}
+
+impl Downsampler {
+    /// Load the coverage recorded under `data_path`
+    pub fn load(data_path: &Path) -> Self {
+        let path = data_path.join(COVERAGE_FILE);
+        let coverage = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable {}: {}", path.display(), e);
+                Coverage::default()
+            }),
+            Err(_) => Coverage::default(),
+        };
+        Self {
+            path,
+            coverage: RwLock::new(coverage),
+        }
+    }
+
+    pub fn coverage(&self) -> Coverage {
+        self.coverage.read().map(|c| c.clone()).unwrap_or_default()
+    }
+
+    /// Roll up every completed hour not covered yet, returning the number of
+    /// hours processed
+    pub fn run_once(
+        &self,
+        storage: &dyn StorageBackend,
+        targets: &[Target],
+        now: i64,
+    ) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+        let end = (now - GRACE_SECONDS).div_euclid(3600) * 3600;
+        let earliest = (now - CATCHUP_SECONDS).div_euclid(3600) * 3600 + 3600;
+        let mut start = self.coverage().last_end().unwrap_or(earliest).max(earliest);
+
+        let mut hours = 0;
+        while start < end {
+            let rows = rollup_rows(storage, targets, start)?;
+            if !rows.is_empty() {
+                storage.insert_rows(&rows)?;
+            }
+
+            let snapshot = {
+                let mut coverage = self.coverage.write().map_err(|e| e.to_string())?;
+                coverage.add(start, start + 3600);
+                coverage.clone()
+            };
+            fs::write(&self.path, serde_json::to_vec(&snapshot)?)?;
+
+            start += 3600;
+            hours += 1;
+        }
+
+        if hours > 0 {
+            info!("Rolled up {} hour(s) of ping data", hours);
+        }
+        Ok(hours)
+    }
+}
+
+/// Build the 1m and 1h rollup rows for the hour starting at `hour_start`
+fn rollup_rows(
+    storage: &dyn StorageBackend,
+    targets: &[Target],
+    hour_start: i64,
+) -> Result<Vec<Row>, Box<dyn std::error::Error + Send + Sync>> {
+    let (minutes, _) = query_aggregated_chunked(
+        storage,
+        &PING_METRICS,
+        None,
+        None,
+        hour_start,
+        hour_start + 3600,
+        Resolution::Minute.seconds(),
+        false,
+    )?;
+
+    let mut hours: HashMap<String, BucketDataPoint> = HashMap::new();
+    let mut rows = Vec::new();
+    for bucket in minutes {
+        push_bucket_rows(&mut rows, targets, Resolution::Minute, &bucket);
+        match hours.get_mut(&bucket.target) {
+            Some(hour) => merge_bucket(hour, &bucket),
+            None => {
+                let mut hour = bucket.clone();
+                hour.timestamp_unix = hour_start;
+                hour.timestamp_end_unix = hour_start + 3600;
+                hours.insert(bucket.target.clone(), hour);
+            }
+        }
+    }
+    for hour in hours.values() {
+        push_bucket_rows(&mut rows, targets, Resolution::Hour, hour);
+    }
+
+    Ok(rows)
+}
+
+/// Merge the counts and latency range of `other` into `bucket`
+pub(crate) fn merge_bucket(bucket: &mut BucketDataPoint, other: &BucketDataPoint) {
+    let sum = bucket.avg.unwrap_or(0.0) * bucket.successful_count as f64
+        + other.avg.unwrap_or(0.0) * other.successful_count as f64;
+    bucket.successful_count += other.successful_count;
+    bucket.failed_count += other.failed_count;
+    bucket.count += other.count;
+    bucket.avg = (bucket.successful_count > 0).then(|| sum / bucket.successful_count as f64);
+    bucket.min = match (bucket.min, other.min) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+    bucket.max = match (bucket.max, other.max) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        (a, b) => a.or(b),
+    };
+    bucket.resolution = match (bucket.resolution, other.resolution) {
+        (Some(a), Some(b)) if a != b => Some("mixed"),
+        (a, b) => a.or(b),
+    };
+    bucket.complete &= other.complete;
+}
+
+fn push_bucket_rows(
+    rows: &mut Vec<Row>,
+    targets: &[Target],
+    resolution: Resolution,
+    bucket: &BucketDataPoint,
+) {
+    let mut labels = vec![
+        Label::new("target", &bucket.target),
+        Label::new("resolution", resolution.as_str()),
+    ];
+    // Target ID lets retention pruning expire rollups with the raw data
+    if let Some(target) = targets.iter().find(|t| t.address == bucket.target) {
+        labels.push(Label::new("target_id", &target.id));
+    }
+    if let Some(ref name) = bucket.target_name {
+        labels.push(Label::new("target_name", name));
+    }
+
+    let mut push = |metric: &str, value: f64| {
+        rows.push(Row::with_labels(
+            metric,
+            labels.clone(),
+            DataPoint::new(bucket.timestamp_unix, value),
+        ));
+    };
+    if let Some(min) = bucket.min {
+        push(ROLLUP_MIN_METRIC, min);
+    }
+    if let Some(max) = bucket.max {
+        push(ROLLUP_MAX_METRIC, max);
+    }
+    if let Some(avg) = bucket.avg {
+        push(ROLLUP_AVG_METRIC, avg);
+    }
+    push(ROLLUP_SUCCESSFUL_METRIC, bucket.successful_count as f64);
+    push(ROLLUP_FAILED_METRIC, bucket.failed_count as f64);
+}
+
+/// Read rollup buckets of `resolution` in `[from, to)`.
+///
+/// `target_filter` matches the target address or ID; matching buckets are
+/// reported under the filter value, like raw aggregated queries. Buckets of
+/// the filtered target's earlier identities in `history` are included.
+pub(crate) fn select_rollups(
+    storage: &dyn StorageBackend,
+    resolution: Resolution,
+    target_filter: Option<&str>,
+    history: &[HistorySource],
+    from: i64,
+    to: i64,
+) -> Result<Vec<BucketDataPoint>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut buckets: HashMap<(String, i64), BucketDataPoint> = HashMap::new();
+
+    let mut chunk_start = from;
+    while chunk_start < to {
+        let chunk_end = (chunk_start + QUERY_CHUNK_SECONDS).min(to);
+
+        for metric in ROLLUP_METRICS {
+            for (labels, points) in storage.select_all(metric, chunk_start, chunk_end)? {
+                let label = |name: &str| labels.iter().find(|l| l.name == name).map(|l| &l.value);
+                if label("resolution").map(String::as_str) != Some(resolution.as_str()) {
+                    continue;
+                }
+                let Some(target) = label("target") else {
+                    continue;
+                };
+                let from_history = |source: &HistorySource| {
+                    label("target_id") == Some(&source.id)
+                        && source.address.as_ref().is_none_or(|a| a == target)
+                };
+                let target = match target_filter {
+                    Some(filter)
+                        if target == filter
+                            || label("target_id").is_some_and(|id| id == filter)
+                            || history.iter().any(from_history) =>
+                    {
+                        filter.to_string()
+                    }
+                    Some(_) => continue,
+                    None => target.clone(),
+                };
+                let target_name = label("target_name").cloned();
+
+                for point in points {
+                    let bucket = buckets
+                        .entry((target.clone(), point.timestamp))
+                        .or_insert_with(|| BucketDataPoint {
+                            timestamp: DateTime::from_timestamp(point.timestamp, 0)
+                                .unwrap_or_else(Utc::now)
+                                .to_rfc3339(),
+                            timestamp_unix: point.timestamp,
+                            timestamp_end_unix: point.timestamp + resolution.seconds(),
+                            target: target.clone(),
+                            target_name: target_name.clone(),
+                            min: None,
+                            max: None,
+                            avg: None,
+                            percentiles: None,
+                            count: 0,
+                            successful_count: 0,
+                            failed_count: 0,
+                            resolution: None,
+                            complete: true,
+                            loss_percent: None,
+                        });
+                    match metric {
+                        ROLLUP_MIN_METRIC => bucket.min = Some(point.value),
+                        ROLLUP_MAX_METRIC => bucket.max = Some(point.value),
+                        ROLLUP_AVG_METRIC => bucket.avg = Some(point.value),
+                        ROLLUP_SUCCESSFUL_METRIC => {
+                            bucket.successful_count = point.value as usize;
+                        }
+                        _ => bucket.failed_count = point.value as usize,
+                    }
+                    bucket.count = bucket.successful_count + bucket.failed_count;
+                }
+            }
+        }
+
+        chunk_start = chunk_end;
+    }
+
+    Ok(buckets.into_values().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("sparkping-downsample-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_coverage_split() {
+        let mut coverage = Coverage::default();
+        coverage.add(3600, 7200);
+        coverage.add(7200, 10800);
+        coverage.add(18000, 21600);
+        assert_eq!(coverage.ranges, vec![(3600, 10800), (18000, 21600)]);
+
+        assert_eq!(
+            coverage.split(0, 20000),
+            vec![
+                (0, 3600, false),
+                (3600, 10800, true),
+                (10800, 18000, false),
+                (18000, 20000, true),
+            ]
+        );
+        assert_eq!(coverage.split(4000, 5000), vec![(4000, 5000, true)]);
+    }
+
+    #[test]
+    fn test_resolution_for_bucket() {
+        assert_eq!(Resolution::for_bucket(86400), Some(Resolution::Hour));
+        assert_eq!(Resolution::for_bucket(300), Some(Resolution::Minute));
+        assert_eq!(Resolution::for_bucket(90), None);
+    }
+
+    #[test]
+    fn test_rollup_round_trip() {
+        let storage = crate::storage::memory_storage();
+        let labels = vec![
+            Label::new("target_id", "t1"),
+            Label::new("target", "192.168.1.1"),
+            Label::new("sequence", "1"),
+        ];
+        let now = 10 * 3600 + 600;
+        let hour = 9 * 3600;
+        storage
+            .insert_rows(&[
+                Row::with_labels(
+                    "ping_latency",
+                    labels.clone(),
+                    DataPoint::new(hour + 5, 10.0),
+                ),
+                Row::with_labels(
+                    "ping_latency",
+                    labels.clone(),
+                    DataPoint::new(hour + 65, 30.0),
+                ),
+                Row::with_labels("ping_failed", labels, DataPoint::new(hour + 70, 0.0)),
+            ])
+            .unwrap();
+        let target = Target {
+            id: "t1".to_string(),
+            address: "192.168.1.1".to_string(),
+            ping_count: 1,
+            ..Default::default()
+        };
+
+        let dir = temp_dir();
+        let downsampler = Downsampler::load(&dir);
+        // Hours 6-9 are completed and within the catch-up window
+        assert_eq!(downsampler.run_once(&*storage, &[target], now).unwrap(), 4);
+        assert_eq!(downsampler.run_once(&*storage, &[], now).unwrap(), 0);
+        assert!(downsampler.coverage().split(hour, hour + 3600)[0].2);
+
+        let minutes = select_rollups(
+            &*storage,
+            Resolution::Minute,
+            Some("t1"),
+            &[],
+            hour,
+            hour + 3600,
+        )
+        .unwrap();
+        assert_eq!(minutes.len(), 2);
+
+        let hours =
+            select_rollups(&*storage, Resolution::Hour, None, &[], hour, hour + 3600).unwrap();
+        assert_eq!(hours.len(), 1);
+        assert_eq!(hours[0].target, "192.168.1.1");
+        assert_eq!(hours[0].min, Some(10.0));
+        assert_eq!(hours[0].max, Some(30.0));
+        assert_eq!(hours[0].avg, Some(20.0));
+        assert_eq!(hours[0].successful_count, 2);
+        assert_eq!(hours[0].failed_count, 1);
+
+        // Coverage survives a restart
+        assert_eq!(Downsampler::load(&dir).coverage(), downsampler.coverage());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
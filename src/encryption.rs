@@ -0,0 +1,634 @@
+//! Encryption of the data directory at rest.
+//!
+//! tsink reads and writes its partitions and WAL directly, so encryption
+//! wraps the data directory instead of the storage engine. With
+//! `[database.encryption] enabled`, `[database] path` only holds sealed
+//! copies of the files (`<name>.enc`). At startup they are decrypted into
+//! `work_path`, where tsink and the other stores run, and the working
+//! directory is sealed back every `seal_interval` seconds and on shutdown.
+//! Plaintext therefore only exists in the working directory while SparkPing
+//! runs; a tmpfs keeps it off the disk entirely.
+//!
+//! While running, the storage backend first writes a consistent snapshot
+//! (`StorageBackend::snapshot`) next to the working directory, which is
+//! sealed and removed, so files being appended to are never sealed half
+//! written. On shutdown the closed storage is sealed directly. The price of
+//! a tmpfs is that a crash, power loss or reboot loses everything written
+//! since the last seal, up to `seal_interval` seconds of data.
+//!
+//! Sealed files are XChaCha20-Poly1305 encrypted in 64 KiB chunks. Every
+//! chunk is bound to the file's relative path and its position, and the
+//! final chunk is flagged, so swapped, reordered, or truncated files fail to
+//! decrypt.
+
+use crate::config::DatabaseConfig;
+use crate::storage::StorageBackend;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+/// Environment variable holding the key as 64 hex characters
+pub const KEY_ENV: &str = "SPARKPING_STORAGE_KEY";
+
+/// Suffix of sealed files
+const SEALED_SUFFIX: &str = ".enc";
+/// Header of sealed files (format version 1)
+const MAGIC: &[u8; 8] = b"SPKSEAL1";
+/// Plaintext bytes per encrypted chunk
+const CHUNK_SIZE: usize = 64 * 1024;
+/// Poly1305 tag appended to every chunk
+const TAG_SIZE: usize = 16;
+/// Random per-file part of the 24-byte nonce; the rest is the chunk index
+const NONCE_PREFIX_SIZE: usize = 16;
+
+/// Keeps the periodic and the shutdown seal from running at the same time
+static SEAL_LOCK: Mutex<()> = Mutex::new(());
+
+/// Key sealing the data directory
+pub struct StorageKey(Key);
+
+impl std::fmt::Debug for StorageKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("StorageKey(..)")
+    }
+}
+
+impl StorageKey {
+    /// Parse a key from 64 hex characters (surrounding whitespace is ignored)
+    pub fn from_hex(value: &str) -> Result<Self, String> {
+        let bytes = hex::decode(value.trim()).map_err(|e| format!("Invalid storage key: {}", e))?;
+        if bytes.len() != 32 {
+            return Err(format!(
+                "Storage key must be 32 bytes (64 hex characters), got {} bytes",
+                bytes.len()
+            ));
+        }
+        Ok(Self(*Key::from_slice(&bytes)))
+    }
+
+    /// Load the key from `SPARKPING_STORAGE_KEY`, falling back to `key_file`
+    pub fn load(key_file: Option<&str>) -> Result<Self, String> {
+        if let Ok(value) = std::env::var(KEY_ENV) {
+            return Self::from_hex(&value);
+        }
+        let path = key_file.ok_or_else(|| {
+            format!(
+                "Encryption is enabled but neither {} nor key_file is set",
+                KEY_ENV
+            )
+        })?;
+        let value = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read key file '{}': {}", path, e))?;
+        Self::from_hex(&value)
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(&self.0)
+    }
+}
+
+/// Load the key and prepare the working directory.
+///
+/// A working directory left behind by a run that did not shut down cleanly
+/// is newer than the sealed copy and is used as is; otherwise the sealed
+/// copy is decrypted into a fresh working directory. Plaintext files found
+/// in `[database] path` (data from before encryption was enabled) are copied
+/// over and replaced by sealed ones on the next seal.
+pub fn open_data_directory(database: &DatabaseConfig) -> Result<StorageKey, String> {
+    let key = StorageKey::load(database.encryption.key_file.as_deref())?;
+    let sealed = Path::new(&database.path);
+    let work = Path::new(&database.encryption.work_path);
+    check_paths(sealed, work)?;
+
+    recover_interrupted_swap(sealed)
+        .map_err(|e| format!("Failed to recover sealed data directory: {}", e))?;
+    // Plaintext left by a seal that did not finish
+    let snapshot = sibling(work, "snapshot");
+    if snapshot.exists() {
+        fs::remove_dir_all(&snapshot)
+            .map_err(|e| format!("Failed to remove {}: {}", snapshot.display(), e))?;
+    }
+
+    let leftover = fs::read_dir(work)
+        .map(|mut entries| entries.next().is_some())
+        .unwrap_or(false);
+    if leftover {
+        warn!(
+            "Working directory {} was not cleaned up by the last run, using it instead of the sealed copy",
+            work.display()
+        );
+        return Ok(key);
+    }
+
+    let report = unseal(sealed, work, &key).map_err(|e| {
+        format!(
+            "Failed to decrypt data directory '{}' (wrong key?): {}",
+            sealed.display(),
+            e
+        )
+    })?;
+    info!(
+        "Decrypted {} files from {} into {}",
+        report.decrypted,
+        sealed.display(),
+        work.display()
+    );
+    if report.plaintext > 0 {
+        warn!(
+            "{} unencrypted files in {} will be encrypted on the next seal",
+            report.plaintext,
+            sealed.display()
+        );
+    }
+    Ok(key)
+}
+
+/// Seal a snapshot of the running storage into `[database] path`; returns
+/// the number of files sealed
+pub fn seal_data_directory(
+    database: &DatabaseConfig,
+    key: &StorageKey,
+    storage: &dyn StorageBackend,
+) -> io::Result<usize> {
+    let snapshot = sibling(Path::new(&database.encryption.work_path), "snapshot");
+    if snapshot.exists() {
+        fs::remove_dir_all(&snapshot)?;
+    }
+    fs::create_dir_all(&snapshot)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&snapshot, fs::Permissions::from_mode(0o700))?;
+    }
+
+    let result = storage
+        .snapshot(&snapshot)
+        .map_err(io::Error::other)
+        .and_then(|()| seal(&snapshot, Path::new(&database.path), key));
+    let removed = fs::remove_dir_all(&snapshot);
+    let count = result?;
+    removed?;
+    Ok(count)
+}
+
+/// Seal the closed storage and remove the plaintext working directory
+pub fn close_data_directory(database: &DatabaseConfig, key: &StorageKey) -> io::Result<()> {
+    let work = Path::new(&database.encryption.work_path);
+    let count = seal(work, Path::new(&database.path), key)?;
+    info!("Sealed {} files into {}", count, database.path);
+    fs::remove_dir_all(work)
+}
+
+/// Files restored by [`unseal`]
+#[derive(Debug, Default, PartialEq, Eq)]
+struct UnsealReport {
+    decrypted: usize,
+    /// Unencrypted files copied as they were
+    plaintext: usize,
+}
+
+/// Reject working directories that overlap the sealed directory, since the
+/// working directory is wiped and rebuilt
+fn check_paths(sealed: &Path, work: &Path) -> Result<(), String> {
+    let sealed_abs = absolute(sealed);
+    let work_abs = absolute(work);
+    if sealed_abs.starts_with(&work_abs) || work_abs.starts_with(&sealed_abs) {
+        return Err(format!(
+            "[database.encryption] work_path '{}' must not overlap [database] path '{}'",
+            work.display(),
+            sealed.display()
+        ));
+    }
+    Ok(())
+}
+
+/// Absolute, lexically normalized path (the directories may not exist yet)
+fn absolute(path: &Path) -> PathBuf {
+    let joined = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir().unwrap_or_default().join(path)
+    };
+    let mut normalized = PathBuf::new();
+    for component in joined.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// `<path>.<suffix>` next to `path`
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!("{}.{}", name, suffix))
+}
+
+/// Put the previous sealed copy back if a seal stopped between moving it
+/// aside and moving the new copy in
+fn recover_interrupted_swap(sealed: &Path) -> io::Result<()> {
+    let previous = sibling(sealed, "sealed-old");
+    if !sealed.exists() && previous.exists() {
+        fs::rename(&previous, sealed)?;
+    }
+    Ok(())
+}
+
+/// Relative paths of all regular files below `dir`
+fn collect_files(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let path = entry.path();
+        if file_type.is_dir() {
+            collect_files(root, &path, files)?;
+        } else if file_type.is_file() {
+            if let Ok(relative) = path.strip_prefix(root) {
+                files.push(relative.to_path_buf());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Relative path as bound into every chunk, with `/` separators
+fn path_aad(relative: &Path) -> String {
+    relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn chunk_nonce(prefix: &[u8; NONCE_PREFIX_SIZE], index: u64) -> XNonce {
+    let mut nonce = [0u8; 24];
+    nonce[..NONCE_PREFIX_SIZE].copy_from_slice(prefix);
+    nonce[NONCE_PREFIX_SIZE..].copy_from_slice(&index.to_be_bytes());
+    *XNonce::from_slice(&nonce)
+}
+
+fn chunk_aad(relative: &str, last: bool) -> Vec<u8> {
+    let mut aad = relative.as_bytes().to_vec();
+    aad.push(last as u8);
+    aad
+}
+
+fn invalid(path: &Path, message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {}", path.display(), message),
+    )
+}
+
+/// Read until `buf` is full or the reader is exhausted
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+fn encrypt_file(
+    cipher: &XChaCha20Poly1305,
+    src: &Path,
+    dst: &Path,
+    relative: &str,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(File::open(src)?);
+    let mut writer = BufWriter::new(File::create(dst)?);
+
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let mut prefix = [0u8; NONCE_PREFIX_SIZE];
+    prefix.copy_from_slice(&nonce[..NONCE_PREFIX_SIZE]);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&prefix)?;
+
+    let mut chunk = vec![0u8; CHUNK_SIZE];
+    let mut next = vec![0u8; CHUNK_SIZE];
+    let mut filled = read_full(&mut reader, &mut chunk)?;
+    let mut index = 0u64;
+    loop {
+        // Read ahead so the final chunk can be flagged
+        let next_filled = if filled == CHUNK_SIZE {
+            read_full(&mut reader, &mut next)?
+        } else {
+            0
+        };
+        let last = next_filled == 0;
+        let ciphertext = cipher
+            .encrypt(
+                &chunk_nonce(&prefix, index),
+                Payload {
+                    msg: &chunk[..filled],
+                    aad: &chunk_aad(relative, last),
+                },
+            )
+            .map_err(|_| invalid(src, "encryption failed"))?;
+        writer.write_all(&(ciphertext.len() as u32).to_le_bytes())?;
+        writer.write_all(&ciphertext)?;
+        if last {
+            break;
+        }
+        std::mem::swap(&mut chunk, &mut next);
+        filled = next_filled;
+        index += 1;
+    }
+
+    writer.into_inner().map_err(|e| e.into_error())?.sync_all()
+}
+
+fn decrypt_file(
+    cipher: &XChaCha20Poly1305,
+    src: &Path,
+    dst: &Path,
+    relative: &str,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(File::open(src)?);
+    let mut header = [0u8; MAGIC.len() + NONCE_PREFIX_SIZE];
+    if read_full(&mut reader, &mut header)? < header.len() || header[..MAGIC.len()] != MAGIC[..] {
+        return Err(invalid(src, "not a sealed file"));
+    }
+    let mut prefix = [0u8; NONCE_PREFIX_SIZE];
+    prefix.copy_from_slice(&header[MAGIC.len()..]);
+
+    let mut writer = BufWriter::new(File::create(dst)?);
+    let mut ciphertext = Vec::with_capacity(CHUNK_SIZE + TAG_SIZE);
+    let mut index = 0u64;
+    loop {
+        let mut len = [0u8; 4];
+        if read_full(&mut reader, &mut len)? < len.len() {
+            return Err(invalid(src, "truncated (final chunk missing)"));
+        }
+        let len = u32::from_le_bytes(len) as usize;
+        if !(TAG_SIZE..=CHUNK_SIZE + TAG_SIZE).contains(&len) {
+            return Err(invalid(src, "corrupt chunk length"));
+        }
+        ciphertext.resize(len, 0);
+        if read_full(&mut reader, &mut ciphertext)? < len {
+            return Err(invalid(src, "truncated chunk"));
+        }
+
+        // Chunks are only flagged as final in the authenticated data
+        let nonce = chunk_nonce(&prefix, index);
+        let decrypt = |last: bool| {
+            cipher.decrypt(
+                &nonce,
+                Payload {
+                    msg: &ciphertext,
+                    aad: &chunk_aad(relative, last),
+                },
+            )
+        };
+        let (plaintext, last) = match decrypt(false) {
+            Ok(plaintext) => (plaintext, false),
+            Err(_) => (
+                decrypt(true).map_err(|_| {
+                    invalid(src, "authentication failed (wrong key or modified file)")
+                })?,
+                true,
+            ),
+        };
+        writer.write_all(&plaintext)?;
+
+        if last {
+            if read_full(&mut reader, &mut [0u8; 1])? != 0 {
+                return Err(invalid(src, "data after final chunk"));
+            }
+            break;
+        }
+        index += 1;
+    }
+
+    writer.into_inner().map_err(|e| e.into_error())?.sync_all()
+}
+
+/// Encrypt every file of `work` into a new copy of `sealed` and swap it in
+fn seal(work: &Path, sealed: &Path, key: &StorageKey) -> io::Result<usize> {
+    let _guard = SEAL_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    let staging = sibling(sealed, "sealing");
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    fs::create_dir_all(&staging)?;
+
+    let cipher = key.cipher();
+    let mut files = Vec::new();
+    collect_files(work, work, &mut files)?;
+    let mut count = 0;
+    for relative in &files {
+        let mut name = relative.as_os_str().to_owned();
+        name.push(SEALED_SUFFIX);
+        let dst = staging.join(name);
+        if let Some(parent) = dst.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        match encrypt_file(&cipher, &work.join(relative), &dst, &path_aad(relative)) {
+            Ok(()) => count += 1,
+            // tsink may remove WAL segments while the directory is sealed
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let _ = fs::remove_file(&dst);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    // Move the previous copy aside only once the new one is complete
+    let previous = sibling(sealed, "sealed-old");
+    if previous.exists() {
+        fs::remove_dir_all(&previous)?;
+    }
+    if sealed.exists() {
+        fs::rename(sealed, &previous)?;
+    }
+    fs::rename(&staging, sealed)?;
+    if previous.exists() {
+        fs::remove_dir_all(&previous)?;
+    }
+    Ok(count)
+}
+
+/// Decrypt `sealed` into a fresh `work` directory
+fn unseal(sealed: &Path, work: &Path, key: &StorageKey) -> io::Result<UnsealReport> {
+    if work.exists() {
+        fs::remove_dir_all(work)?;
+    }
+    fs::create_dir_all(work)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(work, fs::Permissions::from_mode(0o700))?;
+    }
+
+    let mut report = UnsealReport::default();
+    if !sealed.exists() {
+        return Ok(report);
+    }
+
+    let cipher = key.cipher();
+    let mut files = Vec::new();
+    collect_files(sealed, sealed, &mut files)?;
+    for relative in files {
+        let src = sealed.join(&relative);
+        let stripped = relative
+            .to_str()
+            .and_then(|r| r.strip_suffix(SEALED_SUFFIX))
+            .map(PathBuf::from);
+        let dst = work.join(stripped.as_deref().unwrap_or(&relative));
+        if let Some(parent) = dst.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        match stripped {
+            Some(stripped) => {
+                decrypt_file(&cipher, &src, &dst, &path_aad(&stripped))?;
+                report.decrypted += 1;
+            }
+            None => {
+                fs::copy(&src, &dst)?;
+                report.plaintext += 1;
+            }
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("sparkping-encryption-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn key(byte: u8) -> StorageKey {
+        StorageKey::from_hex(&hex::encode([byte; 32])).unwrap()
+    }
+
+    fn write(root: &Path, relative: &str, contents: &[u8]) {
+        let path = root.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn test_key_parsing() {
+        assert!(StorageKey::from_hex(&format!(" {}\n", "ab".repeat(32))).is_ok());
+        assert!(StorageKey::from_hex(&"ab".repeat(16)).is_err());
+        assert!(StorageKey::from_hex("not hex").is_err());
+    }
+
+    #[test]
+    fn test_seal_roundtrip() {
+        let root = temp_dir();
+        let work = root.join("work");
+        let sealed = root.join("data");
+        // Exactly two chunks, a partial chunk, and an empty file
+        let large: Vec<u8> = (0..2 * CHUNK_SIZE).map(|i| i as u8).collect();
+        write(&work, "p-1/data", &large);
+        write(&work, "wal/0001.wal", b"wal segment");
+        write(&work, "empty", b"");
+
+        assert_eq!(seal(&work, &sealed, &key(1)).unwrap(), 3);
+        let sealed_wal = fs::read(sealed.join("wal/0001.wal.enc")).unwrap();
+        assert!(!sealed_wal
+            .windows(b"wal segment".len())
+            .any(|w| w == b"wal segment"));
+
+        let restored = root.join("restored");
+        let report = unseal(&sealed, &restored, &key(1)).unwrap();
+        assert_eq!(report.decrypted, 3);
+        assert_eq!(fs::read(restored.join("p-1/data")).unwrap(), large);
+        assert_eq!(
+            fs::read(restored.join("wal/0001.wal")).unwrap(),
+            b"wal segment"
+        );
+        assert!(fs::read(restored.join("empty")).unwrap().is_empty());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_wrong_key_and_tampering() {
+        let root = temp_dir();
+        let work = root.join("work");
+        let sealed = root.join("data");
+        let large: Vec<u8> = vec![7; CHUNK_SIZE + 10];
+        write(&work, "a", &large);
+        write(&work, "b", b"other");
+        seal(&work, &sealed, &key(1)).unwrap();
+        let restored = root.join("restored");
+
+        assert!(unseal(&sealed, &restored, &key(2)).is_err());
+
+        // Dropping the final chunk is detected
+        let a = sealed.join("a.enc");
+        let bytes = fs::read(&a).unwrap();
+        let first_chunk_end = MAGIC.len() + NONCE_PREFIX_SIZE + 4 + CHUNK_SIZE + TAG_SIZE;
+        fs::write(&a, &bytes[..first_chunk_end]).unwrap();
+        assert!(unseal(&sealed, &restored, &key(1)).is_err());
+
+        // Swapping files is detected, since chunks are bound to their path
+        fs::write(&a, fs::read(sealed.join("b.enc")).unwrap()).unwrap();
+        assert!(unseal(&sealed, &restored, &key(1)).is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_plaintext_migration() {
+        let root = temp_dir();
+        let sealed = root.join("data");
+        let work = root.join("work");
+        write(&sealed, "wal/0001.wal", b"before encryption");
+
+        let report = unseal(&sealed, &work, &key(1)).unwrap();
+        assert_eq!(report.plaintext, 1);
+        assert_eq!(
+            fs::read(work.join("wal/0001.wal")).unwrap(),
+            b"before encryption"
+        );
+
+        // The next seal replaces the plaintext copy
+        seal(&work, &sealed, &key(1)).unwrap();
+        assert!(!sealed.join("wal/0001.wal").exists());
+        assert!(sealed.join("wal/0001.wal.enc").exists());
+        assert!(!sibling(&sealed, "sealed-old").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_interrupted_swap_and_overlapping_paths() {
+        let root = temp_dir();
+        let sealed = root.join("data");
+        write(&sibling(&sealed, "sealed-old"), "a.enc", b"x");
+        recover_interrupted_swap(&sealed).unwrap();
+        assert!(sealed.join("a.enc").exists());
+
+        assert!(check_paths(&sealed, &sealed.join("work")).is_err());
+        assert!(check_paths(&sealed, &root.join("./data")).is_err());
+        assert!(check_paths(&sealed, &root.join("work")).is_ok());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,131 @@
+//! Home Assistant add-on options.
+//!
+//! The Supervisor writes the options set in the add-on UI to
+//! `/data/options.json`. When that file exists, its options take precedence
+//! over `config.toml` at startup and on every config reload, so changing them
+//! in Home Assistant does not depend on the startup script rewriting the
+//! config file.
+
+use crate::config::{AppConfig, SocketType};
+use crate::home_assistant::SUPERVISOR_TOKEN_ENV;
+use serde::Deserialize;
+use std::path::Path;
+
+/// Where the Supervisor writes the add-on options
+pub const ADDON_OPTIONS_PATH: &str = "/data/options.json";
+
+/// Options of the add-on (`home-assistant-addon/config.yaml` schema).
+/// Unknown options are ignored.
+#[derive(Debug, Default, Clone, Deserialize, PartialEq)]
+pub struct AddonOptions {
+    #[serde(default)]
+    pub log_level: Option<String>,
+    #[serde(default)]
+    pub ingress_only: Option<bool>,
+    #[serde(default)]
+    pub ping_socket_type: Option<SocketType>,
+}
+
+impl AddonOptions {
+    /// Read the options file; None if it does not exist
+    pub fn load(path: &Path) -> Result<Option<Self>, String> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+        serde_json::from_str(&content)
+            .map(Some)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+    }
+
+    /// Override the matching config settings
+    pub fn apply(&self, config: &mut AppConfig) {
+        if let Some(level) = self.log_level.as_deref().filter(|l| !l.is_empty()) {
+            config.logging.level = level.to_string();
+        }
+        if let Some(ingress_only) = self.ingress_only {
+            config.server.home_assistant_ingress_only = ingress_only;
+        }
+        if let Some(socket_type) = self.ping_socket_type {
+            config.ping.socket_type = socket_type;
+        }
+    }
+}
+
+/// Apply the add-on options from `ADDON_OPTIONS_PATH`, if present. Returns
+/// whether options were applied.
+pub fn apply_addon_options(config: &mut AppConfig) -> Result<bool, String> {
+    match AddonOptions::load(Path::new(ADDON_OPTIONS_PATH))? {
+        Some(options) => {
+            options.apply(config);
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Whether SparkPing runs as a Home Assistant add-on (the Supervisor passes
+/// an API token to add-ons)
+pub fn running_as_addon() -> bool {
+    std::env::var(SUPERVISOR_TOKEN_ENV).is_ok_and(|token| !token.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> AppConfig {
+        serde_json::from_value(serde_json::json!({
+            "server": { "host": "0.0.0.0", "port": 8080 },
+            "logging": { "level": "info", "file": "sparkping.log" },
+            "database": { "path": "./tsink-data" },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_load_options() {
+        let dir = std::env::temp_dir().join(format!("sparkping-addon-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("options.json");
+
+        assert_eq!(AddonOptions::load(&path), Ok(None));
+
+        std::fs::write(
+            &path,
+            r#"{"log_level": "debug", "ingress_only": false, "ping_socket_type": "raw", "other": 1}"#,
+        )
+        .unwrap();
+        let options = AddonOptions::load(&path).unwrap().unwrap();
+        assert_eq!(options.log_level.as_deref(), Some("debug"));
+        assert_eq!(options.ingress_only, Some(false));
+        assert_eq!(options.ping_socket_type, Some(SocketType::Raw));
+
+        std::fs::write(&path, "not json").unwrap();
+        assert!(AddonOptions::load(&path).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_apply_options() {
+        let mut config = config();
+        config.server.home_assistant_ingress_only = true;
+
+        AddonOptions {
+            log_level: Some("warn".to_string()),
+            ingress_only: Some(false),
+            ping_socket_type: Some(SocketType::Dgram),
+        }
+        .apply(&mut config);
+        assert_eq!(config.logging.level, "warn");
+        assert!(!config.server.home_assistant_ingress_only);
+        assert_eq!(config.ping.socket_type, SocketType::Dgram);
+
+        // Missing options keep the config values
+        AddonOptions::default().apply(&mut config);
+        assert_eq!(config.logging.level, "warn");
+        assert_eq!(config.ping.socket_type, SocketType::Dgram);
+    }
+}
@@ -0,0 +1,218 @@
+//! Process health for `/healthz`, `/readyz` and `/api/status`.
+//!
+//! Tracks when the daemon started, whether it is ready to serve (storage open
+//! and ping tasks started, until shutdown begins), recent storage write
+//! errors and the outcome of the last config reload. The state is process
+//! wide, like the crash report hook, so writers anywhere can record errors.
+
+use crate::config_validation::ConfigIssue;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+use utoipa::ToSchema;
+
+/// Window of the storage write error count
+pub const WRITE_ERROR_WINDOW_SECS: i64 = 3600;
+
+/// Cap on remembered write errors, so a failing disk cannot grow the queue
+/// without bound
+const MAX_WRITE_ERRORS: usize = 100_000;
+
+/// Outcome of the last config file reload
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct ConfigReload {
+    /// Unix timestamp in seconds of the reload
+    pub timestamp_unix: i64,
+    /// Whether the new config was applied
+    pub success: bool,
+    /// Why the config was rejected (the previous config stays active)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub issues: Vec<ConfigIssue>,
+}
+
+/// Health state of the daemon
+pub struct Health {
+    started: OnceLock<(Instant, i64)>,
+    ready: AtomicBool,
+    write_errors: Mutex<VecDeque<i64>>,
+    config_reload: Mutex<Option<ConfigReload>>,
+}
+
+static HEALTH: Health = Health::new();
+
+/// The process-wide health state
+pub fn health() -> &'static Health {
+    &HEALTH
+}
+
+impl Health {
+    const fn new() -> Self {
+        Self {
+            started: OnceLock::new(),
+            ready: AtomicBool::new(false),
+            write_errors: Mutex::new(VecDeque::new()),
+            config_reload: Mutex::new(None),
+        }
+    }
+
+    /// Remember the start time; later calls keep the first one
+    pub fn mark_started(&self) {
+        self.started
+            .get_or_init(|| (Instant::now(), chrono::Utc::now().timestamp()));
+    }
+
+    /// Unix timestamp in seconds the daemon started at
+    pub fn started_at_unix(&self) -> Option<i64> {
+        self.started.get().map(|(_, unix)| *unix)
+    }
+
+    /// Seconds since `mark_started` (0 before it)
+    pub fn uptime_seconds(&self) -> u64 {
+        self.started
+            .get()
+            .map(|(instant, _)| instant.elapsed().as_secs())
+            .unwrap_or(0)
+    }
+
+    pub fn set_ready(&self, ready: bool) {
+        self.ready.store(ready, Ordering::SeqCst);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::SeqCst)
+    }
+
+    /// Count a failed storage write at `now` (Unix seconds)
+    pub fn record_write_error(&self, now: i64) {
+        let Ok(mut errors) = self.write_errors.lock() else {
+            return;
+        };
+        prune_before(&mut errors, now - WRITE_ERROR_WINDOW_SECS);
+        if errors.len() == MAX_WRITE_ERRORS {
+            errors.pop_front();
+        }
+        errors.push_back(now);
+    }
+
+    /// Failed storage writes within the last `WRITE_ERROR_WINDOW_SECS`
+    pub fn recent_write_errors(&self, now: i64) -> usize {
+        self.write_errors
+            .lock()
+            .map(|mut errors| {
+                prune_before(&mut errors, now - WRITE_ERROR_WINDOW_SECS);
+                errors.len()
+            })
+            .unwrap_or(0)
+    }
+
+    /// Remember the outcome of a config reload at `now` (Unix seconds)
+    pub fn record_config_reload(&self, now: i64, result: Result<(), Vec<ConfigIssue>>) {
+        if let Ok(mut reload) = self.config_reload.lock() {
+            *reload = Some(ConfigReload {
+                timestamp_unix: now,
+                success: result.is_ok(),
+                issues: result.err().unwrap_or_default(),
+            });
+        }
+    }
+
+    /// Outcome of the last config reload; None if the config was not
+    /// reloaded since startup
+    pub fn last_config_reload(&self) -> Option<ConfigReload> {
+        self.config_reload.lock().ok().and_then(|r| r.clone())
+    }
+}
+
+fn prune_before(errors: &mut VecDeque<i64>, cutoff: i64) {
+    while errors.front().is_some_and(|&t| t <= cutoff) {
+        errors.pop_front();
+    }
+}
+
+/// Total size of the tsink write-ahead log segments below `data_path`
+pub fn wal_size(data_path: &Path) -> std::io::Result<u64> {
+    let wal_dir = data_path.join("wal");
+    if !wal_dir.exists() {
+        return Ok(0);
+    }
+    Ok(std::fs::read_dir(&wal_dir)?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "wal"))
+        .filter_map(|e| e.metadata().ok())
+        .map(|m| m.len())
+        .sum())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_errors_expire() {
+        let health = Health::new();
+        assert_eq!(health.recent_write_errors(10_000), 0);
+
+        health.record_write_error(5_000);
+        health.record_write_error(6_000);
+        health.record_write_error(9_000);
+        assert_eq!(health.recent_write_errors(9_000), 3);
+        // The first error is more than an hour old
+        assert_eq!(health.recent_write_errors(8_700), 2);
+        assert_eq!(health.recent_write_errors(12_700), 0);
+    }
+
+    #[test]
+    fn test_config_reload_status() {
+        let health = Health::new();
+        assert_eq!(health.last_config_reload(), None);
+
+        health.record_config_reload(100, Err(vec![ConfigIssue::load_error("invalid port")]));
+        let reload = health.last_config_reload().unwrap();
+        assert!(!reload.success);
+        assert_eq!(reload.issues[0].message, "invalid port");
+
+        health.record_config_reload(200, Ok(()));
+        assert_eq!(
+            health.last_config_reload(),
+            Some(ConfigReload {
+                timestamp_unix: 200,
+                success: true,
+                issues: Vec::new(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_uptime_and_readiness() {
+        let health = Health::new();
+        assert_eq!(health.uptime_seconds(), 0);
+        assert_eq!(health.started_at_unix(), None);
+        assert!(!health.is_ready());
+
+        health.mark_started();
+        let started = health.started_at_unix();
+        assert!(started.is_some());
+        health.mark_started();
+        assert_eq!(health.started_at_unix(), started);
+
+        health.set_ready(true);
+        assert!(health.is_ready());
+    }
+
+    #[test]
+    fn test_wal_size() {
+        let dir = std::env::temp_dir().join(format!("sparkping-wal-{}", uuid::Uuid::new_v4()));
+        assert_eq!(wal_size(&dir).unwrap(), 0);
+
+        std::fs::create_dir_all(dir.join("wal")).unwrap();
+        std::fs::write(dir.join("wal").join("0.wal"), [0u8; 100]).unwrap();
+        std::fs::write(dir.join("wal").join("1.wal"), [0u8; 50]).unwrap();
+        std::fs::write(dir.join("wal").join("notes.txt"), [0u8; 10]).unwrap();
+        assert_eq!(wal_size(&dir).unwrap(), 150);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
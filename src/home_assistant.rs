@@ -0,0 +1,286 @@
+//! Home Assistant integration: suggest ping targets from HA's device registry.
+//!
+//! The device registry is only exposed over HA's WebSocket API, so devices are
+//! read through the REST template endpoint (`POST /api/template`), which can
+//! access registry data via `device_id()`/`device_attr()`. IP addresses are
+//! taken from entity attributes (`ip`, `ip_address`, `host`, ...) and from the
+//! device's `configuration_url`.
+
+use crate::config::HomeAssistantConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::time::Duration;
+use tracing::debug;
+
+/// Core API base URL when running as a Home Assistant add-on
+pub const SUPERVISOR_CORE_URL: &str = "http://supervisor/core";
+
+/// Environment variable the Supervisor uses to pass the add-on API token
+pub const SUPERVISOR_TOKEN_ENV: &str = "SUPERVISOR_TOKEN";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Template producing one JSON record per entity with its device registry data
+const DEVICES_TEMPLATE: &str = r#"[
+{%- for s in states -%}
+{%- set d = device_id(s.entity_id) -%}
+{{ {
+  "entity_id": s.entity_id,
+  "device_id": d,
+  "device_name": device_attr(d, "name_by_user") or device_attr(d, "name") if d else none,
+  "manufacturer": device_attr(d, "manufacturer") if d else none,
+  "model": device_attr(d, "model") if d else none,
+  "configuration_url": device_attr(d, "configuration_url") if d else none,
+  "friendly_name": s.attributes.friendly_name or none,
+  "ip": s.attributes.ip or s.attributes.ip_address or s.attributes.local_ip or s.attributes.host or none
+} | to_json }}{{ "," if not loop.last }}
+{%- endfor -%}
+]"#;
+
+/// Per-entity record produced by `DEVICES_TEMPLATE`
+#[derive(Debug, Deserialize)]
+struct EntityRecord {
+    entity_id: String,
+    device_id: Option<String>,
+    device_name: Option<String>,
+    manufacturer: Option<String>,
+    model: Option<String>,
+    configuration_url: Option<String>,
+    friendly_name: Option<String>,
+    ip: Option<serde_json::Value>,
+}
+
+/// A Home Assistant device with at least one known IP address
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct HaDevice {
+    /// Device registry ID (None for entities without a device)
+    pub device_id: Option<String>,
+    /// Device name (user-defined name takes precedence)
+    pub name: String,
+    pub manufacturer: Option<String>,
+    pub model: Option<String>,
+    /// IP addresses found for the device, sorted
+    pub addresses: Vec<String>,
+    /// Entities the addresses were found on
+    pub entity_ids: Vec<String>,
+}
+
+/// Connection settings for the Home Assistant core API
+#[derive(Debug, Clone)]
+pub struct HaConnection {
+    pub base_url: String,
+    pub token: String,
+}
+
+impl HaConnection {
+    /// Resolve the connection from `[home_assistant]` config, falling back to
+    /// the Supervisor proxy when running as an add-on
+    pub fn resolve(config: &HomeAssistantConfig) -> Option<Self> {
+        match config.token {
+            Some(ref token) if !token.is_empty() => Some(Self {
+                base_url: config
+                    .url
+                    .clone()
+                    .unwrap_or_else(|| SUPERVISOR_CORE_URL.to_string()),
+                token: token.clone(),
+            }),
+            _ => Self::from_supervisor_env(),
+        }
+    }
+
+    /// Connection via the Supervisor proxy when running as an add-on
+    fn from_supervisor_env() -> Option<Self> {
+        let token = std::env::var(SUPERVISOR_TOKEN_ENV).ok()?;
+        if token.is_empty() {
+            return None;
+        }
+        Some(Self {
+            base_url: SUPERVISOR_CORE_URL.to_string(),
+            token,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum HomeAssistantError {
+    /// Failed to create HTTP client
+    HttpClient(String),
+    /// HTTP request failed
+    Request(String),
+    /// Non-success HTTP status
+    HttpStatus(u16),
+    /// Failed to read response body
+    ReadBody(String),
+    /// Failed to parse the template result
+    Parse(String),
+}
+
+impl std::fmt::Display for HomeAssistantError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HomeAssistantError::HttpClient(e) => write!(f, "Failed to create HTTP client: {}", e),
+            HomeAssistantError::Request(e) => write!(f, "HTTP request failed: {}", e),
+            HomeAssistantError::HttpStatus(code) => write!(f, "HTTP error: {}", code),
+            HomeAssistantError::ReadBody(e) => write!(f, "Failed to read response: {}", e),
+            HomeAssistantError::Parse(e) => write!(f, "Failed to parse response: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for HomeAssistantError {}
+
+/// Extract an IP address from an attribute value or URL
+fn parse_ip(value: &str) -> Option<IpAddr> {
+    let value = value.trim();
+    if let Ok(ip) = value.parse() {
+        return Some(ip);
+    }
+
+    // URL such as "http://192.168.1.20:8080/path" or "http://[fe80::1]/"
+    let without_scheme = value.split_once("://").map_or(value, |(_, rest)| rest);
+    let host_port = without_scheme.split(['/', '?', '#']).next()?;
+    let host = match host_port.strip_prefix('[') {
+        Some(rest) => rest.split(']').next()?,
+        None => host_port.split(':').next()?,
+    };
+    host.parse().ok()
+}
+
+/// Group entity records into devices with IP addresses
+fn group_devices(records: Vec<EntityRecord>) -> Vec<HaDevice> {
+    // Entities without a device are keyed by entity ID
+    let mut devices: BTreeMap<String, HaDevice> = BTreeMap::new();
+
+    for record in records {
+        let mut addresses: Vec<IpAddr> = Vec::new();
+        match record.ip {
+            Some(serde_json::Value::String(ref s)) => addresses.extend(parse_ip(s)),
+            Some(serde_json::Value::Array(ref values)) => addresses.extend(
+                values
+                    .iter()
+                    .filter_map(|v| v.as_str())
+                    .filter_map(parse_ip),
+            ),
+            _ => {}
+        }
+        if let Some(ref url) = record.configuration_url {
+            addresses.extend(parse_ip(url));
+        }
+        addresses.retain(|ip| !ip.is_loopback() && !ip.is_unspecified());
+        if addresses.is_empty() {
+            continue;
+        }
+
+        let key = record
+            .device_id
+            .clone()
+            .unwrap_or_else(|| record.entity_id.clone());
+        let device = devices.entry(key).or_insert_with(|| HaDevice {
+            device_id: record.device_id.clone(),
+            name: record
+                .device_name
+                .clone()
+                .or_else(|| record.friendly_name.clone())
+                .unwrap_or_else(|| record.entity_id.clone()),
+            manufacturer: record.manufacturer.clone(),
+            model: record.model.clone(),
+            addresses: Vec::new(),
+            entity_ids: Vec::new(),
+        });
+
+        for ip in addresses {
+            let ip = ip.to_string();
+            if !device.addresses.contains(&ip) {
+                device.addresses.push(ip);
+            }
+        }
+        device.entity_ids.push(record.entity_id);
+    }
+
+    let mut devices: Vec<HaDevice> = devices.into_values().collect();
+    for device in &mut devices {
+        device.addresses.sort();
+    }
+    devices.sort_by_key(|d| d.name.to_lowercase());
+    devices
+}
+
+/// List Home Assistant devices that have at least one IP address
+pub async fn fetch_devices(connection: &HaConnection) -> Result<Vec<HaDevice>, HomeAssistantError> {
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .map_err(|e| HomeAssistantError::HttpClient(e.to_string()))?;
+
+    let url = format!("{}/api/template", connection.base_url.trim_end_matches('/'));
+    debug!("Fetching Home Assistant devices from: {}", url);
+
+    let body = serde_json::json!({ "template": DEVICES_TEMPLATE }).to_string();
+    let response = client
+        .post(&url)
+        .bearer_auth(&connection.token)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| HomeAssistantError::Request(e.to_string()))?;
+
+    if !response.status().is_success() {
+        return Err(HomeAssistantError::HttpStatus(response.status().as_u16()));
+    }
+
+    let text = response
+        .text()
+        .await
+        .map_err(|e| HomeAssistantError::ReadBody(e.to_string()))?;
+
+    let records: Vec<EntityRecord> =
+        serde_json::from_str(&text).map_err(|e| HomeAssistantError::Parse(e.to_string()))?;
+
+    Ok(group_devices(records))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ip() {
+        assert_eq!(parse_ip("192.168.1.20"), "192.168.1.20".parse().ok());
+        assert_eq!(
+            parse_ip("http://192.168.1.20:8080/settings"),
+            "192.168.1.20".parse().ok()
+        );
+        assert_eq!(parse_ip("http://[fe80::1]/"), "fe80::1".parse().ok());
+        assert_eq!(parse_ip("https://my.device.local/"), None);
+        assert_eq!(parse_ip("homeassistant://config/zha"), None);
+    }
+
+    #[test]
+    fn test_group_devices() {
+        let json = r#"[
+            {"entity_id": "sensor.printer_status", "device_id": "d1", "device_name": "Printer",
+             "manufacturer": "HP", "model": "LaserJet", "configuration_url": "http://192.168.1.30/",
+             "friendly_name": "Printer Status", "ip": null},
+            {"entity_id": "device_tracker.printer", "device_id": "d1", "device_name": "Printer",
+             "manufacturer": "HP", "model": "LaserJet", "configuration_url": "http://192.168.1.30/",
+             "friendly_name": "Printer", "ip": "192.168.1.31"},
+            {"entity_id": "device_tracker.phone", "device_id": null, "device_name": null,
+             "manufacturer": null, "model": null, "configuration_url": null,
+             "friendly_name": "Phone", "ip": "192.168.1.40"},
+            {"entity_id": "light.kitchen", "device_id": "d2", "device_name": "Kitchen",
+             "manufacturer": null, "model": null, "configuration_url": null,
+             "friendly_name": "Kitchen", "ip": null}
+        ]"#;
+        let records: Vec<EntityRecord> = serde_json::from_str(json).unwrap();
+        let devices = group_devices(records);
+
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].name, "Phone");
+        assert_eq!(devices[0].device_id, None);
+        assert_eq!(devices[1].name, "Printer");
+        assert_eq!(devices[1].addresses, vec!["192.168.1.30", "192.168.1.31"]);
+        assert_eq!(devices[1].entity_ids.len(), 2);
+    }
+}
@@ -1,41 +1,101 @@
+use crate::ping::DEFAULT_TTL;
 use socket2::{Domain, Protocol, Socket, Type};
 use std::io::{self, Read};
-use std::net::{IpAddr, SocketAddr};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::time::{Duration, Instant};
 use tracing::debug;
 
-const ICMP_ECHO_REQUEST: u8 = 8;
-const ICMP_ECHO_REPLY: u8 = 0;
-const ICMP_HEADER_SIZE: usize = 8;
+pub(crate) const ICMP_ECHO_REQUEST: u8 = 8;
+pub(crate) const ICMP_ECHO_REPLY: u8 = 0;
+pub(crate) const ICMP_HEADER_SIZE: usize = 8;
+pub(crate) const ICMPV6_ECHO_REQUEST: u8 = 128;
+pub(crate) const ICMPV6_ECHO_REPLY: u8 = 129;
 const PAYLOAD_SIZE: usize = 24;
-const PACKET_SIZE: usize = ICMP_HEADER_SIZE + PAYLOAD_SIZE;
+pub(crate) const PACKET_SIZE: usize = ICMP_HEADER_SIZE + PAYLOAD_SIZE;
 
-pub fn ping_dgram(addr: IpAddr, timeout: Duration, ident: u16, seq: u16) -> io::Result<Duration> {
+/// Send an echo request with `payload_len` payload bytes and time to live
+/// `ttl` on an unprivileged DGRAM ICMP socket and wait for the reply. Linux
+/// replaces the echo identifier with the socket's local port, so with
+/// `bind_ident` the socket is bound to port `ident` to send with that
+/// identifier (falling back to a kernel-chosen one if it is taken).
+pub fn ping_dgram(
+    addr: IpAddr,
+    timeout: Duration,
+    ident: u16,
+    seq: u16,
+    bind_ident: bool,
+    ttl: u32,
+    payload_len: usize,
+) -> io::Result<Duration> {
     let start = Instant::now();
-    let dest = SocketAddr::new(addr, 0);
+    let socket = dgram_socket(ttl)?;
+    if bind_ident {
+        let local = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), ident);
+        if let Err(e) = socket.bind(&local.into()) {
+            debug!(target = %addr, "binding echo identifier {} failed: {}", ident, e);
+        }
+    }
+    let packet = echo_request_sized(ident, seq, payload_len);
+    exchange(&socket, addr, start, timeout, &packet)
+}
+
+/// Send an echo request carrying `payload_len` payload bytes with the
+/// don't-fragment bit set and wait for the reply, to probe the path MTU.
+/// The cached path MTU is ignored, so oversized packets are sent and dropped
+/// on the path; packets larger than the interface MTU fail with `EMSGSIZE`.
+/// Linux only.
+#[cfg(target_os = "linux")]
+pub fn ping_dgram_sized(
+    addr: IpAddr,
+    timeout: Duration,
+    seq: u16,
+    payload_len: usize,
+) -> io::Result<Duration> {
+    use std::os::fd::AsRawFd;
+
+    let start = Instant::now();
+    let socket = dgram_socket(DEFAULT_TTL)?;
+    let probe: libc::c_int = libc::IP_PMTUDISC_PROBE;
+    // SAFETY: the fd is a valid socket and the option value is a c_int
+    let rc = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_IP,
+            libc::IP_MTU_DISCOVER,
+            &probe as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if rc < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let ident = (std::process::id() as u16).wrapping_add(seq);
+    let packet = echo_request_sized(ident, seq, payload_len);
+    exchange(&socket, addr, start, timeout, &packet)
+}
 
+fn dgram_socket(ttl: u32) -> io::Result<Socket> {
     let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::ICMPV4))
         .map_err(|e| io::Error::new(e.kind(), format!("socket create failed: {}", e)))?;
-    socket.set_ttl_v4(64)?;
-    socket.set_write_timeout(Some(timeout))?;
+    socket.set_ttl_v4(ttl)?;
+    Ok(socket)
+}
 
-    // Build ICMP echo request
-    let mut packet = [0u8; PACKET_SIZE];
-    packet[0] = ICMP_ECHO_REQUEST;
-    packet[1] = 0; // code
-    // checksum at [2..4], filled below
-    packet[4] = (ident >> 8) as u8;
-    packet[5] = ident as u8;
-    packet[6] = (seq >> 8) as u8;
-    packet[7] = seq as u8;
-    // payload: fill with ident bytes for identification
-    for i in ICMP_HEADER_SIZE..PACKET_SIZE {
-        packet[i] = (ident & 0xff) as u8;
-    }
-    write_checksum(&mut packet);
+/// Send `packet` to `addr` and wait for the echo reply until `timeout` has
+/// passed since `start`
+fn exchange(
+    mut socket: &Socket,
+    addr: IpAddr,
+    start: Instant,
+    timeout: Duration,
+    packet: &[u8],
+) -> io::Result<Duration> {
+    let dest = SocketAddr::new(addr, 0);
+    socket.set_write_timeout(Some(timeout))?;
 
     socket
-        .send_to(&packet, &dest.into())
+        .send_to(packet, &dest.into())
         .map_err(|e| io::Error::new(e.kind(), format!("send failed: {}", e)))?;
 
     debug!(target = %addr, "send_to succeeded, waiting for reply");
@@ -49,7 +109,7 @@ pub fn ping_dgram(addr: IpAddr, timeout: Duration, ident: u16, seq: u16) -> io::
         socket.set_read_timeout(Some(timeout - elapsed))?;
 
         let mut buf = [0u8; 2048];
-        match (&socket).read(&mut buf) {
+        match socket.read(&mut buf) {
             Ok(n) if n < ICMP_HEADER_SIZE => continue,
             Ok(n) => {
                 // Linux strips the IP header on DGRAM ICMP sockets, so the ICMP
@@ -89,6 +149,35 @@ pub fn ping_dgram(addr: IpAddr, timeout: Duration, ident: u16, seq: u16) -> io::
     }
 }
 
+/// Build an ICMP echo request packet
+pub(crate) fn echo_request(ident: u16, seq: u16) -> [u8; PACKET_SIZE] {
+    let mut packet = [0u8; PACKET_SIZE];
+    fill_echo_request(&mut packet, ident, seq);
+    packet
+}
+
+/// Build an ICMP echo request packet with `payload_len` bytes of payload
+pub(crate) fn echo_request_sized(ident: u16, seq: u16, payload_len: usize) -> Vec<u8> {
+    let mut packet = vec![0u8; ICMP_HEADER_SIZE + payload_len];
+    fill_echo_request(&mut packet, ident, seq);
+    packet
+}
+
+fn fill_echo_request(packet: &mut [u8], ident: u16, seq: u16) {
+    packet[0] = ICMP_ECHO_REQUEST;
+    packet[1] = 0; // code
+    // checksum at [2..4], filled below
+    packet[4] = (ident >> 8) as u8;
+    packet[5] = ident as u8;
+    packet[6] = (seq >> 8) as u8;
+    packet[7] = seq as u8;
+    // payload: fill with ident bytes for identification
+    for byte in &mut packet[ICMP_HEADER_SIZE..] {
+        *byte = (ident & 0xff) as u8;
+    }
+    write_checksum(packet);
+}
+
 fn write_checksum(buf: &mut [u8]) {
     // Clear checksum field first
     buf[2] = 0;
@@ -0,0 +1,366 @@
+//! Mirror of ping results to an InfluxDB line protocol endpoint
+//! (`[influx_export]`).
+//!
+//! The exporter subscribes to the `LiveFeed`, so it sees results from ping
+//! tasks and from the ingest API alike; results of agents are tagged with
+//! their `site`. Results are written in batches of `batch_size` lines, or
+//! every `flush_interval` seconds if fewer arrive. A failed write is retried
+//! with exponential backoff while new results keep being buffered; after
+//! `max_retries` failed attempts the batch is dropped.
+//! Works with InfluxDB 1.x and 2.x and with VictoriaMetrics' `/write`.
+
+use crate::backoff::backoff;
+use crate::config::{AppConfig, InfluxExportConfig};
+use crate::live::{LiveFeed, LivePing};
+use crate::tasks::wait_until_enabled;
+use std::collections::VecDeque;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Batches buffered while the endpoint is unreachable; older lines are
+/// dropped beyond that
+const MAX_BUFFERED_BATCHES: usize = 20;
+
+/// Escape a measurement name
+fn escape_measurement(value: &str) -> String {
+    value.replace(',', "\\,").replace(' ', "\\ ")
+}
+
+/// Escape a tag key or value
+fn escape_tag(value: &str) -> String {
+    value
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+/// Line protocol for one result, with a timestamp in seconds
+fn to_line(measurement: &str, ping: &LivePing) -> String {
+    let point = &ping.point;
+    let mut line = escape_measurement(measurement);
+    line.push_str(&format!(",target_id={}", escape_tag(&ping.target_id)));
+    line.push_str(&format!(",target={}", escape_tag(&point.target)));
+    if let Some(name) = point.target_name.as_deref().filter(|n| !n.is_empty()) {
+        line.push_str(&format!(",target_name={}", escape_tag(name)));
+    }
+    line.push_str(&format!(",probe_type={}", point.probe_type.as_str()));
+    if let Some(site) = point.site.as_deref() {
+        line.push_str(&format!(",site={}", escape_tag(site)));
+    }
+
+    line.push_str(&format!(
+        " success={},sequence={}i",
+        point.success, point.sequence
+    ));
+    if let Some(latency) = point.latency_ms {
+        line.push_str(&format!(",latency_ms={}", latency));
+    }
+    line.push_str(&format!(" {}", point.timestamp_unix));
+    line
+}
+
+/// Write URL with second precision, unless the URL sets a precision
+fn write_url(url: &str) -> String {
+    if url.contains("precision=") {
+        url.to_string()
+    } else if url.contains('?') {
+        format!("{}&precision=s", url)
+    } else {
+        format!("{}?precision=s", url)
+    }
+}
+
+/// Buffered lines and the retry state of the batch at their front
+#[derive(Debug, Default)]
+struct ExportBuffer {
+    lines: VecDeque<String>,
+    /// Failed attempts of the current batch
+    attempts: u32,
+    retry_at: Option<Instant>,
+    dropped: u64,
+}
+
+impl ExportBuffer {
+    fn push(&mut self, line: String, batch_size: usize) {
+        self.lines.push_back(line);
+        let limit = batch_size.max(1) * MAX_BUFFERED_BATCHES;
+        while self.lines.len() > limit {
+            self.lines.pop_front();
+            self.dropped += 1;
+        }
+    }
+
+    /// Whether a write is due: a full batch, or any lines once the flush
+    /// interval has passed, but never during backoff
+    fn due(&self, batch_size: usize, interval_elapsed: bool, now: Instant) -> bool {
+        if self.retry_at.is_some_and(|at| now < at) {
+            return false;
+        }
+        self.lines.len() >= batch_size.max(1) || (interval_elapsed && !self.lines.is_empty())
+    }
+
+    fn batch(&self, batch_size: usize) -> String {
+        let mut body = String::new();
+        for line in self.lines.iter().take(batch_size.max(1)) {
+            body.push_str(line);
+            body.push('\n');
+        }
+        body
+    }
+
+    fn written(&mut self, batch_size: usize) {
+        let count = self.lines.len().min(batch_size.max(1));
+        self.lines.drain(..count);
+        self.attempts = 0;
+        self.retry_at = None;
+    }
+
+    /// Record a failed write; returns whether the batch was given up on
+    fn failed(&mut self, batch_size: usize, max_retries: u32, now: Instant) -> bool {
+        self.attempts += 1;
+        if self.attempts > max_retries {
+            let count = self.lines.len().min(batch_size.max(1));
+            self.lines.drain(..count);
+            self.dropped += count as u64;
+            self.attempts = 0;
+            self.retry_at = None;
+            return true;
+        }
+        self.retry_at = Some(now + backoff(self.attempts));
+        false
+    }
+}
+
+async fn write_batch(
+    client: &reqwest::Client,
+    settings: &InfluxExportConfig,
+    url: &str,
+    body: String,
+) -> Result<(), String> {
+    let mut request = client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "text/plain; charset=utf-8")
+        .body(body);
+    if let Some(token) = settings.token.as_deref().filter(|t| !t.is_empty()) {
+        request = request.header(reqwest::header::AUTHORIZATION, format!("Token {}", token));
+    } else if let Some(username) = settings.username.as_deref().filter(|u| !u.is_empty()) {
+        request = request.basic_auth(username, settings.password.as_deref());
+    }
+
+    let response = request.send().await.map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        let status = response.status().as_u16();
+        let body = response.text().await.unwrap_or_default();
+        return Err(format!("HTTP {}: {}", status, body.trim()));
+    }
+    Ok(())
+}
+
+fn read_settings(config: &RwLock<AppConfig>) -> Option<InfluxExportConfig> {
+    match config.read() {
+        Ok(c) => Some(c.influx_export.clone()),
+        Err(e) => {
+            error!("Failed to read config for the InfluxDB export: {}", e);
+            None
+        }
+    }
+}
+
+/// Export until the settings change or the feed closes
+async fn run_export(live: &LiveFeed, config: &RwLock<AppConfig>, settings: &InfluxExportConfig) {
+    let Some(url) = settings.url.as_deref().map(write_url) else {
+        return;
+    };
+    let client = match reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => {
+            error!(
+                "Failed to create HTTP client for the InfluxDB export: {}",
+                e
+            );
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            return;
+        }
+    };
+
+    info!("Exporting ping results to {}", url);
+    let mut results = live.subscribe();
+    let mut buffer = ExportBuffer::default();
+    let mut flush = tokio::time::interval(Duration::from_secs(settings.flush_interval.max(1)));
+    // The first tick completes immediately
+    flush.tick().await;
+
+    loop {
+        let mut interval_elapsed = false;
+        tokio::select! {
+            result = results.recv() => match result {
+                Ok(ping) => buffer.push(to_line(&settings.measurement, &ping), settings.batch_size),
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("InfluxDB export fell behind and skipped {} results", skipped);
+                    buffer.dropped += skipped;
+                }
+                Err(RecvError::Closed) => return,
+            },
+            _ = flush.tick() => {
+                if read_settings(config).is_some_and(|current| current != *settings) {
+                    info!("InfluxDB export settings changed");
+                    return;
+                }
+                interval_elapsed = true;
+            }
+        }
+
+        if interval_elapsed && buffer.dropped > 0 {
+            warn!("{} ping results were not exported", buffer.dropped);
+            buffer.dropped = 0;
+        }
+        if !buffer.due(settings.batch_size, interval_elapsed, Instant::now()) {
+            continue;
+        }
+        let body = buffer.batch(settings.batch_size);
+        match write_batch(&client, settings, &url, body).await {
+            Ok(()) => {
+                debug!("Exported ping results to InfluxDB");
+                buffer.written(settings.batch_size);
+            }
+            Err(e) => {
+                if buffer.failed(settings.batch_size, settings.max_retries, Instant::now()) {
+                    error!(
+                        "InfluxDB write failed, dropping batch after {} attempts: {}",
+                        settings.max_retries + 1,
+                        e
+                    );
+                } else {
+                    warn!(
+                        "InfluxDB write failed (attempt {}), retrying in {:?}: {}",
+                        buffer.attempts,
+                        backoff(buffer.attempts),
+                        e
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Start the exporter. Settings are re-read every flush interval; while
+/// the export is disabled the live feed is not subscribed to.
+pub fn start_influx_export_task(
+    live: Arc<LiveFeed>,
+    config: Arc<RwLock<AppConfig>>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            wait_until_enabled(&config, |c| {
+                let settings = &c.influx_export;
+                settings.enabled && settings.url.as_deref().is_some_and(|u| !u.is_empty())
+            })
+            .await;
+
+            let Some(settings) = read_settings(&config) else {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                continue;
+            };
+
+            run_export(&live, &config, &settings).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::ping::dto::PingDataPoint;
+    use crate::config::ProbeType;
+
+    fn ping(name: Option<&str>, latency: Option<f64>) -> LivePing {
+        LivePing {
+            target_id: "router".to_string(),
+            point: PingDataPoint {
+                timestamp: "2024-01-01T00:00:00+00:00".to_string(),
+                timestamp_unix: 1_704_067_200,
+                target: "192.168.1.1".to_string(),
+                target_name: name.map(str::to_string),
+                sequence: 2,
+                probe_type: ProbeType::Icmp,
+                success: latency.is_some(),
+                latency_ms: latency,
+                metric_type: "ping_latency".to_string(),
+                site: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_to_line() {
+        assert_eq!(
+            to_line("sparkping", &ping(Some("Main Router"), Some(12.5))),
+            "sparkping,target_id=router,target=192.168.1.1,target_name=Main\\ Router,probe_type=icmp success=true,sequence=2i,latency_ms=12.5 1704067200"
+        );
+        assert_eq!(
+            to_line("ping results", &ping(None, None)),
+            "ping\\ results,target_id=router,target=192.168.1.1,probe_type=icmp success=false,sequence=2i 1704067200"
+        );
+
+        let mut remote = ping(None, Some(1.0));
+        remote.point.site = Some("berlin".to_string());
+        assert!(to_line("sparkping", &remote).contains(",probe_type=icmp,site=berlin "));
+    }
+
+    #[test]
+    fn test_write_url() {
+        assert_eq!(
+            write_url("http://influx:8086/api/v2/write?org=home&bucket=ping"),
+            "http://influx:8086/api/v2/write?org=home&bucket=ping&precision=s"
+        );
+        assert_eq!(
+            write_url("http://vm:8428/write"),
+            "http://vm:8428/write?precision=s"
+        );
+        assert_eq!(
+            write_url("http://influx:8086/write?db=ping&precision=ms"),
+            "http://influx:8086/write?db=ping&precision=ms"
+        );
+    }
+
+    #[test]
+    fn test_buffer_batches_and_retries() {
+        let now = Instant::now();
+        let mut buffer = ExportBuffer::default();
+        for i in 0..3 {
+            buffer.push(format!("line{}", i), 2);
+        }
+        assert!(buffer.due(2, false, now));
+        assert_eq!(buffer.batch(2), "line0\nline1\n");
+
+        // Failed writes back off, then the batch is dropped
+        assert!(!buffer.failed(2, 1, now));
+        assert!(!buffer.due(2, true, now));
+        assert!(buffer.due(2, true, now + backoff(1)));
+        assert!(buffer.failed(2, 1, now));
+        assert_eq!(buffer.dropped, 2);
+        assert_eq!(buffer.batch(2), "line2\n");
+
+        // A partial batch is only written once the interval has passed
+        assert!(!buffer.due(2, false, now));
+        assert!(buffer.due(2, true, now));
+        buffer.written(2);
+        assert!(buffer.lines.is_empty());
+    }
+
+    #[test]
+    fn test_buffer_drops_oldest_lines() {
+        let mut buffer = ExportBuffer::default();
+        for i in 0..(MAX_BUFFERED_BATCHES + 1) {
+            buffer.push(format!("line{}", i), 1);
+        }
+        assert_eq!(buffer.lines.len(), MAX_BUFFERED_BATCHES);
+        assert_eq!(buffer.lines.front().map(String::as_str), Some("line1"));
+        assert_eq!(buffer.dropped, 1);
+    }
+}
@@ -0,0 +1,515 @@
+//! Verification and compaction of tsink partitions.
+//!
+//! tsink has no API to check or compact its partitions, so both work on the
+//! partition directories directly, like pruning. Verification reads each
+//! partition's `meta.json` and checks that every series lies inside the
+//! `data` file without overlapping another and that the point counts add up.
+//! Corrupt partitions can be moved to `<data path>/quarantine/` (the same
+//! place the startup audit uses) instead of being deleted by hand.
+//!
+//! Compaction rewrites partitions whose `data` file holds bytes no series
+//! refers to (e.g. left behind by an interrupted flush), using the same
+//! swap as pruning. Corrupt partitions are never rewritten.
+//!
+//! tsink keeps partitions it already loaded open, so quarantined or compacted
+//! partitions only change what queries see after the next restart.
+
+use crate::api::ping::dto::MetricMetadata;
+use crate::retention::{recover_interrupted, swap_partition, PRUNE_LOCK};
+use crate::startup_audit::{quarantine, CleanupAction};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+/// Partitions modified this recently may still be written by tsink and are
+/// skipped, so a flush in progress is not taken for a corrupt partition
+const RECENT_WRITE_SECONDS: i64 = 120;
+
+/// Why a partition failed verification
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PartitionProblem {
+    /// Directory without any files
+    Empty,
+    /// `meta.json` is missing
+    MissingMeta,
+    /// `meta.json` is not valid partition metadata
+    CorruptMeta,
+    /// `data` file is missing
+    MissingData,
+    /// A series points past the end of the `data` file
+    SeriesOutOfBounds,
+    /// Two series share bytes of the `data` file
+    OverlappingSeries,
+    /// The partition's point count differs from the sum of its series
+    PointCountMismatch,
+}
+
+/// A partition that failed verification
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CorruptPartition {
+    /// Partition directory
+    pub partition: String,
+    pub problem: PartitionProblem,
+    /// Human-readable description of the problem
+    pub detail: String,
+    /// Where the partition was moved, if it was quarantined
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quarantined_to: Option<String>,
+}
+
+/// Result of a verification run
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct VerifyReport {
+    /// Unix timestamp in seconds when the verification ran
+    pub ran_at: i64,
+    /// Partitions checked
+    pub checked_partitions: usize,
+    /// Partitions skipped because tsink may still be writing them
+    pub skipped_partitions: usize,
+    /// Series in the valid partitions
+    pub series: usize,
+    /// Data points in the valid partitions
+    pub data_points: u64,
+    pub corrupt: Vec<CorruptPartition>,
+    /// Corrupt partitions that could not be quarantined
+    pub errors: Vec<String>,
+}
+
+/// Result of a compaction run
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct CompactReport {
+    /// Unix timestamp in seconds when the compaction ran
+    pub ran_at: i64,
+    /// Partitions checked
+    pub checked_partitions: usize,
+    /// Partitions rewritten without unreferenced bytes
+    pub compacted_partitions: usize,
+    /// Bytes removed from `data` files
+    pub reclaimed_bytes: u64,
+    /// Corrupt partitions left untouched (see POST /api/storage/quarantine)
+    pub corrupt_partitions: usize,
+    /// Partitions that could not be compacted
+    pub errors: Vec<String>,
+}
+
+/// Parsed and checked layout of a valid partition
+#[derive(Debug)]
+struct PartitionLayout {
+    meta: serde_json::Value,
+    /// Series keys in meta.json with their metadata, sorted by offset
+    series: Vec<(String, MetricMetadata)>,
+    data_len: u64,
+}
+
+impl PartitionLayout {
+    fn data_points(&self) -> u64 {
+        self.series.iter().map(|(_, m)| m.num_data_points).sum()
+    }
+
+    /// Bytes of the data file no series refers to
+    fn unreferenced_bytes(&self) -> u64 {
+        let referenced: u64 = self.series.iter().map(|(_, m)| m.encoded_size).sum();
+        self.data_len - referenced
+    }
+}
+
+/// Check all partitions under `data_path`, moving corrupt ones to the
+/// quarantine directory if `quarantine_corrupt` is set
+pub fn verify_partitions(
+    data_path: &Path,
+    now: i64,
+    quarantine_corrupt: bool,
+) -> std::io::Result<VerifyReport> {
+    let _guard = PRUNE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut report = VerifyReport {
+        ran_at: now,
+        ..Default::default()
+    };
+    if !data_path.exists() {
+        return Ok(report);
+    }
+
+    for partition in list_partitions(data_path)? {
+        if recently_modified(&partition, now) {
+            report.skipped_partitions += 1;
+            continue;
+        }
+        report.checked_partitions += 1;
+
+        let (problem, detail) = match check_partition(&partition) {
+            Ok(layout) => {
+                report.series += layout.series.len();
+                report.data_points += layout.data_points();
+                continue;
+            }
+            Err(problem) => problem,
+        };
+        warn!(
+            "Partition {} failed verification: {}",
+            partition.display(),
+            detail
+        );
+
+        let mut corrupt = CorruptPartition {
+            partition: partition.display().to_string(),
+            problem,
+            detail,
+            quarantined_to: None,
+        };
+        if quarantine_corrupt {
+            match quarantine(data_path, &partition) {
+                Ok(CleanupAction::MovedTo { path }) => {
+                    warn!("Quarantined partition {} to {}", partition.display(), path);
+                    corrupt.quarantined_to = Some(path);
+                }
+                Ok(CleanupAction::Removed) => {}
+                Err(e) => report.errors.push(format!(
+                    "Failed to quarantine {}: {}",
+                    partition.display(),
+                    e
+                )),
+            }
+        }
+        report.corrupt.push(corrupt);
+    }
+
+    info!(
+        "Verified {} partitions: {} corrupt, {} skipped",
+        report.checked_partitions,
+        report.corrupt.len(),
+        report.skipped_partitions
+    );
+    Ok(report)
+}
+
+/// Rewrite partitions whose data file holds bytes no series refers to
+pub fn compact_partitions(data_path: &Path, now: i64) -> std::io::Result<CompactReport> {
+    let _guard = PRUNE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut report = CompactReport {
+        ran_at: now,
+        ..Default::default()
+    };
+    if !data_path.exists() {
+        return Ok(report);
+    }
+    recover_interrupted(data_path)?;
+
+    for partition in list_partitions(data_path)? {
+        if recently_modified(&partition, now) {
+            continue;
+        }
+        report.checked_partitions += 1;
+
+        let layout = match check_partition(&partition) {
+            Ok(layout) => layout,
+            Err(_) => {
+                report.corrupt_partitions += 1;
+                continue;
+            }
+        };
+        let unreferenced = layout.unreferenced_bytes();
+        if unreferenced == 0 {
+            continue;
+        }
+        match compact_partition(&partition, layout) {
+            Ok(()) => {
+                report.compacted_partitions += 1;
+                report.reclaimed_bytes += unreferenced;
+            }
+            Err(e) => {
+                warn!("Failed to compact partition {}: {}", partition.display(), e);
+                report
+                    .errors
+                    .push(format!("{}: {}", partition.display(), e));
+            }
+        }
+    }
+
+    if report.compacted_partitions > 0 {
+        info!(
+            "Compacted {} partitions, reclaimed {} bytes",
+            report.compacted_partitions, report.reclaimed_bytes
+        );
+    }
+    Ok(report)
+}
+
+/// `p-*` partition directories, sorted by name
+fn list_partitions(data_path: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut partitions: Vec<PathBuf> = fs::read_dir(data_path)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| {
+            p.is_dir()
+                && p.file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| n.starts_with("p-"))
+        })
+        .collect();
+    partitions.sort();
+    Ok(partitions)
+}
+
+fn recently_modified(partition: &Path, now: i64) -> bool {
+    fs::metadata(partition)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .is_some_and(|t| now - (t.as_secs() as i64) < RECENT_WRITE_SECONDS)
+}
+
+/// Parse and check a partition's metadata against its data file
+fn check_partition(partition: &Path) -> Result<PartitionLayout, (PartitionProblem, String)> {
+    let is_empty = fs::read_dir(partition)
+        .map(|mut entries| entries.next().is_none())
+        .unwrap_or(false);
+    if is_empty {
+        return Err((PartitionProblem::Empty, "partition is empty".to_string()));
+    }
+
+    let meta_content = fs::read_to_string(partition.join("meta.json")).map_err(|e| {
+        (
+            PartitionProblem::MissingMeta,
+            format!("cannot read meta.json: {}", e),
+        )
+    })?;
+    let corrupt_meta = |detail: String| (PartitionProblem::CorruptMeta, detail);
+    let meta: serde_json::Value = serde_json::from_str(&meta_content)
+        .map_err(|e| corrupt_meta(format!("meta.json is not valid JSON: {}", e)))?;
+    let metrics = meta
+        .get("metrics")
+        .and_then(|m| m.as_object())
+        .ok_or_else(|| corrupt_meta("meta.json has no metrics".to_string()))?;
+
+    let mut series = Vec::with_capacity(metrics.len());
+    for (key, value) in metrics {
+        let metric: MetricMetadata = serde_json::from_value(value.clone())
+            .map_err(|e| corrupt_meta(format!("series {}: {}", key, e)))?;
+        if metric.min_timestamp > metric.max_timestamp {
+            return Err(corrupt_meta(format!(
+                "series {} ends before it starts",
+                key
+            )));
+        }
+        series.push((key.clone(), metric));
+    }
+    series.sort_by_key(|(_, m)| m.offset);
+
+    let data_len = fs::metadata(partition.join("data"))
+        .map_err(|e| {
+            (
+                PartitionProblem::MissingData,
+                format!("cannot read data file: {}", e),
+            )
+        })?
+        .len();
+
+    let mut previous_end = 0;
+    let mut previous_key = "";
+    for (key, metric) in &series {
+        let end = metric.offset.saturating_add(metric.encoded_size);
+        if end > data_len {
+            return Err((
+                PartitionProblem::SeriesOutOfBounds,
+                format!(
+                    "series {} ends at byte {} of a {} byte data file",
+                    key, end, data_len
+                ),
+            ));
+        }
+        if metric.offset < previous_end {
+            return Err((
+                PartitionProblem::OverlappingSeries,
+                format!("series {} overlaps series {}", key, previous_key),
+            ));
+        }
+        previous_end = end;
+        previous_key = key;
+    }
+
+    let layout = PartitionLayout {
+        meta,
+        series,
+        data_len,
+    };
+    if let Some(expected) = layout.meta.get("num_data_points").and_then(|n| n.as_u64()) {
+        let actual = layout.data_points();
+        if expected != actual {
+            return Err((
+                PartitionProblem::PointCountMismatch,
+                format!(
+                    "partition counts {} data points but its series hold {}",
+                    expected, actual
+                ),
+            ));
+        }
+    }
+    Ok(layout)
+}
+
+/// Rewrite a valid partition with only the bytes its series refer to
+fn compact_partition(partition: &Path, layout: PartitionLayout) -> std::io::Result<()> {
+    let data = fs::read(partition.join("data"))?;
+    let mut meta = layout.meta;
+    let mut new_data = Vec::with_capacity(data.len());
+    for (key, metric) in &layout.series {
+        let start = metric.offset as usize;
+        let end = start + metric.encoded_size as usize;
+        let Some(chunk) = data.get(start..end) else {
+            // The data file shrank since it was checked
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("series {} points past the end of the data file", key),
+            ));
+        };
+        meta["metrics"][key]["offset"] = serde_json::json!(new_data.len());
+        new_data.extend_from_slice(chunk);
+    }
+    swap_partition(partition, &new_data, &meta)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::startup_audit::QUARANTINE_DIR;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "sparkping-integrity-{}-{}",
+            name,
+            uuid::Uuid::new_v4()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Write a partition with one series per (key, offset, encoded size)
+    fn write_partition(dir: &Path, data: &str, series: &[(&str, usize, usize)]) {
+        fs::create_dir_all(dir).unwrap();
+        let mut metrics = serde_json::Map::new();
+        for (key, offset, size) in series {
+            metrics.insert(
+                key.to_string(),
+                serde_json::json!({
+                    "name": key,
+                    "offset": offset,
+                    "encoded_size": size,
+                    "min_timestamp": 0,
+                    "max_timestamp": 10,
+                    "num_data_points": 2,
+                }),
+            );
+        }
+        let meta = serde_json::json!({
+            "min_timestamp": 0,
+            "max_timestamp": 10,
+            "num_data_points": 2 * series.len(),
+            "metrics": metrics,
+        });
+        fs::write(dir.join("data"), data).unwrap();
+        fs::write(dir.join("meta.json"), meta.to_string()).unwrap();
+    }
+
+    /// A time well after the test partitions were written
+    fn later() -> i64 {
+        chrono::Utc::now().timestamp() + 1000
+    }
+
+    #[test]
+    fn test_verify_partitions() {
+        let data = temp_dir("verify");
+        write_partition(&data.join("p-1"), "AABBB", &[("a", 0, 2), ("b", 2, 3)]);
+        write_partition(&data.join("p-2"), "AAB", &[("a", 0, 2), ("b", 2, 3)]);
+        write_partition(&data.join("p-3"), "AABBB", &[("a", 0, 3), ("b", 2, 3)]);
+        fs::create_dir_all(data.join("p-4")).unwrap();
+        fs::write(data.join("p-4/meta.json"), "{\"metrics\": {").unwrap();
+
+        // Everything was just written and may still be in use by tsink
+        let report = verify_partitions(&data, chrono::Utc::now().timestamp(), true).unwrap();
+        assert_eq!(report.skipped_partitions, 4);
+        assert!(report.corrupt.is_empty());
+
+        let report = verify_partitions(&data, later(), false).unwrap();
+        assert_eq!(report.checked_partitions, 4);
+        assert_eq!(report.series, 2);
+        assert_eq!(report.data_points, 4);
+        let problems: Vec<_> = report.corrupt.iter().map(|c| c.problem).collect();
+        assert_eq!(
+            problems,
+            vec![
+                PartitionProblem::SeriesOutOfBounds,
+                PartitionProblem::OverlappingSeries,
+                PartitionProblem::CorruptMeta,
+            ]
+        );
+        assert!(data.join("p-2").exists());
+
+        let report = verify_partitions(&data, later(), true).unwrap();
+        assert!(report.errors.is_empty());
+        assert!(report.corrupt.iter().all(|c| c.quarantined_to.is_some()));
+        assert!(data.join("p-1").exists());
+        assert!(!data.join("p-2").exists());
+        assert!(data.join(QUARANTINE_DIR).join("p-2/meta.json").exists());
+
+        let report = verify_partitions(&data, later(), false).unwrap();
+        assert!(report.corrupt.is_empty());
+
+        fs::remove_dir_all(&data).unwrap();
+    }
+
+    #[test]
+    fn test_point_count_mismatch() {
+        let data = temp_dir("points");
+        let partition = data.join("p-1");
+        write_partition(&partition, "AA", &[("a", 0, 2)]);
+        let mut meta: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(partition.join("meta.json")).unwrap())
+                .unwrap();
+        meta["num_data_points"] = serde_json::json!(5);
+        fs::write(partition.join("meta.json"), meta.to_string()).unwrap();
+
+        let problem = check_partition(&partition).unwrap_err().0;
+        assert_eq!(problem, PartitionProblem::PointCountMismatch);
+
+        fs::remove_dir_all(&data).unwrap();
+    }
+
+    #[test]
+    fn test_compact_partitions() {
+        let data = temp_dir("compact");
+        // Gaps before, between and after the series
+        write_partition(
+            &data.join("p-1"),
+            "xxAAyyBBBzz",
+            &[("a", 2, 2), ("b", 6, 3)],
+        );
+        write_partition(&data.join("p-2"), "AA", &[("a", 0, 2)]);
+        write_partition(&data.join("p-3"), "A", &[("a", 0, 2)]);
+
+        let report = compact_partitions(&data, later()).unwrap();
+        assert_eq!(report.checked_partitions, 3);
+        assert_eq!(report.compacted_partitions, 1);
+        assert_eq!(report.reclaimed_bytes, 6);
+        assert_eq!(report.corrupt_partitions, 1);
+        assert!(report.errors.is_empty());
+
+        assert_eq!(fs::read_to_string(data.join("p-1/data")).unwrap(), "AABBB");
+        let meta: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(data.join("p-1/meta.json")).unwrap()).unwrap();
+        assert_eq!(meta["metrics"]["a"]["offset"], 0);
+        assert_eq!(meta["metrics"]["b"]["offset"], 2);
+        // Corrupt partitions are left alone
+        assert_eq!(fs::read_to_string(data.join("p-3/data")).unwrap(), "A");
+
+        let report = verify_partitions(&data, later(), false).unwrap();
+        assert_eq!(report.corrupt.len(), 1);
+
+        fs::remove_dir_all(&data).unwrap();
+    }
+}
@@ -6,16 +6,23 @@
 use serde::{Deserialize, Serialize};
 use std::net::{IpAddr, Ipv4Addr};
 use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::net::TcpStream;
 use tokio::sync::mpsc;
 use tokio::time::timeout;
 use tracing::{debug, error, info, warn};
+use utoipa::ToSchema;
 
+use crate::config::SocketType;
 use crate::discovery::{DiscoveredDevice, DiscoveryEvent};
+use crate::netbios::{lookup_llmnr, lookup_netbios};
+use crate::ping::{probe_once, Probe, ProbeOptions};
+use crate::reverse_dns::{self, ReverseResolver};
 
 /// A subnet with additional metadata for display
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct SubnetSuggestion {
     /// Human-readable label for this subnet
     pub label: String,
@@ -57,6 +64,61 @@ pub struct IpScanRequest {
     /// Number of concurrent scans (default: 50)
     #[serde(default = "default_concurrency")]
     pub concurrency: usize,
+    /// Look up the PTR record of each found device (default: true)
+    #[serde(default = "default_reverse_dns")]
+    pub reverse_dns: bool,
+    /// DNS server for reverse lookups, "ip" or "ip:port" (default: the
+    /// first nameserver in /etc/resolv.conf)
+    #[serde(default)]
+    pub dns_server: Option<String>,
+    /// Ask devices without a PTR record for their NetBIOS, then LLMNR,
+    /// name (default: true)
+    #[serde(default = "default_netbios")]
+    pub netbios: bool,
+    /// Timeout for each name lookup in milliseconds (default: 1000)
+    #[serde(default = "default_dns_timeout_ms")]
+    pub dns_timeout_ms: u64,
+    /// Check the ports with TCP connects (default: true)
+    #[serde(default = "default_tcp")]
+    pub tcp: bool,
+    /// Also send each address an ICMP echo request, finding devices without
+    /// open ports (default: false)
+    #[serde(default)]
+    pub icmp: bool,
+    /// Socket type of the ICMP echo sweep (default: `[ping] socket_type`)
+    #[serde(default)]
+    pub socket_type: SocketType,
+    /// Counts scanned addresses while the scan runs
+    #[serde(skip)]
+    pub progress: Option<Arc<ScanProgress>>,
+}
+
+fn default_tcp() -> bool {
+    true
+}
+
+/// Interval between progress events of a running IP scan
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Addresses an IP scan has checked out of the ones in its range
+#[derive(Debug, Default)]
+pub struct ScanProgress {
+    scanned: AtomicUsize,
+    total: AtomicUsize,
+}
+
+impl ScanProgress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// (scanned, total) addresses; total is 0 until the range is parsed
+    pub fn get(&self) -> (usize, usize) {
+        (
+            self.scanned.load(Ordering::Relaxed),
+            self.total.load(Ordering::Relaxed),
+        )
+    }
 }
 
 fn default_ports() -> Vec<u16> {
@@ -71,6 +133,74 @@ fn default_concurrency() -> usize {
     50
 }
 
+fn default_reverse_dns() -> bool {
+    true
+}
+
+fn default_netbios() -> bool {
+    true
+}
+
+fn default_dns_timeout_ms() -> u64 {
+    reverse_dns::DEFAULT_TIMEOUT_MS
+}
+
+/// Name of a found device: its PTR record, else (for Windows hosts without
+/// one) its NetBIOS or LLMNR name. NetBIOS also reports the MAC address.
+async fn resolve_name(
+    ip: Ipv4Addr,
+    resolver: Option<&ReverseResolver>,
+    netbios: bool,
+    timeout: Duration,
+) -> (Option<String>, Option<String>) {
+    if let Some(resolver) = resolver {
+        if let Some(hostname) = resolver.lookup(IpAddr::V4(ip)).await {
+            return (Some(hostname), None);
+        }
+    }
+    if !netbios {
+        return (None, None);
+    }
+    if let Some(info) = lookup_netbios(ip, timeout).await {
+        debug!(
+            "{} is {} (workgroup {:?}) via NetBIOS",
+            ip, info.name, info.workgroup
+        );
+        return (Some(info.name), info.mac);
+    }
+    (lookup_llmnr(IpAddr::V4(ip), timeout).await, None)
+}
+
+/// How the gateway of a scanned subnet was determined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GatewaySource {
+    /// Gateway of a route in the local routing table
+    RoutingTable,
+    /// First host of the subnet (x.x.x.1), the usual router address
+    FirstHost,
+}
+
+/// Gateway information attached to the scan result of a subnet's gateway
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GatewayInfo {
+    /// The subnet this device is the gateway of (CIDR notation)
+    pub subnet: String,
+    pub source: GatewaySource,
+    /// Suggested name for a ping target (e.g., "Gateway – 192.168.1.0/24")
+    pub target_name: String,
+}
+
+impl GatewayInfo {
+    fn new(subnet: String, source: GatewaySource) -> Self {
+        Self {
+            target_name: format!("Gateway – {}", subnet),
+            subnet,
+            source,
+        }
+    }
+}
+
 /// Parse a CIDR notation string into network address, prefix length, and range
 fn parse_cidr(cidr: &str) -> Result<(Ipv4Addr, u8, Ipv4Addr, Ipv4Addr), String> {
     let parts: Vec<&str> = cidr.split('/').collect();
@@ -132,6 +262,14 @@ fn get_ips_in_range(start: Ipv4Addr, end: Ipv4Addr) -> Vec<Ipv4Addr> {
     (start_u32..=end_u32).map(Ipv4Addr::from).collect()
 }
 
+/// Start and end address of a range specification
+pub fn parse_range_spec(range: &IpRangeSpec) -> Result<(Ipv4Addr, Ipv4Addr), String> {
+    match range {
+        IpRangeSpec::Cidr { cidr } => parse_cidr(cidr).map(|(_, _, start, end)| (start, end)),
+        IpRangeSpec::Range { start_ip, end_ip } => parse_ip_range(start_ip, end_ip),
+    }
+}
+
 /// Convert prefix length to subnet mask
 fn prefix_to_mask(prefix: u8) -> Ipv4Addr {
     if prefix == 0 {
@@ -313,23 +451,187 @@ pub fn get_suggested_subnets() -> Vec<SubnetSuggestion> {
     subnets
 }
 
-/// Check if a host is reachable on any of the specified ports
-async fn check_host(ip: Ipv4Addr, ports: &[u16], timeout_duration: Duration) -> Option<u16> {
-    for &port in ports {
-        let addr = format!("{}:{}", ip, port);
-        if let Ok(result) = timeout(timeout_duration, TcpStream::connect(&addr)).await {
-            if result.is_ok() {
-                return Some(port);
+/// Parse gateways from the Linux routing table (`/proc/net/route`).
+/// Default routes come first.
+fn parse_route_table(content: &str) -> Vec<Ipv4Addr> {
+    const RTF_GATEWAY: u32 = 0x2;
+
+    let mut routes: Vec<(bool, Ipv4Addr)> = content
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let destination = u32::from_str_radix(fields.get(1)?, 16).ok()?;
+            let gateway = u32::from_str_radix(fields.get(2)?, 16).ok()?;
+            let flags = u32::from_str_radix(fields.get(3)?, 16).ok()?;
+            if flags & RTF_GATEWAY == 0 || gateway == 0 {
+                return None;
+            }
+            // Addresses are stored in network byte order
+            Some((destination != 0, Ipv4Addr::from(gateway.to_le_bytes())))
+        })
+        .collect();
+    routes.sort_by_key(|(specific, _)| *specific);
+
+    let mut gateways = Vec::new();
+    for (_, gateway) in routes {
+        if !gateways.contains(&gateway) {
+            gateways.push(gateway);
+        }
+    }
+    gateways
+}
+
+/// Get the gateways from the local routing table
+pub fn get_routing_gateways() -> Vec<Ipv4Addr> {
+    if cfg!(target_os = "linux") {
+        match std::fs::read_to_string("/proc/net/route") {
+            Ok(content) => parse_route_table(&content),
+            Err(e) => {
+                warn!("Failed to read routing table: {}", e);
+                Vec::new()
+            }
+        }
+    } else if cfg!(target_os = "macos") {
+        // "route -n get default" prints a "gateway: <ip>" line
+        match Command::new("route")
+            .args(["-n", "get", "default"])
+            .output()
+        {
+            Ok(output) => String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .filter_map(|line| line.trim().strip_prefix("gateway:"))
+                .filter_map(|ip| ip.trim().parse().ok())
+                .collect(),
+            Err(e) => {
+                warn!("Failed to read default route: {}", e);
+                Vec::new()
             }
         }
+    } else {
+        Vec::new()
+    }
+}
+
+/// Find the gateway of a scanned range.
+///
+/// A routing table gateway inside the range wins; otherwise the x.x.x.1
+/// address of the range's first /24 is assumed if it is in the range.
+fn find_gateway(
+    start: Ipv4Addr,
+    end: Ipv4Addr,
+    routing_gateways: &[Ipv4Addr],
+) -> Option<(Ipv4Addr, GatewaySource)> {
+    let in_range = |ip: &Ipv4Addr| (u32::from(start)..=u32::from(end)).contains(&u32::from(*ip));
+
+    if let Some(gateway) = routing_gateways.iter().find(|ip| in_range(ip)) {
+        return Some((*gateway, GatewaySource::RoutingTable));
+    }
+
+    let octets = start.octets();
+    let first_host = Ipv4Addr::new(octets[0], octets[1], octets[2], 1);
+    in_range(&first_host).then_some((first_host, GatewaySource::FirstHost))
+}
+
+/// Check which of the specified ports accept a TCP connection on a host.
+/// Ports are probed concurrently; the returned list keeps the requested order.
+async fn check_host(ip: Ipv4Addr, ports: &[u16], timeout_duration: Duration) -> Vec<u16> {
+    let checks = ports.iter().map(|&port| async move {
+        let addr = format!("{}:{}", ip, port);
+        match timeout(timeout_duration, TcpStream::connect(&addr)).await {
+            Ok(Ok(_)) => Some(port),
+            _ => None,
+        }
+    });
+
+    futures::future::join_all(checks)
+        .await
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+/// Send a progress event every `PROGRESS_INTERVAL` until aborted. Events
+/// the receiver has no room for are skipped; the next one catches up.
+async fn report_progress(
+    tx: mpsc::Sender<DiscoveryEvent>,
+    progress: Arc<ScanProgress>,
+    found: Arc<AtomicUsize>,
+) {
+    let started = std::time::Instant::now();
+    let mut interval = tokio::time::interval(PROGRESS_INTERVAL);
+    // The first tick completes immediately
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        let (scanned, total) = progress.get();
+        let event = DiscoveryEvent::Progress {
+            scanned,
+            total,
+            found: found.load(Ordering::Relaxed),
+            eta_seconds: estimate_eta(scanned, total, started.elapsed()),
+        };
+        if let Err(mpsc::error::TrySendError::Closed(_)) = tx.try_send(event) {
+            break;
+        }
     }
-    None
+}
+
+/// Remaining time if the rest of the range is scanned at the rate so far
+fn estimate_eta(scanned: usize, total: usize, elapsed: Duration) -> Option<u64> {
+    if scanned == 0 {
+        return None;
+    }
+    let remaining = total.saturating_sub(scanned) as f64;
+    Some((elapsed.as_secs_f64() * remaining / scanned as f64).ceil() as u64)
+}
+
+/// Whether a host answers an ICMP echo request within the timeout. Uses the
+/// sockets of the ping engine.
+async fn echo_host(ip: Ipv4Addr, socket_type: SocketType, timeout_duration: Duration) -> bool {
+    probe_once(
+        IpAddr::V4(ip),
+        Probe::Icmp(socket_type),
+        ProbeOptions::with_timeout(timeout_duration),
+        None,
+    )
+    .await
+    .is_ok()
+}
+
+/// How a host was detected, e.g. "ip_scan (icmp, ports 22, 80)"
+fn detection_method(echo_reply: bool, open_ports: &[u16]) -> String {
+    let mut methods = Vec::new();
+    if echo_reply {
+        methods.push("icmp".to_string());
+    }
+    if !open_ports.is_empty() {
+        let port_list = open_ports
+            .iter()
+            .map(|p| p.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        methods.push(format!("ports {}", port_list));
+    }
+    if methods.is_empty() {
+        methods.push("gateway".to_string());
+    }
+    format!("ip_scan ({})", methods.join(", "))
 }
 
 /// Run IP scan discovery and send discovered devices to the channel
 pub async fn run_ip_scan_discovery(tx: mpsc::Sender<DiscoveryEvent>, request: IpScanRequest) {
     info!("Starting IP scan discovery");
 
+    if !request.tcp && !request.icmp {
+        let _ = tx
+            .send(DiscoveryEvent::Error {
+                message: "IP scan needs TCP port checks or an ICMP echo sweep".to_string(),
+            })
+            .await;
+        return;
+    }
+
     // Send started event
     if tx
         .send(DiscoveryEvent::Started {
@@ -341,10 +643,16 @@ pub async fn run_ip_scan_discovery(tx: mpsc::Sender<DiscoveryEvent>, request: Ip
         return;
     }
 
-    // Parse the IP range
-    let ips_to_scan = match &request.range {
+    // Parse the IP range, keeping the subnet for gateway naming
+    let (ips_to_scan, subnet) = match &request.range {
         IpRangeSpec::Cidr { cidr } => match parse_cidr(cidr) {
-            Ok((_, _, start, end)) => get_ips_in_range(start, end),
+            Ok((ip, prefix, start, end)) => {
+                let network = Ipv4Addr::from(u32::from(ip) & u32::from(prefix_to_mask(prefix)));
+                (
+                    get_ips_in_range(start, end),
+                    Some(format!("{}/{}", network, prefix)),
+                )
+            }
             Err(e) => {
                 error!("Failed to parse CIDR: {}", e);
                 let _ = tx
@@ -356,7 +664,7 @@ pub async fn run_ip_scan_discovery(tx: mpsc::Sender<DiscoveryEvent>, request: Ip
             }
         },
         IpRangeSpec::Range { start_ip, end_ip } => match parse_ip_range(start_ip, end_ip) {
-            Ok((start, end)) => get_ips_in_range(start, end),
+            Ok((start, end)) => (get_ips_in_range(start, end), None),
             Err(e) => {
                 error!("Failed to parse IP range: {}", e);
                 let _ = tx
@@ -371,6 +679,26 @@ pub async fn run_ip_scan_discovery(tx: mpsc::Sender<DiscoveryEvent>, request: Ip
 
     let total_ips = ips_to_scan.len();
     info!("Scanning {} IP addresses", total_ips);
+    let progress = request.progress.clone().unwrap_or_default();
+    progress.total.store(total_ips, Ordering::Relaxed);
+
+    let routing_gateways = tokio::task::spawn_blocking(get_routing_gateways)
+        .await
+        .unwrap_or_default();
+    let gateway = match (ips_to_scan.first(), ips_to_scan.last()) {
+        (Some(&start), Some(&end)) => {
+            find_gateway(start, end, &routing_gateways).map(|(ip, source)| {
+                // Custom ranges are named after the gateway's /24
+                let subnet = subnet.clone().unwrap_or_else(|| {
+                    let octets = ip.octets();
+                    format!("{}.{}.{}.0/24", octets[0], octets[1], octets[2])
+                });
+                debug!("Assuming gateway {} for {} ({:?})", ip, subnet, source);
+                (ip, GatewayInfo::new(subnet, source))
+            })
+        }
+        _ => None,
+    };
 
     // Update status message
     let _ = tx
@@ -379,8 +707,34 @@ pub async fn run_ip_scan_discovery(tx: mpsc::Sender<DiscoveryEvent>, request: Ip
         })
         .await;
 
+    // Found devices are named after their PTR record (or NetBIOS/LLMNR
+    // name); a resolver that can't be set up only costs the PTR names
+    let resolver = if request.reverse_dns {
+        match ReverseResolver::new(
+            request.dns_server.as_deref(),
+            Duration::from_millis(request.dns_timeout_ms),
+        ) {
+            Ok(resolver) => Some(std::sync::Arc::new(resolver)),
+            Err(e) => {
+                warn!("Reverse DNS disabled for this scan: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let name_timeout = Duration::from_millis(request.dns_timeout_ms);
+    let netbios = request.netbios;
+
     let timeout_duration = Duration::from_millis(request.timeout_ms);
-    let ports = request.ports.clone();
+    let ports = if request.tcp {
+        request.ports.clone()
+    } else {
+        Vec::new()
+    };
+    let icmp = request.icmp;
+    let socket_type = request.socket_type;
     let concurrency = request.concurrency;
 
     // Use a semaphore to limit concurrency
@@ -388,6 +742,13 @@ pub async fn run_ip_scan_discovery(tx: mpsc::Sender<DiscoveryEvent>, request: Ip
     let mut handles = Vec::new();
     let found_count = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
 
+    // Report progress while the scan runs so large ranges don't look stuck
+    let reporter = tokio::spawn(report_progress(
+        tx.clone(),
+        Arc::clone(&progress),
+        Arc::clone(&found_count),
+    ));
+
     for ip in ips_to_scan {
         if tx.is_closed() {
             info!("Client disconnected, stopping IP scan");
@@ -398,21 +759,43 @@ pub async fn run_ip_scan_discovery(tx: mpsc::Sender<DiscoveryEvent>, request: Ip
         let tx = tx.clone();
         let ports = ports.clone();
         let found_count = found_count.clone();
+        let resolver = resolver.clone();
+        let progress = progress.clone();
+        let gateway_info = gateway
+            .as_ref()
+            .filter(|(gateway_ip, _)| *gateway_ip == ip)
+            .map(|(_, info)| info.clone());
 
         let handle = tokio::spawn(async move {
             let _permit = semaphore.acquire().await;
 
-            if let Some(port) = check_host(ip, &ports, timeout_duration).await {
+            let (open_ports, echo_reply) =
+                tokio::join!(check_host(ip, &ports, timeout_duration), async {
+                    icmp && echo_host(ip, socket_type, timeout_duration).await
+                });
+            progress.scanned.fetch_add(1, Ordering::Relaxed);
+            // A routing table gateway is known to exist even if no port is open
+            let known_gateway = gateway_info
+                .as_ref()
+                .is_some_and(|g| g.source == GatewaySource::RoutingTable);
+            if !open_ports.is_empty() || echo_reply || known_gateway {
+                let discovery_method = detection_method(echo_reply, &open_ports);
+                let (hostname, mac) =
+                    resolve_name(ip, resolver.as_deref(), netbios, name_timeout).await;
+                let hostname = hostname.unwrap_or_else(|| ip.to_string());
                 let device = DiscoveredDevice {
-                    name: ip.to_string(),
+                    name: hostname.clone(),
                     address: ip.to_string(),
                     addresses: vec![ip.to_string()],
-                    hostname: ip.to_string(),
+                    hostname,
                     services: vec![],
                     txt_properties: std::collections::HashMap::new(),
                     ttl: None,
-                    discovery_method: format!("ip_scan (port {})", port),
+                    discovery_method,
                     vendor_info: None,
+                    open_ports,
+                    gateway: gateway_info,
+                    mac,
                 };
 
                 found_count.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
@@ -427,6 +810,7 @@ pub async fn run_ip_scan_discovery(tx: mpsc::Sender<DiscoveryEvent>, request: Ip
     for handle in handles {
         let _ = handle.await;
     }
+    reporter.abort();
 
     let final_count = found_count.load(std::sync::atomic::Ordering::SeqCst);
     info!("IP scan completed, found {} devices", final_count);
@@ -443,6 +827,24 @@ pub async fn run_ip_scan_discovery(tx: mpsc::Sender<DiscoveryEvent>, request: Ip
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_detection_method() {
+        assert_eq!(detection_method(true, &[]), "ip_scan (icmp)");
+        assert_eq!(
+            detection_method(true, &[22, 80]),
+            "ip_scan (icmp, ports 22, 80)"
+        );
+        assert_eq!(detection_method(false, &[443]), "ip_scan (ports 443)");
+        assert_eq!(detection_method(false, &[]), "ip_scan (gateway)");
+    }
+
+    #[test]
+    fn test_estimate_eta() {
+        assert_eq!(estimate_eta(0, 65536, Duration::from_secs(5)), None);
+        assert_eq!(estimate_eta(1000, 4000, Duration::from_secs(10)), Some(30));
+        assert_eq!(estimate_eta(4000, 4000, Duration::from_secs(40)), Some(0));
+    }
+
     #[test]
     fn test_parse_cidr() {
         let (ip, prefix, start, end) = parse_cidr("192.168.1.0/24").unwrap();
@@ -493,4 +895,42 @@ mod tests {
         assert!(!is_private_ip(&Ipv4Addr::new(8, 8, 8, 8)));
         assert!(!is_private_ip(&Ipv4Addr::new(1, 1, 1, 1)));
     }
+
+    #[test]
+    fn test_parse_route_table() {
+        let table =
+            "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT
+eth0\t0000A8C0\t00000000\t0001\t0\t0\t100\t00FFFFFF\t0\t0\t0
+eth1\t00000A0A\tFE000A0A\t0003\t0\t0\t100\t00FFFFFF\t0\t0\t0
+eth0\t00000000\t0101A8C0\t0003\t0\t0\t100\t00000000\t0\t0\t0
+";
+        assert_eq!(
+            parse_route_table(table),
+            vec![Ipv4Addr::new(192, 168, 1, 1), Ipv4Addr::new(10, 10, 0, 254)]
+        );
+    }
+
+    #[test]
+    fn test_find_gateway() {
+        let start = Ipv4Addr::new(192, 168, 1, 1);
+        let end = Ipv4Addr::new(192, 168, 1, 254);
+
+        let routing = [Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(192, 168, 1, 254)];
+        assert_eq!(
+            find_gateway(start, end, &routing),
+            Some((Ipv4Addr::new(192, 168, 1, 254), GatewaySource::RoutingTable))
+        );
+        assert_eq!(
+            find_gateway(start, end, &[]),
+            Some((Ipv4Addr::new(192, 168, 1, 1), GatewaySource::FirstHost))
+        );
+        assert_eq!(
+            find_gateway(Ipv4Addr::new(192, 168, 1, 100), end, &[]),
+            None
+        );
+        assert_eq!(
+            GatewayInfo::new("192.168.1.0/24".to_string(), GatewaySource::FirstHost).target_name,
+            "Gateway – 192.168.1.0/24"
+        );
+    }
 }
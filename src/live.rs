@@ -0,0 +1,404 @@
+//! Broadcast of ping results as they are written.
+//!
+//! Ping tasks publish every result to a shared `LiveFeed`; each client of
+//! GET `/api/ping/live` holds a subscription, as do the InfluxDB export and
+//! the MQTT publisher while they are enabled. Results are only converted
+//! while at least one client is connected, and slow clients skip results
+//! instead of holding back the ping tasks.
+//!
+//! Clients with a `max_rate` get results coalesced per target by a
+//! `LiveCoalescer` and flushed as one summary frame per interval.
+//!
+//! Results that agents push to this server are published with their site;
+//! clients only receive them when they select that site (see `LiveFilter`).
+
+use crate::api::ping::dto::PingDataPoint;
+use crate::api::ping::query::parse_bucket_duration;
+use crate::ping::PingResult;
+use crate::storage::SiteSelector;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+/// Results buffered per client before it starts skipping
+pub const LIVE_CHANNEL_CAPACITY: usize = 1024;
+
+/// A published ping result
+#[derive(Debug)]
+pub struct LivePing {
+    pub target_id: String,
+    pub point: PingDataPoint,
+}
+
+/// Fan-out of live ping results to connected clients
+#[derive(Debug)]
+pub struct LiveFeed {
+    sender: broadcast::Sender<Arc<LivePing>>,
+}
+
+impl Default for LiveFeed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LiveFeed {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(LIVE_CHANNEL_CAPACITY);
+        Self { sender }
+    }
+
+    /// Publish a result of this server's own pings to all connected clients
+    pub fn publish(&self, result: &PingResult) {
+        self.send(result, None);
+    }
+
+    /// Publish a result the agent at `site` measured
+    pub fn publish_from_site(&self, result: &PingResult, site: &str) {
+        self.send(result, Some(site));
+    }
+
+    fn send(&self, result: &PingResult, site: Option<&str>) {
+        if self.sender.receiver_count() == 0 {
+            return;
+        }
+
+        // Only fails when the last client disconnected in the meantime
+        let _ = self.sender.send(Arc::new(LivePing::new(result, site)));
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<LivePing>> {
+        self.sender.subscribe()
+    }
+}
+
+impl LivePing {
+    fn new(result: &PingResult, site: Option<&str>) -> Self {
+        let metric_type = if result.success {
+            "ping_latency"
+        } else {
+            "ping_failed"
+        };
+        let point = PingDataPoint {
+            timestamp: result.timestamp.to_rfc3339(),
+            timestamp_unix: result.timestamp.timestamp(),
+            target: result.target.clone(),
+            target_name: result.target_name.clone(),
+            sequence: result.sequence,
+            probe_type: result.probe_type,
+            success: result.success,
+            latency_ms: if result.success {
+                result.latency_ms
+            } else {
+                None
+            },
+            metric_type: metric_type.to_string(),
+            site: site.map(str::to_string),
+        };
+        Self {
+            target_id: result.target_id.clone(),
+            point,
+        }
+    }
+}
+
+/// Shortest interval between summary frames (10 frames per second)
+pub const MIN_FRAME_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Parse a `max_rate` such as "1/s", "10/m", or "1/5s" into the interval
+/// between frames
+pub fn parse_max_rate(rate: &str) -> Result<Duration, String> {
+    let (count, period) = rate
+        .trim()
+        .split_once('/')
+        .ok_or_else(|| format!("Invalid max_rate '{}'. Expected format like '1/s'", rate))?;
+    let count: u32 = count
+        .trim()
+        .parse()
+        .ok()
+        .filter(|&c| c > 0)
+        .ok_or_else(|| format!("Invalid frame count in max_rate '{}'", rate))?;
+    let period = period.trim();
+    // "1/s" means one per second
+    let period_secs = if period.starts_with(|c: char| c.is_ascii_digit()) {
+        parse_bucket_duration(period)?
+    } else {
+        parse_bucket_duration(&format!("1{}", period))?
+    };
+    if period_secs <= 0 {
+        return Err(format!("Invalid period in max_rate '{}'", rate));
+    }
+
+    let interval = Duration::from_secs(period_secs as u64) / count;
+    if interval < MIN_FRAME_INTERVAL {
+        return Err(format!(
+            "max_rate '{}' exceeds the limit of 10 frames per second",
+            rate
+        ));
+    }
+    Ok(interval)
+}
+
+/// Target and site selection of a live stream client; no targets means all
+/// targets
+#[derive(Debug, Default)]
+pub struct LiveFilter {
+    targets: HashSet<String>,
+    site: SiteSelector,
+}
+
+impl LiveFilter {
+    /// Build a filter from a single `target` and a comma-separated `targets` list
+    pub fn new(target: Option<&str>, targets: Option<&str>) -> Self {
+        let targets = target
+            .into_iter()
+            .chain(targets.into_iter().flat_map(|t| t.split(',')))
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(str::to_string)
+            .collect();
+        Self {
+            targets,
+            site: SiteSelector::Local,
+        }
+    }
+
+    /// Select the results of `site` instead of this server's own
+    pub fn with_site(self, site: SiteSelector) -> Self {
+        Self { site, ..self }
+    }
+
+    /// Whether the result belongs to a selected target (address or id) and
+    /// site
+    pub fn matches(&self, live: &LivePing) -> bool {
+        self.site.includes(live.point.site.as_deref())
+            && (self.targets.is_empty()
+                || self.targets.contains(&live.target_id)
+                || self.targets.contains(&live.point.target))
+    }
+}
+
+/// Results of one target (of one site) since the previous summary frame
+#[derive(Debug, Serialize)]
+pub struct LiveTargetSummary {
+    pub target_id: String,
+    /// Site of the agent that measured the results; absent for this
+    /// server's own pings
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub site: Option<String>,
+    pub count: usize,
+    pub successful_count: usize,
+    pub failed_count: usize,
+    pub min_latency_ms: Option<f64>,
+    pub avg_latency_ms: Option<f64>,
+    pub max_latency_ms: Option<f64>,
+    /// Most recent result
+    pub last: PingDataPoint,
+}
+
+/// Periodic frame sent to rate-limited clients as a `summary` event
+#[derive(Debug, Serialize)]
+pub struct LiveSummary {
+    /// Unix timestamp in seconds of the oldest coalesced result
+    pub from_unix: i64,
+    /// Unix timestamp in seconds of the newest coalesced result
+    pub to_unix: i64,
+    /// Per-target summaries, ordered by target ID (and site)
+    pub targets: Vec<LiveTargetSummary>,
+}
+
+/// Collects results between summary frames
+#[derive(Debug, Default)]
+pub struct LiveCoalescer {
+    /// Summary and latency sum per target and site
+    targets: BTreeMap<(String, Option<String>), (LiveTargetSummary, f64)>,
+    from_unix: i64,
+    to_unix: i64,
+}
+
+impl LiveCoalescer {
+    pub fn add(&mut self, live: &LivePing) {
+        let point = &live.point;
+        if self.targets.is_empty() {
+            self.from_unix = point.timestamp_unix;
+            self.to_unix = point.timestamp_unix;
+        } else {
+            self.from_unix = self.from_unix.min(point.timestamp_unix);
+            self.to_unix = self.to_unix.max(point.timestamp_unix);
+        }
+        let (summary, latency_sum) = self
+            .targets
+            .entry((live.target_id.clone(), point.site.clone()))
+            .or_insert_with(|| {
+                (
+                    LiveTargetSummary {
+                        target_id: live.target_id.clone(),
+                        site: point.site.clone(),
+                        count: 0,
+                        successful_count: 0,
+                        failed_count: 0,
+                        min_latency_ms: None,
+                        avg_latency_ms: None,
+                        max_latency_ms: None,
+                        last: point.clone(),
+                    },
+                    0.0,
+                )
+            });
+
+        summary.count += 1;
+        match point.latency_ms {
+            Some(latency) if point.success => {
+                summary.successful_count += 1;
+                *latency_sum += latency;
+                summary.min_latency_ms =
+                    Some(summary.min_latency_ms.map_or(latency, |m| m.min(latency)));
+                summary.max_latency_ms =
+                    Some(summary.max_latency_ms.map_or(latency, |m| m.max(latency)));
+                summary.avg_latency_ms = Some(*latency_sum / summary.successful_count as f64);
+            }
+            _ => summary.failed_count += 1,
+        }
+        if point.timestamp_unix >= summary.last.timestamp_unix {
+            summary.last = point.clone();
+        }
+    }
+
+    /// Summary of everything added since the last flush, None if nothing was
+    pub fn flush(&mut self) -> Option<LiveSummary> {
+        if self.targets.is_empty() {
+            return None;
+        }
+        let targets = std::mem::take(&mut self.targets)
+            .into_values()
+            .map(|(summary, _)| summary)
+            .collect();
+        Some(LiveSummary {
+            from_unix: self.from_unix,
+            to_unix: self.to_unix,
+            targets,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProbeType;
+
+    fn result(target_id: &str, latency_ms: Option<f64>) -> PingResult {
+        PingResult {
+            timestamp: chrono::Utc::now(),
+            target_id: target_id.to_string(),
+            target: "192.168.1.1".to_string(),
+            target_name: None,
+            sequence: 1,
+            resolved_ip: None,
+            probe_type: ProbeType::Icmp,
+            port: None,
+            success: latency_ms.is_some(),
+            latency_ms,
+            correction_ms: None,
+            flow: None,
+        }
+    }
+
+    #[test]
+    fn test_publish_reaches_subscribers() {
+        let feed = LiveFeed::new();
+        // No subscribers: nothing is buffered
+        feed.publish(&result("router", Some(1.0)));
+
+        let mut rx = feed.subscribe();
+        feed.publish(&result("router", Some(2.5)));
+        feed.publish(&result("router", None));
+
+        let first = rx.try_recv().unwrap();
+        assert_eq!(first.target_id, "router");
+        assert_eq!(first.point.latency_ms, Some(2.5));
+        assert_eq!(first.point.metric_type, "ping_latency");
+
+        let second = rx.try_recv().unwrap();
+        assert!(!second.point.success);
+        assert_eq!(second.point.metric_type, "ping_failed");
+        assert!(rx.try_recv().is_err());
+    }
+
+    fn live(target_id: &str, timestamp_unix: i64, latency_ms: Option<f64>) -> LivePing {
+        let mut result = result(target_id, latency_ms);
+        result.timestamp = chrono::DateTime::from_timestamp(timestamp_unix, 0).unwrap();
+        LivePing::new(&result, None)
+    }
+
+    #[test]
+    fn test_parse_max_rate() {
+        assert_eq!(parse_max_rate("1/s"), Ok(Duration::from_secs(1)));
+        assert_eq!(parse_max_rate("10/m"), Ok(Duration::from_secs(6)));
+        assert_eq!(parse_max_rate("1/5s"), Ok(Duration::from_secs(5)));
+        assert_eq!(parse_max_rate("10/s"), Ok(Duration::from_millis(100)));
+        assert!(parse_max_rate("100/s").is_err());
+        assert!(parse_max_rate("0/s").is_err());
+        assert!(parse_max_rate("1").is_err());
+        assert!(parse_max_rate("1/x").is_err());
+    }
+
+    #[test]
+    fn test_filter_matches_id_or_address() {
+        let ping = live("router", 100, Some(1.0));
+        assert!(LiveFilter::new(None, None).matches(&ping));
+        assert!(LiveFilter::new(None, Some("wan, router")).matches(&ping));
+        assert!(LiveFilter::new(Some("192.168.1.1"), Some("wan")).matches(&ping));
+        assert!(!LiveFilter::new(None, Some("wan,nas")).matches(&ping));
+    }
+
+    #[test]
+    fn test_filter_selects_site() {
+        let local = live("router", 100, Some(1.0));
+        let mut result = result("router", Some(1.0));
+        result.timestamp = chrono::DateTime::from_timestamp(100, 0).unwrap();
+        let remote = LivePing::new(&result, Some("berlin"));
+
+        let filter = LiveFilter::new(Some("router"), None);
+        assert!(filter.matches(&local));
+        assert!(!filter.matches(&remote));
+
+        let filter = LiveFilter::new(Some("router"), None)
+            .with_site(SiteSelector::Site("berlin".to_string()));
+        assert!(!filter.matches(&local));
+        assert!(filter.matches(&remote));
+
+        let filter = LiveFilter::new(None, None).with_site(SiteSelector::All);
+        assert!(filter.matches(&local) && filter.matches(&remote));
+    }
+
+    #[test]
+    fn test_coalescer_summarizes_per_target() {
+        let mut coalescer = LiveCoalescer::default();
+        assert!(coalescer.flush().is_none());
+
+        coalescer.add(&live("router", 101, Some(2.0)));
+        coalescer.add(&live("router", 100, Some(4.0)));
+        coalescer.add(&live("router", 102, None));
+        coalescer.add(&live("nas", 103, Some(1.0)));
+
+        let summary = coalescer.flush().unwrap();
+        assert_eq!(summary.from_unix, 100);
+        assert_eq!(summary.to_unix, 103);
+        assert_eq!(summary.targets.len(), 2);
+
+        let router = &summary.targets[1];
+        assert_eq!(router.target_id, "router");
+        assert_eq!(router.count, 3);
+        assert_eq!(router.failed_count, 1);
+        assert_eq!(router.min_latency_ms, Some(2.0));
+        assert_eq!(router.max_latency_ms, Some(4.0));
+        assert_eq!(router.avg_latency_ms, Some(3.0));
+        assert_eq!(router.last.timestamp_unix, 102);
+        assert!(!router.last.success);
+
+        // Flushing starts a new frame
+        assert!(coalescer.flush().is_none());
+    }
+}
@@ -1,6 +1,13 @@
-use crate::config::LoggingConfig;
-use std::fs::OpenOptions;
-use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use crate::config::{LogFormat, LogRotation, LoggingConfig};
+use crate::crash_report::RecentLogWriter;
+use chrono::{DateTime, Local};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing_subscriber::{
+    fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer, Registry,
+};
 
 /// Custom time formatter for human-readable dates
 struct HumanReadableTimer;
@@ -12,30 +19,226 @@ impl tracing_subscriber::fmt::time::FormatTime for HumanReadableTimer {
     }
 }
 
+/// Log file that is rotated to `<file>.1` once it would grow past
+/// `max_size` bytes or its hour/day is over. Older files move up one number;
+/// the one past `max_files` is deleted.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    /// 0 = no size limit
+    max_size: u64,
+    rotation: LogRotation,
+    /// Hour or day the current file belongs to (None without time rotation)
+    period: Option<String>,
+    max_files: usize,
+}
+
+impl RotatingFile {
+    fn open(
+        path: &Path,
+        max_size: u64,
+        rotation: LogRotation,
+        max_files: usize,
+    ) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let metadata = file.metadata()?;
+        // An existing file belongs to the period it was last written in
+        let modified = metadata
+            .modified()
+            .map(DateTime::<Local>::from)
+            .unwrap_or_else(|_| Local::now());
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            size: metadata.len(),
+            max_size,
+            rotation,
+            period: period_of(rotation, modified),
+            max_files: max_files.max(1),
+        })
+    }
+
+    /// `<file>.<n>`
+    fn numbered(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let _ = fs::remove_file(self.numbered(self.max_files));
+        for n in (1..self.max_files).rev() {
+            let from = self.numbered(n);
+            if from.exists() {
+                fs::rename(&from, self.numbered(n + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.numbered(1))?;
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        Ok(())
+    }
+}
+
+/// Hour or day a time falls in, as a comparable key
+fn period_of(rotation: LogRotation, time: DateTime<Local>) -> Option<String> {
+    match rotation {
+        LogRotation::Never => None,
+        LogRotation::Hourly => Some(time.format("%Y-%m-%d %H").to_string()),
+        LogRotation::Daily => Some(time.format("%Y-%m-%d").to_string()),
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // The fmt layers write each event in one call, so events are never
+        // split across files
+        let period = period_of(self.rotation, Local::now());
+        let full = self.max_size > 0 && self.size + buf.len() as u64 > self.max_size;
+        if (full || period != self.period) && self.size > 0 {
+            // Keep logging into the current file if rotating fails
+            if let Err(e) = self.rotate() {
+                eprintln!("Failed to rotate log file {}: {}", self.path.display(), e);
+            }
+            self.size = 0;
+        }
+        self.period = period;
+
+        self.file.write_all(buf)?;
+        self.size += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Filter of one output: RUST_LOG when set, otherwise the configured level
+fn level_filter(level: &str) -> EnvFilter {
+    EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(level.to_lowercase()))
+}
+
 pub fn init_logging(log_config: &LoggingConfig) -> Result<(), Box<dyn std::error::Error>> {
-    // Parse log level from config, defaulting to "info" if invalid
-    let log_level = log_config.level.to_lowercase();
-    let env_filter =
-        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&log_level));
-
-    // Create file appender
-    let file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(&log_config.file)?;
-
-    // Build subscriber with both console and file outputs
-    tracing_subscriber::registry()
-        .with(env_filter)
-        .with(
+    let file = Mutex::new(RotatingFile::open(
+        Path::new(&log_config.file),
+        log_config.max_size_mb * 1024 * 1024,
+        log_config.rotation,
+        log_config.max_files,
+    )?);
+    let console_filter = level_filter(
+        log_config
+            .console_level
+            .as_deref()
+            .unwrap_or(&log_config.level),
+    );
+    let file_filter = level_filter(
+        log_config
+            .file_level
+            .as_deref()
+            .unwrap_or(&log_config.level),
+    );
+
+    // Console, file, and in-memory outputs, each with its own level
+    let mut layers: Vec<Box<dyn Layer<Registry> + Send + Sync>> = match log_config.format {
+        LogFormat::Text => vec![
             fmt::layer()
                 .with_writer(std::io::stderr)
                 .with_ansi(true)
                 .with_timer(HumanReadableTimer)
-                .compact(), // More compact, readable format for console
-        )
-        .with(fmt::layer().with_writer(file).with_ansi(false))
-        .init();
+                .compact() // More compact, readable format for console
+                .with_filter(console_filter)
+                .boxed(),
+            fmt::layer()
+                .with_writer(file)
+                .with_ansi(false)
+                .with_filter(file_filter)
+                .boxed(),
+        ],
+        LogFormat::Json => vec![
+            fmt::layer()
+                .json()
+                .with_writer(std::io::stderr)
+                .with_filter(console_filter)
+                .boxed(),
+            fmt::layer()
+                .json()
+                .with_writer(file)
+                .with_filter(file_filter)
+                .boxed(),
+        ],
+    };
+    // Last log lines for crash reports
+    layers.push(
+        fmt::layer()
+            .with_writer(RecentLogWriter)
+            .with_ansi(false)
+            .with_filter(level_filter(&log_config.level))
+            .boxed(),
+    );
+
+    tracing_subscriber::registry().with(layers).init();
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotates_by_size() {
+        let dir = std::env::temp_dir().join(format!("sparkping-log-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("sparkping.log");
+        let mut file = RotatingFile::open(&path, 10, LogRotation::Never, 2).unwrap();
+
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+        file.flush().unwrap();
+
+        // Each line overflows the 10 byte limit; only two rotated files are kept
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fourth\n");
+        assert_eq!(
+            fs::read_to_string(dir.join("sparkping.log.1")).unwrap(),
+            "third\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("sparkping.log.2")).unwrap(),
+            "second\n"
+        );
+        assert!(!dir.join("sparkping.log.3").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotates_when_period_changes() {
+        let dir = std::env::temp_dir().join(format!("sparkping-log-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("sparkping.log");
+        let mut file = RotatingFile::open(&path, 0, LogRotation::Daily, 5).unwrap();
+
+        file.write_all(b"today\n").unwrap();
+        file.write_all(b"still today\n").unwrap();
+        assert!(!dir.join("sparkping.log.1").exists());
+
+        // As if the file was written yesterday
+        file.period = Some("2000-01-01".to_string());
+        file.write_all(b"tomorrow\n").unwrap();
+        file.flush().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "tomorrow\n");
+        assert_eq!(
+            fs::read_to_string(dir.join("sparkping.log.1")).unwrap(),
+            "today\nstill today\n"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
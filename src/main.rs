@@ -1,28 +1,86 @@
+mod agent;
+mod alerts;
 mod api;
+mod arp_discovery;
+mod audit_log;
+mod auth;
+mod backoff;
+mod calibration;
+mod capabilities;
 mod config;
 mod config_file;
+mod config_validation;
 mod config_wizard;
+mod crash_report;
 mod device_identification;
+mod diagnose;
 mod discovery;
+mod discovery_session;
+mod downsample;
+mod encryption;
+mod ha_addon;
+mod health;
+mod home_assistant;
 mod icmp;
+mod influx_export;
+mod integrity;
 mod ip_scan;
+mod live;
 mod logging;
 mod memory;
+mod mqtt;
+mod netbios;
+mod notifications;
+mod outages;
 mod ping;
+mod port_history;
+mod preferences;
+mod presence;
+mod resolver;
+mod retention;
+mod reverse_dns;
+mod rollups;
+mod shutdown;
+mod startup_audit;
 mod storage;
 mod tasks;
+mod telemetry;
+mod traceroute;
 mod unified_discovery;
+mod update_check;
 mod vendor_discovery;
+mod ws_discovery;
 
+use crate::agent::start_agent_task;
+use crate::alerts::{start_alert_task, AlertEngine};
 use crate::api::create_router;
-use crate::config::AppConfig;
+use crate::config::{AppConfig, StorageBackendKind};
+use crate::config_validation::{validate_config, ConfigIssue};
+use crate::downsample::Downsampler;
+use crate::influx_export::start_influx_export_task;
+use crate::live::LiveFeed;
+use crate::mqtt::start_mqtt_task;
 use crate::logging::init_logging;
-use crate::tasks::start_ping_task;
+use crate::notifications::target_state::{start_target_state_task, TargetStateMonitor};
+use crate::preferences::PreferencesStore;
+use crate::presence::PresenceTracker;
+use crate::rollups::RollingAggregator;
+use crate::shutdown::shutdown;
+use crate::storage::sqlite::SqliteStorage;
+use crate::storage::{
+    write_latency_calibration, write_pause_transition, StorageBackend, TsinkStorage, WriteBuffer,
+};
+use crate::tasks::monitor::TaskMonitor;
+use crate::tasks::{
+    start_downsample_task, start_ping_task, start_presence_task, start_prune_task, start_seal_task,
+    start_storage_stats_task, start_supervisor_task, start_traceroute_task, start_write_flush_task,
+};
+use crate::update_check::{start_update_check_task, UpdateChecker};
 use clap::Parser;
 use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
 use std::collections::HashMap;
 use std::net::SocketAddr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::sync::RwLock;
@@ -43,6 +101,15 @@ struct Args {
     /// Initialize a new configuration file interactively
     #[arg(long)]
     init: bool,
+
+    /// If the config file is missing, write the built-in defaults to it and
+    /// start, without asking to run the wizard
+    #[arg(long)]
+    defaults: bool,
+
+    /// Print the hash of a password for `[[auth.users]] password_hash`
+    #[arg(long)]
+    hash_password: bool,
 }
 
 /// Log current RSS memory usage (Linux only, no-op elsewhere).
@@ -141,7 +208,7 @@ fn prepare_wal_for_safe_recovery(data_path: &str) -> std::io::Result<bool> {
 
     let total_wal_bytes: u64 = std::fs::read_dir(&wal_dir)?
         .filter_map(|e| e.ok())
-        .filter(|e| e.path().extension().map_or(false, |ext| ext == "wal"))
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "wal"))
         .filter_map(|e| e.metadata().ok())
         .map(|m| m.len())
         .sum();
@@ -259,7 +326,7 @@ fn recover_wal_streaming(
 
     let mut segments: Vec<_> = std::fs::read_dir(&recovery_dir)?
         .filter_map(|e| e.ok())
-        .filter(|e| e.path().extension().map_or(false, |ext| ext == "wal"))
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "wal"))
         .collect();
     segments.sort_by_key(|e| e.file_name());
 
@@ -345,7 +412,7 @@ fn recover_wal_streaming(
                 storage.insert_rows(&batch)?;
                 batch.clear();
 
-                if total_rows % 50_000 == 0 {
+                if total_rows.is_multiple_of(50_000) {
                     info!("WAL streaming recovery: {} rows processed", total_rows);
                 }
             }
@@ -377,7 +444,7 @@ fn recover_wal_streaming(
 }
 
 /// Reload config from file
-fn reload_config(path: &PathBuf) -> Result<AppConfig, String> {
+fn reload_config(path: &Path) -> Result<AppConfig, String> {
     let settings = ::config::Config::builder()
         .add_source(::config::File::with_name(
             path.to_str().expect("Invalid config file path"),
@@ -385,18 +452,24 @@ fn reload_config(path: &PathBuf) -> Result<AppConfig, String> {
         .build()
         .map_err(|e| format!("Failed to build config: {}", e))?;
 
-    let app_config: AppConfig = settings
+    let mut app_config: AppConfig = settings
         .try_deserialize()
         .map_err(|e| format!("Failed to deserialize config: {}", e))?;
+    ha_addon::apply_addon_options(&mut app_config)?;
     Ok(app_config)
 }
 
 /// Reload targets by comparing old and new configs
+#[allow(clippy::too_many_arguments)]
 async fn reload_targets(
     old_config: &AppConfig,
     new_config: &AppConfig,
-    storage: Arc<dyn tsink::Storage>,
+    storage: Arc<dyn StorageBackend>,
+    writer: Arc<WriteBuffer>,
+    rollups: Arc<RollingAggregator>,
+    live: Arc<LiveFeed>,
     task_handles: Arc<RwLock<HashMap<String, tokio::task::AbortHandle>>>,
+    task_monitor: Arc<TaskMonitor>,
 ) {
     info!("Reloading targets due to config change");
 
@@ -418,12 +491,15 @@ async fn reload_targets(
         .map(|t| (t.id.clone(), t))
         .collect();
 
-    // Check if socket_type changed - if so, restart all tasks
-    let socket_type_changed = old_config.ping.socket_type != new_config.ping.socket_type;
-    if socket_type_changed {
+    // Cached aggregated buckets were selected by the old targets
+    writer.aggregate_cache().clear();
+
+    // Check if ping settings changed - if so, restart all tasks
+    let ping_config_changed = old_config.ping != new_config.ping;
+    if ping_config_changed {
         info!(
-            "Socket type changed from {:?} to {:?}, restarting all ping tasks",
-            old_config.ping.socket_type, new_config.ping.socket_type
+            "Ping settings changed from {:?} to {:?}, restarting all ping tasks",
+            old_config.ping, new_config.ping
         );
     }
 
@@ -434,18 +510,45 @@ async fn reload_targets(
             if let Some(handle) = handles.remove(id) {
                 handle.abort();
             }
+            rollups.remove(id);
+            task_monitor.remove(id);
         }
     }
 
+    // Gap reports leave out the time targets were paused
+    let paused: Vec<(String, bool)> = new_targets
+        .iter()
+        .filter(|(id, t)| old_targets.get(*id).is_some_and(|old| old.paused != t.paused))
+        .map(|(id, t)| (id.clone(), t.paused))
+        .collect();
+    if !paused.is_empty() {
+        let storage = Arc::clone(&storage);
+        tokio::task::spawn_blocking(move || {
+            let now = chrono::Utc::now().timestamp();
+            for (id, paused) in paused {
+                if let Err(e) = write_pause_transition(&*storage, &id, paused, now) {
+                    warn!("Failed to record pause of target {}: {}", id, e);
+                }
+            }
+        });
+    }
+
     // Find modified or new targets
     for (id, new_target) in new_targets.iter() {
         let needs_restart = if let Some(old_target) = old_targets.get(id) {
-            // Check if any field changed or socket_type changed
-            socket_type_changed
+            // Check if any field changed or ping settings changed
+            ping_config_changed
                 || old_target.address != new_target.address
                 || old_target.name != new_target.name
                 || old_target.ping_count != new_target.ping_count
                 || old_target.ping_interval != new_target.ping_interval
+                || old_target.probe_type != new_target.probe_type
+                || old_target.port != new_target.port
+                || old_target.paused != new_target.paused
+                || old_target.timeout_ms != new_target.timeout_ms
+                || old_target.ttl != new_target.ttl
+                || old_target.payload_size != new_target.payload_size
+                || old_target.schedule != new_target.schedule
         } else {
             // New target
             true
@@ -455,14 +558,23 @@ async fn reload_targets(
             if let Some(old_handle) = handles.remove(id) {
                 info!("Restarting ping task for modified target: {}", id);
                 old_handle.abort();
-            } else {
+            } else if !new_target.paused {
                 info!("Starting ping task for new target: {}", id);
             }
 
+            if new_target.paused {
+                info!("Target {} is paused, not pinging", id);
+                continue;
+            }
+
             let handle = start_ping_task(
                 new_target,
                 Arc::clone(&storage),
-                new_config.ping.socket_type,
+                Arc::clone(&writer),
+                Arc::clone(&rollups),
+                Arc::clone(&live),
+                Arc::clone(&task_monitor),
+                &new_config.ping,
                 0,
             );
             handles.insert(id.clone(), handle);
@@ -472,7 +584,10 @@ async fn reload_targets(
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Install panic handler to ensure panics are visible
+    health::health().mark_started();
+
+    // Install panic handler to ensure panics are visible and leave a crash
+    // report once the data directory is known
     std::panic::set_hook(Box::new(|panic_info| {
         eprintln!("PANIC: {}", panic_info);
         if let Some(location) = panic_info.location() {
@@ -488,6 +603,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         } else if let Some(s) = panic_info.payload().downcast_ref::<String>() {
             eprintln!("Message: {}", s);
         }
+        if let Some(path) = crash_report::write_crash_report(panic_info) {
+            eprintln!("Crash report written to {}", path.display());
+        }
     }));
 
     let args = Args::parse();
@@ -500,6 +618,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         config_path.with_extension("toml")
     };
 
+    // Handle --hash-password flag: print a hash for the config and exit
+    if args.hash_password {
+        let hash = config_wizard::prompt_password_hash().map_err(|e| {
+            eprintln!("ERROR: {}", e);
+            e
+        })?;
+        println!("{}", hash);
+        std::process::exit(0);
+    }
+
     // Handle --init flag: run the configuration wizard
     if args.init {
         if config_file_path.exists() {
@@ -529,8 +657,24 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Check if config file exists
     if !config_file_path.exists() {
-        // If running in an interactive terminal, offer to create config
-        if config_wizard::is_interactive() {
+        if args.defaults || !config_wizard::is_interactive() {
+            // Zero-config start (e.g. a fresh container): write the defaults so
+            // that targets and settings added through the API are persisted
+            let config_content = config_wizard::default_config();
+            config_wizard::write_config_file(&config_file_path, &config_content).map_err(|e| {
+                eprintln!(
+                    "ERROR: Failed to write default config file '{}': {}",
+                    config_file_path.display(),
+                    e
+                );
+                e
+            })?;
+            eprintln!(
+                "Config file '{}' not found, wrote the built-in defaults to it. Add targets through the web UI or API.",
+                config_file_path.display()
+            );
+        } else {
+            // In an interactive terminal, offer to create config
             let should_create =
                 config_wizard::prompt_create_config(&config_file_path).map_err(|e| {
                     eprintln!("ERROR: Failed to prompt user: {}", e);
@@ -557,26 +701,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             } else {
                 eprintln!();
                 eprintln!(
-                    "No configuration file. Run with --init to create one, or provide a config file with -c."
+                    "No configuration file. Run with --init to create one, --defaults to write and start with the defaults, or provide a config file with -c."
                 );
                 std::process::exit(1);
             }
-        } else {
-            // Non-interactive mode: just error out
-            eprintln!(
-                "ERROR: Config file '{}' not found.",
-                config_file_path.display()
-            );
-            eprintln!();
-            eprintln!("To create a configuration file interactively, run:");
-            eprintln!(
-                "  {} --init",
-                std::env::args()
-                    .next()
-                    .unwrap_or_else(|| "sparkping".to_string())
-            );
-            eprintln!();
-            std::process::exit(1);
         }
     }
 
@@ -623,28 +751,57 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             .get_mut("targets")
             .and_then(|item| item.as_array_of_tables_mut())
         {
-            let mut idx = 0;
-            for target_table in targets_array.iter_mut() {
-                if !target_table.contains_key("id") {
-                    if idx < app_config.targets.len() {
-                        let id = app_config.targets[idx].id.clone();
-                        target_table["id"] = toml_edit::Item::Value(toml_edit::Value::String(
-                            toml_edit::Formatted::new(id),
-                        ));
-                    }
+            for (idx, target_table) in targets_array.iter_mut().enumerate() {
+                if !target_table.contains_key("id") && idx < app_config.targets.len() {
+                    let id = app_config.targets[idx].id.clone();
+                    target_table["id"] = toml_edit::Item::Value(toml_edit::Value::String(
+                        toml_edit::Formatted::new(id),
+                    ));
                 }
-                idx += 1;
             }
         }
         let write_flag = Arc::new(AtomicBool::new(false));
         config_file::write_config_file(&config_path, &doc, &write_flag)?;
     }
 
+    // Options set in the Home Assistant add-on UI take precedence
+    let addon_options = ha_addon::apply_addon_options(&mut app_config).map_err(|e| {
+        eprintln!("ERROR: Failed to apply add-on options: {}", e);
+        e
+    })?;
+
     // Initialize logging before any other output
     init_logging(&app_config.logging).map_err(|e| {
         eprintln!("ERROR: Failed to initialize logging: {}", e);
         e
     })?;
+    if addon_options {
+        info!(
+            "Applied Home Assistant add-on options from {}",
+            ha_addon::ADDON_OPTIONS_PATH
+        );
+    }
+
+    // Decrypt the sealed data directory before anything reads it
+    let storage_key = if app_config.database.encryption.enabled {
+        let key = encryption::open_data_directory(&app_config.database).map_err(|e| {
+            eprintln!("ERROR: {}", e);
+            e
+        })?;
+        Some(Arc::new(key))
+    } else {
+        None
+    };
+    let database_config = app_config.database.clone();
+
+    // Write crash reports into the data directory from now on
+    crash_report::init(Path::new(app_config.database.data_path()));
+    if let Some(crash) = crash_report::last_crash() {
+        warn!(
+            "Previous crash at {}: {} (see GET /api/system/diagnostics)",
+            crash.timestamp, crash.message
+        );
+    }
 
     // Start background memory monitor (logs peak RSS every 60s)
     memory::start_memory_monitor();
@@ -715,6 +872,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         app_config.logging.level, app_config.logging.file
     );
     info!("Database path: {}", app_config.database.path);
+    if app_config.database.encryption.enabled {
+        info!(
+            "Database encryption enabled, working directory: {}",
+            app_config.database.encryption.work_path
+        );
+    }
     info!("Targets to ping:");
     for target in &app_config.targets {
         match &target.name {
@@ -723,56 +886,104 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
+    // Clean up leftovers of crashed runs before tsink opens the data directory
+    let startup_audit = Arc::new(startup_audit::run_startup_audit(
+        &config_file_path,
+        Path::new(app_config.database.data_path()),
+    ));
+
     // Diagnostic: log data directory contents and memory before storage init
-    log_data_directory(&app_config.database.path);
+    log_data_directory(app_config.database.data_path());
     log_memory_usage("before WAL preparation");
 
-    // Check if WAL needs streaming recovery before StorageBuilder loads it all at once
-    let needs_chunked_recovery =
-        prepare_wal_for_safe_recovery(&app_config.database.path).map_err(|e| {
-            eprintln!("ERROR: Failed to prepare WAL for recovery: {}", e);
-            e
-        })?;
+    let storage: Arc<dyn StorageBackend> = match app_config.database.backend {
+        StorageBackendKind::Tsink => {
+            // Check if WAL needs streaming recovery before StorageBuilder loads it all at once
+            let needs_chunked_recovery =
+                prepare_wal_for_safe_recovery(app_config.database.data_path()).map_err(|e| {
+                    eprintln!("ERROR: Failed to prepare WAL for recovery: {}", e);
+                    e
+                })?;
 
-    log_memory_usage("after WAL preparation");
-
-    // Initialize tsink storage with configured path
-    // Timestamp precision must be Seconds to match what storage.rs writes,
-    // otherwise partition_duration math is wrong and partitions never rotate.
-    info!("Initializing tsink storage (this loads existing partitions + remaining WAL)...");
-    let storage: Arc<dyn tsink::Storage> = StorageBuilder::new()
-        .with_data_path(&app_config.database.path)
-        .with_wal_enabled(true)
-        .with_retention(Duration::from_secs(365 * 24 * 3600 * 20)) // 20 years
-        .with_timestamp_precision(TimestampPrecision::Seconds)
-        .with_max_writers(16)
-        .with_write_timeout(Duration::from_secs(60))
-        .with_partition_duration(Duration::from_secs(6 * 3600)) // 6 hours
-        .with_wal_buffer_size(16384) // 16KB
-        .build()
-        .map_err(|e| {
-            eprintln!(
-                "ERROR: Failed to initialize storage at '{}': {}",
-                app_config.database.path, e
+            log_memory_usage("after WAL preparation");
+
+            // Initialize tsink storage with configured path
+            // Timestamp precision must be Seconds to match what storage.rs writes,
+            // otherwise partition_duration math is wrong and partitions never rotate.
+            info!(
+                "Initializing tsink storage (this loads existing partitions + remaining WAL)..."
             );
-            e
-        })?;
+            let tsink_storage: Arc<dyn tsink::Storage> = StorageBuilder::new()
+                .with_data_path(app_config.database.data_path())
+                .with_wal_enabled(true)
+                .with_retention(Duration::from_secs(365 * 24 * 3600 * 20)) // 20 years
+                .with_timestamp_precision(TimestampPrecision::Seconds)
+                .with_max_writers(16)
+                .with_write_timeout(Duration::from_secs(60))
+                .with_partition_duration(Duration::from_secs(6 * 3600)) // 6 hours
+                .with_wal_buffer_size(16384) // 16KB
+                .build()
+                .map_err(|e| {
+                    eprintln!(
+                        "ERROR: Failed to initialize storage at '{}': {}",
+                        app_config.database.data_path(),
+                        e
+                    );
+                    e
+                })?;
 
-    info!(
-        "tsink database initialized at: {}",
-        app_config.database.path
-    );
-    log_memory_usage("after StorageBuilder::build()");
+            info!(
+                "tsink database initialized at: {}",
+                app_config.database.data_path()
+            );
+            log_memory_usage("after StorageBuilder::build()");
 
-    // Stream-recover WAL rows in batches if we moved the WAL aside pre-build
-    if needs_chunked_recovery {
-        recover_wal_streaming(storage.as_ref(), &app_config.database.path).map_err(|e| {
-            eprintln!("ERROR: WAL streaming recovery failed: {}", e);
-            e
-        })?;
-    }
+            // Stream-recover WAL rows in batches if we moved the WAL aside pre-build
+            if needs_chunked_recovery {
+                recover_wal_streaming(tsink_storage.as_ref(), app_config.database.data_path())
+                    .map_err(|e| {
+                        eprintln!("ERROR: WAL streaming recovery failed: {}", e);
+                        e
+                    })?;
+            }
+
+            log_memory_usage("after WAL recovery");
 
-    log_memory_usage("after WAL recovery");
+            Arc::new(TsinkStorage::new(tsink_storage, app_config.database.data_path()))
+        }
+        StorageBackendKind::Sqlite => {
+            let sqlite = SqliteStorage::open(Path::new(app_config.database.data_path()))
+                .map_err(|e| {
+                    eprintln!(
+                        "ERROR: Failed to open SQLite storage at '{}': {}",
+                        app_config.database.data_path(),
+                        e
+                    );
+                    e
+                })?;
+            info!(
+                "SQLite database initialized in: {}",
+                app_config.database.data_path()
+            );
+            Arc::new(sqlite)
+        }
+    };
+
+    // Use a socket type that works if the configured one does not
+    app_config.ping.socket_type = capabilities::detect(app_config.ping.socket_type)
+        .await
+        .effective;
+
+    // Measure the DGRAM socket overhead before any ping task starts
+    if app_config.ping.calibrate {
+        let socket_type = app_config.ping.socket_type;
+        let calibration = calibration::run_calibration(socket_type).await;
+        if let Some(calibration) = calibration {
+            if let Err(e) = write_latency_calibration(storage.as_ref(), calibration) {
+                error!("Error writing latency calibration to tsink: {}", e);
+            }
+        }
+    }
     info!("Starting ping loop (each target runs independently in parallel)...");
     for target in &app_config.targets {
         info!(
@@ -787,24 +998,129 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Create shared state for config and task management
     let server_host = app_config.server.host.clone();
     let server_port = app_config.server.port;
+    let downsampler = Arc::new(Downsampler::load(Path::new(
+        app_config.database.data_path(),
+    )));
+    let preferences = Arc::new(PreferencesStore::load(Path::new(
+        app_config.database.data_path(),
+    )));
+    let writer = Arc::new(WriteBuffer::new(Duration::from_millis(
+        app_config.database.write_flush_ms,
+    )));
     let config_state = Arc::new(RwLock::new(app_config));
     let task_handles = Arc::new(RwLock::new(
         HashMap::<String, tokio::task::AbortHandle>::new(),
     ));
+    crash_report::register_tasks(Arc::clone(&task_handles));
+    let task_monitor = Arc::new(TaskMonitor::new());
     let write_flag = Arc::new(AtomicBool::new(false));
+    let rollups = Arc::new(RollingAggregator::new());
+    let live = Arc::new(LiveFeed::new());
+    let alerts = Arc::new(AlertEngine::new());
+    let updates = Arc::new(UpdateChecker::new());
+    let presence = Arc::new(PresenceTracker::new());
 
     // Start initial ping tasks
     {
         let config = config_state.read().unwrap();
         let mut handles = task_handles.write().unwrap();
-        let socket_type = config.ping.socket_type;
-        for (i, target) in config.targets.iter().enumerate() {
+        for (i, target) in config.targets.iter().filter(|t| !t.paused).enumerate() {
             let stagger_ms = (i as u64) * 200; // 200ms between each target start
-            let handle = start_ping_task(target, Arc::clone(&storage), socket_type, stagger_ms);
+            let handle = start_ping_task(
+                target,
+                Arc::clone(&storage),
+                Arc::clone(&writer),
+                Arc::clone(&rollups),
+                Arc::clone(&live),
+                Arc::clone(&task_monitor),
+                &config.ping,
+                stagger_ms,
+            );
             handles.insert(target.id.clone(), handle);
         }
     }
 
+    // Restart ping tasks that crashed or stalled
+    start_supervisor_task(
+        Arc::clone(&config_state),
+        Arc::clone(&task_handles),
+        Arc::clone(&task_monitor),
+        Arc::clone(&storage),
+        Arc::clone(&writer),
+        Arc::clone(&rollups),
+        Arc::clone(&live),
+    );
+
+    // Write results of all targets together every flush interval
+    start_write_flush_task(Arc::clone(&writer), Arc::clone(&storage));
+
+    // Record storage size snapshots for growth charts
+    start_storage_stats_task(Arc::clone(&storage), Arc::clone(&config_state));
+
+    // Expire old ping data per target retention
+    start_prune_task(
+        Arc::clone(&storage),
+        Arc::clone(&writer),
+        Arc::clone(&config_state),
+    );
+
+    // Roll raw ping data up into 1m/1h series for long-range queries
+    start_downsample_task(
+        Arc::clone(&downsampler),
+        Arc::clone(&storage),
+        Arc::clone(&config_state),
+    );
+
+    // Track devices appearing and disappearing from the ARP/NDP neighbor table
+    start_presence_task(
+        Arc::clone(&presence),
+        Arc::clone(&storage),
+        Arc::clone(&config_state),
+    );
+
+    // Trace the path to selected targets and store the hops
+    start_traceroute_task(Arc::clone(&storage), Arc::clone(&config_state));
+
+    // Periodically re-encrypt the working directory into the data directory
+    if let Some(key) = &storage_key {
+        start_seal_task(database_config.clone(), Arc::clone(key), Arc::clone(&storage));
+    }
+
+    // Notify channels when targets go down or come back up
+    start_target_state_task(
+        Arc::new(TargetStateMonitor::new()),
+        Arc::clone(&rollups),
+        Arc::clone(&config_state),
+    );
+
+    // Evaluate alert rules in the background (rules are re-read every tick)
+    start_alert_task(
+        Arc::clone(&alerts),
+        Arc::clone(&config_state),
+        Arc::clone(&rollups),
+        Arc::clone(&storage),
+    );
+
+    // Check for new releases if `[updates] check` is enabled
+    start_update_check_task(Arc::clone(&updates), Arc::clone(&config_state));
+
+    // Mirror ping results to InfluxDB if `[influx_export] enabled`
+    start_influx_export_task(Arc::clone(&live), Arc::clone(&config_state));
+
+    // Push ping results to a central SparkPing if `[agent] server` is set
+    start_agent_task(Arc::clone(&live), Arc::clone(&config_state));
+
+    // Publish results, target states and presence events if `[mqtt] enabled`
+    start_mqtt_task(
+        Arc::clone(&live),
+        Arc::clone(&presence),
+        Arc::clone(&rollups),
+        Arc::clone(&config_state),
+    );
+
+    // Storage is open and the ping tasks are running
+    health::health().set_ready(true);
+
     // Determine static files directory (from env var or default)
     let static_dir = std::env::var("STATIC_DIR")
         .ok()
@@ -821,6 +1137,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     if let Some(ref dir) = static_dir {
         info!("Serving static files from: {:?}", dir);
+    } else if cfg!(feature = "embed-frontend") {
+        info!("Serving the frontend embedded in the binary");
     } else {
         info!("Static file serving disabled (no static directory found)");
     }
@@ -828,10 +1146,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Create HTTP API router with shared state
     let app = create_router(
         Arc::clone(&storage),
+        Arc::clone(&writer),
+        Arc::clone(&rollups),
+        Arc::clone(&live),
+        Arc::clone(&alerts),
+        updates,
         Arc::clone(&config_state),
         Arc::clone(&task_handles),
+        Arc::clone(&task_monitor),
         Arc::clone(&write_flag),
         config_path.clone(),
+        startup_audit,
+        downsampler,
+        presence,
+        preferences,
         static_dir,
     );
     let addr: SocketAddr = format!("{}:{}", server_host, server_port)
@@ -847,8 +1175,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     info!("Starting HTTP API server on http://{}", addr);
 
-    // Spawn HTTP server task
-    let server_task = tokio::spawn(async move {
+    // Spawn HTTP server task; it stops accepting connections once shutdown
+    // is requested and ends when in-flight requests are done
+    let mut server_task = tokio::spawn(async move {
         let listener = tokio::net::TcpListener::bind(addr)
             .await
             .unwrap_or_else(|e| {
@@ -862,6 +1191,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             listener,
             app.into_make_service_with_connect_info::<SocketAddr>(),
         )
+        .with_graceful_shutdown(shutdown().requested())
         .await
         .unwrap_or_else(|e| {
             let msg = format!("HTTP server error: {}", e);
@@ -881,7 +1211,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let config_path_for_watcher = config_file_path.clone();
     let config_state_for_watcher = Arc::clone(&config_state);
     let storage_for_watcher = Arc::clone(&storage);
+    let writer_for_watcher = Arc::clone(&writer);
+    let rollups_for_watcher = Arc::clone(&rollups);
+    let live_for_watcher = Arc::clone(&live);
     let task_handles_for_watcher = Arc::clone(&task_handles);
+    let task_monitor_for_watcher = Arc::clone(&task_monitor);
     let write_flag_for_watcher = Arc::clone(&write_flag);
 
     let watcher_task = tokio::spawn(async move {
@@ -928,14 +1262,43 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
                         // Reload config
                         info!("Config file changed, reloading...");
+
+                        // Keep the running config if the new one is invalid
+                        let validation = std::fs::read_to_string(&config_path_for_watcher)
+                            .map_err(|e| {
+                                vec![ConfigIssue::load_error(format!(
+                                    "Failed to read config file: {}",
+                                    e
+                                ))]
+                            })
+                            .and_then(|text| validate_config(&text).map(|_| ()));
+                        if let Err(issues) = validation {
+                            for issue in &issues {
+                                error!(
+                                    "Invalid config, not reloading: {} {}",
+                                    issue.path, issue.message
+                                );
+                            }
+                            health::health()
+                                .record_config_reload(chrono::Utc::now().timestamp(), Err(issues));
+                            continue;
+                        }
+
                         match reload_config(&config_path_for_watcher) {
-                            Ok(new_config) => {
+                            Ok(mut new_config) => {
                                 let old_config = {
                                     let config = config_state_for_watcher
                                         .read()
                                         .map_err(|e| format!("Failed to read config: {}", e))?;
                                     config.clone()
                                 };
+                                // Encryption is set up at startup; keep it until restart
+                                new_config.database.encryption =
+                                    old_config.database.encryption.clone();
+                                // Keep falling back to a socket type that works
+                                new_config.ping.socket_type = capabilities::effective_socket_type(
+                                    new_config.ping.socket_type,
+                                );
 
                                 // Update config state
                                 {
@@ -950,12 +1313,22 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                     &old_config,
                                     &new_config,
                                     Arc::clone(&storage_for_watcher),
+                                    Arc::clone(&writer_for_watcher),
+                                    Arc::clone(&rollups_for_watcher),
+                                    Arc::clone(&live_for_watcher),
                                     Arc::clone(&task_handles_for_watcher),
+                                    Arc::clone(&task_monitor_for_watcher),
                                 )
                                 .await;
+                                health::health()
+                                    .record_config_reload(chrono::Utc::now().timestamp(), Ok(()));
                             }
                             Err(e) => {
                                 error!("Failed to reload config: {}", e);
+                                health::health().record_config_reload(
+                                    chrono::Utc::now().timestamp(),
+                                    Err(vec![ConfigIssue::load_error(e)]),
+                                );
                             }
                         }
                     }
@@ -970,7 +1343,6 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     });
 
     // Setup signal handler for graceful shutdown
-    let storage_for_shutdown = storage.clone();
     let shutdown_task = tokio::spawn(async move {
         let ctrl_c = async {
             signal::ctrl_c()
@@ -993,19 +1365,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             _ = ctrl_c => {},
             _ = terminate => {},
         }
-
-        info!("Shutdown signal received, closing storage...");
-        if let Err(e) = storage_for_shutdown.close() {
-            error!("Error closing storage: {}", e);
-        } else {
-            info!("Storage closed successfully");
-        }
     });
 
     // Run HTTP server, file watcher, and shutdown handler concurrently
-    tokio::select! {
-        result = server_task => {
+    let server_running = tokio::select! {
+        result = &mut server_task => {
             error!("HTTP server task ended: {:?}", result);
+            false
         }
         result = watcher_task => {
             match result {
@@ -1013,13 +1379,63 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 Ok(Err(e)) => error!("File watcher error: {}", e),
                 Err(e) => error!("File watcher task panicked: {:?}", e),
             }
+            true
         }
         _ = shutdown_task => {
-            info!("Shutdown handler completed");
+            info!("Shutdown signal received");
+            true
+        }
+    };
+
+    // Stop accepting requests and let ping tasks finish their current batch,
+    // so their results are written before storage is closed
+    health::health().set_ready(false);
+    shutdown().request();
+    let shutdown_timeout = config_state
+        .read()
+        .map(|c| c.server.shutdown_timeout)
+        .unwrap_or(10);
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(shutdown_timeout);
+    info!(
+        "Shutting down: waiting up to {}s for {} ping tasks and in-flight requests",
+        shutdown_timeout,
+        shutdown().active()
+    );
+    if tokio::time::timeout_at(deadline, shutdown().idle())
+        .await
+        .is_err()
+    {
+        warn!(
+            "{} ping tasks still running after {}s, aborting them",
+            shutdown().active(),
+            shutdown_timeout
+        );
+    }
+    if let Ok(mut handles) = task_handles.write() {
+        handles.drain().for_each(|(_, handle)| handle.abort());
+    }
+    if server_running
+        && tokio::time::timeout_at(deadline, &mut server_task)
+            .await
+            .is_err()
+    {
+        // Long-lived streams (live feed, discovery) keep connections open
+        warn!(
+            "Requests still in flight after {}s, closing them",
+            shutdown_timeout
+        );
+        server_task.abort();
+    }
+
+    // Write results still queued for the next flush
+    if writer.pending() > 0 {
+        info!("Writing {} buffered rows...", writer.pending());
+        if let Err(e) = writer.flush(storage.as_ref()) {
+            error!("Error writing buffered ping results to tsink: {}", e);
         }
     }
 
-    // Ensure storage is closed before exit
+    // Close storage, which flushes buffered data points to disk
     info!("Closing storage before exit...");
     if let Err(e) = storage.close() {
         error!("Error closing storage: {}", e);
@@ -1027,5 +1443,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         info!("Storage closed successfully");
     }
 
+    // Seal the closed storage and remove the plaintext working copy
+    if let Some(key) = storage_key {
+        match encryption::close_data_directory(&database_config, &key) {
+            Ok(()) => info!("Data directory sealed"),
+            Err(e) => error!("Error sealing data directory: {}", e),
+        }
+    }
+
     Ok(())
 }
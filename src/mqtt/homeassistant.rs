@@ -0,0 +1,165 @@
+//! Home Assistant MQTT discovery configs.
+//!
+//! Every target becomes a Home Assistant device with a latency sensor (read
+//! from its ping topic) and a connectivity binary sensor (read from its status
+//! topic). Configs are published retained below the discovery prefix; the
+//! entities are unavailable while SparkPing is disconnected from the broker.
+
+use super::{topic_id, Topics, TARGET_DOWN, TARGET_UP};
+use crate::config::Target;
+use serde_json::json;
+use std::collections::HashMap;
+
+/// Retained discovery messages (topic, payload) for the given targets
+pub(crate) fn discovery_configs(
+    discovery_prefix: &str,
+    topics: &Topics,
+    targets: &[Target],
+) -> Vec<(String, String)> {
+    let discovery_prefix = discovery_prefix.trim_matches('/');
+    let mut configs = Vec::with_capacity(targets.len() * 2);
+
+    for target in targets {
+        let node_id = format!("sparkping_{}", topic_id(&target.id));
+        let device = json!({
+            "identifiers": [node_id],
+            "name": target.name.as_deref().unwrap_or(&target.address),
+            "manufacturer": "SparkPing",
+            "model": format!("{} target", target.probe_type.as_str()),
+            "sw_version": env!("CARGO_PKG_VERSION"),
+        });
+
+        let latency = json!({
+            "name": "Latency",
+            "unique_id": format!("{}_latency", node_id),
+            "state_topic": topics.ping(&target.id),
+            "value_template": "{{ value_json.latency_ms }}",
+            "unit_of_measurement": "ms",
+            "device_class": "duration",
+            "state_class": "measurement",
+            "availability_topic": topics.availability(),
+            "device": device,
+        });
+        configs.push((
+            format!("{}/sensor/{}/latency/config", discovery_prefix, node_id),
+            latency.to_string(),
+        ));
+
+        let connectivity = json!({
+            "name": "Connectivity",
+            "unique_id": format!("{}_status", node_id),
+            "state_topic": topics.status(&target.id),
+            "payload_on": TARGET_UP,
+            "payload_off": TARGET_DOWN,
+            "device_class": "connectivity",
+            "availability_topic": topics.availability(),
+            "device": device,
+        });
+        configs.push((
+            format!(
+                "{}/binary_sensor/{}/status/config",
+                discovery_prefix, node_id
+            ),
+            connectivity.to_string(),
+        ));
+    }
+
+    configs
+}
+
+/// Messages that bring the broker from the `published` configs to the
+/// `desired` ones: new and changed configs, and an empty payload (which
+/// removes the entity) for configs of removed targets
+pub(crate) fn discovery_updates(
+    published: &HashMap<String, String>,
+    desired: &[(String, String)],
+) -> Vec<(String, String)> {
+    let mut updates: Vec<(String, String)> = desired
+        .iter()
+        .filter(|(topic, payload)| published.get(topic) != Some(payload))
+        .cloned()
+        .collect();
+
+    let mut removed: Vec<&String> = published
+        .keys()
+        .filter(|topic| !desired.iter().any(|(t, _)| t == *topic))
+        .collect();
+    removed.sort();
+    updates.extend(
+        removed
+            .into_iter()
+            .map(|topic| (topic.clone(), String::new())),
+    );
+    updates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(id: &str, name: Option<&str>) -> Target {
+        Target {
+            id: id.to_string(),
+            address: "192.168.1.1".to_string(),
+            name: name.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_discovery_configs() {
+        let topics = Topics::new("sparkping/");
+        let configs = discovery_configs(
+            "homeassistant",
+            &topics,
+            &[target("main.router", Some("Main Router"))],
+        );
+        assert_eq!(configs.len(), 2);
+
+        let (topic, payload) = &configs[0];
+        assert_eq!(
+            topic,
+            "homeassistant/sensor/sparkping_main_router/latency/config"
+        );
+        let payload: serde_json::Value = serde_json::from_str(payload).unwrap();
+        assert_eq!(payload["unique_id"], "sparkping_main_router_latency");
+        assert_eq!(payload["state_topic"], "sparkping/main_router/ping");
+        assert_eq!(payload["availability_topic"], "sparkping/status");
+        assert_eq!(payload["device"]["name"], "Main Router");
+        assert_eq!(payload["device"]["model"], "icmp target");
+
+        let (topic, payload) = &configs[1];
+        assert_eq!(
+            topic,
+            "homeassistant/binary_sensor/sparkping_main_router/status/config"
+        );
+        let payload: serde_json::Value = serde_json::from_str(payload).unwrap();
+        assert_eq!(payload["state_topic"], "sparkping/main_router/status");
+        assert_eq!(payload["payload_on"], "online");
+        assert_eq!(payload["device_class"], "connectivity");
+    }
+
+    #[test]
+    fn test_discovery_updates() {
+        let published = HashMap::from([
+            ("a/config".to_string(), "1".to_string()),
+            ("b/config".to_string(), "1".to_string()),
+            ("c/config".to_string(), "1".to_string()),
+        ]);
+        let desired = vec![
+            ("a/config".to_string(), "1".to_string()),
+            ("b/config".to_string(), "2".to_string()),
+            ("d/config".to_string(), "1".to_string()),
+        ];
+
+        assert_eq!(
+            discovery_updates(&published, &desired),
+            vec![
+                ("b/config".to_string(), "2".to_string()),
+                ("d/config".to_string(), "1".to_string()),
+                ("c/config".to_string(), String::new()),
+            ]
+        );
+        assert!(discovery_updates(&HashMap::new(), &[]).is_empty());
+    }
+}
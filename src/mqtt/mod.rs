@@ -0,0 +1,388 @@
+//! Publishing to an MQTT broker (`[mqtt]`).
+//!
+//! Topics below `topic_prefix` (target IDs and MACs are reduced to letters,
+//! digits, `_` and `-`):
+//! - `<prefix>/status`: "online" while connected, "offline" as last will (retained)
+//! - `<prefix>/<target>/ping`: every ping result as JSON, from the `LiveFeed`
+//! - `<prefix>/<target>/status`: "online"/"offline" once the target is up or
+//!   down (retained), classified like the target state notifications
+//! - `<prefix>/presence/<mac>`: the last presence transition of a device as
+//!   JSON (retained)
+//!
+//! With `ha_discovery`, Home Assistant discovery configs are published on
+//! every (re)connect and when targets change; see `homeassistant`.
+
+pub mod homeassistant;
+
+use crate::config::{AppConfig, MqttConfig, Target};
+use crate::live::{LiveFeed, LivePing};
+use crate::notifications::target_state::classify;
+use crate::presence::{PresenceEvent, PresenceTracker};
+use crate::rollups::RollingAggregator;
+use crate::tasks::wait_until_enabled;
+use rumqttc::{AsyncClient, Event, LastWill, MqttOptions, Packet, QoS};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+/// How often settings are re-read and target states checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// Delay before reconnecting after a connection error
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+/// Messages queued for the connection before publishes are skipped
+const REQUEST_CAPACITY: usize = 1000;
+
+/// Payload of the availability topic while connected
+const ONLINE: &str = "online";
+/// Last will on the availability topic
+const OFFLINE: &str = "offline";
+/// Payloads of a target's status topic
+pub(crate) const TARGET_UP: &str = "online";
+pub(crate) const TARGET_DOWN: &str = "offline";
+
+/// Reduce an ID to characters valid in topics and Home Assistant object IDs
+pub(crate) fn topic_id(id: &str) -> String {
+    id.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Topics below the configured prefix
+#[derive(Debug, Clone)]
+pub(crate) struct Topics {
+    prefix: String,
+}
+
+impl Topics {
+    pub(crate) fn new(prefix: &str) -> Self {
+        Self {
+            prefix: prefix.trim_matches('/').to_string(),
+        }
+    }
+
+    pub(crate) fn availability(&self) -> String {
+        format!("{}/status", self.prefix)
+    }
+
+    pub(crate) fn ping(&self, target_id: &str) -> String {
+        format!("{}/{}/ping", self.prefix, topic_id(target_id))
+    }
+
+    pub(crate) fn status(&self, target_id: &str) -> String {
+        format!("{}/{}/status", self.prefix, topic_id(target_id))
+    }
+
+    fn presence(&self, mac: &str) -> String {
+        format!(
+            "{}/presence/{}",
+            self.prefix,
+            topic_id(&mac.replace(':', ""))
+        )
+    }
+}
+
+/// Client side of one broker connection and what was published on it
+struct Publisher {
+    client: AsyncClient,
+    topics: Topics,
+    connected: bool,
+    /// Last published up/down state per target
+    states: HashMap<String, bool>,
+    /// Published discovery configs by topic
+    discovery: HashMap<String, String>,
+    /// Messages skipped since the last warning
+    dropped: u64,
+}
+
+impl Publisher {
+    fn new(client: AsyncClient, topics: Topics) -> Self {
+        Self {
+            client,
+            topics,
+            connected: false,
+            states: HashMap::new(),
+            discovery: HashMap::new(),
+            dropped: 0,
+        }
+    }
+
+    /// Queue a message without waiting; skipped if the queue is full
+    fn publish(&mut self, topic: String, qos: QoS, retain: bool, payload: impl Into<Vec<u8>>) {
+        if let Err(e) = self.client.try_publish(topic, qos, retain, payload) {
+            debug!("MQTT publish skipped: {}", e);
+            self.dropped += 1;
+        }
+    }
+
+    /// Announce availability and publish all retained state again, in case
+    /// the broker lost it
+    fn on_connect(
+        &mut self,
+        settings: &MqttConfig,
+        targets: &[Target],
+        rollups: &RollingAggregator,
+    ) {
+        self.connected = true;
+        self.states.clear();
+        self.discovery.clear();
+        self.publish(self.topics.availability(), QoS::AtLeastOnce, true, ONLINE);
+        self.update(settings, targets, rollups);
+    }
+
+    fn publish_ping(&mut self, ping: &LivePing) {
+        match serde_json::to_string(&ping.point) {
+            Ok(payload) => {
+                let topic = self.topics.ping(&ping.target_id);
+                self.publish(topic, QoS::AtMostOnce, false, payload);
+            }
+            Err(e) => error!("Failed to serialize ping result for MQTT: {}", e),
+        }
+    }
+
+    fn publish_presence(&mut self, event: &PresenceEvent) {
+        match serde_json::to_string(event) {
+            Ok(payload) => {
+                let topic = self.topics.presence(&event.mac);
+                self.publish(topic, QoS::AtLeastOnce, true, payload);
+            }
+            Err(e) => error!("Failed to serialize presence event for MQTT: {}", e),
+        }
+    }
+
+    /// Publish changed target states and discovery configs
+    fn update(&mut self, settings: &MqttConfig, targets: &[Target], rollups: &RollingAggregator) {
+        if !self.connected {
+            return;
+        }
+
+        let now = chrono::Utc::now().timestamp();
+        for target in targets {
+            let Some(up) = rollups
+                .rollups(&target.id, now)
+                .and_then(|current| classify(&current, target.ping_count))
+            else {
+                continue;
+            };
+            if self.states.insert(target.id.clone(), up) != Some(up) {
+                let payload = if up { TARGET_UP } else { TARGET_DOWN };
+                self.publish(
+                    self.topics.status(&target.id),
+                    QoS::AtLeastOnce,
+                    true,
+                    payload,
+                );
+            }
+        }
+
+        // Clear the retained state of removed targets
+        let removed: Vec<String> = self
+            .states
+            .keys()
+            .filter(|id| !targets.iter().any(|t| &t.id == *id))
+            .cloned()
+            .collect();
+        for id in removed {
+            self.states.remove(&id);
+            self.publish(self.topics.status(&id), QoS::AtLeastOnce, true, "");
+        }
+
+        if settings.ha_discovery {
+            let desired =
+                homeassistant::discovery_configs(&settings.discovery_prefix, &self.topics, targets);
+            for (topic, payload) in homeassistant::discovery_updates(&self.discovery, &desired) {
+                if payload.is_empty() {
+                    self.discovery.remove(&topic);
+                } else {
+                    self.discovery.insert(topic.clone(), payload.clone());
+                }
+                self.publish(topic, QoS::AtLeastOnce, true, payload);
+            }
+        }
+    }
+}
+
+fn read_settings(config: &RwLock<AppConfig>) -> Option<(MqttConfig, Vec<Target>)> {
+    match config.read() {
+        Ok(c) => Some((c.mqtt.clone(), c.targets.clone())),
+        Err(e) => {
+            error!("Failed to read config for MQTT: {}", e);
+            None
+        }
+    }
+}
+
+/// Client ID from the config, or "sparkping-<hostname>"
+fn client_id(settings: &MqttConfig) -> String {
+    settings
+        .client_id
+        .clone()
+        .filter(|id| !id.is_empty())
+        .unwrap_or_else(|| {
+            let hostname = hostname::get()
+                .ok()
+                .and_then(|h| h.to_str().map(|s| s.to_string()))
+                .unwrap_or_else(|| "unknown".to_string());
+            format!("sparkping-{}", hostname)
+        })
+}
+
+fn mqtt_options(settings: &MqttConfig, host: &str, topics: &Topics) -> MqttOptions {
+    let mut options = MqttOptions::new(client_id(settings), host, settings.port);
+    options.set_keep_alive(KEEP_ALIVE);
+    if let Some(username) = settings.username.as_deref().filter(|u| !u.is_empty()) {
+        options.set_credentials(username, settings.password.clone().unwrap_or_default());
+    }
+    options.set_last_will(LastWill::new(
+        topics.availability(),
+        OFFLINE,
+        QoS::AtLeastOnce,
+        true,
+    ));
+    options
+}
+
+/// Publish until the settings change or a feed closes
+async fn run_publisher(
+    live: &LiveFeed,
+    presence: &PresenceTracker,
+    rollups: &RollingAggregator,
+    config: &RwLock<AppConfig>,
+    settings: &MqttConfig,
+) {
+    let Some(host) = settings.host.as_deref() else {
+        return;
+    };
+    let topics = Topics::new(&settings.topic_prefix);
+    let (client, mut eventloop) =
+        AsyncClient::new(mqtt_options(settings, host, &topics), REQUEST_CAPACITY);
+    let mut publisher = Publisher::new(client, topics);
+
+    info!("Publishing to MQTT broker {}:{}", host, settings.port);
+    let mut results = live.subscribe();
+    let mut presence_events = presence.subscribe();
+    let mut check = tokio::time::interval(CHECK_INTERVAL);
+
+    loop {
+        tokio::select! {
+            event = eventloop.poll() => match event {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    info!("Connected to MQTT broker {}:{}", host, settings.port);
+                    let Some((_, targets)) = read_settings(config) else {
+                        continue;
+                    };
+                    publisher.on_connect(settings, &targets, rollups);
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    if publisher.connected {
+                        warn!("MQTT connection lost: {}", e);
+                    } else {
+                        warn!(
+                            "Failed to connect to MQTT broker {}:{}: {}",
+                            host, settings.port, e
+                        );
+                    }
+                    publisher.connected = false;
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                }
+            },
+            result = results.recv() => match result {
+                // Sensors are per target of this server; results of agents
+                // would overwrite their state with another site's view
+                Ok(ping) if publisher.connected && ping.point.site.is_none() => {
+                    publisher.publish_ping(&ping)
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(skipped)) => publisher.dropped += skipped,
+                Err(RecvError::Closed) => return,
+            },
+            event = presence_events.recv() => match event {
+                Ok(event) if publisher.connected => publisher.publish_presence(&event),
+                Ok(_) => {}
+                Err(RecvError::Lagged(skipped)) => publisher.dropped += skipped,
+                Err(RecvError::Closed) => return,
+            },
+            _ = check.tick() => {
+                let Some((current, targets)) = read_settings(config) else {
+                    continue;
+                };
+                if current != *settings {
+                    info!("MQTT settings changed");
+                    return;
+                }
+                publisher.update(settings, &targets, rollups);
+                if publisher.dropped > 0 {
+                    warn!("{} MQTT messages were not published", publisher.dropped);
+                    publisher.dropped = 0;
+                }
+            }
+        }
+    }
+}
+
+/// Start the MQTT publisher. Settings are re-read every few seconds; while
+/// publishing is disabled no connection is made and the feeds are not
+/// subscribed to.
+pub fn start_mqtt_task(
+    live: Arc<LiveFeed>,
+    presence: Arc<PresenceTracker>,
+    rollups: Arc<RollingAggregator>,
+    config: Arc<RwLock<AppConfig>>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            wait_until_enabled(&config, |c| {
+                c.mqtt.enabled && c.mqtt.host.as_deref().is_some_and(|h| !h.is_empty())
+            })
+            .await;
+
+            let Some((settings, _)) = read_settings(&config) else {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                continue;
+            };
+
+            run_publisher(&live, &presence, &rollups, &config, &settings).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_topic_id() {
+        assert_eq!(topic_id("router-1_a"), "router-1_a");
+        assert_eq!(topic_id("main.router/#+"), "main_router___");
+    }
+
+    #[test]
+    fn test_topics() {
+        let topics = Topics::new("/home/sparkping/");
+        assert_eq!(topics.availability(), "home/sparkping/status");
+        assert_eq!(topics.ping("t1"), "home/sparkping/t1/ping");
+        assert_eq!(topics.status("t1"), "home/sparkping/t1/status");
+        assert_eq!(
+            topics.presence("AA:bb:cc:dd:ee:02"),
+            "home/sparkping/presence/AAbbccddee02"
+        );
+    }
+
+    #[test]
+    fn test_client_id() {
+        let mut settings = MqttConfig::default();
+        assert!(client_id(&settings).starts_with("sparkping-"));
+        settings.client_id = Some("pinger".to_string());
+        assert_eq!(client_id(&settings), "pinger");
+    }
+}
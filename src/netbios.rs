@@ -0,0 +1,258 @@
+//! NetBIOS and LLMNR name lookups for Windows hosts.
+//!
+//! Windows PCs usually neither advertise mDNS services nor have a PTR record.
+//! They do answer a NetBIOS node status request (UDP 137), which lists the
+//! computer name, workgroup and MAC address, and a unicast LLMNR PTR query
+//! (UDP 5355) for their own address.
+
+use crate::reverse_dns;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tracing::debug;
+
+/// Default timeout for each lookup in milliseconds
+pub const DEFAULT_TIMEOUT_MS: u64 = 1000;
+
+const NETBIOS_NAME_PORT: u16 = 137;
+const LLMNR_PORT: u16 = 5355;
+
+/// Node status (NBSTAT) record type
+const TYPE_NBSTAT: u16 = 0x21;
+const CLASS_IN: u16 = 1;
+
+/// Suffix of workstation and workgroup names
+const SUFFIX_WORKSTATION: u8 = 0x00;
+/// Flag of group (workgroup/domain) names
+const GROUP_NAME_FLAG: u16 = 0x8000;
+
+/// Largest node status response accepted
+const MAX_RESPONSE_SIZE: usize = 1024;
+
+/// Names a Windows host reports in a node status response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetbiosInfo {
+    /// Computer name (e.g., "DESKTOP-4F2K9QX")
+    pub name: String,
+    /// Workgroup or domain (e.g., "WORKGROUP")
+    pub workgroup: Option<String>,
+    /// MAC address, if reported
+    pub mac: Option<String>,
+}
+
+/// Ask a host for its NetBIOS names. Failures are logged and reported as
+/// no names.
+pub async fn lookup_netbios(ip: Ipv4Addr, timeout: Duration) -> Option<NetbiosInfo> {
+    match tokio::time::timeout(timeout, query_node_status(ip)).await {
+        Ok(Ok(info)) => info,
+        Ok(Err(e)) => {
+            debug!("NetBIOS lookup of {} failed: {}", ip, e);
+            None
+        }
+        Err(_) => {
+            debug!("NetBIOS lookup of {} timed out", ip);
+            None
+        }
+    }
+}
+
+/// Ask a host for its own name over LLMNR, without the trailing dot
+pub async fn lookup_llmnr(ip: IpAddr, timeout: Duration) -> Option<String> {
+    let query = reverse_dns::query_ptr(SocketAddr::new(ip, LLMNR_PORT), ip, false);
+    match tokio::time::timeout(timeout, query).await {
+        Ok(Ok(name)) => name,
+        Ok(Err(e)) => {
+            debug!("LLMNR lookup of {} failed: {}", ip, e);
+            None
+        }
+        Err(_) => {
+            debug!("LLMNR lookup of {} timed out", ip);
+            None
+        }
+    }
+}
+
+async fn query_node_status(ip: Ipv4Addr) -> Result<Option<NetbiosInfo>, String> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+        .await
+        .map_err(|e| e.to_string())?;
+    socket
+        .connect((ip, NETBIOS_NAME_PORT))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let id = reverse_dns::random_id();
+    socket
+        .send(&build_node_status_request(id))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut buf = [0u8; MAX_RESPONSE_SIZE];
+    loop {
+        let len = socket.recv(&mut buf).await.map_err(|e| e.to_string())?;
+        if len >= 2 && u16::from_be_bytes([buf[0], buf[1]]) == id {
+            return parse_node_status(&buf[..len]);
+        }
+    }
+}
+
+/// Node status request for the wildcard name "*"
+fn build_node_status_request(id: u16) -> Vec<u8> {
+    let mut request = Vec::with_capacity(50);
+    request.extend_from_slice(&id.to_be_bytes());
+    request.extend_from_slice(&[0x00, 0x00]); // query, no flags
+    request.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]); // one question
+
+    // "*" padded with zeros to 16 bytes, each nibble encoded as 'A' + nibble
+    let mut name = [0u8; 16];
+    name[0] = b'*';
+    request.push(32);
+    for byte in name {
+        request.push(b'A' + (byte >> 4));
+        request.push(b'A' + (byte & 0x0f));
+    }
+    request.push(0);
+
+    request.extend_from_slice(&TYPE_NBSTAT.to_be_bytes());
+    request.extend_from_slice(&CLASS_IN.to_be_bytes());
+    request
+}
+
+/// Skip an (encoded or compressed) name at `pos`, returning the position
+/// after it
+fn skip_name(message: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *message.get(pos)? as usize;
+        if len == 0 {
+            return Some(pos + 1);
+        }
+        if len & 0xc0 == 0xc0 {
+            return Some(pos + 2);
+        }
+        pos += 1 + len;
+    }
+}
+
+/// Extract the computer name, workgroup and MAC from a node status response
+fn parse_node_status(response: &[u8]) -> Result<Option<NetbiosInfo>, String> {
+    let malformed = || "Malformed NetBIOS response".to_string();
+    if response.len() < 12 || response[2] & 0x80 == 0 {
+        return Err(malformed());
+    }
+    if u16::from_be_bytes([response[6], response[7]]) == 0 {
+        return Ok(None);
+    }
+
+    let mut pos = skip_name(response, 12).ok_or_else(malformed)?;
+    let header = response.get(pos..pos + 10).ok_or_else(malformed)?;
+    if u16::from_be_bytes([header[0], header[1]]) != TYPE_NBSTAT {
+        return Err(malformed());
+    }
+    pos += 10;
+
+    let count = *response.get(pos).ok_or_else(malformed)? as usize;
+    pos += 1;
+
+    let mut name = None;
+    let mut workgroup = None;
+    for _ in 0..count {
+        let entry = response.get(pos..pos + 18).ok_or_else(malformed)?;
+        pos += 18;
+        if entry[15] != SUFFIX_WORKSTATION {
+            continue;
+        }
+        let value = String::from_utf8_lossy(&entry[..15]).trim_end().to_string();
+        if value.is_empty() {
+            continue;
+        }
+        let flags = u16::from_be_bytes([entry[16], entry[17]]);
+        if flags & GROUP_NAME_FLAG == 0 {
+            name.get_or_insert(value);
+        } else {
+            workgroup.get_or_insert(value);
+        }
+    }
+
+    // The statistics that follow the names start with the MAC address;
+    // Samba reports all zeros
+    let mac = response
+        .get(pos..pos + 6)
+        .filter(|mac| mac.iter().any(|b| *b != 0))
+        .map(|mac| {
+            mac.iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<_>>()
+                .join(":")
+        });
+
+    Ok(name.map(|name| NetbiosInfo {
+        name,
+        workgroup,
+        mac,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name_entry(name: &str, suffix: u8, flags: u16) -> Vec<u8> {
+        let mut entry = format!("{:<15}", name).into_bytes();
+        entry.push(suffix);
+        entry.extend_from_slice(&flags.to_be_bytes());
+        entry
+    }
+
+    fn sample_response(mac: [u8; 6]) -> Vec<u8> {
+        let mut response = build_node_status_request(0x4242);
+        response[2] = 0x84; // response, authoritative
+        response[5] = 0; // no questions
+        response[7] = 1; // one answer
+        response.extend_from_slice(&[0, 0, 0, 0]); // ttl
+        let names = [
+            name_entry("DESKTOP-4F2K9QX", 0x00, 0x0400),
+            name_entry("WORKGROUP", 0x00, 0x8400),
+            name_entry("DESKTOP-4F2K9QX", 0x20, 0x0400),
+        ];
+        let data_len = 1 + names.len() * 18 + 46;
+        response.extend_from_slice(&(data_len as u16).to_be_bytes());
+        response.push(names.len() as u8);
+        for entry in &names {
+            response.extend_from_slice(entry);
+        }
+        response.extend_from_slice(&mac);
+        response.extend_from_slice(&[0; 40]);
+        response
+    }
+
+    #[test]
+    fn test_build_node_status_request() {
+        let request = build_node_status_request(1);
+        assert_eq!(request.len(), 50);
+        assert_eq!(&request[13..17], b"CKAA");
+        assert_eq!(&request[46..], &[0, 0x21, 0, 1]);
+    }
+
+    #[test]
+    fn test_parse_node_status() {
+        let response = sample_response([0x00, 0x15, 0x5d, 0x01, 0x02, 0x03]);
+
+        let info = parse_node_status(&response).unwrap().unwrap();
+        assert_eq!(info.name, "DESKTOP-4F2K9QX");
+        assert_eq!(info.workgroup, Some("WORKGROUP".to_string()));
+        assert_eq!(info.mac, Some("00:15:5d:01:02:03".to_string()));
+    }
+
+    #[test]
+    fn test_parse_node_status_without_mac() {
+        let response = sample_response([0; 6]);
+        let info = parse_node_status(&response).unwrap().unwrap();
+        assert_eq!(info.mac, None);
+    }
+
+    #[test]
+    fn test_parse_node_status_truncated() {
+        let response = sample_response([0; 6]);
+        assert!(parse_node_status(&response[..80]).is_err());
+    }
+}
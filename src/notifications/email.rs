@@ -0,0 +1,84 @@
+use super::{Notification, NotificationError};
+use crate::config::{ChannelKind, SmtpSecurity};
+use lettre::message::header::ContentType;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::time::Duration;
+
+const SMTP_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Plain-text email body
+fn email_body(notification: &Notification) -> String {
+    let mut body = notification.message.clone();
+    body.push_str("\n\n");
+    if let Some(ref target) = notification.target {
+        match notification.target_name {
+            Some(ref name) => body.push_str(&format!("Target: {} ({})\n", name, target)),
+            None => body.push_str(&format!("Target: {}\n", target)),
+        }
+    }
+    if let Some(time) = chrono::DateTime::from_timestamp(notification.timestamp, 0) {
+        body.push_str(&format!("Time: {}\n", time.to_rfc3339()));
+    }
+    body.push_str("\n-- \nSent by SparkPing\n");
+    body
+}
+
+/// Send a notification via SMTP
+pub(super) async fn send(
+    channel: &ChannelKind,
+    notification: &Notification,
+) -> Result<(), NotificationError> {
+    let ChannelKind::Email {
+        smtp_host,
+        smtp_port,
+        security,
+        username,
+        password,
+        from,
+        to,
+    } = channel
+    else {
+        return Ok(());
+    };
+
+    let mut builder = Message::builder()
+        .from(
+            from.parse()
+                .map_err(|e| NotificationError::InvalidEmail(format!("from '{}': {}", from, e)))?,
+        )
+        .subject(&notification.title)
+        .header(ContentType::TEXT_PLAIN);
+    for recipient in to {
+        builder = builder.to(recipient
+            .parse()
+            .map_err(|e| NotificationError::InvalidEmail(format!("to '{}': {}", recipient, e)))?);
+    }
+    let message = builder
+        .body(email_body(notification))
+        .map_err(|e| NotificationError::InvalidEmail(e.to_string()))?;
+
+    let transport = match security {
+        SmtpSecurity::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(smtp_host),
+        SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(smtp_host),
+        SmtpSecurity::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(
+            smtp_host,
+        )),
+    }
+    .map_err(|e| NotificationError::Smtp(e.to_string()))?
+    .port(*smtp_port)
+    .timeout(Some(SMTP_TIMEOUT));
+
+    let transport = match (username, password) {
+        (Some(username), Some(password)) => transport
+            .credentials(Credentials::new(username.clone(), password.clone()))
+            .build(),
+        _ => transport.build(),
+    };
+
+    transport
+        .send(message)
+        .await
+        .map_err(|e| NotificationError::Smtp(e.to_string()))?;
+    Ok(())
+}
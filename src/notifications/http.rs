@@ -0,0 +1,62 @@
+use super::{Notification, NotificationError};
+use crate::config::NotificationKind;
+use std::time::Duration;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// POST a JSON body to a webhook URL
+pub(super) async fn post_json(
+    url: &str,
+    body: &serde_json::Value,
+) -> Result<(), NotificationError> {
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .map_err(|e| NotificationError::HttpClient(e.to_string()))?;
+
+    let response = client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body.to_string())
+        .send()
+        .await
+        .map_err(|e| NotificationError::Request(e.to_string()))?;
+
+    if !response.status().is_success() {
+        return Err(NotificationError::HttpStatus(response.status().as_u16()));
+    }
+    Ok(())
+}
+
+/// Slack-compatible incoming webhook payload (also accepted by Mattermost,
+/// Rocket.Chat and Discord's `/slack` endpoint)
+pub(super) fn slack_payload(notification: &Notification) -> serde_json::Value {
+    let icon = match notification.kind {
+        NotificationKind::TargetDown | NotificationKind::AlertFiring => ":red_circle:",
+        NotificationKind::TargetUp | NotificationKind::AlertResolved => ":large_green_circle:",
+        NotificationKind::UpdateAvailable => ":package:",
+        NotificationKind::Test => ":information_source:",
+    };
+    serde_json::json!({
+        "text": format!("{} *{}*\n{}", icon, notification.title, notification.message),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slack_payload() {
+        let notification = Notification::new(
+            NotificationKind::TargetDown,
+            "Router is down",
+            "No reply for 3 pings",
+            0,
+        );
+        assert_eq!(
+            slack_payload(&notification)["text"],
+            ":red_circle: *Router is down*\nNo reply for 3 pings"
+        );
+    }
+}
@@ -0,0 +1,219 @@
+//! Notification channels (webhook, Slack, email).
+//!
+//! Channels are configured as `[[notifications.channels]]` and receive
+//! target up/down transitions (`target_state`), alert transitions, and new
+//! releases found by the update check (`update_check`). Each channel can
+//! restrict the events it receives via `events`.
+
+mod email;
+mod http;
+pub mod target_state;
+
+use crate::config::{ChannelConfig, ChannelKind, NotificationKind, Target};
+use serde::Serialize;
+use tracing::{debug, warn};
+
+/// A notification sent to channels
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification {
+    pub kind: NotificationKind,
+    /// Short summary, used as email subject
+    pub title: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_name: Option<String>,
+    /// Unix timestamp in seconds
+    pub timestamp: i64,
+    /// Event-specific data (e.g. the alert status)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+}
+
+impl Notification {
+    /// Notification without target information
+    pub fn new(
+        kind: NotificationKind,
+        title: impl Into<String>,
+        message: impl Into<String>,
+        timestamp: i64,
+    ) -> Self {
+        Self {
+            kind,
+            title: title.into(),
+            message: message.into(),
+            target_id: None,
+            target: None,
+            target_name: None,
+            timestamp,
+            details: None,
+        }
+    }
+
+    /// Attach target information
+    pub fn with_target(mut self, target: &Target) -> Self {
+        self.target_id = Some(target.id.clone());
+        self.target = Some(target.address.clone());
+        self.target_name = target.name.clone();
+        self
+    }
+
+    /// Attach event-specific details
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    /// Test notification for `POST /api/notifications/test`
+    pub fn test(channel: &str, timestamp: i64) -> Self {
+        Self::new(
+            NotificationKind::Test,
+            "SparkPing test notification",
+            format!(
+                "This is a test notification for channel '{}'. If you can read this, the channel is configured correctly.",
+                channel
+            ),
+            timestamp,
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum NotificationError {
+    /// Failed to create HTTP client
+    HttpClient(String),
+    /// HTTP request failed
+    Request(String),
+    /// Non-success HTTP status
+    HttpStatus(u16),
+    /// Invalid email address or message
+    InvalidEmail(String),
+    /// SMTP delivery failed
+    Smtp(String),
+}
+
+impl std::fmt::Display for NotificationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NotificationError::HttpClient(e) => write!(f, "Failed to create HTTP client: {}", e),
+            NotificationError::Request(e) => write!(f, "HTTP request failed: {}", e),
+            NotificationError::HttpStatus(code) => write!(f, "HTTP error: {}", code),
+            NotificationError::InvalidEmail(e) => write!(f, "Invalid email: {}", e),
+            NotificationError::Smtp(e) => write!(f, "SMTP delivery failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for NotificationError {}
+
+/// Whether a channel subscribes to an event type
+fn accepts(channel: &ChannelConfig, kind: NotificationKind) -> bool {
+    channel.events.is_empty() || channel.events.contains(&kind)
+}
+
+/// Send a notification to a single channel
+pub async fn send(
+    channel: &ChannelConfig,
+    notification: &Notification,
+) -> Result<(), NotificationError> {
+    debug!(
+        "Sending {:?} notification to channel '{}'",
+        notification.kind, channel.name
+    );
+    match channel.kind {
+        ChannelKind::Webhook { ref url } => {
+            http::post_json(url, &serde_json::to_value(notification).unwrap_or_default()).await
+        }
+        ChannelKind::Slack { ref url } => {
+            http::post_json(url, &http::slack_payload(notification)).await
+        }
+        ChannelKind::Email { .. } => email::send(&channel.kind, notification).await,
+    }
+}
+
+/// Send a notification to every channel subscribed to its event type.
+///
+/// Each delivery runs in its own task so slow channels never delay the
+/// caller; failures are logged.
+pub fn dispatch(channels: &[ChannelConfig], notification: Notification) {
+    for channel in channels.iter().filter(|c| accepts(c, notification.kind)) {
+        let channel = channel.clone();
+        let notification = notification.clone();
+        tokio::spawn(async move {
+            if let Err(e) = send(&channel, &notification).await {
+                warn!(
+                    "Failed to deliver notification to channel '{}': {}",
+                    channel.name, e
+                );
+            }
+        });
+    }
+}
+
+/// Display name of a target in notification texts
+pub(crate) fn target_label(target: &Target) -> String {
+    match target.name {
+        Some(ref name) => format!("{} ({})", name, target.address),
+        None => target.address.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_event_filter() {
+        let mut channel = ChannelConfig {
+            name: "ops".to_string(),
+            events: Vec::new(),
+            kind: ChannelKind::Webhook {
+                url: "http://localhost/hook".to_string(),
+            },
+        };
+        assert!(accepts(&channel, NotificationKind::TargetDown));
+
+        channel.events = vec![NotificationKind::AlertFiring];
+        assert!(accepts(&channel, NotificationKind::AlertFiring));
+        assert!(!accepts(&channel, NotificationKind::TargetDown));
+    }
+
+    #[test]
+    fn test_channel_config_parsing() {
+        let toml = r#"
+            [[channels]]
+            name = "mail"
+            type = "email"
+            smtp_host = "smtp.example.com"
+            from = "sparkping@example.com"
+            to = ["admin@example.com"]
+            events = ["target_down", "target_up"]
+        "#;
+        #[derive(serde::Deserialize)]
+        struct Wrapper {
+            channels: Vec<ChannelConfig>,
+        }
+        let wrapper: Wrapper = config::Config::builder()
+            .add_source(config::File::from_str(toml, config::FileFormat::Toml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+        let channel = &wrapper.channels[0];
+        assert_eq!(channel.events.len(), 2);
+        match channel.kind {
+            ChannelKind::Email {
+                smtp_port,
+                security,
+                ..
+            } => {
+                assert_eq!(smtp_port, 587);
+                assert_eq!(security, crate::config::SmtpSecurity::Starttls);
+            }
+            _ => panic!("expected email channel"),
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! Target up/down tracking based on live rollups.
+//!
+//! A target is down once all pings of a cycle failed (`ping_count`
+//! consecutive failures) and up again after any successful ping. The first
+//! observation of a target only records its state, so restarts do not send
+//! a burst of notifications.
+
+use super::{dispatch, target_label, Notification};
+use crate::config::{AppConfig, NotificationKind, Target};
+use crate::rollups::{RollingAggregator, TargetRollups};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::task::AbortHandle;
+use tracing::{error, info};
+
+/// How often target states are checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Classify a target as up (true) or down (false).
+///
+/// Returns None while the state is undetermined (no results yet, or some but
+/// not all pings of the current cycle failed).
+pub(crate) fn classify(rollups: &TargetRollups, ping_count: u16) -> Option<bool> {
+    rollups.last_sample_unix?;
+    match rollups.consecutive_failed_count {
+        0 => Some(true),
+        n if n >= ping_count.max(1) as u64 => Some(false),
+        _ => None,
+    }
+}
+
+/// Last known up/down state per target
+#[derive(Debug, Default)]
+pub struct TargetStateMonitor {
+    states: Mutex<HashMap<String, bool>>,
+}
+
+impl TargetStateMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Update target states and return notifications for transitions
+    pub fn observe(
+        &self,
+        targets: &[Target],
+        rollups: &RollingAggregator,
+        now: i64,
+    ) -> Vec<Notification> {
+        let mut states = self.states.lock().unwrap_or_else(|e| e.into_inner());
+        states.retain(|id, _| targets.iter().any(|t| &t.id == id));
+
+        let mut notifications = Vec::new();
+        for target in targets {
+            let Some(current) = rollups.rollups(&target.id, now) else {
+                continue;
+            };
+            let Some(up) = classify(&current, target.ping_count) else {
+                continue;
+            };
+
+            match states.insert(target.id.clone(), up) {
+                Some(previous) if previous != up => {
+                    notifications.push(transition_notification(target, up, &current, now));
+                }
+                _ => {}
+            }
+        }
+        notifications
+    }
+}
+
+fn transition_notification(
+    target: &Target,
+    up: bool,
+    rollups: &TargetRollups,
+    now: i64,
+) -> Notification {
+    let label = target_label(target);
+    let (kind, title, message) = if up {
+        let latency = rollups
+            .last_latency_ms
+            .map(|ms| format!(" ({:.1} ms)", ms))
+            .unwrap_or_default();
+        (
+            NotificationKind::TargetUp,
+            format!("{} is up", label),
+            format!("{} is responding to pings again{}.", label, latency),
+        )
+    } else {
+        (
+            NotificationKind::TargetDown,
+            format!("{} is down", label),
+            format!(
+                "The last {} pings to {} failed.",
+                rollups.consecutive_failed_count, label
+            ),
+        )
+    };
+    Notification::new(kind, title, message, now).with_target(target)
+}
+
+/// Start the task that sends target up/down notifications
+pub fn start_target_state_task(
+    monitor: Arc<TargetStateMonitor>,
+    rollups: Arc<RollingAggregator>,
+    config: Arc<RwLock<AppConfig>>,
+) -> AbortHandle {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(CHECK_INTERVAL).await;
+
+            let settings = config
+                .read()
+                .map(|c| (c.targets.clone(), c.notifications.clone()))
+                .ok();
+            let Some((targets, notifications)) = settings else {
+                error!("Failed to read config for target state notifications");
+                continue;
+            };
+
+            let now = chrono::Utc::now().timestamp();
+            for notification in monitor.observe(&targets, &rollups, now) {
+                info!("{}", notification.title);
+                if notifications.target_state {
+                    dispatch(&notifications.channels, notification);
+                }
+            }
+        }
+    })
+    .abort_handle()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target() -> Target {
+        Target {
+            id: "t1".to_string(),
+            address: "192.168.1.1".to_string(),
+            name: Some("Router".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_up_down_transitions() {
+        let monitor = TargetStateMonitor::new();
+        let rollups = RollingAggregator::new();
+        let targets = vec![target()];
+
+        // First observation only records the state
+        rollups.record("t1", 1, Some(10.0));
+        assert!(monitor.observe(&targets, &rollups, 1).is_empty());
+
+        // Partial loss within a cycle is not a transition
+        rollups.record("t1", 2, None);
+        rollups.record("t1", 3, None);
+        assert!(monitor.observe(&targets, &rollups, 3).is_empty());
+
+        rollups.record("t1", 4, None);
+        let notifications = monitor.observe(&targets, &rollups, 4);
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].kind, NotificationKind::TargetDown);
+        assert_eq!(notifications[0].title, "Router (192.168.1.1) is down");
+
+        // Still down: no repeated notification
+        rollups.record("t1", 5, None);
+        assert!(monitor.observe(&targets, &rollups, 5).is_empty());
+
+        rollups.record("t1", 6, Some(12.0));
+        let notifications = monitor.observe(&targets, &rollups, 6);
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].kind, NotificationKind::TargetUp);
+    }
+}
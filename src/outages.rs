@@ -0,0 +1,309 @@
+//! Outage detection from consecutive failed pings.
+//!
+//! Each ping task feeds its results into an [`OutageDetector`]. Once
+//! `[ping] outage_after` pings in a row have failed, an outage is opened at
+//! the first of them; the next successful ping closes it. Both transitions
+//! are stored as an `outage` series in tsink (1.0 = started, 0.0 = ended), so
+//! outages survive restarts and can be listed without scanning raw results.
+
+use crate::ping::PingResult;
+use crate::storage::StorageBackend;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use tsink::{DataPoint, Label, Row};
+
+/// Metric name for outage transitions
+pub const OUTAGE_METRIC: &str = "outage";
+
+/// How far before the start of a query outage starts are looked up, so that
+/// outages that began earlier but overlap the queried range are returned
+const OUTAGE_LOOKBACK_SECONDS: i64 = 7 * 86400;
+
+/// Start or end of an outage, with the Unix timestamp in seconds it applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutageTransition {
+    /// Timestamp of the first failed ping of the outage
+    Started(i64),
+    /// Timestamp of the first successful ping after the outage
+    Ended(i64),
+}
+
+/// Tracks consecutive failed pings of a single target
+#[derive(Debug)]
+pub struct OutageDetector {
+    threshold: u16,
+    failures: u16,
+    first_failure: i64,
+    open: bool,
+}
+
+impl OutageDetector {
+    /// `threshold` is the number of consecutive failed pings that open an outage
+    pub fn new(threshold: u16) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            failures: 0,
+            first_failure: 0,
+            open: false,
+        }
+    }
+
+    /// Add a ping result and return the transition it causes, if any
+    pub fn observe(&mut self, success: bool, timestamp: i64) -> Option<OutageTransition> {
+        if success {
+            self.failures = 0;
+            if self.open {
+                self.open = false;
+                return Some(OutageTransition::Ended(timestamp));
+            }
+            return None;
+        }
+
+        if self.failures == 0 {
+            self.first_failure = timestamp;
+        }
+        self.failures = self.failures.saturating_add(1);
+        if !self.open && self.failures >= self.threshold {
+            self.open = true;
+            return Some(OutageTransition::Started(self.first_failure));
+        }
+        None
+    }
+}
+
+/// Row storing an outage transition of the result's target, written with
+/// the ping batch it happened in
+pub fn outage_row(result: &PingResult, transition: OutageTransition) -> Row {
+    let mut labels = vec![
+        Label::new("target_id", &result.target_id),
+        Label::new("target", &result.target),
+    ];
+    if let Some(ref name) = result.target_name {
+        labels.push(Label::new("target_name", name));
+    }
+
+    let point = match transition {
+        OutageTransition::Started(timestamp) => DataPoint::new(timestamp, 1.0),
+        OutageTransition::Ended(timestamp) => DataPoint::new(timestamp, 0.0),
+    };
+    Row::with_labels(OUTAGE_METRIC, labels, point)
+}
+
+fn rfc3339(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp, 0)
+        .unwrap_or_else(Utc::now)
+        .to_rfc3339()
+}
+
+/// An outage of a single target
+#[derive(Debug, Clone, Serialize)]
+pub struct TargetOutage {
+    pub target_id: String,
+    /// Target IP address or hostname
+    pub target: String,
+    pub target_name: Option<String>,
+    /// ISO 8601 formatted start (UTC)
+    pub start: String,
+    /// Unix timestamp in seconds of the first failed ping
+    pub start_unix: i64,
+    /// ISO 8601 formatted end (UTC), if the outage has ended
+    pub end: Option<String>,
+    /// Unix timestamp in seconds of the first successful ping afterwards
+    pub end_unix: Option<i64>,
+    /// Duration in seconds (None while the end is unknown)
+    pub duration_seconds: Option<i64>,
+    /// Whether the target is still down. An outage whose end was never
+    /// recorded (e.g. the server restarted during it) has no end and is not
+    /// ongoing once a later outage has started.
+    pub ongoing: bool,
+}
+
+/// Query outages overlapping `[from, to]`, oldest first.
+///
+/// `filter` matches the target ID or address.
+pub fn query_outages(
+    storage: &dyn StorageBackend,
+    filter: Option<&str>,
+    from: i64,
+    to: i64,
+) -> Result<Vec<TargetOutage>, Box<dyn std::error::Error + Send + Sync>> {
+    // Transitions per target ID; a target's label set changes when it is renamed
+    let mut transitions: BTreeMap<String, (Vec<Label>, Vec<(i64, bool)>)> = BTreeMap::new();
+
+    let series = storage.select_all(OUTAGE_METRIC, from - OUTAGE_LOOKBACK_SECONDS, to)?;
+    for (labels, points) in series {
+        let label = |name: &str| {
+            labels
+                .iter()
+                .find(|l| l.name == name)
+                .map(|l| l.value.clone())
+        };
+        let (Some(target_id), Some(target)) = (label("target_id"), label("target")) else {
+            continue;
+        };
+        if let Some(filter) = filter {
+            if filter != target_id && filter != target {
+                continue;
+            }
+        }
+        let entry = transitions
+            .entry(target_id)
+            .or_insert_with(|| (labels.clone(), Vec::new()));
+        entry
+            .1
+            .extend(points.iter().map(|p| (p.timestamp, p.value > 0.5)));
+    }
+
+    let mut outages = Vec::new();
+    for (target_id, (labels, mut points)) in transitions {
+        points.sort_by_key(|(timestamp, _)| *timestamp);
+        let label = |name: &str| {
+            labels
+                .iter()
+                .find(|l| l.name == name)
+                .map(|l| l.value.clone())
+        };
+        let target = label("target").unwrap_or_default();
+        let target_name = label("target_name");
+
+        let mut open: Option<i64> = None;
+        let mut close = |start: i64, end: Option<i64>, ongoing: bool| {
+            outages.push(TargetOutage {
+                target_id: target_id.clone(),
+                target: target.clone(),
+                target_name: target_name.clone(),
+                start: rfc3339(start),
+                start_unix: start,
+                end: end.map(rfc3339),
+                end_unix: end,
+                duration_seconds: end.map(|end| end - start),
+                ongoing,
+            })
+        };
+        for (timestamp, started) in points {
+            match (started, open) {
+                (true, Some(start)) => {
+                    close(start, None, false);
+                    open = Some(timestamp);
+                }
+                (true, None) => open = Some(timestamp),
+                (false, Some(start)) => {
+                    close(start, Some(timestamp), false);
+                    open = None;
+                }
+                // End of an outage that started before the lookback
+                (false, None) => {}
+            }
+        }
+        if let Some(start) = open {
+            close(start, None, true);
+        }
+    }
+
+    // Outages with an unknown end only count from their start
+    outages.retain(|o| {
+        o.start_unix <= to
+            && (o.ongoing || o.end_unix.map_or(o.start_unix >= from, |end| end >= from))
+    });
+    outages.sort_by(|a, b| {
+        a.start_unix
+            .cmp(&b.start_unix)
+            .then_with(|| a.target_id.cmp(&b.target_id))
+    });
+    Ok(outages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProbeType;
+
+    fn result(target_id: &str, timestamp: i64) -> PingResult {
+        PingResult {
+            timestamp: DateTime::from_timestamp(timestamp, 0).unwrap(),
+            target_id: target_id.to_string(),
+            target: format!("{}.example.com", target_id),
+            target_name: None,
+            sequence: 1,
+            resolved_ip: None,
+            probe_type: ProbeType::Icmp,
+            port: None,
+            success: false,
+            latency_ms: None,
+            correction_ms: None,
+            flow: None,
+        }
+    }
+
+    #[test]
+    fn test_detector_opens_after_threshold() {
+        let mut detector = OutageDetector::new(3);
+        assert_eq!(detector.observe(false, 100), None);
+        assert_eq!(detector.observe(true, 110), None);
+
+        assert_eq!(detector.observe(false, 120), None);
+        assert_eq!(detector.observe(false, 130), None);
+        assert_eq!(
+            detector.observe(false, 140),
+            Some(OutageTransition::Started(120))
+        );
+        assert_eq!(detector.observe(false, 150), None);
+        assert_eq!(
+            detector.observe(true, 160),
+            Some(OutageTransition::Ended(160))
+        );
+        assert_eq!(detector.observe(true, 170), None);
+    }
+
+    #[test]
+    fn test_query_outages() {
+        let storage = crate::storage::memory_storage();
+        let router = result("router", 0);
+        let isp = result("isp", 0);
+
+        for transition in [
+            OutageTransition::Started(1000),
+            OutageTransition::Ended(1300),
+            // Server restarted during this outage
+            OutageTransition::Started(2000),
+            OutageTransition::Started(3000),
+        ] {
+            storage
+                .insert_rows(&[outage_row(&router, transition)])
+                .unwrap();
+        }
+        storage
+            .insert_rows(&[
+                outage_row(&isp, OutageTransition::Started(1500)),
+                outage_row(&isp, OutageTransition::Ended(1600)),
+            ])
+            .unwrap();
+
+        let outages = query_outages(&*storage, None, 0, 4000).unwrap();
+        let summary: Vec<_> = outages
+            .iter()
+            .map(|o| (o.target_id.as_str(), o.start_unix, o.end_unix, o.ongoing))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("router", 1000, Some(1300), false),
+                ("isp", 1500, Some(1600), false),
+                ("router", 2000, None, false),
+                ("router", 3000, None, true),
+            ]
+        );
+        assert_eq!(outages[0].duration_seconds, Some(300));
+
+        // Overlapping the start of the range, filtered by address
+        let outages = query_outages(&*storage, Some("router.example.com"), 1200, 1400).unwrap();
+        assert_eq!(outages.len(), 1);
+        assert_eq!(outages[0].start_unix, 1000);
+
+        // Ongoing outages overlap every later range
+        let outages = query_outages(&*storage, Some("router"), 3500, 4000).unwrap();
+        assert_eq!(outages.len(), 1);
+        assert!(outages[0].ongoing);
+    }
+}
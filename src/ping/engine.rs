@@ -0,0 +1,372 @@
+//! Shared asynchronous ICMP echo sockets.
+//!
+//! ICMP probes of all targets go through a few non-blocking sockets instead
+//! of a blocking socket per ping: one per address family and socket kind,
+//! plus one per echo identifier of ECMP flows on DGRAM sockets (Linux sets
+//! the identifier to the socket's port there). A receive task per socket
+//! hands each echo reply to the probe waiting for its source address,
+//! identifier and sequence number, so hundreds of targets share a handful of
+//! file descriptors and no blocking threads.
+
+use super::ProbeOptions;
+use crate::config::SocketType;
+use crate::icmp::{
+    echo_request_sized, ICMPV6_ECHO_REPLY, ICMPV6_ECHO_REQUEST, ICMP_ECHO_REPLY, ICMP_HEADER_SIZE,
+};
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use std::collections::HashMap;
+use std::io;
+use std::mem::MaybeUninit;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::io::unix::AsyncFd;
+use tokio::sync::oneshot;
+use tracing::{debug, warn};
+
+/// Largest packet read from a socket; longer replies are truncated, which
+/// keeps their headers
+const RECEIVE_BUFFER_SIZE: usize = 2048;
+
+/// Probe waiting for its reply: source address, echo identifier (0 on
+/// DGRAM sockets, where the kernel matches it) and sequence number
+type ReplyKey = (IpAddr, u16, u16);
+
+/// Which shared socket a probe is sent on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct SocketKey {
+    ipv6: bool,
+    raw: bool,
+    /// Port a DGRAM socket is bound to, i.e. its echo identifier
+    bound_ident: Option<u16>,
+}
+
+/// The shared sockets, opened on first use
+pub struct IcmpEngine {
+    sockets: Mutex<HashMap<SocketKey, Arc<EchoSocket>>>,
+    /// Echo identifier of RAW socket probes without a flow
+    ident: u16,
+}
+
+/// The process-wide ICMP engine
+pub fn engine() -> &'static IcmpEngine {
+    static ENGINE: OnceLock<IcmpEngine> = OnceLock::new();
+    ENGINE.get_or_init(IcmpEngine::new)
+}
+
+impl IcmpEngine {
+    fn new() -> Self {
+        Self {
+            sockets: Mutex::new(HashMap::new()),
+            ident: std::process::id() as u16,
+        }
+    }
+
+    /// Send an echo request to `addr` and wait for the reply, with echo
+    /// identifier `ident` when given. Returns the round-trip time. Must be
+    /// called within the Tokio runtime.
+    pub async fn echo(
+        &self,
+        addr: IpAddr,
+        socket_type: SocketType,
+        options: ProbeOptions,
+        ident: Option<u16>,
+    ) -> io::Result<Duration> {
+        let raw = socket_type == SocketType::Raw;
+        let key = SocketKey {
+            ipv6: addr.is_ipv6(),
+            raw,
+            // RAW sockets write the identifier into each packet
+            bound_ident: ident.filter(|_| !raw),
+        };
+        let socket = self.socket(key)?;
+        socket
+            .echo(addr, ident.unwrap_or(self.ident), options)
+            .await
+    }
+
+    /// The socket for `key`, opened and given a receive task on first use
+    fn socket(&self, key: SocketKey) -> io::Result<Arc<EchoSocket>> {
+        let mut sockets = self.sockets.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(socket) = sockets.get(&key) {
+            return Ok(Arc::clone(socket));
+        }
+        let socket = Arc::new(EchoSocket::open(key)?);
+        tokio::spawn(Arc::clone(&socket).receive());
+        sockets.insert(key, Arc::clone(&socket));
+        debug!(
+            "Opened shared ICMP socket (ipv6: {}, raw: {}, ident: {:?})",
+            key.ipv6, key.raw, key.bound_ident
+        );
+        Ok(socket)
+    }
+}
+
+/// A non-blocking ICMP socket and the probes waiting for replies on it
+struct EchoSocket {
+    fd: AsyncFd<Socket>,
+    ipv6: bool,
+    raw: bool,
+    pending: Mutex<HashMap<ReplyKey, oneshot::Sender<Instant>>>,
+    next_seq: AtomicU16,
+    /// TTL the socket sends with; also serializes sends, which may change it
+    ttl: Mutex<Option<u32>>,
+}
+
+impl EchoSocket {
+    fn open(key: SocketKey) -> io::Result<Self> {
+        let (domain, protocol) = if key.ipv6 {
+            (Domain::IPV6, Protocol::ICMPV6)
+        } else {
+            (Domain::IPV4, Protocol::ICMPV4)
+        };
+        let kind = if key.raw { Type::RAW } else { Type::DGRAM };
+        let socket = Socket::new(domain, kind, Some(protocol))
+            .map_err(|e| io::Error::new(e.kind(), format!("socket create failed: {}", e)))?;
+        socket.set_nonblocking(true)?;
+        if let Some(ident) = key.bound_ident {
+            let local_ip = if key.ipv6 {
+                IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+            } else {
+                IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+            };
+            let local = SocketAddr::new(local_ip, ident);
+            if let Err(e) = socket.bind(&local.into()) {
+                debug!("Binding echo identifier {} failed: {}", ident, e);
+            }
+        }
+        Ok(Self {
+            fd: AsyncFd::new(socket)?,
+            ipv6: key.ipv6,
+            raw: key.raw,
+            pending: Mutex::new(HashMap::new()),
+            next_seq: AtomicU16::new(1),
+            ttl: Mutex::new(None),
+        })
+    }
+
+    async fn echo(&self, addr: IpAddr, ident: u16, options: ProbeOptions) -> io::Result<Duration> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let key = self.register(addr, ident, reply_tx)?;
+        let _pending = PendingGuard { socket: self, key };
+
+        let mut packet = echo_request_sized(ident, key.2, options.payload_size);
+        if self.ipv6 {
+            // The kernel computes ICMPv6 checksums over the pseudo header
+            packet[0] = ICMPV6_ECHO_REQUEST;
+            packet[2] = 0;
+            packet[3] = 0;
+        }
+
+        let exchange = async {
+            let sent = self.send(addr, &packet, options.ttl).await?;
+            let received = reply_rx
+                .await
+                .map_err(|_| io::Error::other("ICMP receive task stopped"))?;
+            Ok::<_, io::Error>(received.saturating_duration_since(sent))
+        };
+        tokio::time::timeout(options.timeout, exchange)
+            .await
+            .unwrap_or_else(|_| {
+                Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "ping timed out (no reply received)",
+                ))
+            })
+    }
+
+    /// Wait for a reply from `addr` under a free sequence number
+    fn register(
+        &self,
+        addr: IpAddr,
+        ident: u16,
+        reply_tx: oneshot::Sender<Instant>,
+    ) -> io::Result<ReplyKey> {
+        let ident = if self.raw { ident } else { 0 };
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        for _ in 0..=u16::MAX {
+            let key = (addr, ident, self.next_seq.fetch_add(1, Ordering::Relaxed));
+            if !pending.contains_key(&key) {
+                pending.insert(key, reply_tx);
+                return Ok(key);
+            }
+        }
+        Err(io::Error::other("too many ICMP probes in flight"))
+    }
+
+    /// Send `packet` with time to live `ttl`; returns when it was sent
+    async fn send(&self, addr: IpAddr, packet: &[u8], ttl: u32) -> io::Result<Instant> {
+        let dest: SockAddr = SocketAddr::new(addr, 0).into();
+        loop {
+            let mut guard = self.fd.writable().await?;
+            let result = {
+                let mut current_ttl = self.ttl.lock().unwrap_or_else(|e| e.into_inner());
+                if *current_ttl != Some(ttl) {
+                    if self.ipv6 {
+                        self.fd.get_ref().set_unicast_hops_v6(ttl)?;
+                    } else {
+                        self.fd.get_ref().set_ttl_v4(ttl)?;
+                    }
+                    *current_ttl = Some(ttl);
+                }
+                let sent = Instant::now();
+                guard
+                    .try_io(|fd| fd.get_ref().send_to(packet, &dest))
+                    .map(|result| result.map(|_| sent))
+            };
+            if let Ok(result) = result {
+                return result.map_err(|e| io::Error::new(e.kind(), format!("send failed: {}", e)));
+            }
+        }
+    }
+
+    /// Read replies and hand them to the waiting probes, for as long as the
+    /// process runs
+    async fn receive(self: Arc<Self>) {
+        let mut buf = [MaybeUninit::<u8>::uninit(); RECEIVE_BUFFER_SIZE];
+        loop {
+            let mut guard = match self.fd.readable().await {
+                Ok(guard) => guard,
+                Err(e) => {
+                    warn!(
+                        "Shared ICMP socket failed, stopping its receive task: {}",
+                        e
+                    );
+                    return;
+                }
+            };
+            let (len, from) = match guard.try_io(|fd| fd.get_ref().recv_from(&mut buf)) {
+                Ok(Ok(received)) => received,
+                // e.g. ICMP errors reported on DGRAM sockets; the probe
+                // runs into its timeout
+                Ok(Err(e)) => {
+                    debug!("ICMP receive failed: {}", e);
+                    continue;
+                }
+                Err(_would_block) => continue,
+            };
+            let received = Instant::now();
+            // SAFETY: recv_from initialized the first `len` bytes
+            let packet = unsafe { std::slice::from_raw_parts(buf.as_ptr().cast::<u8>(), len) };
+            let (Some(source), Some((ident, seq))) =
+                (from.as_socket(), parse_echo_reply(packet, self.ipv6))
+            else {
+                continue;
+            };
+            let key = (source.ip(), if self.raw { ident } else { 0 }, seq);
+            let waiting = self
+                .pending
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .remove(&key);
+            if let Some(reply_tx) = waiting {
+                let _ = reply_tx.send(received);
+            }
+        }
+    }
+}
+
+/// Stops waiting for a reply when the probe finishes or is cancelled
+struct PendingGuard<'a> {
+    socket: &'a EchoSocket,
+    key: ReplyKey,
+}
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        self.socket
+            .pending
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.key);
+    }
+}
+
+/// Echo identifier and sequence number of an echo reply. IPv4 RAW sockets
+/// (and DGRAM sockets on macOS/BSD) deliver the IP header too, which is
+/// skipped using its header length.
+fn parse_echo_reply(packet: &[u8], ipv6: bool) -> Option<(u16, u16)> {
+    let offset = match packet.first() {
+        Some(&first) if !ipv6 && first & 0xf0 == 0x40 => (first & 0x0f) as usize * 4,
+        _ => 0,
+    };
+    let header = packet.get(offset..offset + ICMP_HEADER_SIZE)?;
+    let reply_type = if ipv6 {
+        ICMPV6_ECHO_REPLY
+    } else {
+        ICMP_ECHO_REPLY
+    };
+    if header[0] != reply_type {
+        return None;
+    }
+    Some((
+        u16::from_be_bytes([header[4], header[5]]),
+        u16::from_be_bytes([header[6], header[7]]),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::icmp::ICMP_ECHO_REQUEST;
+
+    fn reply(ident: u16, seq: u16) -> Vec<u8> {
+        let mut packet = echo_request_sized(ident, seq, 24);
+        packet[0] = ICMP_ECHO_REPLY;
+        packet
+    }
+
+    #[test]
+    fn test_parse_echo_reply() {
+        assert_eq!(
+            parse_echo_reply(&reply(0x1234, 7), false),
+            Some((0x1234, 7))
+        );
+
+        // With a 20 byte IPv4 header in front
+        let mut with_header = vec![0x45];
+        with_header.resize(20, 0);
+        with_header.extend(reply(42, 300));
+        assert_eq!(parse_echo_reply(&with_header, false), Some((42, 300)));
+
+        let request = echo_request_sized(42, 1, 24);
+        assert_eq!(request[0], ICMP_ECHO_REQUEST);
+        assert_eq!(parse_echo_reply(&request, false), None);
+        assert_eq!(parse_echo_reply(&request[..6], false), None);
+        assert_eq!(parse_echo_reply(&[], false), None);
+    }
+
+    #[test]
+    fn test_parse_echo_reply_v6() {
+        let mut packet = reply(5, 9);
+        packet[0] = ICMPV6_ECHO_REPLY;
+        assert_eq!(parse_echo_reply(&packet, true), Some((5, 9)));
+        // An ICMPv4 reply type is not an ICMPv6 reply
+        assert_eq!(parse_echo_reply(&reply(5, 9), true), None);
+    }
+
+    #[tokio::test]
+    async fn test_sequence_numbers_are_unique_while_pending() {
+        let Ok(socket) = EchoSocket::open(SocketKey {
+            ipv6: false,
+            raw: false,
+            bound_ident: None,
+        }) else {
+            // Unprivileged ICMP sockets are not permitted here
+            return;
+        };
+        let addr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let first = socket.register(addr, 1, oneshot::channel().0).unwrap();
+        let second = socket.register(addr, 1, oneshot::channel().0).unwrap();
+        assert_ne!(first.2, second.2);
+        // DGRAM sockets leave matching the identifier to the kernel
+        assert_eq!(first.1, 0);
+
+        drop(PendingGuard {
+            socket: &socket,
+            key: first,
+        });
+        assert_eq!(socket.pending.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,413 @@
+pub mod engine;
+
+use crate::config::{PingConfig, ProbeType, SocketType, Target, MAX_ECMP_FLOWS};
+use chrono::{DateTime, Utc};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, Instant};
+use tokio::net::{TcpSocket, TcpStream};
+use tracing::{debug, error, warn};
+
+/// Timeout of a single probe
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Time to live of ICMP echo requests
+pub const DEFAULT_TTL: u32 = 64;
+
+/// Payload bytes of ICMP echo requests
+pub const DEFAULT_PAYLOAD_SIZE: usize = 24;
+
+/// Timeout, TTL and payload size of probes. TTL and payload size only
+/// apply to ICMP probes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProbeOptions {
+    pub timeout: Duration,
+    pub ttl: u32,
+    pub payload_size: usize,
+}
+
+impl Default for ProbeOptions {
+    fn default() -> Self {
+        Self {
+            timeout: PROBE_TIMEOUT,
+            ttl: DEFAULT_TTL,
+            payload_size: DEFAULT_PAYLOAD_SIZE,
+        }
+    }
+}
+
+impl ProbeOptions {
+    /// Default options with another timeout
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            ..Self::default()
+        }
+    }
+
+    /// Options of a target; settings it does not override come from `[ping]`
+    pub fn for_target(target: &Target, ping_config: &PingConfig) -> Self {
+        Self {
+            timeout: Duration::from_millis(target.timeout_ms.unwrap_or(ping_config.timeout_ms)),
+            ttl: target.ttl.unwrap_or(ping_config.ttl),
+            payload_size: target.payload_size.unwrap_or(ping_config.payload_size),
+        }
+    }
+}
+
+/// How a single probe is sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Probe {
+    /// ICMP echo request with the given socket type
+    Icmp(SocketType),
+    /// TCP connect to the given port
+    Tcp(u16),
+}
+
+impl Probe {
+    /// Probe for a target; ICMP targets use the global socket type
+    pub fn for_target(target: &Target, socket_type: SocketType) -> Self {
+        match target.tcp_port() {
+            Some(port) => Probe::Tcp(port),
+            None => Probe::Icmp(socket_type),
+        }
+    }
+
+    pub fn probe_type(&self) -> ProbeType {
+        match self {
+            Probe::Icmp(_) => ProbeType::Icmp,
+            Probe::Tcp(_) => ProbeType::Tcp,
+        }
+    }
+
+    fn port(&self) -> Option<u16> {
+        match self {
+            Probe::Icmp(_) => None,
+            Probe::Tcp(port) => Some(*port),
+        }
+    }
+}
+
+/// Lowest local port (TCP source port or ICMP echo identifier) used for
+/// flows; each target gets a block of `MAX_ECMP_FLOWS` ports above it
+const FLOW_PORT_BASE: u16 = 40000;
+
+/// Number of port blocks, keeping flow ports below the top of the
+/// ephemeral range
+const FLOW_PORT_BLOCKS: u32 = 1250;
+
+/// An ECMP flow a probe is sent on. Probes of a flow share their source port
+/// (TCP) or echo identifier (ICMP), so load balancers hashing on them keep
+/// sending the flow along the same path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Flow {
+    /// Index of the flow, recorded as the `flow` label
+    pub index: u16,
+    /// Local port or echo identifier the flow is sent from
+    pub port: u16,
+}
+
+impl Flow {
+    /// Flow of a batch's ping `sequence` (1-based) for targets with
+    /// `ecmp_flows`. Each sequence always uses the same flow, so a flow's
+    /// series can be read by exact labels.
+    pub fn for_sequence(target: &Target, sequence: u16) -> Option<Self> {
+        let flows = target.flow_count();
+        if flows < 2 {
+            return None;
+        }
+        // Stable per-target block, so flows keep their ports across restarts
+        let block = target.id.bytes().fold(0x811c9dc5u32, |hash, b| {
+            (hash ^ b as u32).wrapping_mul(0x01000193)
+        }) % FLOW_PORT_BLOCKS;
+        let index = sequence.saturating_sub(1) % flows;
+        Some(Flow {
+            index,
+            port: FLOW_PORT_BASE + block as u16 * MAX_ECMP_FLOWS + index,
+        })
+    }
+}
+
+pub struct PingResult {
+    pub timestamp: DateTime<Utc>,
+    pub target_id: String,
+    pub target: String,
+    pub target_name: Option<String>,
+    pub sequence: u16,
+    /// Resolved IP address for hostname targets (None for IP targets)
+    pub resolved_ip: Option<String>,
+    pub probe_type: ProbeType,
+    /// Connected port for TCP probes
+    pub port: Option<u16>,
+    pub success: bool,
+    pub latency_ms: Option<f64>,
+    /// Socket overhead subtracted from `latency_ms` (see `calibration`)
+    pub correction_ms: Option<f64>,
+    /// ECMP flow index for targets with `ecmp_flows`
+    pub flow: Option<u16>,
+}
+
+/// Connect to `ip:port` from `source_port`. The socket lingers for 0
+/// seconds, so closing it resets the connection instead of leaving the
+/// port in TIME_WAIT for the flow's next probe.
+async fn connect_from(ip: IpAddr, port: u16, source_port: u16) -> std::io::Result<TcpStream> {
+    let socket = match ip {
+        IpAddr::V4(_) => TcpSocket::new_v4()?,
+        IpAddr::V6(_) => TcpSocket::new_v6()?,
+    };
+    socket.set_reuseaddr(true)?;
+    socket.set_linger(Some(Duration::ZERO))?;
+    let local_ip = match ip {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    socket.bind(SocketAddr::new(local_ip, source_port))?;
+    socket.connect(SocketAddr::new(ip, port)).await
+}
+
+/// Measure the time to establish a TCP connection, from `source_port` when
+/// given
+async fn tcp_connect(
+    ip: IpAddr,
+    port: u16,
+    timeout: Duration,
+    source_port: Option<u16>,
+) -> std::io::Result<f64> {
+    let start = Instant::now();
+    let connect = async {
+        match source_port {
+            Some(source_port) => match connect_from(ip, port, source_port).await {
+                // Another socket holds the port; an ephemeral one still
+                // measures the target, just not on a fixed path
+                Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => {
+                    debug!(
+                        "Source port {} in use, connecting from any port",
+                        source_port
+                    );
+                    TcpStream::connect(SocketAddr::new(ip, port)).await
+                }
+                result => result,
+            },
+            None => TcpStream::connect(SocketAddr::new(ip, port)).await,
+        }
+    };
+    match tokio::time::timeout(timeout, connect).await {
+        Ok(Ok(_)) => Ok(start.elapsed().as_secs_f64() * 1000.0),
+        Ok(Err(e)) => Err(e),
+        Err(_) => Err(std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            "connection timed out",
+        )),
+    }
+}
+
+/// Send a single probe to `ip_addr` on `flow` and return the latency in
+/// milliseconds. ICMP probes share the sockets of the `engine`.
+pub async fn probe_once(
+    ip_addr: IpAddr,
+    probe: Probe,
+    options: ProbeOptions,
+    flow: Option<Flow>,
+) -> std::io::Result<f64> {
+    let flow_port = flow.map(|f| f.port);
+    match probe {
+        Probe::Icmp(socket_type) => engine::engine()
+            .echo(ip_addr, socket_type, options, flow_port)
+            .await
+            .map(|rtt| rtt.as_secs_f64() * 1000.0),
+        Probe::Tcp(port) => tcp_connect(ip_addr, port, options.timeout, flow_port).await,
+    }
+}
+
+/// Probe a target once. `resolved` is the target's resolved IP address, or
+/// the resolution error for hostname targets that could not be resolved.
+#[allow(clippy::too_many_arguments)]
+pub async fn perform_ping(
+    target_id: &str,
+    address: &str,
+    resolved: Result<IpAddr, String>,
+    sequence: u16,
+    name: &Option<String>,
+    probe: Probe,
+    options: ProbeOptions,
+    flow: Option<Flow>,
+) -> PingResult {
+    let timestamp = Utc::now();
+
+    // Record the resolved address only for hostname targets
+    let resolved_ip = match resolved {
+        Ok(ip) if address.parse::<IpAddr>().is_err() => Some(ip.to_string()),
+        _ => None,
+    };
+
+    let ip_addr: IpAddr = match resolved {
+        Ok(ip) => ip,
+        Err(e) => {
+            error!("Cannot ping {}: {}", address, e);
+            return PingResult {
+                timestamp,
+                target_id: target_id.to_string(),
+                target: address.to_string(),
+                target_name: name.clone(),
+                sequence,
+                resolved_ip,
+                probe_type: probe.probe_type(),
+                port: probe.port(),
+                success: false,
+                latency_ms: None,
+                correction_ms: None,
+                flow: flow.map(|f| f.index),
+            };
+        }
+    };
+
+    let start = Instant::now();
+    let ping_result = probe_once(ip_addr, probe, options, flow).await;
+    let elapsed = start.elapsed();
+
+    match ping_result {
+        Ok(latency_ms) => {
+            let latency_rounded = (latency_ms * 100.0).round() / 100.0;
+            let target_name = name.as_ref().map(|s| s.as_str()).unwrap_or(address);
+            debug!(
+                target = %address,
+                seq = sequence,
+                latency_ms = latency_rounded,
+                "✓ {} (seq {}) - {:.2}ms", target_name, sequence, latency_rounded
+            );
+            PingResult {
+                timestamp,
+                target_id: target_id.to_string(),
+                target: address.to_string(),
+                target_name: name.clone(),
+                sequence,
+                resolved_ip,
+                probe_type: probe.probe_type(),
+                port: probe.port(),
+                success: true,
+                latency_ms: Some(latency_ms),
+                correction_ms: None,
+                flow: flow.map(|f| f.index),
+            }
+        }
+        Err(e) => {
+            let target_name = name.as_ref().map(|s| s.as_str()).unwrap_or(address);
+            let latency_ms = elapsed.as_secs_f64() * 1000.0;
+            warn!(
+                target = %address,
+                seq = sequence,
+                error = %e,
+                "✗ {} (seq {}) - Failed after {:.0}ms: {}", target_name, sequence, latency_ms, e
+            );
+            PingResult {
+                timestamp,
+                target_id: target_id.to_string(),
+                target: address.to_string(),
+                target_name: name.clone(),
+                sequence,
+                resolved_ip,
+                probe_type: probe.probe_type(),
+                port: probe.port(),
+                success: false,
+                latency_ms: None,
+                correction_ms: None,
+                flow: flow.map(|f| f.index),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flow_for_sequence() {
+        let mut target = Target {
+            id: "uplink".to_string(),
+            address: "192.0.2.1".to_string(),
+            ping_count: 6,
+            ..Default::default()
+        };
+        assert_eq!(Flow::for_sequence(&target, 1), None);
+
+        target.ecmp_flows = Some(3);
+        let flows: Vec<Flow> = (1..=6)
+            .map(|sequence| Flow::for_sequence(&target, sequence).unwrap())
+            .collect();
+        let indexes: Vec<u16> = flows.iter().map(|f| f.index).collect();
+        assert_eq!(indexes, vec![0, 1, 2, 0, 1, 2]);
+        // Each flow keeps its port, distinct from the other flows
+        assert_eq!(flows[0].port, flows[3].port);
+        assert_eq!(flows[1].port, flows[0].port + 1);
+        assert!(flows.iter().all(|f| f.port >= FLOW_PORT_BASE));
+    }
+
+    #[test]
+    fn test_probe_options_for_target() {
+        let mut target = Target {
+            address: "192.0.2.1".to_string(),
+            ..Default::default()
+        };
+        let ping_config = PingConfig {
+            ttl: 32,
+            ..PingConfig::default()
+        };
+        assert_eq!(
+            ProbeOptions::for_target(&target, &ping_config),
+            ProbeOptions {
+                timeout: PROBE_TIMEOUT,
+                ttl: 32,
+                payload_size: DEFAULT_PAYLOAD_SIZE,
+            }
+        );
+
+        target.timeout_ms = Some(1500);
+        target.payload_size = Some(1000);
+        assert_eq!(
+            ProbeOptions::for_target(&target, &ping_config),
+            ProbeOptions {
+                timeout: Duration::from_millis(1500),
+                ttl: 32,
+                payload_size: 1000,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tcp_probe() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+        let result = perform_ping(
+            "t1",
+            "127.0.0.1",
+            Ok(localhost),
+            1,
+            &None,
+            Probe::Tcp(port),
+            ProbeOptions::default(),
+            None,
+        )
+        .await;
+        assert!(result.success);
+        assert!(result.latency_ms.is_some());
+        assert_eq!(result.probe_type, ProbeType::Tcp);
+        assert_eq!(result.port, Some(port));
+
+        // Nothing listens on the port anymore
+        drop(listener);
+        let result = perform_ping(
+            "t1",
+            "127.0.0.1",
+            Ok(localhost),
+            2,
+            &None,
+            Probe::Tcp(port),
+            ProbeOptions::default(),
+            None,
+        )
+        .await;
+        assert!(!result.success);
+    }
+}
@@ -0,0 +1,310 @@
+//! Open-port history for devices found by IP scans.
+//!
+//! Every IP scan records, for each responding host, whether each scanned
+//! port was open (1.0) or closed (0.0) as a `port_open` series in tsink.
+//! Comparing consecutive observations of a series yields port changes such
+//! as "port 23 newly open on 192.168.1.20" — a cheap home-network security
+//! signal. Hosts in the scanned range that no longer answer have their open
+//! ports recorded as closed, so a device leaving the network shows up too.
+
+use crate::storage::StorageBackend;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::net::Ipv4Addr;
+use tsink::{DataPoint, Label, Row};
+use utoipa::ToSchema;
+
+/// Metric name for per-port scan observations
+pub const PORT_OPEN_METRIC: &str = "port_open";
+
+/// Record the result of scanning a single host.
+///
+/// Ports in `scanned_ports` that are not in `open_ports` are stored as closed
+/// so that a port disappearing between scans is detected as a change.
+pub fn record_port_scan(
+    storage: &dyn StorageBackend,
+    address: &str,
+    scanned_ports: &[u16],
+    open_ports: &[u16],
+    timestamp: i64,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let rows: Vec<Row> = scanned_ports
+        .iter()
+        .map(|port| {
+            let labels = vec![
+                Label::new("address", address),
+                Label::new("port", port.to_string()),
+            ];
+            let value = if open_ports.contains(port) { 1.0 } else { 0.0 };
+            Row::with_labels(PORT_OPEN_METRIC, labels, DataPoint::new(timestamp, value))
+        })
+        .collect();
+
+    if !rows.is_empty() {
+        storage.insert_rows(&rows)?;
+    }
+
+    Ok(())
+}
+
+/// Record the open ports of hosts that did not answer a completed scan.
+///
+/// Addresses between `start` and `end` not in `seen` whose latest
+/// observation of a port in `scanned_ports` was open get that port stored as
+/// closed. Returns the number of hosts recorded as gone.
+pub fn record_missing_hosts(
+    storage: &dyn StorageBackend,
+    start: Ipv4Addr,
+    end: Ipv4Addr,
+    seen: &HashSet<String>,
+    scanned_ports: &[u16],
+    timestamp: i64,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let mut missing = 0;
+
+    for device in query_port_history(storage, None, timestamp, timestamp)? {
+        let in_range = device
+            .address
+            .parse::<Ipv4Addr>()
+            .is_ok_and(|ip| (start..=end).contains(&ip));
+        if !in_range || seen.contains(&device.address) {
+            continue;
+        }
+
+        let closed: Vec<u16> = device
+            .open_ports
+            .iter()
+            .copied()
+            .filter(|port| scanned_ports.contains(port))
+            .collect();
+        if closed.is_empty() {
+            continue;
+        }
+
+        record_port_scan(storage, &device.address, &closed, &[], timestamp)?;
+        missing += 1;
+    }
+
+    Ok(missing)
+}
+
+/// Direction of a port state change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PortChangeKind {
+    /// Port was closed in the previous scan and is open now
+    Opened,
+    /// Port was open in the previous scan and is closed now
+    Closed,
+}
+
+/// A single port state change between two consecutive scans
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PortChange {
+    /// ISO 8601 formatted timestamp of the scan that observed the change
+    pub timestamp: String,
+    /// Unix timestamp in seconds of the scan that observed the change
+    pub timestamp_unix: i64,
+    /// Device IP address
+    pub address: String,
+    /// Port number
+    pub port: u16,
+    /// Whether the port opened or closed
+    pub change: PortChangeKind,
+}
+
+/// Port history for a single device
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct DevicePortHistory {
+    /// Device IP address
+    pub address: String,
+    /// Ports that were open in the most recent observation of each port
+    pub open_ports: Vec<u16>,
+    /// Unix timestamp in seconds of the most recent scan of this device
+    pub last_scanned_unix: i64,
+    /// Port changes within the requested time range (oldest first)
+    pub changes: Vec<PortChange>,
+}
+
+/// Detect open/closed transitions in a single (address, port) series.
+fn detect_port_changes(address: &str, port: u16, points: &[DataPoint]) -> Vec<PortChange> {
+    let mut changes = Vec::new();
+
+    for pair in points.windows(2) {
+        let was_open = pair[0].value > 0.5;
+        let is_open = pair[1].value > 0.5;
+        if was_open == is_open {
+            continue;
+        }
+
+        let timestamp = pair[1].timestamp;
+        changes.push(PortChange {
+            timestamp: DateTime::from_timestamp(timestamp, 0)
+                .unwrap_or_else(Utc::now)
+                .to_rfc3339(),
+            timestamp_unix: timestamp,
+            address: address.to_string(),
+            port,
+            change: if is_open {
+                PortChangeKind::Opened
+            } else {
+                PortChangeKind::Closed
+            },
+        });
+    }
+
+    changes
+}
+
+/// Query port history for all scanned devices (or a single address).
+///
+/// The full series up to `to` is loaded so that the first change inside
+/// `[from, to]` can be compared against the scan that preceded it.
+pub fn query_port_history(
+    storage: &dyn StorageBackend,
+    address_filter: Option<&str>,
+    from: i64,
+    to: i64,
+) -> Result<Vec<DevicePortHistory>, Box<dyn std::error::Error + Send + Sync>> {
+    let results = storage.select_all(PORT_OPEN_METRIC, 0, to)?;
+
+    let mut devices: BTreeMap<String, DevicePortHistory> = BTreeMap::new();
+    let mut open_ports: BTreeMap<String, BTreeSet<u16>> = BTreeMap::new();
+
+    for (labels, mut points) in results {
+        let address = match labels.iter().find(|l| l.name == "address") {
+            Some(l) => l.value.clone(),
+            None => continue,
+        };
+        if let Some(filter) = address_filter {
+            if address != filter {
+                continue;
+            }
+        }
+        let port = match labels
+            .iter()
+            .find(|l| l.name == "port")
+            .and_then(|l| l.value.parse::<u16>().ok())
+        {
+            Some(port) => port,
+            None => continue,
+        };
+
+        points.sort_by_key(|p| p.timestamp);
+        let latest = match points.last() {
+            Some(p) => p,
+            None => continue,
+        };
+
+        let device = devices
+            .entry(address.clone())
+            .or_insert_with(|| DevicePortHistory {
+                address: address.clone(),
+                open_ports: Vec::new(),
+                last_scanned_unix: latest.timestamp,
+                changes: Vec::new(),
+            });
+        device.last_scanned_unix = device.last_scanned_unix.max(latest.timestamp);
+
+        if latest.value > 0.5 {
+            open_ports.entry(address.clone()).or_default().insert(port);
+        }
+
+        device.changes.extend(
+            detect_port_changes(&address, port, &points)
+                .into_iter()
+                .filter(|c| c.timestamp_unix >= from),
+        );
+    }
+
+    let mut history: Vec<DevicePortHistory> = devices.into_values().collect();
+    for device in &mut history {
+        if let Some(ports) = open_ports.remove(&device.address) {
+            device.open_ports = ports.into_iter().collect();
+        }
+        device.changes.sort_by_key(|c| (c.timestamp_unix, c.port));
+    }
+
+    Ok(history)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(timestamp: i64, value: f64) -> DataPoint {
+        DataPoint::new(timestamp, value)
+    }
+
+    #[test]
+    fn test_detect_port_changes() {
+        let points = vec![
+            point(100, 0.0),
+            point(200, 1.0),
+            point(300, 1.0),
+            point(400, 0.0),
+        ];
+        let changes = detect_port_changes("192.168.1.20", 23, &points);
+
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].timestamp_unix, 200);
+        assert_eq!(changes[0].change, PortChangeKind::Opened);
+        assert_eq!(changes[1].timestamp_unix, 400);
+        assert_eq!(changes[1].change, PortChangeKind::Closed);
+        assert_eq!(changes[1].port, 23);
+    }
+
+    #[test]
+    fn test_detect_port_changes_stable() {
+        let points = vec![point(100, 1.0), point(200, 1.0)];
+        assert!(detect_port_changes("192.168.1.20", 80, &points).is_empty());
+    }
+
+    #[test]
+    fn test_detect_port_changes_single_observation() {
+        // A first observation has nothing to compare against
+        let points = vec![point(100, 1.0)];
+        assert!(detect_port_changes("192.168.1.20", 80, &points).is_empty());
+    }
+
+    #[test]
+    fn test_record_missing_hosts() {
+        let storage = crate::storage::memory_storage();
+        record_port_scan(&*storage, "192.168.1.20", &[22, 80], &[22, 80], 100).unwrap();
+        record_port_scan(&*storage, "192.168.1.30", &[22, 80], &[80], 100).unwrap();
+        record_port_scan(&*storage, "10.0.0.5", &[22, 80], &[22], 100).unwrap();
+
+        // .30 answered again, .20 is gone and 10.0.0.5 was not scanned
+        let seen = HashSet::from(["192.168.1.30".to_string()]);
+        let start = Ipv4Addr::new(192, 168, 1, 0);
+        let end = Ipv4Addr::new(192, 168, 1, 255);
+        let missing = record_missing_hosts(&*storage, start, end, &seen, &[22, 80], 200).unwrap();
+        assert_eq!(missing, 1);
+
+        let history = query_port_history(&*storage, None, 150, 300).unwrap();
+        let gone = history
+            .iter()
+            .find(|d| d.address == "192.168.1.20")
+            .unwrap();
+        assert!(gone.open_ports.is_empty());
+        assert_eq!(gone.last_scanned_unix, 200);
+        assert_eq!(gone.changes.len(), 2);
+        assert!(gone
+            .changes
+            .iter()
+            .all(|c| c.change == PortChangeKind::Closed));
+
+        let outside = history.iter().find(|d| d.address == "10.0.0.5").unwrap();
+        assert_eq!(outside.open_ports, vec![22]);
+        assert!(outside.changes.is_empty());
+    }
+
+    #[test]
+    fn test_port_change_serialization() {
+        let change = detect_port_changes("10.0.0.5", 23, &[point(1, 0.0), point(2, 1.0)]);
+        let json = serde_json::to_string(&change[0]).unwrap();
+        assert!(json.contains("\"change\":\"opened\""));
+        assert!(json.contains("\"port\":23"));
+    }
+}
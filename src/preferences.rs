@@ -0,0 +1,189 @@
+//! Key-value store for UI preferences (default time range, selected targets,
+//! theme, ...), persisted as `<data path>/preferences.json` so settings are
+//! shared by every browser viewing the instance.
+//!
+//! Values are arbitrary JSON. Updates merge into the stored map; a `null`
+//! value removes its key.
+
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::warn;
+
+/// File (inside the data path) holding the preferences
+const PREFERENCES_FILE: &str = "preferences.json";
+
+/// Maximum number of stored keys
+pub const MAX_KEYS: usize = 256;
+/// Maximum length of a key in bytes
+pub const MAX_KEY_LEN: usize = 128;
+/// Maximum size of a single serialized value in bytes
+pub const MAX_VALUE_BYTES: usize = 64 * 1024;
+
+pub type Preferences = BTreeMap<String, Value>;
+
+#[derive(Debug)]
+pub enum PreferencesError {
+    /// The update was rejected by validation
+    Invalid(String),
+    /// The preferences file could not be written
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for PreferencesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PreferencesError::Invalid(e) => write!(f, "Invalid preferences: {}", e),
+            PreferencesError::Io(e) => write!(f, "Failed to save preferences: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PreferencesError {}
+
+/// Preferences loaded from and saved to the data path
+#[derive(Debug)]
+pub struct PreferencesStore {
+    path: PathBuf,
+    values: Mutex<Preferences>,
+}
+
+impl PreferencesStore {
+    /// Load the preferences stored under `data_path`
+    pub fn load(data_path: &Path) -> Self {
+        let path = data_path.join(PREFERENCES_FILE);
+        let values = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable {}: {}", path.display(), e);
+                Preferences::new()
+            }),
+            Err(_) => Preferences::new(),
+        };
+        Self {
+            path,
+            values: Mutex::new(values),
+        }
+    }
+
+    pub fn get(&self) -> Preferences {
+        self.values.lock().map(|v| v.clone()).unwrap_or_default()
+    }
+
+    /// Merge `update` into the stored preferences (`null` removes a key),
+    /// persist them, and return the result
+    pub fn update(&self, update: Preferences) -> Result<Preferences, PreferencesError> {
+        for (key, value) in &update {
+            validate_key(key)?;
+            let size = serde_json::to_vec(value).map(|v| v.len()).unwrap_or(0);
+            if size > MAX_VALUE_BYTES {
+                return Err(PreferencesError::Invalid(format!(
+                    "value of '{}' is {} bytes (max {})",
+                    key, size, MAX_VALUE_BYTES
+                )));
+            }
+        }
+
+        let mut values = self.values.lock().unwrap_or_else(|e| e.into_inner());
+        let mut merged = values.clone();
+        for (key, value) in update {
+            if value.is_null() {
+                merged.remove(&key);
+            } else {
+                merged.insert(key, value);
+            }
+        }
+        if merged.len() > MAX_KEYS {
+            return Err(PreferencesError::Invalid(format!(
+                "{} keys (max {})",
+                merged.len(),
+                MAX_KEYS
+            )));
+        }
+
+        self.save(&merged).map_err(PreferencesError::Io)?;
+        *values = merged.clone();
+        Ok(merged)
+    }
+
+    /// Write through a temporary file so a crash never leaves a truncated file
+    fn save(&self, values: &Preferences) -> std::io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(values)?)?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+fn validate_key(key: &str) -> Result<(), PreferencesError> {
+    let valid = !key.is_empty()
+        && key.len() <= MAX_KEY_LEN
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(PreferencesError::Invalid(format!(
+            "key '{}' must be 1-{} characters of [A-Za-z0-9_.-]",
+            key, MAX_KEY_LEN
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn temp_dir() -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("sparkping-preferences-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn prefs(value: Value) -> Preferences {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_update_merges_and_persists() {
+        let dir = temp_dir();
+        let store = PreferencesStore::load(&dir);
+        assert!(store.get().is_empty());
+
+        store
+            .update(prefs(json!({"theme": "dark", "time_range": "24h"})))
+            .unwrap();
+        let merged = store
+            .update(prefs(json!({"theme": null, "targets": ["router"]})))
+            .unwrap();
+        assert_eq!(
+            merged,
+            prefs(json!({"time_range": "24h", "targets": ["router"]}))
+        );
+
+        let reloaded = PreferencesStore::load(&dir);
+        assert_eq!(reloaded.get(), merged);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_update_rejects_invalid() {
+        let dir = temp_dir();
+        let store = PreferencesStore::load(&dir);
+        store.update(prefs(json!({"theme": "dark"}))).unwrap();
+
+        assert!(store.update(prefs(json!({"bad key": 1}))).is_err());
+        let big = "x".repeat(MAX_VALUE_BYTES + 1);
+        assert!(store.update(prefs(json!({ "big": big }))).is_err());
+
+        // Rejected updates leave the stored preferences untouched
+        assert_eq!(store.get(), prefs(json!({"theme": "dark"})));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
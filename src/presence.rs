@@ -0,0 +1,395 @@
+//! Layer-2 presence detection from the kernel neighbor (ARP/NDP) table.
+//!
+//! A background task polls `ip neigh` (falling back to `/proc/net/arp` for
+//! IPv4) and tracks devices by MAC address. A device that appears in the table
+//! is reported as arrived; one that has not been seen for `away_after`
+//! seconds is reported as departed. Transitions are stored as a `presence`
+//! series in tsink (1.0 = arrived, 0.0 = departed), which makes "is my phone
+//! home" signals possible without running full scans. Transitions are also
+//! broadcast to subscribers such as the MQTT publisher.
+
+use crate::storage::StorageBackend;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::process::Command;
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+use tracing::debug;
+use tsink::{DataPoint, Label, Row};
+use utoipa::ToSchema;
+
+/// Metric name for presence transitions
+pub const PRESENCE_METRIC: &str = "presence";
+
+/// Transitions buffered per subscriber before it starts skipping
+const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+/// An entry of the neighbor table with a resolved link-layer address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Neighbor {
+    pub address: String,
+    pub mac: String,
+    pub interface: String,
+}
+
+/// Parse the output of `ip neigh show`.
+///
+/// Entries without a link-layer address (INCOMPLETE, FAILED) are skipped.
+pub fn parse_ip_neigh(output: &str) -> Vec<Neighbor> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let address = fields.first()?;
+            let value_of = |key: &str| {
+                fields
+                    .iter()
+                    .position(|f| *f == key)
+                    .and_then(|i| fields.get(i + 1))
+            };
+            let state = fields.last()?;
+            if matches!(*state, "FAILED" | "INCOMPLETE") {
+                return None;
+            }
+            Some(Neighbor {
+                address: address.to_string(),
+                mac: value_of("lladdr")?.to_lowercase(),
+                interface: value_of("dev").map(|d| d.to_string()).unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+/// Parse `/proc/net/arp` (IPv4 only).
+///
+/// Incomplete entries (flags 0x0) are skipped.
+pub fn parse_proc_net_arp(content: &str) -> Vec<Neighbor> {
+    content
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 6 || fields[2] == "0x0" || fields[3] == "00:00:00:00:00:00" {
+                return None;
+            }
+            Some(Neighbor {
+                address: fields[0].to_string(),
+                mac: fields[3].to_lowercase(),
+                interface: fields[5].to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Read the current neighbor table
+pub fn read_neighbors() -> std::io::Result<Vec<Neighbor>> {
+    match Command::new("ip").args(["neigh", "show"]).output() {
+        Ok(output) if output.status.success() => {
+            Ok(parse_ip_neigh(&String::from_utf8_lossy(&output.stdout)))
+        }
+        _ => {
+            debug!("`ip neigh` unavailable, falling back to /proc/net/arp");
+            Ok(parse_proc_net_arp(&std::fs::read_to_string(
+                "/proc/net/arp",
+            )?))
+        }
+    }
+}
+
+/// Direction of a presence transition
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PresenceChange {
+    Arrived,
+    Departed,
+}
+
+/// A device appearing in or disappearing from the neighbor table
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PresenceEvent {
+    /// ISO 8601 formatted timestamp of the transition
+    pub timestamp: String,
+    /// Unix timestamp in seconds of the transition
+    pub timestamp_unix: i64,
+    pub mac: String,
+    /// Last IP address seen for the device
+    pub address: String,
+    pub change: PresenceChange,
+}
+
+impl PresenceEvent {
+    fn new(timestamp: i64, mac: &str, address: &str, change: PresenceChange) -> Self {
+        Self {
+            timestamp: DateTime::from_timestamp(timestamp, 0)
+                .unwrap_or_else(Utc::now)
+                .to_rfc3339(),
+            timestamp_unix: timestamp,
+            mac: mac.to_string(),
+            address: address.to_string(),
+            change,
+        }
+    }
+}
+
+/// Current state of a device seen in the neighbor table
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PresenceDevice {
+    pub mac: String,
+    /// Last IP address seen for the device (IPv4 preferred)
+    pub address: String,
+    pub interface: String,
+    pub present: bool,
+    /// Unix timestamp in seconds when the device was last in the table
+    pub last_seen_unix: i64,
+    /// Unix timestamp in seconds of the last arrival
+    pub since_unix: i64,
+}
+
+/// Tracks devices seen in the neighbor table since startup
+#[derive(Debug)]
+pub struct PresenceTracker {
+    devices: Mutex<HashMap<String, PresenceDevice>>,
+    events: broadcast::Sender<PresenceEvent>,
+}
+
+impl Default for PresenceTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PresenceTracker {
+    pub fn new() -> Self {
+        let (events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        Self {
+            devices: Mutex::new(HashMap::new()),
+            events,
+        }
+    }
+
+    /// Receive transitions as they are observed
+    pub fn subscribe(&self) -> broadcast::Receiver<PresenceEvent> {
+        self.events.subscribe()
+    }
+
+    /// Feed one poll of the neighbor table, returning the resulting transitions
+    pub fn observe(&self, neighbors: &[Neighbor], now: i64, away_after: u64) -> Vec<PresenceEvent> {
+        let mut devices = self.devices.lock().unwrap_or_else(|e| e.into_inner());
+        let mut events = Vec::new();
+
+        for neighbor in neighbors {
+            let device = devices
+                .entry(neighbor.mac.clone())
+                .or_insert_with(|| PresenceDevice {
+                    mac: neighbor.mac.clone(),
+                    address: neighbor.address.clone(),
+                    interface: neighbor.interface.clone(),
+                    present: false,
+                    last_seen_unix: now,
+                    since_unix: now,
+                });
+            // A device has several entries (IPv4 + IPv6); prefer showing IPv4
+            if !neighbor.address.contains(':') || device.address.contains(':') {
+                device.address = neighbor.address.clone();
+            }
+            device.interface = neighbor.interface.clone();
+            device.last_seen_unix = now;
+            if !device.present {
+                device.present = true;
+                device.since_unix = now;
+                events.push(PresenceEvent::new(
+                    now,
+                    &device.mac,
+                    &device.address,
+                    PresenceChange::Arrived,
+                ));
+            }
+        }
+
+        for device in devices.values_mut() {
+            if device.present && now - device.last_seen_unix >= away_after as i64 {
+                device.present = false;
+                events.push(PresenceEvent::new(
+                    now,
+                    &device.mac,
+                    &device.address,
+                    PresenceChange::Departed,
+                ));
+            }
+        }
+
+        if self.events.receiver_count() > 0 {
+            for event in &events {
+                // Only fails when the last subscriber went away in the meantime
+                let _ = self.events.send(event.clone());
+            }
+        }
+        events
+    }
+
+    /// All devices seen since startup, present ones first
+    pub fn devices(&self) -> Vec<PresenceDevice> {
+        let devices = self.devices.lock().unwrap_or_else(|e| e.into_inner());
+        let mut list: Vec<PresenceDevice> = devices.values().cloned().collect();
+        list.sort_by(|a, b| b.present.cmp(&a.present).then_with(|| a.mac.cmp(&b.mac)));
+        list
+    }
+}
+
+/// Store presence transitions in tsink
+pub fn record_presence_events(
+    storage: &dyn StorageBackend,
+    events: &[PresenceEvent],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let rows: Vec<Row> = events
+        .iter()
+        .map(|event| {
+            let labels = vec![
+                Label::new("mac", &event.mac),
+                Label::new("address", &event.address),
+            ];
+            let value = match event.change {
+                PresenceChange::Arrived => 1.0,
+                PresenceChange::Departed => 0.0,
+            };
+            Row::with_labels(
+                PRESENCE_METRIC,
+                labels,
+                DataPoint::new(event.timestamp_unix, value),
+            )
+        })
+        .collect();
+
+    if !rows.is_empty() {
+        storage.insert_rows(&rows)?;
+    }
+
+    Ok(())
+}
+
+/// Query stored presence transitions in `[from, to]`, oldest first.
+///
+/// `filter` matches the MAC or IP address.
+pub fn query_presence_events(
+    storage: &dyn StorageBackend,
+    filter: Option<&str>,
+    from: i64,
+    to: i64,
+) -> Result<Vec<PresenceEvent>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut events = Vec::new();
+
+    for (labels, points) in storage.select_all(PRESENCE_METRIC, from, to)? {
+        let label = |name: &str| {
+            labels
+                .iter()
+                .find(|l| l.name == name)
+                .map(|l| l.value.as_str())
+        };
+        let (Some(mac), Some(address)) = (label("mac"), label("address")) else {
+            continue;
+        };
+        if let Some(filter) = filter {
+            if !filter.eq_ignore_ascii_case(mac) && filter != address {
+                continue;
+            }
+        }
+        for point in points {
+            let change = if point.value > 0.5 {
+                PresenceChange::Arrived
+            } else {
+                PresenceChange::Departed
+            };
+            events.push(PresenceEvent::new(point.timestamp, mac, address, change));
+        }
+    }
+
+    events.sort_by(|a, b| {
+        a.timestamp_unix
+            .cmp(&b.timestamp_unix)
+            .then_with(|| a.mac.cmp(&b.mac))
+    });
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn neighbor(address: &str, mac: &str) -> Neighbor {
+        Neighbor {
+            address: address.to_string(),
+            mac: mac.to_string(),
+            interface: "eth0".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_ip_neigh() {
+        let output = "\
+192.168.1.1 dev eth0 lladdr AA:BB:CC:DD:EE:01 REACHABLE
+192.168.1.20 dev eth0 lladdr aa:bb:cc:dd:ee:02 STALE
+192.168.1.30 dev eth0  FAILED
+192.168.1.31 dev eth0 lladdr aa:bb:cc:dd:ee:03 INCOMPLETE
+fe80::1 dev eth0 lladdr aa:bb:cc:dd:ee:01 router REACHABLE
+";
+        let neighbors = parse_ip_neigh(output);
+        assert_eq!(
+            neighbors,
+            vec![
+                neighbor("192.168.1.1", "aa:bb:cc:dd:ee:01"),
+                neighbor("192.168.1.20", "aa:bb:cc:dd:ee:02"),
+                neighbor("fe80::1", "aa:bb:cc:dd:ee:01"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_proc_net_arp() {
+        let content = "\
+IP address       HW type     Flags       HW address            Mask     Device
+192.168.1.1      0x1         0x2         aa:bb:cc:dd:ee:01     *        eth0
+192.168.1.30     0x1         0x0         00:00:00:00:00:00     *        eth0
+";
+        assert_eq!(
+            parse_proc_net_arp(content),
+            vec![neighbor("192.168.1.1", "aa:bb:cc:dd:ee:01")]
+        );
+    }
+
+    #[test]
+    fn test_presence_transitions() {
+        let tracker = PresenceTracker::new();
+        let phone = neighbor("192.168.1.20", "aa:bb:cc:dd:ee:02");
+        let phone_v6 = neighbor("fe80::2", "aa:bb:cc:dd:ee:02");
+
+        let events = tracker.observe(&[phone_v6, phone.clone()], 100, 300);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].change, PresenceChange::Arrived);
+        assert_eq!(tracker.devices()[0].address, "192.168.1.20");
+
+        // Still within away_after
+        assert!(tracker.observe(&[], 300, 300).is_empty());
+
+        let events = tracker.observe(&[], 400, 300);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].change, PresenceChange::Departed);
+        assert!(!tracker.devices()[0].present);
+
+        let events = tracker.observe(&[phone], 500, 300);
+        assert_eq!(events[0].change, PresenceChange::Arrived);
+        assert_eq!(tracker.devices()[0].since_unix, 500);
+    }
+
+    #[test]
+    fn test_presence_events_are_broadcast() {
+        let tracker = PresenceTracker::new();
+        let mut rx = tracker.subscribe();
+
+        tracker.observe(&[neighbor("192.168.1.20", "aa:bb:cc:dd:ee:02")], 100, 300);
+        let event = rx.try_recv().unwrap();
+        assert_eq!(event.mac, "aa:bb:cc:dd:ee:02");
+        assert_eq!(event.change, PresenceChange::Arrived);
+        assert!(rx.try_recv().is_err());
+    }
+}
@@ -0,0 +1,107 @@
+//! Hostname resolution for ping targets.
+//!
+//! Targets may be IP addresses or hostnames. Hostnames are resolved by the
+//! ping task and cached for `[ping] dns_ttl` seconds, so DNS changes are
+//! picked up without restarting the task.
+
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// Resolves and caches the address of a single target
+#[derive(Debug)]
+pub struct HostResolver {
+    host: String,
+    ttl: Duration,
+    cached: Option<(IpAddr, Instant)>,
+}
+
+impl HostResolver {
+    pub fn new(host: &str, ttl_secs: u64) -> Self {
+        Self {
+            host: host.trim().to_string(),
+            ttl: Duration::from_secs(ttl_secs),
+            cached: None,
+        }
+    }
+
+    /// Current address of the target.
+    ///
+    /// IP addresses are returned as-is. Hostnames are re-resolved once the
+    /// cached address is older than the TTL; if re-resolution fails, the last
+    /// known address is kept.
+    pub async fn resolve(&mut self) -> Result<IpAddr, String> {
+        if let Ok(ip) = self.host.parse::<IpAddr>() {
+            return Ok(ip);
+        }
+
+        if let Some((ip, resolved_at)) = self.cached {
+            if resolved_at.elapsed() < self.ttl {
+                return Ok(ip);
+            }
+        }
+
+        match lookup(&self.host).await {
+            Ok(ip) => {
+                match self.cached {
+                    Some((previous, _)) if previous != ip => {
+                        info!("{} now resolves to {} (was {})", self.host, ip, previous)
+                    }
+                    None => info!("{} resolves to {}", self.host, ip),
+                    _ => {}
+                }
+                self.cached = Some((ip, Instant::now()));
+                Ok(ip)
+            }
+            Err(e) => match self.cached {
+                Some((ip, _)) => {
+                    warn!("Failed to re-resolve {}: {} (keeping {})", self.host, e, ip);
+                    // Retry on the next TTL expiry instead of every cycle
+                    self.cached = Some((ip, Instant::now()));
+                    Ok(ip)
+                }
+                None => Err(e),
+            },
+        }
+    }
+}
+
+/// Resolve a hostname, preferring IPv4 addresses
+async fn lookup(host: &str) -> Result<IpAddr, String> {
+    let addrs: Vec<IpAddr> = tokio::net::lookup_host((host, 0))
+        .await
+        .map_err(|e| format!("Failed to resolve {}: {}", host, e))?
+        .map(|addr| addr.ip())
+        .collect();
+
+    addrs
+        .iter()
+        .find(|ip| ip.is_ipv4())
+        .or_else(|| addrs.first())
+        .copied()
+        .ok_or_else(|| format!("No addresses found for {}", host))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_ip_addresses_are_not_resolved() {
+        let mut resolver = HostResolver::new("192.168.1.1", 300);
+        assert_eq!(
+            resolver.resolve().await.unwrap(),
+            "192.168.1.1".parse::<IpAddr>().unwrap()
+        );
+        assert!(resolver.cached.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_hostname_resolution_is_cached() {
+        let mut resolver = HostResolver::new("localhost", 300);
+        let ip = resolver.resolve().await.unwrap();
+        assert!(ip.is_loopback());
+        assert!(resolver.cached.is_some());
+        assert_eq!(resolver.resolve().await.unwrap(), ip);
+    }
+}
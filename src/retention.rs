@@ -0,0 +1,383 @@
+//! Per-target retention and pruning of expired ping data.
+//!
+//! tsink has no API to delete individual series, so pruning works on the
+//! partition directories directly. A series expires once its newest point is
+//! older than its target's `retention_days` (falling back to
+//! `[database] retention_days`, 0 = keep forever). Partitions where every
+//! series expired are removed; partitions with some expired series are
+//! rewritten without them. The rewritten partition is built next to the
+//! original and swapped in with two renames, so a crash leaves either the old
+//! or the new partition (leftovers are resolved on the next prune run).
+//!
+//! tsink keeps partitions it already loaded open, so pruned points can still
+//! show up in queries until the next restart; the disk space is freed then.
+
+use crate::api::ping::dto::MetricMetadata;
+use crate::api::ping::query::extract_target_id_from_metric_name;
+use crate::config::Target;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+/// Prefix of the directory a rewritten partition is built in
+const TEMP_PREFIX: &str = ".prune-tmp-";
+/// Prefix of the original partition while the rewritten one is swapped in
+const BACKUP_PREFIX: &str = ".prune-old-";
+
+/// Serializes the background task, manual prune requests and compaction
+pub(crate) static PRUNE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Result of a prune run
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct PruneReport {
+    /// Unix timestamp in seconds when the prune ran
+    pub ran_at: i64,
+    /// True if nothing was deleted, only counted
+    pub dry_run: bool,
+    /// Partitions deleted because every series in them expired
+    pub removed_partitions: usize,
+    /// Partitions rewritten without their expired series
+    pub rewritten_partitions: usize,
+    /// Expired data points per target ID
+    pub pruned_data_points: BTreeMap<String, u64>,
+    /// Encoded size of the expired series in bytes
+    pub freed_bytes: u64,
+    /// Partitions that could not be pruned
+    pub errors: Vec<String>,
+}
+
+/// Retention of a target in days: its own setting, else the default
+pub fn retention_days(target_id: &str, targets: &[Target], default_days: u32) -> u32 {
+    targets
+        .iter()
+        .find(|t| t.id == target_id)
+        .and_then(|t| t.retention_days)
+        .unwrap_or(default_days)
+}
+
+/// Remove expired series from all partitions under `data_path`.
+///
+/// With `dry_run` the expired data is only counted.
+pub fn prune_expired(
+    data_path: &Path,
+    targets: &[Target],
+    default_days: u32,
+    now: i64,
+    dry_run: bool,
+) -> std::io::Result<PruneReport> {
+    let _guard = PRUNE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut report = PruneReport {
+        ran_at: now,
+        dry_run,
+        ..Default::default()
+    };
+    if !data_path.exists() {
+        return Ok(report);
+    }
+    if !dry_run {
+        recover_interrupted(data_path)?;
+    }
+
+    let mut partitions: Vec<_> = fs::read_dir(data_path)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| {
+            p.is_dir()
+                && p.file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| n.starts_with("p-"))
+                && p.join("meta.json").exists()
+        })
+        .collect();
+    partitions.sort();
+
+    for partition in partitions {
+        let expired_before = |target_id: &str| {
+            let days = retention_days(target_id, targets, default_days);
+            (days > 0).then_some(now - days as i64 * 86400)
+        };
+        if let Err(e) = prune_partition(&partition, &expired_before, dry_run, &mut report) {
+            warn!("Failed to prune partition {}: {}", partition.display(), e);
+            report
+                .errors
+                .push(format!("{}: {}", partition.display(), e));
+        }
+    }
+
+    let pruned: u64 = report.pruned_data_points.values().sum();
+    if pruned > 0 {
+        info!(
+            "{} {} expired data points ({} partitions removed, {} rewritten)",
+            if dry_run { "Would prune" } else { "Pruned" },
+            pruned,
+            report.removed_partitions,
+            report.rewritten_partitions
+        );
+    }
+
+    Ok(report)
+}
+
+/// Prune one partition. `expired_before` returns the cutoff timestamp for a
+/// target ID (None = keep forever); series without a target ID use "".
+fn prune_partition(
+    partition: &Path,
+    expired_before: &dyn Fn(&str) -> Option<i64>,
+    dry_run: bool,
+    report: &mut PruneReport,
+) -> std::io::Result<()> {
+    let meta_content = fs::read_to_string(partition.join("meta.json"))?;
+    let mut meta: serde_json::Value = serde_json::from_str(&meta_content)?;
+    let Some(metrics) = meta.get("metrics").and_then(|m| m.as_object()) else {
+        return Ok(());
+    };
+
+    let mut kept = Vec::new();
+    let mut expired = Vec::new();
+    for (key, value) in metrics {
+        let metric: MetricMetadata = serde_json::from_value(value.clone())?;
+        let target_id = extract_target_id_from_metric_name(&metric.name).unwrap_or_default();
+        let is_expired =
+            expired_before(&target_id).is_some_and(|cutoff| metric.max_timestamp < cutoff);
+        if is_expired {
+            expired.push((target_id, metric));
+        } else {
+            kept.push((key.clone(), metric));
+        }
+    }
+
+    if expired.is_empty() {
+        return Ok(());
+    }
+
+    for (target_id, metric) in &expired {
+        *report
+            .pruned_data_points
+            .entry(target_id.clone())
+            .or_default() += metric.num_data_points;
+        report.freed_bytes += metric.encoded_size;
+    }
+
+    if kept.is_empty() {
+        if !dry_run {
+            fs::remove_dir_all(partition)?;
+        }
+        report.removed_partitions += 1;
+        return Ok(());
+    }
+
+    if !dry_run {
+        let data = fs::read(partition.join("data"))?;
+        let mut new_data = Vec::with_capacity(data.len());
+        kept.sort_by_key(|(_, m)| m.offset);
+        let mut new_metrics = serde_json::Map::new();
+        let mut data_points = 0;
+        for (key, metric) in &kept {
+            let start = metric.offset as usize;
+            let end = start + metric.encoded_size as usize;
+            let Some(chunk) = data.get(start..end) else {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("series {} points past the end of the data file", key),
+                ));
+            };
+            let mut value = meta["metrics"][key].clone();
+            value["offset"] = serde_json::json!(new_data.len());
+            new_metrics.insert(key.clone(), value);
+            new_data.extend_from_slice(chunk);
+            data_points += metric.num_data_points;
+        }
+        meta["metrics"] = serde_json::Value::Object(new_metrics);
+        meta["num_data_points"] = serde_json::json!(data_points);
+
+        swap_partition(partition, &new_data, &meta)?;
+    }
+    report.rewritten_partitions += 1;
+    Ok(())
+}
+
+/// Build the rewritten partition next to the original and swap it in
+pub(crate) fn swap_partition(
+    partition: &Path,
+    data: &[u8],
+    meta: &serde_json::Value,
+) -> std::io::Result<()> {
+    let parent = partition.parent().unwrap_or(Path::new("."));
+    let name = partition
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default();
+    let temp = parent.join(format!("{}{}", TEMP_PREFIX, name));
+    let backup = parent.join(format!("{}{}", BACKUP_PREFIX, name));
+
+    if temp.exists() {
+        fs::remove_dir_all(&temp)?;
+    }
+    fs::create_dir_all(&temp)?;
+    // Keep any other files tsink stores in the partition
+    for entry in fs::read_dir(partition)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        if entry.path().is_file() && file_name != "data" && file_name != "meta.json" {
+            fs::copy(entry.path(), temp.join(&file_name))?;
+        }
+    }
+    fs::write(temp.join("data"), data)?;
+    fs::write(temp.join("meta.json"), serde_json::to_vec(meta)?)?;
+
+    fs::rename(partition, &backup)?;
+    fs::rename(&temp, partition)?;
+    fs::remove_dir_all(&backup)
+}
+
+/// Resolve leftovers of a prune run that was interrupted mid-swap
+pub(crate) fn recover_interrupted(data_path: &Path) -> std::io::Result<()> {
+    for entry in fs::read_dir(data_path)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        if name.starts_with(TEMP_PREFIX) {
+            // The original partition is still in place (or in the backup)
+            fs::remove_dir_all(&path)?;
+        } else if let Some(original) = name.strip_prefix(BACKUP_PREFIX) {
+            let original = data_path.join(original);
+            if original.exists() {
+                fs::remove_dir_all(&path)?;
+            } else {
+                warn!(
+                    "Restoring partition {} from interrupted prune",
+                    original.display()
+                );
+                fs::rename(&path, &original)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "sparkping-retention-{}-{}",
+            name,
+            uuid::Uuid::new_v4()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Hex-encoded metric name as tsink stores it in meta.json
+    fn metric_name(target_id: &str) -> String {
+        let mut bytes = Vec::new();
+        for part in ["ping_latency", "target_id", target_id] {
+            bytes.extend_from_slice(&(part.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(part.as_bytes());
+        }
+        hex::encode(bytes)
+    }
+
+    /// Write a partition with one series per (target ID, payload)
+    fn write_partition(dir: &Path, max_timestamp: i64, series: &[(&str, &str)]) {
+        fs::create_dir_all(dir).unwrap();
+        let mut data = String::new();
+        let mut metrics = serde_json::Map::new();
+        for (target_id, payload) in series {
+            metrics.insert(
+                target_id.to_string(),
+                serde_json::json!({
+                    "name": metric_name(target_id),
+                    "offset": data.len(),
+                    "encoded_size": payload.len(),
+                    "min_timestamp": max_timestamp - 10,
+                    "max_timestamp": max_timestamp,
+                    "num_data_points": 2,
+                }),
+            );
+            data.push_str(payload);
+        }
+        let meta = serde_json::json!({
+            "min_timestamp": max_timestamp - 10,
+            "max_timestamp": max_timestamp,
+            "num_data_points": 2 * series.len(),
+            "metrics": metrics,
+        });
+        fs::write(dir.join("data"), data).unwrap();
+        fs::write(dir.join("meta.json"), meta.to_string()).unwrap();
+    }
+
+    fn target(id: &str, retention_days: Option<u32>) -> Target {
+        Target {
+            id: id.to_string(),
+            address: "192.168.1.1".to_string(),
+            ping_count: 1,
+            retention_days,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_prune_expired() {
+        let data = temp_dir("prune");
+        let day = 86400;
+        let now = 100 * day;
+        // Two days old: "fast" (1 day retention) expired, "slow" kept
+        write_partition(
+            &data.join("p-1"),
+            now - 2 * day,
+            &[("fast", "AAAA"), ("slow", "BB")],
+        );
+        // Only "fast" in an old partition: removed entirely
+        write_partition(&data.join("p-2"), now - 3 * day, &[("fast", "CCC")]);
+        // Recent partition is untouched
+        write_partition(&data.join("p-3"), now - 100, &[("fast", "DD")]);
+
+        let targets = vec![target("fast", Some(1)), target("slow", None)];
+
+        let report = prune_expired(&data, &targets, 30, now, true).unwrap();
+        assert_eq!(report.removed_partitions, 1);
+        assert_eq!(report.rewritten_partitions, 1);
+        assert!(data.join("p-2").exists());
+
+        let report = prune_expired(&data, &targets, 30, now, false).unwrap();
+        assert_eq!(report.pruned_data_points.get("fast"), Some(&4));
+        assert_eq!(report.freed_bytes, 7);
+        assert!(report.errors.is_empty());
+
+        assert!(!data.join("p-2").exists());
+        assert!(data.join("p-3").exists());
+        assert_eq!(fs::read_to_string(data.join("p-1/data")).unwrap(), "BB");
+        let meta: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(data.join("p-1/meta.json")).unwrap()).unwrap();
+        assert_eq!(meta["metrics"]["slow"]["offset"], 0);
+        assert_eq!(meta["num_data_points"], 2);
+        assert!(meta["metrics"].get("fast").is_none());
+
+        fs::remove_dir_all(&data).unwrap();
+    }
+
+    #[test]
+    fn test_recover_interrupted() {
+        let data = temp_dir("recover");
+        // Crash after moving the original aside
+        write_partition(&data.join(".prune-old-p-1"), 10, &[("a", "A")]);
+        fs::create_dir_all(data.join(".prune-tmp-p-1")).unwrap();
+
+        let report = prune_expired(&data, &[], 0, 1000, false).unwrap();
+        assert!(report.errors.is_empty());
+        assert!(data.join("p-1/meta.json").exists());
+        assert!(!data.join(".prune-old-p-1").exists());
+        assert!(!data.join(".prune-tmp-p-1").exists());
+
+        fs::remove_dir_all(&data).unwrap();
+    }
+}
@@ -0,0 +1,327 @@
+//! Reverse DNS (PTR) lookups for discovered devices.
+//!
+//! IP scan results only carry an address. A PTR query per found device, sent
+//! straight to a DNS server over UDP, gives them the hostname the network's
+//! DHCP/DNS server knows them by. Without a configured server the first
+//! nameserver in `/etc/resolv.conf` is asked.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tracing::debug;
+
+/// Default timeout for a single reverse lookup in milliseconds
+pub const DEFAULT_TIMEOUT_MS: u64 = 1000;
+
+const DNS_PORT: u16 = 53;
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+
+const TYPE_PTR: u16 = 12;
+const CLASS_IN: u16 = 1;
+const RCODE_NXDOMAIN: u8 = 3;
+
+/// Largest UDP DNS response accepted
+const MAX_RESPONSE_SIZE: usize = 512;
+
+/// Compression pointers followed per name before giving up
+const MAX_POINTER_JUMPS: usize = 16;
+
+/// Sends PTR queries to a single DNS server
+#[derive(Debug, Clone)]
+pub struct ReverseResolver {
+    server: SocketAddr,
+    timeout: Duration,
+}
+
+impl ReverseResolver {
+    /// Resolver for `server` ("ip" or "ip:port"), or the system nameserver
+    /// if none is given
+    pub fn new(server: Option<&str>, timeout: Duration) -> Result<Self, String> {
+        let server = match server.map(str::trim).filter(|s| !s.is_empty()) {
+            Some(server) => parse_server(server)?,
+            None => {
+                let content = std::fs::read_to_string(RESOLV_CONF)
+                    .map_err(|e| format!("Failed to read {}: {}", RESOLV_CONF, e))?;
+                let ip = parse_resolv_conf(&content)
+                    .ok_or_else(|| format!("No nameserver found in {}", RESOLV_CONF))?;
+                SocketAddr::new(ip, DNS_PORT)
+            }
+        };
+        Ok(Self { server, timeout })
+    }
+
+    /// Hostname of `ip` from its PTR record, without the trailing dot.
+    /// Failures are logged and reported as no hostname.
+    pub async fn lookup(&self, ip: IpAddr) -> Option<String> {
+        let query = query_ptr(self.server, ip, true);
+        match tokio::time::timeout(self.timeout, query).await {
+            Ok(Ok(hostname)) => hostname,
+            Ok(Err(e)) => {
+                debug!("Reverse lookup of {} failed: {}", ip, e);
+                None
+            }
+            Err(_) => {
+                debug!("Reverse lookup of {} timed out", ip);
+                None
+            }
+        }
+    }
+}
+
+/// Send a PTR query for `ip` to `server` and wait for the answer.
+///
+/// DNS servers are asked to recurse; LLMNR responders, which share the DNS
+/// message format, expect the flag to be clear.
+pub(crate) async fn query_ptr(
+    server: SocketAddr,
+    ip: IpAddr,
+    recursion_desired: bool,
+) -> Result<Option<String>, String> {
+    let bind: SocketAddr = if server.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(bind).await.map_err(|e| e.to_string())?;
+    socket.connect(server).await.map_err(|e| e.to_string())?;
+
+    let id = random_id();
+    socket
+        .send(&build_query(id, &ptr_name(ip), recursion_desired))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut buf = [0u8; MAX_RESPONSE_SIZE];
+    loop {
+        let len = socket.recv(&mut buf).await.map_err(|e| e.to_string())?;
+        // Ignore stray responses to earlier queries
+        if len >= 2 && u16::from_be_bytes([buf[0], buf[1]]) == id {
+            return parse_response(&buf[..len]);
+        }
+    }
+}
+
+/// Random transaction ID for a query
+pub(crate) fn random_id() -> u16 {
+    let random = uuid::Uuid::new_v4();
+    u16::from_be_bytes([random.as_bytes()[0], random.as_bytes()[1]])
+}
+
+/// Parse "ip", "ip:port" or "[ipv6]:port"
+fn parse_server(server: &str) -> Result<SocketAddr, String> {
+    server
+        .parse::<SocketAddr>()
+        .or_else(|_| {
+            server
+                .parse::<IpAddr>()
+                .map(|ip| SocketAddr::new(ip, DNS_PORT))
+        })
+        .map_err(|_| format!("Invalid DNS server: {}", server))
+}
+
+/// First usable nameserver in resolv.conf content
+fn parse_resolv_conf(content: &str) -> Option<IpAddr> {
+    content.lines().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        match parts.next() {
+            // Link-local addresses with a zone ("fe80::1%eth0") don't parse
+            // and are skipped
+            Some("nameserver") => parts.next()?.parse().ok(),
+            _ => None,
+        }
+    })
+}
+
+/// The name queried for the PTR record of an address
+fn ptr_name(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, c, d] = v4.octets();
+            format!("{}.{}.{}.{}.in-addr.arpa", d, c, b, a)
+        }
+        IpAddr::V6(v6) => {
+            let mut labels = Vec::with_capacity(33);
+            for byte in v6.octets().iter().rev() {
+                labels.push(format!("{:x}", byte & 0x0f));
+                labels.push(format!("{:x}", byte >> 4));
+            }
+            labels.push("ip6.arpa".to_string());
+            labels.join(".")
+        }
+    }
+}
+
+/// A PTR query for `name`
+fn build_query(id: u16, name: &str, recursion_desired: bool) -> Vec<u8> {
+    let mut query = Vec::with_capacity(12 + name.len() + 6);
+    query.extend_from_slice(&id.to_be_bytes());
+    query.extend_from_slice(&[u8::from(recursion_desired), 0x00]);
+    query.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]); // one question
+    for label in name.split('.') {
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    query.extend_from_slice(&TYPE_PTR.to_be_bytes());
+    query.extend_from_slice(&CLASS_IN.to_be_bytes());
+    query
+}
+
+/// Hostname from the first PTR answer of a response; `None` if the address
+/// has no PTR record
+fn parse_response(response: &[u8]) -> Result<Option<String>, String> {
+    let malformed = || "Malformed DNS response".to_string();
+    if response.len() < 12 || response[2] & 0x80 == 0 {
+        return Err(malformed());
+    }
+
+    match response[3] & 0x0f {
+        0 => {}
+        RCODE_NXDOMAIN => return Ok(None),
+        rcode => return Err(format!("DNS server returned error code {}", rcode)),
+    }
+
+    let questions = u16::from_be_bytes([response[4], response[5]]);
+    let answers = u16::from_be_bytes([response[6], response[7]]);
+
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = read_name(response, pos).ok_or_else(malformed)?.1 + 4;
+    }
+
+    for _ in 0..answers {
+        pos = read_name(response, pos).ok_or_else(malformed)?.1;
+        let header = response.get(pos..pos + 10).ok_or_else(malformed)?;
+        let record_type = u16::from_be_bytes([header[0], header[1]]);
+        let data_len = u16::from_be_bytes([header[8], header[9]]) as usize;
+        pos += 10;
+        if pos + data_len > response.len() {
+            return Err(malformed());
+        }
+
+        if record_type == TYPE_PTR {
+            let (name, _) = read_name(response, pos).ok_or_else(malformed)?;
+            return Ok(Some(name).filter(|n| !n.is_empty()));
+        }
+        pos += data_len;
+    }
+
+    Ok(None)
+}
+
+/// Read a (possibly compressed) name at `pos`, returning it and the position
+/// after it
+fn read_name(message: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut end = None;
+    let mut jumps = 0;
+
+    loop {
+        let len = *message.get(pos)? as usize;
+        match len {
+            0 => {
+                let end = end.unwrap_or(pos + 1);
+                return Some((labels.join("."), end));
+            }
+            l if l & 0xc0 == 0xc0 => {
+                jumps += 1;
+                if jumps > MAX_POINTER_JUMPS {
+                    return None;
+                }
+                let target = ((l & 0x3f) << 8) | *message.get(pos + 1)? as usize;
+                end.get_or_insert(pos + 2);
+                pos = target;
+            }
+            l if l & 0xc0 == 0 => {
+                let label = message.get(pos + 1..pos + 1 + l)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                pos += 1 + l;
+            }
+            _ => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Response to a PTR query for 10.1.168.192.in-addr.arpa, answering
+    /// "nas.lan" with the owner name compressed to the question
+    fn sample_response(id: u16) -> Vec<u8> {
+        let mut response = build_query(id, "10.1.168.192.in-addr.arpa", true);
+        response[2] = 0x81; // response, recursion desired
+        response[3] = 0x80; // recursion available, no error
+        response[7] = 1; // one answer
+        response.extend_from_slice(&[0xc0, 12]); // name: pointer to question
+        response.extend_from_slice(&TYPE_PTR.to_be_bytes());
+        response.extend_from_slice(&CLASS_IN.to_be_bytes());
+        response.extend_from_slice(&3600u32.to_be_bytes());
+        response.extend_from_slice(&9u16.to_be_bytes());
+        response.extend_from_slice(b"\x03nas\x03lan\x00");
+        response
+    }
+
+    #[test]
+    fn test_ptr_name() {
+        assert_eq!(
+            ptr_name("192.168.1.10".parse().unwrap()),
+            "10.1.168.192.in-addr.arpa"
+        );
+        assert_eq!(
+            ptr_name("2001:db8::1".parse().unwrap()),
+            "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa"
+        );
+    }
+
+    #[test]
+    fn test_parse_response() {
+        assert_eq!(
+            parse_response(&sample_response(0x1234)),
+            Ok(Some("nas.lan".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_response_nxdomain() {
+        let mut response = build_query(1, "10.1.168.192.in-addr.arpa", true);
+        response[2] = 0x81;
+        response[3] = 0x83;
+        assert_eq!(parse_response(&response), Ok(None));
+    }
+
+    #[test]
+    fn test_parse_response_truncated() {
+        let response = sample_response(1);
+        assert!(parse_response(&response[..response.len() - 4]).is_err());
+    }
+
+    #[test]
+    fn test_read_name_pointer_loop() {
+        let message = [0u8, 0, 0xc0, 2];
+        assert_eq!(read_name(&message, 2), None);
+    }
+
+    #[test]
+    fn test_parse_resolv_conf() {
+        let content = "# generated\nsearch lan\nnameserver fe80::1%eth0\nnameserver 192.168.1.1\n";
+        assert_eq!(
+            parse_resolv_conf(content),
+            Some("192.168.1.1".parse().unwrap())
+        );
+        assert_eq!(parse_resolv_conf("search lan\n"), None);
+    }
+
+    #[test]
+    fn test_parse_server() {
+        assert_eq!(
+            parse_server("192.168.1.1").unwrap(),
+            "192.168.1.1:53".parse().unwrap()
+        );
+        assert_eq!(
+            parse_server("[::1]:5353").unwrap(),
+            "[::1]:5353".parse().unwrap()
+        );
+        assert!(parse_server("router.lan").is_err());
+    }
+}
@@ -0,0 +1,340 @@
+//! In-memory rolling-window aggregation of live ping results.
+//!
+//! Ping tasks feed every result into a shared `RollingAggregator`, which keeps
+//! per-target 10-second slots for the last hour. Status, alert, and dashboard
+//! consumers read 1m/5m/1h rollups from it instead of re-aggregating tsink data.
+
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::RwLock;
+use utoipa::ToSchema;
+
+/// Granularity of the rolling slots in seconds
+const SLOT_SECONDS: i64 = 10;
+/// Longest window that is kept in memory
+pub const MAX_WINDOW_SECONDS: i64 = 3600;
+
+/// Aggregated counters for one slot
+#[derive(Debug, Clone)]
+struct Slot {
+    start: i64,
+    successful_count: usize,
+    failed_count: usize,
+    latency_sum: f64,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+impl Slot {
+    fn new(start: i64) -> Self {
+        Self {
+            start,
+            successful_count: 0,
+            failed_count: 0,
+            latency_sum: 0.0,
+            min: None,
+            max: None,
+        }
+    }
+
+    fn add(&mut self, latency_ms: Option<f64>) {
+        match latency_ms {
+            Some(value) => {
+                self.successful_count += 1;
+                self.latency_sum += value;
+                self.min = Some(self.min.map_or(value, |m| m.min(value)));
+                self.max = Some(self.max.map_or(value, |m| m.max(value)));
+            }
+            None => self.failed_count += 1,
+        }
+    }
+}
+
+/// Statistics for a single rolling window
+#[derive(Debug, Clone, Serialize, PartialEq, ToSchema)]
+pub struct WindowStats {
+    /// Window length in seconds
+    pub window_seconds: i64,
+    /// Total number of pings in the window
+    pub count: usize,
+    /// Number of successful pings in the window
+    pub successful_count: usize,
+    /// Number of failed pings in the window
+    pub failed_count: usize,
+    /// Packet loss as a percentage (0-100), None if there were no pings
+    pub loss_percent: Option<f64>,
+    /// Average latency in milliseconds (only for successful pings)
+    pub avg_latency_ms: Option<f64>,
+    /// Minimum latency in milliseconds (only for successful pings)
+    pub min_latency_ms: Option<f64>,
+    /// Maximum latency in milliseconds (only for successful pings)
+    pub max_latency_ms: Option<f64>,
+}
+
+/// 1m/5m/1h rollups for a single target
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TargetRollups {
+    /// Unix timestamp in seconds of the most recent ping result
+    pub last_sample_unix: Option<i64>,
+    /// Most recent ping result: true if successful
+    pub last_success: Option<bool>,
+    /// Latency of the most recent successful ping in milliseconds
+    pub last_latency_ms: Option<f64>,
+    /// Successful pings since startup (monotonic)
+    pub total_successful_count: u64,
+    /// Failed pings since startup (monotonic)
+    pub total_failed_count: u64,
+    /// Failed pings since the most recent successful ping
+    pub consecutive_failed_count: u64,
+    pub one_minute: WindowStats,
+    pub five_minutes: WindowStats,
+    pub one_hour: WindowStats,
+}
+
+/// Rolling slots for a single target
+#[derive(Debug, Default)]
+struct TargetWindow {
+    slots: VecDeque<Slot>,
+    last_sample_unix: Option<i64>,
+    last_success: Option<bool>,
+    last_latency_ms: Option<f64>,
+    total_successful_count: u64,
+    total_failed_count: u64,
+    consecutive_failed_count: u64,
+}
+
+impl TargetWindow {
+    fn record(&mut self, timestamp: i64, latency_ms: Option<f64>) {
+        let slot_start = timestamp - timestamp.rem_euclid(SLOT_SECONDS);
+
+        match self.slots.back_mut() {
+            Some(slot) if slot.start == slot_start => slot.add(latency_ms),
+            Some(slot) if slot.start > slot_start => {
+                // Late result (e.g. a slow timeout) - fold into the matching older slot
+                if let Some(slot) = self.slots.iter_mut().rev().find(|s| s.start == slot_start) {
+                    slot.add(latency_ms);
+                }
+            }
+            _ => {
+                let mut slot = Slot::new(slot_start);
+                slot.add(latency_ms);
+                self.slots.push_back(slot);
+            }
+        }
+
+        if self.last_sample_unix.is_none_or(|last| timestamp >= last) {
+            self.last_sample_unix = Some(timestamp);
+            self.last_success = Some(latency_ms.is_some());
+            if latency_ms.is_some() {
+                self.last_latency_ms = latency_ms;
+                self.consecutive_failed_count = 0;
+            } else {
+                self.consecutive_failed_count += 1;
+            }
+        }
+
+        match latency_ms {
+            Some(_) => self.total_successful_count += 1,
+            None => self.total_failed_count += 1,
+        }
+
+        self.evict(timestamp);
+    }
+
+    fn evict(&mut self, now: i64) {
+        while let Some(front) = self.slots.front() {
+            if front.start + SLOT_SECONDS <= now - MAX_WINDOW_SECONDS {
+                self.slots.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn window(&self, now: i64, window_seconds: i64) -> WindowStats {
+        let cutoff = now - window_seconds;
+        let mut successful_count = 0;
+        let mut failed_count = 0;
+        let mut latency_sum = 0.0;
+        let mut min: Option<f64> = None;
+        let mut max: Option<f64> = None;
+
+        for slot in self.slots.iter().rev() {
+            if slot.start + SLOT_SECONDS <= cutoff {
+                break;
+            }
+            successful_count += slot.successful_count;
+            failed_count += slot.failed_count;
+            latency_sum += slot.latency_sum;
+            if let Some(m) = slot.min {
+                min = Some(min.map_or(m, |cur| cur.min(m)));
+            }
+            if let Some(m) = slot.max {
+                max = Some(max.map_or(m, |cur| cur.max(m)));
+            }
+        }
+
+        let count = successful_count + failed_count;
+        WindowStats {
+            window_seconds,
+            count,
+            successful_count,
+            failed_count,
+            loss_percent: if count > 0 {
+                Some(failed_count as f64 / count as f64 * 100.0)
+            } else {
+                None
+            },
+            avg_latency_ms: if successful_count > 0 {
+                Some(latency_sum / successful_count as f64)
+            } else {
+                None
+            },
+            min_latency_ms: min,
+            max_latency_ms: max,
+        }
+    }
+}
+
+/// Shared per-target rolling aggregator, updated from the live write path
+#[derive(Debug, Default)]
+pub struct RollingAggregator {
+    targets: RwLock<HashMap<String, TargetWindow>>,
+}
+
+impl RollingAggregator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a ping result. `latency_ms` is None for failed pings.
+    pub fn record(&self, target_id: &str, timestamp: i64, latency_ms: Option<f64>) {
+        if let Ok(mut targets) = self.targets.write() {
+            targets
+                .entry(target_id.to_string())
+                .or_default()
+                .record(timestamp, latency_ms);
+        }
+    }
+
+    /// Get 1m/5m/1h rollups for a target as of `now`
+    pub fn rollups(&self, target_id: &str, now: i64) -> Option<TargetRollups> {
+        let targets = self.targets.read().ok()?;
+        let window = targets.get(target_id)?;
+        Some(TargetRollups {
+            last_sample_unix: window.last_sample_unix,
+            last_success: window.last_success,
+            last_latency_ms: window.last_latency_ms,
+            total_successful_count: window.total_successful_count,
+            total_failed_count: window.total_failed_count,
+            consecutive_failed_count: window.consecutive_failed_count,
+            one_minute: window.window(now, 60),
+            five_minutes: window.window(now, 300),
+            one_hour: window.window(now, MAX_WINDOW_SECONDS),
+        })
+    }
+
+    /// Statistics of the last `window_seconds` for a target as of `now`.
+    /// Windows longer than `MAX_WINDOW_SECONDS` only cover the last hour.
+    pub fn window(&self, target_id: &str, now: i64, window_seconds: i64) -> Option<WindowStats> {
+        let targets = self.targets.read().ok()?;
+        let window = targets.get(target_id)?;
+        Some(window.window(now, window_seconds.min(MAX_WINDOW_SECONDS)))
+    }
+
+    /// Drop all rolling data for a target (e.g. when it is deleted)
+    pub fn remove(&self, target_id: &str) {
+        if let Ok(mut targets) = self.targets.write() {
+            targets.remove(target_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rollup_windows() {
+        let aggregator = RollingAggregator::new();
+        let now = 10_000;
+
+        // Old sample: only inside the 1h window
+        aggregator.record("t1", now - 1800, Some(100.0));
+        // Inside the 5m window
+        aggregator.record("t1", now - 200, Some(20.0));
+        // Inside the 1m window
+        aggregator.record("t1", now - 5, Some(10.0));
+        aggregator.record("t1", now - 5, None);
+
+        let rollups = aggregator.rollups("t1", now).unwrap();
+        assert_eq!(rollups.one_minute.count, 2);
+        assert_eq!(rollups.one_minute.loss_percent, Some(50.0));
+        assert_eq!(rollups.one_minute.avg_latency_ms, Some(10.0));
+
+        assert_eq!(rollups.five_minutes.count, 3);
+        assert_eq!(rollups.five_minutes.avg_latency_ms, Some(15.0));
+
+        assert_eq!(rollups.one_hour.count, 4);
+        assert_eq!(rollups.one_hour.max_latency_ms, Some(100.0));
+        assert_eq!(rollups.one_hour.min_latency_ms, Some(10.0));
+
+        assert_eq!(rollups.last_sample_unix, Some(now - 5));
+        assert_eq!(rollups.last_success, Some(false));
+        assert_eq!(rollups.last_latency_ms, Some(10.0));
+        assert_eq!(rollups.total_successful_count, 3);
+        assert_eq!(rollups.total_failed_count, 1);
+        assert_eq!(rollups.consecutive_failed_count, 1);
+
+        // A successful ping resets the consecutive failure count
+        aggregator.record("t1", now - 1, Some(12.0));
+        let rollups = aggregator.rollups("t1", now).unwrap();
+        assert_eq!(rollups.consecutive_failed_count, 0);
+    }
+
+    #[test]
+    fn test_rollup_eviction() {
+        let aggregator = RollingAggregator::new();
+        aggregator.record("t1", 0, Some(1.0));
+        aggregator.record("t1", 2 * MAX_WINDOW_SECONDS, Some(2.0));
+
+        let rollups = aggregator.rollups("t1", 2 * MAX_WINDOW_SECONDS).unwrap();
+        assert_eq!(rollups.one_hour.count, 1);
+        assert_eq!(rollups.one_hour.avg_latency_ms, Some(2.0));
+        // Lifetime counters are not affected by eviction
+        assert_eq!(rollups.total_successful_count, 2);
+    }
+
+    #[test]
+    fn test_rollup_empty_window() {
+        let aggregator = RollingAggregator::new();
+        aggregator.record("t1", 0, Some(1.0));
+
+        let rollups = aggregator.rollups("t1", 1000).unwrap();
+        assert_eq!(rollups.one_minute.count, 0);
+        assert_eq!(rollups.one_minute.loss_percent, None);
+        assert!(aggregator.rollups("missing", 1000).is_none());
+    }
+
+    #[test]
+    fn test_rollup_custom_window() {
+        let aggregator = RollingAggregator::new();
+        aggregator.record("t1", 1000, Some(10.0));
+        aggregator.record("t1", 1500, None);
+
+        let stats = aggregator.window("t1", 1600, 120).unwrap();
+        assert_eq!((stats.count, stats.failed_count), (1, 1));
+        let stats = aggregator.window("t1", 1600, 900).unwrap();
+        assert_eq!(stats.count, 2);
+        assert_eq!(stats.window_seconds, 900);
+        assert!(aggregator.window("missing", 1600, 120).is_none());
+    }
+
+    #[test]
+    fn test_rollup_remove() {
+        let aggregator = RollingAggregator::new();
+        aggregator.record("t1", 0, Some(1.0));
+        aggregator.remove("t1");
+        assert!(aggregator.rollups("t1", 0).is_none());
+    }
+}
@@ -0,0 +1,141 @@
+//! Coordinated graceful shutdown.
+//!
+//! On SIGTERM/Ctrl+C `main` requests shutdown: the HTTP server stops
+//! accepting connections, and ping tasks finish their current batch (whose
+//! results are written) instead of starting the next one. Tasks hold a
+//! `WorkGuard` while they run, so `main` can wait until they are idle, up to
+//! `[server] shutdown_timeout`, before closing storage.
+
+use std::sync::OnceLock;
+use tokio::sync::watch;
+
+/// Shutdown request and count of running tracked tasks
+pub struct Shutdown {
+    requested: watch::Sender<bool>,
+    active: watch::Sender<usize>,
+}
+
+/// Counts a task as running until dropped (also when the task is aborted)
+pub struct WorkGuard {
+    shutdown: &'static Shutdown,
+}
+
+impl Drop for WorkGuard {
+    fn drop(&mut self) {
+        self.shutdown.active.send_modify(|active| *active -= 1);
+    }
+}
+
+/// The process-wide shutdown state
+pub fn shutdown() -> &'static Shutdown {
+    static SHUTDOWN: OnceLock<Shutdown> = OnceLock::new();
+    SHUTDOWN.get_or_init(Shutdown::new)
+}
+
+impl Shutdown {
+    fn new() -> Self {
+        Self {
+            requested: watch::channel(false).0,
+            active: watch::channel(0).0,
+        }
+    }
+
+    /// Ask tracked tasks to stop after their current work
+    pub fn request(&self) {
+        self.requested.send_replace(true);
+    }
+
+    pub fn is_requested(&self) -> bool {
+        *self.requested.borrow()
+    }
+
+    /// Completes once shutdown is requested (immediately if it already was)
+    pub async fn requested(&self) {
+        let mut requested = self.requested.subscribe();
+        // The sender lives as long as `self`, so this cannot fail
+        let _ = requested.wait_for(|&requested| requested).await;
+    }
+
+    /// Track a running task until the returned guard is dropped
+    pub fn track(&'static self) -> WorkGuard {
+        self.active.send_modify(|active| *active += 1);
+        WorkGuard { shutdown: self }
+    }
+
+    /// Number of tracked tasks still running
+    pub fn active(&self) -> usize {
+        *self.active.borrow()
+    }
+
+    /// Completes once no tracked task is running
+    pub async fn idle(&self) {
+        let mut active = self.active.subscribe();
+        let _ = active.wait_for(|&active| active == 0).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn leaked() -> &'static Shutdown {
+        Box::leak(Box::new(Shutdown::new()))
+    }
+
+    #[tokio::test]
+    async fn test_request_wakes_waiters() {
+        let shutdown = leaked();
+        assert!(!shutdown.is_requested());
+
+        let waiter = tokio::spawn(shutdown.requested());
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+
+        shutdown.request();
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(shutdown.is_requested());
+        // Waiting after the request completes immediately
+        shutdown.requested().await;
+    }
+
+    #[tokio::test]
+    async fn test_idle_after_guards_drop() {
+        let shutdown = leaked();
+        shutdown.idle().await;
+
+        let first = shutdown.track();
+        let second = shutdown.track();
+        assert_eq!(shutdown.active(), 2);
+
+        let idle = tokio::spawn(shutdown.idle());
+        drop(first);
+        tokio::task::yield_now().await;
+        assert!(!idle.is_finished());
+
+        drop(second);
+        tokio::time::timeout(Duration::from_secs(1), idle)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(shutdown.active(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_aborted_task_releases_guard() {
+        let shutdown = leaked();
+        let task = tokio::spawn(async move {
+            let _guard = shutdown.track();
+            std::future::pending::<()>().await;
+        });
+        tokio::task::yield_now().await;
+        assert_eq!(shutdown.active(), 1);
+
+        task.abort();
+        let _ = task.await;
+        assert_eq!(shutdown.active(), 0);
+    }
+}
@@ -0,0 +1,292 @@
+//! Startup audit for leftovers of crashed runs.
+//!
+//! A crash can leave behind:
+//! - `config.tmp` from an interrupted atomic config write
+//! - partition directories without `meta.json` (crash during a tsink flush,
+//!   ignored by tsink but still using disk space)
+//! - partition directories with a truncated `meta.json`, which make tsink
+//!   fail to open the storage
+//! - empty partition directories
+//!
+//! Empty leftovers are removed. Anything that may still hold data is moved to
+//! `<data path>/quarantine/` instead of being deleted. The result is logged
+//! and exposed at `GET /api/diagnostics`.
+
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Directory (inside the data path) that receives quarantined partitions
+pub const QUARANTINE_DIR: &str = "quarantine";
+
+/// Kind of leftover found by the audit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LeftoverKind {
+    StaleConfigTemp,
+    IncompletePartition,
+    CorruptPartitionMeta,
+    EmptyPartition,
+}
+
+/// What the audit did with a leftover
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum CleanupAction {
+    Removed,
+    /// Moved aside because it may still hold useful data
+    MovedTo {
+        path: String,
+    },
+}
+
+/// A leftover found and cleaned up by the audit
+#[derive(Debug, Clone, Serialize)]
+pub struct CleanupItem {
+    pub kind: LeftoverKind,
+    pub path: String,
+    #[serde(flatten)]
+    pub action: CleanupAction,
+}
+
+/// Result of the startup audit
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StartupAudit {
+    /// Unix timestamp in seconds when the audit ran
+    pub ran_at: i64,
+    pub cleaned: Vec<CleanupItem>,
+    /// Leftovers that could not be cleaned up
+    pub errors: Vec<String>,
+}
+
+impl StartupAudit {
+    fn record(&mut self, kind: LeftoverKind, path: &Path, result: std::io::Result<CleanupAction>) {
+        match result {
+            Ok(action) => {
+                match action {
+                    CleanupAction::Removed => {
+                        info!("Startup audit: removed {:?} {}", kind, path.display())
+                    }
+                    CleanupAction::MovedTo { path: ref to } => warn!(
+                        "Startup audit: moved {:?} {} to {}",
+                        kind,
+                        path.display(),
+                        to
+                    ),
+                }
+                self.cleaned.push(CleanupItem {
+                    kind,
+                    path: path.display().to_string(),
+                    action,
+                });
+            }
+            Err(e) => {
+                let message = format!("Failed to clean up {}: {}", path.display(), e);
+                warn!("Startup audit: {}", message);
+                self.errors.push(message);
+            }
+        }
+    }
+}
+
+/// Audit the config file location and data directory, cleaning up leftovers
+pub fn run_startup_audit(config_file: &Path, data_path: &Path) -> StartupAudit {
+    let mut audit = StartupAudit {
+        ran_at: chrono::Utc::now().timestamp(),
+        ..Default::default()
+    };
+
+    audit_config_temp(config_file, &mut audit);
+    audit_partitions(data_path, &mut audit);
+
+    if audit.cleaned.is_empty() && audit.errors.is_empty() {
+        info!("Startup audit: no leftovers from previous runs");
+    }
+    audit
+}
+
+/// `config.tmp` is written before being renamed over the config file, so a
+/// leftover means the process died mid-write. The config file itself is
+/// intact; the temp file is kept as a backup if its content differs.
+fn audit_config_temp(config_file: &Path, audit: &mut StartupAudit) {
+    let temp_path = config_file.with_extension("tmp");
+    if !temp_path.is_file() {
+        return;
+    }
+
+    let result = (|| {
+        let temp_content = fs::read(&temp_path)?;
+        let config_content = fs::read(config_file).unwrap_or_default();
+        if temp_content.is_empty() || temp_content == config_content {
+            fs::remove_file(&temp_path)?;
+            return Ok(CleanupAction::Removed);
+        }
+        let backup = config_file.with_extension("tmp.bak");
+        fs::rename(&temp_path, &backup)?;
+        Ok(CleanupAction::MovedTo {
+            path: backup.display().to_string(),
+        })
+    })();
+    audit.record(LeftoverKind::StaleConfigTemp, &temp_path, result);
+}
+
+fn audit_partitions(data_path: &Path, audit: &mut StartupAudit) {
+    let entries = match fs::read_dir(data_path) {
+        Ok(entries) => entries,
+        // Data directory is created by tsink on first start
+        Err(_) => return,
+    };
+
+    let mut partitions: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| {
+            p.is_dir()
+                && p.file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| n.starts_with("p-"))
+        })
+        .collect();
+    partitions.sort();
+
+    for partition in partitions {
+        let Some(kind) = classify_partition(&partition) else {
+            continue;
+        };
+        let result = match kind {
+            LeftoverKind::EmptyPartition => {
+                fs::remove_dir(&partition).map(|_| CleanupAction::Removed)
+            }
+            _ => quarantine(data_path, &partition),
+        };
+        audit.record(kind, &partition, result);
+    }
+}
+
+/// Classify a partition directory, None if it is valid
+fn classify_partition(partition: &Path) -> Option<LeftoverKind> {
+    let is_empty = fs::read_dir(partition)
+        .map(|mut entries| entries.next().is_none())
+        .unwrap_or(false);
+    if is_empty {
+        return Some(LeftoverKind::EmptyPartition);
+    }
+
+    let meta_path = partition.join("meta.json");
+    if !meta_path.is_file() {
+        return Some(LeftoverKind::IncompletePartition);
+    }
+
+    let valid_meta = fs::read_to_string(&meta_path)
+        .ok()
+        .is_some_and(|content| serde_json::from_str::<serde_json::Value>(&content).is_ok());
+    if !valid_meta {
+        return Some(LeftoverKind::CorruptPartitionMeta);
+    }
+
+    None
+}
+
+/// Move a partition directory into the quarantine directory
+pub(crate) fn quarantine(data_path: &Path, partition: &Path) -> std::io::Result<CleanupAction> {
+    let quarantine_dir = data_path.join(QUARANTINE_DIR);
+    fs::create_dir_all(&quarantine_dir)?;
+
+    let name = partition
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut target = quarantine_dir.join(&name);
+    let mut suffix = 1;
+    while target.exists() {
+        target = quarantine_dir.join(format!("{}.{}", name, suffix));
+        suffix += 1;
+    }
+
+    fs::rename(partition, &target)?;
+    Ok(CleanupAction::MovedTo {
+        path: target.display().to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("sparkping-audit-{}-{}", name, uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_partition_audit() {
+        let data = temp_dir("partitions");
+        let valid = data.join("p-1-2");
+        fs::create_dir_all(&valid).unwrap();
+        fs::write(valid.join("meta.json"), "{}").unwrap();
+        fs::write(valid.join("data"), "x").unwrap();
+
+        let incomplete = data.join("p-3-4");
+        fs::create_dir_all(&incomplete).unwrap();
+        fs::write(incomplete.join("data"), "x").unwrap();
+
+        let corrupt = data.join("p-5-6");
+        fs::create_dir_all(&corrupt).unwrap();
+        fs::write(corrupt.join("meta.json"), "{\"metrics\": {").unwrap();
+
+        fs::create_dir_all(data.join("p-7-8")).unwrap();
+
+        let audit = run_startup_audit(&data.join("config.toml"), &data);
+        let kinds: Vec<_> = audit.cleaned.iter().map(|i| i.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                LeftoverKind::IncompletePartition,
+                LeftoverKind::CorruptPartitionMeta,
+                LeftoverKind::EmptyPartition
+            ]
+        );
+        assert!(audit.errors.is_empty());
+
+        assert!(valid.exists());
+        assert!(!incomplete.exists());
+        assert!(data
+            .join(QUARANTINE_DIR)
+            .join("p-3-4")
+            .join("data")
+            .exists());
+        assert!(!data.join("p-7-8").exists());
+
+        fs::remove_dir_all(&data).unwrap();
+    }
+
+    #[test]
+    fn test_config_temp_audit() {
+        let dir = temp_dir("config");
+        let config = dir.join("config.toml");
+        fs::write(&config, "a = 1").unwrap();
+
+        // Identical temp file is removed
+        fs::write(dir.join("config.tmp"), "a = 1").unwrap();
+        let audit = run_startup_audit(&config, &dir.join("data"));
+        assert_eq!(audit.cleaned[0].action, CleanupAction::Removed);
+        assert!(!dir.join("config.tmp").exists());
+
+        // Differing temp file is kept as a backup
+        fs::write(dir.join("config.tmp"), "a = 2").unwrap();
+        let audit = run_startup_audit(&config, &dir.join("data"));
+        assert!(matches!(
+            audit.cleaned[0].action,
+            CleanupAction::MovedTo { .. }
+        ));
+        assert_eq!(
+            fs::read_to_string(dir.join("config.tmp.bak")).unwrap(),
+            "a = 2"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,260 @@
+//! Storage backend abstraction.
+//!
+//! Everything SparkPing stores (ping results, rollups, outages, traceroute
+//! hops, ...) is a time series of a metric with labels, so backends only
+//! need to insert rows and select them back by metric and labels. On top of
+//! that each backend reports its size per target and prunes expired data in
+//! its own way. tsink is the default backend; see `sqlite.rs` for the
+//! alternative selected with `[database] backend = "sqlite"`.
+
+use super::{ping_result_rows, PingBatch};
+use crate::api::ping::dto::StorageStatsResponse;
+use crate::api::ping::query::{calculate_storage_stats, earliest_data_timestamp};
+use crate::config::Target;
+use crate::ping::PingResult;
+use crate::retention::{prune_expired, PruneReport};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use tsink::{DataPoint, Label, Row};
+
+/// Error of a storage backend
+#[derive(Debug)]
+pub struct StorageError(Box<dyn std::error::Error + Send + Sync>);
+
+impl StorageError {
+    pub fn new(e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        Self(e.into())
+    }
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for StorageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.0.source()
+    }
+}
+
+impl From<Box<dyn std::error::Error + Send + Sync>> for StorageError {
+    fn from(e: Box<dyn std::error::Error + Send + Sync>) -> Self {
+        Self(e)
+    }
+}
+
+impl From<rusqlite::Error> for StorageError {
+    fn from(e: rusqlite::Error) -> Self {
+        Self(Box::new(e))
+    }
+}
+
+impl From<std::io::Error> for StorageError {
+    fn from(e: std::io::Error) -> Self {
+        Self(Box::new(e))
+    }
+}
+
+/// Where SparkPing keeps its time series.
+///
+/// `select` matches series by their exact label set; `select_all` returns
+/// every series of a metric with its labels. Both return the points in
+/// `[start, end)` in timestamp order.
+pub trait StorageBackend: Send + Sync {
+    /// Backend name for logs and diagnostics
+    fn name(&self) -> &'static str;
+
+    fn insert_rows(&self, rows: &[Row]) -> Result<(), StorageError>;
+
+    fn select(
+        &self,
+        metric: &str,
+        labels: &[Label],
+        start: i64,
+        end: i64,
+    ) -> Result<Vec<DataPoint>, StorageError>;
+
+    fn select_all(
+        &self,
+        metric: &str,
+        start: i64,
+        end: i64,
+    ) -> Result<Vec<(Vec<Label>, Vec<DataPoint>)>, StorageError>;
+
+    /// Stored size and data points per target
+    fn stats(&self) -> Result<StorageStatsResponse, StorageError>;
+
+    /// Remove data past each target's `retention_days` (falling back to
+    /// `default_days`, 0 = keep forever); with `dry_run` it is only counted
+    fn prune(
+        &self,
+        targets: &[Target],
+        default_days: u32,
+        now: i64,
+        dry_run: bool,
+    ) -> Result<PruneReport, StorageError>;
+
+    /// Earliest timestamp any stored point can have, used to clamp
+    /// open-ended queries
+    fn earliest_timestamp(&self, now: i64) -> i64;
+
+    /// Directory of tsink partitions the data is kept in, which can be
+    /// verified and compacted (see `integrity`); None for backends without
+    fn partition_dir(&self) -> Option<&Path> {
+        None
+    }
+
+    /// Write a consistent copy of the data directory into the empty
+    /// directory `dest`, e.g. to seal it while SparkPing keeps writing
+    fn snapshot(&self, dest: &Path) -> Result<(), StorageError>;
+
+    /// Flush buffered data; the backend is not used afterwards
+    fn close(&self) -> Result<(), StorageError>;
+
+    /// Write a ping result with its derived jitter and batch loss rows
+    fn write_ping_result(
+        &self,
+        result: &PingResult,
+        batch: &mut PingBatch,
+    ) -> Result<(), StorageError> {
+        self.insert_rows(&ping_result_rows(result, batch))
+    }
+}
+
+/// Copy the files below `src` into `dst`, except those `skip` rejects;
+/// returns the number of files copied. Files removed while copying (e.g.
+/// WAL segments) are left out.
+pub(crate) fn copy_dir(src: &Path, dst: &Path, skip: &dyn Fn(&Path) -> bool) -> io::Result<usize> {
+    fs::create_dir_all(dst)?;
+    let mut count = 0;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let path = entry.path();
+        if skip(&path) {
+            continue;
+        }
+        let target = dst.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            count += copy_dir(&path, &target, skip)?;
+        } else if file_type.is_file() {
+            match fs::copy(&path, &target) {
+                Ok(_) => count += 1,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    Ok(count)
+}
+
+/// The default backend: tsink partitions in the data directory
+pub struct TsinkStorage {
+    storage: Arc<dyn tsink::Storage>,
+    data_path: PathBuf,
+    /// Held shared by writes and prunes, exclusively by snapshots, so a
+    /// snapshot sees no partly written WAL segment or partition
+    writes: RwLock<()>,
+}
+
+impl TsinkStorage {
+    pub fn new(storage: Arc<dyn tsink::Storage>, data_path: impl Into<PathBuf>) -> Self {
+        Self {
+            storage,
+            data_path: data_path.into(),
+            writes: RwLock::new(()),
+        }
+    }
+}
+
+impl StorageBackend for TsinkStorage {
+    fn name(&self) -> &'static str {
+        "tsink"
+    }
+
+    fn insert_rows(&self, rows: &[Row]) -> Result<(), StorageError> {
+        let _writing = self.writes.read().unwrap_or_else(|e| e.into_inner());
+        self.storage.insert_rows(rows).map_err(StorageError::new)
+    }
+
+    fn select(
+        &self,
+        metric: &str,
+        labels: &[Label],
+        start: i64,
+        end: i64,
+    ) -> Result<Vec<DataPoint>, StorageError> {
+        self.storage
+            .select(metric, labels, start, end)
+            .map_err(StorageError::new)
+    }
+
+    fn select_all(
+        &self,
+        metric: &str,
+        start: i64,
+        end: i64,
+    ) -> Result<Vec<(Vec<Label>, Vec<DataPoint>)>, StorageError> {
+        self.storage
+            .select_all(metric, start, end)
+            .map_err(StorageError::new)
+    }
+
+    fn stats(&self) -> Result<StorageStatsResponse, StorageError> {
+        Ok(calculate_storage_stats(&self.data_path.to_string_lossy())?)
+    }
+
+    fn prune(
+        &self,
+        targets: &[Target],
+        default_days: u32,
+        now: i64,
+        dry_run: bool,
+    ) -> Result<PruneReport, StorageError> {
+        let _writing = self.writes.read().unwrap_or_else(|e| e.into_inner());
+        Ok(prune_expired(
+            &self.data_path,
+            targets,
+            default_days,
+            now,
+            dry_run,
+        )?)
+    }
+
+    fn earliest_timestamp(&self, now: i64) -> i64 {
+        earliest_data_timestamp(&self.data_path, now)
+    }
+
+    fn partition_dir(&self) -> Option<&Path> {
+        Some(&self.data_path)
+    }
+
+    /// Copies the directory while writes wait. Points still in tsink's WAL
+    /// buffer (`with_wal_buffer_size`) are not in the copy yet.
+    fn snapshot(&self, dest: &Path) -> Result<(), StorageError> {
+        let _paused = self.writes.write().unwrap_or_else(|e| e.into_inner());
+        copy_dir(&self.data_path, dest, &|_| false)?;
+        Ok(())
+    }
+
+    fn close(&self) -> Result<(), StorageError> {
+        self.storage.close().map_err(StorageError::new)
+    }
+}
+
+/// In-memory tsink backend for tests
+#[cfg(test)]
+pub(crate) fn memory_storage() -> Arc<dyn StorageBackend> {
+    use tsink::{StorageBuilder, TimestampPrecision};
+
+    let storage = StorageBuilder::new()
+        .with_timestamp_precision(TimestampPrecision::Seconds)
+        .build()
+        .unwrap();
+    Arc::new(TsinkStorage::new(storage, PathBuf::new()))
+}
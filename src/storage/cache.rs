@@ -0,0 +1,167 @@
+//! Finished buckets of aggregated ping queries.
+//!
+//! Dashboards poll the same aggregated query (e.g. the last 24h in 5m
+//! buckets) every few seconds. Buckets that ended before the query ran only
+//! change when results with older timestamps are written, so they are kept
+//! per query (target filter, bucket duration, percentiles) and only the
+//! open and partial buckets are read from storage again. Every write through
+//! the `WriteBuffer` (and ingested batch) drops the cached buckets holding
+//! the written timestamps of its targets; pruning and every change of the
+//! targets (API edits, history migration, config reloads) drop everything,
+//! since which series a query selects follows the target config.
+
+use crate::api::ping::dto::BucketDataPoint;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Mutex;
+use tsink::Row;
+
+/// Queries kept at once; the least recently used one is dropped beyond this
+const MAX_QUERIES: usize = 64;
+
+/// Metrics whose writes change ping aggregates
+const PING_METRICS: [&str; 2] = ["ping_latency", "ping_failed"];
+
+/// Cached query: target filter (None for all targets), bucket duration in
+/// seconds, and whether percentiles were included
+pub type AggregateKey = (Option<String>, i64, bool);
+
+/// Buckets of all matching targets starting at one time, with the
+/// resolution of the query that computed them
+pub type CachedBuckets = (Vec<BucketDataPoint>, &'static str);
+
+#[derive(Debug, Default)]
+struct CachedQuery {
+    buckets: BTreeMap<i64, CachedBuckets>,
+    /// Fills in progress
+    fills: usize,
+    /// Buckets written to while a fill was in progress, which must not be
+    /// stored by that fill
+    invalidated: HashSet<i64>,
+    /// Cleared while a fill was in progress; no running fill stores anything
+    cleared: bool,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct Queries {
+    queries: HashMap<AggregateKey, CachedQuery>,
+    uses: u64,
+}
+
+/// Finished buckets of recent aggregated queries
+#[derive(Debug, Default)]
+pub struct AggregateCache {
+    inner: Mutex<Queries>,
+}
+
+impl AggregateCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start filling a query for the buckets starting in `[from, to)`:
+    /// returns those cached, by start. Buckets outside the range are
+    /// dropped. Every call must be followed by [`Self::end_fill`].
+    pub fn begin_fill(
+        &self,
+        key: &AggregateKey,
+        from: i64,
+        to: i64,
+    ) -> BTreeMap<i64, CachedBuckets> {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.uses += 1;
+        let uses = inner.uses;
+        if !inner.queries.contains_key(key) && inner.queries.len() >= MAX_QUERIES {
+            let idle = inner
+                .queries
+                .iter()
+                .filter(|(_, q)| q.fills == 0)
+                .min_by_key(|(_, q)| q.last_used)
+                .map(|(k, _)| k.clone());
+            if let Some(idle) = idle {
+                inner.queries.remove(&idle);
+            }
+        }
+
+        let query = inner.queries.entry(key.clone()).or_default();
+        query.fills += 1;
+        query.last_used = uses;
+        query.buckets.retain(|start, _| (from..to).contains(start));
+        query
+            .buckets
+            .iter()
+            .map(|(start, buckets)| (*start, buckets.clone()))
+            .collect()
+    }
+
+    /// Store the buckets computed by a fill, except those written to since
+    /// it began
+    pub fn end_fill(&self, key: &AggregateKey, computed: BTreeMap<i64, CachedBuckets>) {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(query) = inner.queries.get_mut(key) else {
+            return;
+        };
+        if !query.cleared {
+            for (start, buckets) in computed {
+                if !query.invalidated.contains(&start) {
+                    query.buckets.insert(start, buckets);
+                }
+            }
+        }
+        query.fills = query.fills.saturating_sub(1);
+        if query.fills == 0 {
+            query.invalidated.clear();
+            query.cleared = false;
+        }
+    }
+
+    /// Drop the buckets holding the ping results of `rows`
+    pub fn invalidate(&self, rows: &[Row]) {
+        let mut written: HashMap<(&str, Option<&str>), Vec<i64>> = HashMap::new();
+        for row in rows.iter().filter(|r| PING_METRICS.contains(&r.metric())) {
+            let label = |name: &str| {
+                row.labels()
+                    .iter()
+                    .find(|l| l.name == name)
+                    .map(|l| l.value.as_str())
+            };
+            written
+                .entry((label("target").unwrap_or_default(), label("target_id")))
+                .or_default()
+                .push(row.data_point().timestamp);
+        }
+        if written.is_empty() {
+            return;
+        }
+
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        for ((target, bucket_seconds, _), query) in inner.queries.iter_mut() {
+            let bucket_seconds = (*bucket_seconds).max(1);
+            for ((address, id), timestamps) in &written {
+                let matches = target
+                    .as_deref()
+                    .is_none_or(|t| t == *address || Some(t) == *id);
+                if !matches {
+                    continue;
+                }
+                for timestamp in timestamps {
+                    let start = timestamp.div_euclid(bucket_seconds) * bucket_seconds;
+                    query.buckets.remove(&start);
+                    if query.fills > 0 {
+                        query.invalidated.insert(start);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Drop everything (e.g. after data was deleted)
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        for query in inner.queries.values_mut() {
+            query.buckets.clear();
+            // Running fills may have read data that is gone
+            query.cleared = query.fills > 0;
+        }
+    }
+}
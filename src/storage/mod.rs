@@ -0,0 +1,869 @@
+use crate::api::ping::dto::StorageStatsResponse;
+use crate::api::ping::query::STORAGE_SIZE_METRIC;
+use crate::calibration::LatencyCalibration;
+use crate::config::Target;
+use crate::ping::PingResult;
+use crate::telemetry::timed_write;
+use cache::AggregateCache;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::error;
+use tsink::{DataPoint, Label, Row};
+
+pub mod backend;
+pub mod cache;
+pub mod sqlite;
+
+#[cfg(test)]
+pub(crate) use backend::memory_storage;
+pub use backend::{StorageBackend, StorageError, TsinkStorage};
+
+/// Label on ICMP results with the calibration correction applied
+pub const LATENCY_CORRECTED_LABEL: &str = "latency_corrected";
+
+/// Label with the ECMP flow index of results of targets with `ecmp_flows`
+pub const FLOW_LABEL: &str = "flow";
+
+/// Label with the site of results pushed by SparkPing agents (`[agent]`).
+/// Such series are a remote vantage point's view of a target, so queries
+/// only read them when asked for that site.
+pub const SITE_LABEL: &str = "site";
+
+/// Site under which this server's own results are reported in per-site
+/// queries (`group_by=site`)
+pub const LOCAL_SITE: &str = "local";
+
+/// `site` parameter selecting the results of every site
+pub const ALL_SITES: &str = "*";
+
+/// Whether agents may use `site` as their site name; the names standing for
+/// this server and for every site are reserved
+pub fn is_valid_site(site: &str) -> bool {
+    !site.is_empty() && site != LOCAL_SITE && site != ALL_SITES
+}
+
+/// Site of a series; None for this server's own results
+pub fn series_site(labels: &[Label]) -> Option<&str> {
+    labels
+        .iter()
+        .find(|l| l.name == SITE_LABEL)
+        .map(|l| l.value.as_str())
+}
+
+/// Results a query reads, by the site that measured them (`site`
+/// parameter)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub enum SiteSelector {
+    /// This server's own results
+    #[default]
+    Local,
+    /// Results pushed by the agent at this site
+    Site(String),
+    /// This server's and every agent's results
+    All,
+}
+
+impl SiteSelector {
+    /// Parse a `site` parameter: omitted or [`LOCAL_SITE`] for this server,
+    /// [`ALL_SITES`] for every site, anything else an agent's site
+    pub fn parse(site: Option<&str>) -> Self {
+        match site.map(str::trim) {
+            None | Some("") | Some(LOCAL_SITE) => SiteSelector::Local,
+            Some(ALL_SITES) => SiteSelector::All,
+            Some(site) => SiteSelector::Site(site.to_string()),
+        }
+    }
+
+    /// Whether results of `site` (None for this server) are selected
+    pub fn includes(&self, site: Option<&str>) -> bool {
+        match self {
+            SiteSelector::Local => site.is_none(),
+            SiteSelector::Site(selected) => site == Some(selected.as_str()),
+            SiteSelector::All => true,
+        }
+    }
+
+    /// Whether a series with `labels` is selected
+    pub fn matches(&self, labels: &[Label]) -> bool {
+        self.includes(series_site(labels))
+    }
+}
+
+/// Metric holding the correction constant of each calibration run
+pub const LATENCY_CORRECTION_METRIC: &str = "latency_correction_ms";
+
+/// Metric holding the RTT delta to the previous ping of the same batch
+pub const JITTER_METRIC: &str = "ping_jitter";
+
+/// Metric holding the loss percentage of each completed batch
+pub const BATCH_LOSS_METRIC: &str = "ping_batch_loss";
+
+/// Metric recording when targets were paused (1.0) and resumed (0.0)
+pub const PAUSED_METRIC: &str = "target_paused";
+
+/// State of a target's current batch of back-to-back pings (sequences
+/// `1..=size`), carried between [`ping_result_rows`] calls
+#[derive(Debug)]
+pub struct PingBatch {
+    size: u16,
+    sent: u16,
+    failed: u16,
+    previous_latency_ms: Option<f64>,
+    /// The target's custom labels, added to every row
+    labels: Vec<Label>,
+}
+
+impl PingBatch {
+    pub fn new(size: u16) -> Self {
+        Self {
+            size: size.max(1),
+            sent: 0,
+            failed: 0,
+            previous_latency_ms: None,
+            labels: Vec::new(),
+        }
+    }
+
+    /// Batch of a target, whose rows carry its custom labels
+    pub fn for_target(target: &Target) -> Self {
+        Self {
+            labels: target_labels(target),
+            ..Self::new(target.ping_count)
+        }
+    }
+
+    /// Add a result and return its jitter (when it and the ping before it
+    /// succeeded) and the batch loss percentage (once the batch is complete)
+    fn observe(&mut self, result: &PingResult) -> (Option<f64>, Option<f64>) {
+        // Sequence 1 starts a new batch, even if the previous one was cut short
+        if result.sequence <= 1 {
+            self.sent = 0;
+            self.failed = 0;
+            self.previous_latency_ms = None;
+        }
+        self.sent += 1;
+
+        let latency = result.latency_ms.filter(|_| result.success);
+        if latency.is_none() {
+            self.failed += 1;
+        }
+        let jitter = match (self.previous_latency_ms, latency) {
+            (Some(previous), Some(current)) => Some((current - previous).abs()),
+            _ => None,
+        };
+        self.previous_latency_ms = latency;
+
+        let loss =
+            (result.sequence >= self.size).then(|| self.failed as f64 / self.sent as f64 * 100.0);
+        (jitter, loss)
+    }
+}
+
+/// Custom labels of a target as stored with its results
+pub fn target_labels(target: &Target) -> Vec<Label> {
+    target
+        .labels
+        .iter()
+        .map(|(name, value)| Label::new(name, value))
+        .collect()
+}
+
+/// Rows of a ping result: its `ping_latency`/`ping_failed` row, plus its
+/// jitter and, for the last ping of a batch, the batch's loss percentage.
+///
+/// The derived rows share the result's labels, so they are found by the
+/// same target lookups as `ping_latency`.
+pub fn ping_result_rows(result: &PingResult, batch: &mut PingBatch) -> Vec<Row> {
+    let timestamp = result.timestamp.timestamp();
+    let labels = ping_result_labels(result, batch.labels.clone());
+    let (jitter, loss) = batch.observe(result);
+
+    let mut rows = Vec::with_capacity(3);
+    if let Some(jitter) = jitter {
+        rows.push(Row::with_labels(
+            JITTER_METRIC,
+            labels.clone(),
+            DataPoint::new(timestamp, jitter),
+        ));
+    }
+    if let Some(loss) = loss {
+        rows.push(Row::with_labels(
+            BATCH_LOSS_METRIC,
+            labels.clone(),
+            DataPoint::new(timestamp, loss),
+        ));
+    }
+    rows.push(result_row(result, labels));
+    rows
+}
+
+/// Buffered writes of ping rows.
+///
+/// Ping tasks hand over the rows of a whole batch (through [`PendingRows`]),
+/// which are inserted into tsink at once. With a flush interval
+/// (`[database] write_flush_ms`) the batches of all targets are collected
+/// instead and inserted together by [`WriteBuffer::flush`], cutting WAL
+/// appends for high-frequency targets at the cost of results reaching
+/// storage up to one interval later.
+pub struct WriteBuffer {
+    flush_interval: Duration,
+    rows: Mutex<Vec<Row>>,
+    /// Aggregated query buckets, invalidated by the rows written
+    aggregates: AggregateCache,
+}
+
+impl WriteBuffer {
+    /// Buffer flushed every `flush_interval`; zero writes each batch as it
+    /// is handed over
+    pub fn new(flush_interval: Duration) -> Self {
+        Self {
+            flush_interval,
+            rows: Mutex::new(Vec::new()),
+            aggregates: AggregateCache::new(),
+        }
+    }
+
+    pub fn flush_interval(&self) -> Duration {
+        self.flush_interval
+    }
+
+    /// Cache of aggregated query buckets; rows written elsewhere must be
+    /// passed to its `invalidate`
+    pub fn aggregate_cache(&self) -> &AggregateCache {
+        &self.aggregates
+    }
+
+    /// Write `rows` now, or queue them for the next flush. Blocks while
+    /// writing; async code writes through [`PendingRows`].
+    pub fn write(
+        &self,
+        storage: &dyn StorageBackend,
+        rows: Vec<Row>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        if self.flush_interval.is_zero() {
+            timed_write(|| storage.insert_rows(&rows))?;
+            self.aggregates.invalidate(&rows);
+        } else {
+            self.rows
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .extend(rows);
+        }
+        Ok(())
+    }
+
+    /// Insert all queued rows at once and return how many were written.
+    /// Rows of a failed insert are dropped, like a failed direct write.
+    pub fn flush(&self, storage: &dyn StorageBackend) -> Result<usize, Box<dyn std::error::Error>> {
+        let rows = std::mem::take(&mut *self.rows.lock().unwrap_or_else(|e| e.into_inner()));
+        if rows.is_empty() {
+            return Ok(0);
+        }
+        timed_write(|| storage.insert_rows(&rows))?;
+        self.aggregates.invalidate(&rows);
+        Ok(rows.len())
+    }
+
+    /// Queue `rows` for the next flush, without writing
+    fn queue(&self, rows: Vec<Row>) {
+        self.rows
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .extend(rows);
+    }
+
+    /// Number of queued rows
+    pub fn pending(&self) -> usize {
+        self.rows.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+}
+
+/// Rows of a ping batch in progress, written through a [`WriteBuffer`] on a
+/// blocking thread once the batch is done.
+///
+/// Rows still held when it is dropped, e.g. because the ping task was
+/// aborted mid-batch by a target edit or pause, are queued for the next
+/// flush (written right away without a flush interval) instead of being
+/// lost with the task.
+pub struct PendingRows {
+    writer: Arc<WriteBuffer>,
+    storage: Arc<dyn StorageBackend>,
+    rows: Vec<Row>,
+}
+
+impl PendingRows {
+    pub fn new(writer: Arc<WriteBuffer>, storage: Arc<dyn StorageBackend>) -> Self {
+        Self {
+            writer,
+            storage,
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn extend(&mut self, rows: Vec<Row>) {
+        self.rows.extend(rows);
+    }
+
+    /// Write the rows (see [`WriteBuffer::write`]) without blocking the
+    /// async runtime
+    pub async fn write(&mut self) -> Result<(), String> {
+        let rows = std::mem::take(&mut self.rows);
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let writer = Arc::clone(&self.writer);
+        let storage = Arc::clone(&self.storage);
+        tokio::task::spawn_blocking(move || {
+            writer.write(&*storage, rows).map_err(|e| e.to_string())
+        })
+        .await
+        .map_err(|e| e.to_string())?
+    }
+}
+
+impl Drop for PendingRows {
+    fn drop(&mut self) {
+        if self.rows.is_empty() {
+            return;
+        }
+        self.writer.queue(std::mem::take(&mut self.rows));
+        if !self.writer.flush_interval.is_zero() {
+            return;
+        }
+        // No flush task runs without a flush interval
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let writer = Arc::clone(&self.writer);
+        let storage = Arc::clone(&self.storage);
+        runtime.spawn_blocking(move || {
+            if let Err(e) = writer.flush(&*storage) {
+                error!("Error writing ping results of an aborted batch: {}", e);
+            }
+        });
+    }
+}
+
+/// Build the `ping_latency`/`ping_failed` row for a ping result.
+/// `extra_labels` are appended after the standard labels.
+pub fn ping_result_row(result: &PingResult, extra_labels: Vec<Label>) -> Row {
+    result_row(result, ping_result_labels(result, extra_labels))
+}
+
+/// Standard labels of a ping result, followed by `extra_labels`
+fn ping_result_labels(result: &PingResult, extra_labels: Vec<Label>) -> Vec<Label> {
+    // Build labels for the metric
+    let mut labels = vec![
+        Label::new("target_id", &result.target_id),
+        Label::new("target", &result.target),
+        Label::new("sequence", result.sequence.to_string()),
+    ];
+
+    // Add target name label if available
+    if let Some(ref name) = result.target_name {
+        labels.push(Label::new("target_name", name));
+    }
+
+    // Hostname targets also record the address that was pinged
+    if let Some(ref ip) = result.resolved_ip {
+        labels.push(Label::new("resolved_ip", ip));
+    }
+
+    labels.push(Label::new("probe_type", result.probe_type.as_str()));
+    if let Some(port) = result.port {
+        labels.push(Label::new("port", port.to_string()));
+    }
+
+    // The correction itself is stored as LATENCY_CORRECTION_METRIC
+    if result.correction_ms.is_some() {
+        labels.push(Label::new(LATENCY_CORRECTED_LABEL, "true"));
+    }
+
+    if let Some(flow) = result.flow {
+        labels.push(Label::new(FLOW_LABEL, flow.to_string()));
+    }
+
+    labels.extend(extra_labels);
+    labels
+}
+
+fn result_row(result: &PingResult, labels: Vec<Label>) -> Row {
+    // Convert timestamp to Unix timestamp (seconds)
+    let timestamp = result.timestamp.timestamp();
+
+    // Create row based on ping result
+    if result.success {
+        // For successful pings, store latency as the value
+        let latency = result.latency_ms.unwrap_or(0.0);
+        Row::with_labels("ping_latency", labels, DataPoint::new(timestamp, latency))
+    } else {
+        // For failed pings, store 0 as the value and use a different metric name
+        Row::with_labels("ping_failed", labels, DataPoint::new(timestamp, 0.0))
+    }
+}
+
+/// Record the latency correction measured at startup
+pub fn write_latency_calibration(
+    storage: &dyn StorageBackend,
+    calibration: &LatencyCalibration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    storage.insert_rows(&[Row::with_labels(
+        LATENCY_CORRECTION_METRIC,
+        vec![Label::new("socket_type", calibration.socket_type.as_str())],
+        DataPoint::new(calibration.measured_at, calibration.correction_ms),
+    )])?;
+    Ok(())
+}
+
+/// Record that the target `target_id` was paused or resumed at `timestamp`
+pub fn write_pause_transition(
+    storage: &dyn StorageBackend,
+    target_id: &str,
+    paused: bool,
+    timestamp: i64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    storage.insert_rows(&[Row::with_labels(
+        PAUSED_METRIC,
+        vec![Label::new("target_id", target_id)],
+        DataPoint::new(timestamp, if paused { 1.0 } else { 0.0 }),
+    )])?;
+    Ok(())
+}
+
+/// Record a storage size snapshot (`storage_size_bytes`) for every target.
+///
+/// Targets that are no longer configured keep their data on disk, so they
+/// are recorded too, with the target ID as address.
+pub fn write_storage_stats(
+    storage: &dyn StorageBackend,
+    stats: &StorageStatsResponse,
+    targets: &[Target],
+    timestamp: i64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let rows: Vec<Row> = stats
+        .targets
+        .iter()
+        .map(|target_stats| {
+            let target = targets.iter().find(|t| t.id == target_stats.target_id);
+            let mut labels = vec![
+                Label::new("target_id", &target_stats.target_id),
+                Label::new(
+                    "target",
+                    target.map_or(&target_stats.target_id, |t| &t.address),
+                ),
+            ];
+            if let Some(name) = target.and_then(|t| t.name.as_ref()) {
+                labels.push(Label::new("target_name", name));
+            }
+            Row::with_labels(
+                STORAGE_SIZE_METRIC,
+                labels,
+                DataPoint::new(timestamp, target_stats.size_bytes as f64),
+            )
+        })
+        .collect();
+
+    if !rows.is_empty() {
+        storage.insert_rows(&rows)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::ping::dto::TargetStorageStats;
+    use crate::api::ping::query::{
+        query_aggregated_chunked, query_site_aggregated, select_target_data,
+    };
+    use crate::config::{HistorySource, ProbeType};
+
+    #[test]
+    fn test_storage_stats_are_aggregatable() {
+        let storage = crate::storage::memory_storage();
+        let target = Target {
+            id: "t1".to_string(),
+            address: "192.168.1.1".to_string(),
+            name: Some("Router".to_string()),
+            ping_count: 1,
+            ..Default::default()
+        };
+        let stats = |size_bytes| StorageStatsResponse {
+            total_size_bytes: size_bytes,
+            targets: vec![TargetStorageStats {
+                target_id: "t1".to_string(),
+                size_bytes,
+                data_point_count: 0,
+                earliest_timestamp: None,
+                latest_timestamp: None,
+            }],
+        };
+
+        let targets = std::slice::from_ref(&target);
+        write_storage_stats(&*storage, &stats(1000), targets, 3600).unwrap();
+        write_storage_stats(&*storage, &stats(3000), targets, 7200).unwrap();
+
+        let (buckets, _) = query_aggregated_chunked(
+            &*storage,
+            &[STORAGE_SIZE_METRIC],
+            Some("192.168.1.1"),
+            Some(&target),
+            0,
+            10_000,
+            86400,
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(buckets.len(), 1);
+        assert_eq!(buckets[0].target_name.as_deref(), Some("Router"));
+        assert_eq!(buckets[0].min, Some(1000.0));
+        assert_eq!(buckets[0].max, Some(3000.0));
+    }
+
+    #[test]
+    fn test_probe_type_series_selection() {
+        let storage = crate::storage::memory_storage();
+        let mut target = Target {
+            id: "t1".to_string(),
+            address: "192.168.1.1".to_string(),
+            ping_count: 1,
+            ..Default::default()
+        };
+        let result = |seconds, probe_type, port| PingResult {
+            timestamp: chrono::DateTime::from_timestamp(seconds, 0).unwrap(),
+            target_id: "t1".to_string(),
+            target: "192.168.1.1".to_string(),
+            target_name: None,
+            sequence: 1,
+            resolved_ip: None,
+            probe_type,
+            port,
+            success: true,
+            latency_ms: Some(1.0),
+            correction_ms: None,
+            flow: None,
+        };
+
+        // Series written before probe types existed carry no probe_type label
+        let legacy = Row::with_labels(
+            "ping_latency",
+            vec![
+                Label::new("target_id", "t1"),
+                Label::new("target", "192.168.1.1"),
+                Label::new("sequence", "1"),
+            ],
+            DataPoint::new(10, 1.0),
+        );
+        storage.insert_rows(&[legacy]).unwrap();
+        storage
+            .write_ping_result(&result(20, ProbeType::Icmp, None), &mut PingBatch::new(1))
+            .unwrap();
+        storage
+            .write_ping_result(
+                &result(30, ProbeType::Tcp, Some(443)),
+                &mut PingBatch::new(1),
+            )
+            .unwrap();
+        let mut corrected = result(25, ProbeType::Icmp, None);
+        corrected.correction_ms = Some(0.1);
+        storage
+            .write_ping_result(&corrected, &mut PingBatch::new(1))
+            .unwrap();
+
+        let select = |target: &Target| {
+            let mut timestamps: Vec<i64> =
+                select_target_data(&*storage, "ping_latency", target, 0, 100)
+                    .unwrap()
+                    .iter()
+                    .map(|p| p.timestamp)
+                    .collect();
+            timestamps.sort();
+            timestamps
+        };
+        assert_eq!(select(&target), vec![10, 20, 25]);
+
+        target.probe_type = ProbeType::Tcp;
+        target.port = Some(443);
+        assert_eq!(select(&target), vec![30]);
+
+        // Results from before flows were enabled are still found
+        let mut flowed = result(40, ProbeType::Tcp, Some(443));
+        flowed.flow = Some(0);
+        storage
+            .write_ping_result(&flowed, &mut PingBatch::new(1))
+            .unwrap();
+        target.ecmp_flows = Some(2);
+        assert_eq!(select(&target), vec![30, 40]);
+
+        // Labeled results are found next to those from before the labels
+        target
+            .labels
+            .insert("location".to_string(), "basement".to_string());
+        let mut labeled = result(50, ProbeType::Tcp, Some(443));
+        labeled.flow = Some(0);
+        storage
+            .write_ping_result(&labeled, &mut PingBatch::for_target(&target))
+            .unwrap();
+        assert_eq!(select(&target), vec![30, 40, 50]);
+    }
+
+    #[test]
+    fn test_history_series_selection() {
+        let storage = crate::storage::memory_storage();
+        let mut target = Target {
+            id: "t1".to_string(),
+            address: "192.168.1.1".to_string(),
+            ping_count: 1,
+            ..Default::default()
+        };
+        let result = |seconds, target_id: &str, address: &str| PingResult {
+            timestamp: chrono::DateTime::from_timestamp(seconds, 0).unwrap(),
+            target_id: target_id.to_string(),
+            target: address.to_string(),
+            target_name: None,
+            sequence: 1,
+            resolved_ip: None,
+            probe_type: ProbeType::Icmp,
+            port: None,
+            success: true,
+            latency_ms: Some(1.0),
+            correction_ms: None,
+            flow: None,
+        };
+        for (seconds, target_id, address) in [
+            (20, "t1", "192.168.1.1"),
+            (10, "t1", "192.168.1.9"),
+            (5, "old", "192.168.1.9"),
+            (7, "other", "192.168.1.9"),
+        ] {
+            storage
+                .write_ping_result(&result(seconds, target_id, address), &mut PingBatch::new(1))
+                .unwrap();
+        }
+
+        let select = |target: &Target| {
+            let mut timestamps: Vec<i64> =
+                select_target_data(&*storage, "ping_latency", target, 0, 100)
+                    .unwrap()
+                    .iter()
+                    .map(|p| p.timestamp)
+                    .collect();
+            timestamps.sort();
+            timestamps
+        };
+        assert_eq!(select(&target), vec![20]);
+
+        // Same ID before the address changed
+        target.history.push(HistorySource {
+            id: "t1".to_string(),
+            address: Some("192.168.1.9".to_string()),
+        });
+        assert_eq!(select(&target), vec![10, 20]);
+
+        // Re-created under a new ID
+        target.history.push(HistorySource {
+            id: "old".to_string(),
+            address: None,
+        });
+        assert_eq!(select(&target), vec![5, 10, 20]);
+    }
+
+    #[test]
+    fn test_site_series_selection() {
+        assert_eq!(SiteSelector::parse(None), SiteSelector::Local);
+        assert_eq!(SiteSelector::parse(Some("local")), SiteSelector::Local);
+        assert_eq!(SiteSelector::parse(Some("*")), SiteSelector::All);
+        assert_eq!(
+            SiteSelector::parse(Some("berlin")),
+            SiteSelector::Site("berlin".to_string())
+        );
+        assert!(!is_valid_site("local") && !is_valid_site("*") && is_valid_site("berlin"));
+
+        let storage = crate::storage::memory_storage();
+        // A hostname target selects its series by ID
+        let target = Target {
+            id: "t1".to_string(),
+            address: "router.lan".to_string(),
+            ping_count: 1,
+            ..Default::default()
+        };
+        let result = |seconds, latency| PingResult {
+            timestamp: chrono::DateTime::from_timestamp(seconds, 0).unwrap(),
+            target_id: "t1".to_string(),
+            target: "router.lan".to_string(),
+            target_name: None,
+            sequence: 0,
+            resolved_ip: None,
+            probe_type: ProbeType::Icmp,
+            port: None,
+            success: true,
+            latency_ms: Some(latency),
+            correction_ms: None,
+            flow: None,
+        };
+        storage
+            .write_ping_result(&result(10, 1.0), &mut PingBatch::new(1))
+            .unwrap();
+        let site_labels = vec![
+            Label::new("source", "ingest"),
+            Label::new(SITE_LABEL, "berlin"),
+        ];
+        storage
+            .insert_rows(&[ping_result_row(&result(20, 50.0), site_labels)])
+            .unwrap();
+
+        let own = select_target_data(&*storage, "ping_latency", &target, 0, 100).unwrap();
+        assert_eq!(own.len(), 1);
+        assert_eq!(own[0].value, 1.0);
+
+        let (buckets, _) =
+            query_aggregated_chunked(&*storage, &["ping_latency"], None, None, 0, 100, 100, false)
+                .unwrap();
+        assert_eq!(buckets.len(), 1);
+        assert_eq!(buckets[0].avg, Some(1.0));
+
+        let site = |selector: &SiteSelector, group_by_site| {
+            query_site_aggregated(
+                &*storage,
+                &["ping_latency"],
+                Some("t1"),
+                None,
+                selector,
+                group_by_site,
+                0,
+                100,
+                100,
+                false,
+            )
+            .unwrap()
+            .0
+        };
+        let berlin = site(&SiteSelector::Site("berlin".to_string()), false);
+        assert_eq!(berlin.len(), 1);
+        assert_eq!(berlin[0].avg, Some(50.0));
+
+        let per_site: Vec<_> = site(&SiteSelector::All, true)
+            .into_iter()
+            .map(|b| (b.target, b.target_name, b.avg))
+            .collect();
+        assert_eq!(
+            per_site,
+            vec![
+                (
+                    "berlin".to_string(),
+                    Some("site:berlin".to_string()),
+                    Some(50.0)
+                ),
+                (
+                    LOCAL_SITE.to_string(),
+                    Some("site:local".to_string()),
+                    Some(1.0)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_buffer() {
+        let storage = crate::storage::memory_storage();
+        let row = |timestamp| {
+            Row::with_labels(
+                "ping_latency",
+                vec![Label::new("target_id", "t1")],
+                DataPoint::new(timestamp, 1.0),
+            )
+        };
+        let count = || {
+            storage
+                .select_all("ping_latency", 0, 100)
+                .unwrap()
+                .into_iter()
+                .map(|(_, points)| points.len())
+                .sum::<usize>()
+        };
+
+        // Without a flush interval rows are written right away
+        let direct = WriteBuffer::new(Duration::ZERO);
+        direct.write(&*storage, vec![row(10), row(11)]).unwrap();
+        assert_eq!(direct.pending(), 0);
+        assert_eq!(count(), 2);
+
+        let buffered = WriteBuffer::new(Duration::from_millis(500));
+        buffered.write(&*storage, vec![row(20)]).unwrap();
+        buffered.write(&*storage, vec![row(21), row(22)]).unwrap();
+        assert_eq!(buffered.pending(), 3);
+        assert_eq!(count(), 2);
+
+        assert_eq!(buffered.flush(&*storage).unwrap(), 3);
+        assert_eq!(buffered.pending(), 0);
+        assert_eq!(count(), 5);
+        assert_eq!(buffered.flush(&*storage).unwrap(), 0);
+
+        // Rows of a batch cut short are queued, not lost
+        let buffered = Arc::new(buffered);
+        let mut pending = PendingRows::new(Arc::clone(&buffered), Arc::clone(&storage));
+        pending.extend(vec![row(30), row(31)]);
+        drop(pending);
+        assert_eq!(buffered.pending(), 2);
+        assert_eq!(buffered.flush(&*storage).unwrap(), 2);
+        assert_eq!(count(), 7);
+    }
+
+    #[test]
+    fn test_jitter_and_batch_loss() {
+        let storage = crate::storage::memory_storage();
+        let result = |sequence: u16, latency_ms: Option<f64>| PingResult {
+            timestamp: chrono::DateTime::from_timestamp(10 + sequence as i64, 0).unwrap(),
+            target_id: "t1".to_string(),
+            target: "192.168.1.1".to_string(),
+            target_name: None,
+            sequence,
+            resolved_ip: None,
+            probe_type: ProbeType::Icmp,
+            port: None,
+            success: latency_ms.is_some(),
+            latency_ms,
+            correction_ms: None,
+            flow: None,
+        };
+
+        let mut batch = PingBatch::new(4);
+        for (sequence, latency) in [(1, Some(10.0)), (2, Some(14.0)), (3, None), (4, Some(11.0))] {
+            storage
+                .write_ping_result(&result(sequence, latency), &mut batch)
+                .unwrap();
+        }
+
+        // Only consecutive successes yield jitter
+        let jitter: Vec<(i64, f64)> = storage
+            .select_all(JITTER_METRIC, 0, 100)
+            .unwrap()
+            .into_iter()
+            .flat_map(|(_, points)| points)
+            .map(|p| (p.timestamp, p.value))
+            .collect();
+        assert_eq!(jitter, vec![(12, 4.0)]);
+
+        let loss = storage.select_all(BATCH_LOSS_METRIC, 0, 100).unwrap();
+        assert_eq!(loss.len(), 1);
+        assert_eq!(loss[0].1.len(), 1);
+        assert_eq!(loss[0].1[0].timestamp, 14);
+        assert_eq!(loss[0].1[0].value, 25.0);
+
+        // A new batch does not carry over the previous latency
+        storage
+            .write_ping_result(&result(1, Some(50.0)), &mut batch)
+            .unwrap();
+        assert_eq!(
+            storage.select_all(JITTER_METRIC, 0, 100).unwrap()[0]
+                .1
+                .len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,547 @@
+//! SQLite storage backend (`[database] backend = "sqlite"`).
+//!
+//! Keeps every series in `<data path>/sparkping.sqlite`, for users who want
+//! to query their data with SQL:
+//!
+//! ```sql
+//! SELECT s.target_id, p.timestamp, p.value
+//! FROM points p JOIN series s ON s.id = p.series_id
+//! WHERE s.metric = 'ping_latency';
+//! ```
+//!
+//! A series is a metric plus its labels as a JSON object with sorted keys;
+//! its `target_id` label is copied into a column for per-target queries,
+//! stats and pruning. Unlike tsink, expired points are deleted one by one,
+//! so pruning never has to wait for a whole series to expire; the freed
+//! pages are returned to the file system by an incremental vacuum.
+//!
+//! Writes go through one connection, queries through a small pool of read
+//! connections, which WAL mode lets read while a write is in progress. The
+//! oldest stored timestamp is cached, since every open-ended query asks for
+//! it.
+
+use super::backend::{copy_dir, StorageBackend, StorageError};
+use crate::api::ping::dto::{StorageStatsResponse, TargetStorageStats};
+use crate::config::Target;
+use crate::retention::{retention_days, PruneReport};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use tracing::info;
+use tsink::{DataPoint, Label, Row};
+
+/// Database file inside the data path
+pub const DATABASE_FILE: &str = "sparkping.sqlite";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS series (
+    id INTEGER PRIMARY KEY,
+    metric TEXT NOT NULL,
+    labels TEXT NOT NULL,
+    target_id TEXT,
+    UNIQUE (metric, labels)
+);
+CREATE INDEX IF NOT EXISTS series_target ON series (target_id);
+CREATE TABLE IF NOT EXISTS points (
+    series_id INTEGER NOT NULL REFERENCES series (id),
+    timestamp INTEGER NOT NULL,
+    value REAL NOT NULL
+);
+CREATE INDEX IF NOT EXISTS points_series_time ON points (series_id, timestamp);
+CREATE INDEX IF NOT EXISTS points_time ON points (timestamp);
+";
+
+/// Connections serving queries
+const READ_CONNECTIONS: usize = 4;
+
+pub struct SqliteStorage {
+    path: PathBuf,
+    /// Connection of all writes (inserts, pruning)
+    conn: Mutex<Connection>,
+    readers: Vec<Mutex<Connection>>,
+    next_reader: AtomicUsize,
+    /// Series IDs by (metric, label key), so inserts skip the lookup
+    series_ids: Mutex<HashMap<(String, String), i64>>,
+    /// Oldest stored timestamp, None while empty; kept up to date by inserts
+    /// and pruning
+    earliest: Mutex<Option<i64>>,
+}
+
+impl SqliteStorage {
+    /// Open (or create) the database in `data_path`
+    pub fn open(data_path: &Path) -> Result<Self, StorageError> {
+        std::fs::create_dir_all(data_path)?;
+        let path = data_path.join(DATABASE_FILE);
+        let conn = Connection::open(&path)?;
+        // Only takes effect before the first table is created
+        conn.pragma_update(None, "auto_vacuum", "INCREMENTAL")?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        conn.execute_batch(SCHEMA)?;
+        let auto_vacuum: i64 = conn.query_row("PRAGMA auto_vacuum", [], |r| r.get(0))?;
+        if auto_vacuum != 2 {
+            // Databases created without it switch with a one-time full vacuum
+            info!("Enabling incremental vacuum of the SQLite database");
+            conn.execute_batch("PRAGMA auto_vacuum = INCREMENTAL; VACUUM;")?;
+        }
+        let earliest = query_earliest(&conn)?;
+
+        let readers = (0..READ_CONNECTIONS)
+            .map(|_| {
+                let reader = Connection::open_with_flags(
+                    &path,
+                    OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+                )?;
+                Ok(Mutex::new(reader))
+            })
+            .collect::<Result<Vec<_>, StorageError>>()?;
+        info!("SQLite database opened at: {}", path.display());
+
+        Ok(Self {
+            path,
+            conn: Mutex::new(conn),
+            readers,
+            next_reader: AtomicUsize::new(0),
+            series_ids: Mutex::new(HashMap::new()),
+            earliest: Mutex::new(earliest),
+        })
+    }
+
+    /// A read connection, preferring an idle one
+    fn reader(&self) -> MutexGuard<'_, Connection> {
+        for reader in &self.readers {
+            if let Ok(conn) = reader.try_lock() {
+                return conn;
+            }
+        }
+        let next = self.next_reader.fetch_add(1, Ordering::Relaxed) % self.readers.len();
+        self.readers[next].lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Bytes the database file (and its WAL) take on disk
+    fn file_size(&self) -> u64 {
+        let wal = self.path.with_extension("sqlite-wal");
+        [&self.path, &wal]
+            .iter()
+            .filter_map(|p| std::fs::metadata(p).ok())
+            .map(|m| m.len())
+            .sum()
+    }
+
+    /// Average bytes per stored point, used to estimate per-target sizes
+    fn bytes_per_point(&self, conn: &Connection) -> Result<f64, StorageError> {
+        let points: u64 = conn.query_row("SELECT COUNT(*) FROM points", [], |row| row.get(0))?;
+        Ok(if points == 0 {
+            0.0
+        } else {
+            self.file_size() as f64 / points as f64
+        })
+    }
+}
+
+/// Oldest stored timestamp, read from the `points_time` index
+fn query_earliest(conn: &Connection) -> Result<Option<i64>, StorageError> {
+    Ok(conn.query_row("SELECT MIN(timestamp) FROM points", [], |r| r.get(0))?)
+}
+
+/// Labels as a JSON object with sorted keys, the identity of a series
+fn label_key(labels: &[Label]) -> String {
+    let sorted: BTreeMap<&str, &str> = labels
+        .iter()
+        .map(|l| (l.name.as_str(), l.value.as_str()))
+        .collect();
+    serde_json::to_string(&sorted).unwrap_or_default()
+}
+
+fn parse_label_key(key: &str) -> Vec<Label> {
+    serde_json::from_str::<BTreeMap<String, String>>(key)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(name, value)| Label::new(name, value))
+        .collect()
+}
+
+impl StorageBackend for SqliteStorage {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    fn insert_rows(&self, rows: &[Row]) -> Result<(), StorageError> {
+        let mut conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let mut series_ids = self.series_ids.lock().unwrap_or_else(|e| e.into_inner());
+        // Only cached once committed; a rolled back transaction drops them
+        let mut new_series_ids = HashMap::new();
+        let tx = conn.transaction()?;
+        {
+            let mut find_series =
+                tx.prepare_cached("SELECT id FROM series WHERE metric = ?1 AND labels = ?2")?;
+            let mut insert_series = tx.prepare_cached(
+                "INSERT INTO series (metric, labels, target_id) VALUES (?1, ?2, ?3)",
+            )?;
+            let mut insert_point = tx.prepare_cached(
+                "INSERT INTO points (series_id, timestamp, value) VALUES (?1, ?2, ?3)",
+            )?;
+
+            for row in rows {
+                let key = (row.metric().to_string(), label_key(row.labels()));
+                let cached = series_ids
+                    .get(&key)
+                    .or_else(|| new_series_ids.get(&key))
+                    .copied();
+                let series_id = match cached {
+                    Some(id) => id,
+                    None => {
+                        let existing: Option<i64> = find_series
+                            .query_row(params![key.0, key.1], |r| r.get(0))
+                            .optional()?;
+                        let id = match existing {
+                            Some(id) => id,
+                            None => {
+                                let target_id = row
+                                    .labels()
+                                    .iter()
+                                    .find(|l| l.name == "target_id")
+                                    .map(|l| l.value.as_str());
+                                insert_series.execute(params![key.0, key.1, target_id])?;
+                                tx.last_insert_rowid()
+                            }
+                        };
+                        new_series_ids.insert(key, id);
+                        id
+                    }
+                };
+                let point = row.data_point();
+                insert_point.execute(params![series_id, point.timestamp, point.value])?;
+            }
+        }
+        tx.commit()?;
+        series_ids.extend(new_series_ids);
+
+        if let Some(oldest) = rows.iter().map(|row| row.data_point().timestamp).min() {
+            let mut earliest = self.earliest.lock().unwrap_or_else(|e| e.into_inner());
+            *earliest = Some(earliest.map_or(oldest, |e| e.min(oldest)));
+        }
+        Ok(())
+    }
+
+    fn select(
+        &self,
+        metric: &str,
+        labels: &[Label],
+        start: i64,
+        end: i64,
+    ) -> Result<Vec<DataPoint>, StorageError> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached(
+            "SELECT p.timestamp, p.value FROM points p JOIN series s ON s.id = p.series_id
+             WHERE s.metric = ?1 AND s.labels = ?2 AND p.timestamp >= ?3 AND p.timestamp < ?4
+             ORDER BY p.timestamp",
+        )?;
+        let points = stmt
+            .query_map(params![metric, label_key(labels), start, end], |r| {
+                Ok(DataPoint::new(r.get(0)?, r.get(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(points)
+    }
+
+    fn select_all(
+        &self,
+        metric: &str,
+        start: i64,
+        end: i64,
+    ) -> Result<Vec<(Vec<Label>, Vec<DataPoint>)>, StorageError> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached(
+            "SELECT s.labels, p.timestamp, p.value FROM points p JOIN series s ON s.id = p.series_id
+             WHERE s.metric = ?1 AND p.timestamp >= ?2 AND p.timestamp < ?3
+             ORDER BY s.id, p.timestamp",
+        )?;
+        let mut rows = stmt.query(params![metric, start, end])?;
+
+        let mut series: Vec<(Vec<Label>, Vec<DataPoint>)> = Vec::new();
+        let mut current_key: Option<String> = None;
+        while let Some(row) = rows.next()? {
+            let key: String = row.get(0)?;
+            let point = DataPoint::new(row.get(1)?, row.get(2)?);
+            match (&current_key, series.last_mut()) {
+                (Some(current), Some((_, points))) if *current == key => points.push(point),
+                _ => {
+                    series.push((parse_label_key(&key), vec![point]));
+                    current_key = Some(key);
+                }
+            }
+        }
+        Ok(series)
+    }
+
+    fn stats(&self) -> Result<StorageStatsResponse, StorageError> {
+        let conn = self.reader();
+        let bytes_per_point = self.bytes_per_point(&conn)?;
+        let mut stmt = conn.prepare_cached(
+            "SELECT s.target_id, COUNT(*), MIN(p.timestamp), MAX(p.timestamp)
+             FROM points p JOIN series s ON s.id = p.series_id
+             WHERE s.target_id IS NOT NULL
+             GROUP BY s.target_id ORDER BY s.target_id",
+        )?;
+        let targets = stmt
+            .query_map([], |r| {
+                let data_point_count: u64 = r.get(1)?;
+                Ok(TargetStorageStats {
+                    target_id: r.get(0)?,
+                    // SQLite does not account space per row, so the file
+                    // size is split by point count
+                    size_bytes: (data_point_count as f64 * bytes_per_point) as u64,
+                    data_point_count,
+                    earliest_timestamp: r.get(2)?,
+                    latest_timestamp: r.get(3)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(StorageStatsResponse {
+            total_size_bytes: self.file_size(),
+            targets,
+        })
+    }
+
+    fn prune(
+        &self,
+        targets: &[Target],
+        default_days: u32,
+        now: i64,
+        dry_run: bool,
+    ) -> Result<PruneReport, StorageError> {
+        let mut conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let bytes_per_point = self.bytes_per_point(&conn)?;
+        let mut report = PruneReport {
+            ran_at: now,
+            dry_run,
+            ..Default::default()
+        };
+
+        let target_ids: Vec<String> = conn
+            .prepare("SELECT DISTINCT COALESCE(target_id, '') FROM series")?
+            .query_map([], |r| r.get(0))?
+            .collect::<Result<_, _>>()?;
+
+        let tx = conn.transaction()?;
+        for target_id in target_ids {
+            let days = retention_days(&target_id, targets, default_days);
+            if days == 0 {
+                continue;
+            }
+            let cutoff = now - days as i64 * 86400;
+            let filter = "series_id IN (SELECT id FROM series WHERE COALESCE(target_id, '') = ?1)
+                 AND timestamp < ?2";
+            let expired: u64 = if dry_run {
+                tx.query_row(
+                    &format!("SELECT COUNT(*) FROM points WHERE {}", filter),
+                    params![target_id, cutoff],
+                    |r| r.get(0),
+                )?
+            } else {
+                tx.execute(
+                    &format!("DELETE FROM points WHERE {}", filter),
+                    params![target_id, cutoff],
+                )? as u64
+            };
+            if expired > 0 {
+                report.freed_bytes += (expired as f64 * bytes_per_point) as u64;
+                report.pruned_data_points.insert(target_id, expired);
+            }
+        }
+        tx.commit()?;
+
+        let pruned: u64 = report.pruned_data_points.values().sum();
+        if pruned > 0 {
+            info!(
+                "{} {} expired data points",
+                if dry_run { "Would prune" } else { "Pruned" },
+                pruned
+            );
+        }
+        if pruned > 0 && !dry_run {
+            // Hand the pages of the deleted points back to the file system
+            conn.execute_batch("PRAGMA incremental_vacuum;")?;
+            *self.earliest.lock().unwrap_or_else(|e| e.into_inner()) = query_earliest(&conn)?;
+        }
+        Ok(report)
+    }
+
+    fn earliest_timestamp(&self, now: i64) -> i64 {
+        self.earliest
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .map_or(now, |earliest| earliest.min(now))
+    }
+
+    /// The database is copied with `VACUUM INTO` on a read connection, which
+    /// reads it in one transaction, so writes continue meanwhile; other files
+    /// of the data directory are copied as they are
+    fn snapshot(&self, dest: &Path) -> Result<(), StorageError> {
+        let data_path = self.path.parent().unwrap_or(Path::new("."));
+        copy_dir(data_path, dest, &|path| {
+            path.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with(DATABASE_FILE))
+        })?;
+        let copy = dest.join(DATABASE_FILE);
+        let conn = self.reader();
+        conn.execute("VACUUM INTO ?1", params![copy.to_string_lossy()])?;
+        Ok(())
+    }
+
+    fn close(&self) -> Result<(), StorageError> {
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE); PRAGMA optimize;")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open_temp() -> (SqliteStorage, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("sparkping-sqlite-{}", uuid::Uuid::new_v4()));
+        (SqliteStorage::open(&dir).unwrap(), dir)
+    }
+
+    fn row(target_id: &str, sequence: &str, timestamp: i64, value: f64) -> Row {
+        Row::with_labels(
+            "ping_latency",
+            vec![
+                Label::new("target_id", target_id),
+                Label::new("sequence", sequence),
+            ],
+            DataPoint::new(timestamp, value),
+        )
+    }
+
+    #[test]
+    fn test_insert_and_select() {
+        let (storage, dir) = open_temp();
+        storage
+            .insert_rows(&[
+                row("a", "1", 20, 2.0),
+                row("a", "1", 10, 1.0),
+                row("a", "2", 10, 3.0),
+                row("b", "1", 10, 4.0),
+            ])
+            .unwrap();
+
+        // Label order does not matter
+        let labels = [Label::new("sequence", "1"), Label::new("target_id", "a")];
+        let points = storage.select("ping_latency", &labels, 0, 100).unwrap();
+        let timestamps: Vec<i64> = points.iter().map(|p| p.timestamp).collect();
+        assert_eq!(timestamps, vec![10, 20]);
+        assert!(storage
+            .select("ping_latency", &labels, 0, 20)
+            .unwrap()
+            .iter()
+            .all(|p| p.timestamp < 20));
+
+        let series = storage.select_all("ping_latency", 0, 100).unwrap();
+        assert_eq!(series.len(), 3);
+        assert_eq!(series[0].1.len(), 2);
+        assert!(series[0]
+            .0
+            .iter()
+            .any(|l| l.name == "target_id" && l.value == "a"));
+        assert!(storage
+            .select_all("ping_failed", 0, 100)
+            .unwrap()
+            .is_empty());
+
+        // Series are found again after reopening
+        drop(storage);
+        let storage = SqliteStorage::open(&dir).unwrap();
+        storage.insert_rows(&[row("a", "1", 30, 5.0)]).unwrap();
+        assert_eq!(
+            storage
+                .select("ping_latency", &labels, 0, 100)
+                .unwrap()
+                .len(),
+            3
+        );
+        assert_eq!(storage.earliest_timestamp(1000), 10);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let (storage, dir) = open_temp();
+        storage.insert_rows(&[row("a", "1", 10, 1.0)]).unwrap();
+        std::fs::write(dir.join("downsample.json"), "{}").unwrap();
+
+        let dest = dir.with_extension("snapshot");
+        storage.snapshot(&dest).unwrap();
+        // Written after the snapshot, so not in it
+        storage.insert_rows(&[row("a", "1", 20, 2.0)]).unwrap();
+
+        assert!(dest.join("downsample.json").exists());
+        assert!(!dest.join("sparkping.sqlite-wal").exists());
+        let copy = SqliteStorage::open(&dest).unwrap();
+        assert_eq!(
+            copy.select_all("ping_latency", 0, 100).unwrap()[0].1.len(),
+            1
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        std::fs::remove_dir_all(&dest).unwrap();
+    }
+
+    #[test]
+    fn test_stats_and_prune() {
+        let (storage, dir) = open_temp();
+        let day = 86400;
+        let now = 100 * day;
+        storage
+            .insert_rows(&[
+                row("a", "1", now - 4 * day, 1.0),
+                row("a", "1", now - 10, 1.0),
+                row("b", "1", now - 3 * day, 1.0),
+            ])
+            .unwrap();
+        assert_eq!(storage.earliest_timestamp(now), now - 4 * day);
+
+        let stats = storage.stats().unwrap();
+        assert_eq!(stats.targets.len(), 2);
+        assert_eq!(stats.targets[0].target_id, "a");
+        assert_eq!(stats.targets[0].data_point_count, 2);
+        assert_eq!(stats.targets[0].earliest_timestamp, Some(now - 4 * day));
+
+        // "a" keeps 1 day, "b" falls back to the default of 0 (forever)
+        let targets = vec![Target {
+            id: "a".to_string(),
+            address: "10.0.0.1".to_string(),
+            ping_count: 1,
+            retention_days: Some(1),
+            ..Default::default()
+        }];
+
+        let report = storage.prune(&targets, 0, now, true).unwrap();
+        assert_eq!(report.pruned_data_points.get("a"), Some(&1));
+        assert_eq!(storage.stats().unwrap().targets[0].data_point_count, 2);
+
+        let report = storage.prune(&targets, 0, now, false).unwrap();
+        assert_eq!(report.pruned_data_points.get("a"), Some(&1));
+        assert!(report.pruned_data_points.get("b").is_none());
+        let stats = storage.stats().unwrap();
+        assert_eq!(stats.targets[0].data_point_count, 1);
+        assert_eq!(stats.targets[1].data_point_count, 1);
+        assert_eq!(storage.earliest_timestamp(now), now - 3 * day);
+        // Freed pages were vacuumed
+        let free_pages: i64 = storage
+            .reader()
+            .query_row("PRAGMA freelist_count", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(free_pages, 0);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,567 @@
+pub mod monitor;
+pub mod schedule;
+pub mod supervisor;
+
+use crate::calibration;
+use crate::config::{AppConfig, DatabaseConfig, PingConfig, Target};
+use crate::downsample::Downsampler;
+use crate::encryption::{seal_data_directory, StorageKey};
+use crate::health::health;
+use crate::live::LiveFeed;
+use crate::outages::{outage_row, OutageDetector};
+use crate::ping::{perform_ping, Flow, Probe, ProbeOptions};
+use crate::presence::{read_neighbors, record_presence_events, PresenceTracker};
+use crate::resolver::HostResolver;
+use crate::rollups::RollingAggregator;
+use crate::shutdown::shutdown;
+use crate::storage::{
+    ping_result_rows, write_storage_stats, PendingRows, PingBatch, StorageBackend, WriteBuffer,
+};
+use crate::tasks::monitor::TaskMonitor;
+use crate::tasks::schedule::Schedule;
+use crate::tasks::supervisor::{RestartBackoff, CHECK_INTERVAL};
+use crate::telemetry::telemetry;
+use crate::traceroute::{record_traceroute, traceroute, TracerouteOptions};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::task::AbortHandle;
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, error, info, warn};
+
+/// Start a ping task for a target and return its abort handle.
+/// `stagger_ms` adds an initial delay to avoid all targets pinging simultaneously.
+/// Every result is fed into the shared rolling aggregator and the target's
+/// outage detector and published to live clients; the results of a batch are
+/// written to tsink together through `writer` once the batch is done, or
+/// queued there if the task is aborted mid-batch.
+/// Hostname targets are resolved once per cycle, cached for `dns_ttl` seconds.
+/// Targets with a `schedule` wait for their next window instead of pinging
+/// outside it. Once shutdown is requested the task ends after its current batch.
+/// Its start, batches and schedule waits are reported to `monitor`.
+#[allow(clippy::too_many_arguments)]
+pub fn start_ping_task(
+    target: &Target,
+    storage: Arc<dyn StorageBackend>,
+    writer: Arc<WriteBuffer>,
+    rollups: Arc<RollingAggregator>,
+    live: Arc<LiveFeed>,
+    monitor: Arc<TaskMonitor>,
+    ping_config: &PingConfig,
+    stagger_ms: u64,
+) -> AbortHandle {
+    let target_id = target.id.clone();
+    let target_address = target.address.clone();
+    let target_name = target.name.clone();
+    let ping_count = target.ping_count;
+    let ping_interval = target.ping_interval;
+    let probe = Probe::for_target(target, ping_config.socket_type);
+    let options = ProbeOptions::for_target(target, ping_config);
+    let schedule = Schedule::for_target(target, ping_config);
+    let flows: Vec<Option<Flow>> = (1..=ping_count)
+        .map(|sequence| Flow::for_sequence(target, sequence))
+        .collect();
+    let correction = calibration::correction_for(ping_config.socket_type);
+    let mut resolver = HostResolver::new(&target.address, ping_config.dns_ttl);
+    let mut outages = OutageDetector::new(ping_config.outage_after);
+    let mut batch = PingBatch::for_target(target);
+    monitor.started(
+        &target_id,
+        ping_interval + ping_count as u64 * options.timeout.as_secs().max(1),
+        stagger_ms.div_ceil(1000),
+        chrono::Utc::now().timestamp(),
+    );
+
+    // Counts as in-flight work until the task ends or is aborted
+    let work = shutdown().track();
+    let handle = tokio::spawn(async move {
+        let _work = work;
+
+        // Stagger start to avoid thundering herd on sockets
+        if stagger_ms > 0 {
+            tokio::select! {
+                _ = tokio::time::sleep(std::time::Duration::from_millis(stagger_ms)) => {}
+                _ = shutdown().requested() => return,
+            }
+        }
+        loop {
+            if let Some(wait) = schedule.wait_until_active(chrono::Utc::now()) {
+                debug!(
+                    "{} is outside its schedule, next window in {}s",
+                    target_address,
+                    wait.as_secs()
+                );
+                monitor.record_idle(
+                    &target_id,
+                    chrono::Utc::now().timestamp() + wait.as_secs() as i64,
+                );
+                tokio::select! {
+                    _ = tokio::time::sleep(wait) => continue,
+                    _ = shutdown().requested() => break,
+                }
+            }
+            let resolved = resolver.resolve().await;
+
+            // Perform ping_count pings back-to-back (no delay between them)
+            // Queued for writing if the task is aborted mid-batch
+            let mut rows = PendingRows::new(Arc::clone(&writer), Arc::clone(&storage));
+            let mut batch_start = None;
+            for sequence in 1..=ping_count {
+                let mut result = perform_ping(
+                    &target_id,
+                    &target_address,
+                    resolved.clone(),
+                    sequence,
+                    &target_name,
+                    probe,
+                    options,
+                    flows[sequence as usize - 1],
+                )
+                .await;
+                if let Some(correction_ms) = correction {
+                    calibration::apply(&mut result, probe, correction_ms);
+                }
+                telemetry().record_ping(result.success);
+
+                // Result, jitter, and batch loss rows, written after the batch
+                rows.extend(ping_result_rows(&result, &mut batch));
+
+                let timestamp = result.timestamp.timestamp();
+                batch_start.get_or_insert(timestamp);
+                if let Some(transition) = outages.observe(result.success, timestamp) {
+                    info!("Outage of {}: {:?}", target_address, transition);
+                    rows.extend(vec![outage_row(&result, transition)]);
+                }
+
+                rollups.record(&target_id, timestamp, result.latency_ms);
+                live.publish(&result);
+            }
+
+            if let Err(e) = rows.write().await {
+                error!("Error writing ping results to tsink: {}", e);
+                health().record_write_error(batch_start.unwrap_or_default());
+            }
+            monitor.record_iteration(&target_id, chrono::Utc::now().timestamp());
+
+            // Wait ping_interval seconds before next batch of pings
+            tokio::select! {
+                _ = tokio::time::sleep(std::time::Duration::from_secs(ping_interval)) => {}
+                _ = shutdown().requested() => {
+                    debug!("Ping task for {} stopped for shutdown", target_address);
+                    break;
+                }
+            }
+        }
+    })
+    .abort_handle();
+
+    handle
+}
+
+/// Start a task that restarts ping tasks that crashed or stalled, with a
+/// backoff per target (see `supervisor.rs`). Restarts are logged and counted
+/// in the self-metrics.
+pub fn start_supervisor_task(
+    config: Arc<RwLock<AppConfig>>,
+    task_handles: Arc<RwLock<HashMap<String, AbortHandle>>>,
+    monitor: Arc<TaskMonitor>,
+    storage: Arc<dyn StorageBackend>,
+    writer: Arc<WriteBuffer>,
+    rollups: Arc<RollingAggregator>,
+    live: Arc<LiveFeed>,
+) -> AbortHandle {
+    tokio::spawn(async move {
+        let mut backoff = RestartBackoff::new();
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(CHECK_INTERVAL) => {}
+                _ = shutdown().requested() => break,
+            }
+            // Ping tasks end by themselves once shutdown is requested
+            if shutdown().is_requested() {
+                break;
+            }
+
+            // Handlers replace handles while holding this lock, so a
+            // finished handle in the map belongs to a task that died
+            let Ok(mut handles) = task_handles.write() else {
+                error!("Failed to access task handles for supervision");
+                continue;
+            };
+            let Ok((targets, ping_config)) =
+                config.read().map(|c| (c.targets.clone(), c.ping.clone()))
+            else {
+                error!("Failed to read config for task supervision");
+                continue;
+            };
+
+            let now = chrono::Utc::now().timestamp();
+            for target in targets.iter().filter(|t| !t.paused) {
+                let Some(handle) = handles.get(&target.id) else {
+                    continue;
+                };
+                let problem = if handle.is_finished() {
+                    "stopped"
+                } else if monitor.health(&target.id, true, false, now).stalled {
+                    "stalled"
+                } else {
+                    continue;
+                };
+                if !backoff.try_restart(&target.id, now) {
+                    debug!(
+                        "Ping task of {} {}, next restart in {}s",
+                        target.id,
+                        problem,
+                        backoff.remaining(&target.id, now)
+                    );
+                    continue;
+                }
+
+                warn!("Ping task of {} {}, restarting it", target.id, problem);
+                handle.abort();
+                let handle = start_ping_task(
+                    target,
+                    Arc::clone(&storage),
+                    Arc::clone(&writer),
+                    Arc::clone(&rollups),
+                    Arc::clone(&live),
+                    Arc::clone(&monitor),
+                    &ping_config,
+                    0,
+                );
+                handles.insert(target.id.clone(), handle);
+                telemetry().record_ping_task_restart();
+            }
+            backoff.retain(|id| handles.contains_key(id));
+        }
+    })
+    .abort_handle()
+}
+
+/// Start a task that writes the rows queued in `writer` every flush
+/// interval (`[database] write_flush_ms`, read at startup). Nothing is
+/// started without a flush interval, as batches are then written directly.
+pub fn start_write_flush_task(
+    writer: Arc<WriteBuffer>,
+    storage: Arc<dyn StorageBackend>,
+) -> Option<AbortHandle> {
+    let interval = writer.flush_interval();
+    if interval.is_zero() {
+        return None;
+    }
+    let handle = tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            let writer = Arc::clone(&writer);
+            let storage = Arc::clone(&storage);
+            let result = tokio::task::spawn_blocking(move || {
+                writer.flush(&*storage).map_err(|e| e.to_string())
+            })
+            .await;
+            match result {
+                Ok(Ok(count)) if count > 0 => debug!("Wrote {} buffered rows", count),
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => {
+                    error!("Error writing buffered ping results to tsink: {}", e);
+                    health().record_write_error(chrono::Utc::now().timestamp());
+                }
+                Err(e) => error!("Write flush task join error: {}", e),
+            }
+        }
+    });
+    Some(handle.abort_handle())
+}
+
+/// Seconds between config reads while a background task is disabled
+const DISABLED_POLL_SECS: u64 = 60;
+
+/// Wait until `enabled` holds for the current config. Disabled tasks call
+/// this instead of exiting so that turning them on in the config takes
+/// effect without a restart.
+pub async fn wait_until_enabled(config: &RwLock<AppConfig>, enabled: impl Fn(&AppConfig) -> bool) {
+    loop {
+        if config.read().is_ok_and(|c| enabled(&c)) {
+            return;
+        }
+        tokio::time::sleep(Duration::from_secs(DISABLED_POLL_SECS)).await;
+    }
+}
+
+/// Start a task that periodically records per-target storage sizes into tsink
+/// (`[database] stats_interval`, re-read every cycle; 0 disables recording).
+pub fn start_storage_stats_task(
+    storage: Arc<dyn StorageBackend>,
+    config: Arc<RwLock<AppConfig>>,
+) -> AbortHandle {
+    tokio::spawn(async move {
+        loop {
+            wait_until_enabled(&config, |c| c.database.stats_interval != 0).await;
+
+            let settings = config
+                .read()
+                .map(|c| (c.database.stats_interval, c.targets.clone()))
+                .ok();
+            let Some((interval, targets)) = settings else {
+                error!("Failed to read config for storage stats recording");
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                continue;
+            };
+
+            let storage_for_write = Arc::clone(&storage);
+            let result = tokio::task::spawn_blocking(move || {
+                let stats = storage_for_write.stats().map_err(|e| e.to_string())?;
+                let now = chrono::Utc::now().timestamp();
+                write_storage_stats(&*storage_for_write, &stats, &targets, now)
+                    .map_err(|e| e.to_string())?;
+                Ok::<usize, String>(stats.targets.len())
+            })
+            .await;
+
+            match result {
+                Ok(Ok(count)) => debug!("Recorded storage size for {} targets", count),
+                Ok(Err(e)) => error!("Error recording storage stats: {}", e),
+                Err(e) => error!("Storage stats task join error: {}", e),
+            }
+
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+        }
+    })
+    .abort_handle()
+}
+
+/// Start a task that periodically prunes ping data past its target's retention
+/// (`[database] prune_interval`, re-read every cycle; 0 disables pruning).
+pub fn start_prune_task(
+    storage: Arc<dyn StorageBackend>,
+    writer: Arc<WriteBuffer>,
+    config: Arc<RwLock<AppConfig>>,
+) -> AbortHandle {
+    tokio::spawn(async move {
+        loop {
+            wait_until_enabled(&config, |c| c.database.prune_interval != 0).await;
+
+            let settings = config
+                .read()
+                .map(|c| {
+                    (
+                        c.database.prune_interval,
+                        c.database.retention_days,
+                        c.targets.clone(),
+                    )
+                })
+                .ok();
+            let Some((interval, default_days, targets)) = settings else {
+                error!("Failed to read config for pruning");
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                continue;
+            };
+
+            let storage = Arc::clone(&storage);
+            let result = tokio::task::spawn_blocking(move || {
+                let now = chrono::Utc::now().timestamp();
+                storage.prune(&targets, default_days, now, false)
+            })
+            .await;
+
+            match result {
+                Ok(Ok(report)) => {
+                    writer.aggregate_cache().clear();
+                    debug!(
+                        "Prune run finished ({} partitions removed, {} rewritten)",
+                        report.removed_partitions, report.rewritten_partitions
+                    )
+                }
+                Ok(Err(e)) => error!("Error pruning expired data: {}", e),
+                Err(e) => error!("Prune task join error: {}", e),
+            }
+
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+        }
+    })
+    .abort_handle()
+}
+
+/// Start a task that seals a snapshot of the decrypted working directory
+/// back into `[database] path` every `[database.encryption] seal_interval`
+/// seconds (read at startup; 0 only seals on shutdown).
+pub fn start_seal_task(
+    database: DatabaseConfig,
+    key: Arc<StorageKey>,
+    storage: Arc<dyn StorageBackend>,
+) -> AbortHandle {
+    tokio::spawn(async move {
+        let interval = database.encryption.seal_interval;
+        if interval == 0 {
+            return;
+        }
+        loop {
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+
+            let database = database.clone();
+            let key = Arc::clone(&key);
+            let storage = Arc::clone(&storage);
+            let result = tokio::task::spawn_blocking(move || {
+                seal_data_directory(&database, &key, &*storage)
+            })
+            .await;
+            match result {
+                Ok(Ok(count)) => debug!("Sealed {} files of the data directory", count),
+                Ok(Err(e)) => error!("Error sealing data directory: {}", e),
+                Err(e) => error!("Seal task join error: {}", e),
+            }
+        }
+    })
+    .abort_handle()
+}
+
+/// Seconds between downsampling runs; each run rolls up all completed hours
+const DOWNSAMPLE_INTERVAL_SECS: u64 = 300;
+
+/// Start a task that rolls completed hours of raw ping data up into 1m/1h
+/// series (`[database] downsample`, re-read every cycle).
+pub fn start_downsample_task(
+    downsampler: Arc<Downsampler>,
+    storage: Arc<dyn StorageBackend>,
+    config: Arc<RwLock<AppConfig>>,
+) -> AbortHandle {
+    tokio::spawn(async move {
+        loop {
+            let settings = config
+                .read()
+                .map(|c| (c.database.downsample, c.targets.clone()))
+                .ok();
+            let Some((enabled, targets)) = settings else {
+                error!("Failed to read config for downsampling");
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                continue;
+            };
+
+            if enabled {
+                let downsampler = Arc::clone(&downsampler);
+                let storage = Arc::clone(&storage);
+                let result = tokio::task::spawn_blocking(move || {
+                    let now = chrono::Utc::now().timestamp();
+                    downsampler.run_once(&*storage, &targets, now)
+                })
+                .await;
+
+                match result {
+                    Ok(Ok(hours)) => debug!("Downsampling run finished ({} hours)", hours),
+                    Ok(Err(e)) => error!("Error downsampling ping data: {}", e),
+                    Err(e) => error!("Downsampling task join error: {}", e),
+                }
+            }
+
+            tokio::time::sleep(Duration::from_secs(DOWNSAMPLE_INTERVAL_SECS)).await;
+        }
+    })
+    .abort_handle()
+}
+
+/// Start a task that polls the kernel neighbor table and records devices
+/// arriving and departing (`[presence]`, re-read every poll).
+pub fn start_presence_task(
+    tracker: Arc<PresenceTracker>,
+    storage: Arc<dyn StorageBackend>,
+    config: Arc<RwLock<AppConfig>>,
+) -> AbortHandle {
+    tokio::spawn(async move {
+        loop {
+            wait_until_enabled(&config, |c| c.presence.enabled).await;
+
+            let Some(settings) = config.read().map(|c| c.presence.clone()).ok() else {
+                error!("Failed to read config for presence detection");
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                continue;
+            };
+
+            let tracker = Arc::clone(&tracker);
+            let storage = Arc::clone(&storage);
+            let result = tokio::task::spawn_blocking(move || {
+                let neighbors = read_neighbors()?;
+                let now = chrono::Utc::now().timestamp();
+                let events = tracker.observe(&neighbors, now, settings.away_after);
+                record_presence_events(&*storage, &events)
+                    .map_err(|e| std::io::Error::other(e.to_string()))?;
+                Ok::<_, std::io::Error>(events)
+            })
+            .await;
+
+            match result {
+                Ok(Ok(events)) => {
+                    for event in events {
+                        info!(
+                            "Presence: {} ({}) {:?}",
+                            event.mac, event.address, event.change
+                        );
+                    }
+                }
+                Ok(Err(e)) => error!("Error reading neighbor table: {}", e),
+                Err(e) => error!("Presence task join error: {}", e),
+            }
+
+            tokio::time::sleep(Duration::from_secs(settings.poll_interval.max(1))).await;
+        }
+    })
+    .abort_handle()
+}
+
+/// Start the background task running scheduled traceroutes.
+///
+/// Every `[traceroute] interval` seconds, each selected target (paused ones
+/// excepted) is traced once, one target at a time, and its hops are stored.
+pub fn start_traceroute_task(
+    storage: Arc<dyn StorageBackend>,
+    config: Arc<RwLock<AppConfig>>,
+) -> AbortHandle {
+    tokio::spawn(async move {
+        loop {
+            wait_until_enabled(&config, |c| c.traceroute.enabled).await;
+
+            let Some((settings, targets, socket_type)) = config
+                .read()
+                .map(|c| (c.traceroute.clone(), c.targets.clone(), c.ping.socket_type))
+                .ok()
+            else {
+                error!("Failed to read config for scheduled traceroutes");
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                continue;
+            };
+
+            let options = TracerouteOptions {
+                max_hops: settings.max_hops,
+                probes_per_hop: settings.probes_per_hop,
+                timeout: Duration::from_millis(settings.timeout_ms),
+                socket_type,
+            };
+            for target in targets
+                .iter()
+                .filter(|t| !t.paused && settings.includes(&t.id))
+            {
+                let ip = match HostResolver::new(&target.address, 0).resolve().await {
+                    Ok(ip) => ip,
+                    Err(e) => {
+                        error!("Traceroute to {} skipped: {}", target.address, e);
+                        continue;
+                    }
+                };
+                let timestamp = chrono::Utc::now().timestamp();
+                match traceroute(ip, options).await {
+                    Ok(hops) => {
+                        debug!("Traceroute to {}: {} hops", target.address, hops.len());
+                        if let Err(e) = record_traceroute(&*storage, &target.id, timestamp, &hops) {
+                            error!("Error storing traceroute to {}: {}", target.address, e);
+                            health().record_write_error(timestamp);
+                        }
+                    }
+                    Err(e) => error!("Traceroute to {} failed: {}", target.address, e),
+                }
+            }
+
+            tokio::time::sleep(Duration::from_secs(settings.interval.max(60))).await;
+        }
+    })
+    .abort_handle()
+}
@@ -0,0 +1,190 @@
+//! Progress of the per-target ping tasks.
+//!
+//! Each ping task reports when it starts, every completed batch and every
+//! wait for its schedule. A task that panicked ends without anyone noticing
+//! (its abort handle shows it finished); one that hangs stops completing
+//! batches and counts as stalled once its last progress is `STALL_CYCLES`
+//! cycles old. Both show in GET /api/targets/:id/task; the supervisor
+//! (`supervisor.rs`) restarts such tasks, as does POST
+//! /api/targets/:id/restart right away.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use utoipa::ToSchema;
+
+/// Cycles without a completed batch after which a running task is stalled
+pub const STALL_CYCLES: i64 = 3;
+
+/// Progress reported by a task
+#[derive(Debug, Clone)]
+struct TaskProgress {
+    started_at: i64,
+    /// No batch is expected before this (start delay)
+    expected_from: i64,
+    /// Expected seconds per batch, including the interval
+    cycle_seconds: u64,
+    last_iteration: Option<i64>,
+    /// End of the current wait for the schedule
+    idle_until: Option<i64>,
+    iterations: u64,
+    restarts: u32,
+}
+
+impl TaskProgress {
+    /// Latest time the task was known to be on track
+    fn last_progress(&self) -> i64 {
+        self.expected_from
+            .max(self.last_iteration.unwrap_or(i64::MIN))
+            .max(self.idle_until.unwrap_or(i64::MIN))
+    }
+}
+
+/// Health of a target's ping task
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct TaskHealth {
+    pub target_id: String,
+    /// Whether the task is alive; false for paused targets and for tasks
+    /// that ended, e.g. by a panic
+    pub running: bool,
+    pub paused: bool,
+    /// Running without a completed batch for `STALL_CYCLES` cycles
+    pub stalled: bool,
+    /// Unix timestamp in seconds the task was last started at
+    pub started_at_unix: Option<i64>,
+    /// Unix timestamp in seconds of the last completed batch
+    pub last_iteration_unix: Option<i64>,
+    /// Unix timestamp in seconds the current wait for the schedule ends at
+    pub idle_until_unix: Option<i64>,
+    /// Batches completed since the last start
+    pub iterations: u64,
+    /// Expected seconds per batch: the interval plus the probe timeouts
+    pub cycle_seconds: Option<u64>,
+    /// Times the task was started again (target updates, resumes, restarts)
+    pub restarts: u32,
+}
+
+/// Shared progress of all ping tasks
+#[derive(Debug, Default)]
+pub struct TaskMonitor {
+    tasks: RwLock<HashMap<String, TaskProgress>>,
+}
+
+impl TaskMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A task was (re)started at `now`, with its first batch due after
+    /// `delay_seconds`
+    pub fn started(&self, target_id: &str, cycle_seconds: u64, delay_seconds: u64, now: i64) {
+        if let Ok(mut tasks) = self.tasks.write() {
+            let restarts = tasks
+                .get(target_id)
+                .map_or(0, |p| p.restarts.saturating_add(1));
+            tasks.insert(
+                target_id.to_string(),
+                TaskProgress {
+                    started_at: now,
+                    expected_from: now + delay_seconds as i64,
+                    cycle_seconds,
+                    last_iteration: None,
+                    idle_until: None,
+                    iterations: 0,
+                    restarts,
+                },
+            );
+        }
+    }
+
+    /// A batch completed at `now`
+    pub fn record_iteration(&self, target_id: &str, now: i64) {
+        if let Ok(mut tasks) = self.tasks.write() {
+            if let Some(progress) = tasks.get_mut(target_id) {
+                progress.last_iteration = Some(now);
+                progress.idle_until = None;
+                progress.iterations += 1;
+            }
+        }
+    }
+
+    /// The task waits for its schedule until `until`
+    pub fn record_idle(&self, target_id: &str, until: i64) {
+        if let Ok(mut tasks) = self.tasks.write() {
+            if let Some(progress) = tasks.get_mut(target_id) {
+                progress.idle_until = Some(until);
+            }
+        }
+    }
+
+    /// Forget a task (e.g. when its target is deleted)
+    pub fn remove(&self, target_id: &str) {
+        if let Ok(mut tasks) = self.tasks.write() {
+            tasks.remove(target_id);
+        }
+    }
+
+    /// Health of a target's task as of `now`; `running` tells whether its
+    /// abort handle exists and has not finished
+    pub fn health(&self, target_id: &str, running: bool, paused: bool, now: i64) -> TaskHealth {
+        let progress = self
+            .tasks
+            .read()
+            .ok()
+            .and_then(|tasks| tasks.get(target_id).cloned());
+        let stalled = running
+            && progress.as_ref().is_some_and(|p| {
+                now - p.last_progress() > STALL_CYCLES * p.cycle_seconds.max(1) as i64
+            });
+        TaskHealth {
+            target_id: target_id.to_string(),
+            running,
+            paused,
+            stalled,
+            started_at_unix: progress.as_ref().map(|p| p.started_at),
+            last_iteration_unix: progress.as_ref().and_then(|p| p.last_iteration),
+            idle_until_unix: progress.as_ref().and_then(|p| p.idle_until),
+            iterations: progress.as_ref().map_or(0, |p| p.iterations),
+            cycle_seconds: progress.as_ref().map(|p| p.cycle_seconds),
+            restarts: progress.map_or(0, |p| p.restarts),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stall_detection() {
+        let monitor = TaskMonitor::new();
+        let now = 10_000;
+        monitor.started("t1", 10, 5, now);
+
+        // Within the start delay and the first cycles
+        assert!(!monitor.health("t1", true, false, now + 30).stalled);
+        // No batch for more than three cycles after the delay
+        assert!(monitor.health("t1", true, false, now + 36).stalled);
+        // A finished task is reported as not running, not as stalled
+        let health = monitor.health("t1", false, false, now + 36);
+        assert!(!health.running && !health.stalled);
+
+        monitor.record_iteration("t1", now + 40);
+        let health = monitor.health("t1", true, false, now + 60);
+        assert!(!health.stalled);
+        assert_eq!(health.iterations, 1);
+        assert_eq!(health.last_iteration_unix, Some(now + 40));
+
+        // Waiting for the schedule is progress until the wait ends
+        monitor.record_idle("t1", now + 3600);
+        assert!(!monitor.health("t1", true, false, now + 3000).stalled);
+        assert!(monitor.health("t1", true, false, now + 3631).stalled);
+
+        // Starting again counts a restart and resets the progress
+        monitor.started("t1", 10, 0, now + 4000);
+        let health = monitor.health("t1", true, false, now + 4000);
+        assert_eq!(health.restarts, 1);
+        assert_eq!(health.iterations, 0);
+        assert!(!health.stalled);
+    }
+}
@@ -0,0 +1,291 @@
+//! Ping schedules of targets.
+//!
+//! A target with `schedule` is only pinged inside its windows, e.g.
+//! `schedule = ["mon-fri 08:00-18:00"]` for an office VPN endpoint, so the
+//! hours it is expected to be down leave a gap instead of failed pings in its
+//! uptime statistics. Windows are in `[ping] timezone` (default: the system
+//! time zone); a window that ends before it starts runs past midnight and
+//! belongs to the day it starts on.
+
+use crate::api::ping::calendar::parse_tz;
+use crate::config::{PingConfig, Target};
+use chrono::{DateTime, Datelike, Local, Timelike, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+/// How far ahead the next window start is searched; every window recurs
+/// within a week
+const MAX_SCAN_MINUTES: i64 = 8 * MINUTES_PER_DAY as i64;
+
+/// Time window of a schedule: `[<days>] HH:MM-HH:MM`, where days are a
+/// comma-separated list of days or day ranges (e.g. `mon-fri`, `sat,sun`,
+/// `fri-mon`) and default to every day
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ScheduleWindow {
+    /// The window as written, with whitespace collapsed
+    text: String,
+    /// Days the window starts on, from Monday
+    days: [bool; 7],
+    /// Start in minutes since midnight
+    start: u32,
+    /// End in minutes since midnight (1440 for `24:00`)
+    end: u32,
+}
+
+impl ScheduleWindow {
+    /// Whether the window covers `minute` (since midnight) of the day with
+    /// index `day` (from Monday)
+    fn contains(&self, day: usize, minute: u32) -> bool {
+        if self.start < self.end {
+            self.days[day] && (self.start..self.end).contains(&minute)
+        } else {
+            // Overnight: from the start on its day until the end on the next
+            (self.days[day] && minute >= self.start)
+                || (self.days[(day + 6) % 7] && minute < self.end)
+        }
+    }
+}
+
+impl FromStr for ScheduleWindow {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let invalid = || {
+            format!(
+                "Invalid schedule window '{}' (expected e.g. \"mon-fri 08:00-18:00\")",
+                text
+            )
+        };
+        let parts: Vec<&str> = text.split_whitespace().collect();
+        let (days, times) = match parts.as_slice() {
+            [times] => ([true; 7], *times),
+            [days, times] => (parse_days(days).ok_or_else(invalid)?, *times),
+            _ => return Err(invalid()),
+        };
+        let (start, end) = times.split_once('-').ok_or_else(invalid)?;
+        let start = parse_time(start)
+            .filter(|&start| start < MINUTES_PER_DAY)
+            .ok_or_else(invalid)?;
+        let end = parse_time(end).ok_or_else(invalid)?;
+        if start == end {
+            return Err(format!(
+                "Schedule window '{}' is empty (use 00:00-24:00 for whole days)",
+                text
+            ));
+        }
+        Ok(Self {
+            text: parts.join(" "),
+            days,
+            start,
+            end,
+        })
+    }
+}
+
+impl fmt::Display for ScheduleWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl TryFrom<String> for ScheduleWindow {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, String> {
+        text.parse()
+    }
+}
+
+impl From<ScheduleWindow> for String {
+    fn from(window: ScheduleWindow) -> Self {
+        window.text
+    }
+}
+
+/// Days of a window, e.g. `mon-fri` or `sat,sun`; ranges may wrap past Sunday
+fn parse_days(text: &str) -> Option<[bool; 7]> {
+    let mut days = [false; 7];
+    for item in text.split(',') {
+        let (first, last) = item.split_once('-').unwrap_or((item, item));
+        let first = first.parse::<Weekday>().ok()?.num_days_from_monday() as usize;
+        let last = last.parse::<Weekday>().ok()?.num_days_from_monday() as usize;
+        let mut day = first;
+        loop {
+            days[day] = true;
+            if day == last {
+                break;
+            }
+            day = (day + 1) % 7;
+        }
+    }
+    Some(days)
+}
+
+/// Minutes since midnight of `HH:MM` (up to `24:00`)
+fn parse_time(text: &str) -> Option<u32> {
+    let (hours, minutes) = text.split_once(':')?;
+    let hours: u32 = hours.parse().ok()?;
+    let minutes: u32 = minutes.parse().ok()?;
+    if minutes >= 60 || hours * 60 + minutes > MINUTES_PER_DAY {
+        return None;
+    }
+    Some(hours * 60 + minutes)
+}
+
+/// When a target's ping task may ping
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    windows: Vec<ScheduleWindow>,
+    /// Zone of the windows; None for the system time zone
+    tz: Option<Tz>,
+}
+
+impl Schedule {
+    /// Schedule of a target in `[ping] timezone`
+    pub fn for_target(target: &Target, ping_config: &PingConfig) -> Self {
+        Self {
+            windows: target.schedule.clone(),
+            tz: ping_config
+                .timezone
+                .as_deref()
+                .and_then(|name| parse_tz(name).ok()),
+        }
+    }
+
+    /// Whether `now` is inside a window; always true without windows
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        if self.windows.is_empty() {
+            return true;
+        }
+        let (day, minute) = match self.tz {
+            Some(tz) => day_and_minute(&now.with_timezone(&tz)),
+            None => day_and_minute(&now.with_timezone(&Local)),
+        };
+        self.windows.iter().any(|w| w.contains(day, minute))
+    }
+
+    /// Time until the next window starts, or None if `now` is inside one
+    pub fn wait_until_active(&self, now: DateTime<Utc>) -> Option<Duration> {
+        if self.is_active(now) {
+            return None;
+        }
+        // Windows start on whole local minutes, which are whole UTC minutes
+        let next_minute = now.timestamp() - now.timestamp().rem_euclid(60) + 60;
+        let start = (0..MAX_SCAN_MINUTES)
+            .map(|i| next_minute + i * 60)
+            .find(|&t| DateTime::from_timestamp(t, 0).is_some_and(|t| self.is_active(t)))
+            .unwrap_or(next_minute);
+        Some(Duration::from_millis(
+            (start * 1000 - now.timestamp_millis()).max(0) as u64,
+        ))
+    }
+}
+
+fn day_and_minute<T: Datelike + Timelike>(time: &T) -> (usize, u32) {
+    (
+        time.weekday().num_days_from_monday() as usize,
+        time.hour() * 60 + time.minute(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn schedule(windows: &[&str], tz: &str) -> Schedule {
+        Schedule {
+            windows: windows.iter().map(|w| w.parse().unwrap()).collect(),
+            tz: Some(parse_tz(tz).unwrap()),
+        }
+    }
+
+    /// 2024-01-01 was a Monday
+    fn utc(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, day, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn test_parse_windows() {
+        let window: ScheduleWindow = "  mon-fri   08:00-18:00 ".parse().unwrap();
+        assert_eq!(window.to_string(), "mon-fri 08:00-18:00");
+        assert_eq!(window.days, [true, true, true, true, true, false, false]);
+        assert_eq!((window.start, window.end), (480, 1080));
+
+        let window: ScheduleWindow = "Fri-Mon,wed 22:00-24:00".parse().unwrap();
+        assert_eq!(window.days, [true, false, true, false, true, true, true]);
+        assert_eq!(window.end, MINUTES_PER_DAY);
+
+        let window: ScheduleWindow = "00:00-24:00".parse().unwrap();
+        assert_eq!(window.days, [true; 7]);
+
+        for invalid in [
+            "",
+            "mon-fri",
+            "mon-fri 8-18",
+            "weekdays 08:00-18:00",
+            "mon 08:00-25:00",
+            "mon 08:60-09:00",
+            "mon 24:00-01:00",
+            "mon 08:00-08:00",
+            "mon fri 08:00-18:00",
+        ] {
+            assert!(invalid.parse::<ScheduleWindow>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_office_hours() {
+        let office = schedule(&["mon-fri 08:00-18:00"], "UTC");
+        assert!(office.is_active(utc(1, 8, 0)));
+        assert!(office.is_active(utc(5, 17, 59)));
+        assert!(!office.is_active(utc(5, 18, 0)));
+        assert!(!office.is_active(utc(1, 7, 59)));
+        // Saturday
+        assert!(!office.is_active(utc(6, 12, 0)));
+
+        // In Berlin (UTC+1 in winter) the window opens at 07:00 UTC
+        let berlin = schedule(&["mon-fri 08:00-18:00"], "Europe/Berlin");
+        assert!(berlin.is_active(utc(1, 7, 0)));
+        assert!(!berlin.is_active(utc(1, 17, 0)));
+    }
+
+    #[test]
+    fn test_overnight_window() {
+        let night = schedule(&["fri 22:00-06:00"], "UTC");
+        assert!(night.is_active(utc(5, 23, 0)));
+        assert!(night.is_active(utc(6, 5, 59)));
+        assert!(!night.is_active(utc(6, 6, 0)));
+        // Thursday night is not in the window
+        assert!(!night.is_active(utc(5, 3, 0)));
+    }
+
+    #[test]
+    fn test_wait_until_active() {
+        let office = schedule(&["mon-fri 08:00-18:00"], "UTC");
+        assert_eq!(office.wait_until_active(utc(1, 9, 0)), None);
+        assert_eq!(
+            office.wait_until_active(utc(1, 7, 30)),
+            Some(Duration::from_secs(30 * 60))
+        );
+        // Friday evening waits for Monday morning
+        assert_eq!(
+            office.wait_until_active(utc(5, 18, 0)),
+            Some(Duration::from_secs(62 * 3600))
+        );
+        let now = utc(1, 7, 59) + chrono::Duration::seconds(30);
+        assert_eq!(office.wait_until_active(now), Some(Duration::from_secs(30)));
+
+        let always = Schedule {
+            windows: Vec::new(),
+            tz: None,
+        };
+        assert_eq!(always.wait_until_active(utc(6, 3, 0)), None);
+    }
+}
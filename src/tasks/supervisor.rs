@@ -0,0 +1,121 @@
+//! Automatic restarts of crashed or stalled ping tasks.
+//!
+//! Every `CHECK_INTERVAL` the supervisor looks at the task of each unpaused
+//! target: one whose abort handle finished (a panic ends a task silently)
+//! or that the `TaskMonitor` reports as stalled is aborted and started
+//! again. Only tasks with a handle are considered; a missing handle means a
+//! target is being added or changed. Repeated restarts of the same target
+//! back off from `MIN_BACKOFF_SECS` to `MAX_BACKOFF_SECS`, so a task that
+//! keeps crashing does not spin, and the backoff starts over once a target
+//! went `MAX_BACKOFF_SECS` without needing a restart.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// How often the ping tasks are checked
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Wait after the first restart of a target before restarting it again
+const MIN_BACKOFF_SECS: i64 = 10;
+
+/// Longest wait between restarts of a target
+const MAX_BACKOFF_SECS: i64 = 600;
+
+/// Restarts of a target so far
+#[derive(Debug, Clone, Copy)]
+struct Restarts {
+    /// Restarts without a quiet period in between
+    attempts: u32,
+    last_at: i64,
+}
+
+impl Restarts {
+    /// Wait after the last restart before the next one is allowed
+    fn backoff(&self) -> i64 {
+        let exponent = self.attempts.saturating_sub(1).min(16);
+        (MIN_BACKOFF_SECS << exponent).min(MAX_BACKOFF_SECS)
+    }
+}
+
+/// Restart backoff per target
+#[derive(Debug, Default)]
+pub struct RestartBackoff {
+    targets: HashMap<String, Restarts>,
+}
+
+impl RestartBackoff {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether a target's task may be restarted at `now`; if so, the restart
+    /// is counted
+    pub fn try_restart(&mut self, target_id: &str, now: i64) -> bool {
+        let attempts = match self.targets.get(target_id) {
+            Some(restarts) if now - restarts.last_at < restarts.backoff() => return false,
+            Some(restarts) if now - restarts.last_at < restarts.backoff() + MAX_BACKOFF_SECS => {
+                restarts.attempts.saturating_add(1)
+            }
+            _ => 1,
+        };
+        self.targets.insert(
+            target_id.to_string(),
+            Restarts {
+                attempts,
+                last_at: now,
+            },
+        );
+        true
+    }
+
+    /// Seconds until a target's task may be restarted again
+    pub fn remaining(&self, target_id: &str, now: i64) -> i64 {
+        self.targets
+            .get(target_id)
+            .map_or(0, |r| (r.last_at + r.backoff() - now).max(0))
+    }
+
+    /// Forget targets that `keep` rejects (e.g. deleted ones)
+    pub fn retain(&mut self, mut keep: impl FnMut(&str) -> bool) {
+        self.targets.retain(|id, _| keep(id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restart_backoff() {
+        let mut backoff = RestartBackoff::new();
+        let now = 10_000;
+
+        assert!(backoff.try_restart("t1", now));
+        // Within the first backoff
+        assert!(!backoff.try_restart("t1", now + 5));
+        assert_eq!(backoff.remaining("t1", now + 5), 5);
+        assert!(backoff.try_restart("t1", now + 10));
+        // The backoff doubles
+        assert!(!backoff.try_restart("t1", now + 25));
+        assert!(backoff.try_restart("t1", now + 30));
+        assert_eq!(backoff.remaining("t1", now + 30), 40);
+        // Other targets are independent
+        assert!(backoff.try_restart("t2", now + 30));
+
+        // After a quiet period it starts over
+        let later = now + 30 + 40 + MAX_BACKOFF_SECS;
+        assert!(backoff.try_restart("t1", later));
+        assert_eq!(backoff.remaining("t1", later), MIN_BACKOFF_SECS);
+
+        // Capped
+        let mut at = later;
+        for _ in 0..20 {
+            at += MAX_BACKOFF_SECS;
+            assert!(backoff.try_restart("t1", at));
+        }
+        assert_eq!(backoff.remaining("t1", at), MAX_BACKOFF_SECS);
+
+        backoff.retain(|id| id != "t1");
+        assert_eq!(backoff.remaining("t1", at), 0);
+    }
+}
@@ -0,0 +1,292 @@
+//! Self-metrics of the daemon.
+//!
+//! Counts pings performed, ping task restarts by the supervisor and
+//! discovery runs, and keeps latency histograms of
+//! storage writes, API requests (per method and matched route) and discovery
+//! runs. Like the health state, the counters are process wide so ping tasks,
+//! the API middleware and discovery can record without extra plumbing.
+//! Snapshots are served in `/api/status` and rendered by `/metrics`.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
+
+/// Bucket bounds (seconds) for storage writes and API requests
+const LATENCY_BUCKETS: &[f64] = &[
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Bucket bounds (seconds) for discovery runs, which take seconds to minutes
+const DISCOVERY_BUCKETS: &[f64] = &[1.0, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0];
+
+/// Fixed-bucket histogram of durations
+#[derive(Debug, Clone)]
+struct Histogram {
+    bounds: &'static [f64],
+    /// Observations per bucket (not cumulative); the last one is `+Inf`
+    counts: Vec<u64>,
+    sum: f64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            counts: vec![0; bounds.len() + 1],
+            sum: 0.0,
+        }
+    }
+
+    fn observe(&mut self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let index = self
+            .bounds
+            .iter()
+            .position(|&bound| seconds <= bound)
+            .unwrap_or(self.bounds.len());
+        self.counts[index] += 1;
+        self.sum += seconds;
+    }
+
+    fn snapshot(&self) -> HistogramSnapshot {
+        let mut cumulative = 0;
+        let buckets = self
+            .bounds
+            .iter()
+            .zip(&self.counts)
+            .map(|(&le, &count)| {
+                cumulative += count;
+                HistogramBucket {
+                    le,
+                    count: cumulative,
+                }
+            })
+            .collect();
+        HistogramSnapshot {
+            count: self.counts.iter().sum(),
+            sum_seconds: self.sum,
+            buckets,
+        }
+    }
+}
+
+/// Cumulative count of observations up to a bucket bound
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct HistogramBucket {
+    /// Upper bound in seconds
+    pub le: f64,
+    /// Observations at or below `le`
+    pub count: u64,
+}
+
+/// Point-in-time copy of a histogram
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct HistogramSnapshot {
+    /// Number of observations
+    pub count: u64,
+    /// Sum of all observations in seconds
+    pub sum_seconds: f64,
+    /// Cumulative bucket counts; observations above the last bound are only
+    /// in `count`
+    pub buckets: Vec<HistogramBucket>,
+}
+
+/// Latency of the requests to one route
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct RouteLatency {
+    pub method: String,
+    /// Route pattern, e.g. `/api/targets/:id`
+    pub route: String,
+    pub latency: HistogramSnapshot,
+}
+
+/// Self-metrics since the daemon started
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct TelemetrySnapshot {
+    /// Pings that got a reply
+    pub pings_succeeded: u64,
+    /// Pings that timed out or failed
+    pub pings_failed: u64,
+    /// Crashed or stalled ping tasks the supervisor restarted
+    pub ping_task_restarts: u64,
+    /// Duration of ping result writes to tsink
+    pub storage_write_latency: HistogramSnapshot,
+    /// Duration of API requests per route
+    pub api_requests: Vec<RouteLatency>,
+    /// Duration of finished device discovery runs
+    pub discovery_runs: HistogramSnapshot,
+}
+
+struct Counters {
+    pings_succeeded: u64,
+    pings_failed: u64,
+    ping_task_restarts: u64,
+    storage_writes: Histogram,
+    api_requests: BTreeMap<(String, String), Histogram>,
+    discovery_runs: Histogram,
+}
+
+/// Self-metrics of the daemon
+pub struct Telemetry {
+    counters: Mutex<Option<Counters>>,
+}
+
+static TELEMETRY: Telemetry = Telemetry::new();
+
+/// The process-wide self-metrics
+pub fn telemetry() -> &'static Telemetry {
+    &TELEMETRY
+}
+
+impl Telemetry {
+    const fn new() -> Self {
+        // Histograms allocate, so the counters are created on first use
+        Self {
+            counters: Mutex::new(None),
+        }
+    }
+
+    fn with_counters(&self, f: impl FnOnce(&mut Counters)) {
+        if let Ok(mut counters) = self.counters.lock() {
+            f(counters.get_or_insert_with(|| Counters {
+                pings_succeeded: 0,
+                pings_failed: 0,
+                ping_task_restarts: 0,
+                storage_writes: Histogram::new(LATENCY_BUCKETS),
+                api_requests: BTreeMap::new(),
+                discovery_runs: Histogram::new(DISCOVERY_BUCKETS),
+            }));
+        }
+    }
+
+    pub fn record_ping(&self, success: bool) {
+        self.with_counters(|c| {
+            if success {
+                c.pings_succeeded += 1;
+            } else {
+                c.pings_failed += 1;
+            }
+        });
+    }
+
+    pub fn record_ping_task_restart(&self) {
+        self.with_counters(|c| c.ping_task_restarts += 1);
+    }
+
+    pub fn record_storage_write(&self, duration: Duration) {
+        self.with_counters(|c| c.storage_writes.observe(duration));
+    }
+
+    /// Record a request to a matched route pattern (not the raw path, which
+    /// would create a series per target ID)
+    pub fn record_api_request(&self, method: &str, route: &str, duration: Duration) {
+        self.with_counters(|c| {
+            c.api_requests
+                .entry((method.to_string(), route.to_string()))
+                .or_insert_with(|| Histogram::new(LATENCY_BUCKETS))
+                .observe(duration);
+        });
+    }
+
+    pub fn record_discovery_run(&self, duration: Duration) {
+        self.with_counters(|c| c.discovery_runs.observe(duration));
+    }
+
+    pub fn snapshot(&self) -> TelemetrySnapshot {
+        let mut snapshot = None;
+        self.with_counters(|c| {
+            snapshot = Some(TelemetrySnapshot {
+                pings_succeeded: c.pings_succeeded,
+                pings_failed: c.pings_failed,
+                ping_task_restarts: c.ping_task_restarts,
+                storage_write_latency: c.storage_writes.snapshot(),
+                api_requests: c
+                    .api_requests
+                    .iter()
+                    .map(|((method, route), histogram)| RouteLatency {
+                        method: method.clone(),
+                        route: route.clone(),
+                        latency: histogram.snapshot(),
+                    })
+                    .collect(),
+                discovery_runs: c.discovery_runs.snapshot(),
+            });
+        });
+        snapshot.unwrap_or_else(|| TelemetrySnapshot {
+            pings_succeeded: 0,
+            pings_failed: 0,
+            ping_task_restarts: 0,
+            storage_write_latency: Histogram::new(LATENCY_BUCKETS).snapshot(),
+            api_requests: Vec::new(),
+            discovery_runs: Histogram::new(DISCOVERY_BUCKETS).snapshot(),
+        })
+    }
+}
+
+/// Run a storage write and record how long it took
+pub fn timed_write<T>(write: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = write();
+    telemetry().record_storage_write(start.elapsed());
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_buckets_are_cumulative() {
+        let mut histogram = Histogram::new(&[0.01, 0.1, 1.0]);
+        histogram.observe(Duration::from_millis(5));
+        histogram.observe(Duration::from_millis(10));
+        histogram.observe(Duration::from_millis(50));
+        histogram.observe(Duration::from_secs(3));
+
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.count, 4);
+        assert!((snapshot.sum_seconds - 3.065).abs() < 1e-9);
+        let counts: Vec<(f64, u64)> = snapshot.buckets.iter().map(|b| (b.le, b.count)).collect();
+        assert_eq!(counts, vec![(0.01, 2), (0.1, 3), (1.0, 3)]);
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let telemetry = Telemetry::new();
+        let empty = telemetry.snapshot();
+        assert_eq!(empty.pings_succeeded, 0);
+        assert_eq!(empty.storage_write_latency.count, 0);
+        assert!(empty.api_requests.is_empty());
+
+        telemetry.record_ping(true);
+        telemetry.record_ping(true);
+        telemetry.record_ping(false);
+        telemetry.record_ping_task_restart();
+        telemetry.record_storage_write(Duration::from_millis(2));
+        telemetry.record_api_request("GET", "/api/targets/:id", Duration::from_millis(3));
+        telemetry.record_api_request("GET", "/api/targets/:id", Duration::from_millis(4));
+        telemetry.record_api_request("DELETE", "/api/targets/:id", Duration::from_millis(4));
+        telemetry.record_discovery_run(Duration::from_secs(20));
+
+        let snapshot = telemetry.snapshot();
+        assert_eq!(snapshot.pings_succeeded, 2);
+        assert_eq!(snapshot.pings_failed, 1);
+        assert_eq!(snapshot.ping_task_restarts, 1);
+        assert_eq!(snapshot.storage_write_latency.count, 1);
+        assert_eq!(snapshot.discovery_runs.count, 1);
+        let routes: Vec<(&str, &str, u64)> = snapshot
+            .api_requests
+            .iter()
+            .map(|r| (r.method.as_str(), r.route.as_str(), r.latency.count))
+            .collect();
+        assert_eq!(
+            routes,
+            vec![
+                ("DELETE", "/api/targets/:id", 1),
+                ("GET", "/api/targets/:id", 2),
+            ]
+        );
+    }
+}
@@ -0,0 +1,623 @@
+//! Traceroute over ICMP echo requests with increasing TTL.
+//!
+//! Uses the same sockets as the ping tasks: with `dgram_native`/`dgram`
+//! sockets (Linux only), ICMP errors from routers on the path are read from
+//! the socket error queue (`IP_RECVERR`), while `raw` sockets receive them
+//! directly. IPv4 only.
+//!
+//! Runs are either on demand (POST `/api/traceroute`) or scheduled per
+//! target (`[traceroute]`). Scheduled runs store every hop as
+//! `traceroute_hop_loss` (all hops) and `traceroute_hop_latency` (answering
+//! hops), labeled by TTL and router address, so path changes can be lined up
+//! with latency spikes.
+
+use crate::config::SocketType;
+use crate::icmp::{echo_request, ICMP_ECHO_REPLY, ICMP_ECHO_REQUEST, ICMP_HEADER_SIZE};
+use crate::storage::StorageBackend;
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::BTreeMap;
+use std::io::{self, Read};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
+use tsink::{DataPoint, Label, Row};
+
+/// Average latency of each answering hop of a stored run
+pub const TRACEROUTE_LATENCY_METRIC: &str = "traceroute_hop_latency";
+
+/// Probe loss percentage of every hop of a stored run
+pub const TRACEROUTE_LOSS_METRIC: &str = "traceroute_hop_loss";
+
+/// `hop` label value of hops that did not answer
+const SILENT_HOP: &str = "*";
+
+/// Consecutive silent hops after which the run is given up
+const MAX_SILENT_HOPS: u8 = 5;
+
+const ICMP_DEST_UNREACHABLE: u8 = 3;
+const ICMP_TIME_EXCEEDED: u8 = 11;
+
+/// Settings of a single traceroute run
+#[derive(Debug, Clone, Copy)]
+pub struct TracerouteOptions {
+    pub max_hops: u8,
+    pub probes_per_hop: u8,
+    /// Timeout of each probe
+    pub timeout: Duration,
+    pub socket_type: SocketType,
+}
+
+/// One TTL step of a traceroute
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TracerouteHop {
+    pub ttl: u8,
+    /// Router (or destination) that answered, None if no probe was answered
+    pub address: Option<String>,
+    /// Latency of each probe in milliseconds (None = no answer); empty for
+    /// stored runs
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub latencies_ms: Vec<Option<f64>>,
+    pub avg_latency_ms: Option<f64>,
+    pub loss_percent: f64,
+}
+
+/// A stored scheduled run
+#[derive(Debug, Clone, Serialize)]
+pub struct StoredTraceroute {
+    /// ISO 8601 formatted start of the run
+    pub timestamp: String,
+    /// Unix timestamp in seconds of the start of the run
+    pub timestamp_unix: i64,
+    pub hops: Vec<TracerouteHop>,
+    /// Whether the answering routers differ from the previous run
+    pub path_changed: bool,
+}
+
+/// Kind of answer to a probe
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReplyKind {
+    /// The destination answered
+    EchoReply,
+    /// A router on the path dropped the probe (TTL expired)
+    TimeExceeded,
+    /// A router reported the destination as unreachable
+    Unreachable,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ProbeReply {
+    from: Ipv4Addr,
+    kind: ReplyKind,
+}
+
+/// Trace the path to `ip`. Stops at the destination, at an unreachable
+/// report, or after `MAX_SILENT_HOPS` hops without any answer.
+pub async fn traceroute(ip: IpAddr, options: TracerouteOptions) -> io::Result<Vec<TracerouteHop>> {
+    let IpAddr::V4(ip) = ip else {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "traceroute supports IPv4 addresses only",
+        ));
+    };
+    tokio::task::spawn_blocking(move || run_traceroute(ip, options))
+        .await
+        .map_err(|e| io::Error::other(e.to_string()))?
+}
+
+fn run_traceroute(dest: Ipv4Addr, options: TracerouteOptions) -> io::Result<Vec<TracerouteHop>> {
+    let ident = std::process::id() as u16;
+    let probes = options.probes_per_hop.max(1);
+    let mut hops = Vec::new();
+    let mut silent_hops = 0;
+
+    for ttl in 1..=options.max_hops {
+        let mut address = None;
+        let mut latencies_ms = Vec::with_capacity(probes as usize);
+        let mut finished = false;
+
+        for probe in 0..probes {
+            let seq = (ttl as u16 - 1)
+                .wrapping_mul(probes as u16)
+                .wrapping_add(probe as u16 + 1);
+            let start = Instant::now();
+            let reply = match options.socket_type {
+                SocketType::Raw => probe_raw(dest, ttl, options.timeout, ident, seq)?,
+                SocketType::DgramNative | SocketType::Dgram => {
+                    probe_dgram(dest, ttl, options.timeout, ident, seq)?
+                }
+            };
+            match reply {
+                Some(reply) => {
+                    latencies_ms.push(Some(start.elapsed().as_secs_f64() * 1000.0));
+                    address.get_or_insert(reply.from);
+                    finished |= reply.kind != ReplyKind::TimeExceeded;
+                }
+                None => latencies_ms.push(None),
+            }
+        }
+
+        if address.is_some() {
+            silent_hops = 0;
+        } else {
+            silent_hops += 1;
+        }
+        hops.push(hop_from_latencies(
+            ttl,
+            address.map(|a| a.to_string()),
+            latencies_ms,
+        ));
+        if finished || silent_hops >= MAX_SILENT_HOPS {
+            break;
+        }
+    }
+
+    Ok(hops)
+}
+
+fn hop_from_latencies(
+    ttl: u8,
+    address: Option<String>,
+    latencies_ms: Vec<Option<f64>>,
+) -> TracerouteHop {
+    let answered: Vec<f64> = latencies_ms.iter().flatten().copied().collect();
+    let loss_percent = if latencies_ms.is_empty() {
+        100.0
+    } else {
+        (latencies_ms.len() - answered.len()) as f64 / latencies_ms.len() as f64 * 100.0
+    };
+    TracerouteHop {
+        ttl,
+        address,
+        avg_latency_ms: (!answered.is_empty())
+            .then(|| answered.iter().sum::<f64>() / answered.len() as f64),
+        loss_percent,
+        latencies_ms,
+    }
+}
+
+/// Send one echo request with `ttl` on a raw socket and wait for the answer
+fn probe_raw(
+    dest: Ipv4Addr,
+    ttl: u8,
+    timeout: Duration,
+    ident: u16,
+    seq: u16,
+) -> io::Result<Option<ProbeReply>> {
+    let socket = Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::ICMPV4))
+        .map_err(|e| io::Error::new(e.kind(), format!("socket create failed: {}", e)))?;
+    socket.set_ttl_v4(ttl as u32)?;
+    socket.send_to(
+        &echo_request(ident, seq),
+        &SocketAddr::new(dest.into(), 0).into(),
+    )?;
+
+    let start = Instant::now();
+    let mut buf = [0u8; 2048];
+    loop {
+        let elapsed = start.elapsed();
+        if elapsed >= timeout {
+            return Ok(None);
+        }
+        socket.set_read_timeout(Some(timeout - elapsed))?;
+        match (&socket).read(&mut buf) {
+            Ok(n) => {
+                if let Some(reply) = parse_raw_reply(&buf[..n], ident, seq) {
+                    return Ok(Some(reply));
+                }
+            }
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                return Ok(None);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Match a packet read from a raw ICMP socket (IP header included) against
+/// the probe `ident`/`seq`
+fn parse_raw_reply(packet: &[u8], ident: u16, seq: u16) -> Option<ProbeReply> {
+    let ip_header_len = ipv4_header_len(packet)?;
+    let from = Ipv4Addr::new(packet[12], packet[13], packet[14], packet[15]);
+    let icmp = packet.get(ip_header_len..)?;
+    if icmp.len() < ICMP_HEADER_SIZE {
+        return None;
+    }
+
+    let matches_probe = |header: &[u8]| {
+        u16::from_be_bytes([header[4], header[5]]) == ident
+            && u16::from_be_bytes([header[6], header[7]]) == seq
+    };
+
+    match icmp[0] {
+        ICMP_ECHO_REPLY if matches_probe(icmp) => Some(ProbeReply {
+            from,
+            kind: ReplyKind::EchoReply,
+        }),
+        kind @ (ICMP_TIME_EXCEEDED | ICMP_DEST_UNREACHABLE) => {
+            // The error quotes the IP header and first 8 bytes of our probe
+            let quoted = &icmp[ICMP_HEADER_SIZE..];
+            let quoted_icmp = quoted.get(ipv4_header_len(quoted)?..)?;
+            if quoted_icmp.len() < ICMP_HEADER_SIZE
+                || quoted_icmp[0] != ICMP_ECHO_REQUEST
+                || !matches_probe(quoted_icmp)
+            {
+                return None;
+            }
+            Some(ProbeReply {
+                from,
+                kind: if kind == ICMP_TIME_EXCEEDED {
+                    ReplyKind::TimeExceeded
+                } else {
+                    ReplyKind::Unreachable
+                },
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Length of the IPv4 header at the start of `packet`
+fn ipv4_header_len(packet: &[u8]) -> Option<usize> {
+    let first = *packet.first()?;
+    if first >> 4 != 4 {
+        return None;
+    }
+    let len = (first & 0x0f) as usize * 4;
+    (len >= 20 && packet.len() >= len).then_some(len)
+}
+
+/// Send one echo request with `ttl` on an unprivileged DGRAM socket and wait
+/// for the answer. Router errors arrive on the socket error queue.
+#[cfg(target_os = "linux")]
+fn probe_dgram(
+    dest: Ipv4Addr,
+    ttl: u8,
+    timeout: Duration,
+    ident: u16,
+    seq: u16,
+) -> io::Result<Option<ProbeReply>> {
+    use std::os::fd::AsRawFd;
+
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::ICMPV4))
+        .map_err(|e| io::Error::new(e.kind(), format!("socket create failed: {}", e)))?;
+    socket.set_ttl_v4(ttl as u32)?;
+    let fd = socket.as_raw_fd();
+    let enable: libc::c_int = 1;
+    // SAFETY: fd is a valid socket and the option value is a c_int
+    let rc = unsafe {
+        libc::setsockopt(
+            fd,
+            libc::IPPROTO_IP,
+            libc::IP_RECVERR,
+            &enable as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if rc < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    socket.send_to(
+        &echo_request(ident, seq),
+        &SocketAddr::new(dest.into(), 0).into(),
+    )?;
+
+    let start = Instant::now();
+    loop {
+        let elapsed = start.elapsed();
+        if elapsed >= timeout {
+            return Ok(None);
+        }
+        let mut pollfd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let remaining_ms = (timeout - elapsed).as_millis().clamp(1, i32::MAX as u128);
+        // SAFETY: pollfd points to one valid pollfd struct
+        let rc = unsafe { libc::poll(&mut pollfd, 1, remaining_ms as libc::c_int) };
+        if rc < 0 {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(e);
+        }
+        if rc == 0 {
+            return Ok(None);
+        }
+
+        // POLLERR is always reported and signals a queued ICMP error
+        if pollfd.revents & libc::POLLERR != 0 {
+            if let Some(reply) = read_error_queue(fd)? {
+                return Ok(Some(reply));
+            }
+            continue;
+        }
+        if pollfd.revents & libc::POLLIN != 0 {
+            let mut buf = [0u8; 2048];
+            let n = (&socket).read(&mut buf)?;
+            // The kernel only delivers replies to our ident on DGRAM sockets
+            if n >= ICMP_HEADER_SIZE && buf[0] == ICMP_ECHO_REPLY {
+                return Ok(Some(ProbeReply {
+                    from: dest,
+                    kind: ReplyKind::EchoReply,
+                }));
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn probe_dgram(
+    _dest: Ipv4Addr,
+    _ttl: u8,
+    _timeout: Duration,
+    _ident: u16,
+    _seq: u16,
+) -> io::Result<Option<ProbeReply>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "traceroute over DGRAM sockets requires Linux; use socket_type = \"raw\"",
+    ))
+}
+
+/// Read one ICMP error from the socket error queue
+#[cfg(target_os = "linux")]
+fn read_error_queue(fd: std::os::fd::RawFd) -> io::Result<Option<ProbeReply>> {
+    let mut data = [0u8; 512];
+    let mut control = [0u8; 512];
+    let mut iov = libc::iovec {
+        iov_base: data.as_mut_ptr() as *mut libc::c_void,
+        iov_len: data.len(),
+    };
+    // SAFETY: msghdr is plain data; all pointers set below outlive the call
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = control.len() as _;
+
+    // SAFETY: fd is a valid socket and msg points to valid buffers
+    let n = unsafe { libc::recvmsg(fd, &mut msg, libc::MSG_ERRQUEUE | libc::MSG_DONTWAIT) };
+    if n < 0 {
+        let e = io::Error::last_os_error();
+        if e.kind() == io::ErrorKind::WouldBlock {
+            return Ok(None);
+        }
+        return Err(e);
+    }
+
+    // SAFETY: the control buffer was filled by recvmsg; CMSG_* walk it within
+    // msg_controllen, and the data of IP_RECVERR messages is a
+    // sock_extended_err followed by the offender's sockaddr_in
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::IPPROTO_IP && (*cmsg).cmsg_type == libc::IP_RECVERR {
+                let err_ptr = libc::CMSG_DATA(cmsg) as *const libc::sock_extended_err;
+                let err = std::ptr::read_unaligned(err_ptr);
+                if err.ee_origin == libc::SO_EE_ORIGIN_ICMP {
+                    let offender =
+                        std::ptr::read_unaligned(err_ptr.add(1) as *const libc::sockaddr_in);
+                    let from = Ipv4Addr::from(u32::from_be(offender.sin_addr.s_addr));
+                    let kind = match err.ee_type {
+                        ICMP_TIME_EXCEEDED => Some(ReplyKind::TimeExceeded),
+                        ICMP_DEST_UNREACHABLE => Some(ReplyKind::Unreachable),
+                        _ => None,
+                    };
+                    if let Some(kind) = kind {
+                        return Ok(Some(ProbeReply { from, kind }));
+                    }
+                }
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+    Ok(None)
+}
+
+/// Store the hops of a scheduled run
+pub fn record_traceroute(
+    storage: &dyn StorageBackend,
+    target_id: &str,
+    timestamp: i64,
+    hops: &[TracerouteHop],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut rows = Vec::with_capacity(hops.len() * 2);
+    for hop in hops {
+        let labels = vec![
+            Label::new("target_id", target_id),
+            Label::new("ttl", hop.ttl.to_string()),
+            Label::new("hop", hop.address.as_deref().unwrap_or(SILENT_HOP)),
+        ];
+        if let Some(latency) = hop.avg_latency_ms {
+            rows.push(Row::with_labels(
+                TRACEROUTE_LATENCY_METRIC,
+                labels.clone(),
+                DataPoint::new(timestamp, latency),
+            ));
+        }
+        rows.push(Row::with_labels(
+            TRACEROUTE_LOSS_METRIC,
+            labels,
+            DataPoint::new(timestamp, hop.loss_percent),
+        ));
+    }
+
+    if !rows.is_empty() {
+        storage.insert_rows(&rows)?;
+    }
+
+    Ok(())
+}
+
+/// Query stored runs of a target in `[from, to]`, oldest first
+pub fn query_traceroutes(
+    storage: &dyn StorageBackend,
+    target_id: &str,
+    from: i64,
+    to: i64,
+) -> Result<Vec<StoredTraceroute>, Box<dyn std::error::Error + Send + Sync>> {
+    // Runs keyed by timestamp, hops keyed by TTL
+    let mut runs: BTreeMap<i64, BTreeMap<u8, TracerouteHop>> = BTreeMap::new();
+
+    for metric in [TRACEROUTE_LOSS_METRIC, TRACEROUTE_LATENCY_METRIC] {
+        for (labels, points) in storage.select_all(metric, from, to)? {
+            let label = |name: &str| {
+                labels
+                    .iter()
+                    .find(|l| l.name == name)
+                    .map(|l| l.value.as_str())
+            };
+            if label("target_id") != Some(target_id) {
+                continue;
+            }
+            let (Some(ttl), Some(address)) =
+                (label("ttl").and_then(|t| t.parse().ok()), label("hop"))
+            else {
+                continue;
+            };
+
+            for point in points {
+                let hop = runs
+                    .entry(point.timestamp)
+                    .or_default()
+                    .entry(ttl)
+                    .or_insert_with(|| TracerouteHop {
+                        ttl,
+                        address: (address != SILENT_HOP).then(|| address.to_string()),
+                        latencies_ms: Vec::new(),
+                        avg_latency_ms: None,
+                        loss_percent: 100.0,
+                    });
+                if metric == TRACEROUTE_LOSS_METRIC {
+                    hop.loss_percent = point.value;
+                } else {
+                    hop.avg_latency_ms = Some(point.value);
+                }
+            }
+        }
+    }
+
+    let mut previous_path: Option<Vec<String>> = None;
+    let mut stored = Vec::with_capacity(runs.len());
+    for (timestamp, hops) in runs {
+        let hops: Vec<TracerouteHop> = hops.into_values().collect();
+        let path = answering_path(&hops);
+        let path_changed = previous_path.as_ref().is_some_and(|p| *p != path);
+        previous_path = Some(path);
+        stored.push(StoredTraceroute {
+            timestamp: DateTime::from_timestamp(timestamp, 0)
+                .map(|t| t.to_rfc3339())
+                .unwrap_or_default(),
+            timestamp_unix: timestamp,
+            hops,
+            path_changed,
+        });
+    }
+
+    Ok(stored)
+}
+
+/// Addresses of the answering hops, in TTL order
+fn answering_path(hops: &[TracerouteHop]) -> Vec<String> {
+    hops.iter().filter_map(|h| h.address.clone()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// IPv4 header (20 bytes) from `src` followed by `payload`
+    fn ipv4_packet(src: [u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0u8; 20];
+        packet[0] = 0x45;
+        packet[12..16].copy_from_slice(&src);
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    #[test]
+    fn test_parse_raw_echo_reply() {
+        let mut reply = echo_request(7, 3);
+        reply[0] = ICMP_ECHO_REPLY;
+        let packet = ipv4_packet([8, 8, 8, 8], &reply);
+
+        let parsed = parse_raw_reply(&packet, 7, 3).unwrap();
+        assert_eq!(parsed.from, Ipv4Addr::new(8, 8, 8, 8));
+        assert_eq!(parsed.kind, ReplyKind::EchoReply);
+        // Replies to other probes are ignored
+        assert!(parse_raw_reply(&packet, 7, 4).is_none());
+    }
+
+    #[test]
+    fn test_parse_raw_time_exceeded() {
+        // Time exceeded quoting our probe's IP header and ICMP header
+        let quoted = ipv4_packet([192, 168, 1, 10], &echo_request(7, 5)[..8]);
+        let mut error = vec![ICMP_TIME_EXCEEDED, 0, 0, 0, 0, 0, 0, 0];
+        error.extend_from_slice(&quoted);
+        let packet = ipv4_packet([10, 0, 0, 1], &error);
+
+        let parsed = parse_raw_reply(&packet, 7, 5).unwrap();
+        assert_eq!(parsed.from, Ipv4Addr::new(10, 0, 0, 1));
+        assert_eq!(parsed.kind, ReplyKind::TimeExceeded);
+        assert!(parse_raw_reply(&packet, 8, 5).is_none());
+        assert!(parse_raw_reply(&packet[..30], 7, 5).is_none());
+    }
+
+    #[test]
+    fn test_hop_from_latencies() {
+        let hop = hop_from_latencies(2, Some("10.0.0.1".into()), vec![Some(1.0), None, Some(3.0)]);
+        assert_eq!(hop.avg_latency_ms, Some(2.0));
+        assert!((hop.loss_percent - 100.0 / 3.0).abs() < 1e-9);
+
+        let silent = hop_from_latencies(3, None, vec![None, None]);
+        assert_eq!(silent.avg_latency_ms, None);
+        assert_eq!(silent.loss_percent, 100.0);
+    }
+
+    #[test]
+    fn test_stored_runs_detect_path_changes() {
+        let storage = crate::storage::memory_storage();
+        let hop = |ttl: u8, address: Option<&str>, latency: Option<f64>| {
+            hop_from_latencies(ttl, address.map(str::to_string), vec![latency])
+        };
+
+        let first = [
+            hop(1, Some("10.0.0.1"), Some(1.0)),
+            hop(2, Some("8.8.8.8"), Some(9.0)),
+        ];
+        let same = [
+            hop(1, Some("10.0.0.1"), Some(1.5)),
+            hop(2, Some("8.8.8.8"), Some(8.0)),
+        ];
+        let changed = [
+            hop(1, Some("10.0.0.1"), Some(1.0)),
+            hop(2, None, None),
+            hop(3, Some("8.8.4.4"), Some(20.0)),
+        ];
+        record_traceroute(&*storage, "wan", 100, &first).unwrap();
+        record_traceroute(&*storage, "wan", 200, &same).unwrap();
+        record_traceroute(&*storage, "wan", 300, &changed).unwrap();
+        record_traceroute(&*storage, "other", 300, &first).unwrap();
+
+        let runs = query_traceroutes(&*storage, "wan", 0, 400).unwrap();
+        assert_eq!(runs.len(), 3);
+        assert!(!runs[0].path_changed);
+        assert!(!runs[1].path_changed);
+        assert!(runs[2].path_changed);
+
+        assert_eq!(runs[1].hops[1].avg_latency_ms, Some(8.0));
+        let silent = &runs[2].hops[1];
+        assert_eq!(silent.address, None);
+        assert_eq!(silent.avg_latency_ms, None);
+        assert_eq!(silent.loss_percent, 100.0);
+    }
+}
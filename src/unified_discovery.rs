@@ -1,18 +1,32 @@
 //! Unified device discovery module.
 //!
-//! This module coordinates multiple discovery methods (mDNS, IP scan) and
-//! merges results into a unified stream. Devices are deduplicated by IP address
-//! to ensure each device is only reported once, even if discovered by multiple methods.
+//! This module coordinates multiple discovery methods (mDNS, IP scan, ARP,
+//! WS-Discovery) and merges results into a unified stream. Devices are
+//! deduplicated by IP address to ensure each device is only reported once,
+//! even if discovered by multiple methods.
+//!
+//! Device updates are dropped instead of stalling discovery when the client
+//! cannot keep up. Drops and queue depth are tracked in `DiscoveryStreamStats`
+//! so slow consumers show up in heartbeat events and `/metrics`.
 
+use crate::arp_discovery::run_arp_discovery;
+use crate::config::SocketType;
 use crate::device_identification::{convert_to_identified, IdentifiedDiscoveryEvent};
 use crate::discovery::{run_mdns_discovery, DiscoveredDevice, DiscoveryEvent};
-use crate::ip_scan::{run_ip_scan_discovery, IpRangeSpec, IpScanRequest};
+use crate::ip_scan::{
+    parse_range_spec, run_ip_scan_discovery, IpRangeSpec, IpScanRequest, ScanProgress,
+};
+use crate::port_history;
+use crate::storage::StorageBackend;
 use crate::vendor_discovery::{self, Vendor, VendorInfo};
+use crate::ws_discovery::run_ws_discovery;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
+use tokio::sync::mpsc::error::TrySendError;
 use tokio::sync::{mpsc, Mutex};
-use tracing::{debug, info};
+use tracing::{debug, error, info, warn};
 
 /// Configuration for unified discovery
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,6 +42,19 @@ pub struct UnifiedDiscoveryConfig {
     /// IP scan configuration (required if ip_scan_enabled is true)
     #[serde(default)]
     pub ip_scan: Option<IpScanConfig>,
+
+    /// Enable neighbor table (ARP) discovery
+    #[serde(default)]
+    pub arp_enabled: bool,
+
+    /// Range to sweep before reading the neighbor table; without one only
+    /// the entries already in the table are reported
+    #[serde(default)]
+    pub arp_range: Option<IpRangeSpec>,
+
+    /// Enable WS-Discovery (printers, scanners, ONVIF cameras)
+    #[serde(default)]
+    pub ws_discovery_enabled: bool,
 }
 
 fn default_true() -> bool {
@@ -52,6 +79,27 @@ pub struct IpScanConfig {
     /// Concurrency level
     #[serde(default = "default_concurrency")]
     pub concurrency: usize,
+    /// Resolve hostnames of found devices via reverse DNS
+    #[serde(default = "default_true")]
+    pub reverse_dns: bool,
+    /// DNS server for reverse lookups (default: the system nameserver)
+    #[serde(default)]
+    pub dns_server: Option<String>,
+    /// Fall back to NetBIOS/LLMNR names for hosts without a PTR record
+    #[serde(default = "default_true")]
+    pub netbios: bool,
+    /// Name lookup timeout in milliseconds
+    #[serde(default = "default_dns_timeout")]
+    pub dns_timeout_ms: u64,
+    /// Check ports with TCP connects
+    #[serde(default = "default_true")]
+    pub tcp: bool,
+    /// Also find hosts answering ICMP echo requests
+    #[serde(default)]
+    pub icmp: bool,
+    /// Socket type of the ICMP echo sweep
+    #[serde(default)]
+    pub socket_type: SocketType,
 }
 
 fn default_ports() -> Vec<u16> {
@@ -66,6 +114,126 @@ fn default_concurrency() -> usize {
     50
 }
 
+fn default_dns_timeout() -> u64 {
+    crate::reverse_dns::DEFAULT_TIMEOUT_MS
+}
+
+/// Capacity of the channel between unified discovery and the SSE stream
+pub const CLIENT_CHANNEL_CAPACITY: usize = 100;
+
+/// Backpressure counters shared by all discovery SSE streams
+#[derive(Debug, Default)]
+pub struct DiscoveryStreamStats {
+    active_streams: AtomicUsize,
+    dropped_events: AtomicU64,
+    peak_queue_depth: AtomicUsize,
+}
+
+/// Point-in-time copy of `DiscoveryStreamStats`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiscoveryStreamSnapshot {
+    /// Number of discovery streams currently open
+    pub active_streams: usize,
+    /// Device updates dropped because a client was not keeping up
+    pub dropped_events: u64,
+    /// Highest number of queued events seen on any stream
+    pub peak_queue_depth: usize,
+}
+
+impl DiscoveryStreamStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a new discovery stream; it is unregistered once the returned
+    /// handle is dropped by both the SSE stream and the discovery task
+    pub fn open_stream(self: &Arc<Self>) -> Arc<StreamBackpressure> {
+        self.active_streams.fetch_add(1, Ordering::Relaxed);
+        Arc::new(StreamBackpressure {
+            stats: Arc::clone(self),
+            dropped_events: AtomicU64::new(0),
+            counted: true,
+        })
+    }
+
+    /// Counters for the queue of a shared discovery session, which is not a
+    /// client stream itself but whose drops count toward the totals
+    pub fn session_backpressure(self: &Arc<Self>) -> Arc<StreamBackpressure> {
+        Arc::new(StreamBackpressure {
+            stats: Arc::clone(self),
+            dropped_events: AtomicU64::new(0),
+            counted: false,
+        })
+    }
+
+    pub fn snapshot(&self) -> DiscoveryStreamSnapshot {
+        DiscoveryStreamSnapshot {
+            active_streams: self.active_streams.load(Ordering::Relaxed),
+            dropped_events: self.dropped_events.load(Ordering::Relaxed),
+            peak_queue_depth: self.peak_queue_depth.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Backpressure counters of a single discovery stream
+#[derive(Debug)]
+pub struct StreamBackpressure {
+    stats: Arc<DiscoveryStreamStats>,
+    dropped_events: AtomicU64,
+    /// Whether this is an open stream in `active_streams`
+    counted: bool,
+}
+
+impl StreamBackpressure {
+    /// Device updates dropped on this stream so far
+    pub fn dropped_events(&self) -> u64 {
+        self.dropped_events.load(Ordering::Relaxed)
+    }
+
+    /// Record the number of events waiting to be sent to the client
+    pub fn record_queue_depth(&self, depth: usize) {
+        self.stats
+            .peak_queue_depth
+            .fetch_max(depth, Ordering::Relaxed);
+    }
+
+    /// Count a dropped event, returning the drop count of this stream
+    fn record_dropped(&self) -> u64 {
+        self.stats.dropped_events.fetch_add(1, Ordering::Relaxed);
+        self.dropped_events.fetch_add(1, Ordering::Relaxed) + 1
+    }
+}
+
+impl Drop for StreamBackpressure {
+    fn drop(&mut self) {
+        if self.counted {
+            self.stats.active_streams.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Send a device update to the client without waiting for queue space.
+///
+/// When the queue is full the update is dropped and counted rather than
+/// stalling every discovery method behind a slow client. Returns false once
+/// the client has disconnected.
+pub fn send_update(
+    tx: &mpsc::Sender<IdentifiedDiscoveryEvent>,
+    event: IdentifiedDiscoveryEvent,
+    backpressure: &StreamBackpressure,
+) -> bool {
+    match tx.try_send(event) {
+        Ok(()) => true,
+        Err(TrySendError::Full(_)) => {
+            if backpressure.record_dropped() == 1 {
+                warn!("Discovery client is not keeping up, dropping device updates");
+            }
+            true
+        }
+        Err(TrySendError::Closed(_)) => false,
+    }
+}
+
 /// State for tracking discovered devices and merging results
 struct DiscoveryState {
     /// Devices indexed by IP address
@@ -128,6 +296,24 @@ impl DiscoveryState {
                 }
             }
 
+            // Merge open ports from IP scans
+            for port in &device.open_ports {
+                if !existing.open_ports.contains(port) {
+                    existing.open_ports.push(*port);
+                    updated = true;
+                }
+            }
+
+            if existing.gateway.is_none() && device.gateway.is_some() {
+                existing.gateway = device.gateway.clone();
+                updated = true;
+            }
+
+            if existing.mac.is_none() && device.mac.is_some() {
+                existing.mac = device.mac.clone();
+                updated = true;
+            }
+
             // Merge TXT properties
             for (key, value) in &device.txt_properties {
                 if !existing.txt_properties.contains_key(key) {
@@ -174,14 +360,16 @@ impl DiscoveryState {
             return None;
         }
 
-        // Detect vendor from service types
+        // Detect vendor from service types, then from the hostname
         let service_types: Vec<String> = device
             .services
             .iter()
             .map(|s| s.service_type.clone())
             .collect();
 
-        if let Some(vendor) = vendor_discovery::detect_vendor(&service_types) {
+        let vendor = vendor_discovery::detect_vendor(&service_types)
+            .or_else(|| vendor_discovery::detect_vendor_from_hostname(&device.hostname));
+        if let Some(vendor) = vendor {
             self.vendor_fetch_in_progress.insert(device.address.clone());
             Some(vendor)
         } else {
@@ -215,31 +403,78 @@ impl DiscoveryState {
 /// Internal event for coordinating discovery methods
 enum InternalEvent {
     /// A device was discovered
-    Device(DiscoveredDevice),
+    Device(Box<DiscoveredDevice>),
     /// A method started
     Started(String),
     /// A method completed
     Completed(String),
     /// An error occurred
     Error(String),
+    /// IP scan progress, passed on as is
+    Progress(IdentifiedDiscoveryEvent),
     /// Vendor-specific information was fetched for a device
     VendorInfo {
         ip_address: String,
-        vendor_info: VendorInfo,
+        vendor_info: Box<VendorInfo>,
         vendor_name: Option<String>,
     },
 }
 
+/// Forward the events of a discovery method until it completes or fails
+async fn forward_events(
+    method: &'static str,
+    mut rx: mpsc::Receiver<DiscoveryEvent>,
+    internal_tx: mpsc::Sender<InternalEvent>,
+) {
+    while let Some(event) = rx.recv().await {
+        match event {
+            DiscoveryEvent::DeviceFound { device } | DiscoveryEvent::DeviceUpdated { device } => {
+                if internal_tx
+                    .send(InternalEvent::Device(Box::new(device)))
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+            DiscoveryEvent::Started { .. } => {
+                let _ = internal_tx
+                    .send(InternalEvent::Started(method.to_string()))
+                    .await;
+            }
+            DiscoveryEvent::Completed { .. } => {
+                let _ = internal_tx
+                    .send(InternalEvent::Completed(method.to_string()))
+                    .await;
+                break;
+            }
+            DiscoveryEvent::Error { message } => {
+                let _ = internal_tx
+                    .send(InternalEvent::Error(format!("{}: {}", method, message)))
+                    .await;
+                break;
+            }
+            DiscoveryEvent::Progress { .. } => {}
+        }
+    }
+}
+
 /// Run unified discovery with multiple methods and send merged results.
 ///
 /// This function coordinates multiple discovery methods, merges their results
 /// by IP address, and sends unified events to the client.
 ///
 /// Events are sent as `IdentifiedDiscoveryEvent` which contains fully parsed
-/// device information.
+/// device information. Open ports found by the IP scan are recorded in
+/// `storage` to build the per-device port history, and scanned addresses are
+/// counted in `progress`. Device updates the client has no room for are
+/// dropped and counted in `backpressure`.
 pub async fn run_unified_discovery(
     tx: mpsc::Sender<IdentifiedDiscoveryEvent>,
     config: UnifiedDiscoveryConfig,
+    storage: Arc<dyn StorageBackend>,
+    backpressure: Arc<StreamBackpressure>,
+    progress: Arc<ScanProgress>,
 ) {
     info!("Starting unified discovery");
 
@@ -251,6 +486,12 @@ pub async fn run_unified_discovery(
     if config.ip_scan_enabled && config.ip_scan.is_some() {
         active_methods += 1;
     }
+    if config.arp_enabled {
+        active_methods += 1;
+    }
+    if config.ws_discovery_enabled {
+        active_methods += 1;
+    }
 
     if active_methods == 0 {
         let _ = tx
@@ -273,6 +514,16 @@ pub async fn run_unified_discovery(
         } else {
             None
         },
+        if config.arp_enabled {
+            Some("ARP")
+        } else {
+            None
+        },
+        if config.ws_discovery_enabled {
+            Some("WS-Discovery")
+        } else {
+            None
+        },
     ]
     .into_iter()
     .flatten()
@@ -298,48 +549,9 @@ pub async fn run_unified_discovery(
 
     // Start mDNS discovery if enabled
     if config.mdns_enabled {
-        let internal_tx = internal_tx.clone();
-        tokio::spawn(async move {
-            let (mdns_tx, mut mdns_rx) = mpsc::channel::<DiscoveryEvent>(100);
-
-            // Spawn the mDNS discovery
-            tokio::spawn(async move {
-                run_mdns_discovery(mdns_tx).await;
-            });
-
-            // Forward events
-            while let Some(event) = mdns_rx.recv().await {
-                match event {
-                    DiscoveryEvent::DeviceFound { device }
-                    | DiscoveryEvent::DeviceUpdated { device } => {
-                        if internal_tx
-                            .send(InternalEvent::Device(device))
-                            .await
-                            .is_err()
-                        {
-                            break;
-                        }
-                    }
-                    DiscoveryEvent::Started { .. } => {
-                        let _ = internal_tx
-                            .send(InternalEvent::Started("mDNS".to_string()))
-                            .await;
-                    }
-                    DiscoveryEvent::Completed { .. } => {
-                        let _ = internal_tx
-                            .send(InternalEvent::Completed("mDNS".to_string()))
-                            .await;
-                        break;
-                    }
-                    DiscoveryEvent::Error { message } => {
-                        let _ = internal_tx
-                            .send(InternalEvent::Error(format!("mDNS: {}", message)))
-                            .await;
-                        break;
-                    }
-                }
-            }
-        });
+        let (mdns_tx, mdns_rx) = mpsc::channel::<DiscoveryEvent>(100);
+        tokio::spawn(run_mdns_discovery(mdns_tx));
+        tokio::spawn(forward_events("mDNS", mdns_rx, internal_tx.clone()));
     }
 
     // Start IP scan if enabled
@@ -360,11 +572,26 @@ pub async fn run_unified_discovery(
             };
 
             if let Some(range) = range {
+                // Without TCP checks no port was seen open or closed
+                let scanned_ports = if ip_config.tcp {
+                    ip_config.ports.clone()
+                } else {
+                    Vec::new()
+                };
+                let scan_range = parse_range_spec(&range).ok();
                 let request = IpScanRequest {
                     range,
                     ports: ip_config.ports,
                     timeout_ms: ip_config.timeout_ms,
                     concurrency: ip_config.concurrency,
+                    reverse_dns: ip_config.reverse_dns,
+                    dns_server: ip_config.dns_server,
+                    netbios: ip_config.netbios,
+                    dns_timeout_ms: ip_config.dns_timeout_ms,
+                    tcp: ip_config.tcp,
+                    icmp: ip_config.icmp,
+                    socket_type: ip_config.socket_type,
+                    progress: Some(progress),
                 };
 
                 tokio::spawn(async move {
@@ -375,13 +602,42 @@ pub async fn run_unified_discovery(
                         run_ip_scan_discovery(scan_tx, request).await;
                     });
 
+                    // Addresses that answered, to tell which hosts are gone
+                    let mut seen = HashSet::new();
+
                     // Forward events
                     while let Some(event) = scan_rx.recv().await {
                         match event {
                             DiscoveryEvent::DeviceFound { device }
                             | DiscoveryEvent::DeviceUpdated { device } => {
+                                seen.insert(device.address.clone());
+                                if !scanned_ports.is_empty() {
+                                    let storage = Arc::clone(&storage);
+                                    let scanned_ports = scanned_ports.clone();
+                                    let address = device.address.clone();
+                                    let open_ports = device.open_ports.clone();
+                                    let result = tokio::task::spawn_blocking(move || {
+                                        port_history::record_port_scan(
+                                            &*storage,
+                                            &address,
+                                            &scanned_ports,
+                                            &open_ports,
+                                            chrono::Utc::now().timestamp(),
+                                        )
+                                        .map_err(|e| e.to_string())
+                                    })
+                                    .await
+                                    .unwrap_or_else(|e| Err(e.to_string()));
+                                    if let Err(e) = result {
+                                        error!(
+                                            "Failed to record open ports for {}: {}",
+                                            device.address, e
+                                        );
+                                    }
+                                }
+
                                 if internal_tx
-                                    .send(InternalEvent::Device(device))
+                                    .send(InternalEvent::Device(Box::new(device)))
                                     .await
                                     .is_err()
                                 {
@@ -394,6 +650,36 @@ pub async fn run_unified_discovery(
                                     .await;
                             }
                             DiscoveryEvent::Completed { .. } => {
+                                if let (Some((start, end)), false) =
+                                    (scan_range, scanned_ports.is_empty())
+                                {
+                                    let storage = Arc::clone(&storage);
+                                    let seen = std::mem::take(&mut seen);
+                                    let scanned_ports = scanned_ports.clone();
+                                    let result = tokio::task::spawn_blocking(move || {
+                                        port_history::record_missing_hosts(
+                                            &*storage,
+                                            start,
+                                            end,
+                                            &seen,
+                                            &scanned_ports,
+                                            chrono::Utc::now().timestamp(),
+                                        )
+                                        .map_err(|e| e.to_string())
+                                    })
+                                    .await
+                                    .unwrap_or_else(|e| Err(e.to_string()));
+                                    match result {
+                                        Ok(0) => {}
+                                        Ok(missing) => {
+                                            info!("Recorded ports of {} hosts gone", missing)
+                                        }
+                                        Err(e) => {
+                                            error!("Failed to record ports of gone hosts: {}", e)
+                                        }
+                                    }
+                                }
+
                                 let _ = internal_tx
                                     .send(InternalEvent::Completed("IP Scan".to_string()))
                                     .await;
@@ -405,6 +691,20 @@ pub async fn run_unified_discovery(
                                     .await;
                                 break;
                             }
+                            DiscoveryEvent::Progress {
+                                scanned,
+                                total,
+                                found,
+                                eta_seconds,
+                            } => {
+                                let progress = IdentifiedDiscoveryEvent::Progress {
+                                    scanned,
+                                    total,
+                                    found,
+                                    eta_seconds,
+                                };
+                                let _ = internal_tx.try_send(InternalEvent::Progress(progress));
+                            }
                         }
                     }
                 });
@@ -412,6 +712,20 @@ pub async fn run_unified_discovery(
         }
     }
 
+    // Start neighbor table discovery if enabled
+    if config.arp_enabled {
+        let (arp_tx, arp_rx) = mpsc::channel::<DiscoveryEvent>(100);
+        tokio::spawn(run_arp_discovery(arp_tx, config.arp_range));
+        tokio::spawn(forward_events("ARP", arp_rx, internal_tx.clone()));
+    }
+
+    // Start WS-Discovery if enabled
+    if config.ws_discovery_enabled {
+        let (wsd_tx, wsd_rx) = mpsc::channel::<DiscoveryEvent>(100);
+        tokio::spawn(run_ws_discovery(wsd_tx));
+        tokio::spawn(forward_events("WS-Discovery", wsd_rx, internal_tx.clone()));
+    }
+
     // Drop our copy of internal_tx so the channel closes when all methods complete
     drop(internal_tx);
 
@@ -425,7 +739,7 @@ pub async fn run_unified_discovery(
         match event {
             InternalEvent::Device(device) => {
                 let mut state_guard = state.lock().await;
-                if let Some((merged_device, is_new)) = state_guard.merge_device(device) {
+                if let Some((merged_device, is_new)) = state_guard.merge_device(*device) {
                     // Check if we should fetch vendor-specific info
                     if let Some(vendor) = state_guard.should_fetch_vendor_info(&merged_device) {
                         let ip_address = merged_device.address.clone();
@@ -441,7 +755,7 @@ pub async fn run_unified_discovery(
                                 let _ = vendor_tx
                                     .send(InternalEvent::VendorInfo {
                                         ip_address,
-                                        vendor_info: info,
+                                        vendor_info: Box::new(info),
                                         vendor_name,
                                     })
                                     .await;
@@ -455,12 +769,19 @@ pub async fn run_unified_discovery(
                     // Convert to identified device
                     let identified = convert_to_identified(merged_device);
 
-                    let event = if is_new {
-                        IdentifiedDiscoveryEvent::DeviceFound { device: identified }
+                    let delivered = if is_new {
+                        // New devices are never dropped
+                        tx.send(IdentifiedDiscoveryEvent::DeviceFound { device: identified })
+                            .await
+                            .is_ok()
                     } else {
-                        IdentifiedDiscoveryEvent::DeviceUpdated { device: identified }
+                        send_update(
+                            &tx,
+                            IdentifiedDiscoveryEvent::DeviceUpdated { device: identified },
+                            &backpressure,
+                        )
                     };
-                    if tx.send(event).await.is_err() {
+                    if !delivered {
                         break;
                     }
                 }
@@ -472,7 +793,7 @@ pub async fn run_unified_discovery(
             } => {
                 let mut state_guard = state.lock().await;
                 if let Some(updated_device) =
-                    state_guard.update_device_vendor_info(&ip_address, vendor_info, vendor_name)
+                    state_guard.update_device_vendor_info(&ip_address, *vendor_info, vendor_name)
                 {
                     drop(state_guard);
 
@@ -484,11 +805,11 @@ pub async fn run_unified_discovery(
                     // Convert to identified device
                     let identified = convert_to_identified(updated_device);
 
-                    if tx
-                        .send(IdentifiedDiscoveryEvent::DeviceUpdated { device: identified })
-                        .await
-                        .is_err()
-                    {
+                    if !send_update(
+                        &tx,
+                        IdentifiedDiscoveryEvent::DeviceUpdated { device: identified },
+                        &backpressure,
+                    ) {
                         break;
                     }
                 }
@@ -516,6 +837,12 @@ pub async fn run_unified_discovery(
                 // Log error but continue with other methods
                 debug!("Discovery error: {}", message);
             }
+            InternalEvent::Progress(progress) => {
+                // Progress is superseded by the next event, so it may be dropped
+                if !send_update(&tx, progress, &backpressure) {
+                    break;
+                }
+            }
         }
     }
 
@@ -541,6 +868,9 @@ mod tests {
             ttl: None,
             discovery_method: "mdns".to_string(),
             vendor_info: None,
+            open_ports: Vec::new(),
+            gateway: None,
+            mac: None,
         };
 
         let result = state.merge_device(device1);
@@ -560,6 +890,9 @@ mod tests {
             ttl: None,
             discovery_method: "ip_scan".to_string(),
             vendor_info: None,
+            open_ports: vec![80],
+            gateway: None,
+            mac: None,
         };
 
         let result = state.merge_device(device2);
@@ -569,5 +902,41 @@ mod tests {
         assert_eq!(device.name, "My Device"); // Should keep the better name
         assert!(device.discovery_method.contains("mdns"));
         assert!(device.discovery_method.contains("ip_scan"));
+        assert_eq!(device.open_ports, vec![80]); // Open ports merged from IP scan
+
+        // Same device from the neighbor table
+        let device3 = DiscoveredDevice {
+            discovery_method: "arp".to_string(),
+            open_ports: Vec::new(),
+            mac: Some("aa:bb:cc:dd:ee:ff".to_string()),
+            ..device.clone()
+        };
+
+        let (device, _) = state.merge_device(device3).unwrap();
+        assert!(device.discovery_method.ends_with("arp"));
+        assert_eq!(device.mac.as_deref(), Some("aa:bb:cc:dd:ee:ff"));
+    }
+
+    #[test]
+    fn test_send_update_drops_when_full() {
+        let stats = Arc::new(DiscoveryStreamStats::new());
+        let backpressure = stats.open_stream();
+        let (tx, rx) = mpsc::channel::<IdentifiedDiscoveryEvent>(1);
+        let event = || IdentifiedDiscoveryEvent::Started {
+            message: "test".to_string(),
+        };
+
+        assert!(send_update(&tx, event(), &backpressure));
+        // Queue is full: the update is dropped but discovery continues
+        assert!(send_update(&tx, event(), &backpressure));
+        assert_eq!(backpressure.dropped_events(), 1);
+        assert_eq!(stats.snapshot().dropped_events, 1);
+        assert_eq!(stats.snapshot().active_streams, 1);
+
+        drop(rx);
+        assert!(!send_update(&tx, event(), &backpressure));
+
+        drop(backpressure);
+        assert_eq!(stats.snapshot().active_streams, 0);
     }
 }
@@ -0,0 +1,320 @@
+//! Opt-in check for new releases (`[updates]`).
+//!
+//! When `check` is enabled, the release feed is fetched every `interval`
+//! seconds. The outcome is shown at GET `/api/system/info`, and each newer
+//! release is sent once to notification channels as `update_available`, so
+//! long-running installs learn about security and bug-fix releases. Nothing
+//! is downloaded or installed.
+
+use crate::config::{AppConfig, NotificationKind};
+use crate::notifications::{dispatch, Notification};
+use crate::tasks::wait_until_enabled;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Version of this build
+pub const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Latest release as served by the feed (GitHub releases API format)
+#[derive(Debug, Clone, Deserialize)]
+pub struct Release {
+    /// Release tag, e.g. "v0.3.0"
+    pub tag_name: String,
+    #[serde(default)]
+    pub html_url: Option<String>,
+    #[serde(default)]
+    pub published_at: Option<String>,
+}
+
+/// Outcome of the update checks so far
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UpdateStatus {
+    /// Whether `[updates] check` is enabled
+    pub enabled: bool,
+    /// Version of the latest release, without a leading "v"
+    pub latest_version: Option<String>,
+    pub update_available: bool,
+    /// Release notes page of the latest release
+    pub release_url: Option<String>,
+    pub published_at: Option<String>,
+    /// Unix timestamp of the last check
+    pub last_checked: Option<i64>,
+    /// Error of the last check, if it failed
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum UpdateCheckError {
+    /// Failed to create HTTP client
+    HttpClient(String),
+    /// HTTP request failed
+    Request(String),
+    /// Non-success HTTP status
+    HttpStatus(u16),
+    /// Failed to read response body
+    ReadBody(String),
+    /// Failed to parse the release feed
+    Parse(String),
+}
+
+impl std::fmt::Display for UpdateCheckError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpdateCheckError::HttpClient(e) => write!(f, "Failed to create HTTP client: {}", e),
+            UpdateCheckError::Request(e) => write!(f, "HTTP request failed: {}", e),
+            UpdateCheckError::HttpStatus(code) => write!(f, "HTTP error: {}", code),
+            UpdateCheckError::ReadBody(e) => write!(f, "Failed to read response: {}", e),
+            UpdateCheckError::Parse(e) => write!(f, "Failed to parse release feed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for UpdateCheckError {}
+
+/// Numeric part of a version ("v1.2.3-rc.1" -> (1, 2, 3)) and whether it is
+/// a pre-release. Missing minor or patch numbers count as zero.
+fn parse_version(version: &str) -> Option<((u64, u64, u64), bool)> {
+    let version = version.trim().trim_start_matches(['v', 'V']);
+    let version = version.split('+').next().unwrap_or(version);
+    let (numbers, pre_release) = match version.split_once('-') {
+        Some((numbers, _)) => (numbers, true),
+        None => (version, false),
+    };
+
+    let mut parts = numbers.split('.').map(|p| p.parse::<u64>());
+    let major = parts.next()?.ok()?;
+    let minor = parts.next().transpose().ok()?.unwrap_or(0);
+    let patch = parts.next().transpose().ok()?.unwrap_or(0);
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(((major, minor, patch), pre_release))
+}
+
+/// Whether `latest` is a newer version than `current`. A release of the same
+/// version supersedes a pre-release of it.
+fn is_newer(latest: &str, current: &str) -> bool {
+    match (parse_version(latest), parse_version(current)) {
+        (Some((latest, latest_pre)), Some((current, current_pre))) => {
+            latest > current || (latest == current && current_pre && !latest_pre)
+        }
+        _ => false,
+    }
+}
+
+/// Fetch the latest release from the feed
+pub async fn fetch_latest_release(url: &str) -> Result<Release, UpdateCheckError> {
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        // GitHub's API rejects requests without a user agent
+        .user_agent(concat!("SparkPing/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .map_err(|e| UpdateCheckError::HttpClient(e.to_string()))?;
+
+    debug!("Checking for updates at: {}", url);
+    let response = client
+        .get(url)
+        .header(reqwest::header::ACCEPT, "application/json")
+        .send()
+        .await
+        .map_err(|e| UpdateCheckError::Request(e.to_string()))?;
+
+    if !response.status().is_success() {
+        return Err(UpdateCheckError::HttpStatus(response.status().as_u16()));
+    }
+
+    let text = response
+        .text()
+        .await
+        .map_err(|e| UpdateCheckError::ReadBody(e.to_string()))?;
+
+    serde_json::from_str(&text).map_err(|e| UpdateCheckError::Parse(e.to_string()))
+}
+
+/// Update check results, shared through `AppState`
+#[derive(Debug, Default)]
+pub struct UpdateChecker {
+    status: RwLock<UpdateStatus>,
+}
+
+impl UpdateChecker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn status(&self) -> UpdateStatus {
+        self.status
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    fn set_enabled(&self, enabled: bool) {
+        let mut status = self.status.write().unwrap_or_else(|e| e.into_inner());
+        status.enabled = enabled;
+    }
+
+    /// Record the outcome of a check at Unix time `now`.
+    ///
+    /// Returns the release if it is newer than this build and was not
+    /// reported by an earlier check. A failed check keeps the last known
+    /// release.
+    pub fn record(
+        &self,
+        result: Result<Release, UpdateCheckError>,
+        current_version: &str,
+        now: i64,
+    ) -> Option<Release> {
+        let mut status = self.status.write().unwrap_or_else(|e| e.into_inner());
+        status.last_checked = Some(now);
+
+        let release = match result {
+            Ok(release) => release,
+            Err(e) => {
+                status.last_error = Some(e.to_string());
+                return None;
+            }
+        };
+        let version = release.tag_name.trim_start_matches(['v', 'V']).to_string();
+        let known = status.latest_version.as_deref() == Some(version.as_str());
+        let newer = is_newer(&version, current_version);
+
+        status.last_error = None;
+        status.latest_version = Some(version);
+        status.update_available = newer;
+        status.release_url = release.html_url.clone();
+        status.published_at = release.published_at.clone();
+
+        (newer && !known).then_some(release)
+    }
+}
+
+fn update_notification(release: &Release, now: i64) -> Notification {
+    let version = release.tag_name.trim_start_matches(['v', 'V']);
+    let message = match release.html_url {
+        Some(ref url) => format!(
+            "SparkPing {} is available (running {}). Release notes: {}",
+            version, CURRENT_VERSION, url
+        ),
+        None => format!(
+            "SparkPing {} is available (running {}).",
+            version, CURRENT_VERSION
+        ),
+    };
+
+    Notification::new(
+        NotificationKind::UpdateAvailable,
+        format!("SparkPing {} available", version),
+        message,
+        now,
+    )
+    .with_details(serde_json::json!({
+        "current_version": CURRENT_VERSION,
+        "latest_version": version,
+        "release_url": release.html_url,
+        "published_at": release.published_at,
+    }))
+}
+
+/// Start the background task checking for new releases
+pub fn start_update_check_task(
+    checker: Arc<UpdateChecker>,
+    config: Arc<RwLock<AppConfig>>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            wait_until_enabled(&config, |c| {
+                checker.set_enabled(c.updates.check);
+                c.updates.check
+            })
+            .await;
+
+            let Some((settings, channels)) = config
+                .read()
+                .map(|c| (c.updates.clone(), c.notifications.channels.clone()))
+                .ok()
+            else {
+                error!("Failed to read config for the update check");
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                continue;
+            };
+
+            let result = fetch_latest_release(&settings.url).await;
+            if let Err(ref e) = result {
+                warn!("Update check failed: {}", e);
+            }
+            let now = chrono::Utc::now().timestamp();
+            if let Some(release) = checker.record(result, CURRENT_VERSION, now) {
+                info!(
+                    "SparkPing {} is available (running {})",
+                    release.tag_name, CURRENT_VERSION
+                );
+                dispatch(&channels, update_notification(&release, now));
+            }
+
+            tokio::time::sleep(Duration::from_secs(settings.interval.max(3600))).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn release(tag: &str) -> Release {
+        Release {
+            tag_name: tag.to_string(),
+            html_url: Some(format!("https://example.com/releases/{}", tag)),
+            published_at: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(parse_version("v1.2.3"), Some(((1, 2, 3), false)));
+        assert_eq!(parse_version("0.3"), Some(((0, 3, 0), false)));
+        assert_eq!(parse_version("1.0.0-rc.1+build5"), Some(((1, 0, 0), true)));
+        assert_eq!(parse_version("nightly"), None);
+        assert_eq!(parse_version("1.2.3.4"), None);
+    }
+
+    #[test]
+    fn test_is_newer() {
+        assert!(is_newer("v0.3.0", "0.2.0"));
+        assert!(is_newer("0.2.1", "0.2.0"));
+        assert!(!is_newer("0.2.0", "0.2.0"));
+        assert!(!is_newer("0.1.9", "0.2.0"));
+        assert!(is_newer("0.3.0", "0.3.0-beta.2"));
+        assert!(!is_newer("garbage", "0.2.0"));
+    }
+
+    #[test]
+    fn test_record_reports_each_release_once() {
+        let checker = UpdateChecker::new();
+
+        assert!(checker.record(Ok(release("v0.2.0")), "0.2.0", 1).is_none());
+        assert!(!checker.status().update_available);
+
+        let found = checker.record(Ok(release("v0.3.0")), "0.2.0", 2);
+        assert_eq!(found.map(|r| r.tag_name), Some("v0.3.0".to_string()));
+        let status = checker.status();
+        assert!(status.update_available);
+        assert_eq!(status.latest_version.as_deref(), Some("0.3.0"));
+
+        // Same release again, then a failed check: nothing new to report
+        assert!(checker.record(Ok(release("v0.3.0")), "0.2.0", 3).is_none());
+        let failed = Err(UpdateCheckError::HttpStatus(503));
+        assert!(checker.record(failed, "0.2.0", 4).is_none());
+        let status = checker.status();
+        assert!(status.update_available);
+        assert_eq!(status.last_checked, Some(4));
+        assert_eq!(status.last_error.as_deref(), Some("HTTP error: 503"));
+
+        assert!(checker.record(Ok(release("v0.3.1")), "0.2.0", 5).is_some());
+    }
+}
@@ -0,0 +1,392 @@
+//! ESPHome-specific device discovery.
+//!
+//! This module asks ESPHome nodes for their device info over the native API
+//! (TCP port 6053): a plaintext `HelloRequest` followed by a
+//! `DeviceInfoRequest`, both of which the node answers without a password.
+//! Nodes with API encryption only speak the Noise protocol and are reported
+//! as `EsphomeError::Encrypted`; their mDNS TXT records still identify them.
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tracing::debug;
+
+/// The port of the ESPHome native API
+const ESPHOME_API_PORT: u16 = 6053;
+
+/// Native API message types
+const HELLO_REQUEST: u32 = 1;
+const HELLO_RESPONSE: u32 = 2;
+const DEVICE_INFO_REQUEST: u32 = 9;
+const DEVICE_INFO_RESPONSE: u32 = 10;
+
+/// API version announced in the hello
+const API_VERSION: (u64, u64) = (1, 10);
+
+/// Largest message accepted from a node
+const MAX_MESSAGE_SIZE: u64 = 64 * 1024;
+
+/// Messages to skip while waiting for a response
+const MAX_SKIPPED_MESSAGES: usize = 8;
+
+/// Parsed ESPHome node information
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct EsphomeInfo {
+    /// Node name (e.g., "living-room-sensor")
+    pub name: String,
+    /// Friendly name configured for the node
+    pub friendly_name: Option<String>,
+    /// MAC address
+    pub mac_address: Option<String>,
+    /// ESPHome version the firmware was built with (e.g., "2024.6.1")
+    pub esphome_version: Option<String>,
+    /// Firmware build time (e.g., "Jun 20 2024, 10:15:42")
+    pub compilation_time: Option<String>,
+    /// Board the firmware was built for (e.g., "esp32dev")
+    pub board: Option<String>,
+    /// Manufacturer of the chip (e.g., "Espressif")
+    pub manufacturer: Option<String>,
+    /// Project name for ESPHome-based products (e.g., "esphome.bluetooth-proxy")
+    pub project_name: Option<String>,
+    pub project_version: Option<String>,
+    /// Area suggested by the node configuration
+    pub suggested_area: Option<String>,
+    /// Native API version of the node (e.g., "1.10")
+    pub api_version: Option<String>,
+}
+
+/// Fetch ESPHome node information over the native API
+///
+/// # Arguments
+/// * `ip_address` - The IP address of the ESPHome node
+/// * `timeout` - Timeout for the whole exchange
+///
+/// # Returns
+/// Parsed ESPHome information if successful
+pub async fn fetch_esphome_info(
+    ip_address: &str,
+    timeout: Duration,
+) -> Result<EsphomeInfo, EsphomeError> {
+    debug!(
+        "Querying ESPHome native API at {}:{}",
+        ip_address, ESPHOME_API_PORT
+    );
+    tokio::time::timeout(timeout, query_device_info(ip_address))
+        .await
+        .map_err(|_| EsphomeError::Timeout)?
+}
+
+async fn query_device_info(ip_address: &str) -> Result<EsphomeInfo, EsphomeError> {
+    let mut stream = TcpStream::connect((ip_address, ESPHOME_API_PORT))
+        .await
+        .map_err(|e| EsphomeError::Connect(e.to_string()))?;
+
+    stream
+        .write_all(&encode_frame(HELLO_REQUEST, &hello_request()))
+        .await
+        .map_err(|e| EsphomeError::Io(e.to_string()))?;
+    let hello = read_message(&mut stream, HELLO_RESPONSE).await?;
+    let fields = decode_fields(&hello)?;
+    let api_version = match (varint_field(&fields, 1), varint_field(&fields, 2)) {
+        (Some(major), Some(minor)) => Some(format!("{}.{}", major, minor)),
+        _ => None,
+    };
+
+    stream
+        .write_all(&encode_frame(DEVICE_INFO_REQUEST, &[]))
+        .await
+        .map_err(|e| EsphomeError::Io(e.to_string()))?;
+    let device_info = read_message(&mut stream, DEVICE_INFO_RESPONSE).await?;
+
+    let mut info = parse_device_info(&device_info)?;
+    info.api_version = api_version;
+    Ok(info)
+}
+
+/// HelloRequest: client_info (1), api_version_major (2), api_version_minor (3)
+fn hello_request() -> Vec<u8> {
+    let client_info = concat!("SparkPing ", env!("CARGO_PKG_VERSION")).as_bytes();
+    let mut payload = vec![(1 << 3) | 2];
+    encode_varint(client_info.len() as u64, &mut payload);
+    payload.extend_from_slice(client_info);
+    payload.push(2 << 3);
+    encode_varint(API_VERSION.0, &mut payload);
+    payload.push(3 << 3);
+    encode_varint(API_VERSION.1, &mut payload);
+    payload
+}
+
+/// Plaintext frame: 0x00, payload length, message type, payload
+fn encode_frame(message_type: u32, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x00];
+    encode_varint(payload.len() as u64, &mut frame);
+    encode_varint(message_type as u64, &mut frame);
+    frame.extend_from_slice(payload);
+    frame
+}
+
+fn encode_varint(mut value: u64, out: &mut Vec<u8>) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Decode a varint at the start of `buf`, returning it and its length
+fn decode_varint(buf: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+    for (i, byte) in buf.iter().enumerate().take(10) {
+        value |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+async fn read_varint<R: AsyncRead + Unpin>(reader: &mut R) -> Result<u64, EsphomeError> {
+    let mut value = 0u64;
+    for i in 0..10 {
+        let byte = reader
+            .read_u8()
+            .await
+            .map_err(|e| EsphomeError::Io(e.to_string()))?;
+        value |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(EsphomeError::Protocol("varint too long".to_string()))
+}
+
+/// Read one frame, returning its message type and payload
+async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> Result<(u64, Vec<u8>), EsphomeError> {
+    let preamble = reader
+        .read_u8()
+        .await
+        .map_err(|e| EsphomeError::Io(e.to_string()))?;
+    match preamble {
+        0x00 => {}
+        // Noise frames start with 0x01
+        0x01 => return Err(EsphomeError::Encrypted),
+        other => {
+            return Err(EsphomeError::Protocol(format!(
+                "unexpected preamble {:#04x}",
+                other
+            )))
+        }
+    }
+
+    let length = read_varint(reader).await?;
+    let message_type = read_varint(reader).await?;
+    if length > MAX_MESSAGE_SIZE {
+        return Err(EsphomeError::Protocol(format!(
+            "message of {} bytes is too large",
+            length
+        )));
+    }
+
+    let mut payload = vec![0u8; length as usize];
+    reader
+        .read_exact(&mut payload)
+        .await
+        .map_err(|e| EsphomeError::Io(e.to_string()))?;
+    Ok((message_type, payload))
+}
+
+/// Read frames until one of the expected type arrives
+async fn read_message<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    expected: u32,
+) -> Result<Vec<u8>, EsphomeError> {
+    for _ in 0..=MAX_SKIPPED_MESSAGES {
+        let (message_type, payload) = read_frame(reader).await?;
+        if message_type == expected as u64 {
+            return Ok(payload);
+        }
+        debug!("Skipping ESPHome message of type {}", message_type);
+    }
+    Err(EsphomeError::Protocol(format!(
+        "no response of type {}",
+        expected
+    )))
+}
+
+/// A decoded protobuf field value
+#[derive(Debug)]
+enum FieldValue<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+}
+
+/// Decode the fields of a protobuf message; fixed-size fields are skipped
+fn decode_fields(payload: &[u8]) -> Result<Vec<(u64, FieldValue<'_>)>, EsphomeError> {
+    let malformed = || EsphomeError::Protocol("malformed message".to_string());
+    let mut fields = Vec::new();
+    let mut pos = 0;
+
+    while pos < payload.len() {
+        let (key, len) = decode_varint(&payload[pos..]).ok_or_else(malformed)?;
+        pos += len;
+        let number = key >> 3;
+        match key & 0x7 {
+            0 => {
+                let (value, len) = decode_varint(&payload[pos..]).ok_or_else(malformed)?;
+                pos += len;
+                fields.push((number, FieldValue::Varint(value)));
+            }
+            2 => {
+                let (size, len) = decode_varint(&payload[pos..]).ok_or_else(malformed)?;
+                pos += len;
+                let end = pos
+                    .checked_add(size as usize)
+                    .filter(|end| *end <= payload.len())
+                    .ok_or_else(malformed)?;
+                fields.push((number, FieldValue::Bytes(&payload[pos..end])));
+                pos = end;
+            }
+            1 => pos += 8,
+            5 => pos += 4,
+            _ => return Err(malformed()),
+        }
+    }
+
+    if pos > payload.len() {
+        return Err(malformed());
+    }
+    Ok(fields)
+}
+
+fn varint_field(fields: &[(u64, FieldValue<'_>)], number: u64) -> Option<u64> {
+    fields.iter().find_map(|(n, value)| match value {
+        FieldValue::Varint(v) if *n == number => Some(*v),
+        _ => None,
+    })
+}
+
+fn string_field(fields: &[(u64, FieldValue<'_>)], number: u64) -> Option<String> {
+    fields
+        .iter()
+        .find_map(|(n, value)| match value {
+            FieldValue::Bytes(b) if *n == number => Some(String::from_utf8_lossy(b).into_owned()),
+            _ => None,
+        })
+        .filter(|s| !s.is_empty())
+}
+
+/// Parse a DeviceInfoResponse
+fn parse_device_info(payload: &[u8]) -> Result<EsphomeInfo, EsphomeError> {
+    let fields = decode_fields(payload)?;
+
+    Ok(EsphomeInfo {
+        name: string_field(&fields, 2)
+            .ok_or_else(|| EsphomeError::Protocol("missing node name".to_string()))?,
+        mac_address: string_field(&fields, 3),
+        esphome_version: string_field(&fields, 4),
+        compilation_time: string_field(&fields, 5),
+        board: string_field(&fields, 6),
+        project_name: string_field(&fields, 8),
+        project_version: string_field(&fields, 9),
+        manufacturer: string_field(&fields, 12),
+        friendly_name: string_field(&fields, 13),
+        suggested_area: string_field(&fields, 16),
+        api_version: None,
+    })
+}
+
+/// Errors that can occur when fetching ESPHome information
+#[derive(Debug, Clone)]
+pub enum EsphomeError {
+    /// Failed to connect to the native API
+    Connect(String),
+    /// Reading or writing the connection failed
+    Io(String),
+    /// The node did not answer in time
+    Timeout,
+    /// The node requires an encrypted (Noise) connection
+    Encrypted,
+    /// Unexpected data from the node
+    Protocol(String),
+}
+
+impl std::fmt::Display for EsphomeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EsphomeError::Connect(e) => write!(f, "Failed to connect: {}", e),
+            EsphomeError::Io(e) => write!(f, "Connection failed: {}", e),
+            EsphomeError::Timeout => write!(f, "Timed out"),
+            EsphomeError::Encrypted => write!(f, "Native API encryption is enabled"),
+            EsphomeError::Protocol(e) => write!(f, "Protocol error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for EsphomeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(number: u8, value: &str, out: &mut Vec<u8>) {
+        out.push((number << 3) | 2);
+        encode_varint(value.len() as u64, out);
+        out.extend_from_slice(value.as_bytes());
+    }
+
+    #[test]
+    fn test_varint_roundtrip() {
+        for value in [0, 1, 127, 128, 300, 65_535, u32::MAX as u64] {
+            let mut buf = Vec::new();
+            encode_varint(value, &mut buf);
+            assert_eq!(decode_varint(&buf), Some((value, buf.len())));
+        }
+        assert_eq!(decode_varint(&[0x80]), None);
+    }
+
+    #[test]
+    fn test_parse_device_info() {
+        let mut payload = vec![1 << 3, 0]; // uses_password = false
+        string(2, "living-room-sensor", &mut payload);
+        string(3, "AC:67:B2:3C:4D:5E", &mut payload);
+        string(4, "2024.6.1", &mut payload);
+        string(6, "esp32dev", &mut payload);
+        payload.extend_from_slice(&[10 << 3, 0x50]); // webserver_port = 80
+        string(12, "Espressif", &mut payload);
+        string(13, "Living Room Sensor", &mut payload);
+
+        let info = parse_device_info(&payload).expect("Failed to parse");
+        assert_eq!(info.name, "living-room-sensor");
+        assert_eq!(info.mac_address, Some("AC:67:B2:3C:4D:5E".to_string()));
+        assert_eq!(info.esphome_version, Some("2024.6.1".to_string()));
+        assert_eq!(info.board, Some("esp32dev".to_string()));
+        assert_eq!(info.manufacturer, Some("Espressif".to_string()));
+        assert_eq!(info.friendly_name, Some("Living Room Sensor".to_string()));
+        assert_eq!(info.project_name, None);
+    }
+
+    #[test]
+    fn test_parse_device_info_truncated() {
+        let payload = [(2 << 3) | 2, 10, b'a'];
+        assert!(parse_device_info(&payload).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_read_message() {
+        let mut data = encode_frame(7, &[]);
+        data.extend(encode_frame(HELLO_RESPONSE, &[1 << 3, 1, 2 << 3, 10]));
+        let mut reader = data.as_slice();
+
+        let payload = read_message(&mut reader, HELLO_RESPONSE).await.unwrap();
+        let fields = decode_fields(&payload).unwrap();
+        assert_eq!(varint_field(&fields, 1), Some(1));
+        assert_eq!(varint_field(&fields, 2), Some(10));
+
+        let mut encrypted: &[u8] = &[0x01, 0x00, 0x00];
+        assert!(matches!(
+            read_frame(&mut encrypted).await,
+            Err(EsphomeError::Encrypted)
+        ));
+    }
+}
@@ -0,0 +1,168 @@
+//! Philips Hue-specific device discovery.
+//!
+//! This module fetches additional information from Hue bridges via the
+//! unauthenticated `/api/config` endpoint of their local REST API.
+//!
+//! The number of connected lights is not part of it: listing lights needs
+//! an application key, which is only handed out after the bridge's link
+//! button has been pressed.
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::debug;
+
+/// The endpoint for the public part of the bridge configuration
+const HUE_CONFIG_ENDPOINT: &str = "/api/config";
+
+/// Parsed Hue bridge information
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HueInfo {
+    /// The bridge name configured in the Hue app
+    pub name: String,
+    /// Model ID (e.g., "BSB002")
+    pub model_id: Option<String>,
+    /// Bridge ID (e.g., "001788FFFE23BFC2")
+    pub bridge_id: Option<String>,
+    /// Software version (e.g., "1967054020")
+    pub software_version: Option<String>,
+    /// API version (e.g., "1.67.0")
+    pub api_version: Option<String>,
+    /// MAC address
+    pub mac_address: Option<String>,
+    /// Whether the bridge has not been set up yet
+    pub factory_new: Option<bool>,
+}
+
+/// JSON structure of the `/api/config` response
+#[derive(Debug, Deserialize)]
+struct BridgeConfig {
+    name: String,
+    #[serde(rename = "modelid", default)]
+    model_id: Option<String>,
+    #[serde(rename = "bridgeid", default)]
+    bridge_id: Option<String>,
+    #[serde(rename = "swversion", default)]
+    software_version: Option<String>,
+    #[serde(rename = "apiversion", default)]
+    api_version: Option<String>,
+    #[serde(default)]
+    mac: Option<String>,
+    #[serde(rename = "factorynew", default)]
+    factory_new: Option<bool>,
+}
+
+/// Fetch Hue bridge information from its local HTTP API
+///
+/// # Arguments
+/// * `ip_address` - The IP address of the Hue bridge
+/// * `timeout` - Request timeout duration
+///
+/// # Returns
+/// Parsed Hue bridge information if successful
+pub async fn fetch_hue_info(ip_address: &str, timeout: Duration) -> Result<HueInfo, HueError> {
+    let client = reqwest::Client::builder()
+        .timeout(timeout)
+        .build()
+        .map_err(|e| HueError::HttpClient(e.to_string()))?;
+
+    let config_url = format!("http://{}{}", ip_address, HUE_CONFIG_ENDPOINT);
+    debug!("Fetching Hue bridge config from: {}", config_url);
+
+    let response = client
+        .get(&config_url)
+        .send()
+        .await
+        .map_err(|e| HueError::Request(e.to_string()))?;
+
+    if !response.status().is_success() {
+        return Err(HueError::HttpStatus(response.status().as_u16()));
+    }
+
+    let body = response
+        .text()
+        .await
+        .map_err(|e| HueError::ReadBody(e.to_string()))?;
+
+    parse_bridge_config(&body)
+}
+
+/// Parse the `/api/config` JSON response
+fn parse_bridge_config(json: &str) -> Result<HueInfo, HueError> {
+    let config: BridgeConfig =
+        serde_json::from_str(json).map_err(|e| HueError::JsonParse(e.to_string()))?;
+
+    Ok(HueInfo {
+        name: config.name,
+        model_id: config.model_id.filter(|s| !s.is_empty()),
+        bridge_id: config.bridge_id.filter(|s| !s.is_empty()),
+        software_version: config.software_version.filter(|s| !s.is_empty()),
+        api_version: config.api_version.filter(|s| !s.is_empty()),
+        mac_address: config.mac.filter(|s| !s.is_empty()),
+        factory_new: config.factory_new,
+    })
+}
+
+/// Errors that can occur when fetching Hue bridge information
+#[derive(Debug, Clone)]
+pub enum HueError {
+    /// Failed to create HTTP client
+    HttpClient(String),
+    /// HTTP request failed
+    Request(String),
+    /// Non-success HTTP status
+    HttpStatus(u16),
+    /// Failed to read response body
+    ReadBody(String),
+    /// Failed to parse JSON
+    JsonParse(String),
+}
+
+impl std::fmt::Display for HueError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HueError::HttpClient(e) => write!(f, "Failed to create HTTP client: {}", e),
+            HueError::Request(e) => write!(f, "HTTP request failed: {}", e),
+            HueError::HttpStatus(code) => write!(f, "HTTP error: {}", code),
+            HueError::ReadBody(e) => write!(f, "Failed to read response: {}", e),
+            HueError::JsonParse(e) => write!(f, "Failed to parse JSON: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for HueError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_CONFIG: &str = r#"{
+        "name": "Hue Wohnzimmer",
+        "datastoreversion": "163",
+        "swversion": "1967054020",
+        "apiversion": "1.67.0",
+        "mac": "00:17:88:23:bf:c2",
+        "bridgeid": "001788FFFE23BFC2",
+        "factorynew": false,
+        "replacesbridgeid": null,
+        "modelid": "BSB002",
+        "starterkitid": ""
+    }"#;
+
+    #[test]
+    fn test_parse_bridge_config() {
+        let info = parse_bridge_config(SAMPLE_CONFIG).expect("Failed to parse");
+
+        assert_eq!(info.name, "Hue Wohnzimmer");
+        assert_eq!(info.model_id, Some("BSB002".to_string()));
+        assert_eq!(info.bridge_id, Some("001788FFFE23BFC2".to_string()));
+        assert_eq!(info.software_version, Some("1967054020".to_string()));
+        assert_eq!(info.api_version, Some("1.67.0".to_string()));
+        assert_eq!(info.mac_address, Some("00:17:88:23:bf:c2".to_string()));
+        assert_eq!(info.factory_new, Some(false));
+    }
+
+    #[test]
+    fn test_parse_bridge_config_invalid() {
+        assert!(parse_bridge_config("[]").is_err());
+    }
+}
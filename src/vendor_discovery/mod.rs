@@ -3,6 +3,9 @@
 //! This module provides functionality to fetch additional device information
 //! from vendor-specific APIs after a device has been discovered via mDNS or IP scan.
 
+pub mod esphome;
+pub mod hue;
+pub mod shelly;
 pub mod sonos;
 
 use serde::{Deserialize, Serialize};
@@ -18,12 +21,21 @@ const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3);
 pub enum VendorInfo {
     /// Sonos speaker information
     Sonos(sonos::SonosInfo),
+    /// Philips Hue bridge information
+    Hue(hue::HueInfo),
+    /// Shelly device information
+    Shelly(shelly::ShellyInfo),
+    /// ESPHome node information
+    Esphome(esphome::EsphomeInfo),
 }
 
 /// Identifies the vendor of a device based on its services or other characteristics
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Vendor {
     Sonos,
+    Hue,
+    Shelly,
+    Esphome,
 }
 
 /// Check if a device is from a specific vendor based on its service types
@@ -33,10 +45,30 @@ pub fn detect_vendor(service_types: &[String]) -> Option<Vendor> {
         if service_lower.contains("_sonos.") {
             return Some(Vendor::Sonos);
         }
+        if service_lower.contains("_hue.") {
+            return Some(Vendor::Hue);
+        }
+        if service_lower.contains("_shelly.") {
+            return Some(Vendor::Shelly);
+        }
+        if service_lower.contains("_esphomelib.") {
+            return Some(Vendor::Esphome);
+        }
     }
     None
 }
 
+/// Check if a device is from a specific vendor based on its hostname
+///
+/// Gen1 Shelly devices only advertise `_http._tcp`, under hostnames like
+/// "shelly1-A4CF12F45C3E.local".
+pub fn detect_vendor_from_hostname(hostname: &str) -> Option<Vendor> {
+    hostname
+        .to_lowercase()
+        .starts_with("shelly")
+        .then_some(Vendor::Shelly)
+}
+
 /// Fetch vendor-specific information for a device
 ///
 /// # Arguments
@@ -57,6 +89,36 @@ pub async fn fetch_vendor_info(vendor: Vendor, ip_address: &str) -> Option<Vendo
                 }
             }
         }
+        Vendor::Shelly => {
+            debug!("Fetching Shelly info for {}", ip_address);
+            match shelly::fetch_shelly_info(ip_address, DEFAULT_TIMEOUT).await {
+                Ok(info) => Some(VendorInfo::Shelly(info)),
+                Err(e) => {
+                    warn!("Failed to fetch Shelly info for {}: {}", ip_address, e);
+                    None
+                }
+            }
+        }
+        Vendor::Hue => {
+            debug!("Fetching Hue bridge info for {}", ip_address);
+            match hue::fetch_hue_info(ip_address, DEFAULT_TIMEOUT).await {
+                Ok(info) => Some(VendorInfo::Hue(info)),
+                Err(e) => {
+                    warn!("Failed to fetch Hue bridge info for {}: {}", ip_address, e);
+                    None
+                }
+            }
+        }
+        Vendor::Esphome => {
+            debug!("Fetching ESPHome info for {}", ip_address);
+            match esphome::fetch_esphome_info(ip_address, DEFAULT_TIMEOUT).await {
+                Ok(info) => Some(VendorInfo::Esphome(info)),
+                Err(e) => {
+                    warn!("Failed to fetch ESPHome info for {}: {}", ip_address, e);
+                    None
+                }
+            }
+        }
     }
 }
 
@@ -73,6 +135,18 @@ pub async fn fetch_vendor_info_with_name(
             let name = Some(info.zone_name.clone());
             (Some(VendorInfo::Sonos(info.clone())), name)
         }
+        Some(VendorInfo::Hue(info)) => {
+            let name = Some(info.name.clone());
+            (Some(VendorInfo::Hue(info)), name)
+        }
+        Some(VendorInfo::Shelly(info)) => {
+            let name = info.name.clone();
+            (Some(VendorInfo::Shelly(info)), name)
+        }
+        Some(VendorInfo::Esphome(info)) => {
+            let name = info.friendly_name.clone().or(Some(info.name.clone()));
+            (Some(VendorInfo::Esphome(info)), name)
+        }
         None => (None, None),
     }
 }
@@ -87,6 +161,32 @@ mod tests {
         assert_eq!(detect_vendor(&services), Some(Vendor::Sonos));
     }
 
+    #[test]
+    fn test_detect_vendor_hue() {
+        let services = vec![
+            "_http._tcp.local.".to_string(),
+            "_hue._tcp.local.".to_string(),
+        ];
+        assert_eq!(detect_vendor(&services), Some(Vendor::Hue));
+    }
+
+    #[test]
+    fn test_detect_vendor_shelly() {
+        let services = vec!["_shelly._tcp.local.".to_string()];
+        assert_eq!(detect_vendor(&services), Some(Vendor::Shelly));
+        assert_eq!(
+            detect_vendor_from_hostname("shelly1-A4CF12F45C3E.local"),
+            Some(Vendor::Shelly)
+        );
+        assert_eq!(detect_vendor_from_hostname("printer.local"), None);
+    }
+
+    #[test]
+    fn test_detect_vendor_esphome() {
+        let services = vec!["_esphomelib._tcp.local.".to_string()];
+        assert_eq!(detect_vendor(&services), Some(Vendor::Esphome));
+    }
+
     #[test]
     fn test_detect_vendor_none() {
         let services = vec!["_http._tcp.local.".to_string()];
@@ -0,0 +1,300 @@
+//! Shelly-specific device discovery.
+//!
+//! This module fetches additional information from Shelly devices via their
+//! local HTTP API. Every generation answers `/shelly`; Gen2+ devices are then
+//! asked for `/rpc/Shelly.GetDeviceInfo` and `/rpc/Cloud.GetStatus`, Gen1
+//! devices for `/status`, which carries the cloud connection.
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// The endpoint every Shelly generation answers
+const SHELLY_ENDPOINT: &str = "/shelly";
+
+/// Gen2+ RPC endpoints
+const GEN2_DEVICE_INFO_ENDPOINT: &str = "/rpc/Shelly.GetDeviceInfo";
+const GEN2_CLOUD_STATUS_ENDPOINT: &str = "/rpc/Cloud.GetStatus";
+
+/// Gen1 status endpoint (includes the cloud connection)
+const GEN1_STATUS_ENDPOINT: &str = "/status";
+
+/// Parsed Shelly device information
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ShellyInfo {
+    /// Device generation (1 for the original devices, 2+ for Plus/Pro/Gen3)
+    pub generation: u8,
+    /// Model identifier (e.g., "SHSW-1", "SNSW-001P16EU")
+    pub model: String,
+    /// Application code of Gen2+ devices (e.g., "Plus1PM")
+    pub app: Option<String>,
+    /// Device ID (e.g., "shellyplus1pm-441793d69718")
+    pub device_id: Option<String>,
+    /// Name configured on the device
+    pub name: Option<String>,
+    /// MAC address
+    pub mac_address: Option<String>,
+    /// Firmware version (e.g., "1.0.8" or "v1.14.0")
+    pub firmware_version: Option<String>,
+    /// Full firmware build ID
+    pub firmware_id: Option<String>,
+    /// Whether the local API requires authentication
+    pub auth_enabled: bool,
+    /// Whether the Shelly cloud connection is enabled (Gen1 only)
+    pub cloud_enabled: Option<bool>,
+    /// Whether the device is connected to the Shelly cloud
+    pub cloud_connected: Option<bool>,
+}
+
+/// JSON structure of the `/shelly` response (both generations)
+#[derive(Debug, Deserialize)]
+struct ShellyResponse {
+    /// Present on Gen2+ devices only
+    #[serde(rename = "gen", default)]
+    generation: Option<u8>,
+    /// Gen1 model identifier
+    #[serde(rename = "type", default)]
+    device_type: Option<String>,
+    /// Gen2+ model identifier
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    app: Option<String>,
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    mac: Option<String>,
+    /// Gen1 firmware build ("20230913-112003/v1.14.0-gcb84623")
+    #[serde(default)]
+    fw: Option<String>,
+    /// Gen2+ firmware version and build ID
+    #[serde(default)]
+    ver: Option<String>,
+    #[serde(default)]
+    fw_id: Option<String>,
+    /// Gen1 authentication flag
+    #[serde(default)]
+    auth: Option<bool>,
+    /// Gen2+ authentication flag
+    #[serde(default)]
+    auth_en: Option<bool>,
+}
+
+/// Cloud section of the Gen1 `/status` response
+#[derive(Debug, Deserialize)]
+struct Gen1Status {
+    cloud: Gen1Cloud,
+}
+
+#[derive(Debug, Deserialize)]
+struct Gen1Cloud {
+    enabled: bool,
+    connected: bool,
+}
+
+/// JSON structure of the Gen2+ `Cloud.GetStatus` response
+#[derive(Debug, Deserialize)]
+struct Gen2CloudStatus {
+    connected: bool,
+}
+
+/// Fetch Shelly device information from its local HTTP API
+///
+/// # Arguments
+/// * `ip_address` - The IP address of the Shelly device
+/// * `timeout` - Request timeout duration
+///
+/// # Returns
+/// Parsed Shelly information if successful
+pub async fn fetch_shelly_info(
+    ip_address: &str,
+    timeout: Duration,
+) -> Result<ShellyInfo, ShellyError> {
+    let client = reqwest::Client::builder()
+        .timeout(timeout)
+        .build()
+        .map_err(|e| ShellyError::HttpClient(e.to_string()))?;
+
+    let body = get_text(&client, ip_address, SHELLY_ENDPOINT).await?;
+    let mut info = parse_shelly_response(&body)?;
+
+    // The remaining endpoints need credentials when authentication is on
+    if info.auth_enabled {
+        return Ok(info);
+    }
+
+    if info.generation >= 2 {
+        // Shelly.GetDeviceInfo has the same fields as /shelly, but some
+        // firmware versions leave the name out of /shelly
+        match get_text(&client, ip_address, GEN2_DEVICE_INFO_ENDPOINT).await {
+            Ok(body) => match parse_shelly_response(&body) {
+                Ok(details) => info.name = info.name.or(details.name),
+                Err(e) => warn!("Failed to parse Shelly device info: {}", e),
+            },
+            Err(e) => warn!("Failed to fetch Shelly device info: {}", e),
+        }
+
+        match get_text(&client, ip_address, GEN2_CLOUD_STATUS_ENDPOINT).await {
+            Ok(body) => match serde_json::from_str::<Gen2CloudStatus>(&body) {
+                Ok(status) => info.cloud_connected = Some(status.connected),
+                Err(e) => warn!("Failed to parse Shelly cloud status: {}", e),
+            },
+            Err(e) => warn!("Failed to fetch Shelly cloud status: {}", e),
+        }
+    } else {
+        match get_text(&client, ip_address, GEN1_STATUS_ENDPOINT).await {
+            Ok(body) => match serde_json::from_str::<Gen1Status>(&body) {
+                Ok(status) => {
+                    info.cloud_enabled = Some(status.cloud.enabled);
+                    info.cloud_connected = Some(status.cloud.connected);
+                }
+                Err(e) => warn!("Failed to parse Shelly status: {}", e),
+            },
+            Err(e) => warn!("Failed to fetch Shelly status: {}", e),
+        }
+    }
+
+    Ok(info)
+}
+
+/// GET an endpoint of the device and return the response body
+async fn get_text(
+    client: &reqwest::Client,
+    ip_address: &str,
+    endpoint: &str,
+) -> Result<String, ShellyError> {
+    let url = format!("http://{}{}", ip_address, endpoint);
+    debug!("Fetching Shelly info from: {}", url);
+
+    let response = client
+        .get(&url)
+        .send()
+        .await
+        .map_err(|e| ShellyError::Request(e.to_string()))?;
+
+    if !response.status().is_success() {
+        return Err(ShellyError::HttpStatus(response.status().as_u16()));
+    }
+
+    response
+        .text()
+        .await
+        .map_err(|e| ShellyError::ReadBody(e.to_string()))
+}
+
+/// Parse a `/shelly` (or Gen2+ `Shelly.GetDeviceInfo`) response
+fn parse_shelly_response(json: &str) -> Result<ShellyInfo, ShellyError> {
+    let response: ShellyResponse =
+        serde_json::from_str(json).map_err(|e| ShellyError::JsonParse(e.to_string()))?;
+
+    let generation = response.generation.unwrap_or(1);
+    let model = if generation >= 2 {
+        response.model
+    } else {
+        response.device_type
+    }
+    .filter(|s| !s.is_empty())
+    .ok_or_else(|| ShellyError::JsonParse("missing model".to_string()))?;
+
+    // Gen1 reports the build ("20230913-112003/v1.14.0-gcb84623"); the
+    // version is the part after the slash, up to the commit hash
+    let (firmware_version, firmware_id) = match response.fw {
+        Some(fw) => {
+            let version = fw
+                .split_once('/')
+                .map(|(_, v)| v.split('-').next().unwrap_or(v).to_string());
+            (version, Some(fw))
+        }
+        None => (response.ver, response.fw_id),
+    };
+
+    Ok(ShellyInfo {
+        generation,
+        model,
+        app: response.app.filter(|s| !s.is_empty()),
+        device_id: response.id.filter(|s| !s.is_empty()),
+        name: response.name.filter(|s| !s.is_empty()),
+        mac_address: response.mac.filter(|s| !s.is_empty()),
+        firmware_version: firmware_version.filter(|s| !s.is_empty()),
+        firmware_id: firmware_id.filter(|s| !s.is_empty()),
+        auth_enabled: response.auth.or(response.auth_en).unwrap_or(false),
+        cloud_enabled: None,
+        cloud_connected: None,
+    })
+}
+
+/// Errors that can occur when fetching Shelly information
+#[derive(Debug, Clone)]
+pub enum ShellyError {
+    /// Failed to create HTTP client
+    HttpClient(String),
+    /// HTTP request failed
+    Request(String),
+    /// Non-success HTTP status
+    HttpStatus(u16),
+    /// Failed to read response body
+    ReadBody(String),
+    /// Failed to parse JSON
+    JsonParse(String),
+}
+
+impl std::fmt::Display for ShellyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShellyError::HttpClient(e) => write!(f, "Failed to create HTTP client: {}", e),
+            ShellyError::Request(e) => write!(f, "HTTP request failed: {}", e),
+            ShellyError::HttpStatus(code) => write!(f, "HTTP error: {}", code),
+            ShellyError::ReadBody(e) => write!(f, "Failed to read response: {}", e),
+            ShellyError::JsonParse(e) => write!(f, "Failed to parse JSON: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ShellyError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_GEN1: &str = r#"{"type":"SHSW-1","mac":"A4CF12F45C3E","auth":false,"fw":"20230913-112003/v1.14.0-gcb84623","discoverable":true,"longid":1,"num_outputs":1}"#;
+
+    const SAMPLE_GEN2: &str = r#"{"name":"Garage","id":"shellyplus1pm-441793d69718","mac":"441793D69718","slot":0,"model":"SNSW-001P16EU","gen":2,"fw_id":"20231107-164738/1.0.8-g8c7bb3f","ver":"1.0.8","app":"Plus1PM","auth_en":false,"auth_domain":null}"#;
+
+    #[test]
+    fn test_parse_gen1() {
+        let info = parse_shelly_response(SAMPLE_GEN1).expect("Failed to parse");
+
+        assert_eq!(info.generation, 1);
+        assert_eq!(info.model, "SHSW-1");
+        assert_eq!(info.app, None);
+        assert_eq!(info.mac_address, Some("A4CF12F45C3E".to_string()));
+        assert_eq!(info.firmware_version, Some("v1.14.0".to_string()));
+        assert_eq!(
+            info.firmware_id,
+            Some("20230913-112003/v1.14.0-gcb84623".to_string())
+        );
+        assert!(!info.auth_enabled);
+    }
+
+    #[test]
+    fn test_parse_gen2() {
+        let info = parse_shelly_response(SAMPLE_GEN2).expect("Failed to parse");
+
+        assert_eq!(info.generation, 2);
+        assert_eq!(info.model, "SNSW-001P16EU");
+        assert_eq!(info.app, Some("Plus1PM".to_string()));
+        assert_eq!(
+            info.device_id,
+            Some("shellyplus1pm-441793d69718".to_string())
+        );
+        assert_eq!(info.name, Some("Garage".to_string()));
+        assert_eq!(info.firmware_version, Some("1.0.8".to_string()));
+    }
+
+    #[test]
+    fn test_parse_missing_model() {
+        assert!(parse_shelly_response(r#"{"gen":2}"#).is_err());
+    }
+}
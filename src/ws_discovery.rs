@@ -0,0 +1,371 @@
+//! WS-Discovery (SOAP-over-UDP) device discovery.
+//!
+//! Sends a Probe to the WS-Discovery multicast group and collects ProbeMatch
+//! replies for a few seconds. Network printers, scanners and ONVIF cameras
+//! often answer WS-Discovery without advertising mDNS. Each reply becomes a
+//! `ws-discovery` service whose types, scopes and transport addresses are
+//! kept as TXT properties, so device identification can parse them.
+
+use crate::discovery::{DiscoveredDevice, DiscoveredService, DiscoveryEvent};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio::time::{timeout_at, Instant};
+use tracing::{debug, error, info};
+
+/// WS-Discovery multicast group and port
+const MULTICAST_ADDR: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+const WS_DISCOVERY_PORT: u16 = 3702;
+
+/// Service type of the services created from ProbeMatch replies
+pub const WS_DISCOVERY_SERVICE_TYPE: &str = "ws-discovery";
+
+/// How long to collect replies after the probe
+const LISTEN_DURATION: Duration = Duration::from_secs(5);
+
+/// Probes are sent more than once since multicast UDP may be lost
+const PROBE_REPEATS: usize = 2;
+const PROBE_REPEAT_DELAY: Duration = Duration::from_millis(250);
+
+/// An empty Probe, which every target service answers
+fn probe_message(message_id: &str) -> String {
+    format!(
+        concat!(
+            r#"<?xml version="1.0" encoding="utf-8"?>"#,
+            r#"<soap:Envelope xmlns:soap="http://www.w3.org/2003/05/soap-envelope" "#,
+            r#"xmlns:wsa="http://schemas.xmlsoap.org/ws/2004/08/addressing" "#,
+            r#"xmlns:wsd="http://schemas.xmlsoap.org/ws/2005/04/discovery">"#,
+            r#"<soap:Header>"#,
+            r#"<wsa:To>urn:schemas-xmlsoap-org:ws:2005:04:discovery</wsa:To>"#,
+            r#"<wsa:Action>http://schemas.xmlsoap.org/ws/2005/04/discovery/Probe</wsa:Action>"#,
+            r#"<wsa:MessageID>{}</wsa:MessageID>"#,
+            r#"</soap:Header>"#,
+            r#"<soap:Body><wsd:Probe/></soap:Body>"#,
+            r#"</soap:Envelope>"#,
+        ),
+        message_id
+    )
+}
+
+/// A target service from a ProbeMatches reply
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct ProbeMatch {
+    /// Endpoint reference address (usually "urn:uuid:...")
+    endpoint: String,
+    /// Type QNames, e.g. "tdn:NetworkVideoTransmitter"
+    types: Vec<String>,
+    /// Scope URIs, e.g. "onvif://www.onvif.org/name/Camera"
+    scopes: Vec<String>,
+    /// Transport addresses, e.g. "http://192.168.1.20/onvif/device_service"
+    xaddrs: Vec<String>,
+}
+
+/// A parsed ProbeMatches message
+#[derive(Debug, Default)]
+struct ProbeMatches {
+    /// MessageID of the probe this message answers
+    relates_to: Option<String>,
+    matches: Vec<ProbeMatch>,
+}
+
+/// Parse a ProbeMatches message. Namespace prefixes are ignored, since
+/// devices use different ones for the same namespaces.
+fn parse_probe_matches(xml: &str) -> Result<ProbeMatches, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut result = ProbeMatches::default();
+    let mut current: Option<ProbeMatch> = None;
+    let mut text = String::new();
+
+    loop {
+        match reader.read_event().map_err(|e| e.to_string())? {
+            Event::Start(e) => {
+                text.clear();
+                if e.local_name().as_ref() == b"ProbeMatch" {
+                    current = Some(ProbeMatch::default());
+                }
+            }
+            Event::Text(t) => {
+                text.push_str(&t.decode().map_err(|e| e.to_string())?);
+            }
+            Event::GeneralRef(r) => {
+                let name = r.decode().map_err(|e| e.to_string())?;
+                if let Some(c) = r.resolve_char_ref().map_err(|e| e.to_string())? {
+                    text.push(c);
+                } else if let Some(s) = quick_xml::escape::resolve_predefined_entity(&name) {
+                    text.push_str(s);
+                }
+            }
+            Event::End(e) => {
+                let name = e.local_name();
+                let value = text.trim();
+                match (name.as_ref(), current.as_mut()) {
+                    (b"RelatesTo", _) => result.relates_to = Some(value.to_string()),
+                    (b"Address", Some(m)) => m.endpoint = value.to_string(),
+                    (b"Types", Some(m)) => m.types = split_list(value),
+                    (b"Scopes", Some(m)) => m.scopes = split_list(value),
+                    (b"XAddrs", Some(m)) => m.xaddrs = split_list(value),
+                    (b"ProbeMatch", Some(_)) => result.matches.extend(current.take()),
+                    _ => {}
+                }
+                text.clear();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(result)
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value.split_whitespace().map(|s| s.to_string()).collect()
+}
+
+/// Port of the first transport address, or 80
+fn xaddr_port(xaddrs: &[String]) -> u16 {
+    xaddrs
+        .first()
+        .and_then(|x| reqwest::Url::parse(x).ok())
+        .and_then(|url| url.port_or_known_default())
+        .unwrap_or(80)
+}
+
+/// Value of an ONVIF scope such as "onvif://www.onvif.org/name/Front%20Door"
+/// in a space-separated scope list
+pub fn onvif_scope(scopes: &str, key: &str) -> Option<String> {
+    let prefix = format!("onvif://www.onvif.org/{}/", key);
+    scopes
+        .split_whitespace()
+        .find_map(|s| s.strip_prefix(prefix.as_str()))
+        .filter(|v| !v.is_empty())
+        .map(percent_decode)
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| {
+            std::str::from_utf8(h)
+                .ok()
+                .and_then(|h| u8::from_str_radix(h, 16).ok())
+        });
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Build the device reported for a ProbeMatch from `source`
+fn match_device(probe_match: &ProbeMatch, source: IpAddr) -> DiscoveredDevice {
+    let address = source.to_string();
+    let scopes = probe_match.scopes.join(" ");
+    let instance_name = onvif_scope(&scopes, "name").unwrap_or_default();
+
+    let txt_properties = HashMap::from([
+        ("types".to_string(), probe_match.types.join(" ")),
+        ("scopes".to_string(), scopes),
+        ("xaddrs".to_string(), probe_match.xaddrs.join(" ")),
+    ]);
+    let service = DiscoveredService {
+        service_type: WS_DISCOVERY_SERVICE_TYPE.to_string(),
+        fullname: probe_match.endpoint.clone(),
+        instance_name: instance_name.clone(),
+        port: xaddr_port(&probe_match.xaddrs),
+        txt_properties: txt_properties.clone(),
+    };
+
+    DiscoveredDevice {
+        name: if instance_name.is_empty() {
+            address.clone()
+        } else {
+            instance_name
+        },
+        address: address.clone(),
+        addresses: vec![address.clone()],
+        hostname: address,
+        services: vec![service],
+        txt_properties,
+        ttl: None,
+        discovery_method: "ws_discovery".to_string(),
+        vendor_info: None,
+        open_ports: Vec::new(),
+        gateway: None,
+        mac: None,
+    }
+}
+
+/// Run WS-Discovery and send discovered devices to the channel
+pub async fn run_ws_discovery(tx: mpsc::Sender<DiscoveryEvent>) {
+    info!("Starting WS-Discovery");
+
+    if tx
+        .send(DiscoveryEvent::Started {
+            message: "Sending WS-Discovery probe...".to_string(),
+        })
+        .await
+        .is_err()
+    {
+        return;
+    }
+
+    let socket = match UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await {
+        Ok(socket) => socket,
+        Err(e) => {
+            error!("Failed to bind WS-Discovery socket: {}", e);
+            let _ = tx
+                .send(DiscoveryEvent::Error {
+                    message: format!("Failed to bind socket: {}", e),
+                })
+                .await;
+            return;
+        }
+    };
+
+    let message_id = format!("urn:uuid:{}", uuid::Uuid::new_v4());
+    let probe = probe_message(&message_id);
+    let group = SocketAddr::from((MULTICAST_ADDR, WS_DISCOVERY_PORT));
+    for i in 0..PROBE_REPEATS {
+        if i > 0 {
+            tokio::time::sleep(PROBE_REPEAT_DELAY).await;
+        }
+        if let Err(e) = socket.send_to(probe.as_bytes(), group).await {
+            error!("Failed to send WS-Discovery probe: {}", e);
+            let _ = tx
+                .send(DiscoveryEvent::Error {
+                    message: format!("Failed to send probe: {}", e),
+                })
+                .await;
+            return;
+        }
+    }
+
+    let deadline = Instant::now() + LISTEN_DURATION;
+    let mut seen = HashSet::new();
+    let mut buf = vec![0u8; 65535];
+
+    while let Ok(received) = timeout_at(deadline, socket.recv_from(&mut buf)).await {
+        let (len, source) = match received {
+            Ok(received) => received,
+            Err(e) => {
+                debug!("WS-Discovery receive failed: {}", e);
+                continue;
+            }
+        };
+
+        let xml = String::from_utf8_lossy(&buf[..len]);
+        let reply = match parse_probe_matches(&xml) {
+            Ok(reply) => reply,
+            Err(e) => {
+                debug!(
+                    "Ignoring malformed WS-Discovery reply from {}: {}",
+                    source, e
+                );
+                continue;
+            }
+        };
+        if reply.relates_to.as_deref() != Some(message_id.as_str()) {
+            continue;
+        }
+
+        for probe_match in reply.matches {
+            if !seen.insert((source.ip(), probe_match.endpoint.clone())) {
+                continue;
+            }
+            let device = match_device(&probe_match, source.ip());
+            if tx
+                .send(DiscoveryEvent::DeviceFound { device })
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+    }
+
+    let device_count = seen.len();
+    info!("WS-Discovery completed, found {} devices", device_count);
+    let _ = tx
+        .send(DiscoveryEvent::Completed {
+            message: format!("WS-Discovery complete. Found {} devices.", device_count),
+            device_count,
+        })
+        .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROBE_MATCHES: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<SOAP-ENV:Envelope xmlns:SOAP-ENV="http://www.w3.org/2003/05/soap-envelope"
+    xmlns:wsa="http://schemas.xmlsoap.org/ws/2004/08/addressing"
+    xmlns:d="http://schemas.xmlsoap.org/ws/2005/04/discovery"
+    xmlns:dn="http://www.onvif.org/ver10/network/wsdl">
+  <SOAP-ENV:Header>
+    <wsa:MessageID>urn:uuid:reply</wsa:MessageID>
+    <wsa:RelatesTo>urn:uuid:probe</wsa:RelatesTo>
+  </SOAP-ENV:Header>
+  <SOAP-ENV:Body>
+    <d:ProbeMatches>
+      <d:ProbeMatch>
+        <wsa:EndpointReference>
+          <wsa:Address>urn:uuid:camera-1</wsa:Address>
+        </wsa:EndpointReference>
+        <d:Types>dn:NetworkVideoTransmitter tds:Device</d:Types>
+        <d:Scopes>onvif://www.onvif.org/name/Front%20Door onvif://www.onvif.org/hardware/P1346</d:Scopes>
+        <d:XAddrs>http://192.168.1.20:8080/onvif/device_service?a=1&amp;b=2</d:XAddrs>
+        <d:MetadataVersion>1</d:MetadataVersion>
+      </d:ProbeMatch>
+    </d:ProbeMatches>
+  </SOAP-ENV:Body>
+</SOAP-ENV:Envelope>"#;
+
+    #[test]
+    fn test_parse_probe_matches() {
+        let reply = parse_probe_matches(PROBE_MATCHES).unwrap();
+        assert_eq!(reply.relates_to.as_deref(), Some("urn:uuid:probe"));
+        assert_eq!(reply.matches.len(), 1);
+
+        let m = &reply.matches[0];
+        assert_eq!(m.endpoint, "urn:uuid:camera-1");
+        assert_eq!(m.types, vec!["dn:NetworkVideoTransmitter", "tds:Device"]);
+        assert_eq!(m.scopes.len(), 2);
+        assert_eq!(
+            m.xaddrs,
+            vec!["http://192.168.1.20:8080/onvif/device_service?a=1&b=2"]
+        );
+    }
+
+    #[test]
+    fn test_match_device() {
+        let reply = parse_probe_matches(PROBE_MATCHES).unwrap();
+        let device = match_device(&reply.matches[0], "192.168.1.20".parse().unwrap());
+
+        assert_eq!(device.name, "Front Door");
+        assert_eq!(device.address, "192.168.1.20");
+        assert_eq!(device.discovery_method, "ws_discovery");
+        assert_eq!(device.services[0].service_type, WS_DISCOVERY_SERVICE_TYPE);
+        assert_eq!(device.services[0].port, 8080);
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("Front%20Door"), "Front Door");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz"), "%zz");
+    }
+}